@@ -0,0 +1,225 @@
+// ATSC A/53 Part 4 ( 6.2.3 ) 与 SMPTE 2016-1: user_data_registered_itu_t_t35 中的 Active Format Description 与 bar data
+//
+// itu_t_t35_country_code ( 0xB5 ) | itu_t_t35_provider_code ( 0x0031 ) | afd_identifier ( "DTG1" ) | afd_data()
+// itu_t_t35_country_code ( 0xB5 ) | itu_t_t35_provider_code ( 0x0031 ) | user_identifier ( "GA94" ) |
+// user_data_type_code ( 0x06 ) | bar_data()
+
+use crate::error::{ self, Error };
+use crate::rbsp::{ RbspReader, UserDataRegistered };
+use super::cc_data::{ ITU_T_T35_COUNTRY_CODE_US, ITU_T_T35_PROVIDER_CODE_ATSC, ATSC_USER_IDENTIFIER_GA94 };
+
+
+// afd_identifier: "DTG1"
+pub const AFD_IDENTIFIER_DTG1: u32 = 0x4454_4731;
+// user_data_type_code: bar_data()
+pub const USER_DATA_TYPE_CODE_BAR_DATA: u8 = 0x06;
+
+
+// ATSC user data 的 user_identifier 以及之后的字节, 不是 ATSC 的 user data 时返回 None
+pub(crate) fn atsc_user_data(user_data: &UserDataRegistered) -> Option<(u32, &[u8])> {
+    if user_data.itu_t_t35_country_code != ITU_T_T35_COUNTRY_CODE_US
+        || user_data.provider_code() != Some(ITU_T_T35_PROVIDER_CODE_ATSC) {
+        return None;
+    }
+
+    let payload = &user_data.payload[2..];
+    if payload.len() < 4 {
+        return None;
+    }
+
+    let user_identifier = u32::from(payload[0]) << 24 | u32::from(payload[1]) << 16
+        | u32::from(payload[2]) << 8 | u32::from(payload[3]);
+    Some((user_identifier, &payload[4..]))
+}
+
+
+// afd_data(): '0' | active_format_flag | reserved ( 6 ) [ | reserved ( 4 ) | active_format ( 4 ) ]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Afd {
+    // active_format_flag 为 0 时为 None
+    pub active_format: Option<u8>,
+}
+
+impl Afd {
+    // 不是 DTG1 的 afd_data() 时返回 Ok(None)
+    pub fn from_user_data(user_data: &UserDataRegistered) -> Result<Option<Self>, Error> {
+        match atsc_user_data(user_data) {
+            Some((AFD_IDENTIFIER_DTG1, bytes)) => Afd::parse(bytes).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Err(error::malformed("afd_data is too short"));
+        }
+
+        let active_format_flag = bytes[0] & 0x40 != 0;
+        if !active_format_flag {
+            return Ok(Afd { active_format: None });
+        }
+        if bytes.len() < 2 {
+            return Err(error::malformed("afd_data is shorter than active_format"));
+        }
+
+        Ok(Afd { active_format: Some(bytes[1] & 0x0F) })
+    }
+
+    // SMPTE 2016-1 Table 1 ( 16:9 编码帧 )
+    pub fn description(&self) -> &'static str {
+        match self.active_format {
+            None => "not signalled",
+            Some(2) => "box 16:9 (top)",
+            Some(3) => "box 14:9 (top)",
+            Some(4) => "box > 16:9 (center)",
+            Some(8) => "full frame",
+            Some(9) => "4:3 (center)",
+            Some(10) => "16:9 (center)",
+            Some(11) => "14:9 (center)",
+            Some(13) => "4:3 (with shoot and protect 14:9 center)",
+            Some(14) => "16:9 (with shoot and protect 14:9 center)",
+            Some(15) => "16:9 (with shoot and protect 4:3 center)",
+            Some(_) => "reserved",
+        }
+    }
+}
+
+
+// bar_data(): top_bar_flag | bottom_bar_flag | left_bar_flag | right_bar_flag | reserved ( 4 ) |
+// 每个 flag 为 1 的值: marker_bits ( 2 ) | 14 位的行号或像素序号
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BarData {
+    // line_number_end_of_top_bar
+    pub top: Option<u16>,
+    // line_number_start_of_bottom_bar
+    pub bottom: Option<u16>,
+    // pixel_number_end_of_left_bar
+    pub left: Option<u16>,
+    // pixel_number_start_of_right_bar
+    pub right: Option<u16>,
+}
+
+impl BarData {
+    // 不是 GA94 的 bar_data() 时返回 Ok(None)
+    pub fn from_user_data(user_data: &UserDataRegistered) -> Result<Option<Self>, Error> {
+        match atsc_user_data(user_data) {
+            Some((ATSC_USER_IDENTIFIER_GA94, bytes)) if bytes.first() == Some(&USER_DATA_TYPE_CODE_BAR_DATA) => {
+                BarData::parse(&bytes[1..]).map(Some)
+            },
+            _ => Ok(None),
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+        let flags = reader.read_bits(4)?;
+        reader.skip_bits(4)?;
+
+        let mut read_bar = |flag: u32| -> Result<Option<u16>, Error> {
+            if flags & flag == 0 {
+                return Ok(None);
+            }
+            let marker_bits = reader.read_bits(2)?;
+            if marker_bits != 0b11 {
+                return Err(error::malformed("bar_data marker_bits are not '11'"));
+            }
+            Ok(Some(reader.read_bits(14)? as u16))
+        };
+
+        Ok(BarData {
+            top: read_bar(0b1000)?,
+            bottom: read_bar(0b0100)?,
+            left: read_bar(0b0010)?,
+            right: read_bar(0b0001)?,
+        })
+    }
+}
+
+
+// AFD 或 bar data 发生变化的图像
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AfdChange {
+    // 输出顺序中的帧序号
+    pub output_index: u64,
+    pub previous: (Option<Afd>, Option<BarData>),
+    pub current: (Option<Afd>, Option<BarData>),
+    // 变化发生在 IDR 或只包含 I / SI slice 的图像上 ( 通常意味着节目内容的切换 )
+    pub intra: bool,
+}
+
+// 按输出顺序跟踪 AFD 与 bar data; 没有携带的图像沿用之前的值
+#[derive(Debug, Clone, Default)]
+pub struct AfdTracker {
+    current: Option<(Option<Afd>, Option<BarData>)>,
+    changes: Vec<AfdChange>,
+}
+
+impl AfdTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, output_index: u64, intra: bool, afd: Option<Afd>, bar_data: Option<BarData>) {
+        let previous = self.current.unwrap_or((None, None));
+        let current = (afd.or(previous.0), bar_data.or(previous.1));
+
+        if self.current.is_some() && current != previous {
+            self.changes.push(AfdChange {
+                output_index: output_index,
+                previous: previous,
+                current: current,
+                intra: intra,
+            });
+        }
+        self.current = Some(current);
+    }
+
+    pub fn changes(&self) -> &[AfdChange] {
+        &self.changes
+    }
+
+    // 没有发生在帧内编码图像上的变化
+    pub fn unaligned_changes(&self) -> impl Iterator<Item = &AfdChange> {
+        self.changes.iter().filter(|change| !change.intra)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ Afd, BarData, AfdTracker };
+    use crate::rbsp::UserDataRegistered;
+
+    #[test]
+    fn test_afd_and_bar_data() {
+        let user_data = UserDataRegistered::parse(&[0xB5, 0x00, 0x31, b'D', b'T', b'G', b'1', 0x41, 0xF9]).unwrap();
+        let afd = Afd::from_user_data(&user_data).unwrap().unwrap();
+        assert_eq!(afd.active_format, Some(9));
+        assert_eq!(afd.description(), "4:3 (center)");
+        assert_eq!(BarData::from_user_data(&user_data).unwrap(), None);
+
+        // top 和 bottom: 第 0 .. 59 行与第 1020 行开始
+        let user_data = UserDataRegistered::parse(&[
+            0xB5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x06, 0xCF, 0xC0, 0x3B, 0xC3, 0xFC,
+        ]).unwrap();
+        let bar_data = BarData::from_user_data(&user_data).unwrap().unwrap();
+        assert_eq!(bar_data, BarData { top: Some(59), bottom: Some(1020), left: None, right: None });
+        assert_eq!(Afd::from_user_data(&user_data).unwrap(), None);
+
+        assert!(BarData::parse(&[0x8F, 0x00, 0x3B]).is_err());
+
+        let mut tracker = AfdTracker::new();
+        let full = Some(Afd { active_format: Some(8) });
+        let pillar = Some(Afd { active_format: Some(9) });
+        tracker.push(0, true, full, None);
+        tracker.push(1, false, None, None);
+        tracker.push(2, true, pillar, None);
+        tracker.push(3, false, full, None);
+        tracker.push(4, false, None, Some(bar_data));
+
+        assert_eq!(tracker.changes().len(), 3);
+        assert_eq!(tracker.changes()[0].output_index, 2);
+        assert_eq!(tracker.unaligned_changes().map(|change| change.output_index).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(tracker.changes()[2].current, (full, Some(bar_data)));
+    }
+}
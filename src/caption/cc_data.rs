@@ -0,0 +1,151 @@
+// ATSC A/53 Part 4 ( 6.2.3 ) 与 CEA-708 ( 4.4 ): user_data_registered_itu_t_t35 中的 cc_data()
+//
+// itu_t_t35_country_code ( 0xB5 ) | itu_t_t35_provider_code ( 0x0031 ) | user_identifier ( "GA94" ) |
+// user_data_type_code ( 0x03 ) | cc_data()
+
+use crate::error::{ self, Error };
+use crate::rbsp::UserDataRegistered;
+use super::afd::atsc_user_data;
+
+
+// itu_t_t35_country_code: United States
+pub const ITU_T_T35_COUNTRY_CODE_US: u8 = 0xB5;
+// itu_t_t35_provider_code: ATSC
+pub const ITU_T_T35_PROVIDER_CODE_ATSC: u16 = 0x0031;
+// ATSC_user_identifier: "GA94"
+pub const ATSC_USER_IDENTIFIER_GA94: u32 = 0x4741_3934;
+// user_data_type_code: cc_data()
+pub const USER_DATA_TYPE_CODE_CC_DATA: u8 = 0x03;
+
+
+// CEA-708 Table 3: cc_type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CcType {
+    // NTSC line 21 field 1 ( CEA-608 CC1, CC2 )
+    Ntsc608Field1,
+    // NTSC line 21 field 2 ( CEA-608 CC3, CC4, XDS )
+    Ntsc608Field2,
+    // DTVCC_packet_data
+    DtvccPacketData,
+    // DTVCC_packet_start
+    DtvccPacketStart,
+}
+
+impl CcType {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => CcType::Ntsc608Field1,
+            1 => CcType::Ntsc608Field2,
+            2 => CcType::DtvccPacketData,
+            _ => CcType::DtvccPacketStart,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        match *self {
+            CcType::Ntsc608Field1 => 0,
+            CcType::Ntsc608Field2 => 1,
+            CcType::DtvccPacketData => 2,
+            CcType::DtvccPacketStart => 3,
+        }
+    }
+
+    pub fn is_cea608(&self) -> bool {
+        *self == CcType::Ntsc608Field1 || *self == CcType::Ntsc608Field2
+    }
+}
+
+// marker_bits ( 5 ) | cc_valid | cc_type ( 2 ) | cc_data_1 | cc_data_2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CcTriplet {
+    pub cc_valid: bool,
+    pub cc_type: CcType,
+    pub cc_data_1: u8,
+    pub cc_data_2: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcData {
+    pub process_cc_data_flag: bool,
+    // cc_count 项
+    pub triplets: Vec<CcTriplet>,
+}
+
+impl CcData {
+    // 不是 ATSC GA94 的 cc_data() 时返回 Ok(None)
+    pub fn from_user_data(user_data: &UserDataRegistered) -> Result<Option<Self>, Error> {
+        match atsc_user_data(user_data) {
+            Some((ATSC_USER_IDENTIFIER_GA94, bytes)) if bytes.first() == Some(&USER_DATA_TYPE_CODE_CC_DATA) => {
+                CcData::parse(&bytes[1..]).map(Some)
+            },
+            _ => Ok(None),
+        }
+    }
+
+    // cc_data(): process_em_data_flag | process_cc_data_flag | additional_data_flag | cc_count ( 5 ) | em_data ( 8 ) |
+    // cc_count 个三字节组 | marker_bits ( 8 )
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(error::malformed("cc_data is too short"));
+        }
+
+        let process_cc_data_flag = bytes[0] & 0x40 != 0;
+        let cc_count = (bytes[0] & 0x1F) as usize;
+        if bytes.len() < 2 + cc_count * 3 {
+            return Err(error::malformed("cc_data is shorter than cc_count"));
+        }
+
+        let triplets = bytes[2..2 + cc_count * 3].chunks(3)
+            .map(|triplet| CcTriplet {
+                cc_valid: triplet[0] & 0x04 != 0,
+                cc_type: CcType::from_bits(triplet[0]),
+                cc_data_1: triplet[1],
+                cc_data_2: triplet[2],
+            })
+            .collect();
+
+        Ok(Self {
+            process_cc_data_flag: process_cc_data_flag,
+            triplets: triplets,
+        })
+    }
+
+    // process_cc_data_flag 为 1 时有效的三字节组
+    pub fn valid_triplets(&self) -> impl Iterator<Item = &CcTriplet> {
+        let process = self.process_cc_data_flag;
+        self.triplets.iter().filter(move |triplet| process && triplet.cc_valid)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ CcData, CcType };
+    use crate::rbsp::UserDataRegistered;
+
+    #[test]
+    fn test_ga94_cc_data() {
+        let bytes = [
+            0xB5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03,
+            0xC2, 0xFF,
+            0xFC, 0x94, 0x20,
+            0xFA, 0x00, 0x00,
+            0xFF,
+        ];
+        let user_data = UserDataRegistered::parse(&bytes).unwrap();
+        assert_eq!(user_data.provider_code(), Some(0x0031));
+
+        let cc_data = CcData::from_user_data(&user_data).unwrap().unwrap();
+        assert!(cc_data.process_cc_data_flag);
+        assert_eq!(cc_data.triplets.len(), 2);
+        assert_eq!(cc_data.triplets[0].cc_type, CcType::Ntsc608Field1);
+        assert_eq!((cc_data.triplets[0].cc_data_1, cc_data.triplets[0].cc_data_2), (0x94, 0x20));
+        assert!(!cc_data.triplets[1].cc_valid);
+        assert_eq!(cc_data.triplets[1].cc_type, CcType::DtvccPacketData);
+        assert_eq!(cc_data.valid_triplets().count(), 1);
+
+        // 其它 provider 的 user data
+        let user_data = UserDataRegistered::parse(&[0xB5, 0x00, 0x2F, 0x03, 0x00]).unwrap();
+        assert_eq!(CcData::from_user_data(&user_data).unwrap(), None);
+    }
+}
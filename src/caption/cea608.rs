@@ -0,0 +1,382 @@
+// CEA-608 ( line 21 ) 字幕解码: 每个场两个数据通道 ( field 1 为 CC1, CC2, field 2 为 CC3, CC4 ),
+// 支持 pop-on, roll-up 与 paint-on 三种模式, 不处理颜色等属性以及 text 模式与 XDS。
+//
+// 显示内容在以下时刻提交给 CueBuilder: pop-on 的 EOC, roll-up 的 CR ( 滚动之前, 包含刚完成的一行 ),
+// paint-on 的每个图像, 以及 EDM。
+
+use super::subtitle::{ Cue, CueBuilder };
+
+use std::mem;
+
+
+const ROWS: usize = 15;
+const COLUMNS: usize = 32;
+
+// 奇校验: 字节中 1 的个数为奇数
+pub fn odd_parity(byte: u8) -> bool {
+    byte.count_ones() % 2 == 1
+}
+
+// 基本字符集 ( 0x20 .. 0x7F ), 与 ASCII 不同的位置
+fn basic_char(code: u8) -> char {
+    match code {
+        0x2A => 'á',
+        0x5C => 'é',
+        0x5E => 'í',
+        0x5F => 'ó',
+        0x60 => 'ú',
+        0x7B => 'ç',
+        0x7C => '÷',
+        0x7D => 'Ñ',
+        0x7E => 'ñ',
+        0x7F => '█',
+        _ => code as char,
+    }
+}
+
+// 特殊字符 ( 0x11 0x30 .. 0x3F )
+const SPECIAL_CHARS: [char; 16] = [
+    '®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û',
+];
+
+// 扩展字符 ( 0x12 0x20 .. 0x3F: 西班牙语, 法语等; 0x13 0x20 .. 0x3F: 葡萄牙语, 德语, 丹麦语 ), 会替换前一个字符
+const EXTENDED_CHARS_12: [char; 32] = [
+    'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '\'', '─', '©', '℠', '•', '“', '”',
+    'À', 'Â', 'Ç', 'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»',
+];
+const EXTENDED_CHARS_13: [char; 32] = [
+    'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~',
+    'Ä', 'ä', 'Ö', 'ö', 'ß', '¥', '¤', '│', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘',
+];
+
+// PAC 第一个字节 ( 去掉通道位 ) 的低 3 位对应的两行 ( 0 起始 ), 第二个字节的 0x20 位选择其中一行
+const PAC_ROWS: [(usize, usize); 8] = [(10, 10), (0, 1), (2, 3), (11, 12), (13, 14), (4, 5), (6, 7), (8, 9)];
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    PopOn,
+    RollUp(usize),
+    PaintOn,
+}
+
+type Memory = [[Option<char>; COLUMNS]; ROWS];
+
+// 一个数据通道的显示内存 ( displayed memory ) 与缓冲内存 ( non-displayed memory )
+#[derive(Debug, Clone)]
+struct Channel {
+    mode: Mode,
+    displayed: Memory,
+    non_displayed: Memory,
+    row: usize,
+    column: usize,
+    // TR, RTD 之后为 text 模式, 字符被忽略
+    text_mode: bool,
+    cues: CueBuilder,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self {
+            mode: Mode::PopOn,
+            displayed: [[None; COLUMNS]; ROWS],
+            non_displayed: [[None; COLUMNS]; ROWS],
+            row: ROWS - 1,
+            column: 0,
+            text_mode: false,
+            cues: CueBuilder::default(),
+        }
+    }
+}
+
+impl Channel {
+    fn memory(&mut self) -> &mut Memory {
+        match self.mode {
+            Mode::PopOn => &mut self.non_displayed,
+            _ => &mut self.displayed,
+        }
+    }
+
+    fn text(memory: &Memory) -> String {
+        let rows: Vec<String> = memory.iter()
+            .map(|row| row.iter().map(|c| c.unwrap_or(' ')).collect::<String>().trim_end().to_string())
+            .filter(|row| !row.is_empty())
+            .collect();
+        rows.join("\n")
+    }
+
+    fn commit(&mut self, frame: u64) {
+        let text = Channel::text(&self.displayed);
+        self.cues.update(frame, &text);
+    }
+
+    fn write_char(&mut self, c: char) {
+        if self.text_mode {
+            return;
+        }
+
+        let (row, column) = (self.row, self.column);
+        self.memory()[row][column] = Some(c);
+        self.column = (column + 1).min(COLUMNS - 1);
+    }
+
+    fn backspace(&mut self) {
+        if self.column > 0 {
+            self.column -= 1;
+            let (row, column) = (self.row, self.column);
+            self.memory()[row][column] = None;
+        }
+    }
+
+    // 0x14 / 0x15 0x20 .. 0x2F ( Miscellaneous Control Codes )
+    fn control(&mut self, code: u8, frame: u64) {
+        match code {
+            // RCL: Resume Caption Loading
+            0x20 => {
+                self.mode = Mode::PopOn;
+                self.text_mode = false;
+            },
+            // BS: Backspace
+            0x21 => self.backspace(),
+            // DER: Delete to End of Row
+            0x24 => {
+                let (row, column) = (self.row, self.column);
+                for cell in self.memory()[row][column..].iter_mut() {
+                    *cell = None;
+                }
+            },
+            // RU2, RU3, RU4: Roll-Up Captions
+            0x25 ..= 0x27 => {
+                let rows = (code - 0x23) as usize;
+                if let Mode::RollUp(_) = self.mode { } else {
+                    self.displayed = [[None; COLUMNS]; ROWS];
+                    self.non_displayed = [[None; COLUMNS]; ROWS];
+                    self.row = ROWS - 1;
+                    self.commit(frame);
+                }
+                self.mode = Mode::RollUp(rows);
+                self.column = 0;
+                self.text_mode = false;
+            },
+            // RDC: Resume Direct Captioning
+            0x29 => {
+                self.mode = Mode::PaintOn;
+                self.text_mode = false;
+            },
+            // TR: Text Restart, RTD: Resume Text Display
+            0x2A | 0x2B => self.text_mode = true,
+            // EDM: Erase Displayed Memory
+            0x2C => {
+                self.displayed = [[None; COLUMNS]; ROWS];
+                self.commit(frame);
+            },
+            // CR: Carriage Return
+            0x2D => {
+                if let Mode::RollUp(rows) = self.mode {
+                    self.commit(frame);
+
+                    let top = (self.row + 1).saturating_sub(rows);
+                    for row in top..self.row {
+                        self.displayed[row] = self.displayed[row + 1];
+                    }
+                    self.displayed[self.row] = [None; COLUMNS];
+                    if top > 0 {
+                        self.displayed[top - 1] = [None; COLUMNS];
+                    }
+                }
+                self.column = 0;
+            },
+            // ENM: Erase Non-Displayed Memory
+            0x2E => self.non_displayed = [[None; COLUMNS]; ROWS],
+            // EOC: End Of Caption
+            0x2F => {
+                mem::swap(&mut self.displayed, &mut self.non_displayed);
+                self.mode = Mode::PopOn;
+                self.commit(frame);
+            },
+            // AOF, AON, FON
+            _ => { },
+        }
+    }
+
+    // PAC: Preamble Address Code
+    fn preamble_address(&mut self, code_1: u8, code_2: u8) {
+        let (first, second) = PAC_ROWS[(code_1 & 0x07) as usize];
+        let row = if code_2 & 0x20 != 0 { second } else { first };
+
+        // roll-up 时基准行移动, 窗口中的内容随之移动
+        if let Mode::RollUp(rows) = self.mode {
+            if row != self.row {
+                let mut moved = [[None; COLUMNS]; ROWS];
+                for i in 0..rows.min(self.row + 1).min(row + 1) {
+                    moved[row - i] = self.displayed[self.row - i];
+                }
+                self.displayed = moved;
+            }
+        }
+
+        self.row = row;
+        self.column = if code_2 & 0x10 != 0 { ((code_2 & 0x0E) >> 1) as usize * 4 } else { 0 };
+    }
+}
+
+
+// 一个场 ( field 1 或 field 2 ) 中 CEA-608 字节对的解码器
+#[derive(Debug, Clone, Default)]
+pub struct Cea608Decoder {
+    channels: [Channel; 2],
+    // 当前的数据通道 ( 0 或 1 ), 由最近的控制码决定
+    current: usize,
+    // 控制码通常重复发送两次, 第二次应被忽略
+    last_control: Option<(u8, u8)>,
+    // 奇校验错误的字节对个数
+    parity_errors: u64,
+}
+
+impl Cea608Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 输出顺序中第 frame 帧的一个字节对 ( 包括奇校验位 )
+    pub fn push(&mut self, frame: u64, cc_data_1: u8, cc_data_2: u8) {
+        if !odd_parity(cc_data_1) || !odd_parity(cc_data_2) {
+            self.parity_errors += 1;
+            return;
+        }
+
+        let (code_1, code_2) = (cc_data_1 & 0x7F, cc_data_2 & 0x7F);
+        if code_1 == 0 && code_2 == 0 {
+            return;
+        }
+
+        if (0x10..=0x1F).contains(&code_1) {
+            if self.last_control == Some((code_1, code_2)) {
+                self.last_control = None;
+                return;
+            }
+            self.last_control = Some((code_1, code_2));
+            self.control_pair(frame, code_1, code_2);
+            return;
+        }
+
+        self.last_control = None;
+        // 0x01 .. 0x0F: XDS ( field 2 ), 不处理
+        if code_1 < 0x10 {
+            return;
+        }
+
+        let channel = &mut self.channels[self.current];
+        channel.write_char(basic_char(code_1));
+        if code_2 >= 0x20 {
+            channel.write_char(basic_char(code_2));
+        }
+    }
+
+    fn control_pair(&mut self, frame: u64, code_1: u8, code_2: u8) {
+        self.current = ((code_1 & 0x08) >> 3) as usize;
+        let channel = &mut self.channels[self.current];
+        let code_1 = code_1 & 0x77;
+
+        match (code_1, code_2) {
+            // Miscellaneous Control Codes ( field 1 为 0x14, field 2 为 0x15 )
+            (0x14, 0x20 ..= 0x2F) | (0x15, 0x20 ..= 0x2F) => channel.control(code_2, frame),
+            // TO1, TO2, TO3: Tab Offset
+            (0x17, 0x21 ..= 0x23) => channel.column = (channel.column + (code_2 - 0x20) as usize).min(COLUMNS - 1),
+            // Mid-Row Codes: 占一个空格
+            (0x11, 0x20 ..= 0x2F) => channel.write_char(' '),
+            // Special Characters
+            (0x11, 0x30 ..= 0x3F) => channel.write_char(SPECIAL_CHARS[(code_2 - 0x30) as usize]),
+            // Extended Characters
+            (0x12, 0x20 ..= 0x3F) => {
+                channel.backspace();
+                channel.write_char(EXTENDED_CHARS_12[(code_2 - 0x20) as usize]);
+            },
+            (0x13, 0x20 ..= 0x3F) => {
+                channel.backspace();
+                channel.write_char(EXTENDED_CHARS_13[(code_2 - 0x20) as usize]);
+            },
+            // Preamble Address Codes
+            (_, 0x40 ..= 0x7F) => channel.preamble_address(code_1, code_2),
+            _ => { },
+        }
+    }
+
+    // 一个图像的字节对处理完之后调用: paint-on 模式直接显示
+    pub fn end_of_picture(&mut self, frame: u64) {
+        for channel in self.channels.iter_mut() {
+            if channel.mode == Mode::PaintOn {
+                channel.commit(frame);
+            }
+        }
+    }
+
+    // 码流结束: 第 frame 帧时关闭仍在显示的字幕
+    pub fn finish(&mut self, frame: u64) {
+        for channel in self.channels.iter_mut() {
+            channel.cues.finish(frame);
+        }
+    }
+
+    // 数据通道 0 或 1 ( CC1 / CC3, CC2 / CC4 ) 的字幕
+    pub fn cues(&self, channel: usize) -> &[Cue] {
+        self.channels[channel].cues.cues()
+    }
+
+    pub fn parity_errors(&self) -> u64 {
+        self.parity_errors
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Cea608Decoder;
+    use crate::caption::Cue;
+
+    // 加上奇校验位
+    fn with_parity(code: u8) -> u8 {
+        if code.count_ones().is_multiple_of(2) { code | 0x80 } else { code }
+    }
+
+    fn push(decoder: &mut Cea608Decoder, frame: u64, pair: [u8; 2]) {
+        decoder.push(frame, with_parity(pair[0]), with_parity(pair[1]));
+        decoder.end_of_picture(frame);
+    }
+
+    #[test]
+    fn test_pop_on_and_roll_up() {
+        let mut decoder = Cea608Decoder::new();
+
+        // RCL RCL, ENM, PAC row 15 indent 4, "HI", Special ♪, EOC EOC
+        let pairs = [
+            [0x14, 0x20], [0x14, 0x20], [0x14, 0x2E], [0x14, 0x52], [b'H', b'I'], [0x11, 0x37], [0x14, 0x2F], [0x14, 0x2F],
+        ];
+        for (frame, &pair) in pairs.iter().enumerate() {
+            push(&mut decoder, frame as u64, pair);
+        }
+
+        // EDM
+        push(&mut decoder, 10, [0x14, 0x2C]);
+
+        // RU2, CR, "AB", CR, "C", CR, 结束
+        let pairs = [[0x14, 0x25], [0x14, 0x2D], [b'A', b'B'], [0x14, 0x2D], [b'C', 0x00], [0x14, 0x2D]];
+        for (i, &pair) in pairs.iter().enumerate() {
+            push(&mut decoder, 20 + i as u64, pair);
+        }
+        decoder.finish(30);
+
+        assert_eq!(decoder.cues(0), &[
+            Cue { start: 6, end: 10, text: "    HI♪".to_string() },
+            Cue { start: 23, end: 25, text: "AB".to_string() },
+            Cue { start: 25, end: 30, text: "AB\nC".to_string() },
+        ]);
+        assert!(decoder.cues(1).is_empty());
+
+        // 偶校验的字节被丢弃
+        decoder.push(31, 0x94, 0xA1);
+        assert_eq!(decoder.parity_errors(), 0);
+        decoder.push(31, 0x14, 0x20);
+        assert_eq!(decoder.parity_errors(), 1);
+    }
+}
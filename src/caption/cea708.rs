@@ -0,0 +1,471 @@
+// CEA-708 ( DTVCC ) 字幕解码: 由 cc_data() 中的 DTVCC_packet_start / DTVCC_packet_data 组装 DTVCC 包,
+// 拆分为各个服务 ( caption service ) 的 service block, 按窗口命令维护每个服务的 8 个窗口。
+//
+// 不处理窗口的位置, 颜色与字体等属性, 每个图像之后把所有可见窗口的文本提交给 CueBuilder。
+
+use super::subtitle::{ Cue, CueBuilder };
+use super::cc_data::CcType;
+
+use std::collections::BTreeMap;
+
+
+const MAX_ROWS: usize = 15;
+const MAX_COLUMNS: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Window {
+    visible: bool,
+    rows: usize,
+    columns: usize,
+    row: usize,
+    column: usize,
+    text: Vec<Vec<Option<char>>>,
+}
+
+impl Window {
+    fn new(visible: bool, rows: usize, columns: usize) -> Self {
+        Self {
+            visible: visible,
+            rows: rows,
+            columns: columns,
+            row: 0,
+            column: 0,
+            text: vec![vec![None; columns]; rows],
+        }
+    }
+
+    fn resize(&mut self, rows: usize, columns: usize) {
+        self.rows = rows;
+        self.columns = columns;
+        self.text.resize(rows, vec![None; columns]);
+        for row in self.text.iter_mut() {
+            row.resize(columns, None);
+        }
+        self.row = self.row.min(rows - 1);
+        self.column = self.column.min(columns - 1);
+    }
+
+    fn clear(&mut self) {
+        for row in self.text.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = None;
+            }
+        }
+        self.row = 0;
+        self.column = 0;
+    }
+
+    fn write_char(&mut self, c: char) {
+        if self.column >= self.columns {
+            return;
+        }
+        self.text[self.row][self.column] = Some(c);
+        self.column += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.column > 0 {
+            self.column -= 1;
+            self.text[self.row][self.column] = None;
+        }
+    }
+
+    // CR: 移到下一行, 已经在最后一行时向上滚动
+    fn carriage_return(&mut self) {
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.text.remove(0);
+            self.text.push(vec![None; self.columns]);
+        }
+        self.column = 0;
+    }
+
+    fn horizontal_carriage_return(&mut self) {
+        for cell in self.text[self.row].iter_mut() {
+            *cell = None;
+        }
+        self.column = 0;
+    }
+
+    fn to_text(&self) -> String {
+        let rows: Vec<String> = self.text.iter()
+            .map(|row| row.iter().map(|c| c.unwrap_or(' ')).collect::<String>().trim_end().to_string())
+            .filter(|row| !row.is_empty())
+            .collect();
+        rows.join("\n")
+    }
+}
+
+
+// 一个字幕服务的窗口状态
+#[derive(Debug, Clone, Default)]
+struct Service {
+    windows: [Option<Window>; 8],
+    current: Option<usize>,
+    cues: CueBuilder,
+}
+
+impl Service {
+    fn window(&mut self) -> Option<&mut Window> {
+        let current = self.current?;
+        self.windows[current].as_mut()
+    }
+
+    fn write_char(&mut self, c: char) {
+        if let Some(window) = self.window() {
+            window.write_char(c);
+        }
+    }
+
+    // 对 window_bitmap 中的每个窗口执行 f
+    fn for_windows<F: FnMut(&mut Option<Window>)>(&mut self, bitmap: u8, mut f: F) {
+        for (i, window) in self.windows.iter_mut().enumerate() {
+            if bitmap & (1 << i) != 0 {
+                f(window);
+            }
+        }
+    }
+
+    fn text(&self) -> String {
+        let texts: Vec<String> = self.windows.iter()
+            .filter_map(|window| window.as_ref())
+            .filter(|window| window.visible)
+            .map(|window| window.to_text())
+            .filter(|text| !text.is_empty())
+            .collect();
+        texts.join("\n")
+    }
+
+    // 处理一个 service block 的数据
+    fn decode(&mut self, data: &[u8]) {
+        let mut i = 0;
+        while i < data.len() {
+            let code = data[i];
+            i += 1;
+
+            // 命令的参数, 不完整时丢弃 service block 的剩余部分
+            let params = |i: usize, n: usize| if i + n <= data.len() { Some(&data[i..i + n]) } else { None };
+
+            match code {
+                // C0: ETX, BS, FF, CR, HCR
+                0x08 => if let Some(window) = self.window() { window.backspace() },
+                0x0C => if let Some(window) = self.window() { window.clear() },
+                0x0D => if let Some(window) = self.window() { window.carriage_return() },
+                0x0E => if let Some(window) = self.window() { window.horizontal_carriage_return() },
+                0x00 ..= 0x0F => { },
+                // EXT1
+                0x10 => {
+                    if i >= data.len() {
+                        return;
+                    }
+                    let code = data[i];
+                    i += 1;
+                    match code {
+                        // C2: 0, 1, 2, 3 个参数
+                        0x00 ..= 0x1F => i += (code >> 3) as usize,
+                        // G2
+                        0x20 ..= 0x7F => self.write_char(g2_char(code)),
+                        // C3: 4, 5 个参数, 0x90 .. 0x9F 为可变长度的命令
+                        0x80 ..= 0x87 => i += 4,
+                        0x88 ..= 0x8F => i += 5,
+                        0x90 ..= 0x9F => match params(i, 1) {
+                            Some(p) => i += 1 + (p[0] & 0x1F) as usize,
+                            None => return,
+                        },
+                        // G3: 0xA0 为 CC 标志
+                        _ => self.write_char(if code == 0xA0 { '㏄' } else { '_' }),
+                    }
+                },
+                // P16: 16 位字符
+                0x18 => match params(i, 2) {
+                    Some(p) => {
+                        i += 2;
+                        let c = std::char::from_u32(u32::from(p[0]) << 8 | u32::from(p[1])).unwrap_or('_');
+                        self.write_char(c);
+                    },
+                    None => return,
+                },
+                0x11 ..= 0x17 => i += 1,
+                0x19 ..= 0x1F => i += 2,
+                // G0
+                0x7F => self.write_char('♪'),
+                0x20 ..= 0x7E => self.write_char(code as char),
+                // C1: CW0 .. CW7
+                0x80 ..= 0x87 => {
+                    let id = (code & 0x07) as usize;
+                    if self.windows[id].is_some() {
+                        self.current = Some(id);
+                    }
+                },
+                // CLW, DSW, HDW, TGW, DLW, DLY
+                0x88 ..= 0x8D => {
+                    let bitmap = match params(i, 1) {
+                        Some(p) => p[0],
+                        None => return,
+                    };
+                    i += 1;
+                    match code {
+                        0x88 => self.for_windows(bitmap, |window| if let Some(window) = window { window.clear() }),
+                        0x89 => self.for_windows(bitmap, |window| if let Some(window) = window { window.visible = true }),
+                        0x8A => self.for_windows(bitmap, |window| if let Some(window) = window { window.visible = false }),
+                        0x8B => self.for_windows(bitmap, |window| if let Some(window) = window { window.visible = !window.visible }),
+                        0x8C => {
+                            self.for_windows(bitmap, |window| *window = None);
+                            if let Some(current) = self.current {
+                                if bitmap & (1 << current) != 0 {
+                                    self.current = None;
+                                }
+                            }
+                        },
+                        // DLY: 不处理延迟
+                        _ => { },
+                    }
+                },
+                // DLC
+                0x8E => { },
+                // RST
+                0x8F => {
+                    let cues = std::mem::take(&mut self.cues);
+                    *self = Service::default();
+                    self.cues = cues;
+                },
+                // SPA, SPL
+                0x90 | 0x92 => match params(i, 2) {
+                    Some(p) => {
+                        i += 2;
+                        if code == 0x92 {
+                            if let Some(window) = self.window() {
+                                window.row = ((p[0] & 0x0F) as usize).min(window.rows - 1);
+                                window.column = ((p[1] & 0x3F) as usize).min(window.columns - 1);
+                            }
+                        }
+                    },
+                    None => return,
+                },
+                // SPC
+                0x91 => i += 3,
+                0x93 ..= 0x96 => { },
+                // SWA
+                0x97 => i += 4,
+                // DF0 .. DF7
+                0x98 ..= 0x9F => {
+                    let p = match params(i, 6) {
+                        Some(p) => p,
+                        None => return,
+                    };
+                    i += 6;
+
+                    let id = (code & 0x07) as usize;
+                    let visible = p[0] & 0x20 != 0;
+                    let rows = ((p[3] & 0x0F) as usize + 1).min(MAX_ROWS);
+                    let columns = ((p[4] & 0x3F) as usize + 1).min(MAX_COLUMNS);
+                    match self.windows[id] {
+                        Some(ref mut window) => {
+                            window.visible = visible;
+                            window.resize(rows, columns);
+                        },
+                        None => self.windows[id] = Some(Window::new(visible, rows, columns)),
+                    }
+                    self.current = Some(id);
+                },
+                // G1: ISO 8859-1
+                _ => self.write_char(code as char),
+            }
+        }
+    }
+}
+
+// G2 字符集
+fn g2_char(code: u8) -> char {
+    match code {
+        0x20 | 0x21 => ' ',
+        0x25 => '…',
+        0x2A => 'Š',
+        0x2C => 'Œ',
+        0x30 => '█',
+        0x31 => '‘',
+        0x32 => '’',
+        0x33 => '“',
+        0x34 => '”',
+        0x35 => '•',
+        0x39 => '™',
+        0x3A => 'š',
+        0x3C => 'œ',
+        0x3D => '℠',
+        0x3F => 'Ÿ',
+        0x76 => '⅛',
+        0x77 => '⅜',
+        0x78 => '⅝',
+        0x79 => '⅞',
+        0x7A => '│',
+        0x7B => '┐',
+        0x7C => '└',
+        0x7D => '─',
+        0x7E => '┘',
+        0x7F => '┌',
+        _ => '_',
+    }
+}
+
+
+// DTVCC 包的组装与各个服务的解码
+#[derive(Debug, Clone, Default)]
+pub struct Cea708Decoder {
+    packet: Vec<u8>,
+    last_sequence_number: Option<u8>,
+    services: BTreeMap<u8, Service>,
+    num_packets: u64,
+    // sequence_number 不连续的次数
+    sequence_errors: u64,
+    // 在下一个 DTVCC_packet_start 之前没有收完的包
+    incomplete_packets: u64,
+}
+
+impl Cea708Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 一个有效的 DTVCC 三字节组
+    pub fn push(&mut self, cc_type: CcType, cc_data_1: u8, cc_data_2: u8) {
+        match cc_type {
+            CcType::DtvccPacketStart => {
+                if !self.packet.is_empty() {
+                    self.incomplete_packets += 1;
+                }
+                self.packet = vec![cc_data_1, cc_data_2];
+            },
+            CcType::DtvccPacketData if !self.packet.is_empty() => {
+                self.packet.push(cc_data_1);
+                self.packet.push(cc_data_2);
+            },
+            _ => return,
+        }
+
+        // CEA-708 5.1: packet_size_code 为 0 时包长 128 字节, 否则为 packet_size_code * 2 ( 包括包头 )
+        let size_code = (self.packet[0] & 0x3F) as usize;
+        let size = if size_code == 0 { 128 } else { size_code * 2 };
+        if self.packet.len() >= size {
+            let mut packet = std::mem::take(&mut self.packet);
+            packet.truncate(size);
+            self.decode_packet(&packet);
+        }
+    }
+
+    // DTVCC_packet(): sequence_number ( 2 ) | packet_size_code ( 6 ) | service_block() ...
+    fn decode_packet(&mut self, packet: &[u8]) {
+        self.num_packets += 1;
+
+        let sequence_number = packet[0] >> 6;
+        if let Some(last) = self.last_sequence_number {
+            if (last + 1) % 4 != sequence_number {
+                self.sequence_errors += 1;
+            }
+        }
+        self.last_sequence_number = Some(sequence_number);
+
+        // service_block(): service_number ( 3 ) | block_size ( 5 ) [ | null_fill_bits ( 2 ) | extended_service_number ( 6 ) ]
+        let mut i = 1;
+        while i < packet.len() {
+            let mut service_number = packet[i] >> 5;
+            let block_size = (packet[i] & 0x1F) as usize;
+            i += 1;
+
+            if service_number == 0 {
+                break;
+            }
+            if service_number == 7 {
+                if i >= packet.len() {
+                    break;
+                }
+                service_number = packet[i] & 0x3F;
+                i += 1;
+            }
+
+            let end = (i + block_size).min(packet.len());
+            self.services.entry(service_number).or_default().decode(&packet[i..end]);
+            i = end;
+        }
+    }
+
+    // 一个图像的三字节组处理完之后调用
+    pub fn end_of_picture(&mut self, frame: u64) {
+        for service in self.services.values_mut() {
+            let text = service.text();
+            service.cues.update(frame, &text);
+        }
+    }
+
+    pub fn finish(&mut self, frame: u64) {
+        for service in self.services.values_mut() {
+            service.cues.finish(frame);
+        }
+    }
+
+    // 出现过的服务号
+    pub fn services(&self) -> Vec<u8> {
+        self.services.keys().cloned().collect()
+    }
+
+    pub fn cues(&self, service_number: u8) -> &[Cue] {
+        self.services.get(&service_number).map(|service| service.cues.cues()).unwrap_or(&[])
+    }
+
+    pub fn num_packets(&self) -> u64 {
+        self.num_packets
+    }
+
+    pub fn sequence_errors(&self) -> u64 {
+        self.sequence_errors
+    }
+
+    pub fn incomplete_packets(&self) -> u64 {
+        self.incomplete_packets
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Cea708Decoder;
+    use crate::caption::{ CcType, Cue };
+
+    fn push_packet(decoder: &mut Cea708Decoder, packet: &[u8]) {
+        for (i, pair) in packet.chunks(2).enumerate() {
+            let cc_type = if i == 0 { CcType::DtvccPacketStart } else { CcType::DtvccPacketData };
+            decoder.push(cc_type, pair[0], *pair.get(1).unwrap_or(&0));
+        }
+    }
+
+    #[test]
+    fn test_dtvcc_windows() {
+        let mut decoder = Cea708Decoder::new();
+
+        // sequence 0, 12 字节: service 1, 10 字节: DF0 ( 可见, 2 行 32 列 ), "HI", CR, 0x7F
+        push_packet(&mut decoder, &[
+            0x06, 0x2A, 0x98, 0x20, 0x00, 0x00, 0x01, 0x1F, 0x00, b'H', b'I', 0x0D,
+        ]);
+        decoder.end_of_picture(0);
+        // sequence 1, 4 字节: service 1, 2 字节: 0x7F, ETX
+        push_packet(&mut decoder, &[0x42, 0x22, 0x7F, 0x03]);
+        decoder.end_of_picture(1);
+        // sequence 3 ( 不连续 ), service 1: HDW 0x01
+        push_packet(&mut decoder, &[0xC2, 0x22, 0x8A, 0x01]);
+        decoder.end_of_picture(5);
+        // 没有收完的包
+        decoder.push(CcType::DtvccPacketStart, 0x08, 0x21);
+        decoder.push(CcType::DtvccPacketStart, 0x02, 0x00);
+        decoder.finish(6);
+
+        assert_eq!(decoder.services(), vec![1]);
+        assert_eq!(decoder.cues(1), &[
+            Cue { start: 0, end: 1, text: "HI".to_string() },
+            Cue { start: 1, end: 5, text: "HI\n♪".to_string() },
+        ]);
+        assert!(decoder.cues(2).is_empty());
+        assert_eq!(decoder.num_packets(), 3);
+        assert_eq!(decoder.sequence_errors(), 1);
+        assert_eq!(decoder.incomplete_packets(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+// 隐藏字幕 ( closed caption ): ATSC A/53 的 cc_data() 中的 CEA-608 字节对与 CEA-708 DTVCC 包,
+// 以及同样由 ATSC user data 携带的 Active Format Description 与 bar data。
+//
+// cc_data() 按解码顺序出现在每个访问单元的 SEI 中, 必须按输出顺序 ( PicOrderCnt ) 重新排列之后才能解码与比较,
+// 见 decoder::CaptionCollector。
+
+mod cc_data;
+mod afd;
+mod cea608;
+mod cea708;
+mod subtitle;
+
+pub use self::cc_data::{
+    CcType, CcTriplet, CcData,
+    ITU_T_T35_COUNTRY_CODE_US, ITU_T_T35_PROVIDER_CODE_ATSC, ATSC_USER_IDENTIFIER_GA94, USER_DATA_TYPE_CODE_CC_DATA,
+};
+pub use self::afd::{ Afd, BarData, AfdChange, AfdTracker, AFD_IDENTIFIER_DTG1, USER_DATA_TYPE_CODE_BAR_DATA };
+pub use self::cea608::{ Cea608Decoder, odd_parity };
+pub use self::cea708::Cea708Decoder;
+pub use self::subtitle::{ Cue, Timebase, to_srt, to_webvtt, to_scc };
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CaptionSummary {
+    pub num_pictures: u64,
+    // 带有 cc_data() 的图像个数
+    pub num_pictures_with_cc_data: u64,
+    // 有效的 CEA-608 字节对 ( field 1, field 2 )
+    pub num_cea608_pairs: [u64; 2],
+    pub cea608_parity_errors: u64,
+    pub num_dtvcc_packets: u64,
+    pub dtvcc_sequence_errors: u64,
+    pub dtvcc_incomplete_packets: u64,
+}
+
+
+// 按输出顺序解码一个码流中的所有字幕
+#[derive(Debug, Clone, Default)]
+pub struct CaptionTrack {
+    fields: [Cea608Decoder; 2],
+    dtvcc: Cea708Decoder,
+    // field 1 的字节对 ( 输出顺序中的帧序号, 字节对 ), 用于导出 SCC
+    field1_pairs: Vec<(u64, [u8; 2])>,
+    num_pictures: u64,
+    num_pictures_with_cc_data: u64,
+    num_cea608_pairs: [u64; 2],
+    next_frame: u64,
+}
+
+impl CaptionTrack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 输出顺序中第 output_index 帧的 cc_data()
+    pub fn push(&mut self, output_index: u64, cc_data: &[CcData]) {
+        self.num_pictures += 1;
+        if !cc_data.is_empty() {
+            self.num_pictures_with_cc_data += 1;
+        }
+
+        for triplet in cc_data.iter().flat_map(|cc_data| cc_data.valid_triplets()) {
+            match triplet.cc_type {
+                CcType::Ntsc608Field1 | CcType::Ntsc608Field2 => {
+                    let field = triplet.cc_type.bits() as usize;
+                    self.num_cea608_pairs[field] += 1;
+                    if field == 0 {
+                        self.field1_pairs.push((output_index, [triplet.cc_data_1, triplet.cc_data_2]));
+                    }
+                    self.fields[field].push(output_index, triplet.cc_data_1, triplet.cc_data_2);
+                },
+                CcType::DtvccPacketData | CcType::DtvccPacketStart => {
+                    self.dtvcc.push(triplet.cc_type, triplet.cc_data_1, triplet.cc_data_2);
+                },
+            }
+        }
+
+        for field in self.fields.iter_mut() {
+            field.end_of_picture(output_index);
+        }
+        self.dtvcc.end_of_picture(output_index);
+        self.next_frame = self.next_frame.max(output_index + 1);
+    }
+
+    // 码流结束, 关闭最后一帧之后仍在显示的字幕
+    pub fn finish(&mut self) {
+        for field in self.fields.iter_mut() {
+            field.finish(self.next_frame);
+        }
+        self.dtvcc.finish(self.next_frame);
+    }
+
+    // CEA-608 CC1 .. CC4, 其它 channel 没有字幕
+    pub fn cea608_cues(&self, channel: usize) -> &[Cue] {
+        if !(1..=4).contains(&channel) {
+            return &[];
+        }
+        self.fields[(channel - 1) / 2].cues((channel - 1) % 2)
+    }
+
+    // CEA-708 service 1 .. 63
+    pub fn cea708_cues(&self, service_number: u8) -> &[Cue] {
+        self.dtvcc.cues(service_number)
+    }
+
+    pub fn cea708_services(&self) -> Vec<u8> {
+        self.dtvcc.services()
+    }
+
+    pub fn scc_pairs(&self) -> &[(u64, [u8; 2])] {
+        &self.field1_pairs
+    }
+
+    pub fn summary(&self) -> CaptionSummary {
+        CaptionSummary {
+            num_pictures: self.num_pictures,
+            num_pictures_with_cc_data: self.num_pictures_with_cc_data,
+            num_cea608_pairs: self.num_cea608_pairs,
+            cea608_parity_errors: self.fields[0].parity_errors() + self.fields[1].parity_errors(),
+            num_dtvcc_packets: self.dtvcc.num_packets(),
+            dtvcc_sequence_errors: self.dtvcc.sequence_errors(),
+            dtvcc_incomplete_packets: self.dtvcc.incomplete_packets(),
+        }
+    }
+}
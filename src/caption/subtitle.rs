@@ -0,0 +1,200 @@
+// 字幕的时间轴以及 SRT, WebVTT, SCC ( Scenarist Closed Caption ) 格式的导出。
+//
+// 时间以输出顺序中的帧序号表示, 导出时按 Timebase 转换。
+
+use crate::rbsp::VuiParameters;
+use crate::decoder::Timecode;
+
+use std::fmt::Write;
+
+
+// 一条字幕: 在输出顺序中第 start 帧显示, 第 end 帧消失
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+// 显示内容变化时产生 Cue
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CueBuilder {
+    current: Option<(u64, String)>,
+    cues: Vec<Cue>,
+}
+
+impl CueBuilder {
+    // 第 frame 帧开始显示 text ( 空字符串表示没有字幕 )
+    pub(crate) fn update(&mut self, frame: u64, text: &str) {
+        if let Some((_, ref current)) = self.current {
+            if current == text {
+                return;
+            }
+        }
+
+        if let Some((start, current)) = self.current.take() {
+            if frame > start {
+                self.cues.push(Cue { start: start, end: frame, text: current });
+            }
+        }
+
+        if !text.is_empty() {
+            self.current = Some((frame, text.to_string()));
+        }
+    }
+
+    pub(crate) fn finish(&mut self, frame: u64) {
+        self.update(frame, "");
+    }
+
+    pub(crate) fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+}
+
+
+// 每帧的时长为 num_units_in_frame / time_scale 秒
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timebase {
+    pub num_units_in_frame: u32,
+    pub time_scale: u32,
+}
+
+impl Timebase {
+    // E.2.1: 一帧为两个 tick ( num_units_in_tick 对应一个场 )
+    pub fn from_vui(vui: &VuiParameters) -> Option<Self> {
+        match (vui.num_units_in_tick, vui.time_scale) {
+            (Some(num_units_in_tick), Some(time_scale)) if num_units_in_tick > 0 && time_scale > 0 => Some(Timebase {
+                num_units_in_frame: num_units_in_tick.saturating_mul(2),
+                time_scale: time_scale,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn millis(&self, frame: u64) -> u64 {
+        frame * self.num_units_in_frame as u64 * 1000 / self.time_scale as u64
+    }
+
+    // SCC 时间码的每秒帧数 ( 取整 )
+    pub fn timecode_rate(&self) -> u64 {
+        ((self.time_scale as u64 + self.num_units_in_frame as u64 / 2) / self.num_units_in_frame as u64).max(1)
+    }
+
+    // 29.97 与 59.94 使用 drop-frame 时间码
+    pub fn drop_frame(&self) -> bool {
+        let rate = self.timecode_rate();
+        (rate == 30 || rate == 60) && self.time_scale as u64 * 1001 == rate * 1000 * self.num_units_in_frame as u64
+    }
+
+    // HH:MM:SS:FF, drop-frame 时为 HH:MM:SS;FF ( SMPTE 12M ), 59.94 时每分钟丢弃 4 个帧号
+    pub fn timecode(&self, frame: u64) -> String {
+        Timecode::from_smpte_frame_count(frame as i64, self.timecode_rate() as u32, self.drop_frame()).to_string()
+    }
+}
+
+fn clock_time(millis: u64, fraction_separator: char) -> String {
+    format!("{:02}:{:02}:{:02}{}{:03}",
+            millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, fraction_separator, millis % 1000)
+}
+
+// SubRip:
+//
+// 1
+// 00:00:01,000 --> 00:00:02,500
+// text
+pub fn to_srt(cues: &[Cue], timebase: &Timebase) -> String {
+    let mut srt = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        writeln!(srt, "{}", index + 1).unwrap();
+        writeln!(srt, "{} --> {}",
+                 clock_time(timebase.millis(cue.start), ','), clock_time(timebase.millis(cue.end), ',')).unwrap();
+        writeln!(srt, "{}\n", cue.text).unwrap();
+    }
+    srt
+}
+
+// WebVTT:
+//
+// WEBVTT
+//
+// 00:00:01.000 --> 00:00:02.500
+// text
+pub fn to_webvtt(cues: &[Cue], timebase: &Timebase) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        writeln!(vtt, "{} --> {}",
+                 clock_time(timebase.millis(cue.start), '.'), clock_time(timebase.millis(cue.end), '.')).unwrap();
+        // WebVTT 的文本中不能出现 "-->", "&" 与 "<" 需要转义
+        let text = cue.text.replace('&', "&amp;").replace('<', "&lt;").replace("-->", "--&gt;");
+        writeln!(vtt, "{}\n", text).unwrap();
+    }
+    vtt
+}
+
+// Scenarist SCC: 每行为一个时间码以及从该帧开始连续的 CEA-608 field 1 字节对 ( 包括奇校验位 )。
+// `pairs` 为 ( 帧序号, cc_data_1, cc_data_2 ), 按帧序号排列, 其中的填充字节对 ( 0x80 0x80 ) 会被跳过。
+pub fn to_scc(pairs: &[(u64, [u8; 2])], timebase: &Timebase) -> String {
+    let mut scc = String::from("Scenarist_SCC V1.0\n");
+    let mut next_frame = None;
+
+    for &(frame, pair) in pairs {
+        if pair == [0x80, 0x80] {
+            continue;
+        }
+
+        if next_frame != Some(frame) {
+            write!(scc, "\n{}\t", timebase.timecode(frame)).unwrap();
+        } else {
+            scc.push(' ');
+        }
+        write!(scc, "{:02x}{:02x}", pair[0], pair[1]).unwrap();
+        next_frame = Some(frame + 1);
+    }
+
+    if next_frame.is_some() {
+        scc.push('\n');
+    }
+    scc
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ Cue, CueBuilder, Timebase, to_srt, to_webvtt, to_scc };
+
+    #[test]
+    fn test_cue_builder_and_formats() {
+        let mut builder = CueBuilder::default();
+        builder.update(0, "");
+        builder.update(30, "HELLO");
+        builder.update(31, "HELLO");
+        builder.update(75, "A <B>\nC");
+        builder.finish(90);
+        assert_eq!(builder.cues(), &[
+            Cue { start: 30, end: 75, text: "HELLO".to_string() },
+            Cue { start: 75, end: 90, text: "A <B>\nC".to_string() },
+        ]);
+
+        let timebase = Timebase { num_units_in_frame: 1001, time_scale: 30000 };
+        assert_eq!(to_srt(builder.cues(), &timebase),
+                   "1\n00:00:01,001 --> 00:00:02,502\nHELLO\n\n2\n00:00:02,502 --> 00:00:03,003\nA <B>\nC\n\n");
+        assert_eq!(to_webvtt(&builder.cues()[1..], &timebase),
+                   "WEBVTT\n\n00:00:02.502 --> 00:00:03.003\nA &lt;B>\nC\n\n");
+
+        // 29.97 drop-frame: 第 1800 帧为 00:01:00;02
+        assert!(timebase.drop_frame());
+        assert_eq!(timebase.timecode(1799), "00:00:59;29");
+        assert_eq!(timebase.timecode(1800), "00:01:00;02");
+        assert_eq!(timebase.timecode(17982), "00:10:00;00");
+        // 59.94: 每分钟丢弃 4 个帧号
+        let timebase_59_94 = Timebase { num_units_in_frame: 1001, time_scale: 60000 };
+        assert!(timebase_59_94.drop_frame());
+        assert_eq!(timebase_59_94.timecode(3600), "00:01:00;04");
+        assert!(!Timebase { num_units_in_frame: 1, time_scale: 25 }.drop_frame());
+        assert_eq!(Timebase { num_units_in_frame: 1, time_scale: 25 }.timecode(26), "00:00:01:01");
+
+        let pairs = [(0, [0x94, 0x20]), (1, [0x94, 0x20]), (2, [0x80, 0x80]), (3, [0xc8, 0x49])];
+        assert_eq!(to_scc(&pairs, &timebase), "Scenarist_SCC V1.0\n\n00:00:00;00\t9420 9420\n00:00:00;03\tc849\n");
+    }
+}
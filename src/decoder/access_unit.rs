@@ -0,0 +1,123 @@
+// 把访问单元中 ( 第一个 slice 之前 ) 的 SEI 等数据与 DpbModel 报告的图像对应起来, 供按解码顺序或输出顺序
+// 报告 SEI 的收集器使用。
+//
+// 7.4.1.2.4: 主编码图像的第一个 VCL NALU 开始一个新的图像 ( 冗余编码图像的 slice 不参与判断 ); DpbModel 对每个
+// 图像按解码顺序产生一个 PictureReport, 与这里按相同顺序记录的数据一一对应。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ ParameterSets, SliceHeader };
+use crate::slice::Slice;
+use super::model::{ DpbModel, PictureReport, OutputReport };
+use super::first_vcl_nal_unit_of_picture;
+
+use std::collections::VecDeque;
+
+
+// 按解码顺序检测主编码图像的第一个 VCL NALU
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PictureBoundary {
+    prev_header: Option<SliceHeader>,
+}
+
+impl PictureBoundary {
+    // 返回 header 所在的 slice 是否开始一个新的图像; 冗余编码图像的 slice 总是返回 false
+    pub fn is_new_picture(&mut self, header: &SliceHeader) -> bool {
+        if header.redundant_pic_cnt.unwrap_or(0) > 0 {
+            return false;
+        }
+
+        let new_picture = match self.prev_header {
+            Some(ref prev) => first_vcl_nal_unit_of_picture(prev, header),
+            None => true,
+        };
+        self.prev_header = Some(header.clone());
+        new_picture
+    }
+}
+
+// T: 访问单元中第一个 slice 之前收集的数据; U: 图像开始时由 T 得到的数据, 与 PictureReport 一起取出
+#[derive(Debug)]
+pub(crate) struct AccessUnitTracker<T, U = T> {
+    model: DpbModel,
+    boundary: PictureBoundary,
+    access_unit: T,
+    // 已开始解码但还没有报告的图像, 按解码顺序
+    decoding: VecDeque<U>,
+}
+
+impl<T: Default, U> Default for AccessUnitTracker<T, U> {
+    fn default() -> Self {
+        Self {
+            model: DpbModel::new(),
+            boundary: PictureBoundary::default(),
+            access_unit: T::default(),
+            decoding: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: Default, U: Default> AccessUnitTracker<T, U> {
+    // 当前访问单元的数据
+    pub fn access_unit(&mut self) -> &mut T {
+        &mut self.access_unit
+    }
+
+    // 处理一个 NALU; 新图像的第一个 slice 由 start 把当前访问单元的数据转换为这个图像的数据
+    pub fn push<F>(&mut self, nalu: &Nalu, start: F) -> Result<(), Error>
+    where
+        F: FnOnce(&ParameterSets, &SliceHeader, T) -> Result<U, Error>,
+    {
+        if let NaluKind::CodedSliceIdr | NaluKind::CodedSliceNonIdr = nalu.kind() {
+            let header = Slice::parse_header(nalu, self.model.parameter_sets())?;
+            if self.boundary.is_new_picture(&header) {
+                let access_unit = std::mem::take(&mut self.access_unit);
+                let picture = start(self.model.parameter_sets(), &header, access_unit)?;
+                self.decoding.push_back(picture);
+            }
+        }
+
+        self.model.push(nalu)
+    }
+
+    // 码流结束: 完成最后一个图像并输出所有等待输出的图像
+    pub fn flush(&mut self) {
+        self.model.flush();
+    }
+
+    // 取出下一个 ( 按解码顺序 ) 已完成的图像的报告以及它的数据
+    pub fn next_report(&mut self) -> Option<(PictureReport, U)> {
+        let report = self.model.next_report()?;
+        let picture = self.decoding.pop_front().unwrap_or_default();
+        Some((report, picture))
+    }
+
+    // 取出下一个 ( 按输出顺序 ) 输出的图像
+    pub fn next_output(&mut self) -> Option<OutputReport> {
+        self.model.next_output()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::PictureBoundary;
+    use crate::slice::Slice;
+    use crate::slice::test::{ Writer, parameter_sets };
+
+    #[test]
+    fn test_picture_boundary() {
+        let parameter_sets = parameter_sets();
+        let p_slice = |frame_num, first_mb| {
+            let nalu = Writer::new(0x41).ue(first_mb).ue(5).ue(0).u(4, frame_num).u(4, 2 * frame_num)
+                .u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish();
+            Slice::parse_header(&nalu, &parameter_sets).unwrap()
+        };
+
+        // 同一个图像的第二个 slice 不开始新的图像
+        let mut boundary = PictureBoundary::default();
+        assert!(boundary.is_new_picture(&p_slice(1, 0)));
+        assert!(!boundary.is_new_picture(&p_slice(1, 1)));
+        assert!(boundary.is_new_picture(&p_slice(2, 0)));
+    }
+}
@@ -0,0 +1,197 @@
+// 把每个访问单元 SEI 中的 cc_data(), AFD 与 bar data 按输出顺序 ( C.4.5 的 "bumping" 过程 ) 重新排列。
+// 只解析 slice 头部, 由 DpbModel 确定输出顺序。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ SupplementalEnhancementInformation, SeiPayload };
+use crate::caption::{ CcData, Afd, BarData, Timebase };
+use super::access_unit::AccessUnitTracker;
+
+use std::collections::{ HashMap, VecDeque };
+
+
+// 一个访问单元中 ( 第一个 slice 之前 ) 的 ATSC user data
+#[derive(Debug, Clone, Default)]
+struct AccessUnitData {
+    cc_data: Vec<CcData>,
+    afd: Option<Afd>,
+    bar_data: Option<BarData>,
+}
+
+// 按输出顺序的一个图像 ( 帧, 互补场对或单个场 ) 以及它的访问单元中的 ATSC user data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptionPicture {
+    // PictureReport::id
+    pub id: u64,
+    pub pic_order_cnt: i32,
+    pub output_index: u64,
+    // IDR 图像或只包含 I / SI slice 的图像
+    pub intra: bool,
+    // 按解码顺序, 互补场对包括两个场的 cc_data()
+    pub cc_data: Vec<CcData>,
+    // 互补场对中后一个场的值优先
+    pub afd: Option<Afd>,
+    pub bar_data: Option<BarData>,
+}
+
+#[derive(Debug, Default)]
+pub struct CaptionCollector {
+    tracker: AccessUnitTracker<AccessUnitData>,
+    // 已解码完成但还没有输出的图像: ( intra, user data )
+    by_id: HashMap<u64, (bool, AccessUnitData)>,
+    pictures: VecDeque<CaptionPicture>,
+    timebase: Option<Timebase>,
+}
+
+impl CaptionCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        match nalu.kind() {
+            NaluKind::SupplementalEnhancementInformation => {
+                let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+                let access_unit = self.tracker.access_unit();
+                for message in sei.messages() {
+                    if let SeiPayload::UserDataRegisteredItuTT35(ref user_data) = message.payload {
+                        if let Some(cc_data) = CcData::from_user_data(user_data)? {
+                            access_unit.cc_data.push(cc_data);
+                        }
+                        if let Some(afd) = Afd::from_user_data(user_data)? {
+                            access_unit.afd = Some(afd);
+                        }
+                        if let Some(bar_data) = BarData::from_user_data(user_data)? {
+                            access_unit.bar_data = Some(bar_data);
+                        }
+                    }
+                }
+                Ok(())
+            },
+            _ => {
+                let timebase = &mut self.timebase;
+                self.tracker.push(nalu, |parameter_sets, header, access_unit| {
+                    let vui = parameter_sets.pps(header.pic_parameter_set_id)
+                        .and_then(|pps| parameter_sets.sps(pps.seq_parameter_set_id()))
+                        .and_then(|sps| sps.vui_parameters());
+                    if let Some(vui_timebase) = vui.and_then(Timebase::from_vui) {
+                        *timebase = Some(vui_timebase);
+                    }
+                    Ok(access_unit)
+                })?;
+                self.drain();
+                Ok(())
+            },
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Some((report, data)) = self.tracker.next_report() {
+            let intra = report.idr || report.slices.iter().all(|slice| slice.slice_type.is_intra());
+
+            let entry = self.by_id.entry(report.id).or_insert((true, AccessUnitData::default()));
+            entry.0 &= intra;
+            entry.1.cc_data.extend(data.cc_data);
+            entry.1.afd = data.afd.or(entry.1.afd);
+            entry.1.bar_data = data.bar_data.or(entry.1.bar_data);
+        }
+
+        while let Some(output) = self.tracker.next_output() {
+            let (intra, data) = self.by_id.remove(&output.id).unwrap_or_default();
+            self.pictures.push_back(CaptionPicture {
+                id: output.id,
+                pic_order_cnt: output.pic_order_cnt,
+                output_index: output.output_index,
+                intra: intra,
+                cc_data: data.cc_data,
+                afd: data.afd,
+                bar_data: data.bar_data,
+            });
+        }
+    }
+
+    // 码流结束: 输出所有等待输出的图像
+    pub fn flush(&mut self) {
+        self.tracker.flush();
+        self.drain();
+    }
+
+    // 取出下一个 ( 按输出顺序 ) 输出的图像
+    pub fn next_picture(&mut self) -> Option<CaptionPicture> {
+        self.pictures.pop_front()
+    }
+
+    // 活动 SPS 的 VUI 中的帧率
+    pub fn timebase(&self) -> Option<Timebase> {
+        self.timebase
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::CaptionCollector;
+    use crate::nalu::Nalu;
+    use crate::slice::test::{ Writer, parameter_set_nalus };
+    use crate::caption::{ CaptionTrack, Cue, Afd };
+
+    // 带有一个 CEA-608 field 1 字节对的 GA94 SEI
+    fn cc_sei(pair: [u8; 2]) -> Nalu {
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 4).u(8, 14);
+        for &byte in [0xB5, 0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, 0xC1, 0xFF, 0xFC, pair[0], pair[1], 0xFF].iter() {
+            writer.u(8, byte as u32);
+        }
+        writer.finish()
+    }
+
+    // DTG1 afd_data()
+    fn afd_sei(active_format: u8) -> Nalu {
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 4).u(8, 9);
+        for &byte in [0xB5, 0x00, 0x31, b'D', b'T', b'G', b'1', 0x41, 0xF0 | active_format].iter() {
+            writer.u(8, byte as u32);
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_output_order() {
+        let (sps, pps) = parameter_set_nalus();
+        let mut collector = CaptionCollector::new();
+        collector.push(&sps).unwrap();
+        collector.push(&pps).unwrap();
+
+        // 解码顺序: I ( POC 0 ), P ( POC 4 ), P ( POC 2, 非参考 ); 输出顺序中的字节对: RCL, "HI", EOC
+        let idr = Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish();
+        let p4 = Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 1).u(4, 4).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish();
+        let p2 = Writer::new(0x01).ue(0).ue(5).ue(0).u(4, 2).u(4, 2).u(1, 0).u(1, 0).se(0).ue(1).finish();
+        // P ( POC 4 ) 的访问单元带有 AFD 9
+        for (i, (sei, slice)) in [(cc_sei([0x94, 0x20]), idr), (cc_sei([0x94, 0x2F]), p4), (cc_sei([0xC8, 0x49]), p2)].iter().enumerate() {
+            if i == 1 {
+                collector.push(&afd_sei(9)).unwrap();
+            }
+            collector.push(sei).unwrap();
+            collector.push(slice).unwrap();
+        }
+        collector.flush();
+
+        let mut track = CaptionTrack::new();
+        let mut pics = vec![];
+        let mut afd = vec![];
+        while let Some(picture) = collector.next_picture() {
+            track.push(picture.output_index, &picture.cc_data);
+            pics.push((picture.pic_order_cnt, picture.output_index, picture.cc_data[0].triplets[0].cc_data_1));
+            afd.push((picture.intra, picture.afd));
+        }
+        track.finish();
+
+        assert_eq!(pics, vec![(0, 0, 0x94), (2, 1, 0xC8), (4, 2, 0x94)]);
+        assert_eq!(afd, vec![(true, None), (false, None), (false, Some(Afd { active_format: Some(9) }))]);
+        assert_eq!(track.cea608_cues(1), &[Cue { start: 2, end: 3, text: "HI".to_string() }]);
+        assert!(track.cea608_cues(0).is_empty());
+        assert!(track.cea608_cues(5).is_empty());
+        assert_eq!(track.summary().num_pictures_with_cc_data, 3);
+        assert_eq!(track.scc_pairs(), &[(0, [0x94, 0x20]), (1, [0xC8, 0x49]), (2, [0x94, 0x2F])]);
+    }
+}
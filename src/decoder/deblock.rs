@@ -0,0 +1,792 @@
+// 8.7 Deblocking filter process ( Page 197 )
+//
+// 按宏块地址的顺序, 对每个宏块先滤波亮度的垂直边与水平边, 再滤波色度的垂直边与水平边。
+// 样本的位置都按图像中的坐标计算, 因此帧宏块, 场宏块 ( MBAFF ) 与场图像可以使用相同的过程。
+
+use crate::rbsp::SliceType;
+use crate::macroblock::MbType;
+use super::picture::{ Picture, Plane, MbState, SliceParams, RefPicId };
+use super::reconstruct::chroma_qp;
+
+
+// Table 8-16 – Derivation of offset dependent threshold variables α′ and β′ from indexA and indexB ( Page 206 )
+const ALPHA: [i32; 52] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    4, 4, 5, 6, 7, 8, 9, 10, 12, 13, 15, 17, 20, 22, 25, 28,
+    32, 36, 40, 45, 50, 56, 63, 71, 80, 90, 101, 113, 127, 144, 162, 182,
+    203, 226, 255, 255,
+];
+
+const BETA: [i32; 52] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 6, 6, 7, 7, 8, 8,
+    9, 9, 10, 10, 11, 11, 12, 12, 13, 13, 14, 14, 15, 15, 16, 16,
+    17, 17, 18, 18,
+];
+
+// Table 8-17 – Value of variable t′C0 as a function of indexA and bS ( Page 207 )
+const TC0: [[i32; 3]; 52] = [
+    [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [0, 0, 0], [0, 0, 1], [0, 0, 1], [0, 0, 1], [0, 0, 1], [0, 1, 1], [0, 1, 1], [1, 1, 1],
+    [1, 1, 1], [1, 1, 1], [1, 1, 1], [1, 1, 2], [1, 1, 2], [1, 1, 2], [1, 1, 2], [1, 2, 3],
+    [1, 2, 3], [2, 2, 3], [2, 2, 4], [2, 3, 4], [2, 3, 4], [3, 3, 5], [3, 4, 6], [3, 4, 6],
+    [4, 5, 7], [4, 5, 8], [4, 6, 9], [5, 7, 10], [6, 8, 11], [6, 8, 13], [7, 10, 14], [8, 11, 16],
+    [9, 12, 18], [10, 13, 20], [11, 15, 23], [13, 17, 25],
+];
+
+
+// 8.7.2.2 Derivation process for the thresholds for each block edge ( Page 205 )
+//
+// 由 qPav 与 FilterOffsetA/B 得到 ( α, β, tC0 ), 均已按位深缩放
+pub fn thresholds(qp_av: i32, filter_offset_a: i32, filter_offset_b: i32, bs: u8, bit_depth: u32) -> (i32, i32, i32) {
+    let index_a = (qp_av + filter_offset_a).clamp(0, 51) as usize;
+    let index_b = (qp_av + filter_offset_b).clamp(0, 51) as usize;
+    let scale = 1 << (bit_depth - 8);
+
+    let tc0 = if bs > 0 && bs < 4 { TC0[index_a][bs as usize - 1] } else { 0 };
+    (ALPHA[index_a] * scale, BETA[index_b] * scale, tc0 * scale)
+}
+
+// 8.7.2.3 Filtering process for edges with bS less than 4 ( Page 207 )
+// 8.7.2.4 Filtering process for edges with bS equal to 4 ( Page 208 )
+//
+// 对边两侧的样本 p[ 0 .. 3 ], q[ 0 .. 3 ] 滤波, 返回 filterSamplesFlag;
+// chromaStyleFilteringFlag 为 1 时只使用并修改 p0, p1, q0, q1 中的 p0 与 q0
+#[allow(clippy::too_many_arguments)]
+pub fn filter_samples(p: &mut [i32; 4], q: &mut [i32; 4], bs: u8, chroma_style: bool,
+                      alpha: i32, beta: i32, tc0: i32, bit_depth: u32) -> bool {
+    let filter = bs > 0
+        && (p[0] - q[0]).abs() < alpha
+        && (p[1] - p[0]).abs() < beta
+        && (q[1] - q[0]).abs() < beta;
+    if !filter {
+        return false;
+    }
+
+    let max = (1 << bit_depth) - 1;
+    let clip1 = |value: i32| value.max(0).min(max);
+    let (p0, p1, q0, q1) = (p[0], p[1], q[0], q[1]);
+
+    if bs < 4 {
+        let (ap, aq) = if chroma_style { (beta, beta) } else { ((p[2] - p0).abs(), (q[2] - q0).abs()) };
+        let tc = if chroma_style {
+            tc0 + 1
+        } else {
+            tc0 + (ap < beta) as i32 + (aq < beta) as i32
+        };
+
+        let delta = ((((q0 - p0) << 2) + (p1 - q1) + 4) >> 3).max(-tc).min(tc);
+        p[0] = clip1(p0 + delta);
+        q[0] = clip1(q0 - delta);
+
+        if !chroma_style && ap < beta {
+            p[1] = p1 + ((p[2] + ((p0 + q0 + 1) >> 1) - (p1 << 1)) >> 1).max(-tc0).min(tc0);
+        }
+        if !chroma_style && aq < beta {
+            q[1] = q1 + ((q[2] + ((p0 + q0 + 1) >> 1) - (q1 << 1)) >> 1).max(-tc0).min(tc0);
+        }
+    } else {
+        let strong = |p: &mut [i32; 4], q: &[i32; 4]| {
+            let (p0, p1, p2, p3, q0, q1) = (p[0], p[1], p[2], p[3], q[0], q[1]);
+            let ap = (p2 - p0).abs();
+            if !chroma_style && ap < beta && (p0 - q0).abs() < ((alpha >> 2) + 2) {
+                p[0] = (p2 + 2 * p1 + 2 * p0 + 2 * q0 + q1 + 4) >> 3;
+                p[1] = (p2 + p1 + p0 + q0 + 2) >> 2;
+                p[2] = (2 * p3 + 3 * p2 + p1 + p0 + q0 + 4) >> 3;
+            } else {
+                p[0] = (2 * p1 + p0 + q1 + 2) >> 2;
+            }
+        };
+
+        let (p_in, q_in) = (*p, *q);
+        strong(p, &q_in);
+        strong(q, &p_in);
+    }
+
+    true
+}
+
+
+// 分区的参考图像 ( 场宏块参考的是场 ) 及运动向量
+type Motion = (Option<RefPicId>, [i32; 2]);
+
+// 边的一侧: 宏块地址与亮度样本在宏块内的位置
+#[derive(Debug, Clone, Copy)]
+struct Side<'a> {
+    addr: u32,
+    state: &'a MbState,
+    x: i32,
+    y: i32,
+}
+
+struct Deblocker<'a> {
+    // 当前帧或当前场的宏块
+    mbs: &'a [Option<MbState>],
+    slices: &'a [SliceParams],
+    width_in_mbs: u32,
+    mbaff: bool,
+    field_pic: bool,
+    bottom_field: bool,
+    chroma_array_type: u32,
+    // SubWidthC, SubHeightC
+    sub_width_c: i32,
+    sub_height_c: i32,
+}
+
+// 对图像 ( 场图像时为当前场 ) 进行去块滤波; 没有解码的宏块 ( 例如丢失的 slice ) 以及它们的边被跳过
+pub(crate) fn deblock_picture(picture: &mut Picture) {
+    let (sub_width_c, sub_height_c) = match picture.cb.as_ref() {
+        Some(cb) => ((picture.luma.width / cb.width) as i32, (picture.luma.height / cb.height) as i32),
+        None => (1, 1),
+    };
+
+    let range = picture.current_mb_range();
+    let deblocker = Deblocker {
+        mbs: &picture.mbs[range],
+        slices: &picture.slices,
+        width_in_mbs: picture.width_in_mbs,
+        mbaff: picture.mbaff_frame_flag,
+        field_pic: picture.field_pic_flag,
+        bottom_field: picture.bottom_field_flag,
+        chroma_array_type: picture.chroma_array_type(),
+        sub_width_c: sub_width_c,
+        sub_height_c: sub_height_c,
+    };
+
+    let bit_depth = [picture.bit_depth(0), picture.bit_depth(1)];
+    let mut planes = [Some(&mut picture.luma), picture.cb.as_mut(), picture.cr.as_mut()];
+
+    // 宏块之间有依赖 ( 后面的宏块使用前面已滤波的样本 ), 但分量之间是独立的
+    for (comp, plane) in planes.iter_mut().enumerate() {
+        let plane = match plane {
+            Some(plane) => plane,
+            None => continue,
+        };
+
+        for addr in 0..deblocker.mbs.len() as u32 {
+            deblocker.macroblock(plane, comp, addr, bit_depth[comp.min(1)]);
+        }
+    }
+}
+
+impl<'a> Deblocker<'a> {
+    fn state(&self, addr: u32) -> Option<&'a MbState> {
+        self.mbs.get(addr as usize)?.as_ref()
+    }
+
+    // 分量在一个宏块内的宽高
+    fn mb_size(&self, comp: usize) -> (i32, i32) {
+        if comp == 0 || self.chroma_array_type == 3 {
+            (16, 16)
+        } else {
+            (16 / self.sub_width_c, 16 / self.sub_height_c)
+        }
+    }
+
+    // 6.4.1 Inverse macroblock scanning process: 宏块第一行的第一个样本的位置, 以及宏块内相邻两行在图像中的距离
+    fn origin(&self, addr: u32, width: i32, height: i32) -> (i32, i32, i32) {
+        if self.field_pic {
+            let x = (addr % self.width_in_mbs) as i32 * width;
+            let y = (addr / self.width_in_mbs) as i32 * height * 2;
+            return (x, y + self.bottom_field as i32, 2);
+        }
+
+        if !self.mbaff {
+            let x = (addr % self.width_in_mbs) as i32 * width;
+            let y = (addr / self.width_in_mbs) as i32 * height;
+            return (x, y, 1);
+        }
+
+        let pair = addr / 2;
+        let x = (pair % self.width_in_mbs) as i32 * width;
+        let y = (pair / self.width_in_mbs) as i32 * height * 2;
+        let bottom = (addr % 2) as i32;
+
+        match self.state(addr) {
+            Some(state) if state.field => (x, y + bottom, 2),
+            _ => (x, y + bottom * height, 1),
+        }
+    }
+
+    // 图像中的样本 ( x, y ) 所在的宏块, 以及它在宏块内的位置
+    fn locate(&self, x: i32, y: i32, width: i32, height: i32) -> Option<(u32, i32, i32)> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+
+        // 场图像中只有当前场的行属于已解码的宏块
+        if self.field_pic {
+            if y % 2 != self.bottom_field as i32 {
+                return None;
+            }
+            let y = y / 2;
+            let addr = (y / height) as u32 * self.width_in_mbs + (x / width) as u32;
+            return Some((addr, x % width, y % height));
+        }
+
+        if !self.mbaff {
+            let addr = (y / height) as u32 * self.width_in_mbs + (x / width) as u32;
+            return Some((addr, x % width, y % height));
+        }
+
+        let top = 2 * ((y / (height * 2)) as u32 * self.width_in_mbs + (x / width) as u32);
+        let row = y % (height * 2);
+        if self.state(top)?.field {
+            Some((top + (row % 2) as u32, x % width, row / 2))
+        } else {
+            Some((top + (row / height) as u32, x % width, row % height))
+        }
+    }
+
+    // 8.7.2.1 中 "the macroblock ... is a field macroblock"
+    fn is_field(&self, state: &MbState) -> bool {
+        self.field_pic || state.field
+    }
+
+    fn is_switching(&self, state: &MbState) -> bool {
+        let slice_type = self.slices[state.slice_num].slice_type;
+        slice_type == SliceType::SP || slice_type == SliceType::SI
+    }
+
+    // 8.7.2.1 Derivation process for the luma content dependent boundary filtering strength ( Page 202 )
+    //
+    // `q` 为包含 q0 的亮度样本, `p_step` 为水平边上 q0 与 p0 在图像中相隔的行数
+    fn boundary_strength(&self, q: Side, vertical: bool, mb_edge: bool, p_step: i32) -> u8 {
+        let (x0, y0, step) = self.origin(q.addr, 16, 16);
+        let (x, y) = (x0 + q.x, y0 + q.y * step);
+        let located = if vertical { self.locate(x - 1, y, 16, 16) } else { self.locate(x, y - p_step, 16, 16) };
+
+        let p = match located.and_then(|(addr, x, y)| Some(Side { addr: addr, state: self.state(addr)?, x: x, y: y })) {
+            Some(p) => p,
+            None => return 0,
+        };
+
+        let (p_field, q_field) = (self.is_field(p.state), self.is_field(q.state));
+        let mixed_mode_edge = self.mbaff && p.state.field != q.state.field;
+
+        let intra = p.state.mb_type.is_intra() || q.state.mb_type.is_intra()
+            || self.is_switching(p.state) || self.is_switching(q.state);
+        if intra && mb_edge && ((!p_field && !q_field) || vertical) {
+            return 4;
+        }
+        if intra {
+            return 3;
+        }
+
+        let non_zero = |side: &Side| side.state.non_zero & (1 << (side.y / 4 * 4 + side.x / 4)) != 0;
+        if non_zero(&p) || non_zero(&q) {
+            return 2;
+        }
+
+        if mixed_mode_edge {
+            return 1;
+        }
+
+        // 垂直分量之差的阈值为 4 个 1/4 帧样本, 即 2 个 1/4 场样本
+        let mv_limit = if q_field { 2 } else { 4 };
+        if self.different_motion(&p, &q, mv_limit) { 1 } else { 0 }
+    }
+
+    // 分区使用的参考图像与运动向量
+    fn motion(&self, side: &Side) -> Vec<Motion> {
+        let part = (side.y / 8 * 2 + side.x / 8) as usize;
+        let blk = (side.y / 4 * 4 + side.x / 4) as usize;
+
+        (0..2).filter(|&list| side.state.ref_idx[list][part] >= 0)
+            .map(|list| (side.state.ref_pic[list][part], side.state.mv[list][blk]))
+            .collect()
+    }
+
+    // 8.7.2.1 中 bS 为 1 的运动条件 ( mixedModeEdgeFlag 为 0 )
+    fn different_motion(&self, p: &Side, q: &Side, mv_limit: i32) -> bool {
+        let far = |a: [i32; 2], b: [i32; 2]| (a[0] - b[0]).abs() >= 4 || (a[1] - b[1]).abs() >= mv_limit;
+
+        let p = self.motion(p);
+        let q = self.motion(q);
+
+        match (p.len(), q.len()) {
+            (1, 1) => p[0].0 != q[0].0 || far(p[0].1, q[0].1),
+            (2, 2) => {
+                let same_pictures = (p[0].0 == q[0].0 && p[1].0 == q[1].0) || (p[0].0 == q[1].0 && p[1].0 == q[0].0);
+                if !same_pictures {
+                    return true;
+                }
+
+                if p[0].0 != p[1].0 {
+                    // 两个不同的参考图像: 比较参考同一图像的运动向量
+                    if p[0].0 == q[0].0 {
+                        far(p[0].1, q[0].1) || far(p[1].1, q[1].1)
+                    } else {
+                        far(p[0].1, q[1].1) || far(p[1].1, q[0].1)
+                    }
+                } else {
+                    // 两个运动向量都参考同一图像
+                    (far(p[0].1, q[0].1) || far(p[1].1, q[1].1)) && (far(p[0].1, q[1].1) || far(p[1].1, q[0].1))
+                }
+            },
+            // 运动向量的个数不同
+            _ => true,
+        }
+    }
+
+    // 8.7.2.2 中的 qPp 或 qPq: 亮度为 QPY ( I_PCM 宏块为 0 ), 色度为对应的 QPC
+    fn qp(&self, state: &MbState, comp: usize, bit_depth: u32) -> i32 {
+        let qp_y = if state.mb_type == MbType::IPcm { 0 } else { state.qp_y };
+        if comp == 0 {
+            qp_y
+        } else {
+            let offset = self.slices[state.slice_num].chroma_qp_index_offset[comp - 1];
+            chroma_qp(qp_y, offset, 6 * (bit_depth as i32 - 8))
+        }
+    }
+
+    // filterLeftMbEdgeFlag 或 filterTopMbEdgeFlag
+    fn filter_mb_edge(&self, state: &MbState, neighbour: Option<u32>) -> bool {
+        let params = &self.slices[state.slice_num];
+        let neighbour = match neighbour.and_then(|addr| self.state(addr)) {
+            Some(neighbour) => neighbour,
+            None => return false,
+        };
+
+        // disable_deblocking_filter_idc 为 2 时不滤波 slice 边界
+        !(params.disable_deblocking_filter_idc == 2 && neighbour.slice_num != state.slice_num)
+    }
+
+    // 8.7.1 Filtering process for block edges ( Page 200 ), 对一个宏块的一个分量
+    fn macroblock(&self, plane: &mut Plane, comp: usize, addr: u32, bit_depth: u32) {
+        let state = match self.state(addr) {
+            Some(state) => state,
+            None => return,
+        };
+
+        let params = &self.slices[state.slice_num];
+        if params.disable_deblocking_filter_idc == 1 {
+            return;
+        }
+
+        let (width, height) = self.mb_size(comp);
+        let (x0, y0, step) = self.origin(addr, width, height);
+        let (luma_x0, luma_y0, _) = self.origin(addr, 16, 16);
+
+        let luma_like = comp == 0 || self.chroma_array_type == 3;
+        let (sub_x, sub_y) = if luma_like { (1, 1) } else { (self.sub_width_c, self.sub_height_c) };
+
+        // mbAddrA 与 mbAddrB ( 图像边界处不可用 )
+        let left = self.locate(luma_x0 - 1, luma_y0, 16, 16).map(|(addr, _, _)| addr).filter(|_| luma_x0 > 0);
+        let top_frame_over_field;
+        let top = if self.mbaff {
+            let first_row = (addr / 2) < self.width_in_mbs;
+            top_frame_over_field = !state.field && addr.is_multiple_of(2) && !first_row
+                && self.state(addr - 2 * self.width_in_mbs + 1).is_some_and(|above| above.field);
+
+            if !state.field && addr % 2 == 1 {
+                Some(addr - 1)
+            } else if first_row {
+                None
+            } else {
+                Some(2 * (addr / 2 - self.width_in_mbs) + 1)
+            }
+        } else {
+            top_frame_over_field = false;
+            addr.checked_sub(self.width_in_mbs)
+        };
+
+        let filter_left = self.filter_mb_edge(state, left);
+        let filter_top = self.filter_mb_edge(state, top);
+
+        let qp_q = self.qp(state, comp, bit_depth);
+        let filter = |plane: &mut Plane, bs: u8, p_addr: u32, coords: [(i32, i32); 8]| {
+            if bs == 0 {
+                return;
+            }
+
+            let p_state = self.state(p_addr).expect("p0 macroblock");
+            let qp_av = (self.qp(p_state, comp, bit_depth) + qp_q + 1) >> 1;
+            let (alpha, beta, tc0) = thresholds(qp_av, params.filter_offset_a, params.filter_offset_b, bs, bit_depth);
+
+            let count = if luma_like { 4 } else { 2 };
+            let mut p = [0i32; 4];
+            let mut q = [0i32; 4];
+            for i in 0..count {
+                p[i] = plane.get(coords[i].0 as usize, coords[i].1 as usize) as i32;
+                q[i] = plane.get(coords[4 + i].0 as usize, coords[4 + i].1 as usize) as i32;
+            }
+
+            if filter_samples(&mut p, &mut q, bs, !luma_like, alpha, beta, tc0, bit_depth) {
+                for i in 0..count {
+                    plane.set(coords[i].0 as usize, coords[i].1 as usize, p[i] as u16);
+                    plane.set(coords[4 + i].0 as usize, coords[4 + i].1 as usize, q[i] as u16);
+                }
+            }
+        };
+
+        // 亮度 ( 以及 ChromaArrayType 为 3 时的色度 ) 使用 8x8 变换时, 不滤波 8x8 块内部的边
+        let skip_edge = |edge: i32| luma_like && state.transform_size_8x8_flag && edge % 8 != 0;
+
+        // 垂直边
+        for edge in (0..width).step_by(4) {
+            if (edge == 0 && !filter_left) || skip_edge(edge) {
+                continue;
+            }
+
+            for k in 0..height {
+                let (x, y) = (x0 + edge, y0 + k * step);
+                let q_side = Side { addr: addr, state: state, x: edge * sub_x, y: k * sub_y };
+                let bs = self.boundary_strength(q_side, true, edge == 0, 0);
+                let p_addr = match self.locate(x - 1, y, width, height) {
+                    Some((p_addr, _, _)) => p_addr,
+                    None => continue,
+                };
+
+                let mut coords = [(0, 0); 8];
+                for i in 0..4 {
+                    coords[i] = (x - 1 - i as i32, y);
+                    coords[4 + i] = (x + i as i32, y);
+                }
+                filter(plane, bs, p_addr, coords);
+            }
+        }
+
+        // 水平边: ( 宏块内的行, q0 与 p0 相隔的行数, 对应的亮度行 )
+        for edge in (0..height).step_by(4) {
+            if (edge == 0 && !filter_top) || skip_edge(edge) {
+                continue;
+            }
+
+            // 帧宏块上方为场宏块对时, 按场分别滤波上边界: 顶场的行与上方的顶场宏块, 底场的行与上方的底场宏块
+            let passes: &[(i32, i32, i32)] = if edge == 0 && top_frame_over_field {
+                &[(0, 2, 0), (1, 2, 1)]
+            } else if edge == 0 {
+                &[(0, step, 0)]
+            } else {
+                &[(edge, step, -1)]
+            };
+
+            for &(row, p_step, luma_row) in passes {
+                let luma_row = if luma_row >= 0 { luma_row } else { row * sub_y };
+                let y = y0 + row * step;
+
+                for k in 0..width {
+                    let x = x0 + k;
+                    let q_side = Side { addr: addr, state: state, x: k * sub_x, y: luma_row };
+                    let bs = self.boundary_strength(q_side, false, edge == 0, p_step);
+                    let p_addr = match self.locate(x, y - p_step, width, height) {
+                        Some((p_addr, _, _)) => p_addr,
+                        None => continue,
+                    };
+
+                    let mut coords = [(0, 0); 8];
+                    for i in 0..4 {
+                        coords[i] = (x, y - p_step * (i as i32 + 1));
+                        coords[4 + i] = (x, y + p_step * i as i32);
+                    }
+                    filter(plane, bs, p_addr, coords);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::ParameterSets;
+    use crate::slice::test::{ Writer, parameter_sets };
+
+    // 32x16 的图像: 左右两个宏块, 左侧样本为 100, 右侧为 110
+    fn picture(mb_type: MbType, qp_y: i32) -> Picture {
+        let parameter_sets = parameter_sets();
+        let mut picture = Picture::new(parameter_sets.sps(0).unwrap());
+
+        for comp in 0..3 {
+            let plane = picture.plane_mut(comp);
+            for y in 0..plane.height {
+                for x in 0..plane.width {
+                    plane.set(x, y, if x < plane.width / 2 { 100 } else { 110 });
+                }
+            }
+        }
+
+        picture.slices.push(SliceParams {
+            slice_type: SliceType::P,
+            disable_deblocking_filter_idc: 0,
+            filter_offset_a: 0,
+            filter_offset_b: 0,
+            chroma_qp_index_offset: [0, 0],
+        });
+
+        for addr in 0..2 {
+            let mut state = MbState::new(0, mb_type, false, qp_y);
+            if mb_type.is_inter() {
+                state.ref_idx[0] = [0; 4];
+                state.ref_pic[0] = [Some((7, None)); 4];
+            }
+            picture.mbs[addr] = Some(state);
+        }
+
+        picture
+    }
+
+    fn deblocker(picture: &Picture) -> Deblocker<'_> {
+        Deblocker {
+            mbs: picture.current_mbs(),
+            slices: &picture.slices,
+            width_in_mbs: picture.width_in_mbs,
+            mbaff: picture.mbaff_frame_flag,
+            field_pic: picture.field_pic_flag,
+            bottom_field: picture.bottom_field_flag,
+            chroma_array_type: 1,
+            sub_width_c: 2,
+            sub_height_c: 2,
+        }
+    }
+
+    fn boundary_strength(picture: &Picture, x: i32, y: i32, vertical: bool) -> u8 {
+        let deblocker = deblocker(picture);
+
+        let addr = (x / 16) as u32;
+        let q = Side { addr: addr, state: deblocker.state(addr).unwrap(), x: x % 16, y: y };
+        deblocker.boundary_strength(q, vertical, (if vertical { x } else { y }) % 16 == 0, 1)
+    }
+
+    // 32x64 的 MBAFF 帧 ( 两行宏块对, 宏块 0 ~ 3 在上, 4 ~ 7 在下 ), 宏块均为 `mb_type`, 参考同一图像且运动向量为 0
+    fn mbaff_picture(mb_type: MbType, qp_y: i32) -> Picture {
+        let mut parameter_sets = ParameterSets::new();
+        let sps = Writer::new(0x67)
+            .u(8, 77).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(1).ue(3)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        assert!(parameter_sets.update(&sps).unwrap());
+
+        let mut picture = Picture::new(parameter_sets.sps(0).unwrap());
+        picture.mbaff_frame_flag = true;
+        picture.slices = self::picture(mb_type, qp_y).slices;
+        for addr in 0..8 {
+            let mut state = MbState::new(0, mb_type, false, qp_y);
+            if mb_type.is_inter() {
+                state.ref_idx[0] = [0; 4];
+                state.ref_pic[0] = [Some((7, None)); 4];
+            }
+            picture.mbs[addr] = Some(state);
+        }
+
+        picture
+    }
+
+    fn set_field(picture: &mut Picture, pair: usize, field: bool) {
+        for addr in 2 * pair..2 * pair + 2 {
+            picture.mbs[addr].as_mut().unwrap().field = field;
+        }
+    }
+
+    // 宏块 `addr` 内 ( x, y ) 处的边的 bS
+    fn mb_boundary_strength(picture: &Picture, addr: u32, x: i32, y: i32, vertical: bool, p_step: i32) -> u8 {
+        let deblocker = deblocker(picture);
+        let q = Side { addr: addr, state: deblocker.state(addr).unwrap(), x: x, y: y };
+        deblocker.boundary_strength(q, vertical, (if vertical { x } else { y }) % 16 == 0, p_step)
+    }
+
+    #[test]
+    fn test_filter_samples() {
+        assert_eq!(thresholds(30, 0, 0, 2, 8), (25, 8, 1));
+        assert_eq!(thresholds(30, 0, 0, 2, 10), (100, 32, 4));
+        assert_eq!(thresholds(50, 12, 0, 1, 8), (255, 18, 13));
+
+        let edge = ([100; 4], [110; 4]);
+
+        // bS 为 4 且 |p0 - q0| < ( α >> 2 ) + 2: 强滤波
+        let (mut p, mut q) = edge;
+        assert!(filter_samples(&mut p, &mut q, 4, false, 40, 10, 0, 8));
+        assert_eq!((p, q), ([104, 103, 101, 100], [106, 108, 109, 110]));
+
+        let (mut p, mut q) = edge;
+        assert!(filter_samples(&mut p, &mut q, 4, false, 20, 10, 0, 8));
+        assert_eq!((p, q), ([103, 100, 100, 100], [108, 110, 110, 110]));
+
+        // tC = tC0 + 2, Δ = 4
+        let (mut p, mut q) = edge;
+        assert!(filter_samples(&mut p, &mut q, 2, false, 40, 10, 2, 8));
+        assert_eq!((p, q), ([104, 102, 100, 100], [106, 108, 110, 110]));
+
+        // chromaStyleFilteringFlag: tC = tC0 + 1
+        let (mut p, mut q) = edge;
+        assert!(filter_samples(&mut p, &mut q, 2, true, 40, 10, 2, 8));
+        assert_eq!((p, q), ([103, 100, 100, 100], [107, 110, 110, 110]));
+
+        // |p0 - q0| >= α
+        let (mut p, mut q) = edge;
+        assert!(!filter_samples(&mut p, &mut q, 4, false, 10, 10, 0, 8));
+        assert_eq!((p, q), edge);
+    }
+
+    #[test]
+    fn test_boundary_strength() {
+        let mut picture = picture(MbType::P16x16, 30);
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 0);
+
+        // 运动向量之差
+        picture.mbs[1].as_mut().unwrap().mv[0] = [[3, -3]; 16];
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 0);
+        picture.mbs[1].as_mut().unwrap().mv[0] = [[0, 4]; 16];
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 1);
+        picture.mbs[1].as_mut().unwrap().mv[0] = [[0; 2]; 16];
+
+        // 不同的参考图像
+        picture.mbs[1].as_mut().unwrap().ref_pic[0] = [Some((8, None)); 4];
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 1);
+
+        // 双向预测: 以不同的列表参考相同的两个图像
+        {
+            let mb = picture.mbs[1].as_mut().unwrap();
+            mb.ref_idx[1] = [0; 4];
+            mb.ref_pic[1] = [Some((7, None)); 4];
+            mb.mv[1] = [[8, 0]; 16];
+        }
+        {
+            let mb = picture.mbs[0].as_mut().unwrap();
+            mb.ref_idx[1] = [0; 4];
+            mb.ref_pic[1] = [Some((8, None)); 4];
+            mb.mv[0] = [[8, 0]; 16];
+        }
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 0);
+        picture.mbs[0].as_mut().unwrap().mv[0] = [[4, 0]; 16];
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 1);
+
+        // 非零变换系数: 左侧宏块中位于 ( 12, 0 ) 的 4x4 块
+        let mut picture = self::picture(MbType::P16x16, 30);
+        picture.mbs[0].as_mut().unwrap().non_zero = 1 << 3;
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 2);
+        assert_eq!(boundary_strength(&picture, 16, 4, true), 0);
+        assert_eq!(boundary_strength(&picture, 12, 4, false), 2);
+        assert_eq!(boundary_strength(&picture, 8, 0, true), 0);
+
+        // 帧内宏块: 宏块边为 4, 内部的边为 3
+        let picture = self::picture(MbType::INxN, 30);
+        assert_eq!(boundary_strength(&picture, 16, 0, true), 4);
+        assert_eq!(boundary_strength(&picture, 20, 8, true), 3);
+        assert_eq!(boundary_strength(&picture, 20, 8, false), 3);
+    }
+
+    #[test]
+    fn test_mbaff_boundary_strength() {
+        // 帧宏块对之间: 运动相同, bS 为 0
+        let mut picture = mbaff_picture(MbType::P16x16, 30);
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 0, true, 0), 0);
+
+        // mixedModeEdgeFlag: 左边为场宏块对, 右边为帧宏块对, 运动相同时 bS 仍为 1
+        set_field(&mut picture, 0, true);
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 0, true, 0), 1);
+        assert_eq!(mb_boundary_strength(&picture, 3, 0, 15, true, 0), 1);
+        assert_eq!(mb_boundary_strength(&picture, 2, 4, 0, true, 0), 0);
+        // 上方为场宏块对的帧宏块的上边界
+        assert_eq!(mb_boundary_strength(&picture, 4, 0, 0, false, 2), 1);
+        assert_eq!(mb_boundary_strength(&picture, 6, 0, 0, false, 1), 0);
+
+        // 运动向量垂直分量之差的阈值: 帧宏块为 4, 场宏块为 2 ( 1/4 场样本 )
+        let mut picture = mbaff_picture(MbType::P16x16, 30);
+        for blk in 4..8 {
+            picture.mbs[2].as_mut().unwrap().mv[0][blk] = [0, 3];
+        }
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 4, false, 1), 0);
+        set_field(&mut picture, 1, true);
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 4, false, 2), 1);
+        for blk in 4..8 {
+            picture.mbs[2].as_mut().unwrap().mv[0][blk] = [0, 1];
+        }
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 4, false, 2), 0);
+        // 水平分量的阈值不变
+        for blk in 4..8 {
+            picture.mbs[2].as_mut().unwrap().mv[0][blk] = [4, 0];
+        }
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 4, false, 2), 1);
+
+        // 帧内宏块: 垂直的宏块边为 4; 涉及场宏块的水平宏块边为 3, 帧宏块之间为 4
+        let mut picture = mbaff_picture(MbType::INxN, 30);
+        set_field(&mut picture, 0, true);
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 0, true, 0), 4);
+        assert_eq!(mb_boundary_strength(&picture, 4, 0, 0, false, 2), 3);
+        assert_eq!(mb_boundary_strength(&picture, 6, 0, 0, false, 1), 4);
+        assert_eq!(mb_boundary_strength(&picture, 5, 0, 0, false, 1), 4);
+        set_field(&mut picture, 2, true);
+        assert_eq!(mb_boundary_strength(&picture, 5, 0, 0, false, 2), 3);
+    }
+
+    #[test]
+    fn test_field_picture_boundary_strength() {
+        // 场图像 ( 底场, 2x2 个宏块 ): 宏块均为场宏块, 运动向量垂直分量的阈值为 2, 帧内水平宏块边的 bS 为 3
+        let mut picture = mbaff_picture(MbType::P16x16, 30);
+        picture.mbaff_frame_flag = false;
+        picture.field_pic_flag = true;
+        picture.bottom_field_flag = true;
+        picture.mbs[6].as_mut().unwrap().mv[0] = [[0, 2]; 16];
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 0, false, 2), 1);
+        assert_eq!(mb_boundary_strength(&picture, 3, 0, 0, false, 2), 0);
+
+        picture.mbs[4].as_mut().unwrap().mb_type = MbType::INxN;
+        assert_eq!(mb_boundary_strength(&picture, 2, 0, 0, false, 2), 3);
+        assert_eq!(mb_boundary_strength(&picture, 1, 0, 0, true, 0), 4);
+    }
+
+    #[test]
+    fn test_deblock_picture() {
+        // QPY 为 40: α = 80, β = 13, 宏块边 ( bS 为 4 ) 使用强滤波
+        let mut picture = picture(MbType::INxN, 40);
+        deblock_picture(&mut picture);
+
+        for y in 0..16 {
+            let row: Vec<u16> = (12..20).map(|x| picture.luma.get(x, y)).collect();
+            assert_eq!(row, vec![100, 101, 103, 104, 106, 108, 109, 110]);
+        }
+
+        // 色度: QPC 为 36, 只修改 p0 与 q0
+        let cb = picture.cb.as_ref().unwrap();
+        let row: Vec<u16> = (6..10).map(|x| cb.get(x, 3)).collect();
+        assert_eq!(row, vec![100, 103, 108, 110]);
+
+        // disable_deblocking_filter_idc 为 1
+        let mut picture = self::picture(MbType::INxN, 40);
+        picture.slices[0].disable_deblocking_filter_idc = 1;
+        deblock_picture(&mut picture);
+        assert_eq!((picture.luma.get(15, 0), picture.luma.get(16, 0)), (100, 110));
+
+        // disable_deblocking_filter_idc 为 2: 不滤波 slice 边界
+        let mut picture = self::picture(MbType::INxN, 40);
+        picture.slices[0].disable_deblocking_filter_idc = 2;
+        picture.slices.push(picture.slices[0].clone());
+        picture.mbs[1].as_mut().unwrap().slice_num = 1;
+        deblock_picture(&mut picture);
+        assert_eq!((picture.luma.get(15, 0), picture.luma.get(16, 0)), (100, 110));
+
+        // I_PCM 宏块的 qPp 为 0: qPav = 20, α = 7
+        let mut picture = self::picture(MbType::INxN, 40);
+        picture.mbs[0].as_mut().unwrap().mb_type = MbType::IPcm;
+        deblock_picture(&mut picture);
+        assert_eq!((picture.luma.get(15, 0), picture.luma.get(16, 0)), (100, 110));
+    }
+
+    #[test]
+    fn test_frame_mb_below_field_pair() {
+        // 上方为场宏块对, 宏块 4 为帧宏块 ( 使用 8x8 变换, 不滤波内部第 4 行的边 )。上方的顶场行为 100, 底场行为 60,
+        // 下方为 110。宏块 4 的上边界按场分别滤波: QPY 为 40, bS 为 3, tC0 = 7, α = 80, β = 13
+        let mut picture = mbaff_picture(MbType::INxN, 40);
+        set_field(&mut picture, 0, true);
+        set_field(&mut picture, 1, true);
+        picture.mbs[4].as_mut().unwrap().transform_size_8x8_flag = true;
+        for y in 0..64 {
+            let value = if y >= 32 { 110 } else if y % 2 == 0 { 100 } else { 60 };
+            for x in 0..32 {
+                picture.luma.set(x, y, value);
+            }
+        }
+
+        deblock_picture(&mut picture);
+
+        // 顶场: p = 100, q = 110, tC = 9, Δ = 4; 底场: p = 60, q = 110, Δ = 19 被限制为 9
+        let column: Vec<u16> = (26..37).map(|y| picture.luma.get(0, y)).collect();
+        assert_eq!(column, vec![100, 60, 102, 67, 104, 69, 106, 101, 107, 103, 110]);
+    }
+}
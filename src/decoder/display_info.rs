@@ -0,0 +1,222 @@
+// 按输出顺序确定每个图像生效的 frame_packing_arrangement, stereo_video_info 与 display_orientation SEI。
+//
+// D.2.26 / D.2.27: repetition_period 为 0 时只作用于当前图像, 否则一直持续到 ( 按输出顺序 ) 下一个同类 SEI,
+// cancel_flag 为 1 的同类 SEI 或新的编码视频序列 ( IDR 图像 ); frame_packing_arrangement 只被
+// frame_packing_arrangement_id 相同的 SEI 结束, 不同 id 的 SEI 可以同时生效。stereo_video_info 持续到下一个
+// stereo_video_info 或新的编码视频序列。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{
+    SupplementalEnhancementInformation, SeiPayload, FramePackingArrangement, StereoVideoInfo, DisplayOrientation,
+};
+use super::access_unit::AccessUnitTracker;
+
+use std::collections::{ BTreeMap, HashMap, VecDeque };
+
+
+// 一个访问单元中 ( 第一个 slice 之前 ) 的 SEI, 同类 ( frame_packing_arrangement 为同一 id ) 的多个 SEI 只保留最后一个
+#[derive(Debug, Clone, Default)]
+struct AccessUnitData {
+    frame_packing_arrangements: BTreeMap<u32, FramePackingArrangement>,
+    stereo_video_info: Option<StereoVideoInfo>,
+    display_orientation: Option<DisplayOrientation>,
+}
+
+// 按输出顺序的一个图像以及对它生效的 SEI ( 被取消或没有时为 None )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayInfoPicture {
+    // PictureReport::id
+    pub id: u64,
+    pub pic_order_cnt: i32,
+    pub output_index: u64,
+    // 按 frame_packing_arrangement_id 排列
+    pub frame_packing_arrangements: Vec<FramePackingArrangement>,
+    pub stereo_video_info: Option<StereoVideoInfo>,
+    pub display_orientation: Option<DisplayOrientation>,
+}
+
+// 由当前图像的 SEI ( 可能取消之前的 SEI ) 与之前持续的 SEI 得到对当前图像生效的 SEI: ( cancel_flag, repetition_period )
+fn resolve<T: Copy>(active: &mut Option<T>, message: Option<T>, persistence: impl Fn(&T) -> (bool, u32)) -> Option<T> {
+    let message = match message {
+        Some(message) => message,
+        None => return *active,
+    };
+
+    match persistence(&message) {
+        (true, _) => {
+            *active = None;
+            None
+        },
+        (false, repetition_period) => {
+            *active = if repetition_period > 0 { Some(message) } else { None };
+            Some(message)
+        },
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DisplayInfoCollector {
+    tracker: AccessUnitTracker<AccessUnitData>,
+    // 已解码完成但还没有输出的图像: ( idr, SEI )
+    by_id: HashMap<u64, (bool, AccessUnitData)>,
+    // 按输出顺序持续生效的 SEI
+    frame_packing_arrangements: BTreeMap<u32, FramePackingArrangement>,
+    stereo_video_info: Option<StereoVideoInfo>,
+    display_orientation: Option<DisplayOrientation>,
+    pictures: VecDeque<DisplayInfoPicture>,
+}
+
+impl DisplayInfoCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        match nalu.kind() {
+            NaluKind::SupplementalEnhancementInformation => {
+                let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+                let access_unit = self.tracker.access_unit();
+                for message in sei.messages() {
+                    match message.payload {
+                        SeiPayload::FramePackingArrangement(payload) => {
+                            access_unit.frame_packing_arrangements.insert(payload.frame_packing_arrangement_id, payload);
+                        },
+                        SeiPayload::StereoVideoInfo(payload) => access_unit.stereo_video_info = Some(payload),
+                        SeiPayload::DisplayOrientation(payload) => access_unit.display_orientation = Some(payload),
+                        _ => { },
+                    }
+                }
+                Ok(())
+            },
+            _ => {
+                self.tracker.push(nalu, |_, _, access_unit| Ok(access_unit))?;
+                self.drain();
+                Ok(())
+            },
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Some((report, data)) = self.tracker.next_report() {
+
+            // 互补场对中后一个场的 SEI 优先
+            let entry = self.by_id.entry(report.id).or_insert((false, AccessUnitData::default()));
+            entry.0 |= report.idr;
+            entry.1.frame_packing_arrangements.extend(data.frame_packing_arrangements);
+            entry.1.stereo_video_info = data.stereo_video_info.or(entry.1.stereo_video_info);
+            entry.1.display_orientation = data.display_orientation.or(entry.1.display_orientation);
+        }
+
+        while let Some(output) = self.tracker.next_output() {
+            let (idr, data) = self.by_id.remove(&output.id).unwrap_or_default();
+            if idr {
+                self.frame_packing_arrangements.clear();
+                self.stereo_video_info = None;
+                self.display_orientation = None;
+            }
+
+            // 持续生效的 arrangement 中没有被这个图像的同一 id 的 SEI 替换的部分
+            let mut frame_packing_arrangements: Vec<FramePackingArrangement> = self.frame_packing_arrangements.iter()
+                .filter(|&(id, _)| !data.frame_packing_arrangements.contains_key(id))
+                .map(|(_, payload)| *payload)
+                .collect();
+            for (id, payload) in data.frame_packing_arrangements {
+                let mut active = self.frame_packing_arrangements.remove(&id);
+                let current = resolve(&mut active, Some(payload), |payload| {
+                    (payload.frame_packing_arrangement_cancel_flag, payload.frame_packing_arrangement_repetition_period)
+                });
+                if let Some(active) = active {
+                    self.frame_packing_arrangements.insert(id, active);
+                }
+                frame_packing_arrangements.extend(current);
+            }
+            frame_packing_arrangements.sort_by_key(|payload| payload.frame_packing_arrangement_id);
+            let display_orientation = resolve(&mut self.display_orientation, data.display_orientation, |payload| {
+                (payload.display_orientation_cancel_flag, payload.display_orientation_repetition_period)
+            });
+            // stereo_video_info 没有 cancel_flag, 持续到下一个 stereo_video_info
+            let stereo_video_info = resolve(&mut self.stereo_video_info, data.stereo_video_info, |_| (false, 1));
+
+            self.pictures.push_back(DisplayInfoPicture {
+                id: output.id,
+                pic_order_cnt: output.pic_order_cnt,
+                output_index: output.output_index,
+                frame_packing_arrangements: frame_packing_arrangements,
+                stereo_video_info: stereo_video_info,
+                display_orientation: display_orientation,
+            });
+        }
+    }
+
+    // 码流结束: 输出所有等待输出的图像
+    pub fn flush(&mut self) {
+        self.tracker.flush();
+        self.drain();
+    }
+
+    // 取出下一个 ( 按输出顺序 ) 输出的图像
+    pub fn next_picture(&mut self) -> Option<DisplayInfoPicture> {
+        self.pictures.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::DisplayInfoCollector;
+    use crate::slice::test::{ Writer, parameter_set_nalus };
+    use crate::rbsp::FramePackingType;
+
+    #[test]
+    fn test_persistence_in_output_order() {
+        let (sps, pps) = parameter_set_nalus();
+        let mut collector = DisplayInfoCollector::new();
+        collector.push(&sps).unwrap();
+        collector.push(&pps).unwrap();
+
+        // IDR: side-by-side ( repetition_period 1 ), stereo_video_info, 只作用于当前图像的 90 度旋转
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 45).u(8, 7).ue(0).bits("0").u(7, 3).bits("0").u(6, 1).bits("000000").u(16, 0).u(8, 0).ue(1).bits("0");
+        writer.bits("1").align();
+        writer.u(8, 21).u(8, 1).bits("01100").bits("100");
+        writer.u(8, 47).u(8, 3).bits("000").u(16, 16384).ue(0).bits("0").bits("100");
+        collector.push(&writer.finish()).unwrap();
+        collector.push(&Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish()).unwrap();
+
+        // P ( POC 4 ): 取消 id 为 0 的 frame packing, 持续的 180 度旋转
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 45).u(8, 1).ue(0).bits("1").bits("0").bits("1").align();
+        writer.u(8, 47).u(8, 3).bits("000").u(16, 32768).ue(1).bits("0").bits("1").align();
+        collector.push(&writer.finish()).unwrap();
+        collector.push(&Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 1).u(4, 4).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish()).unwrap();
+
+        // P ( POC 2, 非参考 ): 取消 id 为 1 的 frame packing, 不影响 id 为 0 的; 按输出顺序在 POC 4 之前
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 45).u(8, 1).ue(1).bits("1").bits("0").bits("1").align();
+        collector.push(&writer.finish()).unwrap();
+        collector.push(&Writer::new(0x01).ue(0).ue(5).ue(0).u(4, 2).u(4, 2).u(1, 0).u(1, 0).se(0).ue(1).finish()).unwrap();
+        collector.flush();
+
+        let idr = collector.next_picture().unwrap();
+        assert_eq!(idr.frame_packing_arrangements.len(), 1);
+        let frame_packing = idr.frame_packing_arrangements[0];
+        assert_eq!(frame_packing.packing_type(), FramePackingType::SideBySide);
+        assert_eq!(frame_packing.content_interpretation_type, 1);
+        assert_eq!(frame_packing.frame_grid_positions, Some([0; 4]));
+        assert!(idr.stereo_video_info.unwrap().current_frame_is_left_view_flag);
+        assert_eq!(idr.display_orientation.unwrap().rotation_degrees(), 90.0);
+
+        let p2 = collector.next_picture().unwrap();
+        assert_eq!(p2.pic_order_cnt, 2);
+        assert_eq!(p2.frame_packing_arrangements, vec![frame_packing]);
+        assert_eq!(p2.display_orientation, None);
+        assert!(p2.stereo_video_info.is_some());
+
+        let p4 = collector.next_picture().unwrap();
+        assert_eq!(p4.pic_order_cnt, 4);
+        assert!(p4.frame_packing_arrangements.is_empty());
+        assert_eq!(p4.display_orientation.unwrap().rotation_degrees(), 180.0);
+        assert!(collector.next_picture().is_none());
+    }
+}
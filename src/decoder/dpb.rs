@@ -0,0 +1,1260 @@
+// 8.2.4 Decoding process for reference picture lists construction ( Page 104 )
+// 8.2.5 Decoded reference picture marking process ( Page 114 )
+// C.4 Operation of the output order DPB ( Page 293 )
+//
+// 帧与场 ( PAFF ) 均可处理: 场的第二个场与第一个场组成互补场对时存入同一项, 参考标记按场记录。
+// `T` 为图像数据, 不需要样本时 ( 例如只分析头部 ) 可以使用 `()`。
+
+use crate::error::{ self, Error };
+use crate::nalu::NaluRefIdc;
+use crate::rbsp::{ SliceHeader, SequenceParameterSet, PictureStructure, RefPicListModification, MemoryManagementControlOperation };
+use super::poc::PicOrderCnt;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    // unused for reference
+    Unused,
+    // used for short-term reference
+    ShortTerm,
+    // used for long-term reference, LongTermFrameIdx
+    LongTerm(u32),
+}
+
+impl Reference {
+    pub fn is_reference(&self) -> bool {
+        *self != Reference::Unused
+    }
+
+    pub fn is_long_term(&self) -> bool {
+        match *self {
+            Reference::LongTerm(_) => true,
+            _ => false,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct DpbEntry<T> {
+    // 解码顺序中的唯一编号
+    pub id: u64,
+    pub frame_num: u32,
+    // 帧 ( 包括 "non-existing" 帧 ), 互补场对, 或单个场 ( 第一个场或没有配对的场 )
+    pub structure: PictureStructure,
+    // 单个场的 TopFieldOrderCnt 与 BottomFieldOrderCnt 相同
+    pub top_field_order_cnt: i32,
+    pub bottom_field_order_cnt: i32,
+    // 整个项的标记: 有长期参考场时为长期参考, 否则有短期参考场时为短期参考
+    pub reference: Reference,
+    // 顶场, 底场各自的标记 ( 单个场中不存在的场为 Unused )
+    pub field_reference: [Reference; 2],
+    pub needed_for_output: bool,
+    // frame_num 间隙中推导出的 "non-existing" 帧没有数据
+    pub data: Option<T>,
+}
+
+impl<T> DpbEntry<T> {
+    // PicOrderCnt( frame ) = Min( TopFieldOrderCnt, BottomFieldOrderCnt )
+    pub fn pic_order_cnt(&self) -> i32 {
+        self.top_field_order_cnt.min(self.bottom_field_order_cnt)
+    }
+
+    pub fn is_non_existing(&self) -> bool {
+        self.data.is_none()
+    }
+
+    // 是否包含该场 ( 0 为顶场, 1 为底场 )
+    pub fn has_field(&self, parity: usize) -> bool {
+        match self.structure.parity() {
+            Some(field) => field == parity,
+            None => true,
+        }
+    }
+
+    pub fn field_order_cnt(&self, parity: usize) -> i32 {
+        if parity == 0 { self.top_field_order_cnt } else { self.bottom_field_order_cnt }
+    }
+
+    // 帧或互补参考场对, 且两个场均为短期参考
+    fn is_short_term_frame(&self) -> bool {
+        !self.structure.is_field() && self.field_reference == [Reference::ShortTerm; 2]
+    }
+
+    // 帧或互补参考场对, 且两个场均为长期参考时的 LongTermFrameIdx
+    fn long_term_frame_idx(&self) -> Option<u32> {
+        match self.field_reference {
+            [Reference::LongTerm(top), Reference::LongTerm(bottom)] if top == bottom && !self.structure.is_field() => Some(top),
+            _ => None,
+        }
+    }
+
+    // 任一场为长期参考时的 LongTermFrameIdx
+    fn long_term_idx(&self) -> Option<u32> {
+        self.field_reference.iter()
+            .filter_map(|reference| match *reference { Reference::LongTerm(idx) => Some(idx), _ => None })
+            .next()
+    }
+
+    // 标记项中的所有场
+    fn set_reference(&mut self, reference: Reference) {
+        for parity in 0..2 {
+            if self.has_field(parity) {
+                self.field_reference[parity] = reference;
+            }
+        }
+        self.reference = reference;
+    }
+
+    fn set_field_reference(&mut self, parity: usize, reference: Reference) {
+        self.field_reference[parity] = reference;
+        self.reference = if let Some(idx) = self.long_term_idx() {
+            Reference::LongTerm(idx)
+        } else if self.field_reference.contains(&Reference::ShortTerm) {
+            Reference::ShortTerm
+        } else {
+            Reference::Unused
+        };
+    }
+}
+
+
+// 参考图像列表构建与参考图像标记中违反规范的情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    // ref_pic_list_modification() 引用了不存在的参考图像 ( 对应的项为 "no reference picture" )
+    MissingModificationTarget { list: usize, operation: RefPicListModification },
+    // ref_pic_list_modification() 的操作个数超过 num_ref_idx_lX_active_minus1 + 1 ( 多余的操作被忽略 )
+    TooManyModifications { list: usize },
+    // memory_management_control_operation 引用了不存在的参考图像, 或 long_term_frame_idx 超过 MaxLongTermFrameIdx
+    // ( 该操作被忽略 )
+    InvalidOperation(MemoryManagementControlOperation),
+    // 参考图像没有 dec_ref_pic_marking() ( 按滑动窗口标记 )
+    MissingMarking,
+    // frame_num 不连续, 但 gaps_in_frame_num_value_allowed_flag 为 0
+    FrameNumGap { prev_ref_frame_num: u32, frame_num: u32 },
+    // 标记之后的参考帧 ( 包括当前图像 ) 超过 max_num_ref_frames
+    TooManyReferenceFrames { num_ref_frames: usize, max_num_ref_frames: usize },
+    // 存入当前图像之后 DPB 中的帧超过 DPB 的容量 ( max_dec_frame_buffering 或 MaxDpbFrames )
+    DpbOverflow { num_frames: usize, dpb_size: usize },
+    // 解码顺序在该图像之前而输出顺序在它之后的帧数超过 VUI 的 max_num_reorder_frames
+    ReorderDepthExceeded { reorder_depth: usize, max_num_reorder_frames: u32 },
+}
+
+impl Violation {
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Violation::MissingModificationTarget { .. } => "ref_pic_list_modification refers to a missing reference picture",
+            Violation::TooManyModifications { .. } => "too many ref_pic_list_modification operations",
+            Violation::InvalidOperation(operation) => match operation {
+                MemoryManagementControlOperation::MarkShortTermUnused { .. } => "memory_management_control_operation 1 refers to a missing picture",
+                MemoryManagementControlOperation::MarkLongTermUnused { .. } => "memory_management_control_operation 2 refers to a missing picture",
+                MemoryManagementControlOperation::MarkShortTermAsLongTerm { .. } => "memory_management_control_operation 3 refers to a missing picture or an invalid long_term_frame_idx",
+                _ => "long_term_frame_idx exceeds MaxLongTermFrameIdx",
+            },
+            Violation::MissingMarking => "reference picture without dec_ref_pic_marking()",
+            Violation::FrameNumGap { .. } => "gap in frame_num is not allowed",
+            Violation::TooManyReferenceFrames { .. } => "number of reference frames exceeds max_num_ref_frames",
+            Violation::DpbOverflow { .. } => "number of frames in the DPB exceeds the DPB size",
+            Violation::ReorderDepthExceeded { .. } => "reorder depth exceeds max_num_reorder_frames",
+        }
+    }
+}
+
+// 第一个违反规范的情况作为错误返回
+fn check(violations: Vec<Violation>) -> Result<(), Error> {
+    match violations.first() {
+        Some(violation) => Err(error::malformed(violation.description())),
+        None => Ok(()),
+    }
+}
+
+
+// 当前图像在参考图像标记之后的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Marking {
+    pub reference: Reference,
+    // 是否包含 memory_management_control_operation 5
+    pub mmco5: bool,
+    // 当前场是否与 DPB 中的第一个场组成互补场对 ( 存入同一项 )
+    pub second_field: bool,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Dpb<T> {
+    entries: Vec<DpbEntry<T>>,
+    // MaxLongTermFrameIdx, None 表示 "no long-term frame indices"
+    max_long_term_frame_idx: Option<u32>,
+    // PrevRefFrameNum
+    prev_ref_frame_num: u32,
+    next_id: u64,
+
+    // 以下由活动 SPS 决定
+    size: usize,
+    max_num_ref_frames: usize,
+    max_frame_num: u32,
+}
+
+impl<T> Default for Dpb<T> {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            max_long_term_frame_idx: None,
+            prev_ref_frame_num: 0,
+            next_id: 0,
+            size: 16,
+            max_num_ref_frames: 16,
+            max_frame_num: 16,
+        }
+    }
+}
+
+impl<T> Dpb<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[DpbEntry<T>] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: u64) -> Option<&DpbEntry<T>> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    // DPB 的容量 ( 帧 )
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn max_long_term_frame_idx(&self) -> Option<u32> {
+        self.max_long_term_frame_idx
+    }
+
+    // PrevRefFrameNum
+    pub fn prev_ref_frame_num(&self) -> u32 {
+        self.prev_ref_frame_num
+    }
+
+    pub fn max_num_ref_frames(&self) -> usize {
+        self.max_num_ref_frames
+    }
+
+    // 每个图像开始解码时调用
+    pub fn activate(&mut self, sps: &SequenceParameterSet) {
+        // A.3.1 item h) 与 E.2.1: max_dec_frame_buffering 存在时取代 MaxDpbFrames
+        let size = sps.vui_parameters()
+            .and_then(|vui| vui.max_dec_frame_buffering)
+            .unwrap_or_else(|| sps.max_dpb_frames());
+
+        self.size = size.max(1) as usize;
+        self.max_num_ref_frames = sps.max_num_ref_frames().max(1) as usize;
+        self.max_frame_num = sps.max_frame_num();
+    }
+
+    // FrameNumWrap ( 8-27 ), 对于帧即 PicNum
+    fn pic_num(&self, entry: &DpbEntry<T>, curr_frame_num: u32) -> i32 {
+        if entry.frame_num > curr_frame_num {
+            entry.frame_num as i32 - self.max_frame_num as i32
+        } else {
+            entry.frame_num as i32
+        }
+    }
+
+    fn short_term(&self, pic_num: i32, curr_frame_num: u32) -> Option<usize> {
+        self.entries.iter()
+            .position(|entry| entry.is_short_term_frame() && self.pic_num(entry, curr_frame_num) == pic_num)
+    }
+
+    fn long_term(&self, long_term_pic_num: u32) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.long_term_frame_idx() == Some(long_term_pic_num))
+    }
+
+    // 8.2.4.1 ( 8-30, 8-31 ): 解码场时, 与当前场奇偶相同的场 PicNum = 2 * FrameNumWrap + 1,
+    // 奇偶相反的场 PicNum = 2 * FrameNumWrap; LongTermPicNum 由 LongTermFrameIdx 同样推导
+    fn field_num(num: i32, parity: usize, header: &SliceHeader) -> i32 {
+        if Some(parity) == header.structure().parity() { 2 * num + 1 } else { 2 * num }
+    }
+
+    // 解码场时 PicNum 为 `pic_num` 的短期参考场: ( 项的下标, 场 )
+    fn short_term_field(&self, pic_num: i32, header: &SliceHeader) -> Option<(usize, usize)> {
+        self.entries.iter()
+            .enumerate()
+            .flat_map(|(idx, entry)| (0..2).map(move |parity| (idx, entry, parity)))
+            .find(|&(_, entry, parity)| {
+                entry.has_field(parity)
+                    && entry.field_reference[parity] == Reference::ShortTerm
+                    && Self::field_num(self.pic_num(entry, header.frame_num), parity, header) == pic_num
+            })
+            .map(|(idx, _, parity)| (idx, parity))
+    }
+
+    // 解码场时 LongTermPicNum 为 `long_term_pic_num` 的长期参考场
+    fn long_term_field(&self, long_term_pic_num: u32, header: &SliceHeader) -> Option<(usize, usize)> {
+        self.entries.iter()
+            .enumerate()
+            .flat_map(|(idx, entry)| (0..2).map(move |parity| (idx, entry, parity)))
+            .find(|&(_, entry, parity)| match entry.field_reference[parity] {
+                Reference::LongTerm(idx) => entry.has_field(parity)
+                    && Self::field_num(idx as i32, parity, header) == long_term_pic_num as i32,
+                _ => false,
+            })
+            .map(|(idx, _, parity)| (idx, parity))
+    }
+
+    // 3.30 complementary field pair: 当前场与上一个存入 DPB 的场奇偶相反, frame_num 相同, 同为参考场或同为
+    // 非参考场, 且当前场不是 IDR 图像, 不包含 memory_management_control_operation 5 时, 返回第一个场所在的项
+    fn first_field_idx(&self, header: &SliceHeader) -> Option<usize> {
+        let parity = header.structure().parity()?;
+        if header.idr_pic_flag() || header.has_mmco5() {
+            return None;
+        }
+
+        let idx = self.entries.iter().position(|entry| entry.id + 1 == self.next_id)?;
+        let entry = &self.entries[idx];
+        let reference = header.nal_ref_idc != NaluRefIdc::DISPOSABLE;
+
+        if entry.structure == PictureStructure::from_parity(1 - parity)
+            && entry.frame_num == header.frame_num
+            && entry.reference.is_reference() == reference
+            && !entry.is_non_existing() {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+
+    // 当前场是否为互补场对的第二个场 ( 在参考图像标记之前调用 )
+    pub fn is_second_field(&self, header: &SliceHeader) -> bool {
+        self.first_field_idx(header).is_some()
+    }
+
+    // 当前场为互补场对的第二个场时, 第一个场所在的项
+    pub fn first_field(&self, header: &SliceHeader) -> Option<&DpbEntry<T>> {
+        self.first_field_idx(header).map(|idx| &self.entries[idx])
+    }
+
+    fn has_empty_frame_buffer(&self) -> bool {
+        self.entries.len() < self.size
+    }
+
+    // C.4.5.3 "Bumping" process: 输出 PicOrderCnt 最小的图像; 没有等待输出的图像时返回 false
+    fn bump(&mut self, output: &mut dyn FnMut(&DpbEntry<T>)) -> bool {
+        let idx = self.entries.iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.needed_for_output)
+            .min_by_key(|(_, entry)| entry.pic_order_cnt())
+            .map(|(idx, _)| idx);
+
+        match idx {
+            Some(idx) => {
+                output(&self.entries[idx]);
+                self.entries[idx].needed_for_output = false;
+                if !self.entries[idx].reference.is_reference() {
+                    self.entries.remove(idx);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    // 输出所有等待输出的图像 ( 码流结束时 )
+    pub fn flush(&mut self, output: &mut dyn FnMut(&DpbEntry<T>)) {
+        while self.bump(output) { }
+    }
+
+    // 8.2.5.3 Sliding window decoded reference picture marking process
+    fn sliding_window(&mut self, curr_frame_num: u32) {
+        loop {
+            let num_ref_frames = self.entries.iter().filter(|entry| entry.reference.is_reference()).count();
+            if num_ref_frames < self.max_num_ref_frames {
+                break;
+            }
+
+            let oldest = self.entries.iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.reference == Reference::ShortTerm)
+                .min_by_key(|(_, entry)| self.pic_num(entry, curr_frame_num))
+                .map(|(idx, _)| idx);
+
+            match oldest {
+                Some(idx) => self.entries[idx].set_reference(Reference::Unused),
+                // 全部为长期参考帧, 码流不符合规范
+                None => break,
+            }
+        }
+    }
+
+    fn remove_unused(&mut self) {
+        self.entries.retain(|entry| entry.reference.is_reference() || entry.needed_for_output);
+    }
+
+    // 8.2.5.2 Decoding process for gaps in frame_num ( Page 115 )
+    //
+    // 在解码当前图像之前调用, 为缺失的 frame_num 插入 "non-existing" 帧
+    pub fn fill_frame_num_gap(&mut self, header: &SliceHeader, output: &mut dyn FnMut(&DpbEntry<T>)) {
+        if header.idr_pic_flag() || header.frame_num == self.prev_ref_frame_num {
+            return;
+        }
+
+        let mut frame_num = (self.prev_ref_frame_num + 1) % self.max_frame_num;
+        while frame_num != header.frame_num {
+            self.sliding_window(frame_num);
+            self.remove_unused();
+            while !self.has_empty_frame_buffer() && self.bump(output) { }
+
+            self.entries.push(DpbEntry {
+                id: self.next_id,
+                frame_num: frame_num,
+                structure: PictureStructure::Frame,
+                top_field_order_cnt: 0,
+                bottom_field_order_cnt: 0,
+                reference: Reference::ShortTerm,
+                field_reference: [Reference::ShortTerm; 2],
+                needed_for_output: false,
+                data: None,
+            });
+            self.next_id += 1;
+
+            self.prev_ref_frame_num = frame_num;
+            frame_num = (frame_num + 1) % self.max_frame_num;
+        }
+    }
+
+    // 8.2.4.2 Initialisation process for reference picture lists ( Page 105 )
+    //
+    // 返回未截断的 RefPicList0, RefPicList1 ( 图像的 id )
+    pub fn initial_ref_pic_lists(&self, header: &SliceHeader, pic_order_cnt: i32) -> [Vec<u64>; 2] {
+        let [list0, list1] = self.initial_ref_pic_list_entries(header, pic_order_cnt);
+        [
+            list0.iter().map(|&(id, _)| id).collect(),
+            list1.iter().map(|&(id, _)| id).collect(),
+        ]
+    }
+
+    // 同 `initial_ref_pic_lists`, 每一项为 ( id, 被参考的图像 ): 解码帧时为 Frame, 解码场时为 TopField 或 BottomField
+    pub fn initial_ref_pic_list_entries(&self, header: &SliceHeader, pic_order_cnt: i32) -> [Vec<(u64, PictureStructure)>; 2] {
+        if header.slice_type.is_intra() {
+            return [vec![], vec![]];
+        }
+
+        if header.field_pic_flag {
+            return self.initial_field_lists(header, pic_order_cnt);
+        }
+
+        let frame = |entries: Vec<&DpbEntry<T>>| -> Vec<(u64, PictureStructure)> {
+            entries.iter().map(|entry| (entry.id, PictureStructure::Frame)).collect()
+        };
+
+        let mut long_term: Vec<&DpbEntry<T>> = self.entries.iter()
+            .filter(|entry| entry.long_term_frame_idx().is_some())
+            .collect();
+        // 对于帧, LongTermPicNum = LongTermFrameIdx
+        long_term.sort_by_key(|entry| entry.long_term_frame_idx());
+        let long_term = frame(long_term);
+
+        let short_term = self.entries.iter().filter(|entry| entry.is_short_term_frame());
+
+        if !header.slice_type.is_bipredictive() {
+            // 8.2.4.2.1: PicNum 降序
+            let mut short_term: Vec<&DpbEntry<T>> = short_term.collect();
+            short_term.sort_by_key(|entry| -self.pic_num(entry, header.frame_num));
+
+            let mut list0 = frame(short_term);
+            list0.extend(long_term);
+            return [list0, vec![]];
+        }
+
+        // 8.2.4.2.3: "non-existing" 帧没有 PicOrderCnt, 不参与 B slice 的列表
+        let (mut before, mut after): (Vec<&DpbEntry<T>>, Vec<&DpbEntry<T>>) = short_term
+            .filter(|entry| !entry.is_non_existing())
+            .partition(|entry| entry.pic_order_cnt() < pic_order_cnt);
+        before.sort_by_key(|entry| -entry.pic_order_cnt());
+        after.sort_by_key(|entry| entry.pic_order_cnt());
+
+        let before = frame(before);
+        let after = frame(after);
+
+        let mut list0 = before.clone();
+        list0.extend(after.iter());
+        list0.extend(long_term.iter());
+
+        let mut list1 = after;
+        list1.extend(before.iter());
+        list1.extend(long_term.iter());
+
+        if list1.len() > 1 && list1 == list0 {
+            list1.swap(0, 1);
+        }
+
+        [list0, list1]
+    }
+
+    // 8.2.4.2.2 Initialisation process for the reference picture list for P and SP slices in fields
+    // 8.2.4.2.4 Initialisation process for reference picture lists for B slices in fields
+    //
+    // 先按帧 ( 包括当前帧已解码的第一个场 ) 排序得到 refFrameList0ShortTerm, refFrameList1ShortTerm 与
+    // refFrameListLongTerm, 再按 8.2.4.2.5 从中交替取出场
+    fn initial_field_lists(&self, header: &SliceHeader, pic_order_cnt: i32) -> [Vec<(u64, PictureStructure)>; 2] {
+        let parity = header.bottom_field_flag as usize;
+
+        let mut long_term: Vec<&DpbEntry<T>> = self.entries.iter()
+            .filter(|entry| entry.long_term_idx().is_some())
+            .collect();
+        long_term.sort_by_key(|entry| entry.long_term_idx());
+        let long_term = Self::alternate_fields(&long_term, parity, Reference::is_long_term);
+
+        let short_term = self.entries.iter().filter(|entry| entry.field_reference.contains(&Reference::ShortTerm));
+        let is_short_term = |reference: &Reference| *reference == Reference::ShortTerm;
+
+        if !header.slice_type.is_bipredictive() {
+            // FrameNumWrap 降序
+            let mut short_term: Vec<&DpbEntry<T>> = short_term.collect();
+            short_term.sort_by_key(|entry| -self.pic_num(entry, header.frame_num));
+
+            let mut list0 = Self::alternate_fields(&short_term, parity, is_short_term);
+            list0.extend(long_term);
+            return [list0, vec![]];
+        }
+
+        // 只考虑短期参考场的 PicOrderCnt; 与帧不同, 等于当前 PicOrderCnt 的在前
+        let field_pic_order_cnt = |entry: &DpbEntry<T>| -> i32 {
+            (0..2)
+                .filter(|&parity| entry.has_field(parity) && entry.field_reference[parity] == Reference::ShortTerm)
+                .map(|parity| entry.field_order_cnt(parity))
+                .min()
+                .unwrap_or(0)
+        };
+        let (mut before, mut after): (Vec<&DpbEntry<T>>, Vec<&DpbEntry<T>>) = short_term
+            .filter(|entry| !entry.is_non_existing())
+            .partition(|entry| field_pic_order_cnt(entry) <= pic_order_cnt);
+        before.sort_by_key(|entry| -field_pic_order_cnt(entry));
+        after.sort_by_key(|entry| field_pic_order_cnt(entry));
+
+        let mut ref_frame_list0: Vec<&DpbEntry<T>> = before.clone();
+        ref_frame_list0.extend(after.iter());
+        let mut ref_frame_list1: Vec<&DpbEntry<T>> = after;
+        ref_frame_list1.extend(before.iter());
+
+        let mut list0 = Self::alternate_fields(&ref_frame_list0, parity, is_short_term);
+        list0.extend(long_term.iter());
+        let mut list1 = Self::alternate_fields(&ref_frame_list1, parity, is_short_term);
+        list1.extend(long_term.iter());
+
+        if list1.len() > 1 && list1 == list0 {
+            list1.swap(0, 1);
+        }
+
+        [list0, list1]
+    }
+
+    // 8.2.4.2.5 Initialisation process for reference picture lists in fields
+    //
+    // 从与当前场奇偶相同的场开始, 交替取出两种奇偶的场 ( 只取出标记满足 `marked` 的场 ); 一种取完之后,
+    // 按顺序加入另一种剩余的场
+    fn alternate_fields(frames: &[&DpbEntry<T>], parity: usize, marked: impl Fn(&Reference) -> bool) -> Vec<(u64, PictureStructure)> {
+        let fields = |parity: usize| {
+            frames.iter()
+                .filter(|entry| entry.has_field(parity) && marked(&entry.field_reference[parity]))
+                .map(|entry| (entry.id, PictureStructure::from_parity(parity)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut same = fields(parity).into_iter();
+        let mut opposite = fields(1 - parity).into_iter();
+
+        let mut list = vec![];
+        loop {
+            match (same.next(), opposite.next()) {
+                (None, None) => break,
+                (field, other) => {
+                    list.extend(field);
+                    list.extend(other);
+                },
+            }
+        }
+
+        list
+    }
+
+    // 8.2.4 RefPicList0, RefPicList1: 初始化, 截断 ( 或以 "no reference picture" 填充 ) 至
+    // num_ref_idx_lX_active_minus1 + 1 项, 然后按 ref_pic_list_modification() 修改
+    pub fn ref_pic_lists(&self, header: &SliceHeader, pic_order_cnt: i32) -> Result<[Vec<Option<u64>>; 2], Error> {
+        let mut violations = vec![];
+        let lists = self.ref_pic_lists_with_violations(header, pic_order_cnt, &mut violations);
+        check(violations)?;
+        Ok(lists)
+    }
+
+    // 同 `ref_pic_lists`, 但违反规范时继续构建列表并记录到 `violations`
+    pub fn ref_pic_lists_with_violations(&self,
+                                         header: &SliceHeader,
+                                         pic_order_cnt: i32,
+                                         violations: &mut Vec<Violation>) -> [Vec<Option<u64>>; 2] {
+        let [list0, list1] = self.ref_pic_list_entries(header, pic_order_cnt, violations);
+        [
+            list0.iter().map(|entry| entry.map(|(id, _)| id)).collect(),
+            list1.iter().map(|entry| entry.map(|(id, _)| id)).collect(),
+        ]
+    }
+
+    // 同 `ref_pic_lists_with_violations`, 每一项为 ( id, 被参考的图像 ), 见 `initial_ref_pic_list_entries`
+    pub fn ref_pic_list_entries(&self,
+                                header: &SliceHeader,
+                                pic_order_cnt: i32,
+                                violations: &mut Vec<Violation>) -> [Vec<Option<(u64, PictureStructure)>>; 2] {
+        let initial = self.initial_ref_pic_list_entries(header, pic_order_cnt);
+        let mut lists = [vec![], vec![]];
+
+        let num_lists = if header.slice_type.is_bipredictive() {
+            2
+        } else if header.slice_type.is_intra() {
+            0
+        } else {
+            1
+        };
+
+        for i in 0..num_lists {
+            let (num_active, modification) = if i == 0 {
+                (header.num_ref_idx_l0_active_minus1 as usize + 1, header.ref_pic_list_modification_l0.as_ref())
+            } else {
+                (header.num_ref_idx_l1_active_minus1 as usize + 1, header.ref_pic_list_modification_l1.as_ref())
+            };
+
+            let mut list: Vec<Option<(u64, PictureStructure)>> = initial[i].iter().map(|&entry| Some(entry)).collect();
+            list.resize(num_active, None);
+
+            if let Some(operations) = modification {
+                self.modify(&mut list, i, operations, header, violations);
+            }
+
+            lists[i] = list;
+        }
+
+        lists
+    }
+
+    // 8.2.4.3 Modification process for reference picture lists ( Page 109 )
+    fn modify(&self,
+              list: &mut Vec<Option<(u64, PictureStructure)>>,
+              list_idx: usize,
+              operations: &[RefPicListModification],
+              header: &SliceHeader,
+              violations: &mut Vec<Violation>) {
+        let num_active = list.len();
+        // 帧: CurrPicNum = frame_num, MaxPicNum = MaxFrameNum; 场: CurrPicNum = 2 * frame_num + 1, MaxPicNum = 2 * MaxFrameNum
+        let (max_pic_num, curr_pic_num) = if header.field_pic_flag {
+            (2 * self.max_frame_num as i32, 2 * header.frame_num as i32 + 1)
+        } else {
+            (self.max_frame_num as i32, header.frame_num as i32)
+        };
+        let mut pic_num_pred = curr_pic_num;
+
+        let frame = |idx: usize| (self.entries[idx].id, PictureStructure::Frame);
+        let field = |(idx, parity): (usize, usize)| (self.entries[idx].id, PictureStructure::from_parity(parity));
+
+        for (ref_idx, operation) in operations.iter().enumerate() {
+            if ref_idx >= num_active {
+                violations.push(Violation::TooManyModifications { list: list_idx });
+                return;
+            }
+
+            let target = match *operation {
+                RefPicListModification::SubtractAbsDiffPicNum(minus1) | RefPicListModification::AddAbsDiffPicNum(minus1) => {
+                    let abs_diff_pic_num = minus1 as i32 + 1;
+
+                    // 8.2.4.3.1 Modification process of reference picture lists for short-term reference pictures
+                    let pic_num_no_wrap = match *operation {
+                        RefPicListModification::SubtractAbsDiffPicNum(_) => {
+                            if pic_num_pred - abs_diff_pic_num < 0 {
+                                pic_num_pred - abs_diff_pic_num + max_pic_num
+                            } else {
+                                pic_num_pred - abs_diff_pic_num
+                            }
+                        },
+                        _ => {
+                            if pic_num_pred + abs_diff_pic_num >= max_pic_num {
+                                pic_num_pred + abs_diff_pic_num - max_pic_num
+                            } else {
+                                pic_num_pred + abs_diff_pic_num
+                            }
+                        },
+                    };
+                    pic_num_pred = pic_num_no_wrap;
+
+                    let pic_num = if pic_num_no_wrap > curr_pic_num {
+                        pic_num_no_wrap - max_pic_num
+                    } else {
+                        pic_num_no_wrap
+                    };
+
+                    // abs_diff_pic_num_minus1 超出范围时视为引用了不存在的图像
+                    if abs_diff_pic_num > max_pic_num {
+                        None
+                    } else if header.field_pic_flag {
+                        self.short_term_field(pic_num, header).map(field)
+                    } else {
+                        self.short_term(pic_num, header.frame_num).map(frame)
+                    }
+                },
+                // 8.2.4.3.2 Modification process of reference picture lists for long-term reference pictures
+                RefPicListModification::LongTermPicNum(long_term_pic_num) => {
+                    if header.field_pic_flag {
+                        self.long_term_field(long_term_pic_num, header).map(field)
+                    } else {
+                        self.long_term(long_term_pic_num).map(frame)
+                    }
+                },
+            };
+
+            if target.is_none() {
+                violations.push(Violation::MissingModificationTarget { list: list_idx, operation: *operation });
+            }
+
+            list.insert(ref_idx, target);
+            let mut n = ref_idx + 1;
+            for c in ref_idx + 1..list.len() {
+                if target.is_none() || list[c] != target {
+                    list[n] = list[c];
+                    n += 1;
+                }
+            }
+            list.truncate(num_active);
+        }
+    }
+
+    // 8.2.5.1 Sequence of operations for decoded reference picture marking process
+    //
+    // 当前图像解码完成后调用, 标记 DPB 中的图像并返回当前图像的标记
+    pub fn mark(&mut self, header: &SliceHeader) -> Result<Marking, Error> {
+        let mut violations = vec![];
+        let marking = self.mark_with_violations(header, &mut violations);
+        check(violations)?;
+        Ok(marking)
+    }
+
+    // 同 `mark`, 但忽略违反规范的操作并记录到 `violations`
+    pub fn mark_with_violations(&mut self, header: &SliceHeader, violations: &mut Vec<Violation>) -> Marking {
+        let first_field = self.first_field_idx(header);
+        let mut marking = Marking {
+            reference: Reference::Unused,
+            mmco5: false,
+            second_field: first_field.is_some(),
+        };
+
+        if header.nal_ref_idc == NaluRefIdc::DISPOSABLE {
+            return marking;
+        }
+
+        // 8.2.5.3: 互补参考场对的第二个场不使用滑动窗口 ( 第一个场已经占用了一帧 )
+        let dec_ref_pic_marking = match header.dec_ref_pic_marking.as_ref() {
+            Some(dec_ref_pic_marking) => dec_ref_pic_marking,
+            None => {
+                violations.push(Violation::MissingMarking);
+                if first_field.is_none() {
+                    self.sliding_window(header.frame_num);
+                }
+                marking.reference = self.second_field_reference(header, first_field);
+                return marking;
+            },
+        };
+
+        if header.idr_pic_flag() {
+            for entry in self.entries.iter_mut() {
+                entry.set_reference(Reference::Unused);
+            }
+
+            if dec_ref_pic_marking.long_term_reference_flag == Some(true) {
+                self.max_long_term_frame_idx = Some(0);
+                marking.reference = Reference::LongTerm(0);
+            } else {
+                self.max_long_term_frame_idx = None;
+                marking.reference = Reference::ShortTerm;
+            }
+
+            return marking;
+        }
+
+        if dec_ref_pic_marking.adaptive_ref_pic_marking_mode_flag == Some(true) {
+            for operation in dec_ref_pic_marking.operations.iter() {
+                if !self.mmco(header, operation, first_field, &mut marking) {
+                    violations.push(Violation::InvalidOperation(*operation));
+                }
+            }
+        } else if first_field.is_none() {
+            self.sliding_window(header.frame_num);
+        }
+
+        if !marking.reference.is_reference() {
+            marking.reference = self.second_field_reference(header, first_field);
+        }
+
+        marking
+    }
+
+    // 8.2.5.1: 第一个场为长期参考时, 互补参考场对的第二个场使用相同的 LongTermFrameIdx, 否则为短期参考
+    fn second_field_reference(&self, header: &SliceHeader, first_field: Option<usize>) -> Reference {
+        let parity = header.bottom_field_flag as usize;
+        match first_field.map(|idx| self.entries[idx].field_reference[1 - parity]) {
+            Some(Reference::LongTerm(idx)) => Reference::LongTerm(idx),
+            _ => Reference::ShortTerm,
+        }
+    }
+
+    // LongTermFrameIdx 已分配给其他帧或场时, 将它们标记为不用于参考 ( `keep` 所在的项除外 )
+    fn release_long_term_frame_idx(&mut self, long_term_frame_idx: u32, keep: Option<usize>) {
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            if Some(idx) == keep {
+                continue;
+            }
+
+            for parity in 0..2 {
+                if entry.field_reference[parity] == Reference::LongTerm(long_term_frame_idx) {
+                    entry.set_field_reference(parity, Reference::Unused);
+                }
+            }
+        }
+    }
+
+    // 8.2.5.4 Adaptive memory control decoded reference picture marking process
+    //
+    // 解码帧时操作的是帧与互补参考场对, 解码场时操作的是单个场。
+    // 操作引用了不存在的参考图像或非法的 long_term_frame_idx 时返回 false
+    fn mmco(&mut self,
+            header: &SliceHeader,
+            operation: &MemoryManagementControlOperation,
+            first_field: Option<usize>,
+            marking: &mut Marking) -> bool {
+        use self::MemoryManagementControlOperation::*;
+
+        let field_pic_flag = header.field_pic_flag;
+        // CurrPicNum
+        let curr_pic_num = if field_pic_flag { 2 * header.frame_num as i32 + 1 } else { header.frame_num as i32 };
+
+        // ( 项的下标, 场 ), 帧的场为 None
+        let short_term = |dpb: &Self, pic_num: i32| -> Option<(usize, Option<usize>)> {
+            if field_pic_flag {
+                dpb.short_term_field(pic_num, header).map(|(idx, parity)| (idx, Some(parity)))
+            } else {
+                dpb.short_term(pic_num, header.frame_num).map(|idx| (idx, None))
+            }
+        };
+        let set = |dpb: &mut Self, (idx, parity): (usize, Option<usize>), reference: Reference| {
+            match parity {
+                Some(parity) => dpb.entries[idx].set_field_reference(parity, reference),
+                None => dpb.entries[idx].set_reference(reference),
+            }
+        };
+
+        match *operation {
+            MarkShortTermUnused { difference_of_pic_nums_minus1 } => {
+                // 8.2.5.4.1
+                let pic_num_x = curr_pic_num - (difference_of_pic_nums_minus1 as i32 + 1);
+                match short_term(self, pic_num_x) {
+                    Some(target) => set(self, target, Reference::Unused),
+                    None => return false,
+                }
+            },
+            MarkLongTermUnused { long_term_pic_num } => {
+                // 8.2.5.4.2
+                let target = if field_pic_flag {
+                    self.long_term_field(long_term_pic_num, header).map(|(idx, parity)| (idx, Some(parity)))
+                } else {
+                    self.long_term(long_term_pic_num).map(|idx| (idx, None))
+                };
+                match target {
+                    Some(target) => set(self, target, Reference::Unused),
+                    None => return false,
+                }
+            },
+            MarkShortTermAsLongTerm { difference_of_pic_nums_minus1, long_term_frame_idx } => {
+                // 8.2.5.4.3
+                if self.max_long_term_frame_idx.is_none_or(|max| long_term_frame_idx > max) {
+                    return false;
+                }
+
+                let pic_num_x = curr_pic_num - (difference_of_pic_nums_minus1 as i32 + 1);
+                let target = match short_term(self, pic_num_x) {
+                    Some(target) => target,
+                    None => return false,
+                };
+
+                // 同一帧中的另一个场可以使用相同的 LongTermFrameIdx
+                self.release_long_term_frame_idx(long_term_frame_idx, Some(target.0));
+                set(self, target, Reference::LongTerm(long_term_frame_idx));
+            },
+            SetMaxLongTermFrameIdx { max_long_term_frame_idx_plus1 } => {
+                // 8.2.5.4.4
+                for entry in self.entries.iter_mut() {
+                    for parity in 0..2 {
+                        if let Reference::LongTerm(idx) = entry.field_reference[parity] {
+                            if idx + 1 > max_long_term_frame_idx_plus1 {
+                                entry.set_field_reference(parity, Reference::Unused);
+                            }
+                        }
+                    }
+                }
+
+                self.max_long_term_frame_idx = max_long_term_frame_idx_plus1.checked_sub(1);
+            },
+            MarkAllUnused => {
+                // 8.2.5.4.5
+                for entry in self.entries.iter_mut() {
+                    entry.set_reference(Reference::Unused);
+                }
+                self.max_long_term_frame_idx = None;
+                marking.mmco5 = true;
+            },
+            MarkCurrentAsLongTerm { long_term_frame_idx } => {
+                // 8.2.5.4.6
+                if self.max_long_term_frame_idx.is_none_or(|max| long_term_frame_idx > max) {
+                    return false;
+                }
+
+                self.release_long_term_frame_idx(long_term_frame_idx, first_field);
+                marking.reference = Reference::LongTerm(long_term_frame_idx);
+            },
+        }
+
+        true
+    }
+
+    // C.4.4 Removal of pictures from the DPB before possible insertion of the current picture
+    // C.4.5 Current decoded picture marking and storage
+    //
+    // `pic_order_cnt` 为 memory_management_control_operation 5 处理之后的值, 返回当前图像的 id。
+    // 互补场对的第二个场存入第一个场所在的项 ( 返回第一个场的 id ), `data` 替换第一个场的数据。
+    pub fn store(&mut self,
+                 header: &SliceHeader,
+                 marking: &Marking,
+                 pic_order_cnt: &PicOrderCnt,
+                 data: T,
+                 output: &mut dyn FnMut(&DpbEntry<T>)) -> u64 {
+        if header.idr_pic_flag() || marking.mmco5 {
+            let no_output_of_prior_pics_flag = header.idr_pic_flag() && header.dec_ref_pic_marking.as_ref()
+                .and_then(|marking| marking.no_output_of_prior_pics_flag)
+                .unwrap_or(false);
+
+            if !no_output_of_prior_pics_flag {
+                self.flush(output);
+            }
+            self.entries.clear();
+        } else {
+            self.remove_unused();
+        }
+
+        // memory_management_control_operation 5 之后 frame_num 视为 0
+        let frame_num = if marking.mmco5 { 0 } else { header.frame_num };
+        if marking.reference.is_reference() {
+            self.prev_ref_frame_num = frame_num;
+        }
+
+        let structure = header.structure();
+        let top = pic_order_cnt.top.or(pic_order_cnt.bottom).unwrap_or(0);
+        let bottom = pic_order_cnt.bottom.or(pic_order_cnt.top).unwrap_or(0);
+
+        let next_id = self.next_id;
+        let first_field = match marking.second_field {
+            true => self.entries.iter_mut().find(|entry| entry.id + 1 == next_id),
+            false => None,
+        };
+        if let Some(entry) = first_field {
+            let parity = header.bottom_field_flag as usize;
+            entry.structure = PictureStructure::FieldPair;
+            if parity == 0 {
+                entry.top_field_order_cnt = top;
+            } else {
+                entry.bottom_field_order_cnt = bottom;
+            }
+            entry.set_field_reference(parity, marking.reference);
+            entry.data = Some(data);
+            return entry.id;
+        }
+
+        let mut entry = DpbEntry {
+            id: self.next_id,
+            frame_num: frame_num,
+            structure: structure,
+            top_field_order_cnt: top,
+            bottom_field_order_cnt: bottom,
+            reference: Reference::Unused,
+            field_reference: [Reference::Unused; 2],
+            needed_for_output: true,
+            data: Some(data),
+        };
+        entry.set_reference(marking.reference);
+        self.next_id += 1;
+
+        if entry.reference.is_reference() {
+            // C.4.5.1 Storage and marking of a reference decoded picture into the DPB
+            while !self.has_empty_frame_buffer() && self.bump(output) { }
+            self.entries.push(entry);
+        } else {
+            // C.4.5.2 Storage and marking of a non-reference decoded picture into the DPB
+            loop {
+                if self.has_empty_frame_buffer() {
+                    self.entries.push(entry);
+                    break;
+                }
+
+                let lowest = self.entries.iter()
+                    .filter(|entry| entry.needed_for_output)
+                    .map(|entry| entry.pic_order_cnt())
+                    .min();
+
+                if lowest.is_none_or(|lowest| entry.pic_order_cnt() < lowest) {
+                    output(&entry);
+                    break;
+                }
+
+                self.bump(output);
+            }
+        }
+
+        self.next_id - 1
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nalu::NaluKind;
+    use crate::rbsp::{ ParameterSets, SliceType, DecRefPicMarking };
+    use crate::slice::test::{ parameter_sets, predicted_slice_header };
+    use crate::decoder::poc::PocState;
+
+    struct Sequence {
+        parameter_sets: ParameterSets,
+        dpb: Dpb<()>,
+        poc: PocState,
+        // 按输出顺序的 PicOrderCnt
+        output: Vec<i32>,
+    }
+
+    impl Sequence {
+        fn new(max_num_ref_frames: usize, size: usize) -> Self {
+            let mut dpb = Dpb::new();
+            dpb.max_num_ref_frames = max_num_ref_frames;
+            dpb.size = size;
+
+            Sequence {
+                parameter_sets: parameter_sets(),
+                dpb: dpb,
+                poc: PocState::new(),
+                output: vec![],
+            }
+        }
+
+        fn header(&self, frame_num: u32, pic_order_cnt_lsb: u32, operations: Vec<MemoryManagementControlOperation>) -> SliceHeader {
+            let mut header = predicted_slice_header(&self.parameter_sets);
+            header.frame_num = frame_num;
+            header.pic_order_cnt_lsb = Some(pic_order_cnt_lsb);
+            if frame_num == 0 && operations.is_empty() {
+                header.nal_unit_type = NaluKind::CodedSliceIdr;
+                header.slice_type = SliceType::I;
+                header.dec_ref_pic_marking = Some(DecRefPicMarking {
+                    no_output_of_prior_pics_flag: Some(false),
+                    long_term_reference_flag: Some(false),
+                    adaptive_ref_pic_marking_mode_flag: None,
+                    operations: vec![],
+                });
+            } else if !operations.is_empty() {
+                header.dec_ref_pic_marking = Some(DecRefPicMarking {
+                    no_output_of_prior_pics_flag: None,
+                    long_term_reference_flag: None,
+                    adaptive_ref_pic_marking_mode_flag: Some(true),
+                    operations: operations,
+                });
+            }
+            header
+        }
+
+        fn decode(&mut self, header: &SliceHeader) -> u64 {
+            let sps = self.parameter_sets.sps(0).unwrap();
+            let output = &mut self.output;
+
+            self.dpb.fill_frame_num_gap(header, &mut |entry| output.push(entry.pic_order_cnt()));
+            let mut poc = self.poc.decode(header, sps);
+            let marking = self.dpb.mark(header).unwrap();
+            if marking.mmco5 {
+                poc.reset();
+            }
+            self.poc.update(header, &poc, marking.mmco5);
+            self.dpb.store(header, &marking, &poc, (), &mut |entry| output.push(entry.pic_order_cnt()))
+        }
+
+        fn reference(&self, id: u64) -> Reference {
+            self.dpb.get(id).map(|entry| entry.reference).unwrap_or(Reference::Unused)
+        }
+    }
+
+    #[test]
+    fn test_sliding_window_and_modification() {
+        let mut sequence = Sequence::new(2, 4);
+
+        let idr = sequence.decode(&sequence.header(0, 0, vec![]));
+        let p1 = sequence.decode(&sequence.header(1, 2, vec![]));
+        let p2 = sequence.decode(&sequence.header(2, 4, vec![]));
+        assert_eq!(sequence.reference(idr), Reference::Unused);
+        assert_eq!(sequence.reference(p1), Reference::ShortTerm);
+        assert_eq!(sequence.reference(p2), Reference::ShortTerm);
+
+        // PicNum 降序
+        let mut header = sequence.header(3, 6, vec![]);
+        assert_eq!(sequence.dpb.initial_ref_pic_lists(&header, 6), [vec![p2, p1], vec![]]);
+        header.num_ref_idx_l0_active_minus1 = 2;
+        assert_eq!(sequence.dpb.ref_pic_lists(&header, 6).unwrap()[0], vec![Some(p2), Some(p1), None]);
+
+        // picNumL0NoWrap = 3 - 2 = 1
+        header.num_ref_idx_l0_active_minus1 = 1;
+        header.ref_pic_list_modification_l0 = Some(vec![RefPicListModification::SubtractAbsDiffPicNum(1)]);
+        assert_eq!(sequence.dpb.ref_pic_lists(&header, 6).unwrap()[0], vec![Some(p1), Some(p2)]);
+
+        // 不存在的图像
+        header.ref_pic_list_modification_l0 = Some(vec![RefPicListModification::LongTermPicNum(0)]);
+        assert!(sequence.dpb.ref_pic_lists(&header, 6).is_err());
+
+        // frame_num 间隙: 推导出 frame_num 为 3, 4 的 "non-existing" 帧
+        let p5 = sequence.decode(&sequence.header(5, 10, vec![]));
+        let non_existing: Vec<u32> = sequence.dpb.entries().iter()
+            .filter(|entry| entry.is_non_existing() && entry.reference.is_reference())
+            .map(|entry| entry.frame_num)
+            .collect();
+        assert_eq!(non_existing, vec![4]);
+        assert_eq!(sequence.reference(p5), Reference::ShortTerm);
+    }
+
+    #[test]
+    fn test_bipredictive_lists_and_bumping() {
+        let mut sequence = Sequence::new(16, 2);
+
+        let idr = sequence.decode(&sequence.header(0, 0, vec![]));
+        let p1 = sequence.decode(&sequence.header(1, 8, vec![]));
+
+        let mut header = sequence.header(2, 4, vec![]);
+        header.slice_type = SliceType::B;
+        header.nal_ref_idc = NaluRefIdc::DISPOSABLE;
+        header.dec_ref_pic_marking = None;
+        assert_eq!(sequence.dpb.initial_ref_pic_lists(&header, 4), [vec![idr, p1], vec![p1, idr]]);
+
+        // 只有一个参考图像在当前图像之后时, RefPicList1 与 RefPicList0 相同则交换前两项
+        assert_eq!(sequence.dpb.initial_ref_pic_lists(&header, 10), [vec![p1, idr], vec![idr, p1]]);
+
+        // DPB 已满: 输出 POC 0, 然后直接输出当前的非参考图像
+        sequence.decode(&header);
+        assert_eq!(sequence.output, vec![0, 4]);
+
+        let output = &mut sequence.output;
+        sequence.dpb.flush(&mut |entry| output.push(entry.pic_order_cnt()));
+        assert_eq!(sequence.output, vec![0, 4, 8]);
+    }
+
+    #[test]
+    fn test_adaptive_marking() {
+        use self::MemoryManagementControlOperation::*;
+
+        let mut sequence = Sequence::new(4, 4);
+
+        let idr = sequence.decode(&sequence.header(0, 0, vec![]));
+        let p1 = sequence.decode(&sequence.header(1, 2, vec![
+            SetMaxLongTermFrameIdx { max_long_term_frame_idx_plus1: 1 },
+            MarkCurrentAsLongTerm { long_term_frame_idx: 0 },
+        ]));
+        assert_eq!(sequence.reference(p1), Reference::LongTerm(0));
+        assert_eq!(sequence.dpb.max_long_term_frame_idx(), Some(0));
+
+        // picNumX = 2 - ( 1 + 1 ) = 0
+        let p2 = sequence.decode(&sequence.header(2, 4, vec![
+            MarkShortTermUnused { difference_of_pic_nums_minus1: 1 },
+        ]));
+        assert_eq!(sequence.reference(idr), Reference::Unused);
+        assert_eq!(sequence.reference(p2), Reference::ShortTerm);
+
+        // 短期参考帧在前, 然后是长期参考帧
+        let header = sequence.header(3, 6, vec![]);
+        assert_eq!(sequence.dpb.initial_ref_pic_lists(&header, 6)[0], vec![p2, p1]);
+
+        // 长期参考帧索引超过 MaxLongTermFrameIdx
+        let header = sequence.header(3, 6, vec![MarkCurrentAsLongTerm { long_term_frame_idx: 1 }]);
+        assert!(sequence.dpb.clone().mark(&header).is_err());
+
+        // memory_management_control_operation 5: 之前的图像全部输出, 当前图像的 POC 为 0
+        let p3 = sequence.decode(&sequence.header(3, 6, vec![MarkAllUnused]));
+        assert_eq!(sequence.output, vec![0, 2, 4]);
+        assert_eq!(sequence.dpb.entries().len(), 1);
+        assert_eq!(sequence.dpb.get(p3).unwrap().pic_order_cnt(), 0);
+        assert_eq!(sequence.dpb.get(p3).unwrap().frame_num, 0);
+
+        // 之后的 frame_num 从 1 开始
+        let p4 = sequence.decode(&sequence.header(1, 10, vec![]));
+        assert_eq!(sequence.dpb.initial_ref_pic_lists(&sequence.header(2, 12, vec![]), 12)[0], vec![p4, p3]);
+    }
+
+    #[test]
+    fn test_field_pairs() {
+        use self::MemoryManagementControlOperation::*;
+        use self::PictureStructure::*;
+
+        let mut sequence = Sequence::new(2, 4);
+        let field = |mut header: SliceHeader, bottom: bool| {
+            header.field_pic_flag = true;
+            header.bottom_field_flag = bottom;
+            header
+        };
+
+        // IDR 顶场, 然后是同一帧的底场 ( 非 IDR 的 P 场 )
+        let idr = sequence.decode(&field(sequence.header(0, 0, vec![]), false));
+        assert_eq!(sequence.dpb.get(idr).unwrap().structure, TopField);
+
+        let mut header = field(sequence.header(0, 1, vec![]), true);
+        header.nal_unit_type = NaluKind::CodedSliceNonIdr;
+        header.slice_type = SliceType::P;
+        header.dec_ref_pic_marking = predicted_slice_header(&sequence.parameter_sets).dec_ref_pic_marking;
+        assert!(sequence.dpb.is_second_field(&header));
+        assert_eq!(sequence.dpb.initial_ref_pic_list_entries(&header, 1)[0], vec![(idr, TopField)]);
+        assert_eq!(sequence.decode(&header), idr);
+
+        let entry = sequence.dpb.get(idr).unwrap();
+        assert_eq!(entry.structure, FieldPair);
+        assert_eq!((entry.top_field_order_cnt, entry.bottom_field_order_cnt), (0, 1));
+        assert_eq!(entry.field_reference, [Reference::ShortTerm; 2]);
+
+        // 8.2.4.2.5: 从同奇偶的场开始交替
+        let header = field(sequence.header(1, 4, vec![]), false);
+        assert_eq!(sequence.dpb.initial_ref_pic_list_entries(&header, 4)[0], vec![(idr, TopField), (idr, BottomField)]);
+        let p1 = sequence.decode(&header);
+
+        // 底场没有同奇偶的场可取时, 依次加入剩余的顶场
+        let mut header = field(sequence.header(1, 5, vec![]), true);
+        assert_eq!(sequence.dpb.initial_ref_pic_list_entries(&header, 5)[0],
+                   vec![(idr, BottomField), (p1, TopField), (idr, TopField)]);
+
+        // CurrPicNum = 3, 顶场 p1 的 PicNum 为 2
+        header.num_ref_idx_l0_active_minus1 = 1;
+        header.ref_pic_list_modification_l0 = Some(vec![RefPicListModification::SubtractAbsDiffPicNum(0)]);
+        let mut violations = vec![];
+        assert_eq!(sequence.dpb.ref_pic_list_entries(&header, 5, &mut violations)[0],
+                   vec![Some((p1, TopField)), Some((idr, BottomField))]);
+        assert!(violations.is_empty());
+
+        // picNumX = 3 - 2 = 1: 只标记 IDR 帧的底场
+        let mut header = field(sequence.header(1, 5, vec![MarkShortTermUnused { difference_of_pic_nums_minus1: 1 }]), true);
+        header.ref_pic_list_modification_l0 = None;
+        assert_eq!(sequence.decode(&header), p1);
+        let entry = sequence.dpb.get(idr).unwrap();
+        assert_eq!(entry.field_reference, [Reference::ShortTerm, Reference::Unused]);
+        assert_eq!(entry.reference, Reference::ShortTerm);
+        assert_eq!(sequence.dpb.get(p1).unwrap().structure, FieldPair);
+
+        // 解码帧时只使用两个场均为参考场的帧
+        let header = sequence.header(2, 8, vec![]);
+        assert_eq!(sequence.dpb.initial_ref_pic_lists(&header, 8)[0], vec![p1]);
+
+        let output = &mut sequence.output;
+        sequence.dpb.flush(&mut |entry| output.push(entry.pic_order_cnt()));
+        assert_eq!(sequence.output, vec![0, 4]);
+    }
+}
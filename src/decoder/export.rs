@@ -0,0 +1,270 @@
+// 导出每个图像中宏块的类型, 分区与运动向量 ( 类似 ffmpeg 的 export_mvs ), 用于低成本的运动分析。
+// 运动向量为 8.4.1 推导出的 mvLX ( 1/4 亮度样本 ), 而不是码流中的 mvd。
+//
+// 场图像与 MBAFF 帧中的场宏块标记为顶场或底场: 宏块覆盖帧中隔行的样本, 运动向量的垂直分量为 1/4 场样本。
+
+use crate::rbsp::PictureStructure;
+use crate::macroblock::{ MbType, SubMbType };
+use super::picture::{ Picture, MbState };
+
+use byteorder::{ LittleEndian, WriteBytesExt };
+
+use std::io::{ self, Write };
+use std::fmt::Write as FmtWrite;
+
+
+// 宏块内的一个运动分区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionPartition {
+    // 在宏块内的位置与大小 ( 亮度样本 )
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub height: u8,
+    // refIdxL0, refIdxL1, 未使用该列表时为 -1
+    pub ref_idx: [i8; 2],
+    // mvL0, mvL1
+    pub mv: [[i32; 2]; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroblockMotion {
+    // 场图像中为场内的地址, MBAFF 帧中为按宏块对排列的地址
+    pub mb_addr: u32,
+    pub mb_type: MbType,
+    // 帧宏块为 Frame, 场宏块为它所在的场
+    pub structure: PictureStructure,
+    // 帧内宏块没有运动分区
+    pub partitions: Vec<MotionPartition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MotionInfo {
+    pub pic_order_cnt: i32,
+    // 帧的宽高 ( 宏块 )
+    pub width_in_mbs: u32,
+    pub height_in_mbs: u32,
+    // 帧, 互补场对, 或没有配对的单个场
+    pub structure: PictureStructure,
+    pub mbaff_frame_flag: bool,
+    // 帧中按 mb_addr 排列; 场图像先是顶场, 然后是底场。只包含已解码的宏块
+    pub macroblocks: Vec<MacroblockMotion>,
+}
+
+impl MotionInfo {
+    pub(crate) fn from_picture(picture: &Picture, pic_order_cnt: i32, structure: PictureStructure) -> Self {
+        let direct_8x8_inference_flag = picture.direct_8x8_inference_flag;
+        let motion = |mb_addr: usize, state: &MbState, structure: PictureStructure| MacroblockMotion {
+            mb_addr: mb_addr as u32,
+            mb_type: state.mb_type,
+            structure: structure,
+            partitions: partitions(state, direct_8x8_inference_flag),
+        };
+
+        let mut macroblocks = vec![];
+        if picture.field_pic_flag {
+            for parity in 0..2 {
+                for (mb_addr, state) in picture.field_mbs(parity).iter().enumerate() {
+                    if let Some(state) = state.as_ref() {
+                        macroblocks.push(motion(mb_addr, state, PictureStructure::from_parity(parity)));
+                    }
+                }
+            }
+        } else {
+            for (mb_addr, state) in picture.mbs.iter().enumerate() {
+                if let Some(state) = state.as_ref() {
+                    let structure = if state.field { PictureStructure::from_parity(mb_addr % 2) } else { PictureStructure::Frame };
+                    macroblocks.push(motion(mb_addr, state, structure));
+                }
+            }
+        }
+
+        MotionInfo {
+            pic_order_cnt: pic_order_cnt,
+            width_in_mbs: picture.width_in_mbs,
+            height_in_mbs: picture.mbs.len() as u32 / picture.width_in_mbs,
+            structure: structure,
+            mbaff_frame_flag: picture.mbaff_frame_flag,
+            macroblocks: macroblocks,
+        }
+    }
+
+    // 宏块第一行的第一个样本在帧中的位置 ( 亮度样本, 未裁剪 ); 场宏块的各行在帧中相隔一行
+    pub fn mb_position(&self, mb: &MacroblockMotion) -> (u32, u32) {
+        let parity = mb.structure.parity().unwrap_or(0) as u32;
+        if !self.mbaff_frame_flag {
+            let (x, y) = (mb.mb_addr % self.width_in_mbs * 16, mb.mb_addr / self.width_in_mbs * 16);
+            return if mb.structure.is_field() { (x, 2 * y + parity) } else { (x, y) };
+        }
+
+        let pair = mb.mb_addr / 2;
+        let (x, y) = (pair % self.width_in_mbs * 16, pair / self.width_in_mbs * 32);
+        if mb.structure.is_field() {
+            (x, y + parity)
+        } else {
+            (x, y + mb.mb_addr % 2 * 16)
+        }
+    }
+
+    // {"pic_order_cnt":0,"width_in_mbs":2,"height_in_mbs":1,"structure":"frame","mbaff":false,"macroblocks":[
+    //  {"mb_addr":0,"mb_type":"P_Skip","structure":"frame",
+    //   "partitions":[{"x":0,"y":0,"width":16,"height":16,"ref_idx":[0,-1],"mv":[[0,0],[0,0]]}]}]}
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(json, "{{\"pic_order_cnt\":{},\"width_in_mbs\":{},\"height_in_mbs\":{},\"structure\":\"{}\",\"mbaff\":{},\"macroblocks\":[",
+               self.pic_order_cnt, self.width_in_mbs, self.height_in_mbs, self.structure.name(), self.mbaff_frame_flag).unwrap();
+
+        for (i, mb) in self.macroblocks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(json, "{{\"mb_addr\":{},\"mb_type\":\"{}\",\"structure\":\"{}\",\"partitions\":[",
+                   mb.mb_addr, mb.mb_type.name(), mb.structure.name()).unwrap();
+
+            for (j, part) in mb.partitions.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                write!(json, "{{\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"ref_idx\":[{},{}],\"mv\":[[{},{}],[{},{}]]}}",
+                       part.x, part.y, part.width, part.height, part.ref_idx[0], part.ref_idx[1],
+                       part.mv[0][0], part.mv[0][1], part.mv[1][0], part.mv[1][1]).unwrap();
+            }
+
+            json.push_str("]}");
+        }
+
+        json.push_str("]}");
+        json
+    }
+
+    // 小端的二进制格式:
+    //
+    //   i32 pic_order_cnt, u16 width_in_mbs, u16 height_in_mbs, u8 structure ( 见 structure_code ), u8 MbaffFrameFlag,
+    //   u32 宏块个数, 然后每个宏块:
+    //   u32 mb_addr, u8 mb_type ( 见 mb_type_code ), u8 structure, u8 分区个数, 然后每个分区:
+    //   u8 x, u8 y, u8 width, u8 height, i8 refIdxL0, i8 refIdxL1, i16 mvL0[ 2 ], i16 mvL1[ 2 ]
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_i32::<LittleEndian>(self.pic_order_cnt)?;
+        writer.write_u16::<LittleEndian>(self.width_in_mbs as u16)?;
+        writer.write_u16::<LittleEndian>(self.height_in_mbs as u16)?;
+        writer.write_u8(structure_code(self.structure))?;
+        writer.write_u8(self.mbaff_frame_flag as u8)?;
+        writer.write_u32::<LittleEndian>(self.macroblocks.len() as u32)?;
+
+        for mb in self.macroblocks.iter() {
+            writer.write_u32::<LittleEndian>(mb.mb_addr)?;
+            writer.write_u8(mb_type_code(mb.mb_type))?;
+            writer.write_u8(structure_code(mb.structure))?;
+            writer.write_u8(mb.partitions.len() as u8)?;
+
+            for part in mb.partitions.iter() {
+                writer.write_all(&[part.x, part.y, part.width, part.height])?;
+                writer.write_i8(part.ref_idx[0])?;
+                writer.write_i8(part.ref_idx[1])?;
+                // 8.4.1: 运动向量的取值范围在 16 位以内
+                for list in 0..2 {
+                    writer.write_i16::<LittleEndian>(part.mv[list][0] as i16)?;
+                    writer.write_i16::<LittleEndian>(part.mv[list][1] as i16)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// 二进制格式中的 mb_type ( 与 slice_type 无关 )
+pub fn mb_type_code(mb_type: MbType) -> u8 {
+    match mb_type {
+        MbType::INxN => 0,
+        MbType::I16x16 { .. } => 1,
+        MbType::IPcm => 2,
+        MbType::Si => 3,
+        MbType::P16x16 => 4,
+        MbType::P16x8 => 5,
+        MbType::P8x16 => 6,
+        MbType::P8x8 => 7,
+        MbType::P8x8Ref0 => 8,
+        MbType::PSkip => 9,
+        MbType::BDirect16x16 => 10,
+        MbType::B16x16(_) => 11,
+        MbType::B16x8(_, _) => 12,
+        MbType::B8x16(_, _) => 13,
+        MbType::B8x8 => 14,
+        MbType::BSkip => 15,
+    }
+}
+
+// 二进制格式中的 structure
+pub fn structure_code(structure: PictureStructure) -> u8 {
+    match structure {
+        PictureStructure::Frame => 0,
+        PictureStructure::TopField => 1,
+        PictureStructure::BottomField => 2,
+        PictureStructure::FieldPair => 3,
+    }
+}
+
+// 按 mb_type 与 sub_mb_type 划分的运动分区; 直接预测的分区在 direct_8x8_inference_flag 为 1 时为 8x8, 否则为 4x4
+fn partitions(state: &MbState, direct_8x8_inference_flag: bool) -> Vec<MotionPartition> {
+    let mut partitions = vec![];
+    if state.mb_type.is_intra() {
+        return partitions;
+    }
+
+    let mut push = |x: u32, y: u32, width: u32, height: u32| {
+        let blk = (y / 4 * 4 + x / 4) as usize;
+        let blk8x8 = (y / 8 * 2 + x / 8) as usize;
+        partitions.push(MotionPartition {
+            x: x as u8,
+            y: y as u8,
+            width: width as u8,
+            height: height as u8,
+            ref_idx: [state.ref_idx[0][blk8x8], state.ref_idx[1][blk8x8]],
+            mv: [state.mv[0][blk], state.mv[1][blk]],
+        });
+    };
+
+    let direct = |push: &mut dyn FnMut(u32, u32, u32, u32), x: u32, y: u32| {
+        if direct_8x8_inference_flag {
+            push(x, y, 8, 8);
+        } else {
+            for i in 0..4 {
+                push(x + i % 2 * 4, y + i / 2 * 4, 4, 4);
+            }
+        }
+    };
+
+    match state.mb_type {
+        MbType::BSkip | MbType::BDirect16x16 => {
+            for mb_part_idx in 0..4 {
+                direct(&mut push, mb_part_idx % 2 * 8, mb_part_idx / 2 * 8);
+            }
+        },
+        MbType::P8x8 | MbType::P8x8Ref0 | MbType::B8x8 => {
+            let sub_mb_types = state.sub_mb_type.unwrap_or([SubMbType::P8x8; 4]);
+            for (mb_part_idx, sub_mb_type) in sub_mb_types.iter().enumerate() {
+                let (x, y) = (mb_part_idx as u32 % 2 * 8, mb_part_idx as u32 / 2 * 8);
+                if *sub_mb_type == SubMbType::BDirect8x8 {
+                    direct(&mut push, x, y);
+                    continue;
+                }
+
+                let (width, height) = sub_mb_type.sub_mb_part_size();
+                for sub_mb_part_idx in 0..sub_mb_type.num_sub_mb_part() as u32 {
+                    push(x + sub_mb_part_idx % (8 / width) * width,
+                         y + sub_mb_part_idx / (8 / width) * height,
+                         width, height);
+                }
+            }
+        },
+        _ => {
+            let (width, height) = state.mb_type.mb_part_size();
+            for mb_part_idx in 0..state.mb_type.num_mb_part() as u32 {
+                push(mb_part_idx % (16 / width) * width, mb_part_idx / (16 / width) * height, width, height);
+            }
+        },
+    }
+
+    partitions
+}
@@ -0,0 +1,250 @@
+// HDR 元数据: VUI 中的颜色描述 ( E.2.1 ) 以及 mastering_display_colour_volume, content_light_level_info,
+// alternative_transfer_characteristics 与 ambient_viewing_environment SEI, 用于检查 HDR10 交付规范。
+
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{
+    SequenceParameterSet, SupplementalEnhancementInformation, SeiMessageKind, SeiPayload,
+    MasteringDisplayColourVolume, ContentLightLevelInfo, AlternativeTransferCharacteristics, AmbientViewingEnvironment,
+};
+
+
+// Table E-3 colour_primaries: BT.2020
+pub const COLOUR_PRIMARIES_BT2020: u8 = 9;
+// Table E-4 transfer_characteristics: SMPTE ST 2084 ( PQ ), ARIB STD-B67 ( HLG )
+pub const TRANSFER_CHARACTERISTICS_PQ: u8 = 16;
+pub const TRANSFER_CHARACTERISTICS_HLG: u8 = 18;
+// Table E-5 matrix_coefficients: BT.2020 non-constant luminance
+pub const MATRIX_COEFFICIENTS_BT2020_NCL: u8 = 9;
+
+
+// 一个码流的 HDR 元数据 ( 最近的 SPS 与每种 SEI 最近的值 )
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HdrMetadata {
+    pub bit_depth_luma: Option<u32>,
+    pub video_full_range_flag: Option<bool>,
+    pub colour_primaries: Option<u8>,
+    pub transfer_characteristics: Option<u8>,
+    pub matrix_coefficients: Option<u8>,
+    pub mastering_display_colour_volume: Option<MasteringDisplayColourVolume>,
+    pub content_light_level_info: Option<ContentLightLevelInfo>,
+    pub alternative_transfer_characteristics: Option<AlternativeTransferCharacteristics>,
+    pub ambient_viewing_environment: Option<AmbientViewingEnvironment>,
+    // 在码流中途改变了取值的 SEI
+    pub changed: Vec<SeiMessageKind>,
+}
+
+fn update<T: PartialEq + Copy>(value: &mut Option<T>, new: T, kind: SeiMessageKind, changed: &mut Vec<SeiMessageKind>) {
+    if let Some(old) = *value {
+        if old != new && !changed.contains(&kind) {
+            changed.push(kind);
+        }
+    }
+    *value = Some(new);
+}
+
+impl HdrMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 处理一个 NALU; SPS 与 SEI 之外的 NALU 被忽略
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        match nalu.kind() {
+            NaluKind::SequenceParameterSet => {
+                let sps = match nalu.payload().as_any().downcast_ref::<SequenceParameterSet>() {
+                    Some(sps) => sps,
+                    None => return Err(error::malformed("sps payload expected")),
+                };
+                self.update_sps(sps);
+            },
+            NaluKind::SupplementalEnhancementInformation => {
+                let sei = match nalu.payload().as_any().downcast_ref::<SupplementalEnhancementInformation>() {
+                    Some(sei) => sei,
+                    None => return Err(error::malformed("sei payload expected")),
+                };
+                self.update_sei(sei);
+            },
+            _ => { },
+        }
+
+        Ok(())
+    }
+
+    pub fn update_sps(&mut self, sps: &SequenceParameterSet) {
+        self.bit_depth_luma = Some(sps.bit_depth_luma());
+
+        let vui = sps.vui_parameters();
+        self.video_full_range_flag = vui.and_then(|vui| vui.video_full_range_flag);
+        self.colour_primaries = vui.and_then(|vui| vui.colour_primaries);
+        self.transfer_characteristics = vui.and_then(|vui| vui.transfer_characteristics);
+        self.matrix_coefficients = vui.and_then(|vui| vui.matrix_coefficients);
+    }
+
+    pub fn update_sei(&mut self, sei: &SupplementalEnhancementInformation) {
+        let changed = &mut self.changed;
+        for message in sei.messages() {
+            match message.payload {
+                SeiPayload::MasteringDisplayColourVolume(payload) => {
+                    update(&mut self.mastering_display_colour_volume, payload, message.kind, changed)
+                },
+                SeiPayload::ContentLightLevelInfo(payload) => {
+                    update(&mut self.content_light_level_info, payload, message.kind, changed)
+                },
+                SeiPayload::AlternativeTransferCharacteristics(payload) => {
+                    update(&mut self.alternative_transfer_characteristics, payload, message.kind, changed)
+                },
+                SeiPayload::AmbientViewingEnvironment(payload) => {
+                    update(&mut self.ambient_viewing_environment, payload, message.kind, changed)
+                },
+                _ => { },
+            }
+        }
+    }
+
+    // D.2.33: 支持 preferred_transfer_characteristics 的解码器使用它代替 VUI 的 transfer_characteristics,
+    // is_pq, is_hlg 与 hdr10_violations 都以它为准
+    pub fn preferred_transfer_characteristics(&self) -> Option<u8> {
+        self.alternative_transfer_characteristics
+            .map(|payload| payload.preferred_transfer_characteristics)
+            .or(self.transfer_characteristics)
+    }
+
+    pub fn is_pq(&self) -> bool {
+        self.preferred_transfer_characteristics() == Some(TRANSFER_CHARACTERISTICS_PQ)
+    }
+
+    // HLG, 或者兼容 SDR 的 HLG ( VUI 为 BT.709 / BT.2020, alternative_transfer_characteristics 为 HLG )
+    pub fn is_hlg(&self) -> bool {
+        self.preferred_transfer_characteristics() == Some(TRANSFER_CHARACTERISTICS_HLG)
+    }
+
+    // 不满足 HDR10 的项 ( 为空时满足 ): 10 比特, BT.2020, PQ, mastering display 与 content light level
+    pub fn hdr10_violations(&self) -> Vec<&'static str> {
+        let mut violations = vec![];
+
+        if self.bit_depth_luma.is_none_or(|bit_depth| bit_depth < 10) {
+            violations.push("bit depth is less than 10");
+        }
+        if self.colour_primaries != Some(COLOUR_PRIMARIES_BT2020) {
+            violations.push("colour_primaries is not BT.2020");
+        }
+        if !self.is_pq() {
+            violations.push("preferred transfer characteristics is not SMPTE ST 2084");
+        }
+        if self.matrix_coefficients != Some(MATRIX_COEFFICIENTS_BT2020_NCL) {
+            violations.push("matrix_coefficients is not BT.2020 non-constant luminance");
+        }
+
+        match self.mastering_display_colour_volume {
+            Some(mastering_display) => {
+                if mastering_display.min_display_mastering_luminance >= mastering_display.max_display_mastering_luminance {
+                    violations.push("min_display_mastering_luminance is not less than max_display_mastering_luminance");
+                }
+            },
+            None => violations.push("mastering_display_colour_volume is missing"),
+        }
+
+        match self.content_light_level_info {
+            Some(content_light_level) => {
+                if content_light_level.max_pic_average_light_level > content_light_level.max_content_light_level {
+                    violations.push("MaxFALL is greater than MaxCLL");
+                }
+            },
+            None => violations.push("content_light_level_info is missing"),
+        }
+
+        if self.ambient_viewing_environment.is_some_and(|ambient| ambient.ambient_illuminance == 0) {
+            violations.push("ambient_illuminance is 0");
+        }
+
+        if !self.changed.is_empty() {
+            violations.push("HDR metadata changes within the stream");
+        }
+
+        violations
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::HdrMetadata;
+    use crate::rbsp::{ SupplementalEnhancementInformation, SeiMessageKind, SeiPayload, ContentLightLevelInfo };
+    use crate::slice::test::Writer;
+
+    #[test]
+    fn test_hdr_metadata() {
+        // High 10, 4:2:0 10 比特, VUI: BT.2020, PQ, BT.2020 非恒定亮度
+        let sps = Writer::new(0x67)
+            .u(8, 110).u(8, 0).u(8, 40).ue(0)
+            .ue(1).ue(2).ue(2).u(1, 0).u(1, 0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 1)
+            .u(1, 0).u(1, 0).u(1, 1).u(3, 5).u(1, 0).u(1, 1).u(8, 9).u(8, 16).u(8, 9)
+            .u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0)
+            .finish();
+
+        let mut writer = Writer::new(0x06);
+        // mastering_display_colour_volume: P3-D65, 1000 / 0.0001 cd/m2
+        writer.u(8, 137).u(8, 24);
+        for &value in [13250, 34500, 7500, 3000, 34000, 16000, 15635, 16450].iter() {
+            writer.u(16, value);
+        }
+        writer.u(32, 10_000_000).u(32, 1);
+        // content_light_level_info: MaxCLL 1000, MaxFALL 400
+        writer.u(8, 144).u(8, 4).u(16, 1000).u(16, 400);
+        // ambient_viewing_environment: 314 lux, D65
+        writer.u(8, 148).u(8, 8).u(32, 3_140_000).u(16, 15635).u(16, 16450);
+        let nalu = writer.finish();
+
+        let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+        let kinds: Vec<SeiMessageKind> = sei.messages().iter().map(|message| message.kind).collect();
+        assert_eq!(kinds, vec![
+            SeiMessageKind::MasteringDisplayColourVolume, SeiMessageKind::ContentLightLevelInfo,
+            SeiMessageKind::AmbientViewingEnvironment,
+        ]);
+        match sei.messages()[0].payload {
+            SeiPayload::MasteringDisplayColourVolume(ref payload) => {
+                assert_eq!(payload.max_luminance(), 1000.0);
+                assert_eq!(payload.min_display_mastering_luminance, 1);
+                assert_eq!(payload.primary(2), (0.68, 0.32));
+                assert_eq!(payload.white_point_y, 16450);
+            },
+            ref payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        let mut hdr = HdrMetadata::new();
+        hdr.push(&sps).unwrap();
+        hdr.push(&nalu).unwrap();
+        assert_eq!(hdr.bit_depth_luma, Some(10));
+        assert_eq!(hdr.content_light_level_info, Some(ContentLightLevelInfo {
+            max_content_light_level: 1000,
+            max_pic_average_light_level: 400,
+        }));
+        assert!(hdr.is_pq() && !hdr.is_hlg());
+        assert_eq!(hdr.ambient_viewing_environment.unwrap().ambient_illuminance, 3_140_000);
+        assert!(hdr.hdr10_violations().is_empty());
+
+        // 再次出现的相同 SEI 不算改变
+        hdr.push(&nalu).unwrap();
+        assert!(hdr.changed.is_empty());
+
+        // alternative_transfer_characteristics 为 HLG: 不再是 HDR10
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 147).u(8, 1).u(8, 18);
+        hdr.push(&writer.finish()).unwrap();
+        assert_eq!(hdr.transfer_characteristics, Some(16));
+        assert_eq!(hdr.preferred_transfer_characteristics(), Some(18));
+        assert!(hdr.is_hlg() && !hdr.is_pq());
+        assert_eq!(hdr.hdr10_violations(), vec!["preferred transfer characteristics is not SMPTE ST 2084"]);
+
+        // ambient_illuminance 为 0 时保留这个 SEI 并报告
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 148).u(8, 8).u(32, 0).u(16, 15635).u(16, 16450);
+        hdr.push(&writer.finish()).unwrap();
+        assert_eq!(hdr.ambient_viewing_environment.unwrap().ambient_illuminance, 0);
+        assert!(hdr.hdr10_violations().contains(&"ambient_illuminance is 0"));
+    }
+}
@@ -0,0 +1,348 @@
+// 8.4.2 Decoding process for inter prediction samples ( Page 162 )
+
+use super::picture::Plane;
+use super::intra::clip;
+
+
+// 参考图像的一个颜色分量: 帧, 或帧中奇偶为 `parity` 的场 ( 8.4.2.1 中参考场的样本位于帧的隔行 )
+#[derive(Debug, Clone, Copy)]
+pub struct RefPlane<'a> {
+    pub plane: &'a Plane,
+    pub parity: Option<usize>,
+}
+
+impl<'a> From<&'a Plane> for RefPlane<'a> {
+    fn from(plane: &'a Plane) -> Self {
+        RefPlane { plane: plane, parity: None }
+    }
+}
+
+// 参考图像中的整数样本位置, 超出图像 ( 或场 ) 边界时取最近的边界样本 ( 8-228, 8-229 )
+fn sample(plane: &RefPlane, x: i32, y: i32) -> i32 {
+    let x = x.max(0).min(plane.plane.width as i32 - 1);
+    match plane.parity {
+        None => {
+            let y = y.max(0).min(plane.plane.height as i32 - 1);
+            plane.plane.get(x as usize, y as usize) as i32
+        },
+        Some(parity) => {
+            let y = y.max(0).min(plane.plane.height as i32 / 2 - 1);
+            plane.plane.get(x as usize, (2 * y) as usize + parity) as i32
+        },
+    }
+}
+
+// 6 抽头滤波器 ( 1, -5, 20, 20, -5, 1 )
+fn tap(e: i32, f: i32, g: i32, h: i32, i: i32, j: i32) -> i32 {
+    e - 5 * f + 20 * g + 20 * h - 5 * i + j
+}
+
+// b1: 位于 ( x, y ) 与 ( x + 1, y ) 之间
+fn horizontal(plane: &RefPlane, x: i32, y: i32) -> i32 {
+    tap(sample(plane, x - 2, y), sample(plane, x - 1, y), sample(plane, x, y),
+        sample(plane, x + 1, y), sample(plane, x + 2, y), sample(plane, x + 3, y))
+}
+
+// h1: 位于 ( x, y ) 与 ( x, y + 1 ) 之间
+fn vertical(plane: &RefPlane, x: i32, y: i32) -> i32 {
+    tap(sample(plane, x, y - 2), sample(plane, x, y - 1), sample(plane, x, y),
+        sample(plane, x, y + 1), sample(plane, x, y + 2), sample(plane, x, y + 3))
+}
+
+// 8.4.2.2.1 Luma sample interpolation process ( Page 166 )
+//
+// 整数位置 ( xInt, yInt ) 加 1/4 样本偏移 ( xFrac, yFrac ) 处的预测值
+pub fn luma_sample<'a>(plane: impl Into<RefPlane<'a>>, x_int: i32, y_int: i32, x_frac: i32, y_frac: i32, bit_depth: u32) -> i32 {
+    let plane = &plane.into();
+    let max = (1 << bit_depth) - 1;
+
+    let g = || sample(plane, x_int, y_int);
+    let b = |y: i32| clip(max, (horizontal(plane, x_int, y) + 16) >> 5);
+    let h = |x: i32| clip(max, (vertical(plane, x, y_int) + 16) >> 5);
+    let j = || {
+        let j1 = tap(horizontal(plane, x_int, y_int - 2), horizontal(plane, x_int, y_int - 1),
+                     horizontal(plane, x_int, y_int), horizontal(plane, x_int, y_int + 1),
+                     horizontal(plane, x_int, y_int + 2), horizontal(plane, x_int, y_int + 3));
+        clip(max, (j1 + 512) >> 10)
+    };
+    let avg = |a: i32, b: i32| (a + b + 1) >> 1;
+
+    // Table 8-12 – Assignment of the luma prediction sample predPartLXL[ xL, yL ]
+    // s = b( y + 1 ), m = h( x + 1 )
+    match (x_frac, y_frac) {
+        (0, 0) => g(),
+        (0, 1) => avg(g(), h(x_int)),
+        (0, 2) => h(x_int),
+        (0, 3) => avg(sample(plane, x_int, y_int + 1), h(x_int)),
+        (1, 0) => avg(g(), b(y_int)),
+        (1, 1) => avg(b(y_int), h(x_int)),
+        (1, 2) => avg(h(x_int), j()),
+        (1, 3) => avg(h(x_int), b(y_int + 1)),
+        (2, 0) => b(y_int),
+        (2, 1) => avg(b(y_int), j()),
+        (2, 2) => j(),
+        (2, 3) => avg(j(), b(y_int + 1)),
+        (3, 0) => avg(sample(plane, x_int + 1, y_int), b(y_int)),
+        (3, 1) => avg(b(y_int), h(x_int + 1)),
+        (3, 2) => avg(j(), h(x_int + 1)),
+        _ => avg(h(x_int + 1), b(y_int + 1)),
+    }
+}
+
+// 亮度块的预测样本; ( x, y ) 为块在图像 ( 参考场时为场 ) 中的位置, `mv` 以 1/4 样本为单位
+#[allow(clippy::too_many_arguments)]
+pub fn predict_luma<'a>(plane: impl Into<RefPlane<'a>>,
+                        x: i32,
+                        y: i32,
+                        mv: [i32; 2],
+                        width: usize,
+                        height: usize,
+                        bit_depth: u32,
+                        pred: &mut [i32]) {
+    let plane = plane.into();
+    let (x_int, y_int) = (x + (mv[0] >> 2), y + (mv[1] >> 2));
+    let (x_frac, y_frac) = (mv[0] & 3, mv[1] & 3);
+
+    for j in 0..height {
+        for i in 0..width {
+            pred[j * width + i] = luma_sample(plane, x_int + i as i32, y_int + j as i32, x_frac, y_frac, bit_depth);
+        }
+    }
+}
+
+// 8.4.2.2.2 Chroma sample interpolation process ( Page 169 )
+//
+// ( x, y ) 为块在色度平面 ( 参考场时为场 ) 中的位置, ( xFracC, yFracC ) 以 1/8 样本为单位
+#[allow(clippy::too_many_arguments)]
+pub fn predict_chroma<'a>(plane: impl Into<RefPlane<'a>>,
+                          x_int: i32,
+                          y_int: i32,
+                          x_frac: i32,
+                          y_frac: i32,
+                          width: usize,
+                          height: usize,
+                          pred: &mut [i32]) {
+    let plane = &plane.into();
+    for j in 0..height {
+        for i in 0..width {
+            let (x, y) = (x_int + i as i32, y_int + j as i32);
+            let a = sample(plane, x, y);
+            let b = sample(plane, x + 1, y);
+            let c = sample(plane, x, y + 1);
+            let d = sample(plane, x + 1, y + 1);
+
+            pred[j * width + i] = ((8 - x_frac) * (8 - y_frac) * a + x_frac * (8 - y_frac) * b
+                + (8 - x_frac) * y_frac * c + x_frac * y_frac * d + 32) >> 6;
+        }
+    }
+}
+
+
+// 8.4.2.3 Weighted sample prediction process 中的 logWD, w0, w1, o0, o1
+// ( 偏移已乘以 1 << ( BitDepth - 8 ) )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weight {
+    pub log_wd: i32,
+    pub w: [i32; 2],
+    pub o: [i32; 2],
+}
+
+// 8.4.2.3.1 Default weighted sample prediction process
+// 8.4.2.3.2 Weighted sample prediction process
+//
+// `pred` 为 predPartL0 与 predPartL1 ( 未使用的列表为 None ), 结果写入 `out`
+pub fn weighted_prediction(pred: [Option<&[i32]>; 2], weight: Option<&Weight>, bit_depth: u32, out: &mut [i32]) {
+    let max = (1 << bit_depth) - 1;
+
+    match (pred, weight) {
+        ([Some(p0), Some(p1)], None) => {
+            for (k, value) in out.iter_mut().enumerate() {
+                *value = (p0[k] + p1[k] + 1) >> 1;
+            }
+        },
+        ([Some(p0), Some(p1)], Some(weight)) => {
+            let log_wd = weight.log_wd;
+            let [w0, w1] = weight.w;
+            let offset = (weight.o[0] + weight.o[1] + 1) >> 1;
+            for (k, value) in out.iter_mut().enumerate() {
+                *value = clip(max, ((p0[k] * w0 + p1[k] * w1 + (1 << log_wd)) >> (log_wd + 1)) + offset);
+            }
+        },
+        ([Some(p), None], weight) | ([None, Some(p)], weight) => {
+            let list = pred[0].is_none() as usize;
+            match weight {
+                None => out.copy_from_slice(&p[..out.len()]),
+                Some(weight) => {
+                    let (log_wd, w, o) = (weight.log_wd, weight.w[list], weight.o[list]);
+                    for (k, value) in out.iter_mut().enumerate() {
+                        *value = if log_wd >= 1 {
+                            clip(max, ((p[k] * w + (1 << (log_wd - 1))) >> log_wd) + o)
+                        } else {
+                            clip(max, p[k] * w + o)
+                        };
+                    }
+                },
+            }
+        },
+        ([None, None], _) => { },
+    }
+}
+
+// 8.4.2.3.1 ( 8-274 ~ 8-277 ): 隐式加权预测 ( weighted_bipred_idc 为 2 ) 的 w0, w1
+//
+// `poc` 为 currPicOrField, pic0, pic1 的 PicOrderCnt, `long_term` 表示 pic0 或 pic1 是否为长期参考图像
+pub fn implicit_weight(poc: i32, poc0: i32, poc1: i32, long_term: bool) -> Weight {
+    let default = Weight { log_wd: 5, w: [32, 32], o: [0, 0] };
+
+    if poc1 - poc0 == 0 || long_term {
+        return default;
+    }
+
+    let dist_scale_factor = dist_scale_factor(poc, poc0, poc1);
+    if (dist_scale_factor >> 2) < -64 || (dist_scale_factor >> 2) > 128 {
+        return default;
+    }
+
+    Weight {
+        log_wd: 5,
+        w: [64 - (dist_scale_factor >> 2), dist_scale_factor >> 2],
+        o: [0, 0],
+    }
+}
+
+// 8.4.1.2.3 ( 8-195 ~ 8-198 ): DistScaleFactor
+pub fn dist_scale_factor(poc: i32, poc0: i32, poc1: i32) -> i32 {
+    let tb = (poc - poc0).clamp(-128, 127);
+    let td = (poc1 - poc0).clamp(-128, 127);
+    let tx = (16384 + (td / 2).abs()) / td;
+    ((tb * tx + 32) >> 6).clamp(-1024, 1023)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_luma_interpolation() {
+        // 水平方向的斜坡: G( x, y ) = 10 * x
+        let mut plane = Plane::new(16, 16, 0);
+        for y in 0..16 {
+            for x in 0..16 {
+                plane.set(x, y, 10 * x as u16);
+            }
+        }
+
+        assert_eq!(luma_sample(&plane, 4, 4, 0, 0, 8), 40);
+        // 半样本: ( 20 - 150 + 800 + 1000 - 300 + 70 + 16 ) >> 5 = 45
+        assert_eq!(luma_sample(&plane, 4, 4, 2, 0, 8), 45);
+        assert_eq!(luma_sample(&plane, 4, 4, 1, 0, 8), 43);
+        assert_eq!(luma_sample(&plane, 4, 4, 3, 0, 8), 48);
+        // 垂直方向没有变化
+        assert_eq!(luma_sample(&plane, 4, 4, 0, 2, 8), 40);
+        assert_eq!(luma_sample(&plane, 4, 4, 2, 2, 8), 45);
+        assert_eq!(luma_sample(&plane, 4, 4, 2, 1, 8), 45);
+        assert_eq!(luma_sample(&plane, 4, 4, 1, 1, 8), 43);
+        // 左侧边界外的样本取 G( 0, y )
+        assert_eq!(luma_sample(&plane, -3, 0, 0, 0, 8), 0);
+        assert_eq!(luma_sample(&plane, 20, 20, 0, 0, 8), 150);
+
+        let mut pred = [0i32; 8];
+        predict_luma(&plane, 2, 2, [6, -4], 4, 2, 8, &mut pred);
+        assert_eq!(&pred[..4], &[35, 45, 55, 65]);
+    }
+
+    #[test]
+    fn test_chroma_interpolation() {
+        let mut plane = Plane::new(8, 8, 0);
+        plane.set(1, 0, 64);
+
+        let mut pred = [0i32; 1];
+        predict_chroma(&plane, 0, 0, 4, 0, 1, 1, &mut pred);
+        assert_eq!(pred[0], 32);
+        predict_chroma(&plane, 0, 0, 4, 4, 1, 1, &mut pred);
+        assert_eq!(pred[0], 16);
+    }
+
+    #[test]
+    fn test_weighted_prediction() {
+        let p0 = [100, 200];
+        let p1 = [50, 61];
+        let mut out = [0i32; 2];
+
+        weighted_prediction([Some(&p0[..]), Some(&p1[..])], None, 8, &mut out);
+        assert_eq!(out, [75, 131]);
+
+        // 显式: logWD = 5, w0 = 64 ( 亮度翻倍 ), o0 = 10
+        let weight = Weight { log_wd: 5, w: [64, 32], o: [10, 0] };
+        weighted_prediction([Some(&p0[..]), None], Some(&weight), 8, &mut out);
+        assert_eq!(out, [210, 255]);
+
+        let weight = implicit_weight(2, 0, 8, false);
+        assert_eq!(weight.w, [48, 16]);
+        assert_eq!(implicit_weight(2, 0, 8, true).w, [32, 32]);
+        weighted_prediction([Some(&p0[..]), Some(&p1[..])], Some(&weight), 8, &mut out);
+        assert_eq!(out, [88, 165]);
+    }
+
+    #[test]
+    fn test_dist_scale_factor() {
+        // tb = 2, td = 8: tx = 16388 / 8 = 2048, ( 2 * 2048 + 32 ) >> 6 = 64
+        assert_eq!(dist_scale_factor(2, 0, 8), 64);
+        // td 为负数时 tx 向 0 取整: tx = 16388 / -8 = -2048
+        assert_eq!(dist_scale_factor(4, 8, 0), 128);
+        // tb 被限制为 127 与 -128: ( 127 * 8192 + 32 ) >> 6 = 16257, ( -128 * 8192 + 32 ) >> 6 = -16384
+        assert_eq!(dist_scale_factor(300, 0, 2), 1023);
+        assert_eq!(dist_scale_factor(-300, 0, 2), -1024);
+        // td 被限制为 127: tx = 16447 / 127 = 129, ( 129 + 32 ) >> 6 = 2 ( 不限制时为 0 )
+        assert_eq!(dist_scale_factor(1, 0, 1000), 2);
+    }
+
+    #[test]
+    fn test_bipred_average() {
+        // ( predPartL0 + predPartL1 + 1 ) >> 1
+        let p0 = [0, 255, 3, 100, 17];
+        let p1 = [1, 254, 4, 100, 20];
+        let mut out = [0i32; 5];
+        weighted_prediction([Some(&p0[..]), Some(&p1[..])], None, 8, &mut out);
+        assert_eq!(out, [1, 255, 4, 100, 19]);
+
+        // 只使用 L1
+        weighted_prediction([None, Some(&p1[..])], None, 8, &mut out);
+        assert_eq!(out, p1);
+
+        // 显式加权的双向预测: ( ( 100 * 16 + 50 * 48 + 32 ) >> 6 ) + ( ( o0 + o1 + 1 ) >> 1 ) = 63 + 3
+        let weight = Weight { log_wd: 5, w: [16, 48], o: [2, 3] };
+        let mut out = [0i32; 1];
+        weighted_prediction([Some(&[100][..]), Some(&[50][..])], Some(&weight), 8, &mut out);
+        assert_eq!(out, [66]);
+        // logWD = 0 的单向预测: predPartL1 * w1 + o1
+        let weight = Weight { log_wd: 0, w: [1, 2], o: [0, -5] };
+        weighted_prediction([None, Some(&[100][..])], Some(&weight), 8, &mut out);
+        assert_eq!(out, [195]);
+    }
+
+    #[test]
+    fn test_implicit_weight() {
+        let default = Weight { log_wd: 5, w: [32, 32], o: [0, 0] };
+
+        // 与 pic0, pic1 距离相同: DistScaleFactor = 128
+        assert_eq!(implicit_weight(4, 0, 8, false), default);
+        assert_eq!(implicit_weight(6, 0, 8, false).w, [16, 48]);
+        // DistScaleFactor = ( -2 * 2048 + 32 ) >> 6 = -64, w1 = -16, w0 = 80
+        let weight = implicit_weight(-2, 0, 8, false);
+        assert_eq!(weight.w, [80, -16]);
+        // DiffPicOrderCnt( pic1, pic0 ) 为 0, 长期参考图像, 以及 DistScaleFactor >> 2 超出 -64 .. 128
+        assert_eq!(implicit_weight(4, 8, 8, false), default);
+        assert_eq!(implicit_weight(6, 0, 8, true), default);
+        assert_eq!(implicit_weight(40, 0, 8, false), default);
+        assert_eq!(implicit_weight(-30, 0, 8, false), default);
+
+        // ( 100 * 80 - 50 * 16 + 32 ) >> 6 = 113, ( 10 * 80 - 200 * 16 + 32 ) >> 6 = -37 被限制为 0
+        let p0 = [100, 10];
+        let p1 = [50, 200];
+        let mut out = [0i32; 2];
+        weighted_prediction([Some(&p0[..]), Some(&p1[..])], Some(&weight), 8, &mut out);
+        assert_eq!(out, [113, 0]);
+    }
+}
@@ -0,0 +1,439 @@
+// 8.3 Intra prediction process ( Page 147 )
+
+use crate::error::{ self, Error };
+
+
+// 预测块的参考样本: p[ -1, -1 ], p[ x, -1 ] ( x = 0 .. 2N - 1 ), p[ -1, y ] ( y = 0 .. N - 1 )
+//
+// 不可用 ( "not available for Intra prediction" ) 的样本由对应的 has_* 标记, 样本值无意义。
+#[derive(Debug, Clone, Default)]
+pub struct RefSamples {
+    pub top_left: i32,
+    pub top: [i32; 32],
+    pub left: [i32; 16],
+    pub has_top_left: bool,
+    pub has_top: bool,
+    pub has_top_right: bool,
+    pub has_left: bool,
+}
+
+impl RefSamples {
+    // p[ x, y ], 其中 x == -1 或 y == -1
+    fn p(&self, x: i32, y: i32) -> i32 {
+        if y < 0 {
+            if x < 0 { self.top_left } else { self.top[x as usize] }
+        } else {
+            self.left[y as usize]
+        }
+    }
+
+    // 8.3.1.2 / 8.3.2.2: p[ N .. 2N - 1, -1 ] 不可用而 p[ N - 1, -1 ] 可用时, 用 p[ N - 1, -1 ] 替代
+    fn substitute_top_right(&mut self, n: usize) {
+        if self.has_top && !self.has_top_right {
+            let value = self.top[n - 1];
+            for x in n..2 * n {
+                self.top[x] = value;
+            }
+            self.has_top_right = true;
+        }
+    }
+
+    fn sum_top(&self, n: usize) -> i32 {
+        self.top[..n].iter().sum()
+    }
+
+    fn sum_left(&self, n: usize) -> i32 {
+        self.left[..n].iter().sum()
+    }
+
+    // 8.3.2.2.1 Reference sample filtering process for Intra_8x8 sample prediction ( Page 159 )
+    fn filter8x8(&self) -> RefSamples {
+        let mut filtered = self.clone();
+
+        if self.has_top {
+            filtered.top[0] = if self.has_top_left {
+                (self.top_left + 2 * self.top[0] + self.top[1] + 2) >> 2
+            } else {
+                (3 * self.top[0] + self.top[1] + 2) >> 2
+            };
+            for x in 1..15 {
+                filtered.top[x] = (self.top[x - 1] + 2 * self.top[x] + self.top[x + 1] + 2) >> 2;
+            }
+            filtered.top[15] = (self.top[14] + 3 * self.top[15] + 2) >> 2;
+        }
+
+        if self.has_top_left {
+            filtered.top_left = match (self.has_top, self.has_left) {
+                (true, true) => (self.top[0] + 2 * self.top_left + self.left[0] + 2) >> 2,
+                (true, false) => (3 * self.top_left + self.top[0] + 2) >> 2,
+                (false, true) => (3 * self.top_left + self.left[0] + 2) >> 2,
+                (false, false) => self.top_left,
+            };
+        }
+
+        if self.has_left {
+            filtered.left[0] = if self.has_top_left {
+                (self.top_left + 2 * self.left[0] + self.left[1] + 2) >> 2
+            } else {
+                (3 * self.left[0] + self.left[1] + 2) >> 2
+            };
+            for y in 1..7 {
+                filtered.left[y] = (self.left[y - 1] + 2 * self.left[y] + self.left[y + 1] + 2) >> 2;
+            }
+            filtered.left[7] = (self.left[6] + 3 * self.left[7] + 2) >> 2;
+        }
+
+        filtered
+    }
+}
+
+
+fn unavailable() -> Error {
+    error::malformed("intra prediction mode references unavailable samples")
+}
+
+// 8.3.1.2 Intra_4x4 sample prediction ( Page 151 )
+// 8.3.2.2 Intra_8x8 sample prediction ( Page 157 )
+//
+// `n` 为 4 或 8, 输出为光栅顺序的 pred[ n * n ]
+pub fn predict_nxn(n: usize, mode: u8, refs: &RefSamples, bit_depth: u32, pred: &mut [i32]) -> Result<(), Error> {
+    let mut refs = refs.clone();
+    refs.substitute_top_right(n);
+    if n == 8 {
+        refs = refs.filter8x8();
+    }
+
+    let ni = n as i32;
+    let p = |x: i32, y: i32| refs.p(x, y);
+
+    let needs_top = mode == 0 || (mode >= 3 && mode != 8);
+    let needs_left = mode == 1 || mode == 4 || mode == 5 || mode == 6 || mode == 8;
+    let needs_top_left = mode == 4 || mode == 5 || mode == 6;
+    if (needs_top && !refs.has_top) || (needs_left && !refs.has_left) || (needs_top_left && !refs.has_top_left) {
+        return Err(unavailable());
+    }
+
+    for y in 0..ni {
+        for x in 0..ni {
+            let value = match mode {
+                // Vertical
+                0 => p(x, -1),
+                // Horizontal
+                1 => p(-1, y),
+                // DC
+                2 => match (refs.has_top, refs.has_left) {
+                    (true, true) => (refs.sum_top(n) + refs.sum_left(n) + ni) >> (if n == 4 { 3 } else { 4 }),
+                    (false, true) => (refs.sum_left(n) + ni / 2) >> (if n == 4 { 2 } else { 3 }),
+                    (true, false) => (refs.sum_top(n) + ni / 2) >> (if n == 4 { 2 } else { 3 }),
+                    (false, false) => 1 << (bit_depth - 1),
+                },
+                // Diagonal_Down_Left
+                3 => {
+                    if x == ni - 1 && y == ni - 1 {
+                        (p(2 * ni - 2, -1) + 3 * p(2 * ni - 1, -1) + 2) >> 2
+                    } else {
+                        (p(x + y, -1) + 2 * p(x + y + 1, -1) + p(x + y + 2, -1) + 2) >> 2
+                    }
+                },
+                // Diagonal_Down_Right
+                4 => {
+                    if x > y {
+                        (p(x - y - 2, -1) + 2 * p(x - y - 1, -1) + p(x - y, -1) + 2) >> 2
+                    } else if x < y {
+                        (p(-1, y - x - 2) + 2 * p(-1, y - x - 1) + p(-1, y - x) + 2) >> 2
+                    } else {
+                        (p(0, -1) + 2 * p(-1, -1) + p(-1, 0) + 2) >> 2
+                    }
+                },
+                // Vertical_Right
+                5 => {
+                    let z = 2 * x - y;
+                    if z >= 0 && z % 2 == 0 {
+                        (p(x - (y >> 1) - 1, -1) + p(x - (y >> 1), -1) + 1) >> 1
+                    } else if z >= 0 {
+                        (p(x - (y >> 1) - 2, -1) + 2 * p(x - (y >> 1) - 1, -1) + p(x - (y >> 1), -1) + 2) >> 2
+                    } else if z == -1 {
+                        (p(-1, 0) + 2 * p(-1, -1) + p(0, -1) + 2) >> 2
+                    } else {
+                        (p(-1, y - 2 * x - 1) + 2 * p(-1, y - 2 * x - 2) + p(-1, y - 2 * x - 3) + 2) >> 2
+                    }
+                },
+                // Horizontal_Down
+                6 => {
+                    let z = 2 * y - x;
+                    if z >= 0 && z % 2 == 0 {
+                        (p(-1, y - (x >> 1) - 1) + p(-1, y - (x >> 1)) + 1) >> 1
+                    } else if z >= 0 {
+                        (p(-1, y - (x >> 1) - 2) + 2 * p(-1, y - (x >> 1) - 1) + p(-1, y - (x >> 1)) + 2) >> 2
+                    } else if z == -1 {
+                        (p(-1, 0) + 2 * p(-1, -1) + p(0, -1) + 2) >> 2
+                    } else {
+                        (p(x - 2 * y - 1, -1) + 2 * p(x - 2 * y - 2, -1) + p(x - 2 * y - 3, -1) + 2) >> 2
+                    }
+                },
+                // Vertical_Left
+                7 => {
+                    if y % 2 == 0 {
+                        (p(x + (y >> 1), -1) + p(x + (y >> 1) + 1, -1) + 1) >> 1
+                    } else {
+                        (p(x + (y >> 1), -1) + 2 * p(x + (y >> 1) + 1, -1) + p(x + (y >> 1) + 2, -1) + 2) >> 2
+                    }
+                },
+                // Horizontal_Up
+                8 => {
+                    let z = x + 2 * y;
+                    let last = 2 * ni - 3;
+                    if z < last && z % 2 == 0 {
+                        (p(-1, y + (x >> 1)) + p(-1, y + (x >> 1) + 1) + 1) >> 1
+                    } else if z < last {
+                        (p(-1, y + (x >> 1)) + 2 * p(-1, y + (x >> 1) + 1) + p(-1, y + (x >> 1) + 2) + 2) >> 2
+                    } else if z == last {
+                        (p(-1, ni - 2) + 3 * p(-1, ni - 1) + 2) >> 2
+                    } else {
+                        p(-1, ni - 1)
+                    }
+                },
+                _ => return Err(error::malformed("intra prediction mode out of range")),
+            };
+
+            pred[(y * ni + x) as usize] = value;
+        }
+    }
+
+    Ok(())
+}
+
+// 8.3.3 Intra_16x16 prediction process for luma samples ( Page 163 )
+pub fn predict16x16(mode: u8, refs: &RefSamples, bit_depth: u32, pred: &mut [i32; 256]) -> Result<(), Error> {
+    match mode {
+        0 if !refs.has_top => return Err(unavailable()),
+        1 if !refs.has_left => return Err(unavailable()),
+        3 if !(refs.has_top && refs.has_left && refs.has_top_left) => return Err(unavailable()),
+        0 ..= 3 => { },
+        _ => return Err(error::malformed("Intra16x16PredMode out of range")),
+    }
+
+    let dc = match (refs.has_top, refs.has_left) {
+        (true, true) => (refs.sum_top(16) + refs.sum_left(16) + 16) >> 5,
+        (false, true) => (refs.sum_left(16) + 8) >> 4,
+        (true, false) => (refs.sum_top(16) + 8) >> 4,
+        (false, false) => 1 << (bit_depth - 1),
+    };
+
+    let (mut a, mut b, mut c) = (0, 0, 0);
+    if mode == 3 {
+        let mut h = 0;
+        let mut v = 0;
+        for i in 0..8 {
+            h += (i + 1) * (refs.p(8 + i, -1) - refs.p(6 - i, -1));
+            v += (i + 1) * (refs.p(-1, 8 + i) - refs.p(-1, 6 - i));
+        }
+        a = 16 * (refs.p(-1, 15) + refs.p(15, -1));
+        b = (5 * h + 32) >> 6;
+        c = (5 * v + 32) >> 6;
+    }
+
+    let max = (1 << bit_depth) - 1;
+    for y in 0..16 {
+        for x in 0..16 {
+            pred[(y * 16 + x) as usize] = match mode {
+                0 => refs.p(x, -1),
+                1 => refs.p(-1, y),
+                2 => dc,
+                _ => clip(max, (a + b * (x - 7) + c * (y - 7) + 16) >> 5),
+            };
+        }
+    }
+
+    Ok(())
+}
+
+// 8.3.4 Intra prediction process for chroma samples ( Page 165 )
+//
+// 仅用于 ChromaArrayType 为 1 或 2; `width`, `height` 为 MbWidthC, MbHeightC
+pub fn predict_chroma(mode: u8,
+                      refs: &RefSamples,
+                      width: usize,
+                      height: usize,
+                      bit_depth: u32,
+                      pred: &mut [i32]) -> Result<(), Error> {
+    match mode {
+        1 if !refs.has_left => return Err(unavailable()),
+        2 if !refs.has_top => return Err(unavailable()),
+        3 if !(refs.has_top && refs.has_left && refs.has_top_left) => return Err(unavailable()),
+        0 ..= 3 => { },
+        _ => return Err(error::malformed("intra_chroma_pred_mode out of range")),
+    }
+
+    let (w, h) = (width as i32, height as i32);
+    let max = (1 << bit_depth) - 1;
+
+    match mode {
+        // DC: 8.3.4.1 - 8.3.4.3, 每个 4x4 色度块单独计算
+        0 => {
+            for y4 in (0..h).step_by(4) {
+                for x4 in (0..w).step_by(4) {
+                    let top: i32 = (0..4).map(|x| refs.p(x4 + x, -1)).sum();
+                    let left: i32 = (0..4).map(|y| refs.p(-1, y4 + y)).sum();
+
+                    let prefer_top = x4 > 0 && y4 == 0;
+                    let prefer_left = x4 == 0 && y4 > 0;
+                    let value = if !prefer_top && !prefer_left && refs.has_top && refs.has_left {
+                        (top + left + 4) >> 3
+                    } else if !prefer_top && refs.has_left {
+                        (left + 2) >> 2
+                    } else if refs.has_top {
+                        (top + 2) >> 2
+                    } else if refs.has_left {
+                        (left + 2) >> 2
+                    } else {
+                        1 << (bit_depth - 1)
+                    };
+
+                    for y in y4..y4 + 4 {
+                        for x in x4..x4 + 4 {
+                            pred[(y * w + x) as usize] = value;
+                        }
+                    }
+                }
+            }
+        },
+        // Horizontal
+        1 => {
+            for y in 0..h {
+                for x in 0..w {
+                    pred[(y * w + x) as usize] = refs.p(-1, y);
+                }
+            }
+        },
+        // Vertical
+        2 => {
+            for y in 0..h {
+                for x in 0..w {
+                    pred[(y * w + x) as usize] = refs.p(x, -1);
+                }
+            }
+        },
+        // Plane
+        _ => {
+            let x_cf = if w == 16 { 4 } else { 0 };
+            let y_cf = if h == 16 { 4 } else { 0 };
+
+            let mut hh = 0;
+            for i in 0..4 + x_cf {
+                hh += (i + 1) * (refs.p(4 + x_cf + i, -1) - refs.p(2 + x_cf - i, -1));
+            }
+            let mut vv = 0;
+            for i in 0..4 + y_cf {
+                vv += (i + 1) * (refs.p(-1, 4 + y_cf + i) - refs.p(-1, 2 + y_cf - i));
+            }
+
+            let a = 16 * (refs.p(-1, h - 1) + refs.p(w - 1, -1));
+            let b = ((34 - 29 * (w == 16) as i32) * hh + 32) >> 6;
+            let c = ((34 - 29 * (h == 16) as i32) * vv + 32) >> 6;
+
+            for y in 0..h {
+                for x in 0..w {
+                    pred[(y * w + x) as usize] = clip(max, (a + b * (x - 3 - x_cf) + c * (y - 3 - y_cf) + 16) >> 5);
+                }
+            }
+        },
+    }
+
+    Ok(())
+}
+
+// Clip3( 0, max, value )
+pub fn clip(max: i32, value: i32) -> i32 {
+    if value < 0 {
+        0
+    } else if value > max {
+        max
+    } else {
+        value
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn refs(top: &[i32], left: &[i32], top_left: Option<i32>) -> RefSamples {
+        let mut refs = RefSamples::default();
+        refs.top[..top.len()].copy_from_slice(top);
+        refs.left[..left.len()].copy_from_slice(left);
+        refs.top_left = top_left.unwrap_or(0);
+        refs.has_top = !top.is_empty();
+        refs.has_top_right = false;
+        refs.has_left = !left.is_empty();
+        refs.has_top_left = top_left.is_some();
+        refs
+    }
+
+    #[test]
+    fn test_predict4x4() {
+        let mut pred = [0i32; 16];
+
+        // 没有任何参考样本时 DC 为 1 << ( BitDepth - 1 )
+        predict_nxn(4, 2, &RefSamples::default(), 8, &mut pred).unwrap();
+        assert!(pred.iter().all(|&value| value == 128));
+        assert!(predict_nxn(4, 0, &RefSamples::default(), 8, &mut pred).is_err());
+
+        let refs = refs(&[10, 20, 30, 40], &[50, 60, 70, 80], Some(0));
+        predict_nxn(4, 0, &refs, 8, &mut pred).unwrap();
+        assert_eq!(pred[..4], [10, 20, 30, 40]);
+        assert_eq!(pred[12..], [10, 20, 30, 40]);
+
+        // Diagonal_Down_Left: 右上样本不可用时用 p[ 3, -1 ] 替代
+        predict_nxn(4, 3, &refs, 8, &mut pred).unwrap();
+        assert_eq!(pred[0], (10 + 40 + 30 + 2) >> 2);
+        assert_eq!(pred[15], 40);
+
+        // Horizontal_Up
+        predict_nxn(4, 8, &refs, 8, &mut pred).unwrap();
+        assert_eq!(pred[0], (50 + 60 + 1) >> 1);
+        assert_eq!(pred[4 * 2 + 1], (70 + 3 * 80 + 2) >> 2);
+        assert_eq!(pred[4 * 2 + 3], 80);
+        assert_eq!(pred[15], 80);
+    }
+
+    #[test]
+    fn test_predict8x8() {
+        let mut pred = [0i32; 64];
+        let top: Vec<i32> = (0..8).map(|x| 100 + x).collect();
+
+        // 滤波后的 p'[ 0, -1 ] = ( 3 * 100 + 101 + 2 ) >> 2
+        predict_nxn(8, 0, &refs(&top, &[], None), 8, &mut pred).unwrap();
+        assert_eq!(pred[0], 100);
+        assert_eq!(pred[7], (106 + 2 * 107 + 107 + 2) >> 2);
+        assert_eq!(pred[63], pred[7]);
+    }
+
+    #[test]
+    fn test_predict16x16() {
+        let mut pred = [0i32; 256];
+        let refs = refs(&[90; 16], &[110; 16], Some(100));
+
+        predict16x16(2, &refs, 8, &mut pred).unwrap();
+        assert!(pred.iter().all(|&value| value == 100));
+
+        // H = 8 * ( 90 - 100 ), V = 8 * ( 110 - 100 )
+        predict16x16(3, &refs, 8, &mut pred).unwrap();
+        let (a, b, c) = (16 * 200, (5 * -80 + 32) >> 6, (5 * 80 + 32) >> 6);
+        assert_eq!(pred[0], (a + b * -7 + c * -7 + 16) >> 5);
+        assert_eq!(pred[255], (a + b * 8 + c * 8 + 16) >> 5);
+    }
+
+    #[test]
+    fn test_predict_chroma() {
+        let mut pred = [0i32; 64];
+        let refs = refs(&[40, 40, 40, 40, 80, 80, 80, 80], &[], None);
+
+        // 只有上方样本: 每个 4x4 块使用正上方的样本
+        predict_chroma(0, &refs, 8, 8, 8, &mut pred).unwrap();
+        assert_eq!(pred[0], 40);
+        assert_eq!(pred[7], 80);
+        assert_eq!(pred[63], 80);
+    }
+}
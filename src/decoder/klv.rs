@@ -0,0 +1,154 @@
+// 按访问单元 ( 解码顺序 ) 收集 user_data_unregistered SEI 中的 MISB KLV 元数据。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ SupplementalEnhancementInformation, SeiPayload, UserDataRegistry };
+use crate::klv::{ self, UasDatalinkSet, PrecisionTimestamp };
+use super::access_unit::AccessUnitTracker;
+
+use std::collections::VecDeque;
+
+
+#[derive(Debug, Clone, Default)]
+struct AccessUnitKlv {
+    timestamps: Vec<PrecisionTimestamp>,
+    sets: Vec<UasDatalinkSet>,
+}
+
+// 一个访问单元 ( 帧或场 ) 以及它的 SEI 中的 KLV
+#[derive(Debug, Clone, PartialEq)]
+pub struct KlvAccessUnit {
+    // PictureReport::id
+    pub id: u64,
+    pub frame_num: u32,
+    pub pic_order_cnt: i32,
+    // MISB ST 0604
+    pub timestamps: Vec<PrecisionTimestamp>,
+    // MISB ST 0601
+    pub sets: Vec<UasDatalinkSet>,
+}
+
+#[derive(Debug)]
+pub struct KlvCollector {
+    tracker: AccessUnitTracker<AccessUnitKlv>,
+    registry: UserDataRegistry,
+    access_units: VecDeque<KlvAccessUnit>,
+}
+
+impl Default for KlvCollector {
+    fn default() -> Self {
+        let mut registry = UserDataRegistry::empty();
+        klv::register(&mut registry);
+
+        Self {
+            tracker: AccessUnitTracker::default(),
+            registry: registry,
+            access_units: VecDeque::new(),
+        }
+    }
+}
+
+impl KlvCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        match nalu.kind() {
+            NaluKind::SupplementalEnhancementInformation => {
+                let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+                let access_unit = self.tracker.access_unit();
+                for message in sei.messages() {
+                    if let SeiPayload::UserDataUnregistered(ref user_data) = message.payload {
+                        let decoded = self.registry.decode(user_data)?;
+                        if let Some(timestamp) = decoded.downcast_ref::<PrecisionTimestamp>() {
+                            access_unit.timestamps.push(*timestamp);
+                        }
+                        if let Some(set) = decoded.downcast_ref::<UasDatalinkSet>() {
+                            access_unit.sets.push(set.clone());
+                        }
+                    }
+                }
+                Ok(())
+            },
+            _ => {
+                self.tracker.push(nalu, |_, _, access_unit| Ok(access_unit))?;
+                self.drain();
+                Ok(())
+            },
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Some((report, data)) = self.tracker.next_report() {
+            self.access_units.push_back(KlvAccessUnit {
+                id: report.id,
+                frame_num: report.frame_num,
+                pic_order_cnt: report.pic_order_cnt,
+                timestamps: data.timestamps,
+                sets: data.sets,
+            });
+        }
+    }
+
+    // 码流结束: 完成最后一个访问单元
+    pub fn flush(&mut self) {
+        self.tracker.flush();
+        self.drain();
+    }
+
+    // 取出下一个 ( 按解码顺序 ) 访问单元
+    pub fn next_access_unit(&mut self) -> Option<KlvAccessUnit> {
+        self.access_units.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::KlvCollector;
+    use crate::nalu::Nalu;
+    use crate::slice::test::{ Writer, parameter_set_nalus };
+    use crate::klv::{ UAS_DATALINK_LS_KEY, MISB_MICROSECTIME_UUID, KlvValue };
+
+    // 一个 user_data_unregistered SEI
+    fn user_data_sei(uuid: &[u8; 16], payload: &[u8]) -> Nalu {
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 5).u(8, 16 + payload.len() as u32);
+        for &byte in uuid.iter().chain(payload) {
+            writer.u(8, byte as u32);
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_klv_per_access_unit() {
+        let (sps, pps) = parameter_set_nalus();
+        let mut collector = KlvCollector::new();
+        collector.push(&sps).unwrap();
+        collector.push(&pps).unwrap();
+
+        let idr = Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish();
+        let p = Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 1).u(4, 2).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish();
+
+        // 第一个访问单元: 时间戳与 Mission ID ( 没有 Checksum ); 第二个访问单元没有 KLV
+        collector.push(&user_data_sei(&MISB_MICROSECTIME_UUID, &[0x80, 0, 0, 0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0x03, 0xE8])).unwrap();
+        collector.push(&user_data_sei(&UAS_DATALINK_LS_KEY, &[0x06, 0x03, 0x04, b'M', b'I', b'S', b'N'])).unwrap();
+        collector.push(&idr).unwrap();
+        collector.push(&p).unwrap();
+        collector.flush();
+
+        let first = collector.next_access_unit().unwrap();
+        assert_eq!((first.frame_num, first.pic_order_cnt), (0, 0));
+        assert_eq!(first.timestamps.len(), 1);
+        assert_eq!(first.timestamps[0].timestamp, 1000);
+        assert_eq!(first.sets.len(), 1);
+        assert_eq!(first.sets[0].get(3), Some(&KlvValue::Text("MISN".to_string())));
+        assert!(!first.sets[0].checksum_valid);
+
+        let second = collector.next_access_unit().unwrap();
+        assert_eq!((second.frame_num, second.pic_order_cnt), (1, 2));
+        assert!(second.timestamps.is_empty() && second.sets.is_empty());
+        assert!(collector.next_access_unit().is_none());
+    }
+}
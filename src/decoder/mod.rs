@@ -0,0 +1,779 @@
+// 8 Decoding process ( Page 95 )
+//
+// 支持 I, P, B slice 的帧图像, 场图像 ( 互补场对写入同一个帧 ) 与 MBAFF 帧的重建与去块滤波,
+// 按 C.4.5 的 "bumping" 过程输出。
+
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluKind, NaluRefIdc };
+use crate::rbsp::{ ParameterSets, SliceHeader, PictureStructure };
+use crate::slice::Slice;
+
+
+mod picture;
+mod transform;
+mod intra;
+mod inter;
+mod motion;
+mod reconstruct;
+mod deblock;
+mod poc;
+mod dpb;
+mod model;
+mod access_unit;
+mod export;
+mod stats;
+mod caption;
+mod klv;
+mod random_access;
+mod display_info;
+mod sei_editor;
+mod timecode;
+mod hdr;
+
+pub use self::picture::{ Plane, Frame, Picture };
+pub use self::reconstruct::chroma_qp;
+pub use self::poc::{ PicOrderCnt, PocState };
+pub use self::dpb::{ Dpb, DpbEntry, Reference, Marking, Violation };
+pub use self::model::{ DpbModel, PictureReport, SliceReport, RefPicture, OutputReport, OutputSummary };
+pub use self::export::{ MotionInfo, MacroblockMotion, MotionPartition, mb_type_code, structure_code };
+pub use self::stats::{ MbStats, SliceStats, PictureStats, StatsCollector };
+pub use self::caption::{ CaptionPicture, CaptionCollector };
+pub use self::klv::{ KlvAccessUnit, KlvCollector };
+pub use self::random_access::{ RandomAccessKind, RandomAccessUnit, RandomAccessCollector };
+pub use self::display_info::{ DisplayInfoPicture, DisplayInfoCollector };
+pub use self::sei_editor::{ SeiEditor, SeiInjector };
+pub use self::timecode::{ Timecode, TimecodeEntry, TimecodePicture, TimecodeDiscontinuity, TimecodeCollector };
+pub use self::hdr::{
+    HdrMetadata, COLOUR_PRIMARIES_BT2020, TRANSFER_CHARACTERISTICS_PQ, TRANSFER_CHARACTERISTICS_HLG, MATRIX_COEFFICIENTS_BT2020_NCL,
+};
+
+use self::picture::{ RefPic, RefPicLists };
+
+
+use std::collections::VecDeque;
+
+
+// 7.4.1.2.4 Detection of the first VCL NAL unit of a primary coded picture ( Page 91 )
+fn first_vcl_nal_unit_of_picture(prev: &SliceHeader, curr: &SliceHeader) -> bool {
+    prev.frame_num != curr.frame_num
+        || prev.pic_parameter_set_id != curr.pic_parameter_set_id
+        || prev.field_pic_flag != curr.field_pic_flag
+        || prev.bottom_field_flag != curr.bottom_field_flag
+        || (prev.nal_ref_idc == NaluRefIdc::DISPOSABLE) != (curr.nal_ref_idc == NaluRefIdc::DISPOSABLE)
+        || prev.pic_order_cnt_lsb != curr.pic_order_cnt_lsb
+        || prev.delta_pic_order_cnt_bottom != curr.delta_pic_order_cnt_bottom
+        || prev.delta_pic_order_cnt != curr.delta_pic_order_cnt
+        || prev.idr_pic_flag() != curr.idr_pic_flag()
+        || prev.idr_pic_id != curr.idr_pic_id
+}
+
+// 3.30 complementary field pair: 奇偶相反, frame_num 相同, 同为参考或非参考的两个场, 第二个场不是 IDR 图像
+// 且不包含 memory_management_control_operation 5
+fn second_field_of_pair(first: &SliceHeader, second: &SliceHeader) -> bool {
+    match (first.structure().parity(), second.structure().parity()) {
+        (Some(first_parity), Some(second_parity)) => {
+            first_parity != second_parity
+                && first.frame_num == second.frame_num
+                && (first.nal_ref_idc == NaluRefIdc::DISPOSABLE) == (second.nal_ref_idc == NaluRefIdc::DISPOSABLE)
+                && !second.idr_pic_flag()
+                && !second.has_mmco5()
+        },
+        _ => false,
+    }
+}
+
+// 把 DPB 中的项转换为参考图像 ( "non-existing" 帧视为 "no reference picture" ); 解码场时参考的是场
+fn resolve_ref_pic_lists<'a>(dpb: &'a Dpb<Picture>,
+                             entries: [Vec<Option<(u64, PictureStructure)>>; 2],
+                             poc: &PicOrderCnt) -> RefPicLists<'a> {
+    let resolve = |entry: Option<(u64, PictureStructure)>| {
+        let (id, structure) = entry?;
+        let entry = dpb.get(id)?;
+        let frame = RefPic {
+            id: entry.id,
+            parity: None,
+            poc: entry.pic_order_cnt(),
+            field_poc: [entry.top_field_order_cnt, entry.bottom_field_order_cnt],
+            long_term: entry.reference.is_long_term(),
+            picture: entry.data.as_ref()?,
+        };
+
+        match structure.parity() {
+            Some(parity) => Some(RefPic {
+                long_term: entry.field_reference[parity].is_long_term(),
+                ..frame.field(parity)
+            }),
+            None => Some(frame),
+        }
+    };
+
+    let [list0, list1] = entries;
+    RefPicLists {
+        poc: poc.value(),
+        field_poc: [poc.top.unwrap_or(0), poc.bottom.unwrap_or(0)],
+        lists: [
+            list0.into_iter().map(resolve).collect(),
+            list1.into_iter().map(resolve).collect(),
+        ],
+    }
+}
+
+
+// DPB 输出的图像 ( "non-existing" 帧没有样本, 不输出 )
+fn output_frame(entry: &DpbEntry<Picture>, export_motion: bool) -> Option<Frame> {
+    let picture = entry.data.as_ref()?;
+    let mut frame = picture.to_frame();
+    if export_motion {
+        frame.motion = Some(MotionInfo::from_picture(picture, entry.pic_order_cnt(), entry.structure));
+    }
+
+    Some(frame)
+}
+
+
+// 正在解码的图像, 以及它最近一个 slice 的头部
+#[derive(Debug)]
+struct CurrentPicture {
+    header: SliceHeader,
+    picture: Picture,
+    poc: PicOrderCnt,
+}
+
+// 按解码顺序输入 NALU, 按输出顺序输出重建后的图像
+#[derive(Debug, Default)]
+pub struct Decoder {
+    parameter_sets: ParameterSets,
+    current: Option<CurrentPicture>,
+    poc: PocState,
+    dpb: Dpb<Picture>,
+    output: VecDeque<Frame>,
+    // 跳过去块滤波 ( 输出与参考的都是滤波前的重建图像 )
+    skip_deblocking: bool,
+    // 在输出的 Frame 中附带运动信息
+    export_motion: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parameter_sets(&self) -> &ParameterSets {
+        &self.parameter_sets
+    }
+
+    // 用于分析滤波前的重建图像; 由于参考图像也未经滤波, 输出与符合规范的解码结果不同
+    pub fn set_skip_deblocking(&mut self, skip: bool) {
+        self.skip_deblocking = skip;
+    }
+
+    // 输出的 Frame::motion 包含每个宏块的类型, 分区, 参考索引与运动向量
+    pub fn set_export_motion(&mut self, export: bool) {
+        self.export_motion = export;
+    }
+
+    // 处理一个 NALU; 参数集会被记录下来, 非 VCL NALU 被忽略
+    pub fn decode(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        if self.parameter_sets.update(nalu)? {
+            return Ok(());
+        }
+
+        match nalu.kind() {
+            NaluKind::CodedSliceIdr | NaluKind::CodedSliceNonIdr => { },
+            _ => return Ok(()),
+        }
+
+        let slice = Slice::parse(nalu, &self.parameter_sets)?;
+
+        // 冗余图像只在主图像丢失时使用
+        if slice.header.redundant_pic_cnt.unwrap_or(0) > 0 {
+            return Ok(());
+        }
+
+        let new_picture = match self.current {
+            Some(ref current) => first_vcl_nal_unit_of_picture(&current.header, &slice.header),
+            None => true,
+        };
+
+        if new_picture {
+            self.finish_picture()?;
+        }
+
+        let pps = self.parameter_sets.pps(slice.header.pic_parameter_set_id).expect("pps has been checked by the slice header");
+        let sps = self.parameter_sets.sps(pps.seq_parameter_set_id()).expect("sps has been checked by the slice header");
+
+        if new_picture {
+            self.dpb.activate(sps);
+
+            let output = &mut self.output;
+            let export_motion = self.export_motion;
+            self.dpb.fill_frame_num_gap(&slice.header, &mut |entry| {
+                output.extend(output_frame(entry, export_motion));
+            });
+
+            // 互补场对的第二个场与第一个场写入同一个帧
+            let picture = match self.dpb.first_field(&slice.header) {
+                Some(entry) => entry.data.clone().unwrap_or_else(|| Picture::new(sps)),
+                None => Picture::new(sps),
+            };
+
+            self.current = Some(CurrentPicture {
+                header: slice.header.clone(),
+                picture: picture,
+                poc: self.poc.decode(&slice.header, sps),
+            });
+        }
+
+        let current = self.current.as_mut().expect("current picture");
+        current.header = slice.header.clone();
+
+        let mut violations = vec![];
+        let entries = self.dpb.ref_pic_list_entries(&slice.header, current.poc.value(), &mut violations);
+        if let Some(violation) = violations.first() {
+            return Err(error::malformed(violation.description()));
+        }
+        let refs = resolve_ref_pic_lists(&self.dpb, entries, &current.poc);
+
+        reconstruct::decode_slice(&mut current.picture, &slice.header, &slice.data, sps, pps, &refs)
+    }
+
+    // 码流结束: 完成当前图像并输出 DPB 中所有等待输出的图像
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.finish_picture()?;
+
+        let output = &mut self.output;
+        let export_motion = self.export_motion;
+        self.dpb.flush(&mut |entry| {
+            output.extend(output_frame(entry, export_motion));
+        });
+
+        Ok(())
+    }
+
+    // 取出下一个输出的图像
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        self.output.pop_front()
+    }
+
+    // 8.7 去块滤波, 8.2.5 参考图像标记, 然后按 C.4.4, C.4.5 存入 DPB
+    fn finish_picture(&mut self) -> Result<(), Error> {
+        let mut current = match self.current.take() {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+
+        if !self.skip_deblocking {
+            deblock::deblock_picture(&mut current.picture);
+        }
+
+        let marking = self.dpb.mark(&current.header)?;
+        if marking.mmco5 {
+            current.poc.reset();
+        }
+        self.poc.update(&current.header, &current.poc, marking.mmco5);
+
+        let output = &mut self.output;
+        let export_motion = self.export_motion;
+        self.dpb.store(&current.header, &marking, &current.poc, current.picture, &mut |entry| {
+            output.extend(output_frame(entry, export_motion));
+        });
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::slice::test::{ Writer, parameter_sets, parameter_set_nalus };
+    use crate::rbsp::{ ParameterSets, SliceType, PictureStructure };
+    use crate::nalu::Nalu;
+    use crate::macroblock::MbType;
+    use crate::macroblock::cabac::{ CabacDecoder, MB_TYPE_I, MB_QP_DELTA, INTRA_CHROMA_PRED_MODE };
+    use crate::macroblock::cabac::test::CabacEncoder;
+    use crate::error::Error;
+    use crate::stream::{ StreamReader, StreamFormat };
+    use super::{ Decoder, Picture, MotionPartition, reconstruct, inter, mb_type_code, structure_code };
+
+    use std::fs::{ self, File };
+    use std::io::BufReader;
+    use std::path::{ Path, PathBuf };
+
+    fn decoder(parameter_sets: &ParameterSets) -> Decoder {
+        let mut decoder = Decoder::new();
+        decoder.parameter_sets = parameter_sets.clone();
+        decoder
+    }
+
+    // I_16x16_<pred>_0_0 ( 无残差 ) + I_PCM
+    fn idr_slice(intra_16x16_pred_mode: u32, idr_pic_id: u32) -> Nalu {
+        let mut writer = Writer::new(0x65);
+        writer.ue(0).ue(7).ue(0).u(4, 0).ue(idr_pic_id).u(4, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        writer.ue(1 + intra_16x16_pred_mode).ue(0).se(-1).bits("1");
+        writer.ue(25).align();
+        for sample in pcm_samples() {
+            writer.u(8, u32::from(sample));
+        }
+        writer.finish()
+    }
+
+    // idr_slice 中 I_PCM 宏块的 256 个亮度样本与 128 个色度样本
+    fn pcm_samples() -> Vec<u8> {
+        (0..256).map(|i| 16 + i % 200).chain(64..192).map(|sample| sample as u8).collect()
+    }
+
+    // 与 idr_slice( 2, 0 ) 相同的宏块, 使用 CABAC ( PPS 1 )
+    fn cabac_idr_slice() -> Nalu {
+        let decoder = CabacDecoder::new(SliceType::I, None, 26).unwrap();
+        let mut encoder = CabacEncoder::new(&decoder);
+
+        // I_16x16_2_0_0 ( 3 ): 1 终止位 0 0 1 0, 相邻宏块不可用
+        encoder.encode_decision(MB_TYPE_I, true);
+        encoder.encode_terminate(false);
+        for &(ctx_idx_inc, bin) in [(3, false), (4, false), (6, true), (7, false)].iter() {
+            encoder.encode_decision(MB_TYPE_I + ctx_idx_inc, bin);
+        }
+        encoder.encode_decision(INTRA_CHROMA_PRED_MODE, false);
+        encoder.encode_decision(MB_QP_DELTA, false);
+        // Intra16x16DCLevel 的 coded_block_flag ( ctxIdxOffset 85 ): 相邻块不可用且为帧内宏块, ctxIdxInc 为 3
+        encoder.encode_decision(85 + 3, false);
+        encoder.encode_terminate(false);
+
+        // I_PCM ( 25 ): mbAddrA 不是 I_NxN, ctxIdxInc 为 1
+        encoder.encode_decision(MB_TYPE_I + 1, true);
+        encoder.encode_terminate(true);
+        encoder.pcm_samples(&pcm_samples());
+        encoder.encode_terminate(true);
+
+        let mut writer = Writer::new(0x65);
+        writer.ue(0).ue(7).ue(1).u(4, 0).ue(0).u(4, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        writer.align_ones();
+        // 最后一位为 rbsp_stop_one_bit, 由 Writer 写入
+        let bits = encoder.bits();
+        for &bit in bits[..bits.len() - 1].iter() {
+            writer.u(1, bit as u32);
+        }
+        writer.finish()
+    }
+
+    // 在 parameter_set_nalus() 之后解码 nalus, 返回输出的图像个数以及按输出顺序的 YUV ( Frame::write_yuv ) 的 MD5
+    fn decode_md5(nalus: &[Nalu]) -> (usize, String) {
+        let (sps, pps) = parameter_set_nalus();
+        let mut decoder = Decoder::new();
+        for nalu in [sps, pps].iter().chain(nalus) {
+            decoder.decode(nalu).unwrap();
+        }
+        decoder.flush().unwrap();
+
+        let mut count = 0;
+        let mut yuv = vec![];
+        while let Some(frame) = decoder.next_frame() {
+            frame.write_yuv(&mut yuv).unwrap();
+            count += 1;
+        }
+        (count, format!("{:x}", md5::compute(&yuv)))
+    }
+
+    #[test]
+    fn test_intra_picture() {
+        let parameter_sets = parameter_sets();
+        let mut decoder = decoder(&parameter_sets);
+
+        decoder.decode(&idr_slice(2, 0)).unwrap();
+        assert!(decoder.next_frame().is_none());
+        decoder.flush().unwrap();
+
+        let frame = decoder.next_frame().unwrap();
+        assert_eq!((frame.width, frame.height), (32, 16));
+        assert_eq!(frame.luma.width, 32);
+
+        // 第一个宏块: DC 预测, 没有可用的相邻样本
+        assert_eq!(frame.luma.get(0, 0), 128);
+        assert_eq!(frame.luma.get(15, 15), 128);
+        // 第二个宏块: I_PCM
+        assert_eq!(frame.luma.get(16, 0), 16);
+        assert_eq!(frame.luma.get(31, 15), 16 + 255 % 200);
+
+        let cb = frame.cb.as_ref().unwrap();
+        let cr = frame.cr.as_ref().unwrap();
+        assert_eq!((cb.width, cb.height), (16, 8));
+        assert_eq!(cb.get(0, 0), 128);
+        assert_eq!(cb.get(8, 0), 64);
+        assert_eq!(cr.get(15, 7), 64 + 127);
+
+        let mut yuv = vec![];
+        frame.write_yuv(&mut yuv).unwrap();
+        assert_eq!(yuv.len(), 32 * 16 * 3 / 2);
+    }
+
+    #[test]
+    fn test_picture_boundary() {
+        let parameter_sets = parameter_sets();
+        let mut decoder = decoder(&parameter_sets);
+
+        decoder.decode(&idr_slice(2, 0)).unwrap();
+        // idr_pic_id 不同: 新的图像, 前一个图像存入 DPB
+        decoder.decode(&idr_slice(2, 1)).unwrap();
+        assert!(decoder.next_frame().is_none());
+        // 第三个 IDR 图像开始时, 第二个 IDR 图像 ( no_output_of_prior_pics_flag 为 0 ) 使 DPB 中的图像全部输出
+        decoder.decode(&idr_slice(2, 2)).unwrap();
+        assert!(decoder.next_frame().is_some());
+        assert!(decoder.next_frame().is_none());
+
+        // 第一个宏块使用垂直预测, 但上方样本不可用
+        assert!(decoder.decode(&idr_slice(0, 3)).is_err());
+    }
+
+    #[test]
+    fn test_predicted_picture() {
+        let parameter_sets = parameter_sets();
+        let mut decoder = decoder(&parameter_sets);
+
+        decoder.decode(&idr_slice(2, 0)).unwrap();
+
+        // P_Skip + P_L0_16x16 ( mvd = ( 1, -2 ), 只有第一个 4x4 块有残差 ), 见 slice::test::test_predicted_slice
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        writer.ue(1).ue(0).se(1).se(-2).ue(2).se(0);
+        writer.bits("000010001110010111101101").bits("1111").bits("11").bits("1");
+        decoder.decode(&writer.finish()).unwrap();
+        decoder.flush().unwrap();
+
+        let idr = decoder.next_frame().unwrap();
+        let frame = decoder.next_frame().unwrap();
+        assert!(decoder.next_frame().is_none());
+
+        // P_Skip: A 不可用, 运动向量为 0
+        assert_eq!(frame.luma.get(0, 0), idr.luma.get(0, 0));
+        assert_eq!(frame.luma.get(15, 15), idr.luma.get(15, 15));
+        // P_L0_16x16: mvpL0 = mvL0A = 0, mvL0 = ( 1, -2 )
+        let expected = inter::luma_sample(&idr.luma, 31, 14, 1, 2, 8);
+        assert_eq!(frame.luma.get(31, 15) as i32, expected);
+        // 色度: mvCL0 = ( 1, -2 ) ( 1/8 样本 )
+        let mut pred = [0i32; 1];
+        inter::predict_chroma(idr.cb.as_ref().unwrap(), 15, 7 - 1, 1, 6, 1, 1, &mut pred);
+        assert_eq!(frame.cb.as_ref().unwrap().get(15, 7) as i32, pred[0]);
+    }
+
+    #[test]
+    fn test_motion_export() {
+        let parameter_sets = parameter_sets();
+        let mut decoder = decoder(&parameter_sets);
+        decoder.set_export_motion(true);
+
+        decoder.decode(&idr_slice(2, 0)).unwrap();
+
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        writer.ue(1).ue(0).se(1).se(-2).ue(2).se(0);
+        writer.bits("000010001110010111101101").bits("1111").bits("11").bits("1");
+        decoder.decode(&writer.finish()).unwrap();
+        decoder.flush().unwrap();
+
+        let idr = decoder.next_frame().unwrap().motion.unwrap();
+        assert_eq!(idr.macroblocks.len(), 2);
+        assert!(idr.macroblocks.iter().all(|mb| mb.partitions.is_empty()));
+
+        let motion = decoder.next_frame().unwrap().motion.unwrap();
+        assert_eq!(motion.pic_order_cnt, 2);
+        assert_eq!(motion.structure, PictureStructure::Frame);
+        assert_eq!(motion.mb_position(&motion.macroblocks[1]), (16, 0));
+        assert_eq!(motion.macroblocks[1].partitions, vec![MotionPartition {
+            x: 0, y: 0, width: 16, height: 16, ref_idx: [0, -1], mv: [[1, -2], [0, 0]],
+        }]);
+        assert_eq!(motion.to_json(), concat!(
+            "{\"pic_order_cnt\":2,\"width_in_mbs\":2,\"height_in_mbs\":1,\"structure\":\"frame\",\"mbaff\":false,\"macroblocks\":[",
+            "{\"mb_addr\":0,\"mb_type\":\"P_Skip\",\"structure\":\"frame\",\"partitions\":[",
+            "{\"x\":0,\"y\":0,\"width\":16,\"height\":16,\"ref_idx\":[0,-1],\"mv\":[[0,0],[0,0]]}]},",
+            "{\"mb_addr\":1,\"mb_type\":\"P_L0_16x16\",\"structure\":\"frame\",\"partitions\":[",
+            "{\"x\":0,\"y\":0,\"width\":16,\"height\":16,\"ref_idx\":[0,-1],\"mv\":[[1,-2],[0,0]]}]}]}",
+        ));
+
+        let mut binary = vec![];
+        motion.write_binary(&mut binary).unwrap();
+        assert_eq!(binary.len(), 14 + 2 * (7 + 14));
+        assert_eq!(binary[8..10], [structure_code(PictureStructure::Frame), 0]);
+        assert_eq!(binary[14 + 21 + 4], mb_type_code(MbType::P16x16));
+        assert_eq!(binary[14 + 21 + 7 + 6 .. 14 + 21 + 7 + 10], [1, 0, 0xfe, 0xff]);
+    }
+
+    // 32x32 的隔行码流 ( frame_mbs_only_flag 为 0, 非 MBAFF ) 的参数集, 每个场为 2x1 个宏块
+    fn field_parameter_sets() -> ParameterSets {
+        let sps = Writer::new(0x67)
+            .u(8, 77).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(2).u(1, 0).ue(1).ue(0)
+            .u(1, 0).u(1, 0).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        let (_, pps) = parameter_set_nalus();
+
+        let mut parameter_sets = ParameterSets::new();
+        assert!(parameter_sets.update(&sps).unwrap());
+        assert!(parameter_sets.update(&pps).unwrap());
+        parameter_sets
+    }
+
+    // 两个 I_PCM 宏块组成的 I 场, 亮度样本为 `luma`, 色度样本为 `chroma`
+    fn pcm_field(nal_header: u8, bottom_field_flag: bool, pic_order_cnt_lsb: u32, luma: u32, chroma: u32) -> Nalu {
+        let mut writer = Writer::new(nal_header);
+        writer.ue(0).ue(7).ue(0).u(4, 0).u(1, 1).u(1, bottom_field_flag as u32);
+        if nal_header & 0x1f == 5 {
+            writer.ue(0).u(4, pic_order_cnt_lsb).u(1, 0).u(1, 0);
+        } else {
+            writer.u(4, pic_order_cnt_lsb).u(1, 0);
+        }
+        writer.se(0).ue(1);
+
+        for _ in 0..2 {
+            writer.ue(25).align();
+            for i in 0..384 {
+                writer.u(8, if i < 256 { luma } else { chroma });
+            }
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_field_pictures() {
+        let parameter_sets = field_parameter_sets();
+        let mut decoder = decoder(&parameter_sets);
+        decoder.set_export_motion(true);
+
+        // IDR 顶场与非 IDR 的底场组成互补参考场对
+        decoder.decode(&pcm_field(0x65, false, 0, 50, 100)).unwrap();
+        decoder.decode(&pcm_field(0x61, true, 1, 200, 30)).unwrap();
+
+        // P 顶场: I_PCM ( 亮度 90 ) 与 P_Skip ( 参考 RefPicList0[ 0 ], 即帧 0 的顶场 )
+        let mut top = Writer::new(0x61);
+        top.ue(0).ue(5).ue(0).u(4, 1).u(1, 1).u(1, 0).u(4, 4)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        top.ue(0).ue(5 + 25).align();
+        for i in 0..384 {
+            top.u(8, if i < 256 { 90 } else { 100 });
+        }
+        top.ue(1);
+        let top = top.finish();
+        // P 底场: RefPicList0 为 ( 帧 0 的底场, 帧 1 的顶场 ); P_L0_16x16 refIdxL0 = 1 ( te(v), 位 0 ),
+        // mvd 为 0, 没有残差; 之后的 P_Skip 的相邻宏块 B 不可用, 参考帧 0 的底场
+        let bottom = Writer::new(0x61)
+            .ue(0).ue(5).ue(0).u(4, 1).u(1, 1).u(1, 1).u(4, 5)
+            .u(1, 1).ue(1).u(1, 0).u(1, 0)
+            .se(0).ue(1)
+            .ue(0).ue(0).u(1, 0).se(0).se(0).ue(0)
+            .ue(1)
+            .finish();
+        decoder.decode(&top).unwrap();
+        decoder.decode(&bottom).unwrap();
+        decoder.flush().unwrap();
+
+        let frame = decoder.next_frame().unwrap();
+        assert_eq!((frame.width, frame.height), (32, 32));
+        let cb = frame.cb.as_ref().unwrap();
+        for y in 0..32 {
+            let expected = if y % 2 == 0 { 50 } else { 200 };
+            assert_eq!((frame.luma.get(0, y), frame.luma.get(31, y)), (expected, expected));
+        }
+        for y in 0..16 {
+            let expected = if y % 2 == 0 { 100 } else { 30 };
+            assert_eq!((cb.get(0, y), cb.get(15, y)), (expected, expected));
+        }
+
+        let frame = decoder.next_frame().unwrap();
+        assert!(decoder.next_frame().is_none());
+        for y in 0..32 {
+            let expected = if y % 2 == 0 { (90, 50) } else { (90, 200) };
+            assert_eq!((frame.luma.get(0, y), frame.luma.get(31, y)), expected);
+        }
+        // 底场参考顶场: Table 8-10 的色度运动向量偏移, 常数平面上的预测不变
+        let cb = frame.cb.as_ref().unwrap();
+        assert_eq!((cb.get(0, 1), cb.get(15, 1)), (100, 30));
+
+        // 运动信息: 先是顶场的宏块, 然后是底场的宏块, mb_addr 为场内的地址
+        let motion = frame.motion.as_ref().unwrap();
+        assert_eq!((motion.structure, motion.mbaff_frame_flag, motion.height_in_mbs), (PictureStructure::FieldPair, false, 2));
+        let mbs: Vec<_> = motion.macroblocks.iter().map(|mb| (mb.mb_addr, mb.structure, motion.mb_position(mb))).collect();
+        assert_eq!(mbs, vec![
+            (0, PictureStructure::TopField, (0, 0)),
+            (1, PictureStructure::TopField, (16, 0)),
+            (0, PictureStructure::BottomField, (0, 1)),
+            (1, PictureStructure::BottomField, (16, 1)),
+        ]);
+        assert_eq!(motion.macroblocks[2].partitions[0].ref_idx, [1, -1]);
+        assert!(motion.to_json().contains("\"mb_addr\":0,\"mb_type\":\"P_L0_16x16\",\"structure\":\"bottom field\""));
+    }
+
+    #[test]
+    fn test_mbaff_picture() {
+        // 与 field_parameter_sets 相同, mb_adaptive_frame_field_flag 为 1
+        let sps = Writer::new(0x67)
+            .u(8, 77).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(2).u(1, 0).ue(1).ue(0)
+            .u(1, 0).u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        let mut parameter_sets = field_parameter_sets();
+        assert!(parameter_sets.update(&sps).unwrap());
+        let mut decoder = decoder(&parameter_sets);
+        decoder.set_export_motion(true);
+
+        // 左边为场宏块对, 右边为帧宏块对, 四个宏块均为 I_PCM
+        let mut writer = Writer::new(0x65);
+        writer.ue(0).ue(7).ue(0).u(4, 0).u(1, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1);
+        for &(field, top, bottom) in [(1, 10, 20), (0, 30, 40)].iter() {
+            writer.u(1, field);
+            for &luma in [top, bottom].iter() {
+                writer.ue(25).align();
+                for i in 0..384 {
+                    writer.u(8, if i < 256 { luma } else { luma + 1 });
+                }
+            }
+        }
+        decoder.decode(&writer.finish()).unwrap();
+        decoder.flush().unwrap();
+
+        let frame = decoder.next_frame().unwrap();
+        for y in 0..32 {
+            let field = if y % 2 == 0 { 10 } else { 20 };
+            let frame_mb = if y < 16 { 30 } else { 40 };
+            assert_eq!((frame.luma.get(0, y), frame.luma.get(31, y)), (field, frame_mb));
+        }
+        let cb = frame.cb.as_ref().unwrap();
+        assert_eq!((cb.get(0, 0), cb.get(0, 1), cb.get(15, 7), cb.get(15, 8)), (11, 21, 31, 41));
+
+        // 场宏块对中的宏块从第 0, 1 行开始隔行排列, 帧宏块对中的宏块从第 0, 16 行开始
+        let motion = frame.motion.as_ref().unwrap();
+        assert_eq!((motion.structure, motion.mbaff_frame_flag), (PictureStructure::Frame, true));
+        let mbs: Vec<_> = motion.macroblocks.iter().map(|mb| (mb.structure, motion.mb_position(mb))).collect();
+        assert_eq!(mbs, vec![
+            (PictureStructure::TopField, (0, 0)),
+            (PictureStructure::BottomField, (0, 1)),
+            (PictureStructure::Frame, (16, 0)),
+            (PictureStructure::Frame, (16, 16)),
+        ]);
+    }
+
+    // 解码输出的 MD5 快照。帧内的值与按样本的解析值独立计算的 YUV 一致, 帧间的值是解码器自身的输出,
+    // 只用于发现回归; 与参考解码器的比较见 test_conformance_streams
+    #[test]
+    fn test_snapshot_md5() {
+        let cabac_pps = Writer::new(0x68)
+            .ue(1).ue(0).u(1, 1).u(1, 0).ue(0)
+            .ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+
+        // CAVLC 与 CABAC 的 I slice: 第一个宏块的样本都为 128, 第二个宏块为 I_PCM 的样本
+        let cavlc = decode_md5(&[idr_slice(2, 0)]);
+        let cabac = decode_md5(&[cabac_pps, cabac_idr_slice()]);
+        assert_eq!(cavlc, (1, "a0527369b24f56a364679067306c2ad4".to_string()));
+        assert_eq!(cabac, cavlc);
+
+        // 帧间预测: IDR, 参考 P ( POC 4, P_Skip + P_L0_16x16, 去块滤波 ), 非参考 P ( POC 2, P_Skip ), 按 POC 输出
+        let mut p_slice = Writer::new(0x41);
+        p_slice.ue(0).ue(5).ue(0).u(4, 1).u(4, 4)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(0).se(0).se(0);
+        p_slice.ue(1).ue(0).se(1).se(-2).ue(2).se(0);
+        p_slice.bits("000010001110010111101101").bits("1111").bits("11").bits("1");
+        let skipped = Writer::new(0x01)
+            .ue(0).ue(5).ue(0).u(4, 2).u(4, 2)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(0).se(0).se(0)
+            .ue(2)
+            .finish();
+        let inter = decode_md5(&[idr_slice(2, 0), p_slice.finish(), skipped]);
+        assert_eq!(inter, (3, "34b528e019d289f59eec6d48d3be289a".to_string()));
+    }
+
+    // 解码 Annex B 码流, 返回按输出顺序的 YUV
+    fn decode_stream(path: &Path) -> Result<Vec<u8>, Error> {
+        let mut decoder = Decoder::new();
+        let mut yuv = vec![];
+
+        for nalu in StreamReader::new(BufReader::new(File::open(path)?), StreamFormat::AnnexB) {
+            decoder.decode(&nalu?)?;
+            while let Some(frame) = decoder.next_frame() {
+                frame.write_yuv(&mut yuv)?;
+            }
+        }
+
+        decoder.flush()?;
+        while let Some(frame) = decoder.next_frame() {
+            frame.write_yuv(&mut yuv)?;
+        }
+
+        Ok(yuv)
+    }
+
+    // JVT 一致性码流 ( 例如 CVSE2_Sony_B, CABA1_SVA_B, CAMA1_Sony_C ) 的解码输出与随码流发布的参考 YUV 的 MD5 比较。
+    //
+    // 码流不随仓库发布: 把解压后的码流 ( .264, .h264, .jsv, .jvt, .26l, .avc ) 与参考 YUV ( <名称>_dec.yuv 或
+    // <名称>.yuv ) 放在 H264_CONFORMANCE_DIR 指向的目录中, H264_CONFORMANCE_STREAMS 可以用逗号分隔的名称前缀
+    // 选择其中的一部分。没有设置 H264_CONFORMANCE_DIR 时跳过。
+    #[test]
+    fn test_conformance_streams() {
+        let dir = match std::env::var_os("H264_CONFORMANCE_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => return,
+        };
+        let prefixes: Vec<String> = std::env::var("H264_CONFORMANCE_STREAMS")
+            .map(|value| value.split(',').map(|prefix| prefix.trim().to_string()).filter(|prefix| !prefix.is_empty()).collect())
+            .unwrap_or_default();
+
+        let mut streams: Vec<(String, PathBuf)> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| matches!(path.extension().and_then(|extension| extension.to_str()),
+                Some("264") | Some("h264") | Some("jsv") | Some("jvt") | Some("26l") | Some("avc")))
+            .map(|path| (path.file_stem().unwrap().to_string_lossy().into_owned(), path))
+            .filter(|(name, _)| prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())))
+            .collect();
+        streams.sort();
+        assert!(!streams.is_empty(), "no bitstreams in {}", dir.display());
+
+        let mut failures = vec![];
+        for (name, path) in streams.iter() {
+            let reference = [format!("{}_dec.yuv", name), format!("{}.yuv", name)].iter()
+                .map(|file_name| dir.join(file_name))
+                .find(|reference| reference.is_file());
+            let reference = match reference {
+                Some(reference) => fs::read(reference).unwrap(),
+                None => {
+                    failures.push(format!("{}: reference YUV is missing", name));
+                    continue;
+                },
+            };
+
+            match decode_stream(path) {
+                Ok(yuv) => {
+                    let expected = format!("{:x}", md5::compute(&reference));
+                    let actual = format!("{:x}", md5::compute(&yuv));
+                    if actual != expected {
+                        failures.push(format!("{}: MD5 {} ( {} bytes ), expected {} ( {} bytes )",
+                                              name, actual, yuv.len(), expected, reference.len()));
+                    }
+                },
+                Err(e) => failures.push(format!("{}: {:?}", name, e)),
+            }
+        }
+
+        assert!(failures.is_empty(), "{} of {} streams failed:\n{}", failures.len(), streams.len(), failures.join("\n"));
+    }
+
+    #[test]
+    fn test_chroma_qp() {
+        assert_eq!(reconstruct::chroma_qp(25, 0, 0), 25);
+        assert_eq!(reconstruct::chroma_qp(30, 0, 0), 29);
+        assert_eq!(reconstruct::chroma_qp(51, 12, 0), 39);
+        assert_eq!(reconstruct::chroma_qp(0, -12, 12), -12);
+    }
+}
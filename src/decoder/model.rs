@@ -0,0 +1,555 @@
+// 8.2.4 Decoding process for reference picture lists construction ( Page 104 )
+// 8.2.5 Decoded reference picture marking process ( Page 114 )
+//
+// C.4.5.3 "Bumping" process ( Page 296 )
+//
+// 只根据 slice header 模拟 DPB 中参考图像的状态 ( 不解码样本 ), 报告每个图像的参考图像列表,
+// 标记的结果以及违反规范的情况; 并按 "bumping" 过程模拟输出, 报告输出顺序与重排序的深度。
+// 场图像 ( PAFF ) 各自产生一个报告, 互补场对的两个场在 DPB 中是同一项 ( id 相同 )。
+
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluKind, NaluRefIdc };
+use crate::rbsp::{ ParameterSets, SliceHeader, SliceType, PictureStructure };
+use crate::slice::Slice;
+use super::first_vcl_nal_unit_of_picture;
+use super::poc::{ PicOrderCnt, PocState };
+use super::dpb::{ Dpb, DpbEntry, Reference, Marking, Violation };
+
+
+use std::collections::VecDeque;
+
+
+// DPB 中的一个帧 ( 或场 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefPicture {
+    // 按解码顺序的编号 ( 与 PictureReport::id 对应 )
+    pub id: u64,
+    pub frame_num: u32,
+    // DPB 中的项为帧, 互补场对或单个场; 解码场时参考图像列表中的项为被参考的场
+    pub structure: PictureStructure,
+    pub pic_order_cnt: i32,
+    pub reference: Reference,
+    // frame_num 间隙中推导出的 "non-existing" 帧
+    pub non_existing: bool,
+}
+
+impl<'a> From<&'a DpbEntry<()>> for RefPicture {
+    fn from(entry: &'a DpbEntry<()>) -> Self {
+        RefPicture {
+            id: entry.id,
+            frame_num: entry.frame_num,
+            structure: entry.structure,
+            pic_order_cnt: entry.pic_order_cnt(),
+            reference: entry.reference,
+            non_existing: entry.is_non_existing(),
+        }
+    }
+}
+
+impl RefPicture {
+    // 参考图像列表中的一项: 帧, 或帧 ( 互补场对 ) 中的一个场
+    fn from_list_entry(entry: &DpbEntry<()>, structure: PictureStructure) -> Self {
+        let mut picture = RefPicture::from(entry);
+        if let Some(parity) = structure.parity() {
+            picture.structure = structure;
+            picture.pic_order_cnt = entry.field_order_cnt(parity);
+            picture.reference = entry.field_reference[parity];
+        }
+        picture
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceReport {
+    pub first_mb_in_slice: u32,
+    pub slice_type: SliceType,
+    // 修改之后的 RefPicList0, RefPicList1 ( "no reference picture" 为 None )
+    pub ref_pic_lists: [Vec<Option<RefPicture>>; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PictureReport {
+    pub id: u64,
+    pub frame_num: u32,
+    // 帧, 顶场或底场
+    pub structure: PictureStructure,
+    // 与前一个场组成互补场对 ( id 与第一个场的报告相同 )
+    pub second_field: bool,
+    pub idr: bool,
+    pub nal_ref_idc: NaluRefIdc,
+    // 构建参考图像列表时使用的 PicOrderCnt ( memory_management_control_operation 5 之前 )
+    pub pic_order_cnt: i32,
+    pub slices: Vec<SliceReport>,
+    pub marking: Marking,
+    // 当前图像存入 DPB 之后的所有参考帧 ( 包括当前图像 )
+    pub references: Vec<RefPicture>,
+    pub violations: Vec<Violation>,
+}
+
+
+// 按输出顺序的一个图像
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputReport {
+    // PictureReport::id
+    pub id: u64,
+    pub frame_num: u32,
+    // 帧, 互补场对或没有配对的单个场
+    pub structure: PictureStructure,
+    pub pic_order_cnt: i32,
+    // 在输出顺序中的序号
+    pub output_index: u64,
+    // 解码顺序在它之前而输出顺序在它之后的帧数
+    pub reorder_depth: usize,
+    // 从它解码完成到输出之间又解码完成的帧数
+    pub output_delay: usize,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputSummary {
+    pub num_output: u64,
+    pub max_reorder_depth: usize,
+    pub max_output_delay: usize,
+    // 活动 SPS 的 VUI 中的 max_num_reorder_frames
+    pub max_num_reorder_frames: Option<u32>,
+    // DPB 的容量 ( max_dec_frame_buffering 或 MaxDpbFrames )
+    pub dpb_size: usize,
+}
+
+#[derive(Debug, Default)]
+struct OutputTracker {
+    // 等待输出的图像: ( id, 在解码顺序中的序号 ), 不包括当前图像
+    pending: Vec<(u64, usize)>,
+    // 已解码完成的图像个数 ( 不包括 "non-existing" 帧 )
+    num_decoded: usize,
+    outputs: VecDeque<OutputReport>,
+    summary: OutputSummary,
+}
+
+impl OutputTracker {
+    fn output(&mut self, entry: &DpbEntry<()>) {
+        // 不在 pending 中的是当前图像 ( C.4.5.2 中直接输出 )
+        let decode_index = match self.pending.iter().position(|&(id, _)| id == entry.id) {
+            Some(idx) => self.pending.remove(idx).1,
+            None => self.num_decoded - 1,
+        };
+
+        let reorder_depth = self.pending.iter().filter(|&&(_, index)| index < decode_index).count();
+        let output_delay = self.num_decoded - 1 - decode_index;
+
+        let mut violations = vec![];
+        if let Some(max_num_reorder_frames) = self.summary.max_num_reorder_frames {
+            if reorder_depth > max_num_reorder_frames as usize {
+                violations.push(Violation::ReorderDepthExceeded {
+                    reorder_depth: reorder_depth,
+                    max_num_reorder_frames: max_num_reorder_frames,
+                });
+            }
+        }
+
+        self.outputs.push_back(OutputReport {
+            id: entry.id,
+            frame_num: entry.frame_num,
+            structure: entry.structure,
+            pic_order_cnt: entry.pic_order_cnt(),
+            output_index: self.summary.num_output,
+            reorder_depth: reorder_depth,
+            output_delay: output_delay,
+            violations: violations,
+        });
+
+        self.summary.num_output += 1;
+        self.summary.max_reorder_depth = self.summary.max_reorder_depth.max(reorder_depth);
+        self.summary.max_output_delay = self.summary.max_output_delay.max(output_delay);
+    }
+}
+
+
+#[derive(Debug)]
+struct CurrentPicture {
+    header: SliceHeader,
+    poc: PicOrderCnt,
+    report: PictureReport,
+}
+
+// 按解码顺序输入 NALU, 每个图像完成后产生一个 PictureReport
+#[derive(Debug, Default)]
+pub struct DpbModel {
+    parameter_sets: ParameterSets,
+    poc: PocState,
+    dpb: Dpb<()>,
+    current: Option<CurrentPicture>,
+    reports: VecDeque<PictureReport>,
+    output: OutputTracker,
+}
+
+impl DpbModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parameter_sets(&self) -> &ParameterSets {
+        &self.parameter_sets
+    }
+
+    pub fn dpb(&self) -> &Dpb<()> {
+        &self.dpb
+    }
+
+    // 处理一个 NALU; 参数集会被记录下来, 非 VCL NALU 被忽略
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        if self.parameter_sets.update(nalu)? {
+            return Ok(());
+        }
+
+        match nalu.kind() {
+            NaluKind::CodedSliceIdr | NaluKind::CodedSliceNonIdr => { },
+            _ => return Ok(()),
+        }
+
+        let header = Slice::parse_header(nalu, &self.parameter_sets)?;
+
+        if header.redundant_pic_cnt.unwrap_or(0) > 0 {
+            return Ok(());
+        }
+
+        let new_picture = match self.current {
+            Some(ref current) => first_vcl_nal_unit_of_picture(&current.header, &header),
+            None => true,
+        };
+
+        if new_picture {
+            self.finish_picture();
+            self.start_picture(&header);
+        }
+
+        let current = self.current.as_mut().expect("current picture");
+        current.header = header.clone();
+
+        let report = &mut current.report;
+        let ids = self.dpb.ref_pic_list_entries(&header, report.pic_order_cnt, &mut report.violations);
+        let dpb = &self.dpb;
+        let resolve = |ids: &[Option<(u64, PictureStructure)>]| -> Vec<Option<RefPicture>> {
+            ids.iter()
+                .map(|id| {
+                    let (id, structure) = (*id)?;
+                    dpb.get(id).map(|entry| RefPicture::from_list_entry(entry, structure))
+                })
+                .collect()
+        };
+
+        report.slices.push(SliceReport {
+            first_mb_in_slice: header.first_mb_in_slice,
+            slice_type: header.slice_type,
+            ref_pic_lists: [resolve(&ids[0]), resolve(&ids[1])],
+        });
+
+        Ok(())
+    }
+
+    // 码流结束: 完成当前图像并输出 DPB 中所有等待输出的图像
+    pub fn flush(&mut self) {
+        self.finish_picture();
+
+        let output = &mut self.output;
+        self.dpb.flush(&mut |entry| output.output(entry));
+    }
+
+    // 取出下一个 ( 按解码顺序 ) 已完成的图像的报告
+    pub fn next_report(&mut self) -> Option<PictureReport> {
+        self.reports.pop_front()
+    }
+
+    // 取出下一个 ( 按输出顺序 ) 输出的图像
+    pub fn next_output(&mut self) -> Option<OutputReport> {
+        self.output.outputs.pop_front()
+    }
+
+    // 到目前为止的输出统计
+    pub fn output_summary(&self) -> &OutputSummary {
+        &self.output.summary
+    }
+
+    fn start_picture(&mut self, header: &SliceHeader) {
+        let pps = self.parameter_sets.pps(header.pic_parameter_set_id).expect("pps has been checked by the slice header");
+        let sps = self.parameter_sets.sps(pps.seq_parameter_set_id()).expect("sps has been checked by the slice header");
+
+        self.dpb.activate(sps);
+        self.output.summary.dpb_size = self.dpb.size();
+        self.output.summary.max_num_reorder_frames = sps.vui_parameters().and_then(|vui| vui.max_num_reorder_frames);
+
+        let mut violations = vec![];
+        let prev_ref_frame_num = self.dpb.prev_ref_frame_num();
+        let gap = !header.idr_pic_flag()
+            && header.frame_num != prev_ref_frame_num
+            && header.frame_num != (prev_ref_frame_num + 1) % sps.max_frame_num();
+        if gap && !sps.gaps_in_frame_num_value_allowed_flag() {
+            violations.push(Violation::FrameNumGap { prev_ref_frame_num: prev_ref_frame_num, frame_num: header.frame_num });
+        }
+
+        let output = &mut self.output;
+        self.dpb.fill_frame_num_gap(header, &mut |entry| output.output(entry));
+        let poc = self.poc.decode(header, sps);
+
+        self.current = Some(CurrentPicture {
+            header: header.clone(),
+            report: PictureReport {
+                id: 0,
+                frame_num: header.frame_num,
+                structure: header.structure(),
+                second_field: false,
+                idr: header.idr_pic_flag(),
+                nal_ref_idc: header.nal_ref_idc,
+                pic_order_cnt: poc.value(),
+                slices: vec![],
+                marking: Marking { reference: Reference::Unused, mmco5: false, second_field: false },
+                references: vec![],
+                violations: violations,
+            },
+            poc: poc,
+        });
+    }
+
+    fn finish_picture(&mut self) {
+        let CurrentPicture { header, mut poc, mut report } = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+
+        let marking = self.dpb.mark_with_violations(&header, &mut report.violations);
+        if marking.mmco5 {
+            poc.reset();
+        }
+        self.poc.update(&header, &poc, marking.mmco5);
+
+        // 互补场对的第二个场与第一个场是同一个输出的图像
+        report.second_field = marking.second_field;
+        if !marking.second_field {
+            self.output.num_decoded += 1;
+        }
+        let output = &mut self.output;
+        report.id = self.dpb.store(&header, &marking, &poc, (), &mut |entry| output.output(entry));
+
+        // 按 no_output_of_prior_pics_flag 丢弃的图像不再输出
+        let dpb = &self.dpb;
+        self.output.pending.retain(|&(id, _)| dpb.get(id).is_some_and(|entry| entry.needed_for_output));
+        if !marking.second_field && dpb.get(report.id).is_some_and(|entry| entry.needed_for_output) {
+            self.output.pending.push((report.id, self.output.num_decoded - 1));
+        }
+
+        let num_frames = self.dpb.entries().len();
+        if num_frames > self.dpb.size() {
+            report.violations.push(Violation::DpbOverflow { num_frames: num_frames, dpb_size: self.dpb.size() });
+        }
+
+        report.marking = marking;
+        report.references = self.dpb.entries().iter()
+            .filter(|entry| entry.reference.is_reference())
+            .map(RefPicture::from)
+            .collect();
+
+        let num_ref_frames = report.references.len();
+        if num_ref_frames > self.dpb.max_num_ref_frames() {
+            report.violations.push(Violation::TooManyReferenceFrames {
+                num_ref_frames: num_ref_frames,
+                max_num_ref_frames: self.dpb.max_num_ref_frames(),
+            });
+        }
+
+        self.reports.push_back(report);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rbsp::{ RefPicListModification, MemoryManagementControlOperation };
+    use crate::slice::test::{ Writer, parameter_sets };
+
+    fn model() -> DpbModel {
+        let mut model = DpbModel::new();
+        model.parameter_sets = parameter_sets();
+
+        // max_num_ref_frames 为 3, 其余与 parameter_sets() 相同
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(3).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        model.push(&sps).unwrap();
+        model
+    }
+
+    fn idr() -> Nalu {
+        Writer::new(0x65)
+            .ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1)
+            .finish()
+    }
+
+    // 只有一个参考图像的 P slice
+    fn predicted(frame_num: u32, pic_order_cnt_lsb: u32, modification: &[(u32, u32)], operations: &[&[u32]]) -> Nalu {
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, frame_num).u(4, pic_order_cnt_lsb).u(1, 0);
+
+        writer.u(1, !modification.is_empty() as u32);
+        if !modification.is_empty() {
+            for &(idc, value) in modification {
+                writer.ue(idc).ue(value);
+            }
+            writer.ue(3);
+        }
+
+        writer.u(1, !operations.is_empty() as u32);
+        if !operations.is_empty() {
+            for operation in operations {
+                for &value in operation.iter() {
+                    writer.ue(value);
+                }
+            }
+            writer.ue(0);
+        }
+
+        writer.se(0).ue(1).finish()
+    }
+
+    fn frame_nums(pictures: &[RefPicture]) -> Vec<u32> {
+        pictures.iter().map(|picture| picture.frame_num).collect()
+    }
+
+    #[test]
+    fn test_reference_marking() {
+        let mut model = model();
+
+        model.push(&idr()).unwrap();
+        model.push(&predicted(1, 2, &[], &[])).unwrap();
+        // picNumL0NoWrap = 2 - 2 = 0
+        model.push(&predicted(2, 4, &[(0, 1)], &[])).unwrap();
+        // picNumX = 3 - 6 = -3 不存在; picNumX = 3 - 3 = 0
+        model.push(&predicted(3, 6, &[], &[&[1, 5], &[1, 2]])).unwrap();
+        // frame_num 间隙 ( 4 ), 且 LongTermPicNum 0 不存在
+        model.push(&predicted(5, 10, &[(2, 0)], &[])).unwrap();
+        model.flush();
+
+        let idr = model.next_report().unwrap();
+        assert!(idr.idr);
+        assert_eq!(idr.marking.reference, Reference::ShortTerm);
+        assert!(idr.slices[0].ref_pic_lists[0].is_empty());
+        assert_eq!(frame_nums(&idr.references), vec![0]);
+
+        let p1 = model.next_report().unwrap();
+        let list0 = &p1.slices[0].ref_pic_lists[0];
+        assert_eq!(list0.len(), 1);
+        assert_eq!(list0[0].as_ref().unwrap().id, idr.id);
+        assert!(p1.violations.is_empty());
+
+        let p2 = model.next_report().unwrap();
+        assert_eq!(p2.slices[0].ref_pic_lists[0][0].as_ref().unwrap().frame_num, 0);
+        assert_eq!(frame_nums(&p2.references), vec![0, 1, 2]);
+        assert!(p2.violations.is_empty());
+
+        let p3 = model.next_report().unwrap();
+        assert_eq!(p3.violations, vec![
+            Violation::InvalidOperation(MemoryManagementControlOperation::MarkShortTermUnused { difference_of_pic_nums_minus1: 5 }),
+        ]);
+        assert_eq!(frame_nums(&p3.references), vec![1, 2, 3]);
+
+        let p5 = model.next_report().unwrap();
+        assert_eq!(p5.violations, vec![
+            Violation::FrameNumGap { prev_ref_frame_num: 3, frame_num: 5 },
+            Violation::MissingModificationTarget { list: 0, operation: RefPicListModification::LongTermPicNum(0) },
+        ]);
+        assert_eq!(p5.slices[0].ref_pic_lists[0], vec![None]);
+        // 滑动窗口: frame_num 4 的 "non-existing" 帧替代了 1, 当前图像替代了 2
+        assert_eq!(frame_nums(&p5.references), vec![3, 4, 5]);
+        assert!(p5.references[1].non_existing);
+
+        assert!(model.next_report().is_none());
+    }
+
+    #[test]
+    fn test_output_order() {
+        let mut model = DpbModel::new();
+        model.parameter_sets = parameter_sets();
+
+        // max_num_ref_frames 为 2; VUI: max_num_reorder_frames 1, max_dec_frame_buffering 3
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(2).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 1)
+            .u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0)
+            .u(1, 1).u(1, 1).ue(0).ue(0).ue(16).ue(16).ue(1).ue(3)
+            .finish();
+        model.push(&sps).unwrap();
+
+        let disposable = |frame_num: u32, pic_order_cnt_lsb: u32| {
+            Writer::new(0x01)
+                .ue(0).ue(5).ue(0).u(4, frame_num).u(4, pic_order_cnt_lsb).u(1, 0).u(1, 0)
+                .se(0).ue(1)
+                .finish()
+        };
+
+        // 解码顺序的 PicOrderCnt: 0, 6, 4, 2 ( 非参考图像 ), 8
+        model.push(&idr()).unwrap();
+        model.push(&predicted(1, 6, &[], &[])).unwrap();
+        model.push(&predicted(2, 4, &[], &[])).unwrap();
+        model.push(&disposable(3, 2)).unwrap();
+        model.push(&predicted(3, 8, &[], &[])).unwrap();
+        model.flush();
+
+        let reports: Vec<PictureReport> = std::iter::from_fn(|| model.next_report()).collect();
+        assert!(reports.iter().all(|report| report.violations.is_empty()));
+
+        let outputs: Vec<OutputReport> = std::iter::from_fn(|| model.next_output()).collect();
+        let pic_order_cnts: Vec<i32> = outputs.iter().map(|output| output.pic_order_cnt).collect();
+        assert_eq!(pic_order_cnts, vec![0, 2, 4, 6, 8]);
+        assert_eq!(outputs[1].id, reports[3].id);
+
+        // PicOrderCnt 2 在 6 与 4 之后解码
+        let reorder_depths: Vec<usize> = outputs.iter().map(|output| output.reorder_depth).collect();
+        assert_eq!(reorder_depths, vec![0, 2, 1, 0, 0]);
+        assert_eq!(outputs[1].violations, vec![
+            Violation::ReorderDepthExceeded { reorder_depth: 2, max_num_reorder_frames: 1 },
+        ]);
+
+        // DPB 满时才输出: IDR 在存入第 4 个图像时输出
+        let output_delays: Vec<usize> = outputs.iter().map(|output| output.output_delay).collect();
+        assert_eq!(output_delays, vec![3, 1, 2, 3, 0]);
+
+        assert_eq!(model.output_summary(), &OutputSummary {
+            num_output: 5,
+            max_reorder_depth: 2,
+            max_output_delay: 3,
+            max_num_reorder_frames: Some(1),
+            dpb_size: 3,
+        });
+    }
+
+    #[test]
+    fn test_too_many_reference_frames() {
+        let mut model = model();
+
+        model.push(&idr()).unwrap();
+        // MaxLongTermFrameIdx = 2, 当前图像标记为长期参考帧
+        model.push(&predicted(1, 2, &[], &[&[4, 3], &[6, 0]])).unwrap();
+        model.push(&predicted(2, 4, &[], &[&[6, 1]])).unwrap();
+        model.push(&predicted(3, 6, &[], &[&[6, 2]])).unwrap();
+        model.flush();
+
+        let reports: Vec<PictureReport> = std::iter::from_fn(|| model.next_report()).collect();
+        assert_eq!(reports[1].marking.reference, Reference::LongTerm(0));
+        assert_eq!(frame_nums(&reports[2].references), vec![0, 1, 2]);
+        assert!(reports[2].violations.is_empty());
+        assert_eq!(reports[3].violations, vec![
+            Violation::TooManyReferenceFrames { num_ref_frames: 4, max_num_ref_frames: 3 },
+        ]);
+
+        // 长期参考帧排在短期参考帧之后
+        let list0 = &reports[3].slices[0].ref_pic_lists[0];
+        assert_eq!(list0[0].as_ref().unwrap().frame_num, 0);
+    }
+}
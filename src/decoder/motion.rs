@@ -0,0 +1,903 @@
+// 8.4.1 Derivation process for motion vector components and reference indices ( Page 137 )
+//
+// 帧宏块与场宏块 ( 场图像, MBAFF 帧 ) 均可处理。
+
+use crate::error::{ self, Error };
+use crate::macroblock::{ Macroblock, MbType, SubMbType, MbNeighbour };
+use super::picture::{ Picture, MbState, RefPicLists, RefPicId };
+use super::inter;
+
+
+// 相邻分区的 ( refIdxLXN, mvLXN ); None 表示该分区不可用。
+// 帧内宏块或未使用该列表的分区为 ( -1, 0 )
+type Neighbour = Option<(i8, [i32; 2])>;
+
+// 8.4.1.2.2: 空间直接预测在整个宏块内共用的 refIdxL0, refIdxL1 与 mvpL0, mvpL1
+#[derive(Debug, Clone, Copy)]
+struct SpatialDirect {
+    ref_idx: [i8; 2],
+    mv: [[i32; 2]; 2],
+    direct_zero_prediction: bool,
+}
+
+// 8.4.1.2.1 中的 vertMvScale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertMvScale {
+    OneToOne,
+    FrmToFld,
+    FldToFrm,
+}
+
+// co-located 分区的 refIdxCol, mvCol, refIdxCol 所指向的参考图像 ( 帧或场 ) 以及 vertMvScale
+type Colocated = (i8, [i32; 2], Option<RefPicId>, VertMvScale);
+
+
+pub(crate) struct MotionContext<'a> {
+    // 当前图像 ( 相邻宏块 )
+    pub picture: &'a Picture,
+    pub slice_num: usize,
+    pub refs: &'a RefPicLists<'a>,
+    pub direct_spatial_mv_pred_flag: bool,
+    pub direct_8x8_inference_flag: bool,
+}
+
+impl<'a> MotionContext<'a> {
+    // 推导帧间宏块的 refIdxLX, mvLX, 写入 `state`
+    pub fn derive(&self, mb: &Macroblock, state: &mut MbState) -> Result<(), Error> {
+        // 当前宏块内已推导出运动向量的 4x4 块 ( 光栅顺序 )
+        let mut decoded = [false; 16];
+        let mut spatial = None;
+
+        match mb.mb_type {
+            MbType::PSkip => {
+                let mv = self.p_skip(mb.mb_addr, state, &decoded);
+                set_motion(state, 0, 0, 0, 16, 16, 0, mv);
+            },
+            MbType::BSkip | MbType::BDirect16x16 => {
+                for mb_part_idx in 0..4 {
+                    self.direct(mb.mb_addr, state, &mut decoded, mb_part_idx, &mut spatial)?;
+                }
+            },
+            MbType::P8x8 | MbType::P8x8Ref0 | MbType::B8x8 => {
+                let sub_mb_types = match mb.sub_mb_type {
+                    Some(sub_mb_types) => sub_mb_types,
+                    None => return Err(error::malformed("8x8 macroblock without sub_mb_type")),
+                };
+
+                for mb_part_idx in 0..4 {
+                    let sub_mb_type = sub_mb_types[mb_part_idx];
+                    if sub_mb_type == SubMbType::BDirect8x8 {
+                        self.direct(mb.mb_addr, state, &mut decoded, mb_part_idx, &mut spatial)?;
+                        continue;
+                    }
+
+                    let (width, height) = sub_mb_type.sub_mb_part_size();
+                    let (width, height) = (width as i32, height as i32);
+                    let pred_mode = sub_mb_type.sub_mb_pred_mode();
+
+                    for sub_mb_part_idx in 0..sub_mb_type.num_sub_mb_part() {
+                        let x = (mb_part_idx % 2) as i32 * 8 + (sub_mb_part_idx as i32 % (8 / width)) * width;
+                        let y = (mb_part_idx / 2) as i32 * 8 + (sub_mb_part_idx as i32 / (8 / width)) * height;
+
+                        for list in 0..2 {
+                            let uses_list = if list == 0 { pred_mode.uses_l0() } else { pred_mode.uses_l1() };
+                            let ref_idx = if uses_list { mb.ref_idx[list][mb_part_idx] } else { -1 };
+                            let mut mv = [0, 0];
+                            if uses_list {
+                                let mvp = self.mvp(mb.mb_addr, state, &decoded, list, x, y, width, height, ref_idx);
+                                let mvd = mb.mvd[list][mb_part_idx][sub_mb_part_idx];
+                                mv = [mvp[0] + mvd[0], mvp[1] + mvd[1]];
+                            }
+                            set_motion(state, list, x, y, width, height, ref_idx, mv);
+                        }
+
+                        set_decoded(&mut decoded, x, y, width, height);
+                    }
+                }
+            },
+            _ => {
+                let (width, height) = mb.mb_type.mb_part_size();
+                let (width, height) = (width as i32, height as i32);
+
+                for mb_part_idx in 0..mb.mb_type.num_mb_part() {
+                    let x = (mb_part_idx as i32 % (16 / width)) * width;
+                    let y = (mb_part_idx as i32 / (16 / width)) * height;
+                    let pred_mode = match mb.mb_type.mb_part_pred_mode(mb_part_idx) {
+                        Some(pred_mode) => pred_mode,
+                        None => return Err(error::malformed("inter macroblock without prediction mode")),
+                    };
+
+                    for list in 0..2 {
+                        let uses_list = if list == 0 { pred_mode.uses_l0() } else { pred_mode.uses_l1() };
+                        let ref_idx = if uses_list { mb.ref_idx[list][mb_part_idx] } else { -1 };
+                        let mut mv = [0, 0];
+                        if uses_list {
+                            let mvp = self.mvp(mb.mb_addr, state, &decoded, list, x, y, width, height, ref_idx);
+                            let mvd = mb.mvd[list][mb_part_idx][0];
+                            mv = [mvp[0] + mvd[0], mvp[1] + mvd[1]];
+                        }
+                        set_motion(state, list, x, y, width, height, ref_idx, mv);
+                    }
+
+                    set_decoded(&mut decoded, x, y, width, height);
+                }
+            },
+        }
+
+        let mb_parity = self.mb_parity(mb.mb_addr, state);
+        for list in 0..2 {
+            for mb_part_idx in 0..4 {
+                let ref_idx = state.ref_idx[list][mb_part_idx];
+                if ref_idx < 0 {
+                    continue;
+                }
+
+                match self.refs.get_mb(list, ref_idx, mb_parity) {
+                    Some(ref_pic) => state.ref_pic[list][mb_part_idx] = Some(ref_pic.ref_pic_id()),
+                    None => return Err(error::malformed("ref_idx refers to a missing reference picture")),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // MBAFF 帧中的场宏块所在的场的奇偶 ( 按 8.4.2.1 选择参考场 ), 其他宏块为 None
+    fn mb_parity(&self, mb_addr: u32, state: &MbState) -> Option<usize> {
+        if self.picture.mbaff_frame_flag && state.field {
+            Some((mb_addr % 2) as usize)
+        } else {
+            None
+        }
+    }
+
+    // 当前场, 或 MBAFF 帧中场宏块所在的场的奇偶; 帧宏块为 None
+    fn field_parity(&self, mb_addr: u32, state: &MbState) -> Option<usize> {
+        if self.picture.field_pic_flag {
+            Some(self.picture.bottom_field_flag as usize)
+        } else {
+            self.mb_parity(mb_addr, state)
+        }
+    }
+
+    // 6.4.11.7 Derivation process for neighbouring partitions
+    // 8.4.1.3.2 Derivation process for motion data of neighbouring partitions
+    fn neighbour(&self, mb_addr: u32, state: &MbState, decoded: &[bool; 16], list: usize, xn: i32, yn: i32) -> Neighbour {
+        let (kind, addr, xw, yw) = self.picture.neighbour_location(self.slice_num, mb_addr, state.field, xn, yn, 16, 16)?;
+        let blk = (yw / 4 * 4 + xw / 4) as usize;
+        let part = (yw / 8 * 2 + xw / 8) as usize;
+
+        let n = if kind == MbNeighbour::Curr {
+            // 尚未解码的分区不可用
+            if !decoded[blk] {
+                return None;
+            }
+            state
+        } else {
+            let n = self.picture.mb(addr)?;
+            if n.slice_num != self.slice_num {
+                return None;
+            }
+            n
+        };
+
+        let (mut ref_idx, mut mv) = (n.ref_idx[list][part], n.mv[list][blk]);
+
+        // MBAFF 帧中当前宏块为场宏块而相邻宏块为帧宏块时 ( 8-214, 8-215 ), 以及相反的情况 ( 8-216, 8-217 )
+        if self.picture.mbaff_frame_flag && ref_idx >= 0 {
+            if state.field && !n.field {
+                mv[1] /= 2;
+                ref_idx *= 2;
+            } else if !state.field && n.field {
+                mv[1] *= 2;
+                ref_idx >>= 1;
+            }
+        }
+
+        Some((ref_idx, mv))
+    }
+
+    // 8.4.1.3 Derivation process for luma motion vector prediction ( Page 143 )
+    //
+    // 分区位于宏块内的 ( x, y ); `width` 为 predPartWidth, ( width, height ) 为 16x8 或 8x16 时使用方向性预测
+    #[allow(clippy::too_many_arguments)]
+    fn mvp(&self,
+           mb_addr: u32,
+           state: &MbState,
+           decoded: &[bool; 16],
+           list: usize,
+           x: i32,
+           y: i32,
+           width: i32,
+           height: i32,
+           ref_idx: i8) -> [i32; 2] {
+        let a = self.neighbour(mb_addr, state, decoded, list, x - 1, y);
+        let mut b = self.neighbour(mb_addr, state, decoded, list, x, y - 1);
+        let mut c = self.neighbour(mb_addr, state, decoded, list, x + width, y - 1);
+        if c.is_none() {
+            c = self.neighbour(mb_addr, state, decoded, list, x - 1, y - 1);
+        }
+
+        let unavailable = (-1, [0, 0]);
+
+        // 8.4.1.3 ( 8-203 ~ 8-206 ): 16x8 与 8x16 分区的方向性预测
+        if (width, height) == (16, 8) || (width, height) == (8, 16) {
+            let n = match (width, x, y) {
+                (16, _, 0) => b,
+                (16, _, _) => a,
+                (_, 0, _) => a,
+                _ => c,
+            };
+            let (ref_idx_n, mv_n) = n.unwrap_or(unavailable);
+            if ref_idx_n == ref_idx {
+                return mv_n;
+            }
+        }
+
+        // 8.4.1.3.1 Derivation process for median luma motion vector prediction
+        if b.is_none() && c.is_none() && a.is_some() {
+            b = a;
+            c = a;
+        }
+
+        let (ref_idx_a, mv_a) = a.unwrap_or(unavailable);
+        let (ref_idx_b, mv_b) = b.unwrap_or(unavailable);
+        let (ref_idx_c, mv_c) = c.unwrap_or(unavailable);
+
+        let matches = [ref_idx_a == ref_idx, ref_idx_b == ref_idx, ref_idx_c == ref_idx];
+        match matches {
+            [true, false, false] => mv_a,
+            [false, true, false] => mv_b,
+            [false, false, true] => mv_c,
+            _ => [median(mv_a[0], mv_b[0], mv_c[0]), median(mv_a[1], mv_b[1], mv_c[1])],
+        }
+    }
+
+    // 8.4.1.1 Derivation process for luma motion vectors for skipped macroblocks in P and SP slices
+    fn p_skip(&self, mb_addr: u32, state: &MbState, decoded: &[bool; 16]) -> [i32; 2] {
+        let a = self.neighbour(mb_addr, state, decoded, 0, -1, 0);
+        let b = self.neighbour(mb_addr, state, decoded, 0, 0, -1);
+
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                if a == (0, [0, 0]) || b == (0, [0, 0]) {
+                    [0, 0]
+                } else {
+                    self.mvp(mb_addr, state, decoded, 0, 0, 0, 16, 16, 0)
+                }
+            },
+            _ => [0, 0],
+        }
+    }
+
+    // 8.4.1.2 Derivation process for luma motion vectors for B_Skip, B_Direct_16x16, and B_Direct_8x8
+    fn direct(&self,
+              mb_addr: u32,
+              state: &mut MbState,
+              decoded: &mut [bool; 16],
+              mb_part_idx: usize,
+              spatial: &mut Option<SpatialDirect>) -> Result<(), Error> {
+        let x0 = (mb_part_idx % 2) as i32 * 8;
+        let y0 = (mb_part_idx / 2) as i32 * 8;
+        let mb_parity = self.mb_parity(mb_addr, state);
+
+        let col_pic = match self.refs.get(1, 0) {
+            Some(col_pic) => *col_pic,
+            None => return Err(error::malformed("direct prediction without RefPicList1[ 0 ]")),
+        };
+
+        if self.direct_spatial_mv_pred_flag {
+            let spatial = match *spatial {
+                Some(spatial) => spatial,
+                None => {
+                    let value = self.spatial_direct(mb_addr, state, decoded);
+                    *spatial = Some(value);
+                    value
+                },
+            };
+
+            // 8.4.1.2.2 Derivation process for spatial direct luma motion vector and reference index prediction
+            for sub_mb_part_idx in 0..4 {
+                let x = x0 + (sub_mb_part_idx % 2) * 4;
+                let y = y0 + (sub_mb_part_idx / 2) * 4;
+                let (ref_idx_col, mv_col, _, _) = self.colocated(mb_addr, state, x, y)?;
+
+                let col_zero_flag = !col_pic.long_term
+                    && ref_idx_col == 0
+                    && mv_col[0] >= -1 && mv_col[0] <= 1
+                    && mv_col[1] >= -1 && mv_col[1] <= 1;
+
+                for list in 0..2 {
+                    let ref_idx = spatial.ref_idx[list];
+                    let mv = if spatial.direct_zero_prediction || ref_idx < 0 || (ref_idx == 0 && col_zero_flag) {
+                        [0, 0]
+                    } else {
+                        spatial.mv[list]
+                    };
+                    set_motion(state, list, x, y, 4, 4, ref_idx, mv);
+                }
+            }
+        } else {
+            // 8.4.1.2.3 Derivation process for temporal direct luma motion vector and reference index prediction
+            for sub_mb_part_idx in 0..4 {
+                let x = x0 + (sub_mb_part_idx % 2) * 4;
+                let y = y0 + (sub_mb_part_idx / 2) * 4;
+                let (ref_idx_col, mut mv_col, ref_pic_col, vert_mv_scale) = self.colocated(mb_addr, state, x, y)?;
+
+                // 8-191, 8-192
+                match vert_mv_scale {
+                    VertMvScale::FrmToFld => mv_col[1] /= 2,
+                    VertMvScale::FldToFrm => mv_col[1] *= 2,
+                    VertMvScale::OneToOne => {},
+                }
+
+                // MapColToList0( refIdxCol ): 当前为场 ( 或场宏块 ) 时引用 refPicCol 所在帧中与当前奇偶相同的场
+                // ( vertMvScale 为 Frm_To_Fld ) 或 refPicCol 本身; 当前为帧宏块时引用 refPicCol 所在的帧
+                let ref_idx_l0 = if ref_idx_col < 0 {
+                    0
+                } else {
+                    let target = match (ref_pic_col, self.field_parity(mb_addr, state)) {
+                        (Some((id, parity)), Some(curr)) => (id, Some(parity.unwrap_or(curr))),
+                        (Some((id, _)), None) => (id, None),
+                        (None, _) => return Err(error::malformed("co-located partition without reference picture")),
+                    };
+                    let position = (0..self.refs.len_mb(0, mb_parity))
+                        .position(|i| self.refs.get_mb(0, i as i8, mb_parity).map(|ref_pic| ref_pic.ref_pic_id()) == Some(target));
+                    match position {
+                        Some(position) => position as i8,
+                        None => return Err(error::malformed("temporal direct reference picture is not in RefPicList0")),
+                    }
+                };
+
+                let (pic0, pic1) = match (self.refs.get_mb(0, ref_idx_l0, mb_parity), self.refs.get_mb(1, 0, mb_parity)) {
+                    (Some(pic0), Some(pic1)) => (pic0, pic1),
+                    _ => return Err(error::malformed("direct prediction without RefPicList0 entry")),
+                };
+
+                let (mv_l0, mv_l1) = if pic0.long_term || pic1.poc - pic0.poc == 0 {
+                    (mv_col, [0, 0])
+                } else {
+                    let curr_poc = self.refs.curr_poc(mb_parity);
+                    let dist_scale_factor = inter::dist_scale_factor(curr_poc, pic0.poc, pic1.poc);
+                    let mv_l0 = [
+                        (dist_scale_factor * mv_col[0] + 128) >> 8,
+                        (dist_scale_factor * mv_col[1] + 128) >> 8,
+                    ];
+                    (mv_l0, [mv_l0[0] - mv_col[0], mv_l0[1] - mv_col[1]])
+                };
+
+                set_motion(state, 0, x, y, 4, 4, ref_idx_l0, mv_l0);
+                set_motion(state, 1, x, y, 4, 4, 0, mv_l1);
+            }
+        }
+
+        set_decoded(decoded, x0, y0, 8, 8);
+
+        Ok(())
+    }
+
+    // 8.4.1.2.2 中 refIdxL0, refIdxL1 与 directZeroPredictionFlag 的推导
+    fn spatial_direct(&self, mb_addr: u32, state: &MbState, decoded: &[bool; 16]) -> SpatialDirect {
+        // MinPositive( x, y )
+        let min_positive = |x: i8, y: i8| if x >= 0 && y >= 0 { x.min(y) } else { x.max(y) };
+
+        let mut ref_idx = [-1i8; 2];
+        for list in 0..2 {
+            let a = self.neighbour(mb_addr, state, decoded, list, -1, 0);
+            let b = self.neighbour(mb_addr, state, decoded, list, 0, -1);
+            let mut c = self.neighbour(mb_addr, state, decoded, list, 16, -1);
+            if c.is_none() {
+                c = self.neighbour(mb_addr, state, decoded, list, -1, -1);
+            }
+
+            let ref_idx_n = |n: Neighbour| n.map(|(ref_idx, _)| ref_idx).unwrap_or(-1);
+            ref_idx[list] = min_positive(ref_idx_n(a), min_positive(ref_idx_n(b), ref_idx_n(c)));
+        }
+
+        let direct_zero_prediction = ref_idx[0] < 0 && ref_idx[1] < 0;
+        if direct_zero_prediction {
+            ref_idx = [0, 0];
+        }
+
+        let mut mv = [[0; 2]; 2];
+        for list in 0..2 {
+            if !direct_zero_prediction && ref_idx[list] >= 0 {
+                mv[list] = self.mvp(mb_addr, state, decoded, list, 0, 0, 16, 16, ref_idx[list]);
+            }
+        }
+
+        SpatialDirect {
+            ref_idx: ref_idx,
+            mv: mv,
+            direct_zero_prediction: direct_zero_prediction,
+        }
+    }
+
+    // 8.4.1.2.1 Derivation process for the co-located 4x4 sub-macroblock partitions ( Page 139 )
+    //
+    // colPic 按 Table 8-6 选择, mbAddrCol, yM 与 vertMvScale 按 Table 8-8 推导
+    fn colocated(&self, mb_addr: u32, state: &MbState, x: i32, y: i32) -> Result<Colocated, Error> {
+        let col_pic = match self.refs.get(1, 0) {
+            Some(col_pic) => col_pic,
+            None => return Err(error::malformed("direct prediction without RefPicList1[ 0 ]")),
+        };
+        let curr = self.picture;
+        let col = col_pic.picture;
+        let width = curr.width_in_mbs;
+
+        // direct_8x8_inference_flag 为 1 时使用 8x8 块的角上的 4x4 块
+        let (x_col, y_col) = if self.direct_8x8_inference_flag {
+            (if x < 8 { 0 } else { 12 }, if y < 8 { 0 } else { 12 })
+        } else {
+            (x, y)
+        };
+
+        // Table 8-6: 当前为帧宏块而 RefPicList1[ 0 ] 为互补场对时, 选择 POC 与当前图像 ( 或当前宏块所在的场 ) 较近的场
+        let nearest_field = || match self.mb_parity(mb_addr, state) {
+            Some(parity) => parity,
+            None => {
+                let top = (col_pic.field_poc[0] - self.refs.poc).abs();
+                let bottom = (col_pic.field_poc[1] - self.refs.poc).abs();
+                if top < bottom { 0 } else { 1 }
+            },
+        };
+
+        let field_mb = |addr: u32| col.mbs.get(addr as usize).and_then(|mb| mb.as_ref()).map(|mb| mb.field);
+        let (mbs, mb_addr_col, y_m, vert_mv_scale) = if curr.field_pic_flag {
+            if col.field_pic_flag {
+                let parity = col_pic.parity.unwrap_or(curr.bottom_field_flag as usize);
+                (col.field_mbs(parity), mb_addr, y_col, VertMvScale::OneToOne)
+            } else if !col.mbaff_frame_flag {
+                // mbAddrCol1
+                let addr = 2 * width * (mb_addr / width) + mb_addr % width + width * (y_col / 8) as u32;
+                (&col.mbs[..], addr, (2 * y_col) % 16, VertMvScale::FrmToFld)
+            } else if field_mb(2 * mb_addr) == Some(true) {
+                // mbAddrCol3
+                (&col.mbs[..], 2 * mb_addr + curr.bottom_field_flag as u32, y_col, VertMvScale::OneToOne)
+            } else {
+                // mbAddrCol2
+                (&col.mbs[..], 2 * mb_addr + (y_col / 8) as u32, (2 * y_col) % 16, VertMvScale::FrmToFld)
+            }
+        } else if col.field_pic_flag {
+            let mbs = col.field_mbs(nearest_field());
+            if !curr.mbaff_frame_flag {
+                // mbAddrCol4
+                let addr = width * (mb_addr / (2 * width)) + mb_addr % width;
+                (mbs, addr, 8 * ((mb_addr / width) % 2) as i32 + 4 * (y_col / 8), VertMvScale::FldToFrm)
+            } else if !state.field {
+                // mbAddrCol5
+                (mbs, mb_addr / 2, 8 * (mb_addr % 2) as i32 + 4 * (y_col / 8), VertMvScale::FldToFrm)
+            } else {
+                (mbs, mb_addr / 2, y_col, VertMvScale::OneToOne)
+            }
+        } else if curr.mbaff_frame_flag != col.mbaff_frame_flag {
+            return Err(error::malformed("co-located frame differs in MbaffFrameFlag"));
+        } else if !curr.mbaff_frame_flag {
+            (&col.mbs[..], mb_addr, y_col, VertMvScale::OneToOne)
+        } else {
+            match (state.field, field_mb(mb_addr)) {
+                // mbAddrCol6
+                (false, Some(true)) => {
+                    let addr = 2 * (mb_addr / 2) + nearest_field() as u32;
+                    (&col.mbs[..], addr, 8 * (mb_addr % 2) as i32 + 4 * (y_col / 8), VertMvScale::FldToFrm)
+                },
+                // mbAddrCol7
+                (true, Some(false)) => {
+                    let addr = 2 * (mb_addr / 2) + (y_col / 8) as u32;
+                    (&col.mbs[..], addr, (2 * y_col) % 16, VertMvScale::FrmToFld)
+                },
+                _ => (&col.mbs[..], mb_addr, y_col, VertMvScale::OneToOne),
+            }
+        };
+
+        let col_mb = match mbs.get(mb_addr_col as usize) {
+            Some(Some(col_mb)) => col_mb,
+            _ => return Err(error::malformed("co-located macroblock is missing")),
+        };
+
+        if col_mb.mb_type.is_intra() {
+            return Ok((-1, [0, 0], None, vert_mv_scale));
+        }
+
+        let blk = (y_m / 4 * 4 + x_col / 4) as usize;
+        let part = (y_m / 8 * 2 + x_col / 8) as usize;
+        let list = if col_mb.ref_idx[0][part] >= 0 { 0 } else { 1 };
+        Ok((col_mb.ref_idx[list][part], col_mb.mv[list][blk], col_mb.ref_pic[list][part], vert_mv_scale))
+    }
+}
+
+
+fn median(a: i32, b: i32, c: i32) -> i32 {
+    a + b + c - a.min(b).min(c) - a.max(b).max(c)
+}
+
+// 记录位于 ( x, y ), 大小为 width x height 的分区的运动数据
+#[allow(clippy::too_many_arguments)]
+fn set_motion(state: &mut MbState, list: usize, x: i32, y: i32, width: i32, height: i32, ref_idx: i8, mv: [i32; 2]) {
+    let mv = if ref_idx >= 0 { mv } else { [0, 0] };
+
+    for j in (y..y + height).step_by(4) {
+        for i in (x..x + width).step_by(4) {
+            state.mv[list][(j / 4 * 4 + i / 4) as usize] = mv;
+            state.ref_idx[list][(j / 8 * 2 + i / 8) as usize] = ref_idx;
+        }
+    }
+}
+
+fn set_decoded(decoded: &mut [bool; 16], x: i32, y: i32, width: i32, height: i32) {
+    for j in (y..y + height).step_by(4) {
+        for i in (x..x + width).step_by(4) {
+            decoded[(j / 4 * 4 + i / 4) as usize] = true;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decoder::picture::RefPic;
+    use crate::rbsp::ParameterSets;
+    use crate::slice::test::{ Writer, parameter_sets };
+
+    fn context<'a>(picture: &'a Picture, refs: &'a RefPicLists<'a>) -> MotionContext<'a> {
+        MotionContext {
+            picture: picture,
+            slice_num: 0,
+            refs: refs,
+            direct_spatial_mv_pred_flag: true,
+            direct_8x8_inference_flag: true,
+        }
+    }
+
+    fn inter_state(ref_idx: i8, mv: [i32; 2]) -> MbState {
+        let mut state = MbState::new(0, MbType::P16x16, false, 26);
+        set_motion(&mut state, 0, 0, 0, 16, 16, ref_idx, mv);
+        state
+    }
+
+    #[test]
+    fn test_median_prediction() {
+        let parameter_sets = parameter_sets();
+        let sps = parameter_sets.sps(0).unwrap();
+        // 2x1 个宏块
+        let mut picture = Picture::new(sps);
+        let refs = RefPicLists::default();
+
+        let state = MbState::new(0, MbType::P16x16, false, 26);
+        let decoded = [false; 16];
+
+        {
+            let context = context(&picture, &refs);
+            // 没有可用的相邻宏块
+            assert_eq!(context.p_skip(0, &state, &decoded), [0, 0]);
+            assert_eq!(context.mvp(0, &state, &decoded, 0, 0, 0, 16, 16, 0), [0, 0]);
+        }
+
+        // 只有 A 可用: mvLXB = mvLXC = mvLXA
+        picture.mbs[0] = Some(inter_state(1, [4, -8]));
+        let context = context(&picture, &refs);
+        assert_eq!(context.mvp(1, &state, &decoded, 0, 0, 0, 16, 16, 0), [4, -8]);
+        assert_eq!(context.mvp(1, &state, &decoded, 0, 0, 0, 16, 16, 1), [4, -8]);
+        // B 不可用: P_Skip 的运动向量为 0
+        assert_eq!(context.p_skip(1, &state, &decoded), [0, 0]);
+
+        // 当前宏块内: 8x16 的第二个分区, C 不可用 ( 位于右侧宏块 ), 使用 D ( 同样不可用 ), 于是只有 A 可用
+        let mut state = MbState::new(0, MbType::P8x16, false, 26);
+        let mut decoded = [false; 16];
+        set_motion(&mut state, 0, 0, 0, 8, 16, 0, [6, 2]);
+        set_decoded(&mut decoded, 0, 0, 8, 16);
+        assert_eq!(context.mvp(1, &state, &decoded, 0, 8, 0, 8, 16, 1), [6, 2]);
+        // 第二个分区的 refIdx 与 A 相同时方向性预测直接使用 mvLXA ( 8x16 的第二个分区使用 C, 不可用 )
+        assert_eq!(context.mvp(1, &state, &decoded, 0, 8, 0, 8, 16, 0), [6, 2]);
+        // 16x8 的第二个分区: A ( 左侧宏块 ) 的 refIdx 不同, 只有 B 的 refIdx 相同
+        assert_eq!(context.mvp(1, &state, &decoded, 0, 0, 8, 16, 8, 0), [6, 2]);
+
+        assert_eq!(median(3, -1, 7), 3);
+        assert_eq!(median(5, 5, -2), 5);
+    }
+
+    // width_in_mbs x height_in_mbs 个宏块的 Baseline 图像
+    fn picture(width_in_mbs: u32, height_in_mbs: u32) -> Picture {
+        let mut parameter_sets = ParameterSets::new();
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(width_in_mbs - 1).ue(height_in_mbs - 1)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        assert!(parameter_sets.update(&sps).unwrap());
+        Picture::new(parameter_sets.sps(0).unwrap())
+    }
+
+    fn ref_pic(id: u64, poc: i32, picture: &Picture) -> Option<RefPic<'_>> {
+        Some(RefPic { id: id, parity: None, poc: poc, field_poc: [poc, poc], long_term: false, picture: picture })
+    }
+
+    // 每个 8x8 块使用 ( refIdxL0, refIdxL1, mvL0, mvL1 ) 的帧间宏块, 未使用的列表 refIdx 为 -1
+    fn partitions(parts: [(i8, i8, [i32; 2], [i32; 2]); 4]) -> MbState {
+        let mut state = MbState::new(0, MbType::B8x8, false, 26);
+        for (part, &(ref_idx_l0, ref_idx_l1, mv_l0, mv_l1)) in parts.iter().enumerate() {
+            let (x, y) = ((part % 2) as i32 * 8, (part / 2) as i32 * 8);
+            set_motion(&mut state, 0, x, y, 8, 8, ref_idx_l0, mv_l0);
+            set_motion(&mut state, 1, x, y, 8, 8, ref_idx_l1, mv_l1);
+        }
+        state
+    }
+
+    #[test]
+    fn test_colocated() {
+        let picture = picture(2, 1);
+        let mut col_picture = picture.clone();
+
+        // 宏块 0: 4x4 块 k 的 mvL0 为 ( k, -k ); 8x8 块 1 只使用 L1
+        let mut col = partitions([
+            (0, -1, [0, 0], [0, 0]),
+            (-1, 1, [0, 0], [0, 0]),
+            (2, 0, [0, 0], [0, 0]),
+            (1, -1, [0, 0], [0, 0]),
+        ]);
+        for blk in 0..16 {
+            col.mv[0][blk] = [blk as i32, -(blk as i32)];
+            col.mv[1][blk] = [100 + blk as i32, 0];
+        }
+        col.ref_pic = [[Some((20, None)), None, Some((22, None)), Some((21, None))], [None, Some((23, None)), Some((24, None)), None]];
+        col_picture.mbs[0] = Some(col);
+        col_picture.mbs[1] = Some(MbState::new(0, MbType::I16x16 {
+            intra_16x16_pred_mode: 0,
+            coded_block_pattern_chroma: 0,
+            coded_block_pattern_luma: 0,
+        }, false, 26));
+
+        let mut refs = RefPicLists::default();
+        refs.lists[1].push(ref_pic(30, 8, &col_picture));
+        let mut context = context(&picture, &refs);
+        let frame_mb = MbState::new(0, MbType::BSkip, false, 26);
+
+        // direct_8x8_inference_flag 为 1: 使用 8x8 块角上的 4x4 块 ( 0, 3, 12, 15 )
+        assert_eq!(context.colocated(0, &frame_mb, 4, 4).unwrap(), (0, [0, 0], Some((20, None)), VertMvScale::OneToOne));
+        assert_eq!(context.colocated(0, &frame_mb, 8, 4).unwrap(), (1, [103, 0], Some((23, None)), VertMvScale::OneToOne));
+        assert_eq!(context.colocated(0, &frame_mb, 4, 8).unwrap(), (2, [12, -12], Some((22, None)), VertMvScale::OneToOne));
+        assert_eq!(context.colocated(0, &frame_mb, 8, 8).unwrap(), (1, [15, -15], Some((21, None)), VertMvScale::OneToOne));
+
+        // direct_8x8_inference_flag 为 0: 与当前 4x4 块位置相同; predFlagL0Col 为 0 时使用 L1 的运动数据
+        context.direct_8x8_inference_flag = false;
+        assert_eq!(context.colocated(0, &frame_mb, 4, 4).unwrap(), (0, [5, -5], Some((20, None)), VertMvScale::OneToOne));
+        assert_eq!(context.colocated(0, &frame_mb, 12, 4).unwrap(), (1, [107, 0], Some((23, None)), VertMvScale::OneToOne));
+        assert_eq!(context.colocated(0, &frame_mb, 4, 12).unwrap(), (2, [13, -13], Some((22, None)), VertMvScale::OneToOne));
+
+        // 帧内的 co-located 宏块: refIdxCol 为 -1, mvCol 为 0
+        assert_eq!(context.colocated(1, &frame_mb, 0, 0).unwrap(), (-1, [0, 0], None, VertMvScale::OneToOne));
+
+        let refs = RefPicLists::default();
+        assert!(self::context(&picture, &refs).colocated(0, &frame_mb, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_direct_spatial() {
+        // 2x2 个宏块, 当前为宏块 3: A 为宏块 2, B 为宏块 1, C 不可用, 使用 D ( 宏块 0 )
+        let mut picture = picture(2, 2);
+        picture.mbs[2] = Some(partitions([(1, -1, [4, 4], [0, 0]); 4]));
+        picture.mbs[1] = Some(partitions([(0, 2, [-8, 2], [6, 6]); 4]));
+        picture.mbs[0] = Some(partitions([(2, 0, [20, 20], [-2, 10]); 4]));
+
+        // co-located 宏块: 8x8 块 0 与 3 满足 colZeroFlag; 8x8 块 1 的 mvCol 超出 ±1, 8x8 块 2 的 refIdxCol 不为 0
+        let mut col_picture = picture.clone();
+        col_picture.mbs[3] = Some(partitions([
+            (0, -1, [1, -1], [0, 0]),
+            (0, -1, [2, 0], [0, 0]),
+            (1, -1, [0, 0], [0, 0]),
+            (0, -1, [0, 1], [0, 0]),
+        ]));
+
+        let l0 = picture.clone();
+        let mut refs = RefPicLists::default();
+        refs.poc = 4;
+        refs.lists[0] = vec![ref_pic(10, 0, &l0), ref_pic(11, 2, &l0), ref_pic(12, -2, &l0)];
+        refs.lists[1] = vec![ref_pic(20, 8, &col_picture), ref_pic(21, 10, &l0), ref_pic(22, 12, &l0)];
+
+        let mb = Macroblock::new(3, MbType::BSkip);
+        let mut state = MbState::new(0, MbType::BSkip, false, 26);
+        context(&picture, &refs).derive(&mb, &mut state).unwrap();
+
+        // refIdxL0 = MinPositive( 1, MinPositive( 0, 2 ) ) = 0, 只有 B 的 refIdxL0 为 0: mvpL0 = mvL0B
+        // refIdxL1 = MinPositive( -1, MinPositive( 2, 0 ) ) = 0, 只有 C ( D ) 的 refIdxL1 为 0: mvpL1 = mvL1D
+        assert_eq!(state.ref_idx, [[0; 4], [0; 4]]);
+        assert_eq!(state.mv[0][0], [0, 0]);
+        assert_eq!(state.mv[1][0], [0, 0]);
+        assert_eq!(state.mv[0][3], [-8, 2]);
+        assert_eq!(state.mv[1][3], [-2, 10]);
+        assert_eq!(state.mv[0][8], [-8, 2]);
+        assert_eq!(state.mv[1][15], [0, 0]);
+        assert_eq!(state.ref_pic, [[Some((10, None)); 4], [Some((20, None)); 4]]);
+
+        // RefPicList1[ 0 ] 为长期参考图像时 colZeroFlag 为 0
+        refs.lists[1][0].as_mut().unwrap().long_term = true;
+        let mut state = MbState::new(0, MbType::BSkip, false, 26);
+        context(&picture, &refs).derive(&mb, &mut state).unwrap();
+        assert_eq!(state.mv[0], [[-8, 2]; 16]);
+        assert_eq!(state.mv[1], [[-2, 10]; 16]);
+
+        // 没有可用的相邻宏块: directZeroPredictionFlag 为 1, refIdxL0 = refIdxL1 = 0, 运动向量为 0
+        let mb = Macroblock::new(0, MbType::BDirect16x16);
+        let mut state = MbState::new(0, MbType::BDirect16x16, false, 26);
+        let picture = self::picture(2, 2);
+        context(&picture, &refs).derive(&mb, &mut state).unwrap();
+        assert_eq!(state.ref_idx, [[0; 4], [0; 4]]);
+        assert_eq!(state.mv, [[[0, 0]; 16]; 2]);
+    }
+
+    #[test]
+    fn test_direct_temporal() {
+        let picture = picture(2, 1);
+
+        // co-located 宏块 0: 8x8 块 1 只使用 L1; refIdxCol 指向的图像 ( id ) 映射到 RefPicList0 中的位置
+        let mut col_picture = picture.clone();
+        let mut col = partitions([
+            (0, -1, [16, -8], [0, 0]),
+            (-1, 0, [0, 0], [-12, 4]),
+            (1, -1, [40, 0], [0, 0]),
+            (0, -1, [3, 3], [0, 0]),
+        ]);
+        col.ref_pic = [[Some((11, None)), None, Some((10, None)), Some((11, None))], [None, Some((10, None)), None, None]];
+        col_picture.mbs[0] = Some(col);
+        col_picture.mbs[1] = Some(MbState::new(0, MbType::I16x16 {
+            intra_16x16_pred_mode: 0,
+            coded_block_pattern_chroma: 0,
+            coded_block_pattern_luma: 0,
+        }, false, 26));
+
+        let l0 = picture.clone();
+        let mut refs = RefPicLists::default();
+        refs.poc = 4;
+        refs.lists[0] = vec![ref_pic(10, 0, &l0), ref_pic(11, 2, &l0)];
+        refs.lists[1] = vec![ref_pic(12, 8, &col_picture)];
+
+        let derive = |refs: &RefPicLists, mb_addr: u32| -> MbState {
+            let mut context = context(&picture, refs);
+            context.direct_spatial_mv_pred_flag = false;
+            let mut state = MbState::new(0, MbType::BSkip, false, 26);
+            context.derive(&Macroblock::new(mb_addr, MbType::BSkip), &mut state).unwrap();
+            state
+        };
+
+        // 8x8 块 0, 3: refIdxL0 = 1, tb = 2, td = 6, tx = 16387 / 6 = 2731, DistScaleFactor = 5494 >> 6 = 85
+        // 8x8 块 1, 2: refIdxL0 = 0, tb = 4, td = 8, tx = 2048, DistScaleFactor = 128
+        let state = derive(&refs, 0);
+        assert_eq!(state.ref_idx, [[1, 0, 0, 1], [0; 4]]);
+        // ( 85 * 16 + 128 ) >> 8 = 5, ( 85 * -8 + 128 ) >> 8 = -3, mvL1 = mvL0 - mvCol
+        assert_eq!((state.mv[0][0], state.mv[1][0]), ([5, -3], [-11, 5]));
+        // ( 128 * -12 + 128 ) >> 8 = -6, ( 128 * 4 + 128 ) >> 8 = 2
+        assert_eq!((state.mv[0][2], state.mv[1][2]), ([-6, 2], [6, -2]));
+        assert_eq!((state.mv[0][8], state.mv[1][8]), ([20, 0], [-20, 0]));
+        assert_eq!((state.mv[0][15], state.mv[1][15]), ([1, 1], [-2, -2]));
+        assert_eq!(state.ref_pic, [[Some((11, None)), Some((10, None)), Some((10, None)), Some((11, None))], [Some((12, None)); 4]]);
+
+        // 帧内的 co-located 宏块: refIdxL0 = 0, 运动向量为 0
+        let state = derive(&refs, 1);
+        assert_eq!(state.ref_idx, [[0; 4], [0; 4]]);
+        assert_eq!(state.mv, [[[0, 0]; 16]; 2]);
+
+        // tb = 40, td = 8: DistScaleFactor = 1280 被限制为 1023, ( 1023 * 40 + 128 ) >> 8 = 160
+        refs.poc = 40;
+        let state = derive(&refs, 0);
+        assert_eq!((state.mv[0][8], state.mv[1][8]), ([160, 0], [120, 0]));
+
+        // RefPicList0[ refIdxL0 ] 为长期参考图像: mvL0 = mvCol, mvL1 = 0
+        refs.poc = 4;
+        refs.lists[0][1].as_mut().unwrap().long_term = true;
+        let state = derive(&refs, 0);
+        assert_eq!((state.mv[0][0], state.mv[1][0]), ([16, -8], [0, 0]));
+        assert_eq!((state.mv[0][8], state.mv[1][8]), ([20, 0], [-20, 0]));
+
+        // refIdxCol 指向的图像不在 RefPicList0 中
+        refs.lists[0].truncate(1);
+        let mut context = context(&picture, &refs);
+        context.direct_spatial_mv_pred_flag = false;
+        let mut state = MbState::new(0, MbType::BSkip, false, 26);
+        assert!(context.derive(&Macroblock::new(0, MbType::BSkip), &mut state).is_err());
+    }
+
+    #[test]
+    fn test_mbaff_neighbour() {
+        // 2x2 个宏块 ( 一行宏块对 ): 当前宏块 2 为右边宏块对的顶部宏块, A 为左边的宏块对 ( 宏块 0, 1 )
+        let mut picture = picture(2, 2);
+        picture.mbaff_frame_flag = true;
+        picture.mbs[0] = Some(inter_state(1, [4, 6]));
+        picture.mbs[1] = Some(inter_state(0, [2, 2]));
+        let refs = RefPicLists::default();
+        let decoded = [false; 16];
+
+        // 当前为场宏块, A 为帧宏块对: yN < 8 时为顶部宏块, 否则为底部宏块; refIdxA * 2, mvA[ 1 ] / 2
+        let mut state = MbState::new(0, MbType::P16x16, false, 26);
+        state.field = true;
+        let context = context(&picture, &refs);
+        assert_eq!(context.neighbour(2, &state, &decoded, 0, -1, 0), Some((2, [4, 3])));
+        assert_eq!(context.neighbour(2, &state, &decoded, 0, -1, 8), Some((0, [2, 1])));
+
+        // 当前为帧宏块, A 为场宏块对: 顶部宏块的 yM = yN >> 1; refIdxA >> 1, mvA[ 1 ] * 2
+        let mut field_picture = picture.clone();
+        for addr in 0..2 {
+            let mut state = inter_state(3, [4, 6]);
+            state.field = true;
+            field_picture.mbs[addr] = Some(state);
+        }
+        let state = MbState::new(0, MbType::P16x16, false, 26);
+        assert_eq!(self::context(&field_picture, &refs).neighbour(2, &state, &decoded, 0, -1, 0), Some((1, [4, 12])));
+
+        // 相同类型的宏块对之间不换算
+        assert_eq!(context.neighbour(2, &state, &decoded, 0, -1, 0), Some((1, [4, 6])));
+    }
+
+    #[test]
+    fn test_field_colocated() {
+        // 当前为帧 ( 非 MBAFF ), colPic 为互补场对: 场宏块按场存放 ( 宏块 0, 1 为顶场, 2, 3 为底场 )
+        let picture = picture(2, 2);
+        let mut col_picture = picture.clone();
+        col_picture.field_pic_flag = true;
+        for (addr, id, offset) in [(0, 40, 0), (2, 41, 100)].iter().cloned() {
+            let mut col = partitions([(0, -1, [0, 0], [0, 0]); 4]);
+            for blk in 0..16 {
+                col.mv[0][blk] = [offset + blk as i32, -(blk as i32)];
+            }
+            col.ref_pic = [[Some((id, Some(addr / 2))); 4], [None; 4]];
+            col_picture.mbs[addr] = Some(col);
+        }
+
+        let l0 = picture.clone();
+        let mut refs = RefPicLists {
+            poc: 4,
+            field_poc: [4, 5],
+            lists: [
+                vec![ref_pic(40, 0, &l0), ref_pic(41, 2, &l0)],
+                vec![Some(RefPic { field_poc: [8, 9], ..ref_pic(30, 8, &col_picture).unwrap() })],
+            ],
+        };
+
+        // 宏块 2 ( 第二行 ): mbAddrCol4 = 0, yM = 8 + 4 * ( yCol / 8 ); 顶场的 POC 与当前图像更近
+        let frame_mb = MbState::new(0, MbType::BSkip, false, 26);
+        let context = context(&picture, &refs);
+        assert_eq!(context.colocated(2, &frame_mb, 0, 0).unwrap(), (0, [8, -8], Some((40, Some(0))), VertMvScale::FldToFrm));
+        assert_eq!(context.colocated(2, &frame_mb, 8, 8).unwrap(), (0, [15, -15], Some((40, Some(0))), VertMvScale::FldToFrm));
+
+        // 时间直接预测: mvCol[ 1 ] * 2, refIdxL0 引用 refPicCol 所在的帧 ( id 40 ), DistScaleFactor = 128
+        let mut direct = self::context(&picture, &refs);
+        direct.direct_spatial_mv_pred_flag = false;
+        let mut state = MbState::new(0, MbType::BSkip, false, 26);
+        direct.derive(&Macroblock::new(2, MbType::BSkip), &mut state).unwrap();
+        assert_eq!(state.ref_idx, [[0; 4], [0; 4]]);
+        // mvCol = ( 8, -16 ): ( 128 * 8 + 128 ) >> 8 = 4, ( 128 * -16 + 128 ) >> 8 = -8
+        assert_eq!((state.mv[0][0], state.mv[1][0]), ([4, -8], [-4, 8]));
+        // mvCol = ( 12, -24 ): ( 128 * -24 + 128 ) >> 8 = -12
+        assert_eq!((state.mv[0][8], state.mv[1][8]), ([6, -12], [-6, 12]));
+        assert_eq!(state.ref_pic, [[Some((40, None)); 4], [Some((30, None)); 4]]);
+
+        // 底场更近: 使用底场中的宏块
+        refs.lists[1][0].as_mut().unwrap().field_poc = [9, 5];
+        let context = self::context(&picture, &refs);
+        assert_eq!(context.colocated(2, &frame_mb, 0, 0).unwrap(), (0, [108, -8], Some((41, Some(1))), VertMvScale::FldToFrm));
+    }
+
+    #[test]
+    fn test_mbaff_field_references() {
+        // MBAFF 帧中的场宏块: refIdx 的最低位选择奇偶, refIdx >> 1 为帧在列表中的位置
+        let picture = picture(2, 2);
+        let refs = RefPicLists {
+            poc: 8,
+            field_poc: [8, 9],
+            lists: [vec![Some(RefPic { field_poc: [0, 1], ..ref_pic(10, 0, &picture).unwrap() })], vec![]],
+        };
+
+        assert_eq!(refs.len_mb(0, Some(1)), 2);
+        let same = refs.get_mb(0, 0, Some(1)).unwrap();
+        let opposite = refs.get_mb(0, 1, Some(1)).unwrap();
+        assert_eq!((same.ref_pic_id(), same.poc), ((10, Some(1)), 1));
+        assert_eq!((opposite.ref_pic_id(), opposite.poc), ((10, Some(0)), 0));
+        assert!(refs.get_mb(0, 2, Some(1)).is_none());
+        assert_eq!((refs.curr_poc(Some(1)), refs.curr_poc(None)), (9, 8));
+
+        // 帧宏块直接使用 RefPicList0[ refIdx ]
+        assert_eq!(refs.get_mb(0, 0, None).unwrap().ref_pic_id(), (10, None));
+        assert!(refs.get_mb(0, 1, None).is_none());
+    }
+}
@@ -0,0 +1,437 @@
+use crate::rbsp::{ SequenceParameterSet, SliceType };
+use crate::macroblock::{ MbType, SubMbType, MbNeighbour, neighbour_location, neighbour_location_mbaff };
+use super::export::MotionInfo;
+
+
+use std::fmt;
+use std::io::{ self, Write };
+use std::ops::Range;
+
+
+// 一个颜色分量的样本, 按行存放
+#[derive(Clone, PartialEq, Eq)]
+pub struct Plane {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u16>,
+}
+
+impl Plane {
+    pub fn new(width: usize, height: usize, value: u16) -> Self {
+        Self {
+            width: width,
+            height: height,
+            data: vec![value; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u16 {
+        self.data[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: u16) {
+        self.data[y * self.width + x] = value;
+    }
+
+    pub fn row(&self, y: usize) -> &[u16] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    fn crop(&self, left: usize, top: usize, width: usize, height: usize) -> Plane {
+        let mut data = Vec::with_capacity(width * height);
+        for y in top..top + height {
+            data.extend_from_slice(&self.row(y)[left..left + width]);
+        }
+
+        Plane {
+            width: width,
+            height: height,
+            data: data,
+        }
+    }
+
+    fn write<W: Write>(&self, writer: &mut W, bit_depth: u32) -> io::Result<()> {
+        if bit_depth > 8 {
+            for sample in self.data.iter() {
+                writer.write_all(&sample.to_le_bytes())?;
+            }
+        } else {
+            let bytes: Vec<u8> = self.data.iter().map(|&sample| sample as u8).collect();
+            writer.write_all(&bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Plane {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Plane {{ width: {}, height: {} }}", self.width, self.height)
+    }
+}
+
+
+// 输出的图像 ( 已按 SPS 的 frame_crop_*_offset 裁剪 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub luma: Plane,
+    // chroma_format_idc 为 0 ( 单色 ) 时不存在
+    pub cb: Option<Plane>,
+    pub cr: Option<Plane>,
+    // 每个宏块的类型, 分区与运动向量 ( 见 Decoder::set_export_motion )
+    pub motion: Option<MotionInfo>,
+}
+
+impl Frame {
+    // 按 Y, Cb, Cr 的顺序输出平面数据 ( 位深大于 8 时每个样本为 16 位小端 ), 即常见的 .yuv 文件格式
+    pub fn write_yuv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.luma.write(writer, self.bit_depth_luma)?;
+        if let (Some(cb), Some(cr)) = (self.cb.as_ref(), self.cr.as_ref()) {
+            cb.write(writer, self.bit_depth_chroma)?;
+            cr.write(writer, self.bit_depth_chroma)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+// 解码后的宏块信息, 供相邻宏块的预测使用
+#[derive(Debug, Clone)]
+pub(crate) struct MbState {
+    // 所属 slice 在当前图像中的序号
+    pub slice_num: usize,
+    pub mb_type: MbType,
+    // P_8x8, P_8x8ref0, B_8x8 的 sub_mb_type ( 供运动信息的导出使用 )
+    pub sub_mb_type: Option<[SubMbType; 4]>,
+    // 场宏块 ( field_pic_flag 或 mb_field_decoding_flag )
+    pub field: bool,
+    pub transform_size_8x8_flag: bool,
+    // QPY
+    pub qp_y: i32,
+    // Intra4x4PredMode[ luma4x4BlkIdx ]; Intra_8x8 宏块的 Intra8x8PredMode 记录在它包含的 4 个 4x4 块上
+    pub intra_pred_modes: [u8; 16],
+    // refIdxL0, refIdxL1 ( 每个 8x8 块 ), 帧内宏块或未使用该列表时为 -1
+    pub ref_idx: [[i8; 4]; 2],
+    // mvL0, mvL1 ( 每个 4x4 块, 按光栅顺序 )
+    pub mv: [[[i32; 2]; 16]; 2],
+    // refIdxLX 所指向的参考图像 ( 供时间直接预测与去块滤波使用 )
+    pub ref_pic: [[Option<RefPicId>; 4]; 2],
+    // 包含非零变换系数的亮度 4x4 块 ( 按光栅顺序的位 ), 8x8 变换时记录在它包含的 4 个 4x4 块上;
+    // ChromaArrayType 为 3 时也包括 Cb, Cr
+    pub non_zero: u16,
+}
+
+impl MbState {
+    pub fn new(slice_num: usize, mb_type: MbType, transform_size_8x8_flag: bool, qp_y: i32) -> Self {
+        Self {
+            slice_num: slice_num,
+            mb_type: mb_type,
+            sub_mb_type: None,
+            field: false,
+            transform_size_8x8_flag: transform_size_8x8_flag,
+            qp_y: qp_y,
+            intra_pred_modes: [2; 16],
+            ref_idx: [[-1; 4]; 2],
+            mv: [[[0; 2]; 16]; 2],
+            ref_pic: [[None; 4]; 2],
+            non_zero: 0,
+        }
+    }
+}
+
+
+// 去块滤波使用的 slice 参数
+#[derive(Debug, Clone)]
+pub(crate) struct SliceParams {
+    pub slice_type: SliceType,
+    pub disable_deblocking_filter_idc: u32,
+    // FilterOffsetA, FilterOffsetB
+    pub filter_offset_a: i32,
+    pub filter_offset_b: i32,
+    // chroma_qp_index_offset, second_chroma_qp_index_offset
+    pub chroma_qp_index_offset: [i32; 2],
+}
+
+
+// 被参考的图像: DPB 中的 id, 以及参考场时场的奇偶 ( 0: 顶场, 1: 底场 )
+pub(crate) type RefPicId = (u64, Option<usize>);
+
+// 当前 slice 的参考图像列表中的一项
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RefPic<'a> {
+    pub id: u64,
+    // 参考帧 ( 或互补场对 ) 时为 None, 参考场时为场的奇偶
+    pub parity: Option<usize>,
+    // PicOrderCnt( frame ) 或 PicOrderCnt( field )
+    pub poc: i32,
+    // TopFieldOrderCnt, BottomFieldOrderCnt
+    pub field_poc: [i32; 2],
+    pub long_term: bool,
+    pub picture: &'a Picture,
+}
+
+impl<'a> RefPic<'a> {
+    pub fn ref_pic_id(&self) -> RefPicId {
+        (self.id, self.parity)
+    }
+
+    // 参考帧中奇偶为 `parity` 的场
+    pub fn field(&self, parity: usize) -> RefPic<'a> {
+        RefPic {
+            parity: Some(parity),
+            poc: self.field_poc[parity],
+            ..*self
+        }
+    }
+}
+
+// RefPicList0, RefPicList1 ( "no reference picture" 为 None ) 与当前图像的 PicOrderCnt
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RefPicLists<'a> {
+    pub poc: i32,
+    // 当前帧的 TopFieldOrderCnt, BottomFieldOrderCnt ( MBAFF 帧中的场宏块使用 )
+    pub field_poc: [i32; 2],
+    pub lists: [Vec<Option<RefPic<'a>>>; 2],
+}
+
+impl<'a> RefPicLists<'a> {
+    pub fn get(&self, list: usize, ref_idx: i8) -> Option<&RefPic<'a>> {
+        if ref_idx < 0 {
+            return None;
+        }
+        self.lists[list].get(ref_idx as usize)?.as_ref()
+    }
+
+    // 8.4.2.1 Reference picture selection process: MBAFF 帧中的场宏块 ( `mb_parity` 为宏块所在场的奇偶 ) 使用
+    // RefPicListX[ refIdxLX >> 1 ] 中的场, refIdxLX 为偶数时与当前宏块奇偶相同; 其他宏块直接使用 RefPicListX[ refIdxLX ]
+    pub fn get_mb(&self, list: usize, ref_idx: i8, mb_parity: Option<usize>) -> Option<RefPic<'a>> {
+        match mb_parity {
+            None => self.get(list, ref_idx).copied(),
+            Some(parity) => {
+                let frame = self.get(list, ref_idx >> 1)?;
+                Some(frame.field(parity ^ (ref_idx & 1) as usize))
+            },
+        }
+    }
+
+    // 与 `get_mb` 对应的列表长度
+    pub fn len_mb(&self, list: usize, mb_parity: Option<usize>) -> usize {
+        self.lists[list].len() << mb_parity.is_some() as usize
+    }
+
+    // currPicOrField: 当前图像, 或 MBAFF 帧中场宏块所在的场的 PicOrderCnt
+    pub fn curr_poc(&self, mb_parity: Option<usize>) -> i32 {
+        match mb_parity {
+            None => self.poc,
+            Some(parity) => self.field_poc[parity],
+        }
+    }
+}
+
+
+// 正在解码的图像 ( 未裁剪 )
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub luma: Plane,
+    pub cb: Option<Plane>,
+    pub cr: Option<Plane>,
+    pub(crate) width_in_mbs: u32,
+    pub(crate) mbs: Vec<Option<MbState>>,
+    // 已解码的 slice, 下标为 MbState::slice_num
+    pub(crate) slices: Vec<SliceParams>,
+    // MbaffFrameFlag, field_pic_flag 与 bottom_field_flag ( 最近解码的 slice )
+    pub(crate) mbaff_frame_flag: bool,
+    pub(crate) field_pic_flag: bool,
+    pub(crate) bottom_field_flag: bool,
+    // direct_8x8_inference_flag: 直接预测的运动向量以 8x8 块为单位
+    pub(crate) direct_8x8_inference_flag: bool,
+
+    chroma_format_idc: u32,
+    bit_depth_luma: u32,
+    bit_depth_chroma: u32,
+    crop_rect: (u32, u32, u32, u32),
+}
+
+impl Picture {
+    pub fn new(sps: &SequenceParameterSet) -> Self {
+        let width = sps.width() as usize;
+        let height = sps.height() as usize;
+        let luma = Plane::new(width, height, 1 << (sps.bit_depth_luma() - 1));
+
+        let (cb, cr) = match sps.chroma_array_type() {
+            0 => (None, None),
+            _ => {
+                let width = (sps.pic_width_in_mbs() * sps.mb_width_c()) as usize;
+                let height = (sps.frame_height_in_mbs() * sps.mb_height_c()) as usize;
+                let plane = Plane::new(width, height, 1 << (sps.bit_depth_chroma() - 1));
+                (Some(plane.clone()), Some(plane))
+            },
+        };
+
+        Self {
+            luma: luma,
+            cb: cb,
+            cr: cr,
+            width_in_mbs: sps.pic_width_in_mbs(),
+            mbs: vec![None; (sps.pic_width_in_mbs() * sps.frame_height_in_mbs()) as usize],
+            slices: vec![],
+            mbaff_frame_flag: false,
+            field_pic_flag: false,
+            bottom_field_flag: false,
+            direct_8x8_inference_flag: sps.direct_8x8_inference_flag(),
+            chroma_format_idc: sps.chroma_array_type(),
+            bit_depth_luma: sps.bit_depth_luma(),
+            bit_depth_chroma: sps.bit_depth_chroma(),
+            crop_rect: sps.crop_rect(),
+        }
+    }
+
+    // 0: Y, 1: Cb, 2: Cr
+    pub(crate) fn plane_mut(&mut self, comp: usize) -> &mut Plane {
+        match comp {
+            0 => &mut self.luma,
+            1 => self.cb.as_mut().expect("chroma plane"),
+            _ => self.cr.as_mut().expect("chroma plane"),
+        }
+    }
+
+    pub(crate) fn plane(&self, comp: usize) -> &Plane {
+        match comp {
+            0 => &self.luma,
+            1 => self.cb.as_ref().expect("chroma plane"),
+            _ => self.cr.as_ref().expect("chroma plane"),
+        }
+    }
+
+    pub(crate) fn chroma_array_type(&self) -> u32 {
+        self.chroma_format_idc
+    }
+
+    pub(crate) fn bit_depth(&self, comp: usize) -> u32 {
+        if comp == 0 { self.bit_depth_luma } else { self.bit_depth_chroma }
+    }
+
+    // 场图像 ( field_pic_flag 为 1 ) 的宏块按场分别存放, 顶场在前: 奇偶为 `parity` 的场的宏块, 下标为场中的 mbAddr
+    pub(crate) fn field_mbs(&self, parity: usize) -> &[Option<MbState>] {
+        let half = self.mbs.len() / 2;
+        &self.mbs[parity * half..(parity + 1) * half]
+    }
+
+    // 当前帧或当前场的宏块在 `mbs` 中的范围
+    pub(crate) fn current_mb_range(&self) -> Range<usize> {
+        let half = self.mbs.len() / 2;
+        match (self.field_pic_flag, self.bottom_field_flag) {
+            (false, _) => 0..self.mbs.len(),
+            (true, false) => 0..half,
+            (true, true) => half..self.mbs.len(),
+        }
+    }
+
+    // 当前帧或当前场的宏块, 下标为 mbAddr
+    pub(crate) fn current_mbs(&self) -> &[Option<MbState>] {
+        &self.mbs[self.current_mb_range()]
+    }
+
+    // 当前帧或当前场中的宏块
+    pub(crate) fn mb(&self, mb_addr: u32) -> Option<&MbState> {
+        self.current_mbs().get(mb_addr as usize)?.as_ref()
+    }
+
+    pub(crate) fn set_mb(&mut self, mb_addr: u32, state: MbState) {
+        let offset = self.current_mb_range().start;
+        self.mbs[offset + mb_addr as usize] = Some(state);
+    }
+
+    // 6.4.1 Inverse macroblock scanning process ( Page 32 )
+    //
+    // 大小为 width x height 的宏块第一行的第一个样本在平面中的位置, 以及宏块内相邻两行在平面中的距离。
+    // 场图像的宏块位于当前场的行, MBAFF 帧中 `field` 为宏块对是否为场宏块对
+    pub(crate) fn mb_origin(&self, mb_addr: u32, field: bool, width: i32, height: i32) -> (i32, i32, i32) {
+        if self.field_pic_flag {
+            let x = (mb_addr % self.width_in_mbs) as i32 * width;
+            let y = (mb_addr / self.width_in_mbs) as i32 * height * 2;
+            return (x, y + self.bottom_field_flag as i32, 2);
+        }
+
+        if !self.mbaff_frame_flag {
+            let x = (mb_addr % self.width_in_mbs) as i32 * width;
+            let y = (mb_addr / self.width_in_mbs) as i32 * height;
+            return (x, y, 1);
+        }
+
+        let pair = mb_addr / 2;
+        let x = (pair % self.width_in_mbs) as i32 * width;
+        let y = (pair / self.width_in_mbs) as i32 * height * 2;
+        let bottom = (mb_addr % 2) as i32;
+        if field {
+            (x, y + bottom, 2)
+        } else {
+            (x, y + bottom * height, 1)
+        }
+    }
+
+    // 6.4.12 Derivation process for neighbouring locations ( Page 47 )
+    //
+    // MBAFF 帧按 6.4.12.2 ( 只使用属于 `slice_num` 的相邻宏块对 ), 否则按 6.4.12.1; `field` 为当前宏块是否为场宏块。
+    // 返回 ( N, mbAddrN, xW, yW ), mbAddrN 是否已解码, 是否属于同一个 slice 由调用者判断
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn neighbour_location(&self,
+                                     slice_num: usize,
+                                     mb_addr: u32,
+                                     field: bool,
+                                     xn: i32,
+                                     yn: i32,
+                                     max_w: i32,
+                                     max_h: i32) -> Option<(MbNeighbour, u32, i32, i32)> {
+        if !self.mbaff_frame_flag {
+            return neighbour_location(mb_addr, self.width_in_mbs, xn, yn, max_w, max_h);
+        }
+
+        let pair_field = |addr: u32| {
+            self.mb(addr).filter(|state| state.slice_num == slice_num).map(|state| state.field)
+        };
+        neighbour_location_mbaff(mb_addr, self.width_in_mbs, !field, xn, yn, max_w, max_h, &pair_field)
+    }
+
+    // 所有宏块是否均已解码
+    pub fn is_complete(&self) -> bool {
+        self.mbs.iter().all(|mb| mb.is_some())
+    }
+
+    // 按裁剪矩形输出
+    pub fn to_frame(&self) -> Frame {
+        let (left, right, top, bottom) = self.crop_rect;
+        let width = (self.luma.width as u32).saturating_sub(left + right);
+        let height = (self.luma.height as u32).saturating_sub(top + bottom);
+
+        let luma = self.luma.crop(left as usize, top as usize, width as usize, height as usize);
+
+        let crop_chroma = |plane: &Plane| {
+            // CropUnitX / CropUnitY 保证裁剪偏移是色度采样间隔的整数倍
+            let sub_width_c = self.luma.width / plane.width;
+            let sub_height_c = self.luma.height / plane.height;
+            plane.crop(left as usize / sub_width_c,
+                       top as usize / sub_height_c,
+                       width as usize / sub_width_c,
+                       height as usize / sub_height_c)
+        };
+
+        Frame {
+            width: width,
+            height: height,
+            chroma_format_idc: self.chroma_format_idc,
+            bit_depth_luma: self.bit_depth_luma,
+            bit_depth_chroma: self.bit_depth_chroma,
+            luma: luma,
+            cb: self.cb.as_ref().map(crop_chroma),
+            cr: self.cr.as_ref().map(crop_chroma),
+            motion: None,
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// 8.2.1 Decoding process for picture order count ( Page 97 )
+
+use crate::nalu::NaluRefIdc;
+use crate::rbsp::{ SliceHeader, SequenceParameterSet };
+
+
+// 当前图像的 TopFieldOrderCnt, BottomFieldOrderCnt ( 场图像只有其中一个 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PicOrderCnt {
+    pub top: Option<i32>,
+    pub bottom: Option<i32>,
+    // PicOrderCntMsb ( pic_order_cnt_type 0 )
+    msb: i32,
+    // FrameNumOffset ( pic_order_cnt_type 1, 2 )
+    frame_num_offset: i32,
+}
+
+impl PicOrderCnt {
+    // PicOrderCnt( CurrPic )
+    pub fn value(&self) -> i32 {
+        match (self.top, self.bottom) {
+            (Some(top), Some(bottom)) => top.min(bottom),
+            (Some(top), None) => top,
+            (None, Some(bottom)) => bottom,
+            (None, None) => 0,
+        }
+    }
+
+    // 8.2.1: memory_management_control_operation 5 之后, tempPicOrderCnt = PicOrderCnt( CurrPic )
+    pub fn reset(&mut self) {
+        let temp = self.value();
+        self.top = self.top.map(|top| top - temp);
+        self.bottom = self.bottom.map(|bottom| bottom - temp);
+    }
+}
+
+
+// 解码顺序中前一个 ( 参考 ) 图像留下的状态
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PocState {
+    prev_pic_order_cnt_msb: i32,
+    prev_pic_order_cnt_lsb: i32,
+    prev_frame_num_offset: i32,
+    prev_frame_num: u32,
+}
+
+impl PocState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decode(&self, header: &SliceHeader, sps: &SequenceParameterSet) -> PicOrderCnt {
+        match sps.pic_order_cnt_type() {
+            0 => self.decode_type0(header, sps),
+            1 => self.decode_type1(header, sps),
+            _ => self.decode_type2(header, sps),
+        }
+    }
+
+    // 当前图像解码完成后调用; `poc` 为 memory_management_control_operation 5 处理之后的值
+    pub fn update(&mut self, header: &SliceHeader, poc: &PicOrderCnt, mmco5: bool) {
+        if header.nal_ref_idc != NaluRefIdc::DISPOSABLE {
+            if mmco5 {
+                self.prev_pic_order_cnt_msb = 0;
+                self.prev_pic_order_cnt_lsb = match header.field_pic_flag && header.bottom_field_flag {
+                    true => 0,
+                    false => poc.top.unwrap_or(0),
+                };
+            } else {
+                self.prev_pic_order_cnt_msb = poc.msb;
+                self.prev_pic_order_cnt_lsb = header.pic_order_cnt_lsb.unwrap_or(0) as i32;
+            }
+        }
+
+        if mmco5 {
+            self.prev_frame_num_offset = 0;
+            self.prev_frame_num = 0;
+        } else {
+            self.prev_frame_num_offset = poc.frame_num_offset;
+            self.prev_frame_num = header.frame_num;
+        }
+    }
+
+    // 8.2.1.1 Decoding process for picture order count type 0
+    fn decode_type0(&self, header: &SliceHeader, sps: &SequenceParameterSet) -> PicOrderCnt {
+        let (prev_msb, prev_lsb) = if header.idr_pic_flag() {
+            (0, 0)
+        } else {
+            (self.prev_pic_order_cnt_msb, self.prev_pic_order_cnt_lsb)
+        };
+
+        let max_lsb = sps.max_pic_order_cnt_lsb() as i32;
+        let lsb = header.pic_order_cnt_lsb.unwrap_or(0) as i32;
+
+        let msb = if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+            prev_msb + max_lsb
+        } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+            prev_msb - max_lsb
+        } else {
+            prev_msb
+        };
+
+        let (top, bottom) = if !header.field_pic_flag {
+            let top = msb + lsb;
+            (Some(top), Some(top + header.delta_pic_order_cnt_bottom.unwrap_or(0)))
+        } else if !header.bottom_field_flag {
+            (Some(msb + lsb), None)
+        } else {
+            (None, Some(msb + lsb))
+        };
+
+        PicOrderCnt {
+            top: top,
+            bottom: bottom,
+            msb: msb,
+            frame_num_offset: 0,
+        }
+    }
+
+    // FrameNumOffset ( 8-6, 8-11 )
+    fn frame_num_offset(&self, header: &SliceHeader, sps: &SequenceParameterSet) -> i32 {
+        if header.idr_pic_flag() {
+            0
+        } else if self.prev_frame_num > header.frame_num {
+            self.prev_frame_num_offset + sps.max_frame_num() as i32
+        } else {
+            self.prev_frame_num_offset
+        }
+    }
+
+    // 8.2.1.2 Decoding process for picture order count type 1
+    fn decode_type1(&self, header: &SliceHeader, sps: &SequenceParameterSet) -> PicOrderCnt {
+        let frame_num_offset = self.frame_num_offset(header, sps);
+        let non_ref = header.nal_ref_idc == NaluRefIdc::DISPOSABLE;
+        let offset_for_ref_frame = sps.offset_for_ref_frame();
+        let cycle_len = sps.num_ref_frames_in_pic_order_cnt_cycle() as i32;
+
+        let mut abs_frame_num = if cycle_len != 0 { frame_num_offset + header.frame_num as i32 } else { 0 };
+        if non_ref && abs_frame_num > 0 {
+            abs_frame_num -= 1;
+        }
+
+        let mut expected_pic_order_cnt = 0;
+        if abs_frame_num > 0 {
+            let pic_order_cnt_cycle_cnt = (abs_frame_num - 1) / cycle_len;
+            let frame_num_in_pic_order_cnt_cycle = (abs_frame_num - 1) % cycle_len;
+            let expected_delta_per_pic_order_cnt_cycle: i32 = offset_for_ref_frame.iter().sum();
+
+            expected_pic_order_cnt = pic_order_cnt_cycle_cnt * expected_delta_per_pic_order_cnt_cycle;
+            for i in 0..=frame_num_in_pic_order_cnt_cycle as usize {
+                expected_pic_order_cnt += offset_for_ref_frame[i];
+            }
+        }
+        if non_ref {
+            expected_pic_order_cnt += sps.offset_for_non_ref_pic();
+        }
+
+        let delta = [
+            header.delta_pic_order_cnt[0].unwrap_or(0),
+            header.delta_pic_order_cnt[1].unwrap_or(0),
+        ];
+        let (top, bottom) = if !header.field_pic_flag {
+            let top = expected_pic_order_cnt + delta[0];
+            (Some(top), Some(top + sps.offset_for_top_to_bottom_field() + delta[1]))
+        } else if !header.bottom_field_flag {
+            (Some(expected_pic_order_cnt + delta[0]), None)
+        } else {
+            (None, Some(expected_pic_order_cnt + sps.offset_for_top_to_bottom_field() + delta[0]))
+        };
+
+        PicOrderCnt {
+            top: top,
+            bottom: bottom,
+            msb: 0,
+            frame_num_offset: frame_num_offset,
+        }
+    }
+
+    // 8.2.1.3 Decoding process for picture order count type 2
+    fn decode_type2(&self, header: &SliceHeader, sps: &SequenceParameterSet) -> PicOrderCnt {
+        let frame_num_offset = self.frame_num_offset(header, sps);
+
+        let temp_pic_order_cnt = if header.idr_pic_flag() {
+            0
+        } else if header.nal_ref_idc == NaluRefIdc::DISPOSABLE {
+            2 * (frame_num_offset + header.frame_num as i32) - 1
+        } else {
+            2 * (frame_num_offset + header.frame_num as i32)
+        };
+
+        let (top, bottom) = if !header.field_pic_flag {
+            (Some(temp_pic_order_cnt), Some(temp_pic_order_cnt))
+        } else if !header.bottom_field_flag {
+            (Some(temp_pic_order_cnt), None)
+        } else {
+            (None, Some(temp_pic_order_cnt))
+        };
+
+        PicOrderCnt {
+            top: top,
+            bottom: bottom,
+            msb: 0,
+            frame_num_offset: frame_num_offset,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nalu::NaluKind;
+    use crate::slice::test::{ Writer, parameter_sets, predicted_slice_header };
+
+    fn header(idr: bool, nal_ref_idc: NaluRefIdc, frame_num: u32, pic_order_cnt_lsb: u32) -> SliceHeader {
+        let mut header = predicted_slice_header(&parameter_sets());
+        if idr {
+            header.nal_unit_type = NaluKind::CodedSliceIdr;
+        }
+        header.nal_ref_idc = nal_ref_idc;
+        header.frame_num = frame_num;
+        header.pic_order_cnt_lsb = Some(pic_order_cnt_lsb);
+        header
+    }
+
+    fn decode(state: &mut PocState, header: &SliceHeader, sps: &SequenceParameterSet) -> i32 {
+        let poc = state.decode(header, sps);
+        state.update(header, &poc, false);
+        poc.value()
+    }
+
+    #[test]
+    fn test_pic_order_cnt_type0() {
+        let parameter_sets = parameter_sets();
+        let sps = parameter_sets.sps(0).unwrap();
+        assert_eq!(sps.max_pic_order_cnt_lsb(), 16);
+
+        let mut state = PocState::new();
+        assert_eq!(decode(&mut state, &header(true, NaluRefIdc::HIGH, 0, 0), sps), 0);
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::HIGH, 1, 6), sps), 6);
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::HIGH, 2, 12), sps), 12);
+        // pic_order_cnt_lsb 回绕: PicOrderCntMsb = 16
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::HIGH, 3, 2), sps), 18);
+        // 非参考图像不更新 prevPicOrderCntMsb
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::DISPOSABLE, 4, 0), sps), 16);
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::DISPOSABLE, 4, 12), sps), 12);
+
+        let mut poc = state.decode(&header(false, NaluRefIdc::HIGH, 4, 4), sps);
+        assert_eq!((poc.top, poc.bottom), (Some(20), Some(20)));
+        // memory_management_control_operation 5
+        poc.reset();
+        assert_eq!(poc.value(), 0);
+    }
+
+    #[test]
+    fn test_pic_order_cnt_type2() {
+        let mut parameter_sets = parameter_sets();
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(2)
+            .ue(1).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        parameter_sets.update(&sps).unwrap();
+        let sps = parameter_sets.sps(0).unwrap();
+        assert_eq!(sps.pic_order_cnt_type(), 2);
+
+        let mut state = PocState::new();
+        assert_eq!(decode(&mut state, &header(true, NaluRefIdc::HIGH, 0, 0), sps), 0);
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::HIGH, 1, 0), sps), 2);
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::DISPOSABLE, 2, 0), sps), 3);
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::HIGH, 2, 0), sps), 4);
+        // frame_num 回绕: FrameNumOffset = MaxFrameNum
+        assert_eq!(decode(&mut state, &header(false, NaluRefIdc::HIGH, 0, 0), sps), 32);
+    }
+}
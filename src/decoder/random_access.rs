@@ -0,0 +1,217 @@
+// 随机访问点的分类: IDR 图像, 带有 recovery_point SEI 的图像 ( open GOP, 渐进帧内刷新 ), 以及不能作为解码起点的图像。
+// 对恢复点计算解码收敛的访问单元 ( D.2.8: frame_num 等于 recovery_frame_num 的帧, 按解码顺序 )。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ SupplementalEnhancementInformation, SeiPayload, RecoveryPoint };
+use super::access_unit::AccessUnitTracker;
+
+use std::collections::VecDeque;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomAccessKind {
+    Idr,
+    // 带有 recovery_point SEI
+    RecoveryPoint,
+    // 从这里开始解码不能得到正确的图像
+    NotRandomAccess,
+}
+
+impl RandomAccessKind {
+    pub fn is_random_access(&self) -> bool {
+        *self != RandomAccessKind::NotRandomAccess
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RandomAccessUnit {
+    // 在解码顺序中的序号
+    pub index: u64,
+    // PictureReport::id
+    pub id: u64,
+    pub frame_num: u32,
+    pub pic_order_cnt: i32,
+    pub kind: RandomAccessKind,
+    pub recovery_point: Option<RecoveryPoint>,
+    // 从这里开始解码时, 解码收敛 ( 之后按输出顺序的图像都正确 ) 的访问单元的 index;
+    // 恢复点之后码流结束或出现 IDR 图像时为 None, NotRandomAccess 时为 None
+    pub recovery_index: Option<u64>,
+}
+
+// 一个恢复点等待 frame_num 为 recovery_frame_num 的帧
+#[derive(Debug, Clone, Copy)]
+struct PendingRecovery {
+    index: u64,
+    recovery_frame_num: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct RandomAccessCollector {
+    // 访问单元中 ( 第一个 slice 之前 ) 的 recovery_point SEI; 图像开始时加上 MaxFrameNum
+    tracker: AccessUnitTracker<Option<RecoveryPoint>, (Option<RecoveryPoint>, u32)>,
+    // 等待恢复点收敛的访问单元 ( 包括其后的所有访问单元 )
+    waiting: VecDeque<RandomAccessUnit>,
+    pending: Vec<PendingRecovery>,
+    units: VecDeque<RandomAccessUnit>,
+    next_index: u64,
+}
+
+impl RandomAccessCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        match nalu.kind() {
+            NaluKind::SupplementalEnhancementInformation => {
+                let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+                for message in sei.messages() {
+                    if let SeiPayload::RecoveryPoint(recovery_point) = message.payload {
+                        *self.tracker.access_unit() = Some(recovery_point);
+                    }
+                }
+                Ok(())
+            },
+            _ => {
+                self.tracker.push(nalu, |parameter_sets, header, recovery_point| {
+                    let max_frame_num = parameter_sets.pps(header.pic_parameter_set_id)
+                        .and_then(|pps| parameter_sets.sps(pps.seq_parameter_set_id()))
+                        .map(|sps| sps.max_frame_num())
+                        .expect("sps has been checked by the slice header");
+                    Ok((recovery_point, max_frame_num))
+                })?;
+                self.drain();
+                Ok(())
+            },
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Some((report, (recovery_point, max_frame_num))) = self.tracker.next_report() {
+            let index = self.next_index;
+            self.next_index += 1;
+
+            // IDR 图像重新开始 frame_num, 之前还没有收敛的恢复点不会再收敛
+            if report.idr {
+                self.pending.clear();
+                self.release(true);
+            }
+
+            for pending in self.pending.iter().filter(|pending| pending.recovery_frame_num == report.frame_num) {
+                if let Some(unit) = self.waiting.iter_mut().find(|unit| unit.index == pending.index) {
+                    unit.recovery_index = Some(index);
+                }
+            }
+            self.pending.retain(|pending| pending.recovery_frame_num != report.frame_num);
+
+            let (kind, recovery_index) = if report.idr {
+                (RandomAccessKind::Idr, Some(index))
+            } else if let Some(recovery_point) = recovery_point {
+                let recovery_frame_num = recovery_point.recovery_frame_num(report.frame_num, max_frame_num.max(1));
+                if recovery_point.recovery_frame_cnt == 0 {
+                    (RandomAccessKind::RecoveryPoint, Some(index))
+                } else {
+                    self.pending.push(PendingRecovery { index: index, recovery_frame_num: recovery_frame_num });
+                    (RandomAccessKind::RecoveryPoint, None)
+                }
+            } else {
+                (RandomAccessKind::NotRandomAccess, None)
+            };
+
+            self.waiting.push_back(RandomAccessUnit {
+                index: index,
+                id: report.id,
+                frame_num: report.frame_num,
+                pic_order_cnt: report.pic_order_cnt,
+                kind: kind,
+                recovery_point: recovery_point,
+                recovery_index: recovery_index,
+            });
+            self.release(false);
+        }
+    }
+
+    // 把不再等待收敛的访问单元移到输出队列, all 为 true 时全部移出
+    fn release(&mut self, all: bool) {
+        while let Some(unit) = self.waiting.front() {
+            if !all && self.pending.iter().any(|pending| pending.index == unit.index) {
+                break;
+            }
+            let unit = self.waiting.pop_front().expect("front unit");
+            self.units.push_back(unit);
+        }
+    }
+
+    // 码流结束: 还没有收敛的恢复点的 recovery_index 为 None
+    pub fn flush(&mut self) {
+        self.tracker.flush();
+        self.drain();
+        self.pending.clear();
+        self.release(true);
+    }
+
+    // 取出下一个 ( 按解码顺序 ) 访问单元; 恢复点在收敛之后才能取出
+    pub fn next_unit(&mut self) -> Option<RandomAccessUnit> {
+        self.units.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ RandomAccessCollector, RandomAccessKind };
+    use crate::nalu::Nalu;
+    use crate::slice::test::{ Writer, parameter_set_nalus };
+
+    fn recovery_point_sei(recovery_frame_cnt: u32) -> Nalu {
+        // recovery_frame_cnt, exact_match_flag 0, broken_link_flag 1, changing_slice_group_idc 0, 对齐
+        let ue_bits = 2 * (31 - (recovery_frame_cnt + 1).leading_zeros()) + 1;
+        let mut writer = Writer::new(0x06);
+        writer.u(8, 6).u(8, (ue_bits + 5).div_ceil(8)).ue(recovery_frame_cnt).bits("0100");
+        writer.bits("1").align();
+        writer.finish()
+    }
+
+    fn p_slice(frame_num: u32, lsb: u32) -> Nalu {
+        Writer::new(0x41).ue(0).ue(5).ue(0).u(4, frame_num).u(4, lsb).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish()
+    }
+
+    #[test]
+    fn test_random_access_points() {
+        let (sps, pps) = parameter_set_nalus();
+        let mut collector = RandomAccessCollector::new();
+        collector.push(&sps).unwrap();
+        collector.push(&pps).unwrap();
+
+        let idr = Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish();
+        collector.push(&idr).unwrap();
+        // frame_num 1 的恢复点在 frame_num 3 收敛, frame_num 4 的恢复点立即收敛, frame_num 5 的恢复点没有收敛
+        collector.push(&recovery_point_sei(2)).unwrap();
+        collector.push(&p_slice(1, 2)).unwrap();
+        collector.push(&p_slice(2, 4)).unwrap();
+        assert_eq!(collector.next_unit().map(|unit| unit.index), Some(0));
+        assert!(collector.next_unit().is_none());
+
+        collector.push(&p_slice(3, 6)).unwrap();
+        collector.push(&recovery_point_sei(0)).unwrap();
+        collector.push(&p_slice(4, 8)).unwrap();
+        collector.push(&recovery_point_sei(5)).unwrap();
+        collector.push(&p_slice(5, 10)).unwrap();
+        collector.flush();
+
+        let mut units = vec![];
+        while let Some(unit) = collector.next_unit() {
+            units.push((unit.index, unit.frame_num, unit.kind, unit.recovery_index));
+        }
+        assert_eq!(units, vec![
+            (1, 1, RandomAccessKind::RecoveryPoint, Some(3)),
+            (2, 2, RandomAccessKind::NotRandomAccess, None),
+            (3, 3, RandomAccessKind::NotRandomAccess, None),
+            (4, 4, RandomAccessKind::RecoveryPoint, Some(4)),
+            (5, 5, RandomAccessKind::RecoveryPoint, None),
+        ]);
+        assert!(RandomAccessKind::Idr.is_random_access());
+        assert!(!RandomAccessKind::NotRandomAccess.is_random_access());
+    }
+}
@@ -0,0 +1,720 @@
+// 8.3 Intra prediction process ( Page 147 )
+// 8.4 Inter prediction process ( Page 161 )
+// 8.5 Transform coefficient decoding process and picture construction process prior to deblocking filter process ( Page 177 )
+
+use crate::error::{ self, Error };
+use crate::rbsp::{ SliceHeader, SliceType, SequenceParameterSet, PictureParameterSet, ScalingMatrix };
+use crate::macroblock::{
+    self, Macroblock, MbType, IntraPredMode, ResidualBlocks, SliceData,
+    MbNeighbour, luma4x4_blk_idx, luma4x4_blk_position,
+};
+use super::picture::{ Picture, MbState, SliceParams, RefPicLists };
+use super::intra::{ self, RefSamples };
+use super::inter::{ self, Weight, RefPlane };
+use super::motion::MotionContext;
+use super::transform::{ self, LevelScale, ZIGZAG_4X4, FIELD_4X4, ZIGZAG_8X8, FIELD_8X8 };
+
+
+// Table 8-15 – Specification of QPC as a function of qPI ( Page 185 )
+const QP_C_TABLE: [i32; 22] = [
+    29, 30, 31, 32, 32, 33, 34, 34, 35, 35, 36, 36, 37, 37, 37, 38, 38, 38, 39, 39, 39, 39,
+];
+
+// 8.5.8 Derivation process for chroma quantisation parameters: QPC
+pub fn chroma_qp(qp_y: i32, qp_index_offset: i32, qp_bd_offset_c: i32) -> i32 {
+    let qp_i = (qp_y + qp_index_offset).max(-qp_bd_offset_c).min(51);
+    if qp_i < 30 {
+        qp_i
+    } else {
+        QP_C_TABLE[(qp_i - 30) as usize]
+    }
+}
+
+
+// 解码一个 slice 的所有宏块, 写入 `picture`
+pub(crate) fn decode_slice(picture: &mut Picture,
+                           header: &SliceHeader,
+                           data: &SliceData,
+                           sps: &SequenceParameterSet,
+                           pps: &PictureParameterSet,
+                           refs: &RefPicLists) -> Result<(), Error> {
+    if sps.separate_colour_plane_flag() {
+        return Err(error::unsupported("separate colour planes are not supported"));
+    }
+
+    if header.slice_type == SliceType::SP || header.slice_type == SliceType::SI {
+        return Err(error::unsupported("SP and SI slices are not supported"));
+    }
+
+    picture.mbaff_frame_flag = header.mbaff_frame_flag(sps);
+    picture.field_pic_flag = header.field_pic_flag;
+    picture.bottom_field_flag = header.bottom_field_flag;
+
+    if picture.current_mbs().len() != header.pic_size_in_mbs(sps) as usize {
+        return Err(error::malformed("slice does not match the picture size"));
+    }
+
+    let slice_num = picture.slices.len();
+    picture.slices.push(SliceParams {
+        slice_type: header.slice_type,
+        disable_deblocking_filter_idc: header.disable_deblocking_filter_idc,
+        filter_offset_a: header.filter_offset_a(),
+        filter_offset_b: header.filter_offset_b(),
+        chroma_qp_index_offset: [pps.chroma_qp_index_offset(), pps.second_chroma_qp_index_offset()],
+    });
+
+    let mut reconstructor = Reconstructor {
+        picture: picture,
+        header: header,
+        sps: sps,
+        pps: pps,
+        refs: refs,
+        slice_num: slice_num,
+        level_scale: LevelScale::new(&ScalingMatrix::derive(sps, pps)),
+        chroma_array_type: sps.chroma_array_type(),
+        mb_field: header.field_pic_flag,
+    };
+
+    let qp_bd_offset_y = sps.qp_bd_offset_luma();
+    let mut qp_y = header.slice_qp_y(pps);
+
+    for mb in data.macroblocks.iter() {
+        qp_y = macroblock::qp_y(qp_y, mb.mb_qp_delta, qp_bd_offset_y);
+        reconstructor.macroblock(mb, qp_y)?;
+    }
+
+    Ok(())
+}
+
+
+struct Reconstructor<'a> {
+    picture: &'a mut Picture,
+    header: &'a SliceHeader,
+    sps: &'a SequenceParameterSet,
+    pps: &'a PictureParameterSet,
+    refs: &'a RefPicLists<'a>,
+    slice_num: usize,
+    level_scale: LevelScale,
+    chroma_array_type: u32,
+    // 当前宏块是否为场宏块 ( 场图像或 MBAFF 帧中的场宏块对 ), 场宏块使用 field scan
+    mb_field: bool,
+}
+
+impl<'a> Reconstructor<'a> {
+    fn macroblock(&mut self, mb: &Macroblock, qp_y: i32) -> Result<(), Error> {
+        let mut state = MbState::new(self.slice_num, mb.mb_type, mb.transform_size_8x8_flag, qp_y);
+        state.sub_mb_type = mb.sub_mb_type;
+        state.field = mb.mb_field_decoding_flag;
+        self.mb_field = mb.mb_field_decoding_flag;
+        state.non_zero = self.non_zero(mb);
+
+        // 8.5.15: TransformBypassModeFlag
+        if mb.mb_type != MbType::IPcm
+            && self.sps.qpprime_y_zero_transform_bypass_flag()
+            && qp_y + self.sps.qp_bd_offset_luma() == 0 {
+            return Err(error::unsupported("transform bypass is not supported"));
+        }
+
+        if mb.mb_type == MbType::IPcm {
+            self.pcm(mb)?;
+        } else if mb.mb_type.is_inter() {
+            self.inter(mb, &mut state)?;
+        } else {
+            self.intra_luma(mb, 0, &mut state)?;
+
+            match self.chroma_array_type {
+                1 | 2 => self.intra_chroma(mb, qp_y)?,
+                3 => {
+                    self.intra_luma(mb, 1, &mut state)?;
+                    self.intra_luma(mb, 2, &mut state)?;
+                },
+                _ => { },
+            }
+        }
+
+        self.picture.set_mb(mb.mb_addr, state);
+
+        Ok(())
+    }
+
+    // 包含非零变换系数的 4x4 块, 见 MbState::non_zero
+    fn non_zero(&self, mb: &Macroblock) -> u16 {
+        let num_comps = if self.chroma_array_type == 3 { 3 } else { 1 };
+        let mut blocks = [false; 16];
+        for comp in 0..num_comps {
+            for (blk, &total_coeff) in mb.total_coeff[comp].iter().enumerate() {
+                blocks[blk] |= total_coeff > 0;
+            }
+        }
+
+        if mb.transform_size_8x8_flag {
+            for blk8x8 in 0..4 {
+                let any = blocks[blk8x8 * 4..blk8x8 * 4 + 4].iter().any(|&non_zero| non_zero);
+                for blk in blk8x8 * 4..blk8x8 * 4 + 4 {
+                    blocks[blk] = any;
+                }
+            }
+        }
+
+        let mut non_zero = 0;
+        for (blk, _) in blocks.iter().enumerate().filter(|(_, &non_zero)| non_zero) {
+            let (x, y) = luma4x4_blk_position(blk);
+            non_zero |= 1 << (y / 4 * 4 + x / 4);
+        }
+
+        non_zero
+    }
+
+    // QP'Y 或 QP'C
+    fn qp(&self, comp: usize, qp_y: i32) -> i32 {
+        match comp {
+            0 => qp_y + self.sps.qp_bd_offset_luma(),
+            _ => {
+                let offset = if comp == 1 {
+                    self.pps.chroma_qp_index_offset()
+                } else {
+                    self.pps.second_chroma_qp_index_offset()
+                };
+                let qp_bd_offset_c = self.sps.qp_bd_offset_chroma();
+                chroma_qp(qp_y, offset, qp_bd_offset_c) + qp_bd_offset_c
+            },
+        }
+    }
+
+    fn bit_depth(&self, comp: usize) -> u32 {
+        if comp == 0 { self.sps.bit_depth_luma() } else { self.sps.bit_depth_chroma() }
+    }
+
+    // 分量在一个宏块内的宽高
+    fn mb_size(&self, comp: usize) -> (i32, i32) {
+        if comp == 0 || self.chroma_array_type == 3 {
+            (16, 16)
+        } else {
+            (self.sps.mb_width_c() as i32, self.sps.mb_height_c() as i32)
+        }
+    }
+
+    // 当前宏块第一行的第一个样本在分量平面中的位置, 以及宏块内相邻两行在平面中的距离
+    fn mb_origin(&self, comp: usize, mb_addr: u32) -> (i32, i32, i32) {
+        let (width, height) = self.mb_size(comp);
+        self.picture.mb_origin(mb_addr, self.mb_field, width, height)
+    }
+
+    // MBAFF 帧中的场宏块所在的场的奇偶 ( 按 8.4.2.1 选择参考场 ), 其他宏块为 None
+    fn mb_parity(&self, mb_addr: u32) -> Option<usize> {
+        if self.picture.mbaff_frame_flag && self.mb_field {
+            Some((mb_addr % 2) as usize)
+        } else {
+            None
+        }
+    }
+
+    // 6.4.11 与 8.3.1.2: 相邻位置 ( xN, yN ) 处可用于帧内预测的样本, 不可用时为 None
+    fn neighbour_sample(&self, comp: usize, mb_addr: u32, xn: i32, yn: i32) -> Option<i32> {
+        let (max_w, max_h) = self.mb_size(comp);
+        let (kind, addr, xw, yw) = self.picture.neighbour_location(self.slice_num, mb_addr, self.mb_field, xn, yn, max_w, max_h)?;
+        let field = if kind == MbNeighbour::Curr { self.mb_field } else { self.neighbour_state(addr)?.field };
+        let (x, y, step) = self.picture.mb_origin(addr, field, max_w, max_h);
+        Some(self.picture.plane(comp).get((x + xw) as usize, (y + yw * step) as usize) as i32)
+    }
+
+    // 同一 slice 内已解码, 且不因 constrained_intra_pred_flag 而不可用的宏块
+    fn neighbour_state(&self, mb_addr: u32) -> Option<&MbState> {
+        let state = self.picture.mb(mb_addr)?;
+        if state.slice_num != self.slice_num {
+            return None;
+        }
+
+        if state.mb_type.is_inter() && self.pps.constrained_intra_pred_flag() {
+            return None;
+        }
+
+        Some(state)
+    }
+
+    // 位于 ( x, y ), 大小为 width x height 的块的参考样本; `top_len` 为需要的 p[ x, -1 ] 个数
+    #[allow(clippy::too_many_arguments)]
+    fn ref_samples(&self,
+                   comp: usize,
+                   mb_addr: u32,
+                   x: i32,
+                   y: i32,
+                   width: i32,
+                   height: i32,
+                   top_len: i32) -> RefSamples {
+        let sample = |xn: i32, yn: i32| self.neighbour_sample(comp, mb_addr, xn, yn);
+        // MBAFF 帧中同一列 ( 行 ) 的样本可能来自不同的相邻宏块, 任一样本不可用时整列 ( 行 ) 不可用
+        let fill = |out: &mut [i32], position: &dyn Fn(i32) -> (i32, i32)| {
+            out.iter_mut().enumerate().all(|(i, value)| {
+                let (xn, yn) = position(i as i32);
+                sample(xn, yn).map(|sample| *value = sample).is_some()
+            })
+        };
+
+        let mut refs = RefSamples::default();
+
+        let mut left = [0i32; 16];
+        refs.has_left = fill(&mut left[..height as usize], &|i| (x - 1, y + i));
+        if refs.has_left {
+            refs.left[..height as usize].copy_from_slice(&left[..height as usize]);
+        }
+
+        let mut top = [0i32; 32];
+        refs.has_top = fill(&mut top[..width as usize], &|i| (x + i, y - 1));
+
+        // 8.3.1.2: luma4x4BlkIdx 为 3 或 11 的块, 右上方的 4x4 块尚未解码
+        let blocked = width == 4 && x == 4 && (y == 4 || y == 12);
+        refs.has_top_right = top_len > width && !blocked
+            && fill(&mut top[width as usize..top_len as usize], &|i| (x + width + i, y - 1));
+
+        if refs.has_top {
+            refs.top[..width as usize].copy_from_slice(&top[..width as usize]);
+        }
+        if refs.has_top_right {
+            refs.top[width as usize..top_len as usize].copy_from_slice(&top[width as usize..top_len as usize]);
+        }
+
+        if let Some(top_left) = sample(x - 1, y - 1) {
+            refs.has_top_left = true;
+            refs.top_left = top_left;
+        }
+
+        refs
+    }
+
+    // 把 Clip1( pred + r ) 写入位于 ( x, y ) 的 width x height 块
+    #[allow(clippy::too_many_arguments)]
+    fn put(&mut self, comp: usize, mb_addr: u32, x: i32, y: i32, width: i32, height: i32, pred: &[i32], residual: &[i32]) {
+        let (origin_x, origin_y, step) = self.mb_origin(comp, mb_addr);
+        let max = (1 << self.bit_depth(comp)) - 1;
+        let plane = self.picture.plane_mut(comp);
+
+        for j in 0..height {
+            for i in 0..width {
+                let k = (j * width + i) as usize;
+                let value = intra::clip(max, pred[k] + residual[k]);
+                plane.set((origin_x + x + i) as usize, (origin_y + (y + j) * step) as usize, value as u16);
+            }
+        }
+    }
+
+    fn scan4x4(&self) -> &'static [usize; 16] {
+        if self.mb_field { &FIELD_4X4 } else { &ZIGZAG_4X4 }
+    }
+
+    fn scan8x8(&self) -> &'static [usize; 64] {
+        if self.mb_field { &FIELD_8X8 } else { &ZIGZAG_8X8 }
+    }
+
+    // 8.3.1.1 Derivation process for Intra4x4PredMode ( Page 148 )
+    // 8.3.2.1 Derivation process for Intra8x8PredMode ( Page 155 )
+    //
+    // 相邻块 N ( 位于 ( xN, yN ) ) 的 intraMxMPredModeN; 返回 None 表示 dcPredModePredictedFlag 为 1。
+    // `n` 为 8.3.2.1 中 Intra_4x4 相邻宏块的 4x4 块偏移 ( A: 1, B: 2 )
+    fn neighbour_pred_mode(&self, mb: &Macroblock, modes: &[u8; 16], xn: i32, yn: i32, eight: bool, n: usize) -> Option<u8> {
+        let (kind, addr, xw, yw) = self.picture.neighbour_location(self.slice_num, mb.mb_addr, self.mb_field, xn, yn, 16, 16)?;
+
+        let (mb_type, transform_size_8x8_flag, modes) = if kind == MbNeighbour::Curr {
+            (mb.mb_type, mb.transform_size_8x8_flag, modes)
+        } else {
+            let state = self.neighbour_state(addr)?;
+            (state.mb_type, state.transform_size_8x8_flag, &state.intra_pred_modes)
+        };
+
+        if mb_type != MbType::INxN {
+            return Some(2);
+        }
+
+        if !eight {
+            Some(modes[luma4x4_blk_idx(xw, yw)])
+        } else {
+            let luma8x8_blk_idx = (2 * (yw / 8) + xw / 8) as usize;
+            if transform_size_8x8_flag {
+                Some(modes[luma8x8_blk_idx * 4])
+            } else {
+                Some(modes[luma8x8_blk_idx * 4 + n])
+            }
+        }
+    }
+
+    fn derive_pred_mode(&self, mb: &Macroblock, modes: &[u8; 16], idx: usize, x: i32, y: i32, eight: bool) -> u8 {
+        let mode_a = self.neighbour_pred_mode(mb, modes, x - 1, y, eight, 1);
+        let mode_b = self.neighbour_pred_mode(mb, modes, x, y - 1, eight, 2);
+
+        let pred_mode = match (mode_a, mode_b) {
+            (Some(a), Some(b)) => a.min(b),
+            _ => 2,
+        };
+
+        if mb.prev_intra_pred_mode_flag[idx] {
+            pred_mode
+        } else if mb.rem_intra_pred_mode[idx] < pred_mode {
+            mb.rem_intra_pred_mode[idx]
+        } else {
+            mb.rem_intra_pred_mode[idx] + 1
+        }
+    }
+
+    fn residual_blocks<'m>(&self, mb: &'m Macroblock, comp: usize) -> Option<&'m ResidualBlocks> {
+        let residual = mb.residual.as_ref()?;
+        match comp {
+            0 => Some(&residual.luma),
+            1 => residual.cb.as_ref(),
+            _ => residual.cr.as_ref(),
+        }
+    }
+
+    // 亮度 ( 或 ChromaArrayType 为 3 时的 Cb, Cr ) 的帧内预测与残差重建
+    fn intra_luma(&mut self, mb: &Macroblock, comp: usize, state: &mut MbState) -> Result<(), Error> {
+        let qp = self.qp(comp, state.qp_y);
+        let bit_depth = self.bit_depth(comp);
+        let blocks = self.residual_blocks(mb, comp);
+        let addr = mb.mb_addr;
+
+        match mb.intra_pred_mode().expect("intra macroblock") {
+            IntraPredMode::Intra4x4 => {
+                let level_scale = *self.level_scale.get4x4(comp, qp);
+
+                for blk in 0..16 {
+                    let (x, y) = luma4x4_blk_position(blk);
+                    if comp == 0 {
+                        state.intra_pred_modes[blk] = self.derive_pred_mode(mb, &state.intra_pred_modes, blk, x, y, false);
+                    }
+
+                    let refs = self.ref_samples(comp, addr, x, y, 4, 4, 8);
+                    let mut pred = [0i32; 16];
+                    intra::predict_nxn(4, state.intra_pred_modes[blk], &refs, bit_depth, &mut pred)?;
+
+                    let mut residual = [0i32; 16];
+                    if let Some(blocks) = blocks {
+                        residual = transform::scale4x4(&blocks.level4x4[blk], self.scan4x4(), &level_scale, qp, None);
+                        transform::inverse_transform4x4(&mut residual);
+                    }
+
+                    self.put(comp, addr, x, y, 4, 4, &pred, &residual);
+                }
+            },
+            IntraPredMode::Intra8x8 => {
+                let level_scale = *self.level_scale.get8x8(2 * comp, qp);
+
+                for blk in 0..4 {
+                    let (x, y) = (blk as i32 % 2 * 8, blk as i32 / 2 * 8);
+                    if comp == 0 {
+                        let mode = self.derive_pred_mode(mb, &state.intra_pred_modes, blk, x, y, true);
+                        for i in 0..4 {
+                            state.intra_pred_modes[blk * 4 + i] = mode;
+                        }
+                    }
+
+                    let refs = self.ref_samples(comp, addr, x, y, 8, 8, 16);
+                    let mut pred = [0i32; 64];
+                    intra::predict_nxn(8, state.intra_pred_modes[blk * 4], &refs, bit_depth, &mut pred)?;
+
+                    let mut residual = [0i32; 64];
+                    if let Some(blocks) = blocks {
+                        residual = transform::scale8x8(&blocks.level8x8[blk], self.scan8x8(), &level_scale, qp);
+                        transform::inverse_transform8x8(&mut residual);
+                    }
+
+                    self.put(comp, addr, x, y, 8, 8, &pred, &residual);
+                }
+            },
+            IntraPredMode::Intra16x16 => {
+                let mode = match mb.mb_type {
+                    MbType::I16x16 { intra_16x16_pred_mode, .. } => intra_16x16_pred_mode,
+                    _ => unreachable!(),
+                };
+
+                let refs = self.ref_samples(comp, addr, 0, 0, 16, 16, 16);
+                let mut pred = [0i32; 256];
+                intra::predict16x16(mode, &refs, bit_depth, &mut pred)?;
+
+                let mut residual = [0i32; 256];
+                if let Some(blocks) = blocks {
+                    let level_scale = *self.level_scale.get4x4(comp, qp);
+                    let dc = transform::luma_dc(&blocks.dc, self.scan4x4(), level_scale[0], qp);
+
+                    for blk in 0..16 {
+                        let (x, y) = luma4x4_blk_position(blk);
+                        let dc = dc[(y / 4 * 4 + x / 4) as usize];
+                        let mut block = transform::scale4x4(&blocks.level4x4[blk], self.scan4x4(), &level_scale, qp, Some(dc));
+                        transform::inverse_transform4x4(&mut block);
+                        copy_block(&block, 4, &mut residual, 16, x, y);
+                    }
+                }
+
+                self.put(comp, addr, 0, 0, 16, 16, &pred, &residual);
+            },
+        }
+
+        Ok(())
+    }
+
+    // 8.3.4 Intra prediction process for chroma samples ( ChromaArrayType 为 1 或 2 )
+    fn intra_chroma(&mut self, mb: &Macroblock, qp_y: i32) -> Result<(), Error> {
+        let (width, height) = self.mb_size(1);
+        let bit_depth = self.bit_depth(1);
+        let addr = mb.mb_addr;
+
+        for comp in 1..3 {
+            let refs = self.ref_samples(comp, addr, 0, 0, width, height, width);
+            let mut pred = [0i32; 128];
+            intra::predict_chroma(mb.intra_chroma_pred_mode, &refs, width as usize, height as usize, bit_depth, &mut pred)?;
+
+            let residual = self.chroma_residual(mb, comp, qp_y, comp);
+            self.put(comp, addr, 0, 0, width, height, &pred, &residual);
+        }
+
+        Ok(())
+    }
+
+    // 8.5.11 Specification of transform decoding process for chroma samples
+    //
+    // 色度残差 ( ChromaArrayType 为 1 或 2 ), 每行 MbWidthC 个样本; `list` 为缩放矩阵的序号
+    fn chroma_residual(&self, mb: &Macroblock, comp: usize, qp_y: i32, list: usize) -> [i32; 128] {
+        let (width, height) = self.mb_size(comp);
+        let mut residual = [0i32; 128];
+
+        if let Some(blocks) = mb.residual.as_ref() {
+            let qp = self.qp(comp, qp_y);
+            let level_scale = *self.level_scale.get4x4(list, qp);
+            let i_cb_cr = comp - 1;
+            let dc = transform::chroma_dc(&blocks.chroma_dc[i_cb_cr], self.chroma_array_type, &self.level_scale.dc(list), qp);
+
+            for blk in 0..(width * height / 16) as usize {
+                let (x, y) = (blk as i32 % 2 * 4, blk as i32 / 2 * 4);
+                let mut block = transform::scale4x4(&blocks.chroma_ac[i_cb_cr][blk], self.scan4x4(), &level_scale, qp, Some(dc[blk]));
+                transform::inverse_transform4x4(&mut block);
+                copy_block(&block, 4, &mut residual, width, x, y);
+            }
+        }
+
+        residual
+    }
+
+    // 8.5.12 / 8.5.13: 帧间宏块的亮度 ( 或 ChromaArrayType 为 3 时的 Cb, Cr ) 残差
+    fn inter_residual(&self, mb: &Macroblock, comp: usize, qp_y: i32) -> [i32; 256] {
+        let mut residual = [0i32; 256];
+        let blocks = match self.residual_blocks(mb, comp) {
+            Some(blocks) => blocks,
+            None => return residual,
+        };
+
+        let qp = self.qp(comp, qp_y);
+        if mb.transform_size_8x8_flag {
+            let level_scale = *self.level_scale.get8x8(2 * comp + 1, qp);
+            for blk in 0..4 {
+                let mut block = transform::scale8x8(&blocks.level8x8[blk], self.scan8x8(), &level_scale, qp);
+                transform::inverse_transform8x8(&mut block);
+                copy_block(&block, 8, &mut residual, 16, blk as i32 % 2 * 8, blk as i32 / 2 * 8);
+            }
+        } else {
+            let level_scale = *self.level_scale.get4x4(3 + comp, qp);
+            for blk in 0..16 {
+                let (x, y) = luma4x4_blk_position(blk);
+                let mut block = transform::scale4x4(&blocks.level4x4[blk], self.scan4x4(), &level_scale, qp, None);
+                transform::inverse_transform4x4(&mut block);
+                copy_block(&block, 4, &mut residual, 16, x, y);
+            }
+        }
+
+        residual
+    }
+
+    // 8.4 Inter prediction process: 运动向量推导, 预测样本与残差
+    fn inter(&mut self, mb: &Macroblock, state: &mut MbState) -> Result<(), Error> {
+        let context = MotionContext {
+            picture: &*self.picture,
+            slice_num: self.slice_num,
+            refs: self.refs,
+            direct_spatial_mv_pred_flag: self.header.direct_spatial_mv_pred_flag.unwrap_or(false),
+            direct_8x8_inference_flag: self.sps.direct_8x8_inference_flag(),
+        };
+        context.derive(mb, state)?;
+
+        let num_comps = if self.chroma_array_type == 0 { 1 } else { 3 };
+        for comp in 0..num_comps {
+            let (width, height) = self.mb_size(comp);
+            let pred = self.inter_pred(comp, mb.mb_addr, state)?;
+
+            if comp == 0 || self.chroma_array_type == 3 {
+                let residual = self.inter_residual(mb, comp, state.qp_y);
+                self.put(comp, mb.mb_addr, 0, 0, width, height, &pred, &residual);
+            } else {
+                let residual = self.chroma_residual(mb, comp, state.qp_y, 3 + comp);
+                self.put(comp, mb.mb_addr, 0, 0, width, height, &pred, &residual);
+            }
+        }
+
+        Ok(())
+    }
+
+    // 8.4.2 Decoding process for inter prediction samples
+    //
+    // 按亮度 4x4 块 ( 以及对应的色度块 ) 进行预测, 每行 MbWidth 个样本
+    fn inter_pred(&self, comp: usize, mb_addr: u32, state: &MbState) -> Result<[i32; 256], Error> {
+        let (mb_width, mb_height) = self.mb_size(comp);
+        let (width, height) = ((mb_width / 4) as usize, (mb_height / 4) as usize);
+        let (origin_x, origin_y, step) = self.mb_origin(comp, mb_addr);
+        let bit_depth = self.bit_depth(comp);
+        let chroma = comp != 0 && self.chroma_array_type != 3;
+        let mb_parity = self.mb_parity(mb_addr);
+
+        // 场宏块在所在的场中预测: 宏块在场中的位置与场的奇偶
+        let (origin_y, curr_parity) = if step == 2 {
+            (origin_y / 2, Some((origin_y % 2) as usize))
+        } else {
+            (origin_y, None)
+        };
+
+        let mut pred = [0i32; 256];
+        for blk in 0..16 {
+            let (x, y) = ((blk % 4) as i32 * width as i32, (blk / 4) as i32 * height as i32);
+            let part = blk / 8 * 2 + blk % 4 / 2;
+
+            let mut samples = [[0i32; 16]; 2];
+            let mut used = [false; 2];
+            for list in 0..2 {
+                let ref_idx = state.ref_idx[list][part];
+                if ref_idx < 0 {
+                    continue;
+                }
+
+                let ref_pic = match self.refs.get_mb(list, ref_idx, mb_parity) {
+                    Some(ref_pic) => ref_pic,
+                    None => return Err(error::malformed("ref_idx refers to a missing reference picture")),
+                };
+                if ref_pic.parity.is_some() != curr_parity.is_some() {
+                    return Err(error::malformed("field macroblock refers to a frame, or frame macroblock to a field"));
+                }
+                let plane = RefPlane { plane: ref_pic.picture.plane(comp), parity: ref_pic.parity };
+                let mut mv = state.mv[list][blk];
+
+                if !chroma {
+                    inter::predict_luma(plane, origin_x + x, origin_y + y, mv, width, height, bit_depth, &mut samples[list]);
+                } else {
+                    // 8.4.1.4 Derivation process for chroma motion vectors: mvCLX = mvLX,
+                    // 单位为 1 / ( 4 * SubWidthC ) 与 1 / ( 4 * SubHeightC ) 个色度样本;
+                    // ChromaArrayType 为 1 的场宏块参考奇偶不同的场时按 Table 8-10 调整垂直分量
+                    if self.chroma_array_type == 1 {
+                        match (curr_parity, ref_pic.parity) {
+                            (Some(0), Some(1)) => mv[1] -= 2,
+                            (Some(1), Some(0)) => mv[1] += 2,
+                            _ => {},
+                        }
+                    }
+                    let (x_int, x_frac) = (origin_x + x + (mv[0] >> 3), mv[0] & 7);
+                    let (y_int, y_frac) = if mb_height == 8 {
+                        (origin_y + y + (mv[1] >> 3), mv[1] & 7)
+                    } else {
+                        (origin_y + y + (mv[1] >> 2), (mv[1] & 3) << 1)
+                    };
+                    inter::predict_chroma(plane, x_int, y_int, x_frac, y_frac, width, height, &mut samples[list]);
+                }
+                used[list] = true;
+            }
+
+            let size = width * height;
+            let weight = self.weight(comp, state.ref_idx[0][part], state.ref_idx[1][part], mb_parity);
+            let mut block = [0i32; 16];
+            inter::weighted_prediction([
+                if used[0] { Some(&samples[0][..size]) } else { None },
+                if used[1] { Some(&samples[1][..size]) } else { None },
+            ], weight.as_ref(), bit_depth, &mut block[..size]);
+
+            for j in 0..height {
+                for i in 0..width {
+                    pred[(y as usize + j) * mb_width as usize + x as usize + i] = block[j * width + i];
+                }
+            }
+        }
+
+        Ok(pred)
+    }
+
+    // 8.4.2.3 Weighted sample prediction process: 默认加权预测时返回 None;
+    // `mb_parity` 为 MBAFF 帧中的场宏块所在的场的奇偶
+    fn weight(&self, comp: usize, ref_idx_l0: i8, ref_idx_l1: i8, mb_parity: Option<usize>) -> Option<Weight> {
+        let slice_type = self.header.slice_type;
+        let bipredictive = slice_type.is_bipredictive();
+        let explicit = (self.pps.weighted_pred_flag() && !bipredictive) || (self.pps.weighted_bipred_idc() == 1 && bipredictive);
+
+        if explicit {
+            // 8.4.2.3 ( 8-287 ~ 8-292 ): 显式加权预测
+            let table = self.header.pred_weight_table.as_ref()?;
+            let shift = self.bit_depth(comp) as i32 - 8;
+            let log_wd = if comp == 0 {
+                table.luma_log2_weight_denom as i32
+            } else {
+                table.chroma_log2_weight_denom.unwrap_or(0) as i32
+            };
+
+            let mut weight = Weight { log_wd: log_wd, w: [1 << log_wd; 2], o: [0; 2] };
+            for (list, &ref_idx) in [ref_idx_l0, ref_idx_l1].iter().enumerate() {
+                if ref_idx < 0 {
+                    continue;
+                }
+
+                // refIdxL0WP, refIdxL1WP: MBAFF 帧中的场宏块为 refIdxLX >> 1
+                let ref_idx_wp = if mb_parity.is_some() { ref_idx >> 1 } else { ref_idx };
+                let entries = if list == 0 { &table.l0 } else { &table.l1 };
+                let value = entries.get(ref_idx_wp as usize).and_then(|entry| {
+                    if comp == 0 { entry.luma } else { entry.chroma.map(|chroma| chroma[comp - 1]) }
+                });
+                if let Some((w, o)) = value {
+                    weight.w[list] = w;
+                    weight.o[list] = o << shift;
+                }
+            }
+
+            return Some(weight);
+        }
+
+        if bipredictive && self.pps.weighted_bipred_idc() == 2 && ref_idx_l0 >= 0 && ref_idx_l1 >= 0 {
+            // 8.4.2.3 ( 8-274 ~ 8-277 ): 隐式加权预测
+            // currPicOrField, pic0, pic1: MBAFF 帧中的场宏块使用所在的场与参考场
+            let pic0 = self.refs.get_mb(0, ref_idx_l0, mb_parity)?;
+            let pic1 = self.refs.get_mb(1, ref_idx_l1, mb_parity)?;
+            let curr_poc = self.refs.curr_poc(mb_parity);
+            return Some(inter::implicit_weight(curr_poc, pic0.poc, pic1.poc, pic0.long_term || pic1.long_term));
+        }
+
+        None
+    }
+
+    // 8.3.5 Sample construction process for I_PCM macroblocks ( Page 170 )
+    fn pcm(&mut self, mb: &Macroblock) -> Result<(), Error> {
+        let samples = match mb.pcm_samples.as_ref() {
+            Some(samples) => samples,
+            None => return Err(error::malformed("I_PCM macroblock without samples")),
+        };
+
+        let zeros = [0i32; 256];
+        let luma: Vec<i32> = samples.luma.iter().map(|&sample| sample as i32).collect();
+        self.put(0, mb.mb_addr, 0, 0, 16, 16, &luma, &zeros);
+
+        if self.chroma_array_type != 0 {
+            let (width, height) = self.mb_size(1);
+            let size = (width * height) as usize;
+            if samples.chroma.len() != 2 * size {
+                return Err(error::malformed("I_PCM chroma sample count mismatch"));
+            }
+
+            for comp in 1..3 {
+                let chroma: Vec<i32> = samples.chroma[(comp - 1) * size..comp * size].iter().map(|&sample| sample as i32).collect();
+                self.put(comp, mb.mb_addr, 0, 0, width, height, &chroma, &zeros);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// 把 size x size 的块复制到宽度为 `stride` 的数组中的 ( x, y ) 处
+fn copy_block(block: &[i32], size: i32, dst: &mut [i32], stride: i32, x: i32, y: i32) {
+    for j in 0..size {
+        for i in 0..size {
+            dst[((y + j) * stride + x + i) as usize] = block[(j * size + i) as usize];
+        }
+    }
+}
@@ -0,0 +1,365 @@
+// 按访问单元删除, 插入 SEI 消息并重新写入 SEI NALU, 其它 NALU 原样输出。
+//
+// 7.4.1.2.3: SEI NALU 位于访问单元的第一个 VCL NALU 之前; D.2.1: buffering_period 必须是访问单元中第一个
+// SEI NALU 的第一个 SEI 消息。插入的消息放在一个新的 SEI NALU 中: 包含 buffering_period 时放在访问单元的
+// 第一个 SEI NALU 之前, 否则放在第一个 VCL NALU 之前; 访问单元中已有 buffering_period 时插入的 buffering_period
+// 替换它。没有改变的 NALU ( 包括无法解析的 NALU ) 保留原始字节。
+
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluHeader, NaluKind };
+use crate::rbsp::{ ParameterSets, SliceHeader, SupplementalEnhancementInformation, SeiMessage, SeiMessageKind };
+use crate::slice::Slice;
+use super::access_unit::PictureBoundary;
+
+use std::fmt;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+
+// 为访问单元 ( 在码流中的序号, 第一个 slice 的头部 ) 生成需要插入的 SEI 消息
+pub type SeiInjector = Box<dyn FnMut(u64, &SliceHeader) -> Vec<SeiMessage>>;
+
+// 访问单元中的一个 NALU
+#[derive(Debug)]
+struct AccessUnitNalu {
+    kind: NaluKind,
+    bytes: Vec<u8>,
+    sei: Option<SupplementalEnhancementInformation>,
+    // sei 中的消息被替换, 需要重新写入
+    edited: bool,
+}
+
+#[derive(Default)]
+pub struct SeiEditor {
+    parameter_sets: ParameterSets,
+    dropped_kinds: Vec<SeiMessageKind>,
+    injector: Option<SeiInjector>,
+    access_unit: Vec<AccessUnitNalu>,
+    // 当前访问单元中第一个 slice 的头部
+    first_header: Option<SliceHeader>,
+    boundary: PictureBoundary,
+    next_index: u64,
+    output: VecDeque<Vec<u8>>,
+}
+
+impl fmt::Debug for SeiEditor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SeiEditor {{ dropped_kinds: {:?}, injector: {}, next_index: {} }}",
+            self.dropped_kinds, self.injector.is_some(), self.next_index)
+    }
+}
+
+impl SeiEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 删除所有这种类型的 SEI 消息, 不再包含任何消息的 SEI NALU 也被删除
+    pub fn drop_kind(&mut self, kind: SeiMessageKind) {
+        if !self.dropped_kinds.contains(&kind) {
+            self.dropped_kinds.push(kind);
+        }
+    }
+
+    pub fn set_injector<F: FnMut(u64, &SliceHeader) -> Vec<SeiMessage> + 'static>(&mut self, injector: F) {
+        self.injector = Some(Box::new(injector));
+    }
+
+    // 输入一个 NALU 的原始字节 ( 不包括起始码 )
+    // 无法解析的 NALU 原样输出; 无法解析头部的 slice 视为当前访问单元的一部分
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let header = *bytes.first().ok_or_else(|| error::malformed("nal unit is empty"))?;
+        // forbidden_zero_bit 不为 0 的 NALU 与 nal_unit_type 为 0 的 NALU 一样不影响访问单元的边界
+        let kind = NaluHeader::try_from(header).map(|header| header.nal_unit_type()).unwrap_or(NaluKind::Unspecified(0));
+        let nalu = Nalu::try_from(bytes).ok();
+        if let Some(ref nalu) = nalu {
+            // 参数集只用于解析 slice 头部, 无法使用的参数集不影响输出
+            let _ = self.parameter_sets.update(nalu);
+        }
+
+        let mut sei = None;
+        match kind {
+            NaluKind::CodedSliceIdr | NaluKind::CodedSliceNonIdr => {
+                let header = nalu.as_ref().and_then(|nalu| Slice::parse_header(nalu, &self.parameter_sets).ok());
+                if let Some(header) = header {
+                    if self.boundary.is_new_picture(&header) {
+                        if self.first_header.is_some() {
+                            self.finish_access_unit();
+                        }
+                        self.first_header = Some(header);
+                    }
+                }
+            },
+            // 7.4.1.2.3: 这些 NALU 出现在最后一个 VCL NALU 之后时开始新的访问单元
+            NaluKind::SupplementalEnhancementInformation
+            | NaluKind::AccessUnitDelimiter
+            | NaluKind::SequenceParameterSet
+            | NaluKind::PictureParameterSet
+            | NaluKind::PrefixNALUnit
+            | NaluKind::SubsetSequenceParameterSet
+            | NaluKind::DepthParameterSet
+            | NaluKind::Reserved(17) | NaluKind::Reserved(18) => {
+                if self.first_header.is_some() {
+                    self.finish_access_unit();
+                }
+                if let Some(ref nalu) = nalu {
+                    if nalu.kind() == NaluKind::SupplementalEnhancementInformation {
+                        sei = Some(nalu.payload_downcast_ref::<SupplementalEnhancementInformation>().clone());
+                    }
+                }
+            },
+            _ => { },
+        }
+
+        self.access_unit.push(AccessUnitNalu {
+            kind: kind,
+            bytes: bytes.to_vec(),
+            sei: sei,
+            edited: false,
+        });
+
+        Ok(())
+    }
+
+    fn finish_access_unit(&mut self) {
+        let mut access_unit = std::mem::take(&mut self.access_unit);
+        let mut injected = match (self.first_header.take(), self.injector.as_mut()) {
+            (Some(header), Some(injector)) => {
+                let index = self.next_index;
+                self.next_index += 1;
+                injector(index, &header)
+            },
+            (Some(_), None) => {
+                self.next_index += 1;
+                vec![]
+            },
+            (None, _) => vec![],
+        };
+
+        // 访问单元中已有 ( 没有被删除的 ) buffering_period 时, 用插入的 buffering_period 替换它
+        let is_buffering_period = |message: &SeiMessage| message.kind == SeiMessageKind::BufferingPeriod;
+        if !self.dropped_kinds.contains(&SeiMessageKind::BufferingPeriod) {
+            let existing = access_unit.iter_mut().find(|nalu| {
+                nalu.sei.as_ref().is_some_and(|sei| sei.messages().iter().any(is_buffering_period))
+            });
+            if let (Some(nalu), Some(i)) = (existing, injected.iter().position(is_buffering_period)) {
+                let buffering_period = injected.remove(i);
+                let messages = nalu.sei.take().expect("sei with buffering_period").into_messages().into_iter()
+                    .map(|message| if is_buffering_period(&message) { buffering_period.clone() } else { message })
+                    .collect();
+                nalu.sei = SupplementalEnhancementInformation::new(messages).ok();
+                nalu.edited = true;
+            }
+        }
+
+        let mut injected_position = None;
+        if !injected.is_empty() {
+            let has_buffering_period = injected.iter().any(is_buffering_period);
+            injected_position = access_unit.iter().position(|nalu| match nalu.kind {
+                NaluKind::SupplementalEnhancementInformation => has_buffering_period,
+                NaluKind::CodedSliceIdr | NaluKind::CodedSliceNonIdr => true,
+                _ => false,
+            }).or(Some(access_unit.len()));
+        }
+
+        let mut injected = Some(injected);
+        for (i, nalu) in access_unit.into_iter().enumerate() {
+            if injected_position == Some(i) {
+                self.output_injected(injected.take().unwrap_or_default());
+            }
+            self.output_nalu(nalu);
+        }
+        if let Some(injected) = injected {
+            self.output_injected(injected);
+        }
+    }
+
+    fn output_injected(&mut self, mut messages: Vec<SeiMessage>) {
+        // buffering_period 在前, 其余按原来的顺序
+        messages.sort_by_key(|message| message.kind != SeiMessageKind::BufferingPeriod);
+        if let Ok(sei) = SupplementalEnhancementInformation::new(messages) {
+            self.output.push_back(sei.to_nalu_bytes());
+        }
+    }
+
+    fn output_nalu(&mut self, nalu: AccessUnitNalu) {
+        let sei = match nalu.sei {
+            Some(ref sei) if nalu.edited || sei.messages().iter().any(|message| self.dropped_kinds.contains(&message.kind)) => sei,
+            _ => {
+                self.output.push_back(nalu.bytes);
+                return;
+            },
+        };
+
+        let messages: Vec<SeiMessage> = sei.messages().iter()
+            .filter(|message| !self.dropped_kinds.contains(&message.kind))
+            .cloned()
+            .collect();
+        if let Ok(sei) = SupplementalEnhancementInformation::new(messages) {
+            self.output.push_back(sei.to_nalu_bytes());
+        }
+    }
+
+    // 码流结束: 输出最后一个访问单元
+    pub fn flush(&mut self) {
+        self.finish_access_unit();
+    }
+
+    // 取出下一个输出的 NALU 的原始字节 ( 不包括起始码 )
+    pub fn next_nalu(&mut self) -> Option<Vec<u8>> {
+        self.output.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::SeiEditor;
+    use crate::nalu::{ Nalu, NaluKind };
+    use crate::rbsp::{
+        SupplementalEnhancementInformation, SeiMessage, SeiMessageKind, SeiPayload, UserDataUnregistered, RecoveryPoint,
+    };
+    use crate::slice::test::{ Writer, parameter_set_bytes };
+
+    use std::convert::TryFrom;
+
+    fn user_data(id: u8) -> SeiMessage {
+        SeiMessage::new(SeiPayload::UserDataUnregistered(UserDataUnregistered {
+            uuid_iso_iec_11578: [id; 16],
+            payload: vec![id],
+        })).unwrap()
+    }
+
+    fn recovery_point() -> SeiMessage {
+        SeiMessage::new(SeiPayload::RecoveryPoint(RecoveryPoint {
+            recovery_frame_cnt: 3,
+            exact_match_flag: false,
+            broken_link_flag: true,
+            changing_slice_group_idc: 0,
+        })).unwrap()
+    }
+
+    fn kinds(bytes: &[u8]) -> (NaluKind, Vec<SeiMessageKind>) {
+        let nalu = Nalu::try_from(bytes).unwrap();
+        let kinds = match nalu.kind() {
+            NaluKind::SupplementalEnhancementInformation => {
+                nalu.payload_downcast_ref::<SupplementalEnhancementInformation>().messages().iter()
+                    .map(|message| message.kind)
+                    .collect()
+            },
+            _ => vec![],
+        };
+        (nalu.kind(), kinds)
+    }
+
+    #[test]
+    fn test_sei_writer() {
+        // 写入后再解析得到相同的消息; payloadSize 大于 255 时使用 ff_byte
+        let mut large = user_data(7);
+        if let SeiPayload::UserDataUnregistered(ref mut payload) = large.payload {
+            payload.payload = vec![0; 300];
+        }
+        let large = SeiMessage::new(large.payload).unwrap();
+        let messages = vec![user_data(1), recovery_point(), large];
+        let sei = SupplementalEnhancementInformation::new(messages.clone()).unwrap();
+        let bytes = sei.to_nalu_bytes();
+        assert_eq!(bytes[..3], [0x06, 5, 17]);
+
+        let nalu = Nalu::try_from(&bytes[..]).unwrap();
+        let parsed = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+        assert_eq!(parsed.messages(), &messages[..]);
+        assert_eq!(parsed.messages()[2].payload_size(), 316);
+        assert!(SupplementalEnhancementInformation::new(vec![]).is_err());
+        assert!(SeiMessage::new(SeiPayload::Raw).is_err());
+    }
+
+    #[test]
+    fn test_sei_editor() {
+        let (sps, pps) = parameter_set_bytes();
+        let idr = Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish_bytes();
+        let p = Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 1).u(4, 2).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish_bytes();
+        let sei = |messages: Vec<SeiMessage>| SupplementalEnhancementInformation::new(messages).unwrap().to_nalu_bytes();
+
+        let mut editor = SeiEditor::new();
+        editor.drop_kind(SeiMessageKind::UserDataUnregistered);
+        // 第一个访问单元插入 buffering_period 与帧号, 第二个访问单元插入帧号与恢复点
+        editor.set_injector(|index, header| {
+            let mut messages = vec![user_data(100 + index as u8)];
+            if header.idr_pic_flag() {
+                messages.push(SeiMessage::from_bytes(SeiMessageKind::BufferingPeriod, vec![0x80]));
+            } else {
+                messages.push(recovery_point());
+            }
+            messages
+        });
+
+        let input = [
+            sps.clone(), pps.clone(), sei(vec![user_data(1), recovery_point()]), idr.clone(),
+            sei(vec![user_data(2)]), p.clone(),
+        ];
+        for bytes in input.iter() {
+            editor.push(bytes).unwrap();
+        }
+        editor.flush();
+
+        let mut output = vec![];
+        while let Some(bytes) = editor.next_nalu() {
+            output.push(bytes);
+        }
+        assert_eq!(output.len(), 7);
+        assert_eq!(output[0], sps);
+        assert_eq!(output[1], pps);
+        assert_eq!(kinds(&output[2]), (
+            NaluKind::SupplementalEnhancementInformation,
+            vec![SeiMessageKind::BufferingPeriod, SeiMessageKind::UserDataUnregistered],
+        ));
+        assert_eq!(kinds(&output[3]), (NaluKind::SupplementalEnhancementInformation, vec![SeiMessageKind::RecoveryPoint]));
+        assert_eq!(output[4], idr);
+        assert_eq!(kinds(&output[5]), (
+            NaluKind::SupplementalEnhancementInformation,
+            vec![SeiMessageKind::UserDataUnregistered, SeiMessageKind::RecoveryPoint],
+        ));
+        assert_eq!(output[6], p);
+
+        let nalu = Nalu::try_from(&output[5][..]).unwrap();
+        match nalu.payload_downcast_ref::<SupplementalEnhancementInformation>().messages()[0].payload {
+            SeiPayload::UserDataUnregistered(ref user_data) => assert_eq!(user_data.payload, vec![101]),
+            ref payload => panic!("unexpected payload {:?}", payload),
+        }
+    }
+
+    #[test]
+    fn test_sei_editor_replaces_buffering_period() {
+        let (sps, pps) = parameter_set_bytes();
+        let idr = Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish_bytes();
+        let p = Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 1).u(4, 2).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish_bytes();
+        let buffering_period = |byte| SeiMessage::from_bytes(SeiMessageKind::BufferingPeriod, vec![byte]);
+        let existing = SupplementalEnhancementInformation::new(vec![buffering_period(0x40), recovery_point()]).unwrap();
+        // payloadSize 超出 NALU 的 SEI 无法解析
+        let broken = vec![0x06, 0x05, 0x10, 0x00, 0x80];
+        assert!(Nalu::try_from(&broken[..]).is_err());
+
+        let mut editor = SeiEditor::new();
+        editor.set_injector(move |_, _| vec![buffering_period(0x80)]);
+        let input = [sps.clone(), pps.clone(), existing.to_nalu_bytes(), idr.clone(), broken.clone(), p.clone()];
+        for bytes in input.iter() {
+            editor.push(bytes).unwrap();
+        }
+        editor.flush();
+
+        let output: Vec<Vec<u8>> = std::iter::from_fn(|| editor.next_nalu()).collect();
+        assert_eq!(output.len(), 7);
+        assert_eq!(output[..2], [sps, pps]);
+        // 第一个访问单元中已有的 buffering_period 被替换, 没有插入新的 SEI NALU
+        let nalu = Nalu::try_from(&output[2][..]).unwrap();
+        let messages = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>().messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].as_bytes(), [0x80]);
+        assert_eq!(messages[1].kind, SeiMessageKind::RecoveryPoint);
+        assert_eq!(output[3], idr);
+        // 无法解析的 SEI NALU 原样输出, 插入的 buffering_period 在它之前
+        assert_eq!(kinds(&output[4]), (NaluKind::SupplementalEnhancementInformation, vec![SeiMessageKind::BufferingPeriod]));
+        assert_eq!(output[5], broken);
+        assert_eq!(output[6], p);
+    }
+}
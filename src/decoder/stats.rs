@@ -0,0 +1,293 @@
+// 每个宏块的 QPY 与比特数 ( 宏块头部, 运动信息, 残差 ), 以及每个图像的统计, 用于码率控制的分析。
+// 只解析语法元素, 不重建样本。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ ParameterSets, SliceHeader, SliceType, SequenceParameterSet, PictureParameterSet, PictureStructure };
+use crate::macroblock::{ self, MbType, MbBits };
+use crate::slice::Slice;
+use super::{ first_vcl_nal_unit_of_picture, second_field_of_pair };
+
+use std::collections::VecDeque;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MbStats {
+    pub mb_addr: u32,
+    pub mb_type: MbType,
+    // QPY ( SliceQPY 加上之前各宏块的 mb_qp_delta )
+    pub qp_y: i32,
+    pub bits: MbBits,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceStats {
+    pub first_mb_in_slice: u32,
+    pub slice_type: SliceType,
+    // SliceQPY
+    pub slice_qp_y: i32,
+    // 按解码顺序排列, 包括跳过的宏块
+    pub macroblocks: Vec<MbStats>,
+}
+
+impl SliceStats {
+    pub fn new(slice: &Slice, sps: &SequenceParameterSet, pps: &PictureParameterSet) -> Self {
+        let qp_bd_offset_y = sps.qp_bd_offset_luma();
+        let slice_qp_y = slice.header.slice_qp_y(pps);
+        let mut qp_y = slice_qp_y;
+
+        let macroblocks = slice.data.macroblocks.iter()
+            .map(|mb| {
+                qp_y = macroblock::qp_y(qp_y, mb.mb_qp_delta, qp_bd_offset_y);
+                MbStats {
+                    mb_addr: mb.mb_addr,
+                    mb_type: mb.mb_type,
+                    qp_y: qp_y,
+                    bits: mb.bits,
+                }
+            })
+            .collect();
+
+        SliceStats {
+            first_mb_in_slice: slice.header.first_mb_in_slice,
+            slice_type: slice.header.slice_type,
+            slice_qp_y: slice_qp_y,
+            macroblocks: macroblocks,
+        }
+    }
+
+    pub fn bits(&self) -> MbBits {
+        sum_bits(self.macroblocks.iter())
+    }
+}
+
+fn sum_bits<'a>(macroblocks: impl Iterator<Item = &'a MbStats>) -> MbBits {
+    macroblocks.fold(MbBits::default(), |sum, mb| MbBits {
+        header: sum.header + mb.bits.header,
+        motion: sum.motion + mb.bits.motion,
+        residual: sum.residual + mb.bits.residual,
+    })
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PictureStats {
+    pub frame_num: u32,
+    pub idr: bool,
+    pub width_in_mbs: u32,
+    pub height_in_mbs: u32,
+    // 帧, 顶场或底场; second_field 表示与前一个场组成互补场对
+    pub structure: PictureStructure,
+    pub second_field: bool,
+    // MbaffFrameFlag: mb_addr 按宏块对排列
+    pub mbaff: bool,
+    // 按解码顺序排列
+    pub slices: Vec<SliceStats>,
+}
+
+impl PictureStats {
+    pub fn macroblocks(&self) -> impl Iterator<Item = &MbStats> {
+        self.slices.iter().flat_map(|slice| slice.macroblocks.iter())
+    }
+
+    // 6.4.1: 宏块按光栅顺序的下标, MBAFF 帧中宏块对的两个宏块上下相邻
+    fn raster_index(&self, mb_addr: u32) -> usize {
+        if !self.mbaff {
+            return mb_addr as usize;
+        }
+
+        let pair = mb_addr / 2;
+        ((pair / self.width_in_mbs * 2 + mb_addr % 2) * self.width_in_mbs + pair % self.width_in_mbs) as usize
+    }
+
+    // 按光栅顺序的 QPY, 没有解码的宏块为 None
+    pub fn qp_map(&self) -> Vec<Option<i32>> {
+        let mut map = vec![None; (self.width_in_mbs * self.height_in_mbs) as usize];
+        for mb in self.macroblocks() {
+            if let Some(entry) = map.get_mut(self.raster_index(mb.mb_addr)) {
+                *entry = Some(mb.qp_y);
+            }
+        }
+        map
+    }
+
+    // 按光栅顺序的比特数, 没有解码的宏块为 None
+    pub fn bits_map(&self) -> Vec<Option<MbBits>> {
+        let mut map = vec![None; (self.width_in_mbs * self.height_in_mbs) as usize];
+        for mb in self.macroblocks() {
+            if let Some(entry) = map.get_mut(self.raster_index(mb.mb_addr)) {
+                *entry = Some(mb.bits);
+            }
+        }
+        map
+    }
+
+    pub fn bits(&self) -> MbBits {
+        sum_bits(self.macroblocks())
+    }
+
+    pub fn num_macroblocks(&self) -> usize {
+        self.macroblocks().count()
+    }
+
+    pub fn num_skipped(&self) -> usize {
+        self.macroblocks().filter(|mb| mb.mb_type.is_skip()).count()
+    }
+
+    pub fn average_qp(&self) -> Option<f64> {
+        let num_macroblocks = self.num_macroblocks();
+        if num_macroblocks == 0 {
+            return None;
+        }
+
+        let sum: i64 = self.macroblocks().map(|mb| mb.qp_y as i64).sum();
+        Some(sum as f64 / num_macroblocks as f64)
+    }
+
+    // ( 最小, 最大 ) QPY
+    pub fn qp_range(&self) -> Option<(i32, i32)> {
+        let min = self.macroblocks().map(|mb| mb.qp_y).min()?;
+        let max = self.macroblocks().map(|mb| mb.qp_y).max()?;
+        Some((min, max))
+    }
+}
+
+
+// 按解码顺序输入 NALU, 每个图像完成后产生一个 PictureStats
+#[derive(Debug, Default)]
+pub struct StatsCollector {
+    parameter_sets: ParameterSets,
+    // 当前图像最近一个 slice 的头部
+    current: Option<(SliceHeader, PictureStats)>,
+    pictures: VecDeque<PictureStats>,
+}
+
+impl StatsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parameter_sets(&self) -> &ParameterSets {
+        &self.parameter_sets
+    }
+
+    // 处理一个 NALU; 参数集会被记录下来, 非 VCL NALU 被忽略
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        if self.parameter_sets.update(nalu)? {
+            return Ok(());
+        }
+
+        match nalu.kind() {
+            NaluKind::CodedSliceIdr | NaluKind::CodedSliceNonIdr => { },
+            _ => return Ok(()),
+        }
+
+        let slice = Slice::parse(nalu, &self.parameter_sets)?;
+        let pps = self.parameter_sets.pps(slice.header.pic_parameter_set_id).expect("pps has been checked by the slice header");
+        let sps = self.parameter_sets.sps(pps.seq_parameter_set_id()).expect("sps has been checked by the slice header");
+
+        let new_picture = match self.current {
+            Some((ref header, _)) => first_vcl_nal_unit_of_picture(header, &slice.header),
+            None => true,
+        };
+
+        if new_picture {
+            let mut second_field = false;
+            if let Some((header, picture)) = self.current.take() {
+                second_field = !picture.second_field && second_field_of_pair(&header, &slice.header);
+                self.pictures.push_back(picture);
+            }
+
+            let width_in_mbs = sps.pic_width_in_mbs();
+            let picture = PictureStats {
+                frame_num: slice.header.frame_num,
+                idr: slice.header.idr_pic_flag(),
+                width_in_mbs: width_in_mbs,
+                height_in_mbs: slice.header.pic_size_in_mbs(sps) / width_in_mbs,
+                structure: slice.header.structure(),
+                second_field: second_field,
+                mbaff: slice.header.mbaff_frame_flag(sps),
+                slices: vec![],
+            };
+            self.current = Some((slice.header.clone(), picture));
+        }
+
+        let current = self.current.as_mut().expect("current picture");
+        current.1.slices.push(SliceStats::new(&slice, sps, pps));
+        current.0 = slice.header;
+
+        Ok(())
+    }
+
+    // 码流结束: 完成当前图像
+    pub fn flush(&mut self) {
+        if let Some((_, picture)) = self.current.take() {
+            self.pictures.push_back(picture);
+        }
+    }
+
+    // 取出下一个 ( 按解码顺序 ) 已完成的图像
+    pub fn next_picture(&mut self) -> Option<PictureStats> {
+        self.pictures.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::slice::test::{ Writer, parameter_sets };
+    use crate::macroblock::MbBits;
+    use super::StatsCollector;
+
+    #[test]
+    fn test_macroblock_stats() {
+        let mut collector = StatsCollector::new();
+        collector.parameter_sets = parameter_sets();
+
+        // I_16x16_2_0_0 ( mb_qp_delta -1 ) + I_PCM, 见 slice::test::test_intra_slice
+        let mut writer = Writer::new(0x65);
+        writer.ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        writer.ue(3).ue(0).se(-1).bits("1");
+        writer.ue(25).align();
+        for _ in 0..384 {
+            writer.u(8, 128);
+        }
+        collector.push(&writer.finish()).unwrap();
+
+        // P_Skip + P_L0_16x16, 见 slice::test::test_predicted_slice
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        writer.ue(1).ue(0).se(1).se(-2).ue(2).se(0);
+        writer.bits("000010001110010111101101").bits("1111").bits("11").bits("1");
+        collector.push(&writer.finish()).unwrap();
+        collector.flush();
+
+        let idr = collector.next_picture().unwrap();
+        assert!(idr.idr);
+        assert_eq!(idr.qp_map(), vec![Some(25), Some(25)]);
+        // mb_type ( 5 ), intra_chroma_pred_mode ( 1 ), mb_qp_delta ( 3 ); coeff_token ( 1 )
+        // mb_type ( 9 ); pcm_alignment_zero_bit ( 5 ) 与 384 个样本
+        assert_eq!(idr.bits_map(), vec![
+            Some(MbBits { header: 9, motion: 0, residual: 1 }),
+            Some(MbBits { header: 9, motion: 0, residual: 5 + 384 * 8 }),
+        ]);
+        assert_eq!(idr.qp_range(), Some((25, 25)));
+
+        let picture = collector.next_picture().unwrap();
+        assert!(collector.next_picture().is_none());
+        assert_eq!(picture.frame_num, 1);
+        assert_eq!(picture.num_skipped(), 1);
+        assert_eq!(picture.average_qp(), Some(26.0));
+        // mb_skip_run 计入第一个跳过的宏块; mb_type ( 1 ), coded_block_pattern ( 3 ), mb_qp_delta ( 1 ); mvd ( 3 + 5 )
+        assert_eq!(picture.bits_map(), vec![
+            Some(MbBits { header: 3, motion: 0, residual: 0 }),
+            Some(MbBits { header: 5, motion: 8, residual: 31 }),
+        ]);
+        assert_eq!(picture.bits().total(), 3 + 5 + 8 + 31);
+        assert_eq!(picture.slices[0].slice_qp_y, 26);
+    }
+}
@@ -0,0 +1,401 @@
+// 由 pic_timing SEI 的 clock_timestamp 得到按输出顺序的 SMPTE 12M 时间码 ( 每个显示的帧或场一个 )。
+//
+// D.2.3: n_frames 的计数单位为 num_units_in_tick * ( 1 + nuit_field_based_flag ), 即每秒
+// time_scale / ( num_units_in_tick * ( 1 + nuit_field_based_flag ) ) 个; counting_type 为 4 时,
+// 除每十分钟外, 每分钟开始 ( seconds_value 为 0 ) 时丢弃 n_frames 为 0 与 1 的计数 ( drop-frame ), 与计数的频率无关。
+// 字幕导出的时间码 ( caption::Timebase ) 按 SMPTE ST 12-1, 59.94 时每分钟丢弃 4 个帧号, 见 from_smpte_frame_count。
+
+use crate::error::Error;
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{
+    ParameterSets, SliceHeader, SupplementalEnhancementInformation, SeiMessage, SeiMessageKind, PictureStructure,
+    ClockTimestamp,
+};
+use super::access_unit::AccessUnitTracker;
+
+use std::fmt;
+use std::collections::{ HashMap, VecDeque };
+
+
+// Table D-2 – Definition of counting_type values: 4 为 drop-frame
+const COUNTING_TYPE_DROP_FRAME: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub drop_frame: bool,
+}
+
+// Table D-2: counting_type 为 4 时每分钟 ( 每十分钟除外 ) 丢弃的计数
+const DROPPED_COUNTS_PER_MINUTE: i64 = 2;
+
+impl Timecode {
+    // 从 00:00:00:00 开始的计数 ( rate 为每秒的计数 ), drop-frame 时减去丢弃的计数
+    pub fn frame_count(&self, rate: u32) -> i64 {
+        let minutes = i64::from(self.hours) * 60 + i64::from(self.minutes);
+        let count = (minutes * 60 + i64::from(self.seconds)) * i64::from(rate) + i64::from(self.frames);
+        if self.drop_frame {
+            count - DROPPED_COUNTS_PER_MINUTE * (minutes - minutes / 10)
+        } else {
+            count
+        }
+    }
+
+    pub fn from_frame_count(count: i64, rate: u32, drop_frame: bool) -> Self {
+        Self::from_count(count, rate, if drop_frame { Some(DROPPED_COUNTS_PER_MINUTE) } else { None })
+    }
+
+    // SMPTE ST 12-1 的帧号: drop-frame 时 29.97 每分钟丢弃 2 个, 59.94 丢弃 4 个, 只用于字幕导出
+    pub(crate) fn from_smpte_frame_count(count: i64, rate: u32, drop_frame: bool) -> Self {
+        let drop = 2 * ((i64::from(rate) + 15) / 30).max(1);
+        Self::from_count(count, rate, if drop_frame { Some(drop) } else { None })
+    }
+
+    // `dropped`: drop-frame 时每分钟丢弃的计数
+    fn from_count(count: i64, rate: u32, dropped: Option<i64>) -> Self {
+        let rate = i64::from(rate.max(1));
+        let mut count = count.max(0);
+        if let Some(drop) = dropped {
+            let frames_per_minute = rate * 60 - drop;
+            let frames_per_10_minutes = rate * 600 - drop * 9;
+            let tens = count / frames_per_10_minutes;
+            let rest = count % frames_per_10_minutes;
+            let dropped = if rest < drop { 0 } else { (rest - drop) / frames_per_minute };
+            count += drop * 9 * tens + drop * dropped;
+        }
+
+        Self {
+            hours: (count / (rate * 3600) % 24) as u8,
+            minutes: (count / (rate * 60) % 60) as u8,
+            seconds: (count / rate % 60) as u8,
+            frames: (count % rate) as u8,
+            drop_frame: dropped.is_some(),
+        }
+    }
+}
+
+// HH:MM:SS:FF, drop-frame 时为 HH:MM:SS;FF
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if self.drop_frame { ';' } else { ':' };
+        write!(f, "{:02}:{:02}:{:02}{}{:02}", self.hours, self.minutes, self.seconds, separator, self.frames)
+    }
+}
+
+// 一个显示的帧或场的时间码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimecodeEntry {
+    // Frame, TopField 或 BottomField
+    pub structure: PictureStructure,
+    pub timecode: Timecode,
+    pub discontinuity_flag: bool,
+    pub time_offset: i32,
+}
+
+// 按输出顺序的一个图像以及它的 pic_timing 中的时间码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimecodePicture {
+    // PictureReport::id
+    pub id: u64,
+    pub pic_order_cnt: i32,
+    pub output_index: u64,
+    pub pic_struct: Option<u8>,
+    // 每秒的 n_frames 计数 ( 取整 ), VUI 中没有 timing_info 时为 None
+    pub rate: Option<u32>,
+    // 按显示顺序, 只包括 clock_timestamp_flag 为 1 的时间戳
+    pub timecodes: Vec<TimecodeEntry>,
+}
+
+// 按输出顺序相邻的两个时间码不连续: discontinuity_flag 为 1, 或者计数之差不是 0 或 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimecodeDiscontinuity {
+    pub output_index: u64,
+    pub previous: Timecode,
+    pub current: Timecode,
+    pub flagged: bool,
+}
+
+// Table D-1 – Interpretation of pic_struct: 每个时间戳对应的帧或场
+fn display_structures(pic_struct: u8) -> &'static [PictureStructure] {
+    use crate::rbsp::PictureStructure::*;
+
+    match pic_struct {
+        1 => &[TopField],
+        2 => &[BottomField],
+        3 => &[TopField, BottomField],
+        4 => &[BottomField, TopField],
+        5 => &[TopField, BottomField, TopField],
+        6 => &[BottomField, TopField, BottomField],
+        7 => &[Frame, Frame],
+        8 => &[Frame, Frame, Frame],
+        _ => &[Frame],
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct PictureTimecodes {
+    pic_struct: Option<u8>,
+    rate: Option<u32>,
+    timecodes: Vec<TimecodeEntry>,
+}
+
+// 图像开始时用激活的 SPS 解析访问单元中的 pic_timing
+fn start_picture(
+    prev_time: &mut (u8, u8, u8), parameter_sets: &ParameterSets, header: &SliceHeader, pic_timing: Option<SeiMessage>,
+) -> Result<PictureTimecodes, Error> {
+    let mut picture = PictureTimecodes::default();
+
+    if let Some(message) = pic_timing {
+        let sps = parameter_sets.pps(header.pic_parameter_set_id)
+            .and_then(|pps| parameter_sets.sps(pps.seq_parameter_set_id()))
+            .expect("sps has been checked by the slice header");
+        let timing_info = sps.vui_parameters().and_then(|vui| match (vui.num_units_in_tick, vui.time_scale) {
+            (Some(num_units_in_tick), Some(time_scale)) if num_units_in_tick > 0 => Some((num_units_in_tick, time_scale)),
+            _ => None,
+        });
+
+        if let Some(pic_timing) = message.pic_timing(sps)? {
+            picture.pic_struct = pic_timing.pic_struct;
+            let structures = display_structures(pic_timing.pic_struct.unwrap_or(0));
+            for (clock_timestamp, &structure) in pic_timing.clock_timestamps.iter().zip(structures) {
+                if let Some(clock_timestamp) = clock_timestamp {
+                    picture.rate = timing_info.map(|(num_units_in_tick, time_scale)| {
+                        let unit = u64::from(num_units_in_tick) * (1 + clock_timestamp.nuit_field_based_flag as u64);
+                        ((u64::from(time_scale) + unit / 2) / unit).max(1) as u32
+                    });
+                    picture.timecodes.push(timecode(prev_time, clock_timestamp, structure));
+                }
+            }
+        }
+    }
+
+    Ok(picture)
+}
+
+// D.2.3: full_timestamp_flag 为 0 时不存在的 seconds_value, minutes_value, hours_value 与前一个时间戳相同
+fn timecode(prev_time: &mut (u8, u8, u8), clock_timestamp: &ClockTimestamp, structure: PictureStructure) -> TimecodeEntry {
+    let (prev_hours, prev_minutes, prev_seconds) = *prev_time;
+    let hours = clock_timestamp.hours_value.unwrap_or(prev_hours);
+    let minutes = clock_timestamp.minutes_value.unwrap_or(prev_minutes);
+    let seconds = clock_timestamp.seconds_value.unwrap_or(prev_seconds);
+    *prev_time = (hours, minutes, seconds);
+
+    TimecodeEntry {
+        structure: structure,
+        timecode: Timecode {
+            hours: hours,
+            minutes: minutes,
+            seconds: seconds,
+            frames: clock_timestamp.n_frames,
+            drop_frame: clock_timestamp.counting_type == COUNTING_TYPE_DROP_FRAME,
+        },
+        discontinuity_flag: clock_timestamp.discontinuity_flag,
+        time_offset: clock_timestamp.time_offset,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TimecodeCollector {
+    // 访问单元中的 pic_timing ( 需要激活的 SPS 才能解析, 在图像开始时解析 )
+    tracker: AccessUnitTracker<Option<SeiMessage>, PictureTimecodes>,
+    // 按解码顺序最近的 ( hours, minutes, seconds ), 用于推断不存在的值
+    prev_time: (u8, u8, u8),
+    by_id: HashMap<u64, PictureTimecodes>,
+    prev_timecode: Option<(Timecode, Option<u32>)>,
+    discontinuities: Vec<TimecodeDiscontinuity>,
+    pictures: VecDeque<TimecodePicture>,
+}
+
+impl TimecodeCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        match nalu.kind() {
+            NaluKind::SupplementalEnhancementInformation => {
+                let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+                for message in sei.messages() {
+                    if message.kind == SeiMessageKind::PicTiming {
+                        *self.tracker.access_unit() = Some(message.clone());
+                    }
+                }
+                Ok(())
+            },
+            _ => {
+                let prev_time = &mut self.prev_time;
+                self.tracker.push(nalu, |parameter_sets, header, pic_timing| {
+                    start_picture(prev_time, parameter_sets, header, pic_timing)
+                })?;
+                self.drain();
+                Ok(())
+            },
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Some((report, picture)) = self.tracker.next_report() {
+            // 互补场对: 两个场的时间码按解码顺序合并
+            let entry = self.by_id.entry(report.id).or_default();
+            entry.pic_struct = picture.pic_struct.or(entry.pic_struct);
+            entry.rate = picture.rate.or(entry.rate);
+            entry.timecodes.extend(picture.timecodes);
+        }
+
+        while let Some(output) = self.tracker.next_output() {
+            let picture = self.by_id.remove(&output.id).unwrap_or_default();
+
+            for entry in picture.timecodes.iter() {
+                if let Some((previous, rate)) = self.prev_timecode {
+                    let delta = rate.or(picture.rate)
+                        .map(|rate| entry.timecode.frame_count(rate) - previous.frame_count(rate));
+                    let continuous = delta.is_none_or(|delta| delta == 0 || delta == 1);
+                    if entry.discontinuity_flag || !continuous {
+                        self.discontinuities.push(TimecodeDiscontinuity {
+                            output_index: output.output_index,
+                            previous: previous,
+                            current: entry.timecode,
+                            flagged: entry.discontinuity_flag,
+                        });
+                    }
+                }
+                self.prev_timecode = Some((entry.timecode, picture.rate));
+            }
+
+            self.pictures.push_back(TimecodePicture {
+                id: output.id,
+                pic_order_cnt: output.pic_order_cnt,
+                output_index: output.output_index,
+                pic_struct: picture.pic_struct,
+                rate: picture.rate,
+                timecodes: picture.timecodes,
+            });
+        }
+    }
+
+    // 码流结束: 输出所有等待输出的图像
+    pub fn flush(&mut self) {
+        self.tracker.flush();
+        self.drain();
+    }
+
+    // 取出下一个 ( 按输出顺序 ) 输出的图像
+    pub fn next_picture(&mut self) -> Option<TimecodePicture> {
+        self.pictures.pop_front()
+    }
+
+    // 到目前为止输出的图像中的不连续
+    pub fn discontinuities(&self) -> &[TimecodeDiscontinuity] {
+        &self.discontinuities
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ TimecodeCollector, Timecode };
+    use crate::nalu::Nalu;
+    use crate::slice::test::{ Writer, parameter_set_nalus };
+    use crate::rbsp::PictureStructure;
+
+    // pic_struct 0, drop-frame ( counting_type 4 ), time_offset 为 0
+    fn pic_timing_sei(nuit_field_based_flag: u32, n_frames: u32, time: Option<(u32, u32, u32)>) -> Nalu {
+        let mut writer = Writer::new(0x06);
+        match time {
+            Some((hours, minutes, seconds)) => {
+                writer.u(8, 1).u(8, 9).u(4, 0).bits("1").u(2, 0).u(1, nuit_field_based_flag).u(5, 4).bits("100").u(8, n_frames);
+                writer.u(6, seconds).u(6, minutes).u(5, hours);
+            },
+            None => {
+                writer.u(8, 1).u(8, 7).u(4, 0).bits("1").u(2, 0).u(1, nuit_field_based_flag).u(5, 4).bits("000").u(8, n_frames).bits("0");
+            },
+        }
+        writer.u(24, 0).bits("1").align();
+        writer.finish()
+    }
+
+    #[test]
+    fn test_timecode() {
+        assert_eq!(Timecode::from_frame_count(1800, 30, true).to_string(), "00:01:00;02");
+        assert_eq!(Timecode::from_frame_count(17982, 30, true).to_string(), "00:10:00;00");
+        assert_eq!(Timecode::from_frame_count(3600 * 25 + 1, 25, false).to_string(), "01:00:00:01");
+        let timecode = Timecode { hours: 1, minutes: 23, seconds: 45, frames: 12, drop_frame: true };
+        assert_eq!(Timecode::from_frame_count(timecode.frame_count(30), 30, true), timecode);
+        // 每秒 60 个计数时 clock_timestamp 仍然只丢弃 2 个计数, SMPTE ST 12-1 的帧号丢弃 4 个
+        assert_eq!(Timecode::from_frame_count(3600, 60, true).to_string(), "00:01:00;02");
+        assert_eq!(Timecode::from_frame_count(35982, 60, true).to_string(), "00:10:00;00");
+        assert_eq!(Timecode::from_frame_count(timecode.frame_count(60), 60, true), timecode);
+        assert_eq!(Timecode::from_smpte_frame_count(3600, 60, true).to_string(), "00:01:00;04");
+        assert_eq!(Timecode::from_smpte_frame_count(35964, 60, true).to_string(), "00:10:00;00");
+
+        // VUI: 1001 / 60000, pic_struct_present_flag
+        let (_, pps) = parameter_set_nalus();
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 1)
+            .u(1, 0).u(1, 0).u(1, 0).u(1, 0)
+            .u(1, 1).u(32, 1001).u(32, 60000).u(1, 1)
+            .u(1, 0).u(1, 0).u(1, 1).u(1, 0)
+            .finish();
+        let mut collector = TimecodeCollector::new();
+        collector.push(&sps).unwrap();
+        collector.push(&pps).unwrap();
+
+        // 解码顺序: 00:00:59;29 ( POC 0 ), 00:01:00;03 ( POC 4 ), ;02 ( POC 2, 时分秒与前一个相同 ), 00:01:05;00 ( POC 6 )
+        let slices = [
+            Writer::new(0x65).ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0).u(1, 0).u(1, 0).se(0).ue(1).finish(),
+            Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 1).u(4, 4).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish(),
+            Writer::new(0x01).ue(0).ue(5).ue(0).u(4, 2).u(4, 2).u(1, 0).u(1, 0).se(0).ue(1).finish(),
+            Writer::new(0x41).ue(0).ue(5).ue(0).u(4, 2).u(4, 6).u(1, 0).u(1, 0).u(1, 0).se(0).ue(1).finish(),
+        ];
+        let seis = [
+            pic_timing_sei(1, 29, Some((0, 0, 59))), pic_timing_sei(1, 3, Some((0, 1, 0))), pic_timing_sei(1, 2, None),
+            pic_timing_sei(1, 0, Some((0, 1, 5))),
+        ];
+        for (sei, slice) in seis.iter().zip(slices.iter()) {
+            collector.push(sei).unwrap();
+            collector.push(slice).unwrap();
+        }
+        collector.flush();
+
+        let mut timecodes = vec![];
+        while let Some(picture) = collector.next_picture() {
+            assert_eq!(picture.rate, Some(30));
+            assert_eq!(picture.timecodes[0].structure, PictureStructure::Frame);
+            timecodes.push(picture.timecodes[0].timecode.to_string());
+        }
+        assert_eq!(timecodes, vec!["00:00:59;29", "00:01:00;02", "00:01:00;03", "00:01:05;00"]);
+
+        let discontinuities = collector.discontinuities();
+        assert_eq!(discontinuities.len(), 1);
+        assert_eq!(discontinuities[0].output_index, 3);
+        assert_eq!(discontinuities[0].previous.to_string(), "00:01:00;03");
+        assert!(!discontinuities[0].flagged);
+
+        // nuit_field_based_flag 为 0: 每秒 60 个计数, 跨过一分钟时仍然只丢弃 n_frames 为 0 与 1 的计数
+        let mut collector = TimecodeCollector::new();
+        collector.push(&sps).unwrap();
+        collector.push(&pps).unwrap();
+        let seis = [pic_timing_sei(0, 59, Some((0, 0, 59))), pic_timing_sei(0, 2, Some((0, 1, 0)))];
+        for (sei, slice) in seis.iter().zip(slices.iter()) {
+            collector.push(sei).unwrap();
+            collector.push(slice).unwrap();
+        }
+        collector.flush();
+
+        let mut timecodes = vec![];
+        while let Some(picture) = collector.next_picture() {
+            assert_eq!(picture.rate, Some(60));
+            timecodes.push(picture.timecodes[0].timecode);
+        }
+        assert_eq!(timecodes.iter().map(|timecode| timecode.to_string()).collect::<Vec<_>>(),
+                   vec!["00:00:59;59", "00:01:00;02"]);
+        assert_eq!(timecodes[1].frame_count(60) - timecodes[0].frame_count(60), 1);
+        assert!(collector.discontinuities().is_empty());
+    }
+}
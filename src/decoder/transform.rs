@@ -0,0 +1,426 @@
+// 8.5 Transform coefficient decoding process and picture construction process prior to deblocking filter process ( Page 177 )
+
+use crate::rbsp::ScalingMatrix;
+
+
+// Table 8-13 – Specification of mapping of idx to cij for zig-zag and field scan ( Page 182 )
+//
+// 扫描位置 -> 光栅位置 ( 4 * i + j, i 为行, j 为列 )
+pub const ZIGZAG_4X4: [usize; 16] = [
+    0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15,
+];
+
+pub const FIELD_4X4: [usize; 16] = [
+    0, 4, 1, 8, 12, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15,
+];
+
+// Table 8-14 – Specification of mapping of idx to cij for 8x8 luma blocks ( Page 183 )
+//
+// 扫描位置 -> 光栅位置 ( 8 * i + j )
+pub const ZIGZAG_8X8: [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10, 17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+pub const FIELD_8X8: [usize; 64] = [
+     0,  8, 16,  1,  9, 24, 32, 17,  2, 25, 40, 48, 56, 33, 10,  3,
+    18, 41, 49, 57, 26, 11,  4, 19, 34, 42, 50, 58, 27, 12,  5, 20,
+    35, 43, 51, 59, 28, 13,  6, 21, 36, 44, 52, 60, 29, 14, 22, 37,
+    45, 53, 61, 30,  7, 15, 38, 46, 54, 62, 23, 31, 39, 47, 55, 63,
+];
+
+// 8.5.9 Derivation process for scaling functions ( Page 186 )
+const NORM_ADJUST_4X4: [[i32; 3]; 6] = [
+    [10, 16, 13],
+    [11, 18, 14],
+    [13, 20, 16],
+    [14, 23, 18],
+    [16, 25, 20],
+    [18, 29, 23],
+];
+
+const NORM_ADJUST_8X8: [[i32; 6]; 6] = [
+    [20, 18, 32, 19, 25, 24],
+    [22, 19, 35, 21, 28, 26],
+    [26, 23, 42, 24, 33, 31],
+    [28, 25, 45, 26, 35, 33],
+    [32, 28, 51, 30, 40, 38],
+    [36, 32, 58, 34, 46, 43],
+];
+
+fn norm_adjust4x4(m: usize, i: usize, j: usize) -> i32 {
+    if i.is_multiple_of(2) && j.is_multiple_of(2) {
+        NORM_ADJUST_4X4[m][0]
+    } else if i % 2 == 1 && j % 2 == 1 {
+        NORM_ADJUST_4X4[m][1]
+    } else {
+        NORM_ADJUST_4X4[m][2]
+    }
+}
+
+fn norm_adjust8x8(m: usize, i: usize, j: usize) -> i32 {
+    let k = if i.is_multiple_of(4) && j.is_multiple_of(4) {
+        0
+    } else if i % 2 == 1 && j % 2 == 1 {
+        1
+    } else if i % 4 == 2 && j % 4 == 2 {
+        2
+    } else if (i.is_multiple_of(4) && j % 2 == 1) || (i % 2 == 1 && j.is_multiple_of(4)) {
+        3
+    } else if (i.is_multiple_of(4) && j % 4 == 2) || (i % 4 == 2 && j.is_multiple_of(4)) {
+        4
+    } else {
+        5
+    };
+
+    NORM_ADJUST_8X8[m][k]
+}
+
+
+// LevelScale4x4( m, i, j ) 与 LevelScale8x8( m, i, j ), 下标与 ScalingMatrix 的列表一致, 按光栅位置排列
+pub struct LevelScale {
+    level_scale4x4: [[[i32; 16]; 6]; 6],
+    level_scale8x8: [[[i32; 64]; 6]; 6],
+}
+
+impl LevelScale {
+    pub fn new(matrix: &ScalingMatrix) -> Self {
+        let mut level_scale4x4 = [[[0; 16]; 6]; 6];
+        let mut level_scale8x8 = [[[0; 64]; 6]; 6];
+
+        for list in 0..6 {
+            for m in 0..6 {
+                // weightScale4x4 / weightScale8x8 固定使用 zig-zag 扫描 ( 8.5.6, 8.5.7 )
+                for (idx, &pos) in ZIGZAG_4X4.iter().enumerate() {
+                    let weight = matrix.list4x4[list][idx] as i32;
+                    level_scale4x4[list][m][pos] = weight * norm_adjust4x4(m, pos / 4, pos % 4);
+                }
+                for (idx, &pos) in ZIGZAG_8X8.iter().enumerate() {
+                    let weight = matrix.list8x8[list][idx] as i32;
+                    level_scale8x8[list][m][pos] = weight * norm_adjust8x8(m, pos / 8, pos % 8);
+                }
+            }
+        }
+
+        Self {
+            level_scale4x4: level_scale4x4,
+            level_scale8x8: level_scale8x8,
+        }
+    }
+
+    pub fn get4x4(&self, list: usize, qp: i32) -> &[i32; 16] {
+        &self.level_scale4x4[list][(qp % 6) as usize]
+    }
+
+    pub fn get8x8(&self, list: usize, qp: i32) -> &[i32; 64] {
+        &self.level_scale8x8[list][(qp % 6) as usize]
+    }
+
+    // LevelScale4x4( m, 0, 0 ), m = 0 .. 5
+    pub fn dc(&self, list: usize) -> [i32; 6] {
+        let mut dc = [0; 6];
+        for m in 0..6 {
+            dc[m] = self.level_scale4x4[list][m][0];
+        }
+        dc
+    }
+}
+
+
+// 8.5.6 Inverse scanning process for 4x4 transform coefficients and scaling lists
+// 8.5.12.1 Scaling process for residual 4x4 blocks ( Page 190 )
+//
+// `levels` 为扫描顺序的系数; `dc` 为已经过 DC 变换的 c00 ( Intra_16x16 以及色度块 ), 不再参与缩放。
+// 返回光栅顺序的 d
+pub fn scale4x4(levels: &[i32; 16], scan: &[usize; 16], level_scale: &[i32; 16], qp: i32, dc: Option<i32>) -> [i32; 16] {
+    let mut d = [0i32; 16];
+
+    for (idx, &pos) in scan.iter().enumerate() {
+        let c = levels[idx];
+        if c == 0 {
+            continue;
+        }
+
+        d[pos] = if qp >= 24 {
+            (c * level_scale[pos]) << (qp / 6 - 4)
+        } else {
+            (c * level_scale[pos] + (1 << (3 - qp / 6))) >> (4 - qp / 6)
+        };
+    }
+
+    if let Some(dc) = dc {
+        d[0] = dc;
+    }
+
+    d
+}
+
+// 8.5.7 Inverse scanning process for 8x8 transform coefficients and scaling lists
+// 8.5.13.1 Scaling process for residual 8x8 blocks ( Page 192 )
+pub fn scale8x8(levels: &[i32; 64], scan: &[usize; 64], level_scale: &[i32; 64], qp: i32) -> [i32; 64] {
+    let mut d = [0i32; 64];
+
+    for (idx, &pos) in scan.iter().enumerate() {
+        let c = levels[idx];
+        if c == 0 {
+            continue;
+        }
+
+        d[pos] = if qp >= 36 {
+            (c * level_scale[pos]) << (qp / 6 - 6)
+        } else {
+            (c * level_scale[pos] + (1 << (5 - qp / 6))) >> (6 - qp / 6)
+        };
+    }
+
+    d
+}
+
+// 8.5.12.2 Transformation process for residual 4x4 blocks ( Page 191 )
+//
+// 原地把 d 变换为残差 r
+pub fn inverse_transform4x4(d: &mut [i32; 16]) {
+    for i in 0..4 {
+        let row = &mut d[i * 4..i * 4 + 4];
+        let e0 = row[0] + row[2];
+        let e1 = row[0] - row[2];
+        let e2 = (row[1] >> 1) - row[3];
+        let e3 = row[1] + (row[3] >> 1);
+
+        row[0] = e0 + e3;
+        row[1] = e1 + e2;
+        row[2] = e1 - e2;
+        row[3] = e0 - e3;
+    }
+
+    for j in 0..4 {
+        let g0 = d[j] + d[8 + j];
+        let g1 = d[j] - d[8 + j];
+        let g2 = (d[4 + j] >> 1) - d[12 + j];
+        let g3 = d[4 + j] + (d[12 + j] >> 1);
+
+        d[j] = (g0 + g3 + 32) >> 6;
+        d[4 + j] = (g1 + g2 + 32) >> 6;
+        d[8 + j] = (g1 - g2 + 32) >> 6;
+        d[12 + j] = (g0 - g3 + 32) >> 6;
+    }
+}
+
+// 8.5.13.2 Transformation process for residual 8x8 blocks 的一维变换
+fn transform8(d: [i32; 8]) -> [i32; 8] {
+    let a0 = d[0] + d[4];
+    let a4 = d[0] - d[4];
+    let a2 = (d[2] >> 1) - d[6];
+    let a6 = d[2] + (d[6] >> 1);
+
+    let b0 = a0 + a6;
+    let b2 = a4 + a2;
+    let b4 = a4 - a2;
+    let b6 = a0 - a6;
+
+    let a1 = -d[3] + d[5] - d[7] - (d[7] >> 1);
+    let a3 = d[1] + d[7] - d[3] - (d[3] >> 1);
+    let a5 = -d[1] + d[7] + d[5] + (d[5] >> 1);
+    let a7 = d[3] + d[5] + d[1] + (d[1] >> 1);
+
+    let b1 = a1 + (a7 >> 2);
+    let b7 = a7 - (a1 >> 2);
+    let b3 = a3 + (a5 >> 2);
+    let b5 = (a3 >> 2) - a5;
+
+    [b0 + b7, b2 + b5, b4 + b3, b6 + b1, b6 - b1, b4 - b3, b2 - b5, b0 - b7]
+}
+
+// 8.5.13.2 Transformation process for residual 8x8 blocks ( Page 193 )
+pub fn inverse_transform8x8(d: &mut [i32; 64]) {
+    for i in 0..8 {
+        let mut row = [0i32; 8];
+        row.copy_from_slice(&d[i * 8..i * 8 + 8]);
+        d[i * 8..i * 8 + 8].copy_from_slice(&transform8(row));
+    }
+
+    for j in 0..8 {
+        let mut column = [0i32; 8];
+        for i in 0..8 {
+            column[i] = d[i * 8 + j];
+        }
+        let column = transform8(column);
+        for i in 0..8 {
+            d[i * 8 + j] = (column[i] + 32) >> 6;
+        }
+    }
+}
+
+// 8.5.10 Scaling and transformation process for DC transform coefficients for Intra_16x16 macroblock type ( Page 187 )
+//
+// `levels` 为扫描顺序的 Intra16x16DCLevel, `level_scale` 为 LevelScale4x4( qP % 6, 0, 0 );
+// 返回 dcY ( 光栅顺序, 第 i 行第 j 列对应位于 ( 4j, 4i ) 的 4x4 块 )
+pub fn luma_dc(levels: &[i32; 16], scan: &[usize; 16], level_scale: i32, qp: i32) -> [i32; 16] {
+    let mut c = [0i32; 16];
+    for (idx, &pos) in scan.iter().enumerate() {
+        c[pos] = levels[idx];
+    }
+
+    let mut f = hadamard4x4(&c);
+    for value in f.iter_mut() {
+        *value = if qp >= 36 {
+            (*value * level_scale) << (qp / 6 - 6)
+        } else {
+            (*value * level_scale + (1 << (5 - qp / 6))) >> (6 - qp / 6)
+        };
+    }
+
+    f
+}
+
+// 4x4 Hadamard 变换: [1 1 1 1; 1 1 -1 -1; 1 -1 -1 1; 1 -1 1 -1] * c * 同一矩阵
+fn hadamard4x4(c: &[i32; 16]) -> [i32; 16] {
+    let mut f = [0i32; 16];
+
+    for i in 0..4 {
+        let row = &c[i * 4..i * 4 + 4];
+        f[i * 4] = row[0] + row[1] + row[2] + row[3];
+        f[i * 4 + 1] = row[0] + row[1] - row[2] - row[3];
+        f[i * 4 + 2] = row[0] - row[1] - row[2] + row[3];
+        f[i * 4 + 3] = row[0] - row[1] + row[2] - row[3];
+    }
+
+    for j in 0..4 {
+        let (c0, c1, c2, c3) = (f[j], f[4 + j], f[8 + j], f[12 + j]);
+        f[j] = c0 + c1 + c2 + c3;
+        f[4 + j] = c0 + c1 - c2 - c3;
+        f[8 + j] = c0 - c1 - c2 + c3;
+        f[12 + j] = c0 - c1 + c2 - c3;
+    }
+
+    f
+}
+
+// 8.5.11 Scaling and transformation process for chroma DC transform coefficients ( Page 188 )
+//
+// `levels` 为 ChromaDCLevel ( 4:2:0 为 4 项, 4:2:2 为 8 项 ), `level_scale` 为 LevelScale4x4( ·, 0, 0 ) 的 6 个取值,
+// `qp` 为 QP'C。返回 dcC, 下标为 chroma4x4BlkIdx
+pub fn chroma_dc(levels: &[i32; 8], chroma_array_type: u32, level_scale: &[i32; 6], qp: i32) -> [i32; 8] {
+    let mut dc = [0i32; 8];
+
+    if chroma_array_type == 1 {
+        // c = [ c0 c1; c2 c3 ], f = [1 1; 1 -1] * c * [1 1; 1 -1]
+        let (c0, c1, c2, c3) = (levels[0], levels[1], levels[2], levels[3]);
+        let f = [c0 + c1 + c2 + c3, c0 - c1 + c2 - c3, c0 + c1 - c2 - c3, c0 - c1 - c2 + c3];
+
+        let scale = level_scale[(qp % 6) as usize];
+        for i in 0..4 {
+            dc[i] = ((f[i] * scale) << (qp / 6)) >> 5;
+        }
+    } else {
+        // 8.5.11.1: 4:2:2 时 c = [ c0 c2; c1 c5; c3 c6; c4 c7 ]
+        const ORDER: [usize; 8] = [0, 2, 1, 5, 3, 6, 4, 7];
+        let mut c = [0i32; 8];
+        for i in 0..8 {
+            c[i] = levels[ORDER[i]];
+        }
+
+        // f = A4 * c * A2
+        let mut f = [0i32; 8];
+        for j in 0..2 {
+            let (c0, c1, c2, c3) = (c[j], c[2 + j], c[4 + j], c[6 + j]);
+            f[j] = c0 + c1 + c2 + c3;
+            f[2 + j] = c0 + c1 - c2 - c3;
+            f[4 + j] = c0 - c1 - c2 + c3;
+            f[6 + j] = c0 - c1 + c2 - c3;
+        }
+        for i in 0..4 {
+            let (f0, f1) = (f[2 * i], f[2 * i + 1]);
+            f[2 * i] = f0 + f1;
+            f[2 * i + 1] = f0 - f1;
+        }
+
+        let qp_dc = qp + 3;
+        let scale = level_scale[(qp_dc % 6) as usize];
+        for i in 0..8 {
+            dc[i] = if qp_dc >= 36 {
+                (f[i] * scale) << (qp_dc / 6 - 6)
+            } else {
+                (f[i] * scale + (1 << (5 - qp_dc / 6))) >> (6 - qp_dc / 6)
+            };
+        }
+    }
+
+    dc
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scan_tables() {
+        for scan in [&ZIGZAG_4X4, &FIELD_4X4].iter() {
+            let mut seen = [false; 16];
+            for &pos in scan.iter() {
+                seen[pos] = true;
+            }
+            assert!(seen.iter().all(|&seen| seen));
+        }
+
+        for scan in [&ZIGZAG_8X8, &FIELD_8X8].iter() {
+            let mut seen = [false; 64];
+            for &pos in scan.iter() {
+                seen[pos] = true;
+            }
+            assert!(seen.iter().all(|&seen| seen));
+        }
+    }
+
+    #[test]
+    fn test_level_scale() {
+        let level_scale = LevelScale::new(&ScalingMatrix::default());
+        // Flat_4x4_16: 16 * normAdjust4x4
+        assert_eq!(level_scale.get4x4(0, 28)[0], 16 * 16);
+        assert_eq!(level_scale.get4x4(0, 28)[5], 16 * 25);
+        assert_eq!(level_scale.get4x4(0, 28)[1], 16 * 20);
+        assert_eq!(level_scale.get8x8(0, 0)[0], 16 * 20);
+        assert_eq!(level_scale.get8x8(0, 0)[9], 16 * 18);
+    }
+
+    #[test]
+    fn test_inverse_transform() {
+        // 只有 DC 的块得到常数残差
+        let mut d = [0i32; 16];
+        d[0] = 64 * 5;
+        inverse_transform4x4(&mut d);
+        assert!(d.iter().all(|&r| r == 5));
+
+        let mut d = [0i32; 64];
+        d[0] = 64 * -3;
+        inverse_transform8x8(&mut d);
+        assert!(d.iter().all(|&r| r == -3));
+
+        // qP = 28, Flat: c = 1 -> d00 = 16 * 16 << 0 = 256, r = 4
+        let level_scale = LevelScale::new(&ScalingMatrix::default());
+        let mut levels = [0i32; 16];
+        levels[0] = 1;
+        let mut d = scale4x4(&levels, &ZIGZAG_4X4, level_scale.get4x4(0, 28), 28, None);
+        assert_eq!(d[0], 256);
+        inverse_transform4x4(&mut d);
+        assert!(d.iter().all(|&r| r == 4));
+    }
+
+    #[test]
+    fn test_dc_transform() {
+        let mut levels = [0i32; 16];
+        levels[0] = 2;
+        let dc = luma_dc(&levels, &ZIGZAG_4X4, 16 * 10, 12);
+        // f = 2, ( 2 * 160 + 8 ) >> 4 = 20
+        assert!(dc.iter().all(|&value| value == 20));
+
+        let mut levels = [0i32; 8];
+        levels[0] = 4;
+        let dc = chroma_dc(&levels, 1, &[160, 176, 208, 224, 256, 288], 6);
+        // ( ( 4 * 160 ) << 1 ) >> 5 = 40
+        assert_eq!(dc[..4], [40, 40, 40, 40]);
+    }
+}
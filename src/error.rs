@@ -1,4 +1,3 @@
-
 use std::io;
 
 
@@ -13,3 +12,12 @@ impl From<io::Error> for Error {
     }
 }
 
+// 语法元素取值非法
+pub(crate) fn malformed(reason: &'static str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason).into()
+}
+
+// 合法但尚未实现的特性
+pub(crate) fn unsupported(reason: &'static str) -> Error {
+    io::Error::new(io::ErrorKind::Other, reason).into()
+}
@@ -0,0 +1,106 @@
+// SMPTE 336M KLV ( Key-Length-Value ) 以及 MISB 的 UAS Datalink Local Set ( ST 0601 ) 与精确时间戳 ( ST 0604 )。
+//
+// 两者都由 user_data_unregistered SEI 携带: 精确时间戳的 uuid_iso_iec_11578 为 "MISPmicrosectime",
+// UAS Datalink LS 的 uuid_iso_iec_11578 为它的 16 字节 Universal Key, payload 为 BER 长度以及 Local Set 的值。
+// 使用 register 把这两种 UUID 的解码函数加入 UserDataRegistry。
+
+mod st0601;
+mod st0604;
+
+pub use self::st0601::{ UasDatalinkSet, UasItem, KlvValue, UAS_DATALINK_LS_KEY, tag_name };
+pub use self::st0604::{ PrecisionTimestamp, MISB_MICROSECTIME_UUID };
+
+use crate::error::{ self, Error };
+use crate::rbsp::UserDataRegistry;
+
+
+// 把 ST 0601 与 ST 0604 的解码函数加入 registry
+pub fn register(registry: &mut UserDataRegistry) {
+    registry.register(UAS_DATALINK_LS_KEY, "MISB ST 0601", |payload| Ok(Box::new(UasDatalinkSet::parse(payload)?)));
+    registry.register(MISB_MICROSECTIME_UUID, "MISB ST 0604", |payload| Ok(Box::new(PrecisionTimestamp::parse(payload)?)));
+}
+
+
+// BER 长度 ( SMPTE 336M 3.2.1 ): 短格式为一个字节 ( < 128 ), 长格式为 0x80 | n 以及之后 n 个字节
+// 返回 ( 长度, 长度本身占用的字节数 )
+pub fn read_ber_length(bytes: &[u8]) -> Result<(usize, usize), Error> {
+    let first = *bytes.first().ok_or_else(|| error::malformed("BER length is missing"))?;
+    if first & 0x80 == 0 {
+        return Ok((first as usize, 1));
+    }
+
+    let n = (first & 0x7F) as usize;
+    if n == 0 || n > 8 || bytes.len() < 1 + n {
+        return Err(error::malformed("BER long form length is invalid"));
+    }
+
+    let length = bytes[1..1 + n].iter().fold(0u64, |length, &byte| length << 8 | u64::from(byte));
+    Ok((length as usize, 1 + n))
+}
+
+// BER-OID ( Local Set 的 tag ): 每个字节的低 7 位, 最高位为 1 表示还有后续字节
+pub fn read_ber_oid(bytes: &[u8]) -> Result<(u32, usize), Error> {
+    let mut value = 0u32;
+    for (i, &byte) in bytes.iter().enumerate().take(4) {
+        value = value << 7 | u32::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(error::malformed("BER-OID tag is invalid"))
+}
+
+// Local Set 中的一项: ( tag, value )
+pub fn local_set_items(bytes: &[u8]) -> Result<Vec<(u32, &[u8])>, Error> {
+    let mut items = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let (tag, tag_size) = read_ber_oid(&bytes[i..])?;
+        i += tag_size;
+        let (length, length_size) = read_ber_length(&bytes[i..])?;
+        i += length_size;
+        // length 可能接近 2^64, 与剩余字节数比较以免溢出
+        if length > bytes.len() - i {
+            return Err(error::malformed("local set item is longer than the set"));
+        }
+        items.push((tag, &bytes[i..i + length]));
+        i += length;
+    }
+
+    Ok(items)
+}
+
+// ST 0601 6.5.2: 从 16 字节的 Key 开始到 checksum 的长度字节 ( 包括 ) 的 16 位累加和, 偶数位置的字节为高 8 位
+pub fn checksum(bytes: &[u8]) -> u16 {
+    bytes.iter().enumerate().fold(0u16, |sum, (i, &byte)| {
+        sum.wrapping_add(u16::from(byte) << (8 * ((i + 1) % 2)))
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ read_ber_length, read_ber_oid, local_set_items, checksum };
+
+    #[test]
+    fn test_ber() {
+        assert_eq!(read_ber_length(&[0x2A]).unwrap(), (42, 1));
+        assert_eq!(read_ber_length(&[0x81, 0xC9]).unwrap(), (201, 2));
+        assert_eq!(read_ber_length(&[0x82, 0x01, 0x00]).unwrap(), (256, 3));
+        assert!(read_ber_length(&[0x82, 0x01]).is_err());
+        assert!(read_ber_length(&[]).is_err());
+
+        assert_eq!(read_ber_oid(&[0x41]).unwrap(), (65, 1));
+        assert_eq!(read_ber_oid(&[0x81, 0x01]).unwrap(), (129, 2));
+        assert!(read_ber_oid(&[0x81]).is_err());
+
+        let items = local_set_items(&[0x03, 0x02, b'A', b'B', 0x41, 0x01, 0x0D]).unwrap();
+        assert_eq!(items, vec![(3, &b"AB"[..]), (65, &[0x0D][..])]);
+        assert!(local_set_items(&[0x03, 0x05, b'A']).is_err());
+        assert!(local_set_items(&[0x03, 0x88, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+
+        assert_eq!(checksum(&[0x01, 0x02, 0x03]), 0x0402);
+        assert_eq!(checksum(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF]), 0xFEFE);
+    }
+}
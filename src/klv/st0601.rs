@@ -0,0 +1,248 @@
+// MISB ST 0601 UAS Datalink Local Set
+//
+// 只对常用的 tag 做单位换算 ( 见 tag_name 与 UasItem::value ), 其它 tag ( 包括嵌套的 Security Local Set ) 保留原始字节。
+
+use crate::error::{ self, Error };
+use crate::rbsp::UserDataPayload;
+use super::{ read_ber_length, local_set_items, checksum };
+
+use std::any::Any;
+
+
+// 06.0E.2B.34.02.0B.01.01.0E.01.03.01.01.00.00.00
+pub const UAS_DATALINK_LS_KEY: [u8; 16] = [
+    0x06, 0x0E, 0x2B, 0x34, 0x02, 0x0B, 0x01, 0x01, 0x0E, 0x01, 0x03, 0x01, 0x01, 0x00, 0x00, 0x00,
+];
+
+const TAG_CHECKSUM: u32 = 1;
+const TAG_PRECISION_TIME_STAMP: u32 = 2;
+const TAG_VERSION_NUMBER: u32 = 65;
+
+
+pub fn tag_name(tag: u32) -> &'static str {
+    match tag {
+        1 => "Checksum",
+        2 => "Precision Time Stamp",
+        3 => "Mission ID",
+        4 => "Platform Tail Number",
+        5 => "Platform Heading Angle",
+        6 => "Platform Pitch Angle",
+        7 => "Platform Roll Angle",
+        10 => "Platform Designation",
+        11 => "Image Source Sensor",
+        12 => "Image Coordinate System",
+        13 => "Sensor Latitude",
+        14 => "Sensor Longitude",
+        15 => "Sensor True Altitude",
+        16 => "Sensor Horizontal Field of View",
+        17 => "Sensor Vertical Field of View",
+        18 => "Sensor Relative Azimuth Angle",
+        19 => "Sensor Relative Elevation Angle",
+        20 => "Sensor Relative Roll Angle",
+        21 => "Slant Range",
+        22 => "Target Width",
+        23 => "Frame Center Latitude",
+        24 => "Frame Center Longitude",
+        25 => "Frame Center Elevation",
+        48 => "Security Local Set",
+        65 => "UAS Datalink LS Version Number",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KlvValue {
+    Unsigned(u64),
+    // 换算之后的值 ( 度, 米 )
+    Float(f64),
+    Text(String),
+    // 有符号的值为最小值 ( 如 0x8000 ) 时表示 "Out of Range"
+    OutOfRange,
+    Bytes(Vec<u8>),
+}
+
+fn unsigned(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |value, &byte| value << 8 | u64::from(byte))
+}
+
+// 把 n 字节的无符号整数线性映射到 [min, max]
+fn map_unsigned(bytes: &[u8], min: f64, max: f64) -> KlvValue {
+    let range = ((1u128 << (8 * bytes.len())) - 1) as f64;
+    KlvValue::Float(min + unsigned(bytes) as f64 * (max - min) / range)
+}
+
+// 把 n 字节的有符号整数线性映射到 [-limit, limit], 最小值表示 "Out of Range"
+fn map_signed(bytes: &[u8], limit: f64) -> KlvValue {
+    let bits = 8 * bytes.len() as u32;
+    let raw = unsigned(bytes);
+    if raw == 1u64 << (bits - 1) {
+        return KlvValue::OutOfRange;
+    }
+
+    let value = ((raw << (64 - bits)) as i64) >> (64 - bits);
+    let range = ((1u128 << bits) - 2) as f64;
+    KlvValue::Float(value as f64 * 2.0 * limit / range)
+}
+
+fn convert(tag: u32, bytes: &[u8]) -> KlvValue {
+    let expect = |len: usize| bytes.len() == len;
+    match tag {
+        TAG_PRECISION_TIME_STAMP if expect(8) => KlvValue::Unsigned(unsigned(bytes)),
+        3 | 4 | 10 | 11 | 12 => KlvValue::Text(String::from_utf8_lossy(bytes).into_owned()),
+        5 if expect(2) => map_unsigned(bytes, 0.0, 360.0),
+        6 if expect(2) => map_signed(bytes, 20.0),
+        7 if expect(2) => map_signed(bytes, 50.0),
+        13 | 23 if expect(4) => map_signed(bytes, 90.0),
+        14 | 24 if expect(4) => map_signed(bytes, 180.0),
+        15 | 25 if expect(2) => map_unsigned(bytes, -900.0, 19000.0),
+        16 | 17 if expect(2) => map_unsigned(bytes, 0.0, 180.0),
+        18 | 20 if expect(4) => map_unsigned(bytes, 0.0, 360.0),
+        19 if expect(4) => map_signed(bytes, 180.0),
+        21 if expect(4) => map_unsigned(bytes, 0.0, 5_000_000.0),
+        22 if expect(2) => map_unsigned(bytes, 0.0, 10_000.0),
+        TAG_CHECKSUM | TAG_VERSION_NUMBER if bytes.len() <= 2 => KlvValue::Unsigned(unsigned(bytes)),
+        _ => KlvValue::Bytes(bytes.to_vec()),
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UasItem {
+    pub tag: u32,
+    pub value: KlvValue,
+    pub raw: Vec<u8>,
+}
+
+impl UasItem {
+    pub fn name(&self) -> &'static str {
+        tag_name(self.tag)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UasDatalinkSet {
+    // 按出现顺序, 包括 Checksum
+    pub items: Vec<UasItem>,
+    // Checksum 项的值与计算结果一致 ( 没有 Checksum 项时为 false )
+    pub checksum_valid: bool,
+}
+
+impl UasDatalinkSet {
+    // payload: BER 长度 | Local Set ( Key 为 user_data_unregistered 的 uuid_iso_iec_11578 )
+    pub fn parse(payload: &[u8]) -> Result<Self, Error> {
+        let (length, length_size) = read_ber_length(payload)?;
+        // read_ber_length 保证 length_size 不超过 payload 的长度, length 可能接近 2^64
+        if length > payload.len() - length_size {
+            return Err(error::malformed("UAS Datalink LS is shorter than its BER length"));
+        }
+        let value = &payload[length_size..length_size + length];
+
+        let items: Vec<UasItem> = local_set_items(value)?.into_iter()
+            .map(|(tag, bytes)| UasItem {
+                tag: tag,
+                value: convert(tag, bytes),
+                raw: bytes.to_vec(),
+            })
+            .collect();
+
+        // ST 0601: Checksum 为最后一项, tag 1 长度 2, 累加范围为 Key 到 Checksum 的长度字节
+        let checksum_valid = match items.last() {
+            Some(item) if item.tag == TAG_CHECKSUM && item.raw.len() == 2 => {
+                let mut packet = UAS_DATALINK_LS_KEY.to_vec();
+                packet.extend_from_slice(&payload[..length_size + length - 2]);
+                checksum(&packet) == unsigned(&item.raw) as u16
+            },
+            _ => false,
+        };
+
+        Ok(Self {
+            items: items,
+            checksum_valid: checksum_valid,
+        })
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&KlvValue> {
+        self.items.iter().find(|item| item.tag == tag).map(|item| &item.value)
+    }
+
+    // Precision Time Stamp ( tag 2 ): 1970-01-01 起的微秒数
+    pub fn precision_time_stamp(&self) -> Option<u64> {
+        match self.get(TAG_PRECISION_TIME_STAMP) {
+            Some(KlvValue::Unsigned(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn version(&self) -> Option<u64> {
+        match self.get(TAG_VERSION_NUMBER) {
+            Some(KlvValue::Unsigned(value)) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl UserDataPayload for UasDatalinkSet {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ UasDatalinkSet, KlvValue, UAS_DATALINK_LS_KEY };
+    use crate::klv::checksum;
+
+    #[test]
+    fn test_uas_datalink_set() {
+        let mut value = vec![
+            // Precision Time Stamp
+            0x02, 0x08, 0x00, 0x04, 0x59, 0xF4, 0xA6, 0xAA, 0x4A, 0xA8,
+            // Mission ID
+            0x03, 0x04, b'M', b'I', b'S', b'N',
+            // Platform Heading Angle: 0x8000 -> 180.0027
+            0x05, 0x02, 0x80, 0x00,
+            // Platform Pitch Angle: Out of Range
+            0x06, 0x02, 0x80, 0x00,
+            // Sensor Latitude: 0x40000000 -> 45
+            0x0D, 0x04, 0x40, 0x00, 0x00, 0x00,
+            // Sensor True Altitude: 0xFFFF -> 19000
+            0x0F, 0x02, 0xFF, 0xFF,
+            // UAS Datalink LS Version Number
+            0x41, 0x01, 0x0D,
+            // Checksum
+            0x01, 0x02,
+        ];
+        let mut payload = vec![value.len() as u8 + 2];
+        payload.append(&mut value);
+        let mut packet = UAS_DATALINK_LS_KEY.to_vec();
+        packet.extend_from_slice(&payload);
+        let sum = checksum(&packet);
+        payload.push((sum >> 8) as u8);
+        payload.push(sum as u8);
+
+        let set = UasDatalinkSet::parse(&payload).unwrap();
+        assert!(set.checksum_valid);
+        assert_eq!(set.items.len(), 8);
+        assert_eq!(set.precision_time_stamp(), Some(1_224_807_209_913_000));
+        assert_eq!(set.get(3), Some(&KlvValue::Text("MISN".to_string())));
+        assert_eq!(set.get(6), Some(&KlvValue::OutOfRange));
+        assert_eq!(set.get(15), Some(&KlvValue::Float(19000.0)));
+        assert_eq!(set.version(), Some(13));
+        assert_eq!(set.items[2].name(), "Platform Heading Angle");
+
+        let float = |tag| match set.get(tag) {
+            Some(&KlvValue::Float(value)) => value,
+            value => panic!("unexpected value {:?}", value),
+        };
+        assert!((float(5) - 180.0027).abs() < 1e-4);
+        assert!((float(13) - 45.0).abs() < 1e-6);
+
+        // 修改一个字节之后 checksum 不一致
+        let mut corrupted = payload.clone();
+        corrupted[13] = b'X';
+        assert!(!UasDatalinkSet::parse(&corrupted).unwrap().checksum_valid);
+        assert!(UasDatalinkSet::parse(&payload[..10]).is_err());
+        assert!(UasDatalinkSet::parse(&[0x88, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]).is_err());
+    }
+}
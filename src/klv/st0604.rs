@@ -0,0 +1,82 @@
+// MISB ST 0604 精确时间戳 ( Precision Time Stamp ) SEI
+//
+// uuid_iso_iec_11578 ( "MISPmicrosectime" ) | Status ( 1 ) | 时间戳的 8 个字节, 每两个字节之后插入 0xFF
+// ( 防止出现起始码 ), 共 12 字节。
+
+use crate::error::{ self, Error };
+use crate::rbsp::UserDataPayload;
+
+use std::any::Any;
+
+
+// "MISPmicrosectime"
+pub const MISB_MICROSECTIME_UUID: [u8; 16] = *b"MISPmicrosectime";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrecisionTimestamp {
+    pub status: u8,
+    // 1970-01-01 起的微秒数 ( 不含闰秒 )
+    pub timestamp: u64,
+}
+
+impl PrecisionTimestamp {
+    pub fn parse(payload: &[u8]) -> Result<Self, Error> {
+        if payload.len() < 12 {
+            return Err(error::malformed("MISB precision time stamp is shorter than 12 bytes"));
+        }
+
+        if payload[3] != 0xFF || payload[6] != 0xFF || payload[9] != 0xFF {
+            return Err(error::malformed("MISB precision time stamp start code emulation bytes are not 0xFF"));
+        }
+
+        let timestamp = [1, 2, 4, 5, 7, 8, 10, 11].iter()
+            .fold(0u64, |timestamp, &i| timestamp << 8 | u64::from(payload[i]));
+
+        Ok(Self {
+            status: payload[0],
+            timestamp: timestamp,
+        })
+    }
+
+    // Status bit 7: 时钟已锁定
+    pub fn locked(&self) -> bool {
+        self.status & 0x80 != 0
+    }
+
+    // Status bit 6: 时间戳不连续
+    pub fn discontinuity(&self) -> bool {
+        self.status & 0x40 != 0
+    }
+
+    // Status bit 5: 反向播放
+    pub fn reverse(&self) -> bool {
+        self.status & 0x20 != 0
+    }
+}
+
+impl UserDataPayload for PrecisionTimestamp {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::PrecisionTimestamp;
+
+    #[test]
+    fn test_precision_timestamp() {
+        let payload = [0x9F, 0x00, 0x04, 0xFF, 0x59, 0xF4, 0xFF, 0xA6, 0xAA, 0xFF, 0x4A, 0xA8];
+        let timestamp = PrecisionTimestamp::parse(&payload).unwrap();
+        assert_eq!(timestamp.timestamp, 1_224_807_209_913_000);
+        assert!(timestamp.locked());
+        assert!(!timestamp.discontinuity());
+        assert!(!timestamp.reverse());
+
+        let mut payload = payload;
+        payload[6] = 0x00;
+        assert!(PrecisionTimestamp::parse(&payload).is_err());
+        assert!(PrecisionTimestamp::parse(&payload[..11]).is_err());
+    }
+}
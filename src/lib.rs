@@ -17,3 +17,9 @@ pub mod rbsp;
 pub mod stream;
 pub mod golomb;
 pub mod error;
+pub mod macroblock;
+pub mod slice;
+pub mod partition;
+pub mod decoder;
+pub mod caption;
+pub mod klv;
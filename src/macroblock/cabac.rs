@@ -0,0 +1,1441 @@
+// 9.3 CABAC parsing process for slice data ( Page 223 )
+
+use crate::error::{ self, Error };
+use crate::rbsp::{ RbspReader, SliceType };
+use super::cabac_tables::{ CONTEXT_INIT_I, CONTEXT_INIT_PB };
+
+
+// Table 9-44 – Specification of rangeTabLPS depending on pStateIdx and qCodIRangeIdx ( Page 259 )
+pub const RANGE_TAB_LPS: [[u8; 4]; 64] = [
+    [128, 176, 208, 240], [128, 167, 197, 227], [128, 158, 187, 216], [123, 150, 178, 205],
+    [116, 142, 169, 195], [111, 135, 160, 185], [105, 128, 152, 175], [100, 122, 144, 166],
+    [ 95, 116, 137, 158], [ 90, 110, 130, 150], [ 85, 104, 123, 142], [ 81,  99, 117, 135],
+    [ 77,  94, 111, 128], [ 73,  89, 105, 122], [ 69,  85, 100, 116], [ 66,  80,  95, 110],
+    [ 62,  76,  90, 104], [ 59,  72,  86,  99], [ 56,  69,  81,  94], [ 53,  65,  77,  89],
+    [ 51,  62,  73,  85], [ 48,  59,  69,  80], [ 46,  56,  66,  76], [ 43,  53,  63,  72],
+    [ 41,  50,  59,  69], [ 39,  48,  56,  65], [ 37,  45,  54,  62], [ 35,  43,  51,  59],
+    [ 33,  41,  48,  56], [ 32,  39,  46,  53], [ 30,  37,  43,  50], [ 29,  35,  41,  48],
+    [ 27,  33,  39,  45], [ 26,  31,  37,  43], [ 24,  30,  35,  41], [ 23,  28,  33,  39],
+    [ 22,  27,  32,  37], [ 21,  26,  30,  35], [ 20,  24,  29,  33], [ 19,  23,  27,  31],
+    [ 18,  22,  26,  30], [ 17,  21,  25,  28], [ 16,  20,  23,  27], [ 15,  19,  22,  25],
+    [ 14,  18,  21,  24], [ 14,  17,  20,  23], [ 13,  16,  19,  22], [ 12,  15,  18,  21],
+    [ 12,  14,  17,  20], [ 11,  14,  16,  19], [ 11,  13,  15,  18], [ 10,  12,  15,  17],
+    [ 10,  12,  14,  16], [  9,  11,  13,  15], [  9,  11,  12,  14], [  8,  10,  12,  14],
+    [  8,   9,  11,  13], [  7,   9,  11,  12], [  7,   9,  10,  12], [  7,   8,  10,  11],
+    [  6,   8,   9,  11], [  6,   7,   9,  10], [  6,   7,   8,   9], [  2,   2,   2,   2],
+];
+
+// Table 9-45 – State transition table ( Page 260 )
+pub const TRANS_IDX_LPS: [u8; 64] = [
+     0,  0,  1,  2,  2,  4,  4,  5,  6,  7,  8,  9,  9, 11, 11, 12,
+    13, 13, 15, 15, 16, 16, 18, 18, 19, 19, 21, 21, 22, 22, 23, 24,
+    24, 25, 26, 26, 27, 27, 28, 29, 29, 30, 30, 30, 31, 32, 32, 33,
+    33, 33, 34, 34, 35, 35, 35, 36, 36, 36, 37, 37, 37, 38, 38, 63,
+];
+
+pub const TRANS_IDX_MPS: [u8; 64] = [
+     1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15, 16,
+    17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32,
+    33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48,
+    49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 62, 63,
+];
+
+// Table 9-43 – Mapping of scanning position to ctxIdxInc for ctxBlockCat == 5, 9, or 13 ( Page 254 )
+//
+// significant_coeff_flag ( 帧宏块, 场宏块 ) 以及 last_significant_coeff_flag
+pub const SIGNIFICANT_COEFF_FLAG_8X8_INC: [[u8; 63]; 2] = [
+    [
+         0,  1,  2,  3,  4,  5,  5,  4,  4,  3,  3,  4,  4,  4,  5,  5,
+         4,  4,  4,  4,  3,  3,  6,  7,  7,  7,  8,  9, 10,  9,  8,  7,
+         7,  6, 11, 12, 13, 11,  6,  7,  8,  9, 14, 10,  9,  8,  6, 11,
+        12, 13, 11,  6,  9, 14, 10,  9, 11, 12, 13, 11, 14, 10, 12,
+    ],
+    [
+         0,  1,  1,  2,  2,  3,  3,  4,  5,  6,  7,  7,  7,  8,  4,  5,
+         6,  9, 10, 10,  8, 11, 12, 11,  9,  9, 10, 10,  8, 11, 12, 11,
+         9,  9, 10, 10,  8, 11, 12, 11,  9,  9, 10, 10,  8, 13, 13,  9,
+         9, 10, 10,  8, 13, 13,  9,  9, 10, 10, 14, 14, 14, 14, 14,
+    ],
+];
+
+pub const LAST_SIGNIFICANT_COEFF_FLAG_8X8_INC: [u8; 63] = [
+    0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    3, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4,
+    5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7, 8, 8, 8,
+];
+
+// Table 9-40 – Assignment of ctxBlockCatOffset to ctxBlockCat ( Page 243 )
+//
+// 依次为 coded_block_flag, significant_coeff_flag ( 以及 last_significant_coeff_flag ), coeff_abs_level_minus1
+const CTX_BLOCK_CAT_OFFSET: [[usize; 14]; 3] = [
+    [ 0, 4, 8, 12, 16, 0, 0, 4, 8, 4, 0, 4, 8, 8 ],
+    [ 0, 15, 29, 44, 47, 0, 0, 15, 29, 0, 0, 15, 29, 0 ],
+    [ 0, 10, 20, 30, 39, 0, 0, 10, 20, 0, 0, 10, 20, 0 ],
+];
+
+pub const CTX_IDX_COUNT: usize = 1024;
+
+// Table 9-34 中各语法元素的 ctxIdxOffset
+pub const MB_TYPE_SI_PREFIX: usize = 0;
+pub const MB_TYPE_I: usize = 3;
+pub const MB_SKIP_FLAG_P: usize = 11;
+pub const MB_TYPE_P_PREFIX: usize = 14;
+pub const MB_TYPE_P_SUFFIX: usize = 17;
+pub const SUB_MB_TYPE_P: usize = 21;
+pub const MB_SKIP_FLAG_B: usize = 24;
+pub const MB_TYPE_B_PREFIX: usize = 27;
+pub const MB_TYPE_B_SUFFIX: usize = 32;
+pub const SUB_MB_TYPE_B: usize = 36;
+pub const MVD_X: usize = 40;
+pub const MVD_Y: usize = 47;
+pub const REF_IDX: usize = 54;
+pub const MB_QP_DELTA: usize = 60;
+pub const INTRA_CHROMA_PRED_MODE: usize = 64;
+pub const PREV_INTRA_PRED_MODE_FLAG: usize = 68;
+pub const REM_INTRA_PRED_MODE: usize = 69;
+pub const MB_FIELD_DECODING_FLAG: usize = 70;
+pub const CODED_BLOCK_PATTERN_LUMA: usize = 73;
+pub const CODED_BLOCK_PATTERN_CHROMA: usize = 77;
+pub const TRANSFORM_SIZE_8X8_FLAG: usize = 399;
+
+// coded_block_flag 的 ctxIdxOffset
+fn coded_block_flag_offset(ctx_block_cat: usize) -> usize {
+    match ctx_block_cat {
+        0 ..= 4 => 85,
+        6 ..= 8 => 460,
+        10 ..= 12 => 472,
+        _ => 1012,
+    }
+}
+
+// significant_coeff_flag 的 ctxIdxOffset
+fn significant_coeff_flag_offset(ctx_block_cat: usize, field: bool) -> usize {
+    match (ctx_block_cat, field) {
+        (0 ..= 4, false) => 105,
+        (0 ..= 4, true) => 277,
+        (5, false) => 402,
+        (5, true) => 436,
+        (6 ..= 8, false) => 484,
+        (6 ..= 8, true) => 776,
+        (9, false) => 660,
+        (9, true) => 675,
+        (10 ..= 12, false) => 528,
+        (10 ..= 12, true) => 820,
+        (_, false) => 718,
+        (_, true) => 733,
+    }
+}
+
+// last_significant_coeff_flag 的 ctxIdxOffset
+fn last_significant_coeff_flag_offset(ctx_block_cat: usize, field: bool) -> usize {
+    match (ctx_block_cat, field) {
+        (0 ..= 4, false) => 166,
+        (0 ..= 4, true) => 338,
+        (5, false) => 417,
+        (5, true) => 451,
+        (6 ..= 8, false) => 572,
+        (6 ..= 8, true) => 864,
+        (9, false) => 690,
+        (9, true) => 699,
+        (10 ..= 12, false) => 616,
+        (10 ..= 12, true) => 908,
+        (_, false) => 748,
+        (_, true) => 757,
+    }
+}
+
+// coeff_abs_level_minus1 的 ctxIdxOffset
+fn coeff_abs_level_minus1_offset(ctx_block_cat: usize) -> usize {
+    match ctx_block_cat {
+        0 ..= 4 => 227,
+        5 => 426,
+        6 ..= 8 => 952,
+        9 => 708,
+        10 ..= 12 => 982,
+        _ => 766,
+    }
+}
+
+// ctxIdx 460 ~ 1023 与亮度对应的 ctxIdx 使用同样的 ( m, n )
+fn base_ctx_idx(ctx_idx: usize) -> usize {
+    match ctx_idx {
+        0 ..= 459 => ctx_idx,
+        460 ..= 471 => ctx_idx - 460 + 85,
+        472 ..= 483 => ctx_idx - 472 + 85,
+        484 ..= 527 => ctx_idx - 484 + 105,
+        528 ..= 571 => ctx_idx - 528 + 105,
+        572 ..= 615 => ctx_idx - 572 + 166,
+        616 ..= 659 => ctx_idx - 616 + 166,
+        660 ..= 674 => ctx_idx - 660 + 402,
+        675 ..= 689 => ctx_idx - 675 + 436,
+        690 ..= 698 => ctx_idx - 690 + 417,
+        699 ..= 707 => ctx_idx - 699 + 451,
+        708 ..= 717 => ctx_idx - 708 + 426,
+        718 ..= 732 => ctx_idx - 718 + 402,
+        733 ..= 747 => ctx_idx - 733 + 436,
+        748 ..= 756 => ctx_idx - 748 + 417,
+        757 ..= 765 => ctx_idx - 757 + 451,
+        766 ..= 775 => ctx_idx - 766 + 426,
+        776 ..= 819 => ctx_idx - 776 + 277,
+        820 ..= 863 => ctx_idx - 820 + 277,
+        864 ..= 907 => ctx_idx - 864 + 338,
+        908 ..= 951 => ctx_idx - 908 + 338,
+        952 ..= 981 => ctx_idx - 952 + 227,
+        982 ..= 1011 => ctx_idx - 982 + 227,
+        // 8x8 块的 coded_block_flag 与 ctxBlockCat 2 的相同
+        _ => (ctx_idx - 1012) % 4 + 93,
+    }
+}
+
+
+// 上下文变量 ( pStateIdx, valMPS )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextVariable {
+    pub p_state_idx: u8,
+    pub val_mps: bool,
+}
+
+impl ContextVariable {
+    // 9.3.1.1 Initialisation process for context variables
+    pub fn new(m: i32, n: i32, slice_qp_y: i32) -> Self {
+        let qp = slice_qp_y.clamp(0, 51);
+        let pre_ctx_state = (((m * qp) >> 4) + n).clamp(1, 126);
+
+        if pre_ctx_state <= 63 {
+            ContextVariable { p_state_idx: (63 - pre_ctx_state) as u8, val_mps: false }
+        } else {
+            ContextVariable { p_state_idx: (pre_ctx_state - 64) as u8, val_mps: true }
+        }
+    }
+}
+
+
+// 9.3.1.2 Initialisation process for the arithmetic decoding engine 以及
+// 9.3.3.2 Arithmetic decoding process for a binary decision
+//
+// 算术解码引擎本身不持有 RbspReader, 以便 I_PCM 等语法元素可以直接从码流读取。
+#[derive(Debug, Clone)]
+pub struct CabacDecoder {
+    contexts: Vec<ContextVariable>,
+    cod_i_range: u32,
+    cod_i_offset: u32,
+}
+
+impl CabacDecoder {
+    // 在每个 slice 开始时初始化全部上下文变量
+    pub fn new(slice_type: SliceType, cabac_init_idc: Option<u32>, slice_qp_y: i32) -> Result<Self, Error> {
+        let table = if slice_type.is_intra() {
+            &CONTEXT_INIT_I
+        } else {
+            match cabac_init_idc {
+                Some(idc @ 0 ..= 2) => &CONTEXT_INIT_PB[idc as usize],
+                _ => return Err(error::malformed("cabac_init_idc out of range")),
+            }
+        };
+
+        let contexts = (0..CTX_IDX_COUNT)
+            .map(|ctx_idx| {
+                let (m, n) = table[base_ctx_idx(ctx_idx)];
+                ContextVariable::new(m as i32, n as i32, slice_qp_y)
+            })
+            .collect();
+
+        Ok(CabacDecoder {
+            contexts: contexts,
+            cod_i_range: 510,
+            cod_i_offset: 0,
+        })
+    }
+
+    pub fn context(&self, ctx_idx: usize) -> ContextVariable {
+        self.contexts[ctx_idx]
+    }
+
+    // 9.3.1.2: slice 数据开始处以及 I_PCM 宏块之后调用
+    pub fn init_engine(&mut self, reader: &mut RbspReader) -> Result<(), Error> {
+        self.cod_i_range = 510;
+        self.cod_i_offset = reader.read_bits(9)?;
+
+        if self.cod_i_offset == 510 || self.cod_i_offset == 511 {
+            return Err(error::malformed("codIOffset must not be equal to 510 or 511"));
+        }
+
+        Ok(())
+    }
+
+    // 9.3.3.2.2 Renormalization process in the arithmetic decoding engine
+    fn renorm(&mut self, reader: &mut RbspReader) -> Result<(), Error> {
+        while self.cod_i_range < 256 {
+            self.cod_i_range <<= 1;
+            self.cod_i_offset = (self.cod_i_offset << 1) | reader.read_bit()? as u32;
+        }
+
+        Ok(())
+    }
+
+    // 9.3.3.2.1 Arithmetic decoding process for a binary decision
+    pub fn decode_decision(&mut self, reader: &mut RbspReader, ctx_idx: usize) -> Result<bool, Error> {
+        let context = &mut self.contexts[ctx_idx];
+        let q_cod_i_range_idx = ((self.cod_i_range >> 6) & 3) as usize;
+        let cod_i_range_lps = RANGE_TAB_LPS[context.p_state_idx as usize][q_cod_i_range_idx] as u32;
+
+        self.cod_i_range -= cod_i_range_lps;
+
+        let bin_val = if self.cod_i_offset >= self.cod_i_range {
+            let bin_val = !context.val_mps;
+            self.cod_i_offset -= self.cod_i_range;
+            self.cod_i_range = cod_i_range_lps;
+
+            if context.p_state_idx == 0 {
+                context.val_mps = !context.val_mps;
+            }
+            context.p_state_idx = TRANS_IDX_LPS[context.p_state_idx as usize];
+
+            bin_val
+        } else {
+            context.p_state_idx = TRANS_IDX_MPS[context.p_state_idx as usize];
+            context.val_mps
+        };
+
+        self.renorm(reader)?;
+
+        Ok(bin_val)
+    }
+
+    // 9.3.3.2.3 Bypass decoding process for binary decisions
+    pub fn decode_bypass(&mut self, reader: &mut RbspReader) -> Result<bool, Error> {
+        self.cod_i_offset = (self.cod_i_offset << 1) | reader.read_bit()? as u32;
+
+        if self.cod_i_offset >= self.cod_i_range {
+            self.cod_i_offset -= self.cod_i_range;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    // 9.3.3.2.4 Decoding process for binary decisions before termination
+    //
+    // 结果为 1 时不再重新归一化, 此时最后读入 codIOffset 的比特为 rbsp_stop_one_bit
+    // ( end_of_slice_flag ) 或 I_PCM 之前的最后一个比特。
+    pub fn decode_terminate(&mut self, reader: &mut RbspReader) -> Result<bool, Error> {
+        self.cod_i_range -= 2;
+
+        if self.cod_i_offset >= self.cod_i_range {
+            Ok(true)
+        } else {
+            self.renorm(reader)?;
+            Ok(false)
+        }
+    }
+
+    // 9.3.2.3 Concatenated unary/ k-th order Exp-Golomb ( UEGk ) 的后缀部分
+    fn decode_exp_golomb_bypass(&mut self, reader: &mut RbspReader, k: u32) -> Result<u32, Error> {
+        let mut k = k;
+        let mut value = 0u32;
+
+        while self.decode_bypass(reader)? {
+            value += 1 << k;
+            k += 1;
+            if k >= 31 {
+                return Err(error::malformed("UEGk suffix is too long"));
+            }
+        }
+
+        while k > 0 {
+            k -= 1;
+            value += (self.decode_bypass(reader)? as u32) << k;
+        }
+
+        Ok(value)
+    }
+
+    // mb_skip_flag
+    pub fn mb_skip_flag(&mut self, reader: &mut RbspReader, slice_type: SliceType, ctx_idx_inc: usize) -> Result<bool, Error> {
+        let offset = if slice_type.is_bipredictive() { MB_SKIP_FLAG_B } else { MB_SKIP_FLAG_P };
+        self.decode_decision(reader, offset + ctx_idx_inc)
+    }
+
+    // mb_field_decoding_flag
+    pub fn mb_field_decoding_flag(&mut self, reader: &mut RbspReader, ctx_idx_inc: usize) -> Result<bool, Error> {
+        self.decode_decision(reader, MB_FIELD_DECODING_FLAG + ctx_idx_inc)
+    }
+
+    // end_of_slice_flag
+    pub fn end_of_slice_flag(&mut self, reader: &mut RbspReader) -> Result<bool, Error> {
+        self.decode_terminate(reader)
+    }
+
+    // 9.3.2.5 Binarization process for macroblock type and sub-macroblock type
+    //
+    // I slice 的 mb_type ( Table 9-36 ), 以及 SI/P/SP/B slice 中 mb_type 的 I 后缀部分。
+    // 前缀时 bin 0 的 ctxIdxInc 由相邻宏块决定, 后缀时为 0。
+    fn mb_type_i(&mut self, reader: &mut RbspReader, offset: usize, ctx_idx_inc: usize) -> Result<u32, Error> {
+        let prefix = offset == MB_TYPE_I;
+
+        if !self.decode_decision(reader, offset + ctx_idx_inc)? {
+            // I_NxN
+            return Ok(0);
+        }
+
+        if self.decode_terminate(reader)? {
+            // I_PCM
+            return Ok(25);
+        }
+
+        // bin 2 ~ 6 的 ctxIdxInc ( Table 9-39 ), 预测模式的两个 bin 与色度 bin 的取值无关
+        let (luma, chroma, chroma2, pred0, pred1) = if prefix { (3, 4, 5, 6, 7) } else { (1, 2, 2, 3, 3) };
+
+        let mut mb_type = 1;
+        if self.decode_decision(reader, offset + luma)? {
+            mb_type += 12;
+        }
+
+        if self.decode_decision(reader, offset + chroma)? {
+            mb_type += if self.decode_decision(reader, offset + chroma2)? { 8 } else { 4 };
+        }
+
+        if self.decode_decision(reader, offset + pred0)? {
+            mb_type += 2;
+        }
+        if self.decode_decision(reader, offset + pred1)? {
+            mb_type += 1;
+        }
+
+        Ok(mb_type)
+    }
+
+    // mb_type: 返回值与 CAVLC 的 ue(v) 取值一致, 可交给 MbType::from_code 处理。
+    //
+    // ctx_idx_inc 为前缀 bin 0 ( SI, P 除外 ) 由相邻宏块推导出的 ctxIdxInc。
+    pub fn mb_type(&mut self, reader: &mut RbspReader, slice_type: SliceType, ctx_idx_inc: usize) -> Result<u32, Error> {
+        match slice_type {
+            SliceType::I => self.mb_type_i(reader, MB_TYPE_I, ctx_idx_inc),
+            SliceType::SI => {
+                // 前缀与后缀的 bin 0 均使用相邻宏块推导出的 ctxIdxInc, 由调用者分别给出
+                Err(error::malformed("SI mb_type must be decoded with mb_type_si"))
+            },
+            SliceType::P | SliceType::SP => {
+                if self.decode_decision(reader, MB_TYPE_P_PREFIX)? {
+                    return Ok(5 + self.mb_type_i(reader, MB_TYPE_P_SUFFIX, 0)?);
+                }
+
+                if !self.decode_decision(reader, MB_TYPE_P_PREFIX + 1)? {
+                    // P_L0_16x16 ( 0 0 0 ), P_8x8 ( 0 0 1 )
+                    let bin = self.decode_decision(reader, MB_TYPE_P_PREFIX + 2)?;
+                    Ok(if bin { 3 } else { 0 })
+                } else {
+                    // P_L0_L0_16x8 ( 0 1 1 ), P_L0_L0_8x16 ( 0 1 0 )
+                    let bin = self.decode_decision(reader, MB_TYPE_P_PREFIX + 3)?;
+                    Ok(if bin { 1 } else { 2 })
+                }
+            },
+            SliceType::B => {
+                if !self.decode_decision(reader, MB_TYPE_B_PREFIX + ctx_idx_inc)? {
+                    // B_Direct_16x16
+                    return Ok(0);
+                }
+
+                if !self.decode_decision(reader, MB_TYPE_B_PREFIX + 3)? {
+                    // B_L0_16x16, B_L1_16x16
+                    return Ok(1 + self.decode_decision(reader, MB_TYPE_B_PREFIX + 5)? as u32);
+                }
+
+                let mut bits = (self.decode_decision(reader, MB_TYPE_B_PREFIX + 4)? as u32) << 3;
+                for shift in (0..3).rev() {
+                    bits |= (self.decode_decision(reader, MB_TYPE_B_PREFIX + 5)? as u32) << shift;
+                }
+
+                match bits {
+                    // B_Bi_16x16 ~ B_L1_L0_16x8
+                    0 ..= 7 => Ok(bits + 3),
+                    // 1 1 1 1 0 1: I 宏块前缀
+                    13 => Ok(23 + self.mb_type_i(reader, MB_TYPE_B_SUFFIX, 0)?),
+                    // B_L1_L0_8x16
+                    14 => Ok(11),
+                    // B_8x8
+                    15 => Ok(22),
+                    // B_L0_Bi_16x8 ~ B_Bi_Bi_8x16
+                    _ => {
+                        let bits = (bits << 1) | self.decode_decision(reader, MB_TYPE_B_PREFIX + 5)? as u32;
+                        Ok(bits - 4)
+                    },
+                }
+            },
+        }
+    }
+
+    // SI slice 的 mb_type: 前缀 ( ctxIdxOffset 0 ) 与后缀 ( ctxIdxOffset 3 ) 的 bin 0 均依赖相邻宏块
+    pub fn mb_type_si(&mut self, reader: &mut RbspReader, prefix_ctx_idx_inc: usize, suffix_ctx_idx_inc: usize) -> Result<u32, Error> {
+        if !self.decode_decision(reader, MB_TYPE_SI_PREFIX + prefix_ctx_idx_inc)? {
+            return Ok(0);
+        }
+
+        Ok(1 + self.mb_type_i(reader, MB_TYPE_I, suffix_ctx_idx_inc)?)
+    }
+
+    // sub_mb_type ( Table 9-38 )
+    pub fn sub_mb_type(&mut self, reader: &mut RbspReader, slice_type: SliceType) -> Result<u32, Error> {
+        if !slice_type.is_bipredictive() {
+            if self.decode_decision(reader, SUB_MB_TYPE_P)? {
+                return Ok(0);
+            }
+            if !self.decode_decision(reader, SUB_MB_TYPE_P + 1)? {
+                return Ok(1);
+            }
+            return Ok(if self.decode_decision(reader, SUB_MB_TYPE_P + 2)? { 2 } else { 3 });
+        }
+
+        if !self.decode_decision(reader, SUB_MB_TYPE_B)? {
+            // B_Direct_8x8
+            return Ok(0);
+        }
+
+        if !self.decode_decision(reader, SUB_MB_TYPE_B + 1)? {
+            // B_L0_8x8, B_L1_8x8
+            return Ok(1 + self.decode_decision(reader, SUB_MB_TYPE_B + 3)? as u32);
+        }
+
+        let mut sub_mb_type = 3;
+        if self.decode_decision(reader, SUB_MB_TYPE_B + 2)? {
+            if self.decode_decision(reader, SUB_MB_TYPE_B + 3)? {
+                // B_L1_4x4, B_Bi_4x4
+                return Ok(11 + self.decode_decision(reader, SUB_MB_TYPE_B + 3)? as u32);
+            }
+            sub_mb_type += 4;
+        }
+
+        if self.decode_decision(reader, SUB_MB_TYPE_B + 3)? {
+            sub_mb_type += 2;
+        }
+        if self.decode_decision(reader, SUB_MB_TYPE_B + 3)? {
+            sub_mb_type += 1;
+        }
+
+        Ok(sub_mb_type)
+    }
+
+    // transform_size_8x8_flag
+    pub fn transform_size_8x8_flag(&mut self, reader: &mut RbspReader, ctx_idx_inc: usize) -> Result<bool, Error> {
+        self.decode_decision(reader, TRANSFORM_SIZE_8X8_FLAG + ctx_idx_inc)
+    }
+
+    // prev_intra4x4_pred_mode_flag, prev_intra8x8_pred_mode_flag
+    pub fn prev_intra_pred_mode_flag(&mut self, reader: &mut RbspReader) -> Result<bool, Error> {
+        self.decode_decision(reader, PREV_INTRA_PRED_MODE_FLAG)
+    }
+
+    // rem_intra4x4_pred_mode, rem_intra8x8_pred_mode: FL, cMax = 7, bin 0 为最低位
+    pub fn rem_intra_pred_mode(&mut self, reader: &mut RbspReader) -> Result<u8, Error> {
+        let mut mode = 0;
+        for bin_idx in 0..3 {
+            mode |= (self.decode_decision(reader, REM_INTRA_PRED_MODE)? as u8) << bin_idx;
+        }
+
+        Ok(mode)
+    }
+
+    // intra_chroma_pred_mode: TU, cMax = 3
+    pub fn intra_chroma_pred_mode(&mut self, reader: &mut RbspReader, ctx_idx_inc: usize) -> Result<u8, Error> {
+        if !self.decode_decision(reader, INTRA_CHROMA_PRED_MODE + ctx_idx_inc)? {
+            return Ok(0);
+        }
+
+        let mut mode = 1;
+        while mode < 3 && self.decode_decision(reader, INTRA_CHROMA_PRED_MODE + 3)? {
+            mode += 1;
+        }
+
+        Ok(mode)
+    }
+
+    // ref_idx_l0, ref_idx_l1: U
+    pub fn ref_idx(&mut self, reader: &mut RbspReader, ctx_idx_inc: usize, max: u32) -> Result<u32, Error> {
+        if !self.decode_decision(reader, REF_IDX + ctx_idx_inc)? {
+            return Ok(0);
+        }
+
+        let mut ref_idx = 1;
+        while self.decode_decision(reader, REF_IDX + if ref_idx == 1 { 4 } else { 5 })? {
+            ref_idx += 1;
+            if ref_idx > max {
+                return Err(error::malformed("ref_idx out of range"));
+            }
+        }
+
+        Ok(ref_idx)
+    }
+
+    // mvd_l0, mvd_l1: UEG3, signedValFlag = 1, uCoff = 9
+    //
+    // abs_mvd_comp_sum 为相邻分区 A, B 的 absMvdComp 之和 ( 9.3.3.1.1.7 )
+    pub fn mvd(&mut self, reader: &mut RbspReader, comp_idx: usize, abs_mvd_comp_sum: u32) -> Result<i32, Error> {
+        let offset = if comp_idx == 0 { MVD_X } else { MVD_Y };
+        let ctx_idx_inc = if abs_mvd_comp_sum < 3 {
+            0
+        } else if abs_mvd_comp_sum <= 32 {
+            1
+        } else {
+            2
+        };
+
+        if !self.decode_decision(reader, offset + ctx_idx_inc)? {
+            return Ok(0);
+        }
+
+        let mut prefix = 1;
+        while prefix < 9 && self.decode_decision(reader, offset + std::cmp::min(prefix + 2, 6) as usize)? {
+            prefix += 1;
+        }
+
+        let mut value = prefix;
+        if prefix == 9 {
+            value += self.decode_exp_golomb_bypass(reader, 3)?;
+        }
+
+        if value > i32::MAX as u32 {
+            return Err(error::malformed("mvd out of range"));
+        }
+
+        if self.decode_bypass(reader)? {
+            Ok(-(value as i32))
+        } else {
+            Ok(value as i32)
+        }
+    }
+
+    // mb_qp_delta: Table 9-3 映射后的 U 二值化
+    pub fn mb_qp_delta(&mut self, reader: &mut RbspReader, ctx_idx_inc: usize) -> Result<i32, Error> {
+        if !self.decode_decision(reader, MB_QP_DELTA + ctx_idx_inc)? {
+            return Ok(0);
+        }
+
+        let mut k = 1u32;
+        while self.decode_decision(reader, MB_QP_DELTA + if k == 1 { 2 } else { 3 })? {
+            k += 1;
+            if k > 2 * 64 {
+                return Err(error::malformed("mb_qp_delta out of range"));
+            }
+        }
+
+        if k % 2 == 1 {
+            Ok(k.div_ceil(2) as i32)
+        } else {
+            Ok(-((k / 2) as i32))
+        }
+    }
+
+    // coded_block_pattern 的单个 bin, 由调用者根据相邻块推导 ctxIdxInc
+    pub fn coded_block_pattern_bin(&mut self, reader: &mut RbspReader, offset: usize, ctx_idx_inc: usize) -> Result<bool, Error> {
+        self.decode_decision(reader, offset + ctx_idx_inc)
+    }
+
+    // 7.3.5.3.3 Residual block CABAC syntax ( Page 83 )
+    //
+    // coded_block_flag_inc 为 None 时 coded_block_flag 不出现 ( 推断为 1 ), 否则为其 ctxIdxInc。
+    // 返回非零系数的个数。
+    #[allow(clippy::too_many_arguments)]
+    pub fn residual_block(&mut self,
+                          reader: &mut RbspReader,
+                          coeff_level: &mut [i32],
+                          start_idx: usize,
+                          end_idx: usize,
+                          max_num_coeff: usize,
+                          ctx_block_cat: usize,
+                          field: bool,
+                          coded_block_flag_inc: Option<usize>) -> Result<u8, Error> {
+        for level in coeff_level[..max_num_coeff].iter_mut() {
+            *level = 0;
+        }
+
+        if let Some(ctx_idx_inc) = coded_block_flag_inc {
+            let ctx_idx = coded_block_flag_offset(ctx_block_cat)
+                + CTX_BLOCK_CAT_OFFSET[0][ctx_block_cat]
+                + ctx_idx_inc;
+            if !self.decode_decision(reader, ctx_idx)? {
+                return Ok(0);
+            }
+        }
+
+        let sig_offset = significant_coeff_flag_offset(ctx_block_cat, field) + CTX_BLOCK_CAT_OFFSET[1][ctx_block_cat];
+        let last_offset = last_significant_coeff_flag_offset(ctx_block_cat, field) + CTX_BLOCK_CAT_OFFSET[1][ctx_block_cat];
+        let abs_offset = coeff_abs_level_minus1_offset(ctx_block_cat) + CTX_BLOCK_CAT_OFFSET[2][ctx_block_cat];
+        let is_8x8 = ctx_block_cat == 5 || ctx_block_cat == 9 || ctx_block_cat == 13;
+        let num_c8x8 = std::cmp::max(1, max_num_coeff / 4);
+
+        // 9.3.3.1.3 Assignment process of ctxIdxInc for syntax elements significant_coeff_flag,
+        // last_significant_coeff_flag, and coeff_abs_level_minus1
+        let sig_inc = |level_list_idx: usize| -> usize {
+            if ctx_block_cat == 3 {
+                std::cmp::min(level_list_idx / num_c8x8, 2)
+            } else if is_8x8 {
+                SIGNIFICANT_COEFF_FLAG_8X8_INC[field as usize][level_list_idx] as usize
+            } else {
+                level_list_idx
+            }
+        };
+        let last_inc = |level_list_idx: usize| -> usize {
+            if ctx_block_cat == 3 {
+                std::cmp::min(level_list_idx / num_c8x8, 2)
+            } else if is_8x8 {
+                LAST_SIGNIFICANT_COEFF_FLAG_8X8_INC[level_list_idx] as usize
+            } else {
+                level_list_idx
+            }
+        };
+
+        let mut significant = [false; 64];
+        let mut num_coeff = end_idx + 1;
+        let mut i = start_idx;
+        while i + 1 < num_coeff {
+            significant[i] = self.decode_decision(reader, sig_offset + sig_inc(i))?;
+            if significant[i] && self.decode_decision(reader, last_offset + last_inc(i))? {
+                num_coeff = i + 1;
+            }
+            i += 1;
+        }
+        significant[num_coeff - 1] = true;
+
+        let max_gt1_inc = if ctx_block_cat == 3 { 3 } else { 4 };
+        let mut num_decod_abs_level_gt1 = 0;
+        let mut num_decod_abs_level_eq1 = 0;
+        let mut total_coeff = 0;
+
+        for i in (start_idx..num_coeff).rev() {
+            if !significant[i] {
+                continue;
+            }
+
+            let ctx_idx_inc = if num_decod_abs_level_gt1 != 0 {
+                0
+            } else {
+                std::cmp::min(4, 1 + num_decod_abs_level_eq1)
+            };
+
+            // 前缀: TU, cMax = 14
+            let mut coeff_abs_level_minus1 = 0u32;
+            if self.decode_decision(reader, abs_offset + ctx_idx_inc)? {
+                coeff_abs_level_minus1 = 1;
+                let ctx_idx = abs_offset + 5 + std::cmp::min(max_gt1_inc, num_decod_abs_level_gt1);
+                while coeff_abs_level_minus1 < 14 && self.decode_decision(reader, ctx_idx)? {
+                    coeff_abs_level_minus1 += 1;
+                }
+
+                if coeff_abs_level_minus1 == 14 {
+                    coeff_abs_level_minus1 += self.decode_exp_golomb_bypass(reader, 0)?;
+                }
+            }
+
+            if coeff_abs_level_minus1 >= i32::MAX as u32 {
+                return Err(error::malformed("coeff_abs_level_minus1 out of range"));
+            }
+
+            let level = coeff_abs_level_minus1 as i32 + 1;
+            coeff_level[i] = if self.decode_bypass(reader)? { -level } else { level };
+
+            if level == 1 {
+                num_decod_abs_level_eq1 += 1;
+            } else {
+                num_decod_abs_level_gt1 += 1;
+            }
+            total_coeff += 1;
+        }
+
+        Ok(total_coeff)
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::rbsp::{ RbspReader, SliceType };
+    use super::*;
+
+    // 9.3.4.2 Arithmetic encoding process, 仅用于测试解码过程
+    pub(crate) struct CabacEncoder {
+        contexts: Vec<ContextVariable>,
+        cod_i_low: u32,
+        cod_i_range: u32,
+        first_bit_flag: bool,
+        bits_outstanding: u32,
+        bits: Vec<bool>,
+    }
+
+    impl CabacEncoder {
+        pub(crate) fn new(decoder: &CabacDecoder) -> Self {
+            CabacEncoder {
+                contexts: (0..CTX_IDX_COUNT).map(|ctx_idx| decoder.context(ctx_idx)).collect(),
+                cod_i_low: 0,
+                cod_i_range: 510,
+                first_bit_flag: true,
+                bits_outstanding: 0,
+                bits: vec![],
+            }
+        }
+
+        fn put_bit(&mut self, bit: bool) {
+            if self.first_bit_flag {
+                self.first_bit_flag = false;
+            } else {
+                self.bits.push(bit);
+            }
+
+            while self.bits_outstanding > 0 {
+                self.bits.push(!bit);
+                self.bits_outstanding -= 1;
+            }
+        }
+
+        fn renorm(&mut self) {
+            while self.cod_i_range < 256 {
+                if self.cod_i_low < 256 {
+                    self.put_bit(false);
+                } else if self.cod_i_low >= 512 {
+                    self.cod_i_low -= 512;
+                    self.put_bit(true);
+                } else {
+                    self.cod_i_low -= 256;
+                    self.bits_outstanding += 1;
+                }
+                self.cod_i_range <<= 1;
+                self.cod_i_low <<= 1;
+            }
+        }
+
+        pub(crate) fn encode_decision(&mut self, ctx_idx: usize, bin_val: bool) {
+            let context = &mut self.contexts[ctx_idx];
+            let q_cod_i_range_idx = ((self.cod_i_range >> 6) & 3) as usize;
+            let cod_i_range_lps = RANGE_TAB_LPS[context.p_state_idx as usize][q_cod_i_range_idx] as u32;
+
+            self.cod_i_range -= cod_i_range_lps;
+            if bin_val != context.val_mps {
+                self.cod_i_low += self.cod_i_range;
+                self.cod_i_range = cod_i_range_lps;
+                if context.p_state_idx == 0 {
+                    context.val_mps = !context.val_mps;
+                }
+                context.p_state_idx = TRANS_IDX_LPS[context.p_state_idx as usize];
+            } else {
+                context.p_state_idx = TRANS_IDX_MPS[context.p_state_idx as usize];
+            }
+
+            self.renorm();
+        }
+
+        pub(crate) fn encode_bypass(&mut self, bin_val: bool) {
+            self.cod_i_low <<= 1;
+            if bin_val {
+                self.cod_i_low += self.cod_i_range;
+            }
+
+            if self.cod_i_low >= 1024 {
+                self.put_bit(true);
+                self.cod_i_low -= 1024;
+            } else if self.cod_i_low < 512 {
+                self.put_bit(false);
+            } else {
+                self.cod_i_low -= 512;
+                self.bits_outstanding += 1;
+            }
+        }
+
+        pub(crate) fn encode_terminate(&mut self, bin_val: bool) {
+            self.cod_i_range -= 2;
+            if bin_val {
+                self.cod_i_low += self.cod_i_range;
+                // EncodeFlush
+                self.cod_i_range = 2;
+                self.renorm();
+                let bit = (self.cod_i_low >> 9) & 1 == 1;
+                self.put_bit(bit);
+                self.bits.push((self.cod_i_low >> 8) & 1 == 1);
+                // rbsp_stop_one_bit
+                self.bits.push(true);
+            } else {
+                self.renorm();
+            }
+        }
+
+        // I_PCM 宏块 ( mb_type 的终止位为 1 之后 ): pcm_alignment_zero_bit, 样本, 然后重新初始化编码引擎 ( 9.3.1.2 )
+        pub(crate) fn pcm_samples(&mut self, samples: &[u8]) {
+            // EncodeFlush 写入的最后一位不是 rbsp_stop_one_bit, 而是算术码的一部分
+            while !self.bits.len().is_multiple_of(8) {
+                self.bits.push(false);
+            }
+            for &sample in samples {
+                self.bits.extend((0..8).rev().map(|i| (sample >> i) & 1 == 1));
+            }
+
+            self.cod_i_low = 0;
+            self.cod_i_range = 510;
+            self.first_bit_flag = true;
+            self.bits_outstanding = 0;
+        }
+
+        // 写入的比特 ( 最后的 end_of_slice_flag 为 1 时包括 rbsp_stop_one_bit )
+        pub(crate) fn bits(&self) -> &[bool] {
+            &self.bits
+        }
+
+        fn finish(self) -> Vec<u8> {
+            let mut data = vec![0u8; self.bits.len().div_ceil(8)];
+            for (i, bit) in self.bits.iter().enumerate() {
+                if *bit {
+                    data[i / 8] |= 0x80 >> (i % 8);
+                }
+            }
+            data
+        }
+    }
+
+    fn decoder(slice_type: SliceType) -> CabacDecoder {
+        CabacDecoder::new(slice_type, Some(0), 26).unwrap()
+    }
+
+    #[test]
+    fn test_context_init() {
+        // m = 0, n = 63 -> preCtxState = 63
+        assert_eq!(ContextVariable::new(0, 63, 26), ContextVariable { p_state_idx: 0, val_mps: false });
+        assert_eq!(ContextVariable::new(0, 64, 26), ContextVariable { p_state_idx: 0, val_mps: true });
+        // ( 20 * 51 ) >> 4 - 15 = 48
+        assert_eq!(ContextVariable::new(20, -15, 60), ContextVariable { p_state_idx: 15, val_mps: false });
+        assert_eq!(ContextVariable::new(-28, 127, 0), ContextVariable { p_state_idx: 62, val_mps: true });
+
+        assert!(CabacDecoder::new(SliceType::P, None, 26).is_err());
+        assert!(CabacDecoder::new(SliceType::B, Some(3), 26).is_err());
+
+        let decoder = decoder(SliceType::I);
+        assert_eq!(decoder.context(484), decoder.context(105));
+        assert_eq!(decoder.context(1012), decoder.context(93));
+        assert_eq!(decoder.context(1023), decoder.context(96));
+    }
+
+    #[test]
+    fn test_engine_round_trip() {
+        let mut decoder = decoder(SliceType::P);
+        let mut encoder = CabacEncoder::new(&decoder);
+
+        let mut bins = vec![];
+        let mut seed = 0x1234_5678u32;
+        for i in 0..2000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let ctx_idx = 11 + (i % 7);
+            // 偏向 0 的分布, 使上下文状态发生变化
+            let bin_val = (seed >> 16).is_multiple_of(5);
+            let bypass = i % 11 == 0;
+            bins.push((ctx_idx, bin_val, bypass));
+
+            if bypass {
+                encoder.encode_bypass(bin_val);
+            } else {
+                encoder.encode_decision(ctx_idx, bin_val);
+            }
+            encoder.encode_terminate(false);
+        }
+        encoder.encode_terminate(true);
+
+        let data = encoder.finish();
+        let mut reader = RbspReader::new(&data);
+        decoder.init_engine(&mut reader).unwrap();
+
+        for &(ctx_idx, bin_val, bypass) in bins.iter() {
+            let decoded = if bypass {
+                decoder.decode_bypass(&mut reader).unwrap()
+            } else {
+                decoder.decode_decision(&mut reader, ctx_idx).unwrap()
+            };
+            assert_eq!(decoded, bin_val);
+            assert!(!decoder.decode_terminate(&mut reader).unwrap());
+        }
+
+        assert!(decoder.end_of_slice_flag(&mut reader).unwrap());
+        // 最后读入的比特为 rbsp_stop_one_bit
+        assert_eq!(reader.bits_left(), (8 - reader.position() % 8) % 8);
+    }
+
+    #[test]
+    fn test_binarizations() {
+        let mut decoder = decoder(SliceType::B);
+        let mut encoder = CabacEncoder::new(&decoder);
+
+        // B_L1_Bi_16x8: 1 1 1 0 0 1 0
+        for (i, &bin) in [true, true, true, false, false, true, false].iter().enumerate() {
+            let ctx_idx = match i {
+                0 => MB_TYPE_B_PREFIX + 1,
+                1 => MB_TYPE_B_PREFIX + 3,
+                2 => MB_TYPE_B_PREFIX + 4,
+                _ => MB_TYPE_B_PREFIX + 5,
+            };
+            encoder.encode_decision(ctx_idx, bin);
+        }
+
+        // B_Bi_4x4 ( 12 ): 1 1 1 1 1
+        for &ctx_idx in [SUB_MB_TYPE_B, SUB_MB_TYPE_B + 1, SUB_MB_TYPE_B + 2, SUB_MB_TYPE_B + 3, SUB_MB_TYPE_B + 3].iter() {
+            encoder.encode_decision(ctx_idx, true);
+        }
+
+        // mvd = -12: 前缀 9 个 1, 后缀 UEG3( 3 ) = 0 011, 符号 1
+        for prefix in 0..9 {
+            encoder.encode_decision(MVD_Y + if prefix == 0 { 1 } else { std::cmp::min(prefix + 2, 6) }, true);
+        }
+        for &bin in [false, false, true, true, true].iter() {
+            encoder.encode_bypass(bin);
+        }
+
+        // mb_qp_delta = -2: k = 4, 1 1 1 1 0
+        for &(ctx_idx_inc, bin) in [(1, true), (2, true), (3, true), (3, true), (3, false)].iter() {
+            encoder.encode_decision(MB_QP_DELTA + ctx_idx_inc, bin);
+        }
+
+        // I_16x16_2_1_1 ( 19 ): 1 0 1 1 0 1 0
+        encoder.encode_decision(MB_TYPE_I + 2, true);
+        encoder.encode_terminate(false);
+        for &(ctx_idx_inc, bin) in [(3, true), (4, true), (5, false), (6, true), (7, false)].iter() {
+            encoder.encode_decision(MB_TYPE_I + ctx_idx_inc, bin);
+        }
+
+        encoder.encode_terminate(true);
+
+        let data = encoder.finish();
+        let mut reader = RbspReader::new(&data);
+        decoder.init_engine(&mut reader).unwrap();
+
+        assert_eq!(decoder.mb_type(&mut reader, SliceType::B, 1).unwrap(), 14);
+        assert_eq!(decoder.sub_mb_type(&mut reader, SliceType::B).unwrap(), 12);
+        assert_eq!(decoder.mvd(&mut reader, 1, 10).unwrap(), -12);
+        assert_eq!(decoder.mb_qp_delta(&mut reader, 1).unwrap(), -2);
+        assert_eq!(decoder.mb_type(&mut reader, SliceType::I, 2).unwrap(), 19);
+        assert!(decoder.end_of_slice_flag(&mut reader).is_ok());
+    }
+
+    // 按 bin 串编码, ctx_idx( binIdx, b ) 给出每个 bin 的 ctxIdx, 其中 276 为终止 bin
+    fn encode_bins<F: Fn(usize, &[bool]) -> usize>(encoder: &mut CabacEncoder, bins: &str, ctx_idx: F) {
+        let b: Vec<bool> = bins.chars().map(|c| c == '1').collect();
+        for (bin_idx, &bin) in b.iter().enumerate() {
+            match ctx_idx(bin_idx, &b) {
+                276 => encoder.encode_terminate(bin),
+                ctx_idx => encoder.encode_decision(ctx_idx, bin),
+            }
+        }
+    }
+
+    // 9.3.2.3 k-th order Exp-Golomb 的 bypass 编码
+    fn encode_exp_golomb_bypass(encoder: &mut CabacEncoder, value: u32, k: u32) {
+        let (mut value, mut k) = (value, k);
+        while value >= 1 << k {
+            encoder.encode_bypass(true);
+            value -= 1 << k;
+            k += 1;
+        }
+        encoder.encode_bypass(false);
+        while k > 0 {
+            k -= 1;
+            encoder.encode_bypass((value >> k) & 1 == 1);
+        }
+    }
+
+    // 所有 bin 都使用了相同的 ctxIdx 时, 编码与解码两端的上下文变量完全一致
+    fn assert_contexts_eq(decoder: &CabacDecoder, contexts: &[ContextVariable]) {
+        for (ctx_idx, context) in contexts.iter().enumerate() {
+            assert_eq!(decoder.context(ctx_idx), *context, "ctxIdx {}", ctx_idx);
+        }
+    }
+
+    // Table 9-36: I slice 中的 mb_type ( 以及其它 slice 中的 I 后缀 )
+    fn mb_type_i_bins(mb_type: u32) -> String {
+        match mb_type {
+            0 => "0".to_string(),
+            25 => "11".to_string(),
+            _ => {
+                let pred_mode = (mb_type - 1) % 4;
+                let chroma = (mb_type - 1) / 4 % 3;
+                let luma = (mb_type - 1) / 12;
+                let mut bins = format!("10{}{}", luma, (chroma != 0) as u8);
+                if chroma != 0 {
+                    bins += if chroma == 2 { "1" } else { "0" };
+                }
+                bins + &format!("{}{}", pred_mode >> 1, pred_mode & 1)
+            },
+        }
+    }
+
+    // Table 9-39: 前缀 ( ctxIdxOffset 3 ) 与后缀 ( ctxIdxOffset 17, 32 ) 的 ctxIdx
+    fn mb_type_i_ctx_idx(offset: usize, ctx_idx_inc: usize, bin_idx: usize, b: &[bool]) -> usize {
+        let prefix = offset == MB_TYPE_I;
+        let b3 = b.len() > 3 && b[3];
+        let inc = match (prefix, bin_idx) {
+            (_, 0) => ctx_idx_inc,
+            (_, 1) => return 276,
+            (true, 2) => 3,
+            (true, 3) => 4,
+            (true, 4) => if b3 { 5 } else { 6 },
+            (true, 5) => if b3 { 6 } else { 7 },
+            (true, _) => 7,
+            (false, 2) => 1,
+            (false, 3) => 2,
+            (false, 4) => if b3 { 2 } else { 3 },
+            (false, _) => 3,
+        };
+        offset + inc
+    }
+
+    // Table 9-37: P/SP 以及 B slice 中的 mb_type ( 不含 I 后缀 )
+    const MB_TYPE_P_BINS: [&str; 4] = ["000", "011", "010", "001"];
+    const MB_TYPE_B_BINS: [&str; 23] = [
+        "0", "100", "101", "110000", "110001", "110010", "110011", "110100", "110101", "110110", "110111", "111110",
+        "1110000", "1110001", "1110010", "1110011", "1110100", "1110101", "1110110", "1110111", "1111000", "1111001",
+        "111111",
+    ];
+
+    fn encode_mb_type(encoder: &mut CabacEncoder, slice_type: SliceType, mb_type: u32, ctx_idx_inc: usize) {
+        match slice_type {
+            SliceType::I => {
+                encode_bins(encoder, &mb_type_i_bins(mb_type), |i, b| mb_type_i_ctx_idx(MB_TYPE_I, ctx_idx_inc, i, b));
+            },
+            SliceType::P => {
+                let ctx_idx = |i: usize, b: &[bool]| match i {
+                    0 => MB_TYPE_P_PREFIX,
+                    1 => MB_TYPE_P_PREFIX + 1,
+                    _ => MB_TYPE_P_PREFIX + if b[1] { 3 } else { 2 },
+                };
+                if mb_type < 5 {
+                    encode_bins(encoder, MB_TYPE_P_BINS[mb_type as usize], ctx_idx);
+                } else {
+                    encode_bins(encoder, "1", ctx_idx);
+                    encode_bins(encoder, &mb_type_i_bins(mb_type - 5), |i, b| mb_type_i_ctx_idx(MB_TYPE_P_SUFFIX, 0, i, b));
+                }
+            },
+            _ => {
+                let ctx_idx = |i: usize, b: &[bool]| match i {
+                    0 => MB_TYPE_B_PREFIX + ctx_idx_inc,
+                    1 => MB_TYPE_B_PREFIX + 3,
+                    2 => MB_TYPE_B_PREFIX + if b[1] { 4 } else { 5 },
+                    _ => MB_TYPE_B_PREFIX + 5,
+                };
+                if mb_type < 23 {
+                    encode_bins(encoder, MB_TYPE_B_BINS[mb_type as usize], ctx_idx);
+                } else {
+                    encode_bins(encoder, "111101", ctx_idx);
+                    encode_bins(encoder, &mb_type_i_bins(mb_type - 23), |i, b| mb_type_i_ctx_idx(MB_TYPE_B_SUFFIX, 0, i, b));
+                }
+            },
+        }
+    }
+
+    #[test]
+    fn test_mb_type_round_trip() {
+        // I_PCM 的终止 bin 为 1 时编码引擎刷新, 所以放在最后
+        let cases = [
+            (SliceType::I, (0..=25).collect::<Vec<u32>>()),
+            (SliceType::P, (0..4).chain(5..=30).collect()),
+            (SliceType::B, (0..=48).collect()),
+        ];
+
+        for (slice_type, mb_types) in cases.iter() {
+            let mut decoder = decoder(*slice_type);
+            let mut encoder = CabacEncoder::new(&decoder);
+            for &mb_type in mb_types.iter() {
+                encode_mb_type(&mut encoder, *slice_type, mb_type, mb_type as usize % 3);
+            }
+
+            let contexts = encoder.contexts.clone();
+            let data = encoder.finish();
+            let mut reader = RbspReader::new(&data);
+            decoder.init_engine(&mut reader).unwrap();
+            for &mb_type in mb_types.iter() {
+                assert_eq!(decoder.mb_type(&mut reader, *slice_type, mb_type as usize % 3).unwrap(), mb_type);
+            }
+            assert_contexts_eq(&decoder, &contexts);
+        }
+    }
+
+    // Table 9-38
+    const SUB_MB_TYPE_P_BINS: [&str; 4] = ["1", "00", "011", "010"];
+    const SUB_MB_TYPE_B_BINS: [&str; 13] = [
+        "0", "100", "101", "11000", "11001", "11010", "11011", "111000", "111001", "111010", "111011", "11110", "11111",
+    ];
+
+    #[test]
+    fn test_sub_mb_type_round_trip() {
+        for &slice_type in [SliceType::P, SliceType::B].iter() {
+            let mut decoder = decoder(slice_type);
+            let mut encoder = CabacEncoder::new(&decoder);
+
+            let sub_mb_types: Vec<u32> = if slice_type == SliceType::P { (0..4).collect() } else { (0..13).collect() };
+            // 每个取值编码两次, 使上下文状态在两次之间发生变化
+            for &sub_mb_type in sub_mb_types.iter().chain(sub_mb_types.iter().rev()) {
+                if slice_type == SliceType::P {
+                    encode_bins(&mut encoder, SUB_MB_TYPE_P_BINS[sub_mb_type as usize], |i, _| SUB_MB_TYPE_P + i);
+                } else {
+                    encode_bins(&mut encoder, SUB_MB_TYPE_B_BINS[sub_mb_type as usize], |i, b| match i {
+                        0 | 1 => SUB_MB_TYPE_B + i,
+                        2 => SUB_MB_TYPE_B + if b[1] { 2 } else { 3 },
+                        _ => SUB_MB_TYPE_B + 3,
+                    });
+                }
+            }
+            encoder.encode_terminate(true);
+
+            let contexts = encoder.contexts.clone();
+            let data = encoder.finish();
+            let mut reader = RbspReader::new(&data);
+            decoder.init_engine(&mut reader).unwrap();
+            for &sub_mb_type in sub_mb_types.iter().chain(sub_mb_types.iter().rev()) {
+                assert_eq!(decoder.sub_mb_type(&mut reader, slice_type).unwrap(), sub_mb_type);
+            }
+            assert!(decoder.end_of_slice_flag(&mut reader).unwrap());
+
+            assert_contexts_eq(&decoder, &contexts);
+        }
+    }
+
+    // mvd: UEG3, signedValFlag = 1, uCoff = 9; 前缀 bin 0 的 ctxIdxInc 由 absMvdComp 之和决定, bin 1 .. 4 为 3, 4, 5, 6
+    fn encode_mvd(encoder: &mut CabacEncoder, comp_idx: usize, abs_mvd_comp_sum: u32, mvd: i32) {
+        let offset = if comp_idx == 0 { MVD_X } else { MVD_Y };
+        let abs = mvd.unsigned_abs();
+        let prefix = std::cmp::min(abs, 9) as usize;
+
+        let mut bins = "1".repeat(prefix);
+        if prefix < 9 {
+            bins += "0";
+        }
+        encode_bins(encoder, &bins, |i, _| match i {
+            0 if abs_mvd_comp_sum < 3 => offset,
+            0 if abs_mvd_comp_sum <= 32 => offset + 1,
+            0 => offset + 2,
+            _ => offset + std::cmp::min(i + 2, 6),
+        });
+
+        if abs >= 9 {
+            encode_exp_golomb_bypass(encoder, abs - 9, 3);
+        }
+        if abs != 0 {
+            encoder.encode_bypass(mvd < 0);
+        }
+    }
+
+    #[test]
+    fn test_mvd_ref_idx_round_trip() {
+        let mut decoder = decoder(SliceType::B);
+        let mut encoder = CabacEncoder::new(&decoder);
+
+        let mut cases = vec![];
+        for comp_idx in 0..2 {
+            for &sum in [0, 2, 3, 32, 33, 200].iter() {
+                for &mvd in [0, 1, -1, 2, -8, 9, -9, 10, 16, 17, -100, 5000].iter() {
+                    cases.push((comp_idx, sum, mvd));
+                }
+            }
+        }
+
+        // ref_idx: U, bin 0 的 ctxIdxInc 为 condTermFlagA + 2 * condTermFlagB, bin 1 为 4, 其余为 5
+        for ctx_idx_inc in 0..4 {
+            for ref_idx in 0..6 {
+                encode_bins(&mut encoder, &("1".repeat(ref_idx) + "0"), |i, _| match i {
+                    0 => REF_IDX + ctx_idx_inc,
+                    1 => REF_IDX + 4,
+                    _ => REF_IDX + 5,
+                });
+            }
+        }
+        for &(comp_idx, sum, mvd) in cases.iter() {
+            encode_mvd(&mut encoder, comp_idx, sum, mvd);
+        }
+        encoder.encode_terminate(true);
+
+        let contexts = encoder.contexts.clone();
+        let data = encoder.finish();
+        let mut reader = RbspReader::new(&data);
+        decoder.init_engine(&mut reader).unwrap();
+
+        for ctx_idx_inc in 0..4 {
+            for ref_idx in 0..6 {
+                assert_eq!(decoder.ref_idx(&mut reader, ctx_idx_inc, 15).unwrap(), ref_idx);
+            }
+        }
+        for &(comp_idx, sum, mvd) in cases.iter() {
+            assert_eq!(decoder.mvd(&mut reader, comp_idx, sum).unwrap(), mvd, "mvd {} sum {}", mvd, sum);
+        }
+        assert!(decoder.end_of_slice_flag(&mut reader).unwrap());
+
+        assert_contexts_eq(&decoder, &contexts);
+    }
+
+    // Table 9-34 与 Table 9-40: 帧宏块中各 ctxBlockCat 的 ( coded_block_flag, significant_coeff_flag,
+    // last_significant_coeff_flag, coeff_abs_level_minus1 ) 的 ctxIdxOffset + ctxBlockCatOffset
+    const FRAME_CTX_IDX_BASES: [(usize, usize, usize, usize); 14] = [
+        (85, 105, 166, 227), (89, 120, 181, 237), (93, 134, 195, 247), (97, 149, 210, 257), (101, 152, 213, 266),
+        (1012, 402, 417, 426),
+        (460, 484, 572, 952), (464, 499, 587, 962), (468, 513, 601, 972),
+        (1016, 660, 690, 708),
+        (472, 528, 616, 982), (476, 543, 631, 992), (480, 557, 645, 1002),
+        (1020, 718, 748, 766),
+    ];
+
+    // Table 9-43 中帧宏块的 significant_coeff_flag 以及 last_significant_coeff_flag 的 ctxIdxInc
+    const FRAME_SIGNIFICANT_8X8_INC: [usize; 63] = [
+        0, 1, 2, 3, 4, 5, 5, 4, 4, 3, 3, 4, 4, 4, 5, 5, 4, 4, 4, 4, 3, 3, 6, 7, 7, 7, 8, 9, 10, 9, 8, 7,
+        7, 6, 11, 12, 13, 11, 6, 7, 8, 9, 14, 10, 9, 8, 6, 11, 12, 13, 11, 6, 9, 14, 10, 9, 11, 12, 13, 11, 14, 10, 12,
+    ];
+    const LAST_SIGNIFICANT_8X8_INC: [usize; 63] = [
+        0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+        3, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7, 8, 8, 8,
+    ];
+
+    // 7.3.5.3.3 residual_block_cabac( ) 的编码, coeff_level 的长度为 maxNumCoeff ( startIdx 为 0 ), 帧宏块
+    fn encode_residual_block(encoder: &mut CabacEncoder,
+                             coeff_level: &[i32],
+                             ctx_block_cat: usize,
+                             coded_block_flag_inc: Option<usize>) {
+        let (cbf_base, sig_base, last_base, abs_base) = FRAME_CTX_IDX_BASES[ctx_block_cat];
+        let num_c8x8 = std::cmp::max(1, coeff_level.len() / 4);
+        let inc = |table: &[usize; 63], i: usize| -> usize {
+            match ctx_block_cat {
+                3 => std::cmp::min(i / num_c8x8, 2),
+                5 | 9 | 13 => table[i],
+                _ => i,
+            }
+        };
+
+        let last = coeff_level.iter().rposition(|&level| level != 0);
+        if let Some(ctx_idx_inc) = coded_block_flag_inc {
+            encoder.encode_decision(cbf_base + ctx_idx_inc, last.is_some());
+        }
+        let last = match last {
+            Some(last) => last,
+            None => return,
+        };
+
+        for (i, &level) in coeff_level[..coeff_level.len() - 1].iter().enumerate() {
+            encoder.encode_decision(sig_base + inc(&FRAME_SIGNIFICANT_8X8_INC, i), level != 0);
+            if level != 0 {
+                encoder.encode_decision(last_base + inc(&LAST_SIGNIFICANT_8X8_INC, i), i == last);
+                if i == last {
+                    break;
+                }
+            }
+        }
+
+        // coeff_abs_level_minus1: 前缀为 TU ( cMax = 14 ), 后缀为 EG0; 然后是 coeff_sign_flag
+        let max_gt1_inc = if ctx_block_cat == 3 { 3 } else { 4 };
+        let (mut num_decod_abs_level_gt1, mut num_decod_abs_level_eq1) = (0, 0);
+        for &level in coeff_level[..=last].iter().rev().filter(|&&level| level != 0) {
+            let coeff_abs_level_minus1 = level.unsigned_abs() - 1;
+            let prefix = std::cmp::min(coeff_abs_level_minus1, 14) as usize;
+            let mut bins = "1".repeat(prefix);
+            if prefix < 14 {
+                bins += "0";
+            }
+            encode_bins(encoder, &bins, |i, _| match i {
+                0 if num_decod_abs_level_gt1 != 0 => abs_base,
+                0 => abs_base + std::cmp::min(4, 1 + num_decod_abs_level_eq1),
+                _ => abs_base + 5 + std::cmp::min(max_gt1_inc, num_decod_abs_level_gt1),
+            });
+            if coeff_abs_level_minus1 >= 14 {
+                encode_exp_golomb_bypass(encoder, coeff_abs_level_minus1 - 14, 0);
+            }
+            encoder.encode_bypass(level < 0);
+
+            if coeff_abs_level_minus1 == 0 {
+                num_decod_abs_level_eq1 += 1;
+            } else {
+                num_decod_abs_level_gt1 += 1;
+            }
+        }
+    }
+
+    // 对 blocks 中的 ( ctxBlockCat, coded_block_flag 的 ctxIdxInc, coeffLevel ) 编码后逐个解码
+    fn residual_round_trip(blocks: &[(usize, Option<usize>, Vec<i32>)]) {
+        let mut decoder = decoder(SliceType::P);
+        let mut encoder = CabacEncoder::new(&decoder);
+        for (ctx_block_cat, coded_block_flag_inc, coeff_level) in blocks.iter() {
+            encode_residual_block(&mut encoder, coeff_level, *ctx_block_cat, *coded_block_flag_inc);
+        }
+        encoder.encode_terminate(true);
+
+        let contexts = encoder.contexts.clone();
+        let data = encoder.finish();
+        let mut reader = RbspReader::new(&data);
+        decoder.init_engine(&mut reader).unwrap();
+
+        for (ctx_block_cat, coded_block_flag_inc, coeff_level) in blocks.iter() {
+            let max_num_coeff = coeff_level.len();
+            let mut decoded = vec![7; 64];
+            let total_coeff = decoder.residual_block(&mut reader, &mut decoded, 0, max_num_coeff - 1, max_num_coeff,
+                                                     *ctx_block_cat, false, *coded_block_flag_inc).unwrap();
+            assert_eq!(decoded[..max_num_coeff], coeff_level[..], "ctxBlockCat {}", ctx_block_cat);
+            assert_eq!(total_coeff as usize, coeff_level.iter().filter(|&&level| level != 0).count());
+        }
+        assert!(decoder.end_of_slice_flag(&mut reader).unwrap());
+
+        assert_contexts_eq(&decoder, &contexts);
+    }
+
+    #[test]
+    fn test_coded_block_flag_round_trip() {
+        // maxNumCoeff: ChromaDCLevel 为 4:2:0 的 4 个系数
+        const MAX_NUM_COEFF: [usize; 14] = [16, 15, 16, 4, 15, 64, 16, 15, 16, 64, 16, 15, 16, 64];
+
+        let mut blocks = vec![];
+        for (ctx_block_cat, &max_num_coeff) in MAX_NUM_COEFF.iter().enumerate() {
+            for ctx_idx_inc in 0..4 {
+                let mut coeff_level = vec![0; max_num_coeff];
+                if (ctx_block_cat + ctx_idx_inc) % 2 == 1 {
+                    coeff_level[ctx_idx_inc] = ctx_idx_inc as i32 - 2;
+                }
+                blocks.push((ctx_block_cat, Some(ctx_idx_inc), coeff_level));
+            }
+        }
+        residual_round_trip(&blocks);
+    }
+
+    #[test]
+    fn test_significance_map_8x8_round_trip() {
+        assert_eq!(SIGNIFICANT_COEFF_FLAG_8X8_INC[0].iter().map(|&inc| inc as usize).collect::<Vec<_>>()[..],
+                   FRAME_SIGNIFICANT_8X8_INC[..]);
+        assert_eq!(LAST_SIGNIFICANT_COEFF_FLAG_8X8_INC.iter().map(|&inc| inc as usize).collect::<Vec<_>>()[..],
+                   LAST_SIGNIFICANT_8X8_INC[..]);
+
+        // ChromaArrayType 不为 3 时 8x8 块没有 coded_block_flag; 最后一个系数在 63 时 significant_coeff_flag 被推断
+        let mut blocks = vec![];
+        let mut seed = 0x2468_ace0u32;
+        for &last in [0, 5, 22, 42, 62, 63].iter() {
+            let mut coeff_level = vec![0; 64];
+            for level in coeff_level[..last].iter_mut() {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                if (seed >> 16).is_multiple_of(3) {
+                    *level = ((seed >> 20) % 5) as i32 - 2;
+                }
+            }
+            coeff_level[last] = 1;
+            blocks.push((5, None, coeff_level));
+        }
+        // 4:4:4 中 Cb, Cr 的 8x8 块
+        let mut coeff_level = vec![0; 64];
+        coeff_level[33] = -3;
+        blocks.push((9, Some(2), coeff_level.clone()));
+        blocks.push((13, None, coeff_level));
+
+        residual_round_trip(&blocks);
+    }
+
+    #[test]
+    fn test_coeff_abs_level_round_trip() {
+        let mut luma = vec![5, -3, 2, 0, 2, -2, 0, 0, 15, 0, -16, 1, 0, -1, 0, -1000];
+        let blocks = vec![
+            // coeff_abs_level_minus1 为 13, 14 ( 后缀 0 ), 15 以及 999 ( EG0 后缀 ), numDecodAbsLevelGt1 超过 4
+            (2, Some(0), luma.clone()),
+            // 4:2:2 的 ChromaDCLevel: significant_coeff_flag 的 ctxIdxInc 为 Min( numDecodAbsLevel / NumC8x8, 2 )
+            (3, Some(1), vec![0, 0, 0, 4, 0, -1, 0, 2]),
+            (3, Some(3), vec![-14, 3, -2, 2]),
+            // Intra16x16ACLevel 与 ChromaACLevel: 15 个系数
+            (1, Some(1), vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 65535]),
+            (4, Some(2), vec![-2, 0, 1, 1, 1, 1, -1, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+        residual_round_trip(&blocks);
+
+        luma.reverse();
+        residual_round_trip(&[(0, Some(3), luma)]);
+    }
+}
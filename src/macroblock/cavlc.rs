@@ -0,0 +1,444 @@
+// 9.2 CAVLC parsing process for transform coefficient levels ( Page 214 )
+
+use crate::error::{ self, Error };
+use crate::rbsp::RbspReader;
+
+
+// Table 9-5 – coeff_token, TrailingOnes, TotalCoeff 以及 nC 的对应关系 ( Page 218 )
+//
+// 下标为 TotalCoeff * 4 + TrailingOnes, 码长为 0 表示该组合不存在。
+// 依次为: 0 <= nC < 2, 2 <= nC < 4, 4 <= nC < 8, 8 <= nC
+pub const COEFF_TOKEN_LEN: [[u8; 4 * 17]; 4] = [
+    [
+         1, 0, 0, 0,
+         6, 2, 0, 0,     8, 6, 3, 0,     9, 8, 7, 5,    10, 9, 8, 6,
+        11,10, 9, 7,    13,11,10, 8,    13,13,11, 9,    13,13,13,10,
+        14,14,13,11,    14,14,14,13,    15,15,14,14,    15,15,15,14,
+        16,15,15,15,    16,16,16,15,    16,16,16,16,    16,16,16,16,
+    ],
+    [
+         2, 0, 0, 0,
+         6, 2, 0, 0,     6, 5, 3, 0,     7, 6, 6, 4,     8, 6, 6, 4,
+         8, 7, 7, 5,     9, 8, 8, 6,    11, 9, 9, 6,    11,11,11, 7,
+        12,11,11, 9,    12,12,12,11,    12,12,12,11,    13,13,13,12,
+        13,13,13,13,    13,14,13,13,    14,14,14,13,    14,14,14,14,
+    ],
+    [
+         4, 0, 0, 0,
+         6, 4, 0, 0,     6, 5, 4, 0,     6, 5, 5, 4,     7, 5, 5, 4,
+         7, 5, 5, 4,     7, 6, 6, 4,     7, 6, 6, 4,     8, 7, 7, 5,
+         8, 8, 7, 6,     9, 8, 8, 7,     9, 9, 8, 8,     9, 9, 9, 8,
+        10, 9, 9, 9,    10,10,10,10,    10,10,10,10,    10,10,10,10,
+    ],
+    [
+         6, 0, 0, 0,
+         6, 6, 0, 0,     6, 6, 6, 0,     6, 6, 6, 6,     6, 6, 6, 6,
+         6, 6, 6, 6,     6, 6, 6, 6,     6, 6, 6, 6,     6, 6, 6, 6,
+         6, 6, 6, 6,     6, 6, 6, 6,     6, 6, 6, 6,     6, 6, 6, 6,
+         6, 6, 6, 6,     6, 6, 6, 6,     6, 6, 6, 6,     6, 6, 6, 6,
+    ],
+];
+
+pub const COEFF_TOKEN_CODE: [[u8; 4 * 17]; 4] = [
+    [
+         1, 0, 0, 0,
+         5, 1, 0, 0,     7, 4, 1, 0,     7, 6, 5, 3,     7, 6, 5, 3,
+         7, 6, 5, 4,    15, 6, 5, 4,    11,14, 5, 4,     8,10,13, 4,
+        15,14, 9, 4,    11,10,13,12,    15,14, 9,12,    11,10,13, 8,
+        15, 1, 9,12,    11,14,13, 8,     7,10, 9,12,     4, 6, 5, 8,
+    ],
+    [
+         3, 0, 0, 0,
+        11, 2, 0, 0,     7, 7, 3, 0,     7,10, 9, 5,     7, 6, 5, 4,
+         4, 6, 5, 6,     7, 6, 5, 8,    15, 6, 5, 4,    11,14,13, 4,
+        15,10, 9, 4,    11,14,13,12,     8,10, 9, 8,    15,14,13,12,
+        11,10, 9,12,     7,11, 6, 8,     9, 8,10, 1,     7, 6, 5, 4,
+    ],
+    [
+        15, 0, 0, 0,
+        15,14, 0, 0,    11,15,13, 0,     8,12,14,12,    15,10,11,11,
+        11, 8, 9,10,     9,14,13, 9,     8,10, 9, 8,    15,14,13,13,
+        11,14,10,12,    15,10,13,12,    11,14, 9,12,     8,10,13, 8,
+        13, 7, 9,12,     9,12,11,10,     5, 8, 7, 6,     1, 4, 3, 2,
+    ],
+    [
+         3, 0, 0, 0,
+         0, 1, 0, 0,     4, 5, 6, 0,     8, 9,10,11,    12,13,14,15,
+        16,17,18,19,    20,21,22,23,    24,25,26,27,    28,29,30,31,
+        32,33,34,35,    36,37,38,39,    40,41,42,43,    44,45,46,47,
+        48,49,50,51,    52,53,54,55,    56,57,58,59,    60,61,62,63,
+    ],
+];
+
+// nC == -1 ( ChromaArrayType == 1 的色度 DC )
+pub const CHROMA_DC_COEFF_TOKEN_LEN: [u8; 4 * 5] = [
+     2, 0, 0, 0,
+     6, 1, 0, 0,
+     6, 6, 3, 0,
+     6, 7, 7, 6,
+     6, 8, 8, 7,
+];
+
+pub const CHROMA_DC_COEFF_TOKEN_CODE: [u8; 4 * 5] = [
+     1, 0, 0, 0,
+     7, 1, 0, 0,
+     4, 6, 1, 0,
+     3, 3, 2, 5,
+     2, 3, 2, 0,
+];
+
+// nC == -2 ( ChromaArrayType == 2 的色度 DC )
+pub const CHROMA422_DC_COEFF_TOKEN_LEN: [u8; 4 * 9] = [
+     1, 0, 0, 0,
+     7, 2, 0, 0,
+     7, 7, 3, 0,
+     9, 7, 7, 5,
+     9, 9, 7, 6,
+    10,10, 9, 7,
+    11,11,10, 7,
+    12,12,11,10,
+    13,12,12,11,
+];
+
+pub const CHROMA422_DC_COEFF_TOKEN_CODE: [u8; 4 * 9] = [
+     1, 0, 0, 0,
+    15, 1, 0, 0,
+    14,13, 1, 0,
+     7,12,11, 1,
+     6, 5,10, 1,
+     7, 6, 4, 9,
+     7, 6, 5, 8,
+     7, 6, 5, 4,
+     7, 5, 4, 4,
+];
+
+// Table 9-7, 9-8 – total_zeros tables for 4x4 blocks ( Page 220 )
+// 下标为 [ tzVlcIndex - 1 ][ total_zeros ]
+pub const TOTAL_ZEROS_LEN: [[u8; 16]; 15] = [
+    [1,3,3,4,4,5,5,6,6,7,7,8,8,9,9,9],
+    [3,3,3,3,3,4,4,4,4,5,5,6,6,6,6,0],
+    [4,3,3,3,4,4,3,3,4,5,5,6,5,6,0,0],
+    [5,3,4,4,3,3,3,4,3,4,5,5,5,0,0,0],
+    [4,4,4,3,3,3,3,3,4,5,4,5,0,0,0,0],
+    [6,5,3,3,3,3,3,3,4,3,6,0,0,0,0,0],
+    [6,5,3,3,3,2,3,4,3,6,0,0,0,0,0,0],
+    [6,4,5,3,2,2,3,3,6,0,0,0,0,0,0,0],
+    [6,6,4,2,2,3,2,5,0,0,0,0,0,0,0,0],
+    [5,5,3,2,2,2,4,0,0,0,0,0,0,0,0,0],
+    [4,4,3,3,1,3,0,0,0,0,0,0,0,0,0,0],
+    [4,4,2,1,3,0,0,0,0,0,0,0,0,0,0,0],
+    [3,3,1,2,0,0,0,0,0,0,0,0,0,0,0,0],
+    [2,2,1,0,0,0,0,0,0,0,0,0,0,0,0,0],
+    [1,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
+];
+
+pub const TOTAL_ZEROS_CODE: [[u8; 16]; 15] = [
+    [1,3,2,3,2,3,2,3,2,3,2,3,2,3,2,1],
+    [7,6,5,4,3,5,4,3,2,3,2,3,2,1,0,0],
+    [5,7,6,5,4,3,4,3,2,3,2,1,1,0,0,0],
+    [3,7,5,4,6,5,4,3,3,2,2,1,0,0,0,0],
+    [5,4,3,7,6,5,4,3,2,1,1,0,0,0,0,0],
+    [1,1,7,6,5,4,3,2,1,1,0,0,0,0,0,0],
+    [1,1,5,4,3,3,2,1,1,0,0,0,0,0,0,0],
+    [1,1,1,3,3,2,2,1,0,0,0,0,0,0,0,0],
+    [1,0,1,3,2,1,1,1,0,0,0,0,0,0,0,0],
+    [1,0,1,3,2,1,1,0,0,0,0,0,0,0,0,0],
+    [0,1,1,2,1,3,0,0,0,0,0,0,0,0,0,0],
+    [0,1,1,1,1,0,0,0,0,0,0,0,0,0,0,0],
+    [0,1,1,1,0,0,0,0,0,0,0,0,0,0,0,0],
+    [0,1,1,0,0,0,0,0,0,0,0,0,0,0,0,0],
+    [0,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
+];
+
+// Table 9-9 (a) – total_zeros tables for chroma DC 2x2 blocks ( 4:2:0 )
+pub const CHROMA_DC_TOTAL_ZEROS_LEN: [[u8; 4]; 3] = [
+    [1,2,3,3],
+    [1,2,2,0],
+    [1,1,0,0],
+];
+
+pub const CHROMA_DC_TOTAL_ZEROS_CODE: [[u8; 4]; 3] = [
+    [1,1,1,0],
+    [1,1,0,0],
+    [1,0,0,0],
+];
+
+// Table 9-9 (b) – total_zeros tables for chroma DC 2x4 blocks ( 4:2:2 )
+pub const CHROMA422_DC_TOTAL_ZEROS_LEN: [[u8; 8]; 7] = [
+    [1,3,3,4,4,4,5,5],
+    [3,2,3,3,3,3,3,0],
+    [3,3,2,2,3,3,0,0],
+    [3,2,2,2,3,0,0,0],
+    [2,2,2,2,0,0,0,0],
+    [2,2,1,0,0,0,0,0],
+    [1,1,0,0,0,0,0,0],
+];
+
+pub const CHROMA422_DC_TOTAL_ZEROS_CODE: [[u8; 8]; 7] = [
+    [1,2,3,2,3,1,1,0],
+    [0,1,1,4,5,6,7,0],
+    [0,1,1,2,6,7,0,0],
+    [6,0,1,2,7,0,0,0],
+    [0,1,2,3,0,0,0,0],
+    [0,1,1,0,0,0,0,0],
+    [0,1,0,0,0,0,0,0],
+];
+
+// Table 9-10 – Tables for run_before ( Page 221 )
+// 下标为 [ Min( zerosLeft, 7 ) - 1 ][ run_before ]
+pub const RUN_BEFORE_LEN: [[u8; 15]; 7] = [
+    [1,1,0,0,0,0,0,0,0,0,0,0,0,0,0],
+    [1,2,2,0,0,0,0,0,0,0,0,0,0,0,0],
+    [2,2,2,2,0,0,0,0,0,0,0,0,0,0,0],
+    [2,2,2,3,3,0,0,0,0,0,0,0,0,0,0],
+    [2,2,3,3,3,3,0,0,0,0,0,0,0,0,0],
+    [2,3,3,3,3,3,3,0,0,0,0,0,0,0,0],
+    [3,3,3,3,3,3,3,4,5,6,7,8,9,10,11],
+];
+
+pub const RUN_BEFORE_CODE: [[u8; 15]; 7] = [
+    [1,0,0,0,0,0,0,0,0,0,0,0,0,0,0],
+    [1,1,0,0,0,0,0,0,0,0,0,0,0,0,0],
+    [3,2,1,0,0,0,0,0,0,0,0,0,0,0,0],
+    [3,2,1,1,0,0,0,0,0,0,0,0,0,0,0],
+    [3,2,3,2,1,0,0,0,0,0,0,0,0,0,0],
+    [3,0,1,3,2,5,4,0,0,0,0,0,0,0,0],
+    [7,6,5,4,3,2,1,1,1,1,1,1,1,1,1],
+];
+
+
+// 在变长码表中查找与后续比特匹配的码字, 返回其下标
+fn read_vlc(reader: &mut RbspReader, lens: &[u8], codes: &[u8]) -> Result<usize, Error> {
+    for (index, (&len, &code)) in lens.iter().zip(codes.iter()).enumerate() {
+        if len == 0 {
+            continue;
+        }
+
+        if reader.peek_bits(len as u32) == code as u32 {
+            reader.skip_bits(len as usize)?;
+            return Ok(index);
+        }
+    }
+
+    Err(error::malformed("invalid cavlc code"))
+}
+
+// coeff_token, 返回 ( TrailingOnes, TotalCoeff )
+pub fn read_coeff_token(reader: &mut RbspReader, nc: i32) -> Result<(usize, usize), Error> {
+    let index = match nc {
+        -1 => read_vlc(reader, &CHROMA_DC_COEFF_TOKEN_LEN, &CHROMA_DC_COEFF_TOKEN_CODE)?,
+        -2 => read_vlc(reader, &CHROMA422_DC_COEFF_TOKEN_LEN, &CHROMA422_DC_COEFF_TOKEN_CODE)?,
+        0 ..= 1 => read_vlc(reader, &COEFF_TOKEN_LEN[0], &COEFF_TOKEN_CODE[0])?,
+        2 ..= 3 => read_vlc(reader, &COEFF_TOKEN_LEN[1], &COEFF_TOKEN_CODE[1])?,
+        4 ..= 7 => read_vlc(reader, &COEFF_TOKEN_LEN[2], &COEFF_TOKEN_CODE[2])?,
+        _ if nc >= 8 => read_vlc(reader, &COEFF_TOKEN_LEN[3], &COEFF_TOKEN_CODE[3])?,
+        _ => return Err(error::malformed("nC out of range")),
+    };
+
+    Ok((index % 4, index / 4))
+}
+
+// level_prefix ( 9.2.2.1 )
+fn read_level_prefix(reader: &mut RbspReader) -> Result<u32, Error> {
+    let mut leading_zero_bits = 0u32;
+    while !reader.read_bit()? {
+        leading_zero_bits += 1;
+        if leading_zero_bits > 32 {
+            return Err(error::malformed("level_prefix is too long"));
+        }
+    }
+
+    Ok(leading_zero_bits)
+}
+
+fn read_total_zeros(reader: &mut RbspReader, total_coeff: usize, max_num_coeff: usize) -> Result<usize, Error> {
+    let index = total_coeff - 1;
+    match max_num_coeff {
+        4 => read_vlc(reader, &CHROMA_DC_TOTAL_ZEROS_LEN[index], &CHROMA_DC_TOTAL_ZEROS_CODE[index]),
+        8 => read_vlc(reader, &CHROMA422_DC_TOTAL_ZEROS_LEN[index], &CHROMA422_DC_TOTAL_ZEROS_CODE[index]),
+        _ => read_vlc(reader, &TOTAL_ZEROS_LEN[index], &TOTAL_ZEROS_CODE[index]),
+    }
+}
+
+fn read_run_before(reader: &mut RbspReader, zeros_left: usize) -> Result<usize, Error> {
+    let index = std::cmp::min(zeros_left, 7) - 1;
+    read_vlc(reader, &RUN_BEFORE_LEN[index], &RUN_BEFORE_CODE[index])
+}
+
+// 7.3.5.3.2 Residual block CAVLC syntax ( Page 82 )
+//
+// 系数写入 coeff_level[ start_idx ..= end_idx ], 返回 TotalCoeff( coeff_token )
+pub fn residual_block_cavlc(reader: &mut RbspReader,
+                            nc: i32,
+                            coeff_level: &mut [i32],
+                            start_idx: usize,
+                            end_idx: usize,
+                            max_num_coeff: usize) -> Result<u8, Error> {
+    for level in coeff_level[..max_num_coeff].iter_mut() {
+        *level = 0;
+    }
+
+    let (trailing_ones, total_coeff) = read_coeff_token(reader, nc)?;
+    if total_coeff == 0 {
+        return Ok(0);
+    }
+
+    if total_coeff > end_idx + 1 - start_idx {
+        return Err(error::malformed("TotalCoeff exceeds the number of coefficients"));
+    }
+
+    let mut level_val = [0i32; 16];
+    let mut suffix_length = if total_coeff > 10 && trailing_ones < 3 { 1u32 } else { 0u32 };
+
+    for i in 0..total_coeff {
+        if i < trailing_ones {
+            let trailing_ones_sign_flag = reader.read_flag()?;
+            level_val[i] = 1 - 2 * trailing_ones_sign_flag as i32;
+            continue;
+        }
+
+        let level_prefix = read_level_prefix(reader)?;
+        let mut level_code = (std::cmp::min(15, level_prefix) as i64) << suffix_length;
+
+        let level_suffix_size = if level_prefix == 14 && suffix_length == 0 {
+            4
+        } else if level_prefix >= 15 {
+            level_prefix - 3
+        } else {
+            suffix_length
+        };
+
+        if (suffix_length > 0 || level_prefix >= 14) && level_suffix_size > 0 {
+            if level_suffix_size > 32 {
+                return Err(error::malformed("level_suffix is too long"));
+            }
+            level_code += reader.read_bits(level_suffix_size)? as i64;
+        }
+
+        if level_prefix >= 15 && suffix_length == 0 {
+            level_code += 15;
+        }
+
+        if level_prefix >= 16 {
+            level_code += (1i64 << (level_prefix - 3)) - 4096;
+        }
+
+        if i == trailing_ones && trailing_ones < 3 {
+            level_code += 2;
+        }
+
+        let value = if level_code % 2 == 0 {
+            (level_code + 2) >> 1
+        } else {
+            (-level_code - 1) >> 1
+        };
+
+        if value < i32::MIN as i64 || value > i32::MAX as i64 {
+            return Err(error::malformed("coefficient level out of range"));
+        }
+        level_val[i] = value as i32;
+
+        if suffix_length == 0 {
+            suffix_length = 1;
+        }
+
+        if value.abs() > (3 << (suffix_length - 1)) && suffix_length < 6 {
+            suffix_length += 1;
+        }
+    }
+
+    let mut zeros_left = 0usize;
+    if total_coeff < end_idx + 1 - start_idx {
+        zeros_left = read_total_zeros(reader, total_coeff, max_num_coeff)?;
+        if zeros_left > end_idx + 1 - start_idx - total_coeff {
+            return Err(error::malformed("total_zeros out of range"));
+        }
+    }
+
+    let mut run_val = [0usize; 16];
+    for i in 0..total_coeff - 1 {
+        if zeros_left > 0 {
+            let run_before = read_run_before(reader, zeros_left)?;
+            if run_before > zeros_left {
+                return Err(error::malformed("run_before out of range"));
+            }
+            run_val[i] = run_before;
+        }
+        zeros_left -= run_val[i];
+    }
+    run_val[total_coeff - 1] = zeros_left;
+
+    let mut coeff_num = 0usize;
+    for i in (0..total_coeff).rev() {
+        coeff_num += run_val[i] + 1;
+        coeff_level[start_idx + coeff_num - 1] = level_val[i];
+    }
+
+    Ok(total_coeff as u8)
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::rbsp::RbspReader;
+    use super::*;
+
+    // 码表应为前缀码
+    fn assert_prefix_free(lens: &[u8], codes: &[u8]) {
+        let entries = lens.iter().zip(codes.iter())
+            .filter(|&(&len, _)| len > 0)
+            .map(|(&len, &code)| (len as u32, code as u32))
+            .collect::<Vec<_>>();
+
+        for (i, &(len_a, code_a)) in entries.iter().enumerate() {
+            assert!(code_a < (1 << len_a));
+            for &(len_b, code_b) in entries.iter().skip(i + 1) {
+                let len = std::cmp::min(len_a, len_b);
+                assert_ne!(code_a >> (len_a - len), code_b >> (len_b - len));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tables_are_prefix_free() {
+        for table in 0..4 {
+            assert_prefix_free(&COEFF_TOKEN_LEN[table], &COEFF_TOKEN_CODE[table]);
+        }
+        assert_prefix_free(&CHROMA_DC_COEFF_TOKEN_LEN, &CHROMA_DC_COEFF_TOKEN_CODE);
+        assert_prefix_free(&CHROMA422_DC_COEFF_TOKEN_LEN, &CHROMA422_DC_COEFF_TOKEN_CODE);
+
+        for index in 0..15 {
+            assert_prefix_free(&TOTAL_ZEROS_LEN[index], &TOTAL_ZEROS_CODE[index]);
+        }
+        for index in 0..3 {
+            assert_prefix_free(&CHROMA_DC_TOTAL_ZEROS_LEN[index], &CHROMA_DC_TOTAL_ZEROS_CODE[index]);
+        }
+        for index in 0..7 {
+            assert_prefix_free(&CHROMA422_DC_TOTAL_ZEROS_LEN[index], &CHROMA422_DC_TOTAL_ZEROS_CODE[index]);
+            assert_prefix_free(&RUN_BEFORE_LEN[index], &RUN_BEFORE_CODE[index]);
+        }
+    }
+
+    #[test]
+    fn test_residual_block_cavlc() {
+        // 0000100 011 1 0010 111 10 1 1 01
+        let data = [0b0000_1000, 0b1110_0101, 0b1110_1101];
+        let mut reader = RbspReader::new(&data);
+        let mut coeff_level = [0i32; 16];
+
+        let total_coeff = residual_block_cavlc(&mut reader, 0, &mut coeff_level, 0, 15, 16).unwrap();
+
+        assert_eq!(total_coeff, 5);
+        assert_eq!(coeff_level, [0, 3, 0, 1, -1, -1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(reader.position(), 24);
+    }
+
+    #[test]
+    fn test_chroma_dc_coeff_token() {
+        // TrailingOnes = 1, TotalCoeff = 1: "1"
+        let data = [0b1000_0000];
+        let mut reader = RbspReader::new(&data);
+        assert_eq!(read_coeff_token(&mut reader, -1).unwrap(), (1, 1));
+        assert_eq!(reader.position(), 1);
+    }
+}
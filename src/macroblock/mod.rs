@@ -0,0 +1,509 @@
+// 7.3.5 Macroblock layer syntax ( Page 77 )
+// 7.4.5 Macroblock layer semantics ( Page 131 )
+
+use crate::error::{ self, Error };
+use crate::rbsp::SliceType;
+
+
+mod neighbour;
+mod slice_data;
+mod slice_group;
+mod cabac_tables;
+pub mod cavlc;
+pub mod cabac;
+
+pub use self::slice_data::SliceData;
+pub use self::slice_group::{ SliceGroupMap, map_unit_to_slice_group_map };
+pub(crate) use self::neighbour::{ MbNeighbour, neighbour_location, neighbour_location_mbaff, luma4x4_blk_idx, chroma4x4_blk_idx, luma4x4_blk_position };
+
+
+// MbPartPredMode, SubMbPredMode ( 帧间部分 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredMode {
+    L0,
+    L1,
+    BiPred,
+    Direct,
+}
+
+impl PredMode {
+    // predFlagL0
+    pub fn uses_l0(&self) -> bool {
+        *self == PredMode::L0 || *self == PredMode::BiPred
+    }
+
+    // predFlagL1
+    pub fn uses_l1(&self) -> bool {
+        *self == PredMode::L1 || *self == PredMode::BiPred
+    }
+
+    // Table 7-14 中 mb_type 名称使用的写法
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PredMode::L0 => "L0",
+            PredMode::L1 => "L1",
+            PredMode::BiPred => "Bi",
+            PredMode::Direct => "Direct",
+        }
+    }
+}
+
+// 帧内预测模式 ( MbPartPredMode 的帧内部分 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntraPredMode {
+    Intra4x4,
+    Intra8x8,
+    Intra16x16,
+}
+
+
+// Table 7-11 – Macroblock types for I slices ( Page 134 )
+// Table 7-12 – Macroblock type with value 0 for SI slices
+// Table 7-13 – Macroblock type values 0 to 4 for P and SP slices
+// Table 7-14 – Macroblock type values 0 to 22 for B slices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MbType {
+    // I_NxN
+    INxN,
+    // I_16x16_<Intra16x16PredMode>_<CodedBlockPatternChroma>_<CodedBlockPatternLuma>
+    I16x16 {
+        intra_16x16_pred_mode: u8,
+        coded_block_pattern_chroma: u8,
+        coded_block_pattern_luma: u8,
+    },
+    // I_PCM
+    IPcm,
+    // SI
+    Si,
+    // P_L0_16x16
+    P16x16,
+    // P_L0_L0_16x8
+    P16x8,
+    // P_L0_L0_8x16
+    P8x16,
+    // P_8x8
+    P8x8,
+    // P_8x8ref0
+    P8x8Ref0,
+    // P_Skip
+    PSkip,
+    // B_Direct_16x16
+    BDirect16x16,
+    // B_X_16x16
+    B16x16(PredMode),
+    // B_X_Y_16x8
+    B16x8(PredMode, PredMode),
+    // B_X_Y_8x16
+    B8x16(PredMode, PredMode),
+    // B_8x8
+    B8x8,
+    // B_Skip
+    BSkip,
+}
+
+impl MbType {
+    fn from_i(value: u32) -> Result<Self, Error> {
+        match value {
+            0 => Ok(MbType::INxN),
+            1 ..= 24 => Ok(MbType::I16x16 {
+                intra_16x16_pred_mode: ((value - 1) % 4) as u8,
+                coded_block_pattern_chroma: (((value - 1) / 4) % 3) as u8,
+                coded_block_pattern_luma: if value >= 13 { 15 } else { 0 },
+            }),
+            25 => Ok(MbType::IPcm),
+            _ => Err(error::malformed("mb_type out of range")),
+        }
+    }
+
+    fn from_b(value: u32) -> Result<Self, Error> {
+        use self::PredMode::*;
+
+        // Table 7-14: 4 .. 21 的两个分区预测方向
+        const PARTITION_PRED_MODES: [(PredMode, PredMode); 9] = [
+            (L0, L0), (L1, L1), (L0, L1), (L1, L0), (L0, BiPred),
+            (L1, BiPred), (BiPred, L0), (BiPred, L1), (BiPred, BiPred),
+        ];
+
+        match value {
+            0 => Ok(MbType::BDirect16x16),
+            1 => Ok(MbType::B16x16(L0)),
+            2 => Ok(MbType::B16x16(L1)),
+            3 => Ok(MbType::B16x16(BiPred)),
+            4 ..= 21 => {
+                let (first, second) = PARTITION_PRED_MODES[(value as usize - 4) / 2];
+                if value.is_multiple_of(2) {
+                    Ok(MbType::B16x8(first, second))
+                } else {
+                    Ok(MbType::B8x16(first, second))
+                }
+            },
+            22 => Ok(MbType::B8x8),
+            _ => MbType::from_i(value - 23),
+        }
+    }
+
+    // 7.4.5: mb_type 的语义取决于 slice_type
+    pub fn from_code(slice_type: SliceType, value: u32) -> Result<Self, Error> {
+        match slice_type {
+            SliceType::I => MbType::from_i(value),
+            SliceType::SI => match value {
+                0 => Ok(MbType::Si),
+                _ => MbType::from_i(value - 1),
+            },
+            SliceType::P | SliceType::SP => match value {
+                0 => Ok(MbType::P16x16),
+                1 => Ok(MbType::P16x8),
+                2 => Ok(MbType::P8x16),
+                3 => Ok(MbType::P8x8),
+                4 => Ok(MbType::P8x8Ref0),
+                _ => MbType::from_i(value - 5),
+            },
+            SliceType::B => MbType::from_b(value),
+        }
+    }
+
+    pub fn is_intra(&self) -> bool {
+        match *self {
+            MbType::INxN | MbType::I16x16 { .. } | MbType::IPcm | MbType::Si => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_inter(&self) -> bool {
+        !self.is_intra()
+    }
+
+    pub fn is_skip(&self) -> bool {
+        *self == MbType::PSkip || *self == MbType::BSkip
+    }
+
+    pub fn is_intra_16x16(&self) -> bool {
+        match *self {
+            MbType::I16x16 { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Table 7-11, 7-13, 7-14 中的名称, 例如 P_L0_16x16, B_L0_Bi_16x8
+    pub fn name(&self) -> String {
+        match *self {
+            MbType::INxN => "I_NxN".to_string(),
+            MbType::I16x16 { intra_16x16_pred_mode, coded_block_pattern_chroma, coded_block_pattern_luma } => {
+                format!("I_16x16_{}_{}_{}", intra_16x16_pred_mode, coded_block_pattern_chroma,
+                        if coded_block_pattern_luma == 0 { 0 } else { 1 })
+            },
+            MbType::IPcm => "I_PCM".to_string(),
+            MbType::Si => "SI".to_string(),
+            MbType::P16x16 => "P_L0_16x16".to_string(),
+            MbType::P16x8 => "P_L0_L0_16x8".to_string(),
+            MbType::P8x16 => "P_L0_L0_8x16".to_string(),
+            MbType::P8x8 => "P_8x8".to_string(),
+            MbType::P8x8Ref0 => "P_8x8ref0".to_string(),
+            MbType::PSkip => "P_Skip".to_string(),
+            MbType::BDirect16x16 => "B_Direct_16x16".to_string(),
+            MbType::B16x16(mode) => format!("B_{}_16x16", mode.name()),
+            MbType::B16x8(first, second) => format!("B_{}_{}_16x8", first.name(), second.name()),
+            MbType::B8x16(first, second) => format!("B_{}_{}_8x16", first.name(), second.name()),
+            MbType::B8x8 => "B_8x8".to_string(),
+            MbType::BSkip => "B_Skip".to_string(),
+        }
+    }
+
+    // NumMbPart( mb_type )
+    pub fn num_mb_part(&self) -> usize {
+        match *self {
+            MbType::P16x16 | MbType::PSkip | MbType::B16x16(_) => 1,
+            MbType::P16x8 | MbType::P8x16 | MbType::B16x8(_, _) | MbType::B8x16(_, _) => 2,
+            MbType::P8x8 | MbType::P8x8Ref0 | MbType::B8x8 | MbType::BDirect16x16 | MbType::BSkip => 4,
+            _ => 1,
+        }
+    }
+
+    // ( MbPartWidth, MbPartHeight )
+    pub fn mb_part_size(&self) -> (u32, u32) {
+        match *self {
+            MbType::P16x8 | MbType::B16x8(_, _) => (16, 8),
+            MbType::P8x16 | MbType::B8x16(_, _) => (8, 16),
+            MbType::P8x8 | MbType::P8x8Ref0 | MbType::B8x8 | MbType::BDirect16x16 | MbType::BSkip => (8, 8),
+            _ => (16, 16),
+        }
+    }
+
+    // MbPartPredMode( mb_type, mbPartIdx ), 仅限帧间宏块
+    pub fn mb_part_pred_mode(&self, mb_part_idx: usize) -> Option<PredMode> {
+        match *self {
+            MbType::P16x16 | MbType::P16x8 | MbType::P8x16 | MbType::PSkip => Some(PredMode::L0),
+            MbType::BDirect16x16 | MbType::BSkip => Some(PredMode::Direct),
+            MbType::B16x16(mode) => Some(mode),
+            MbType::B16x8(first, second) | MbType::B8x16(first, second) => {
+                if mb_part_idx == 0 { Some(first) } else { Some(second) }
+            },
+            _ => None,
+        }
+    }
+}
+
+
+// Table 7-17 – Sub-macroblock types in P macroblocks ( Page 141 )
+// Table 7-18 – Sub-macroblock types in B macroblocks ( Page 142 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubMbType {
+    // P_L0_8x8
+    P8x8,
+    // P_L0_8x4
+    P8x4,
+    // P_L0_4x8
+    P4x8,
+    // P_L0_4x4
+    P4x4,
+    // B_Direct_8x8
+    BDirect8x8,
+    // B_X_8x8
+    B8x8(PredMode),
+    // B_X_8x4
+    B8x4(PredMode),
+    // B_X_4x8
+    B4x8(PredMode),
+    // B_X_4x4
+    B4x4(PredMode),
+}
+
+impl SubMbType {
+    pub fn from_code(slice_type: SliceType, value: u32) -> Result<Self, Error> {
+        use self::PredMode::*;
+
+        if slice_type.is_bipredictive() {
+            match value {
+                0 => Ok(SubMbType::BDirect8x8),
+                1 => Ok(SubMbType::B8x8(L0)),
+                2 => Ok(SubMbType::B8x8(L1)),
+                3 => Ok(SubMbType::B8x8(BiPred)),
+                4 => Ok(SubMbType::B8x4(L0)),
+                5 => Ok(SubMbType::B4x8(L0)),
+                6 => Ok(SubMbType::B8x4(L1)),
+                7 => Ok(SubMbType::B4x8(L1)),
+                8 => Ok(SubMbType::B8x4(BiPred)),
+                9 => Ok(SubMbType::B4x8(BiPred)),
+                10 => Ok(SubMbType::B4x4(L0)),
+                11 => Ok(SubMbType::B4x4(L1)),
+                12 => Ok(SubMbType::B4x4(BiPred)),
+                _ => Err(error::malformed("sub_mb_type out of range")),
+            }
+        } else {
+            match value {
+                0 => Ok(SubMbType::P8x8),
+                1 => Ok(SubMbType::P8x4),
+                2 => Ok(SubMbType::P4x8),
+                3 => Ok(SubMbType::P4x4),
+                _ => Err(error::malformed("sub_mb_type out of range")),
+            }
+        }
+    }
+
+    // NumSubMbPart( sub_mb_type )
+    pub fn num_sub_mb_part(&self) -> usize {
+        match *self {
+            SubMbType::P8x8 | SubMbType::B8x8(_) => 1,
+            SubMbType::P8x4 | SubMbType::P4x8 | SubMbType::B8x4(_) | SubMbType::B4x8(_) => 2,
+            SubMbType::P4x4 | SubMbType::B4x4(_) | SubMbType::BDirect8x8 => 4,
+        }
+    }
+
+    // ( SubMbPartWidth, SubMbPartHeight )
+    pub fn sub_mb_part_size(&self) -> (u32, u32) {
+        match *self {
+            SubMbType::P8x8 | SubMbType::B8x8(_) => (8, 8),
+            SubMbType::P8x4 | SubMbType::B8x4(_) => (8, 4),
+            SubMbType::P4x8 | SubMbType::B4x8(_) => (4, 8),
+            SubMbType::P4x4 | SubMbType::B4x4(_) | SubMbType::BDirect8x8 => (4, 4),
+        }
+    }
+
+    // SubMbPredMode( sub_mb_type )
+    pub fn sub_mb_pred_mode(&self) -> PredMode {
+        match *self {
+            SubMbType::P8x8 | SubMbType::P8x4 | SubMbType::P4x8 | SubMbType::P4x4 => PredMode::L0,
+            SubMbType::BDirect8x8 => PredMode::Direct,
+            SubMbType::B8x8(mode) | SubMbType::B8x4(mode) | SubMbType::B4x8(mode) | SubMbType::B4x4(mode) => mode,
+        }
+    }
+}
+
+
+// I_PCM 宏块的样本值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcmSamples {
+    // pcm_sample_luma[ 256 ]
+    pub luma: Vec<u16>,
+    // pcm_sample_chroma[ 2 * MbWidthC * MbHeightC ], 先 Cb 后 Cr
+    pub chroma: Vec<u16>,
+}
+
+
+// 一个颜色分量 ( 亮度, 或 ChromaArrayType 为 3 时的 Cb/Cr ) 的变换系数，均按扫描顺序排列。
+#[derive(Clone, PartialEq, Eq)]
+pub struct ResidualBlocks {
+    // Intra16x16DCLevel
+    pub dc: [i32; 16],
+    // level4x4; Intra_16x16 宏块的 Intra16x16ACLevel 存放在下标 1 ..= 15
+    pub level4x4: [[i32; 16]; 16],
+    // level8x8
+    pub level8x8: [[i32; 64]; 4],
+}
+
+impl Default for ResidualBlocks {
+    fn default() -> Self {
+        Self {
+            dc: [0; 16],
+            level4x4: [[0; 16]; 16],
+            level8x8: [[0; 64]; 4],
+        }
+    }
+}
+
+impl std::fmt::Debug for ResidualBlocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let non_zero = self.dc.iter()
+            .chain(self.level4x4.iter().flat_map(|block| block.iter()))
+            .chain(self.level8x8.iter().flat_map(|block| block.iter()))
+            .filter(|&&level| level != 0)
+            .count();
+        write!(f, "ResidualBlocks {{ non_zero: {} }}", non_zero)
+    }
+}
+
+// 7.3.5.3 Residual data syntax ( Page 80 )
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Residual {
+    pub luma: ResidualBlocks,
+    // ChromaArrayType 为 3 时按亮度方式编码的 Cb, Cr
+    pub cb: Option<ResidualBlocks>,
+    pub cr: Option<ResidualBlocks>,
+    // ChromaArrayType 为 1 或 2 时: ChromaDCLevel[ iCbCr ] ( 4 或 8 项 )
+    pub chroma_dc: [[i32; 8]; 2],
+    // ChromaACLevel[ iCbCr ][ i8x8 * 4 + i4x4 ], 存放在下标 1 ..= 15
+    pub chroma_ac: [[[i32; 16]; 8]; 2],
+}
+
+
+// 宏块占用的比特数: 宏块头部 ( mb_type, 预测模式, coded_block_pattern, mb_qp_delta 等 ),
+// 运动信息 ( ref_idx, mvd ) 与残差 ( 包括 PCM 样本 )
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MbBits {
+    pub header: u32,
+    pub motion: u32,
+    pub residual: u32,
+}
+
+impl MbBits {
+    pub fn total(&self) -> u32 {
+        self.header + self.motion + self.residual
+    }
+}
+
+
+// 7.4.5: QPY = ( ( QPY,PRED + mb_qp_delta + 52 + 2 * QpBdOffsetY ) % ( 52 + QpBdOffsetY ) ) - QpBdOffsetY
+pub fn qp_y(qp_y_pred: i32, mb_qp_delta: i32, qp_bd_offset_y: i32) -> i32 {
+    ((qp_y_pred + mb_qp_delta + 52 + 2 * qp_bd_offset_y) % (52 + qp_bd_offset_y)) - qp_bd_offset_y
+}
+
+
+// 7.3.5 Macroblock layer syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macroblock {
+    // CurrMbAddr
+    pub mb_addr: u32,
+    pub mb_type: MbType,
+    pub mb_field_decoding_flag: bool,
+    pub transform_size_8x8_flag: bool,
+    pub coded_block_pattern_luma: u8,
+    pub coded_block_pattern_chroma: u8,
+    pub mb_qp_delta: i32,
+
+    // prev_intra4x4_pred_mode_flag / prev_intra8x8_pred_mode_flag
+    pub prev_intra_pred_mode_flag: [bool; 16],
+    // rem_intra4x4_pred_mode / rem_intra8x8_pred_mode
+    pub rem_intra_pred_mode: [u8; 16],
+    pub intra_chroma_pred_mode: u8,
+
+    pub sub_mb_type: Option<[SubMbType; 4]>,
+    // refIdxLX[ mbPartIdx ], 未使用该列表时为 -1
+    pub ref_idx: [[i8; 4]; 2],
+    // mvd_lX[ mbPartIdx ][ subMbPartIdx ][ compIdx ]
+    pub mvd: [[[[i32; 2]; 4]; 4]; 2],
+
+    pub pcm_samples: Option<PcmSamples>,
+    pub residual: Option<Box<Residual>>,
+    // 每个 4x4 块的 TotalCoeff( coeff_token ) ( Y, Cb, Cr ), 用于推导 nC;
+    // CABAC 时为非零系数的个数, 8x8 块的个数记录在它包含的 4 个 4x4 块上
+    pub total_coeff: [[u8; 16]; 3],
+    // DC 块 ( Intra16x16DCLevel 或 ChromaDCLevel ) 的 coded_block_flag ( Y, Cb, Cr )
+    pub coded_block_flag_dc: [bool; 3],
+    pub bits: MbBits,
+}
+
+impl Macroblock {
+    pub fn new(mb_addr: u32, mb_type: MbType) -> Self {
+        let (coded_block_pattern_luma, coded_block_pattern_chroma) = match mb_type {
+            MbType::I16x16 { coded_block_pattern_luma, coded_block_pattern_chroma, .. } => {
+                (coded_block_pattern_luma, coded_block_pattern_chroma)
+            },
+            _ => (0, 0),
+        };
+
+        Self {
+            mb_addr: mb_addr,
+            mb_type: mb_type,
+            mb_field_decoding_flag: false,
+            transform_size_8x8_flag: false,
+            coded_block_pattern_luma: coded_block_pattern_luma,
+            coded_block_pattern_chroma: coded_block_pattern_chroma,
+            mb_qp_delta: 0,
+            prev_intra_pred_mode_flag: [false; 16],
+            rem_intra_pred_mode: [0; 16],
+            intra_chroma_pred_mode: 0,
+            sub_mb_type: None,
+            ref_idx: [[-1; 4]; 2],
+            mvd: [[[[0; 2]; 4]; 4]; 2],
+            pcm_samples: None,
+            residual: None,
+            total_coeff: [[0; 16]; 3],
+            coded_block_flag_dc: [false; 3],
+            bits: MbBits::default(),
+        }
+    }
+
+    // MbPartPredMode( mb_type, 0 ) 的帧内部分
+    pub fn intra_pred_mode(&self) -> Option<IntraPredMode> {
+        match self.mb_type {
+            MbType::INxN if self.transform_size_8x8_flag => Some(IntraPredMode::Intra8x8),
+            MbType::INxN | MbType::Si => Some(IntraPredMode::Intra4x4),
+            MbType::I16x16 { .. } => Some(IntraPredMode::Intra16x16),
+            _ => None,
+        }
+    }
+
+    // 6.4.13.4 Derivation process for macroblock and sub-macroblock partition indices
+    //
+    // 亮度位置 ( x, y ) 所在的 ( mbPartIdx, subMbPartIdx )
+    pub fn partition_idx(&self, x: i32, y: i32) -> (usize, usize) {
+        if self.mb_type.is_intra() {
+            return (0, 0);
+        }
+
+        let (width, height) = self.mb_type.mb_part_size();
+        let (width, height) = (width as i32, height as i32);
+        let mb_part_idx = ((16 / width) * (y / height) + x / width) as usize;
+
+        let sub_mb_part_idx = match self.sub_mb_type {
+            Some(sub_mb_types) => {
+                let (width, height) = sub_mb_types[mb_part_idx].sub_mb_part_size();
+                let (width, height) = (width as i32, height as i32);
+                ((8 / width) * ((y % 8) / height) + (x % 8) / width) as usize
+            },
+            None => 0,
+        };
+
+        (mb_part_idx, sub_mb_part_idx)
+    }
+}
@@ -0,0 +1,218 @@
+// 6.4.12 Derivation process for neighbouring locations ( Page 47 )
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MbNeighbour {
+    A,
+    B,
+    C,
+    D,
+    Curr,
+}
+
+// 6.4.12.1 Specification for neighbouring locations in fields and non-MBAFF frames
+//
+// 返回 ( mbAddrN, xW, yW ), mbAddrN 是否可用 ( 是否属于同一个 slice ) 由调用者判断。
+pub fn neighbour_location(curr_mb_addr: u32,
+                          pic_width_in_mbs: u32,
+                          xn: i32,
+                          yn: i32,
+                          max_w: i32,
+                          max_h: i32) -> Option<(MbNeighbour, u32, i32, i32)> {
+    let curr = curr_mb_addr as i64;
+    let width = pic_width_in_mbs as i64;
+    let at_left_edge = curr % width == 0;
+    let at_right_edge = (curr + 1) % width == 0;
+
+    let (neighbour, addr) = if xn < 0 && yn < 0 {
+        (MbNeighbour::D, if at_left_edge { -1 } else { curr - width - 1 })
+    } else if xn < 0 && yn < max_h {
+        (MbNeighbour::A, if at_left_edge { -1 } else { curr - 1 })
+    } else if xn < max_w && yn < 0 {
+        (MbNeighbour::B, curr - width)
+    } else if xn < max_w && yn < max_h {
+        (MbNeighbour::Curr, curr)
+    } else if yn < 0 {
+        (MbNeighbour::C, if at_right_edge { -1 } else { curr - width + 1 })
+    } else {
+        return None;
+    };
+
+    if addr < 0 {
+        return None;
+    }
+
+    let xw = (xn + max_w) % max_w;
+    let yw = (yn + max_h) % max_h;
+
+    Some((neighbour, addr as u32, xw, yw))
+}
+
+// 6.4.12.2 Specification for neighbouring locations in MBAFF frames ( Table 6-4 )
+//
+// `pair_field( mbAddrX )` 返回以顶部宏块地址 mbAddrX 表示的相邻宏块对是否为场宏块对, 该宏块对不可用时为 None。
+// 返回 ( mbAddrN, xW, yW ), mbAddrN 所在的宏块对 ( 当前宏块对除外 ) 已确认可用。
+#[allow(clippy::too_many_arguments)]
+pub fn neighbour_location_mbaff(curr_mb_addr: u32,
+                                pic_width_in_mbs: u32,
+                                curr_mb_frame_flag: bool,
+                                xn: i32,
+                                yn: i32,
+                                max_w: i32,
+                                max_h: i32,
+                                pair_field: &dyn Fn(u32) -> Option<bool>) -> Option<(MbNeighbour, u32, i32, i32)> {
+    let width = pic_width_in_mbs as i64;
+    let pair = (curr_mb_addr / 2) as i64;
+    let mb_is_top_mb_flag = curr_mb_addr.is_multiple_of(2);
+    let at_left_edge = pair % width == 0;
+    let at_right_edge = (pair + 1) % width == 0;
+
+    // 6.4.10: mbAddrA, mbAddrB, mbAddrC, mbAddrD 为相邻宏块对的顶部宏块, 同时返回 mbAddrXFrameFlag
+    let mb_addr_x = |neighbour: MbNeighbour| -> Option<(u32, bool)> {
+        let addr = match neighbour {
+            MbNeighbour::A if !at_left_edge => 2 * (pair - 1),
+            MbNeighbour::B => 2 * (pair - width),
+            MbNeighbour::C if !at_right_edge => 2 * (pair - width + 1),
+            MbNeighbour::D if !at_left_edge => 2 * (pair - width - 1),
+            _ => return None,
+        };
+        if addr < 0 {
+            return None;
+        }
+        Some((addr as u32, !pair_field(addr as u32)?))
+    };
+
+    if yn > max_h - 1 || (xn > max_w - 1 && yn >= 0) {
+        return None;
+    }
+
+    let (neighbour, addr, ym) = if xn >= 0 && yn >= 0 {
+        (MbNeighbour::Curr, curr_mb_addr, yn)
+    } else if yn >= 0 {
+        // xN < 0, 0 <= yN <= maxH - 1
+        let (a, a_frame) = mb_addr_x(MbNeighbour::A)?;
+        let (addr, ym) = match (curr_mb_frame_flag, mb_is_top_mb_flag, a_frame) {
+            (true, true, true) => (a, yn),
+            (true, true, false) => (a + (yn % 2) as u32, yn >> 1),
+            (true, false, true) => (a + 1, yn),
+            (true, false, false) => (a + (yn % 2) as u32, (yn + max_h) >> 1),
+            (false, true, true) if yn < max_h / 2 => (a, yn << 1),
+            (false, true, true) => (a + 1, (yn << 1) - max_h),
+            (false, false, true) if yn < max_h / 2 => (a, (yn << 1) + 1),
+            (false, false, true) => (a + 1, (yn << 1) + 1 - max_h),
+            (false, true, false) => (a, yn),
+            (false, false, false) => (a + 1, yn),
+        };
+        (MbNeighbour::A, addr, ym)
+    } else {
+        // yN < 0: D ( xN < 0 ), B ( 0 <= xN <= maxW - 1 ), C ( xN > maxW - 1 )
+        let neighbour = if xn < 0 {
+            MbNeighbour::D
+        } else if xn < max_w {
+            MbNeighbour::B
+        } else {
+            MbNeighbour::C
+        };
+
+        let (addr, ym) = match (curr_mb_frame_flag, mb_is_top_mb_flag) {
+            (true, true) | (false, false) => (mb_addr_x(neighbour)?.0 + 1, yn),
+            (true, false) => match neighbour {
+                // 底部帧宏块的 D 在左边宏块对中
+                MbNeighbour::D => {
+                    let (a, a_frame) = mb_addr_x(MbNeighbour::A)?;
+                    (a, if a_frame { yn } else { (yn + max_h) >> 1 })
+                },
+                MbNeighbour::B => (curr_mb_addr - 1, yn),
+                _ => return None,
+            },
+            (false, true) => {
+                let (x, x_frame) = mb_addr_x(neighbour)?;
+                if x_frame { (x + 1, 2 * yn) } else { (x, yn) }
+            },
+        };
+        (neighbour, addr, ym)
+    };
+
+    let xw = (xn + max_w) % max_w;
+    let yw = (ym + max_h) % max_h;
+
+    Some((neighbour, addr, xw, yw))
+}
+
+// 6.4.3 Inverse 4x4 luma block scanning process: luma4x4BlkIdx -> ( x, y )
+pub fn luma4x4_blk_position(luma4x4_blk_idx: usize) -> (i32, i32) {
+    let idx = luma4x4_blk_idx as i32;
+    let x = (idx / 4 % 2) * 8 + (idx % 4 % 2) * 4;
+    let y = (idx / 4 / 2) * 8 + (idx % 4 / 2) * 4;
+    (x, y)
+}
+
+// 6.4.13.1 Derivation process for 4x4 luma block indices
+pub fn luma4x4_blk_idx(x: i32, y: i32) -> usize {
+    (8 * (y / 8) + 4 * (x / 8) + 2 * ((y % 8) / 4) + ((x % 8) / 4)) as usize
+}
+
+// 6.4.13.2 Derivation process for 4x4 chroma block indices
+pub fn chroma4x4_blk_idx(x: i32, y: i32) -> usize {
+    (2 * (y / 4) + (x / 4)) as usize
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ neighbour_location, neighbour_location_mbaff, luma4x4_blk_idx, luma4x4_blk_position, MbNeighbour };
+
+    #[test]
+    fn test_luma4x4_blk_idx() {
+        for idx in 0..16 {
+            let (x, y) = luma4x4_blk_position(idx);
+            assert_eq!(luma4x4_blk_idx(x, y), idx);
+        }
+        assert_eq!(luma4x4_blk_position(3), (4, 4));
+        assert_eq!(luma4x4_blk_position(4), (8, 0));
+        assert_eq!(luma4x4_blk_position(10), (0, 12));
+    }
+
+    #[test]
+    fn test_neighbour_location() {
+        // 宽度为 3 个宏块, 当前宏块为第二行第一个
+        assert_eq!(neighbour_location(3, 3, -1, 0, 16, 16), None);
+        assert_eq!(neighbour_location(3, 3, 0, -1, 16, 16), Some((MbNeighbour::B, 0, 0, 15)));
+        assert_eq!(neighbour_location(3, 3, 16, -1, 16, 16), Some((MbNeighbour::C, 1, 0, 15)));
+        assert_eq!(neighbour_location(4, 3, -1, 4, 16, 16), Some((MbNeighbour::A, 3, 15, 4)));
+        assert_eq!(neighbour_location(4, 3, -1, -1, 16, 16), Some((MbNeighbour::D, 0, 15, 15)));
+        assert_eq!(neighbour_location(5, 3, 16, -1, 16, 16), None);
+        assert_eq!(neighbour_location(4, 3, 16, 0, 16, 16), None);
+    }
+
+    #[test]
+    fn test_neighbour_location_mbaff() {
+        // 宽度为 2 个宏块对: 宏块对 0 ( 宏块 0, 1 ) 为帧宏块对, 宏块对 1 ( 宏块 2, 3 ) 为场宏块对,
+        // 当前为第二行的宏块对 2 ( 宏块 4, 5 ) 与宏块对 3 ( 宏块 6, 7 )
+        let pair_field = |addr: u32| -> Option<bool> {
+            match addr {
+                0 => Some(false),
+                2 | 4 => Some(true),
+                _ => None,
+            }
+        };
+
+        // 帧宏块: 上边为上方宏块对的底部宏块, 左边场宏块对按行的奇偶交替
+        assert_eq!(neighbour_location_mbaff(6, 2, true, 0, -1, 16, 16, &pair_field), Some((MbNeighbour::B, 3, 0, 15)));
+        assert_eq!(neighbour_location_mbaff(6, 2, true, -1, 3, 16, 16, &pair_field), Some((MbNeighbour::A, 5, 15, 1)));
+        assert_eq!(neighbour_location_mbaff(7, 2, true, -1, 2, 16, 16, &pair_field), Some((MbNeighbour::A, 4, 15, 9)));
+        assert_eq!(neighbour_location_mbaff(7, 2, true, 0, -1, 16, 16, &pair_field), Some((MbNeighbour::B, 6, 0, 15)));
+        assert_eq!(neighbour_location_mbaff(7, 2, true, 16, -1, 16, 16, &pair_field), None);
+
+        // 场宏块: 上方为帧宏块对时取底部宏块的倒数第二行
+        assert_eq!(neighbour_location_mbaff(4, 2, false, 0, -1, 16, 16, &pair_field), Some((MbNeighbour::B, 1, 0, 14)));
+        assert_eq!(neighbour_location_mbaff(6, 2, false, 0, -1, 16, 16, &pair_field), Some((MbNeighbour::B, 2, 0, 15)));
+        assert_eq!(neighbour_location_mbaff(7, 2, false, 0, -1, 16, 16, &pair_field), Some((MbNeighbour::B, 3, 0, 15)));
+        assert_eq!(neighbour_location_mbaff(7, 2, false, -1, 0, 16, 16, &pair_field), Some((MbNeighbour::A, 5, 15, 0)));
+        assert_eq!(neighbour_location_mbaff(6, 2, false, -1, 8, 16, 16, &pair_field), Some((MbNeighbour::A, 4, 15, 8)));
+
+        // 左边宏块对不可用
+        assert_eq!(neighbour_location_mbaff(4, 2, true, -1, 0, 16, 16, &pair_field), None);
+        assert_eq!(neighbour_location_mbaff(5, 2, true, 3, 4, 16, 16, &pair_field), Some((MbNeighbour::Curr, 5, 3, 4)));
+    }
+}
@@ -0,0 +1,1355 @@
+use crate::error::{ self, Error };
+use crate::golomb;
+use crate::nalu::NaluKind;
+use crate::rbsp::{ RbspReader, SliceHeader, SliceType, SequenceParameterSet, PictureParameterSet };
+use super::cavlc;
+use super::cabac::{ self, CabacDecoder };
+use super::{
+    Macroblock, MbType, SubMbType, PredMode, IntraPredMode, PcmSamples, Residual, ResidualBlocks,
+    MbNeighbour, MbBits, SliceGroupMap, neighbour_location, neighbour_location_mbaff, luma4x4_blk_idx, chroma4x4_blk_idx, luma4x4_blk_position,
+};
+
+
+// 7.3.4 Slice data syntax ( Page 76 )
+#[derive(Debug, Clone)]
+pub struct SliceData {
+    // 按解码顺序排列, 包含跳过的宏块 ( P_Skip, B_Skip )
+    pub macroblocks: Vec<Macroblock>,
+}
+
+impl SliceData {
+    pub fn parse(reader: &mut RbspReader,
+                 header: &SliceHeader,
+                 sps: &SequenceParameterSet,
+                 pps: &PictureParameterSet) -> Result<Self, Error> {
+        let mut parser = MacroblockParser::new(reader, header, sps, pps)?;
+        parser.slice_data()?;
+
+        Ok(SliceData {
+            macroblocks: parser.macroblocks,
+        })
+    }
+
+    // 7.3.2.9 Slice data partition RBSP syntax ( Page 70 )
+    //
+    // reader 为 partition A ( 类别 2 ), intra 与 inter 分别为 partition B ( 类别 3 ) 与 C ( 类别 4 )。
+    // 返回的 [bool; 2] 表示需要但没有提供的 partition B, C: 其中的残差按全零处理, PCM 样本取中间值。
+    // 缺少 B 时, C 中的 nC 依赖帧内宏块的非零系数个数, 因此之后的解析结果不可靠。
+    pub fn parse_partitioned<'a>(reader: &mut RbspReader<'a>,
+                                 intra: Option<&mut RbspReader<'a>>,
+                                 inter: Option<&mut RbspReader<'a>>,
+                                 header: &SliceHeader,
+                                 sps: &SequenceParameterSet,
+                                 pps: &PictureParameterSet) -> Result<(Self, [bool; 2]), Error> {
+        if pps.entropy_coding_mode_flag() {
+            return Err(error::unsupported("slice data partitioning with CABAC is not supported"));
+        }
+
+        let mut parser = MacroblockParser::new(reader, header, sps, pps)?;
+        parser.partitioned = true;
+        parser.partitions = [intra, inter];
+        parser.slice_data()?;
+
+        let missing_partitions = parser.missing_partitions;
+        Ok((SliceData { macroblocks: parser.macroblocks }, missing_partitions))
+    }
+}
+
+
+struct MacroblockParser<'a, 'b> {
+    reader: &'b mut RbspReader<'a>,
+    header: &'b SliceHeader,
+    sps: &'b SequenceParameterSet,
+    pps: &'b PictureParameterSet,
+    chroma_array_type: u32,
+    pic_size_in_mbs: u32,
+    slice_group_map: SliceGroupMap,
+    // MbaffFrameFlag 以及当前宏块对的 mb_field_decoding_flag ( 非 MBAFF 时等于 field_pic_flag )
+    mbaff: bool,
+    mb_field_decoding_flag: bool,
+    // CurrMbAddr -> 在 macroblocks 中的下标, 仅记录当前 slice 内的宏块
+    mb_index: Vec<Option<usize>>,
+    macroblocks: Vec<Macroblock>,
+    // entropy_coding_mode_flag 为 1 时, 在 slice_data 开始处初始化
+    cabac: Option<CabacDecoder>,
+    // 使用 slice data partitioning 时, 帧内与帧间宏块的残差 ( partition B, C ) 来自单独的 RBSP
+    partitioned: bool,
+    partitions: [Option<&'b mut RbspReader<'a>>; 2],
+    missing_partitions: [bool; 2],
+    // 当前宏块在 reader 中开始的比特位置, 以及已读取的运动信息 ( ref_idx, mvd ) 与残差 ( 包括 PCM 样本 ) 的比特数
+    mb_start: usize,
+    motion_bits: usize,
+    residual_bits: usize,
+}
+
+impl<'a, 'b> MacroblockParser<'a, 'b> {
+    fn new(reader: &'b mut RbspReader<'a>,
+           header: &'b SliceHeader,
+           sps: &'b SequenceParameterSet,
+           pps: &'b PictureParameterSet) -> Result<Self, Error> {
+        let pic_size_in_mbs = header.pic_size_in_mbs(sps);
+        let slice_group_map = SliceGroupMap::new(header, sps, pps)?;
+
+        let mb_start = reader.position();
+
+        Ok(Self {
+            reader: reader,
+            header: header,
+            sps: sps,
+            pps: pps,
+            chroma_array_type: sps.chroma_array_type(),
+            pic_size_in_mbs: pic_size_in_mbs,
+            slice_group_map: slice_group_map,
+            mbaff: header.mbaff_frame_flag(sps),
+            mb_field_decoding_flag: header.field_pic_flag,
+            mb_index: vec![None; pic_size_in_mbs as usize],
+            macroblocks: vec![],
+            cabac: None,
+            partitioned: false,
+            partitions: [None, None],
+            missing_partitions: [false; 2],
+            mb_start: mb_start,
+            motion_bits: 0,
+            residual_bits: 0,
+        })
+    }
+
+    // NextMbAddress( n )
+    fn next_mb_address(&self, n: u32) -> u32 {
+        self.slice_group_map.next_mb_address(n)
+    }
+
+    // 宏块的比特数为 reader 从上一个宏块结束到当前宏块结束之间读取的比特 ( 包括 mb_skip_run, mb_skip_flag 与
+    // end_of_slice_flag ), 再加上 partition B, C 中的残差。CABAC 的算术解码引擎会预先读取比特, 因此只是近似值
+    fn push(&mut self, mut mb: Macroblock) {
+        let position = self.reader.position();
+        let residual_in_reader = if self.partitioned { 0 } else { self.residual_bits };
+        mb.bits = MbBits {
+            header: (position - self.mb_start - self.motion_bits - residual_in_reader) as u32,
+            motion: self.motion_bits as u32,
+            residual: self.residual_bits as u32,
+        };
+        self.mb_start = position;
+        self.motion_bits = 0;
+        self.residual_bits = 0;
+
+        self.mb_index[mb.mb_addr as usize] = Some(self.macroblocks.len());
+        self.macroblocks.push(mb);
+    }
+
+    fn check_mb_addr(&self, curr_mb_addr: u32) -> Result<(), Error> {
+        if curr_mb_addr >= self.pic_size_in_mbs || self.mb_index[curr_mb_addr as usize].is_some() {
+            return Err(error::malformed("macroblock address out of range"));
+        }
+
+        Ok(())
+    }
+
+    fn cabac(&mut self) -> (&mut CabacDecoder, &mut RbspReader<'a>) {
+        (self.cabac.as_mut().expect("entropy_coding_mode_flag is not set"), &mut *self.reader)
+    }
+
+    // 类别 3 ( 帧内宏块 ) 或类别 4 ( 帧间宏块 ) 的语法元素所在的 RBSP, 对应的 partition 缺失时为 None
+    fn residual_reader(&mut self, intra: bool) -> Option<&mut RbspReader<'a>> {
+        if !self.partitioned {
+            return Some(&mut *self.reader);
+        }
+
+        let idx = if intra { 0 } else { 1 };
+        match self.partitions[idx] {
+            Some(ref mut reader) => Some(&mut **reader),
+            None => {
+                self.missing_partitions[idx] = true;
+                None
+            },
+        }
+    }
+
+    // 6.4.12 Derivation process for neighbouring locations: MBAFF 帧中按当前宏块对的 mb_field_decoding_flag 与
+    // 相邻宏块对的帧/场类型确定位置
+    fn neighbour_location(&self,
+                          curr_mb_addr: u32,
+                          xn: i32,
+                          yn: i32,
+                          max_w: i32,
+                          max_h: i32) -> Option<(MbNeighbour, u32, i32, i32)> {
+        let pic_width_in_mbs = self.sps.pic_width_in_mbs();
+        if !self.mbaff {
+            return neighbour_location(curr_mb_addr, pic_width_in_mbs, xn, yn, max_w, max_h);
+        }
+
+        let pair_field = |addr: u32| -> Option<bool> {
+            self.mb_index[addr as usize].map(|index| self.macroblocks[index].mb_field_decoding_flag)
+        };
+        neighbour_location_mbaff(curr_mb_addr, pic_width_in_mbs, !self.mb_field_decoding_flag,
+                                 xn, yn, max_w, max_h, &pair_field)
+    }
+
+    // 6.4.10 Derivation process for neighbouring macroblock addresses and their availability in MBAFF frames:
+    // 左边 ( mbAddrA ) 与上边 ( mbAddrB ) 宏块对是否为场宏块对, 不可用时为 None
+    fn neighbour_pair_fields(&self, curr_mb_addr: u32) -> [Option<bool>; 2] {
+        let width = self.sps.pic_width_in_mbs();
+        let pair = curr_mb_addr / 2;
+        let field = |addr: u32| self.mb_index[addr as usize].map(|index| self.macroblocks[index].mb_field_decoding_flag);
+
+        let a = if !pair.is_multiple_of(width) { field(2 * (pair - 1)) } else { None };
+        let b = if pair >= width { field(2 * (pair - width)) } else { None };
+        [a, b]
+    }
+
+    // 7.4.4: 宏块对中没有 mb_field_decoding_flag 时, 取同一 slice 中左边宏块对的值, 其次为上边宏块对, 否则为 0
+    fn inferred_mb_field_decoding_flag(&self, curr_mb_addr: u32) -> bool {
+        let [a, b] = self.neighbour_pair_fields(curr_mb_addr);
+        a.or(b).unwrap_or(false)
+    }
+
+    // mb_field_decoding_flag: 顶部宏块跳过时由底部宏块读取, 两者使用相同的值
+    fn read_mb_field_decoding_flag(&mut self, curr_mb_addr: u32) -> Result<(), Error> {
+        let flag = if self.cabac.is_some() {
+            // 9.3.3.1.1.2: 相邻宏块对可用且为场宏块对时 condTermFlagN 为 1
+            let ctx_idx_inc = self.neighbour_pair_fields(curr_mb_addr).iter()
+                .filter(|&&field| field == Some(true))
+                .count();
+            let (cabac, reader) = self.cabac();
+            cabac.mb_field_decoding_flag(reader, ctx_idx_inc)?
+        } else {
+            self.reader.read_flag()?
+        };
+
+        self.mb_field_decoding_flag = flag;
+        if curr_mb_addr % 2 == 1 {
+            if let Some(index) = self.mb_index[curr_mb_addr as usize - 1] {
+                self.macroblocks[index].mb_field_decoding_flag = flag;
+            }
+        }
+
+        Ok(())
+    }
+
+    // 6.4.11.1 Derivation process for neighbouring macroblocks: mbAddrA ( xN = -1 ) 或 mbAddrB ( yN = -1 )
+    fn neighbour_mb(&self, curr_mb_addr: u32, xn: i32, yn: i32) -> Option<&Macroblock> {
+        let (_, addr, _, _) = self.neighbour_location(curr_mb_addr, xn, yn, 16, 16)?;
+        let index = self.mb_index[addr as usize]?;
+
+        Some(&self.macroblocks[index])
+    }
+
+    // 相邻位置 ( xN, yN ) 所在的宏块 ( 可能是当前宏块 ) 以及在该宏块中的位置 ( xW, yW )
+    fn neighbour_block<'c>(&'c self,
+                           mb: &'c Macroblock,
+                           xn: i32,
+                           yn: i32,
+                           max_w: i32,
+                           max_h: i32) -> Option<(&'c Macroblock, i32, i32)> {
+        let (kind, addr, xw, yw) = self.neighbour_location(mb.mb_addr, xn, yn, max_w, max_h)?;
+        if kind == MbNeighbour::Curr {
+            return Some((mb, xw, yw));
+        }
+
+        let index = self.mb_index[addr as usize]?;
+
+        Some((&self.macroblocks[index], xw, yw))
+    }
+
+    // slice_data()
+    fn slice_data(&mut self) -> Result<(), Error> {
+        if self.pps.entropy_coding_mode_flag() {
+            while !self.reader.byte_aligned() {
+                if !self.reader.read_bit()? {
+                    return Err(error::malformed("cabac_alignment_one_bit must be equal to 1"));
+                }
+            }
+
+            let mut cabac = CabacDecoder::new(self.header.slice_type,
+                                              self.header.cabac_init_idc,
+                                              self.header.slice_qp_y(self.pps))?;
+            cabac.init_engine(self.reader)?;
+            self.cabac = Some(cabac);
+
+            return self.slice_data_cabac();
+        }
+
+        let slice_type = self.header.slice_type;
+        let mut curr_mb_addr = self.header.first_mb_in_slice * (1 + self.mbaff as u32);
+        let mut more_data_flag = true;
+        let mut prev_mb_skipped = false;
+
+        loop {
+            if !slice_type.is_intra() {
+                let mb_skip_run = self.reader.read_ue()?;
+                prev_mb_skipped = mb_skip_run > 0;
+                for _ in 0..mb_skip_run {
+                    self.check_mb_addr(curr_mb_addr)?;
+                    if self.mbaff && curr_mb_addr.is_multiple_of(2) {
+                        self.mb_field_decoding_flag = self.inferred_mb_field_decoding_flag(curr_mb_addr);
+                    }
+                    let mb = self.skipped_macroblock(curr_mb_addr);
+                    self.push(mb);
+                    curr_mb_addr = self.next_mb_address(curr_mb_addr);
+                }
+
+                if mb_skip_run > 0 {
+                    more_data_flag = self.reader.more_rbsp_data();
+                }
+            }
+
+            if more_data_flag {
+                self.check_mb_addr(curr_mb_addr)?;
+                if self.mbaff && (curr_mb_addr.is_multiple_of(2) || prev_mb_skipped) {
+                    self.read_mb_field_decoding_flag(curr_mb_addr)?;
+                }
+                let mb = self.macroblock_layer(curr_mb_addr)?;
+                self.push(mb);
+            }
+
+            more_data_flag = self.reader.more_rbsp_data();
+            if !more_data_flag {
+                break;
+            }
+
+            curr_mb_addr = self.next_mb_address(curr_mb_addr);
+        }
+
+        Ok(())
+    }
+
+    // slice_data() 中 entropy_coding_mode_flag 为 1 的部分
+    fn slice_data_cabac(&mut self) -> Result<(), Error> {
+        let slice_type = self.header.slice_type;
+        let mut curr_mb_addr = self.header.first_mb_in_slice * (1 + self.mbaff as u32);
+        let mut prev_mb_skipped = false;
+
+        loop {
+            self.check_mb_addr(curr_mb_addr)?;
+            if self.mbaff && curr_mb_addr.is_multiple_of(2) {
+                self.mb_field_decoding_flag = self.inferred_mb_field_decoding_flag(curr_mb_addr);
+            }
+
+            let mut mb_skip_flag = false;
+            if !slice_type.is_intra() {
+                // 9.3.3.1.1.1: 相邻宏块可用且不是跳过的宏块时 condTermFlagN 为 1
+                let cond_term_flag = |mb_n: Option<&Macroblock>| -> usize {
+                    mb_n.map(|mb_n| !mb_n.mb_type.is_skip() as usize).unwrap_or(0)
+                };
+                let ctx_idx_inc = cond_term_flag(self.neighbour_mb(curr_mb_addr, -1, 0))
+                    + cond_term_flag(self.neighbour_mb(curr_mb_addr, 0, -1));
+
+                let (cabac, reader) = self.cabac();
+                mb_skip_flag = cabac.mb_skip_flag(reader, slice_type, ctx_idx_inc)?;
+            }
+
+            let mb = if mb_skip_flag {
+                self.skipped_macroblock(curr_mb_addr)
+            } else {
+                if self.mbaff && (curr_mb_addr.is_multiple_of(2) || prev_mb_skipped) {
+                    self.read_mb_field_decoding_flag(curr_mb_addr)?;
+                }
+                self.macroblock_layer(curr_mb_addr)?
+            };
+            self.push(mb);
+            prev_mb_skipped = mb_skip_flag;
+
+            // MBAFF 帧中宏块对的顶部宏块之后没有 end_of_slice_flag
+            if self.mbaff && curr_mb_addr.is_multiple_of(2) {
+                curr_mb_addr += 1;
+                continue;
+            }
+
+            let (cabac, reader) = self.cabac();
+            if cabac.end_of_slice_flag(reader)? {
+                break;
+            }
+
+            curr_mb_addr = self.next_mb_address(curr_mb_addr);
+        }
+
+        Ok(())
+    }
+
+    fn skipped_macroblock(&self, curr_mb_addr: u32) -> Macroblock {
+        let mb_type = if self.header.slice_type.is_bipredictive() { MbType::BSkip } else { MbType::PSkip };
+        let mut mb = Macroblock::new(curr_mb_addr, mb_type);
+        mb.mb_field_decoding_flag = self.mb_field_decoding_flag;
+
+        if mb_type == MbType::PSkip {
+            mb.ref_idx[0] = [0; 4];
+        }
+
+        mb
+    }
+
+    // 7.3.5 Macroblock layer syntax
+    fn macroblock_layer(&mut self, curr_mb_addr: u32) -> Result<Macroblock, Error> {
+        let code = if self.cabac.is_some() { self.read_mb_type_cabac(curr_mb_addr)? } else { self.reader.read_ue()? };
+        let mb_type = MbType::from_code(self.header.slice_type, code)?;
+
+        let mut mb = Macroblock::new(curr_mb_addr, mb_type);
+        mb.mb_field_decoding_flag = self.mb_field_decoding_flag;
+
+        if mb_type == MbType::IPcm {
+            self.pcm_samples(&mut mb)?;
+
+            if let Some(ref mut cabac) = self.cabac {
+                cabac.init_engine(self.reader)?;
+            }
+
+            return Ok(mb);
+        }
+
+        let mut no_sub_mb_part_size_less_than_8x8_flag = true;
+
+        if mb_type != MbType::INxN && !mb_type.is_intra_16x16() && mb_type.num_mb_part() == 4 {
+            self.sub_mb_pred(&mut mb)?;
+
+            if let Some(sub_mb_types) = mb.sub_mb_type {
+                for sub_mb_type in sub_mb_types.iter() {
+                    if *sub_mb_type != SubMbType::BDirect8x8 {
+                        if sub_mb_type.num_sub_mb_part() > 1 {
+                            no_sub_mb_part_size_less_than_8x8_flag = false;
+                        }
+                    } else if !self.sps.direct_8x8_inference_flag() {
+                        no_sub_mb_part_size_less_than_8x8_flag = false;
+                    }
+                }
+            }
+        } else {
+            if self.pps.transform_8x8_mode_flag() && mb_type == MbType::INxN {
+                mb.transform_size_8x8_flag = self.read_transform_size_8x8_flag(&mb)?;
+            }
+            self.mb_pred(&mut mb)?;
+        }
+
+        if !mb_type.is_intra_16x16() {
+            self.coded_block_pattern(&mut mb)?;
+
+            if mb.coded_block_pattern_luma > 0
+                && self.pps.transform_8x8_mode_flag()
+                && mb_type != MbType::INxN
+                && no_sub_mb_part_size_less_than_8x8_flag
+                && (mb_type != MbType::BDirect16x16 || self.sps.direct_8x8_inference_flag()) {
+                mb.transform_size_8x8_flag = self.read_transform_size_8x8_flag(&mb)?;
+            }
+        }
+
+        if mb.coded_block_pattern_luma > 0 || mb.coded_block_pattern_chroma > 0 || mb_type.is_intra_16x16() {
+            let mb_qp_delta = self.read_mb_qp_delta()?;
+            let limit = 26 + self.sps.qp_bd_offset_luma() / 2;
+            if mb_qp_delta < -limit || mb_qp_delta > limit - 1 {
+                return Err(error::malformed("mb_qp_delta out of range"));
+            }
+            mb.mb_qp_delta = mb_qp_delta;
+
+            self.residual(&mut mb, 0, 15)?;
+        }
+
+        Ok(mb)
+    }
+
+    // 9.3.3.1.1.3 Derivation process of ctxIdxInc for the syntax element mb_type
+    fn read_mb_type_cabac(&mut self, curr_mb_addr: u32) -> Result<u32, Error> {
+        let slice_type = self.header.slice_type;
+        let mb_a = self.neighbour_mb(curr_mb_addr, -1, 0).map(|mb| mb.mb_type);
+        let mb_b = self.neighbour_mb(curr_mb_addr, 0, -1).map(|mb| mb.mb_type);
+
+        let ctx_idx_inc = |cond_term_flag: fn(MbType) -> bool| -> usize {
+            mb_a.map(cond_term_flag).unwrap_or(false) as usize
+                + mb_b.map(cond_term_flag).unwrap_or(false) as usize
+        };
+
+        let suffix_inc = ctx_idx_inc(|mb_type| mb_type != MbType::INxN);
+
+        match slice_type {
+            SliceType::SI => {
+                let prefix_inc = ctx_idx_inc(|mb_type| mb_type != MbType::Si);
+                let (cabac, reader) = self.cabac();
+                cabac.mb_type_si(reader, prefix_inc, suffix_inc)
+            },
+            SliceType::B => {
+                let inc = ctx_idx_inc(|mb_type| mb_type != MbType::BSkip && mb_type != MbType::BDirect16x16);
+                let (cabac, reader) = self.cabac();
+                cabac.mb_type(reader, slice_type, inc)
+            },
+            _ => {
+                let (cabac, reader) = self.cabac();
+                cabac.mb_type(reader, slice_type, suffix_inc)
+            },
+        }
+    }
+
+    fn read_transform_size_8x8_flag(&mut self, mb: &Macroblock) -> Result<bool, Error> {
+        if self.cabac.is_none() {
+            return self.reader.read_flag();
+        }
+
+        // 9.3.3.1.1.10
+        let cond_term_flag = |mb_n: Option<&Macroblock>| -> usize {
+            mb_n.map(|mb_n| mb_n.transform_size_8x8_flag as usize).unwrap_or(0)
+        };
+        let ctx_idx_inc = cond_term_flag(self.neighbour_mb(mb.mb_addr, -1, 0))
+            + cond_term_flag(self.neighbour_mb(mb.mb_addr, 0, -1));
+
+        let (cabac, reader) = self.cabac();
+        cabac.transform_size_8x8_flag(reader, ctx_idx_inc)
+    }
+
+    fn read_mb_qp_delta(&mut self) -> Result<i32, Error> {
+        if self.cabac.is_none() {
+            return self.reader.read_se();
+        }
+
+        // 9.3.3.1.1.5: prevMbAddr 为当前 slice 中按解码顺序的前一个宏块
+        let ctx_idx_inc = match self.macroblocks.last() {
+            Some(prev_mb) => {
+                let no_residual = !prev_mb.mb_type.is_intra_16x16()
+                    && prev_mb.coded_block_pattern_luma == 0
+                    && prev_mb.coded_block_pattern_chroma == 0;
+                let zero = prev_mb.mb_type.is_skip()
+                    || prev_mb.mb_type == MbType::IPcm
+                    || no_residual
+                    || prev_mb.mb_qp_delta == 0;
+                !zero as usize
+            },
+            None => 0,
+        };
+
+        let (cabac, reader) = self.cabac();
+        cabac.mb_qp_delta(reader, ctx_idx_inc)
+    }
+
+    fn pcm_samples(&mut self, mb: &mut Macroblock) -> Result<(), Error> {
+        let bit_depth_luma = self.sps.bit_depth_luma();
+        let bit_depth_chroma = self.sps.bit_depth_chroma();
+        let num_chroma = 2 * self.sps.mb_width_c() * self.sps.mb_height_c();
+
+        // pcm_alignment_zero_bit 与 pcm_sample_* 属于类别 3
+        let reader = match self.residual_reader(true) {
+            Some(reader) => reader,
+            None => {
+                mb.pcm_samples = Some(PcmSamples {
+                    luma: vec![1 << (bit_depth_luma - 1); 256],
+                    chroma: vec![1 << (bit_depth_chroma - 1); num_chroma as usize],
+                });
+                mb.total_coeff = [[16; 16]; 3];
+                return Ok(());
+            },
+        };
+
+        let start = reader.position();
+        while !reader.byte_aligned() {
+            if reader.read_bit()? {
+                return Err(error::malformed("pcm_alignment_zero_bit must be equal to 0"));
+            }
+        }
+
+        let mut luma = Vec::with_capacity(256);
+        for _ in 0..256 {
+            luma.push(reader.read_bits(bit_depth_luma)? as u16);
+        }
+
+        let mut chroma = Vec::with_capacity(num_chroma as usize);
+        for _ in 0..num_chroma {
+            chroma.push(reader.read_bits(bit_depth_chroma)? as u16);
+        }
+        self.residual_bits += reader.position() - start;
+
+        mb.pcm_samples = Some(PcmSamples { luma: luma, chroma: chroma });
+        mb.total_coeff = [[16; 16]; 3];
+
+        Ok(())
+    }
+
+    // te(v) 的取值上限
+    fn ref_idx_range(&self, num_ref_idx_active_minus1: u32, mb: &Macroblock) -> u32 {
+        if self.mbaff && mb.mb_field_decoding_flag {
+            num_ref_idx_active_minus1 * 2 + 1
+        } else {
+            num_ref_idx_active_minus1
+        }
+    }
+
+    // ( x, y ) 为分区左上角的亮度位置
+    fn read_ref_idx(&mut self, mb: &Macroblock, list: usize, x: i32, y: i32, range: u32) -> Result<i8, Error> {
+        let start = self.reader.position();
+        let ref_idx = if self.cabac.is_some() {
+            // 9.3.3.1.1.6: 直接预测的分区 refIdxLX 记录为 -1, MBAFF 帧中当前为帧宏块而相邻为场宏块时阈值为 1
+            let cond_term_flag = |xn: i32, yn: i32| -> usize {
+                match self.neighbour_block(mb, xn, yn, 16, 16) {
+                    Some((mb_n, xw, yw)) if !mb_n.mb_type.is_skip() && mb_n.mb_type.is_inter() => {
+                        let (mb_part_idx, _) = mb_n.partition_idx(xw, yw);
+                        let threshold = (self.mbaff && !mb.mb_field_decoding_flag && mb_n.mb_field_decoding_flag) as i8;
+                        (mb_n.ref_idx[list][mb_part_idx] > threshold) as usize
+                    },
+                    _ => 0,
+                }
+            };
+            let ctx_idx_inc = cond_term_flag(x - 1, y) + 2 * cond_term_flag(x, y - 1);
+
+            let (cabac, reader) = self.cabac();
+            cabac.ref_idx(reader, ctx_idx_inc, range)?
+        } else {
+            self.reader.read_te(range)?
+        };
+        self.motion_bits += self.reader.position() - start;
+
+        if ref_idx > range {
+            return Err(error::malformed("ref_idx out of range"));
+        }
+
+        Ok(ref_idx as i8)
+    }
+
+    fn read_mvd(&mut self, mb: &Macroblock, list: usize, x: i32, y: i32) -> Result<[i32; 2], Error> {
+        let start = self.reader.position();
+        if self.cabac.is_none() {
+            let mvd = [self.reader.read_se()?, self.reader.read_se()?];
+            self.motion_bits += self.reader.position() - start;
+            return Ok(mvd);
+        }
+
+        // 9.3.3.1.1.7: 不可用, 跳过, 帧内以及未使用该列表的分区 mvd 均记录为 0。MBAFF 帧中当前宏块与相邻宏块
+        // 帧/场类型不同时, 垂直分量按场的比例换算
+        let abs_mvd_comp = |xn: i32, yn: i32, comp_idx: usize| -> u32 {
+            match self.neighbour_block(mb, xn, yn, 16, 16) {
+                Some((mb_n, xw, yw)) => {
+                    let (mb_part_idx, sub_mb_part_idx) = mb_n.partition_idx(xw, yw);
+                    let abs = mb_n.mvd[list][mb_part_idx][sub_mb_part_idx][comp_idx].unsigned_abs();
+                    match (comp_idx, mb.mb_field_decoding_flag, mb_n.mb_field_decoding_flag) {
+                        (1, false, true) if self.mbaff => abs * 2,
+                        (1, true, false) if self.mbaff => abs / 2,
+                        _ => abs,
+                    }
+                },
+                None => 0,
+            }
+        };
+
+        let sums = [
+            abs_mvd_comp(x - 1, y, 0) + abs_mvd_comp(x, y - 1, 0),
+            abs_mvd_comp(x - 1, y, 1) + abs_mvd_comp(x, y - 1, 1),
+        ];
+
+        let mut mvd = [0; 2];
+        for (comp_idx, &sum) in sums.iter().enumerate() {
+            let (cabac, reader) = self.cabac();
+            mvd[comp_idx] = cabac.mvd(reader, comp_idx, sum)?;
+        }
+        self.motion_bits += self.reader.position() - start;
+
+        Ok(mvd)
+    }
+
+    fn read_prev_intra_pred_mode_flag(&mut self) -> Result<bool, Error> {
+        match self.cabac {
+            Some(ref mut cabac) => cabac.prev_intra_pred_mode_flag(self.reader),
+            None => self.reader.read_flag(),
+        }
+    }
+
+    fn read_rem_intra_pred_mode(&mut self) -> Result<u8, Error> {
+        match self.cabac {
+            Some(ref mut cabac) => cabac.rem_intra_pred_mode(self.reader),
+            None => Ok(self.reader.read_bits(3)? as u8),
+        }
+    }
+
+    fn read_intra_chroma_pred_mode(&mut self, mb: &Macroblock) -> Result<u8, Error> {
+        if self.cabac.is_none() {
+            let intra_chroma_pred_mode = self.reader.read_ue()?;
+            if intra_chroma_pred_mode > 3 {
+                return Err(error::malformed("intra_chroma_pred_mode out of range"));
+            }
+            return Ok(intra_chroma_pred_mode as u8);
+        }
+
+        // 9.3.3.1.1.8
+        let cond_term_flag = |mb_n: Option<&Macroblock>| -> usize {
+            match mb_n {
+                Some(mb_n) => (mb_n.mb_type.is_intra()
+                    && mb_n.mb_type != MbType::IPcm
+                    && mb_n.intra_chroma_pred_mode != 0) as usize,
+                None => 0,
+            }
+        };
+        let ctx_idx_inc = cond_term_flag(self.neighbour_mb(mb.mb_addr, -1, 0))
+            + cond_term_flag(self.neighbour_mb(mb.mb_addr, 0, -1));
+
+        let (cabac, reader) = self.cabac();
+        cabac.intra_chroma_pred_mode(reader, ctx_idx_inc)
+    }
+
+    // 7.3.5.1 Macroblock prediction syntax ( Page 79 )
+    fn mb_pred(&mut self, mb: &mut Macroblock) -> Result<(), Error> {
+        if let Some(intra_pred_mode) = mb.intra_pred_mode() {
+            let count = match intra_pred_mode {
+                IntraPredMode::Intra4x4 => 16,
+                IntraPredMode::Intra8x8 => 4,
+                IntraPredMode::Intra16x16 => 0,
+            };
+
+            for idx in 0..count {
+                mb.prev_intra_pred_mode_flag[idx] = self.read_prev_intra_pred_mode_flag()?;
+                if !mb.prev_intra_pred_mode_flag[idx] {
+                    mb.rem_intra_pred_mode[idx] = self.read_rem_intra_pred_mode()?;
+                }
+            }
+
+            if self.chroma_array_type == 1 || self.chroma_array_type == 2 {
+                mb.intra_chroma_pred_mode = self.read_intra_chroma_pred_mode(mb)?;
+            }
+
+            return Ok(());
+        }
+
+        if mb.mb_type.mb_part_pred_mode(0) == Some(PredMode::Direct) {
+            return Ok(());
+        }
+
+        let num_mb_part = mb.mb_type.num_mb_part();
+        let (part_width, part_height) = mb.mb_type.mb_part_size();
+        let part_position = |mb_part_idx: usize| -> (i32, i32) {
+            let parts_per_row = (16 / part_width) as usize;
+            ((mb_part_idx % parts_per_row) as i32 * part_width as i32,
+             (mb_part_idx / parts_per_row) as i32 * part_height as i32)
+        };
+        let field_mismatch = mb.mb_field_decoding_flag != self.header.field_pic_flag;
+        let num_ref_idx_active_minus1 = [
+            self.header.num_ref_idx_l0_active_minus1,
+            self.header.num_ref_idx_l1_active_minus1,
+        ];
+
+        for list in 0..2 {
+            let range = self.ref_idx_range(num_ref_idx_active_minus1[list], mb);
+            for mb_part_idx in 0..num_mb_part {
+                let pred_mode = mb.mb_type.mb_part_pred_mode(mb_part_idx).unwrap_or(PredMode::Direct);
+                let uses_list = if list == 0 { pred_mode.uses_l0() } else { pred_mode.uses_l1() };
+                if !uses_list {
+                    continue;
+                }
+
+                mb.ref_idx[list][mb_part_idx] = if num_ref_idx_active_minus1[list] > 0 || field_mismatch {
+                    let (x, y) = part_position(mb_part_idx);
+                    self.read_ref_idx(mb, list, x, y, range)?
+                } else {
+                    0
+                };
+            }
+        }
+
+        for list in 0..2 {
+            for mb_part_idx in 0..num_mb_part {
+                let pred_mode = mb.mb_type.mb_part_pred_mode(mb_part_idx).unwrap_or(PredMode::Direct);
+                let uses_list = if list == 0 { pred_mode.uses_l0() } else { pred_mode.uses_l1() };
+                if uses_list {
+                    let (x, y) = part_position(mb_part_idx);
+                    mb.mvd[list][mb_part_idx][0] = self.read_mvd(mb, list, x, y)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // 7.3.5.2 Sub-macroblock prediction syntax ( Page 80 )
+    fn sub_mb_pred(&mut self, mb: &mut Macroblock) -> Result<(), Error> {
+        let mut sub_mb_types = [SubMbType::P8x8; 4];
+        for sub_mb_type in sub_mb_types.iter_mut() {
+            let code = match self.cabac {
+                Some(ref mut cabac) => cabac.sub_mb_type(self.reader, self.header.slice_type)?,
+                None => self.reader.read_ue()?,
+            };
+            *sub_mb_type = SubMbType::from_code(self.header.slice_type, code)?;
+        }
+        mb.sub_mb_type = Some(sub_mb_types);
+
+        let field_mismatch = mb.mb_field_decoding_flag != self.header.field_pic_flag;
+        let num_ref_idx_active_minus1 = [
+            self.header.num_ref_idx_l0_active_minus1,
+            self.header.num_ref_idx_l1_active_minus1,
+        ];
+
+        for list in 0..2 {
+            let range = self.ref_idx_range(num_ref_idx_active_minus1[list], mb);
+            for mb_part_idx in 0..4 {
+                let pred_mode = sub_mb_types[mb_part_idx].sub_mb_pred_mode();
+                let uses_list = if list == 0 { pred_mode.uses_l0() } else { pred_mode.uses_l1() };
+                if !uses_list {
+                    continue;
+                }
+
+                let present = (num_ref_idx_active_minus1[list] > 0 || field_mismatch)
+                    && mb.mb_type != MbType::P8x8Ref0;
+
+                mb.ref_idx[list][mb_part_idx] = if present {
+                    let (x, y) = ((mb_part_idx % 2) as i32 * 8, (mb_part_idx / 2) as i32 * 8);
+                    self.read_ref_idx(mb, list, x, y, range)?
+                } else {
+                    0
+                };
+            }
+        }
+
+        for list in 0..2 {
+            for mb_part_idx in 0..4 {
+                let sub_mb_type = sub_mb_types[mb_part_idx];
+                let pred_mode = sub_mb_type.sub_mb_pred_mode();
+                let uses_list = if list == 0 { pred_mode.uses_l0() } else { pred_mode.uses_l1() };
+                if !uses_list {
+                    continue;
+                }
+
+                let (sub_width, sub_height) = sub_mb_type.sub_mb_part_size();
+                let parts_per_row = (8 / sub_width) as usize;
+                for sub_mb_part_idx in 0..sub_mb_type.num_sub_mb_part() {
+                    let x = (mb_part_idx % 2) as i32 * 8 + (sub_mb_part_idx % parts_per_row) as i32 * sub_width as i32;
+                    let y = (mb_part_idx / 2) as i32 * 8 + (sub_mb_part_idx / parts_per_row) as i32 * sub_height as i32;
+                    mb.mvd[list][mb_part_idx][sub_mb_part_idx] = self.read_mvd(mb, list, x, y)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // coded_block_pattern: me(v) ( 9.1.2 ) 或 ae(v)
+    fn coded_block_pattern(&mut self, mb: &mut Macroblock) -> Result<(), Error> {
+        if self.cabac.is_some() {
+            return self.coded_block_pattern_cabac(mb);
+        }
+
+        let code = self.reader.read_ue()?;
+        let max = if self.chroma_array_type == 1 || self.chroma_array_type == 2 { 47 } else { 15 };
+        if code > max {
+            return Err(error::malformed("coded_block_pattern out of range"));
+        }
+
+        let (intra, inter) = golomb::me_decode(code, self.chroma_array_type as u8);
+        let coded_block_pattern = if mb.mb_type.is_intra() { intra } else { inter };
+
+        mb.coded_block_pattern_luma = coded_block_pattern % 16;
+        mb.coded_block_pattern_chroma = coded_block_pattern / 16;
+
+        Ok(())
+    }
+
+    // 9.3.2.6 Binarization process for coded_block_pattern 以及 9.3.3.1.1.4
+    //
+    // 前缀 ( 亮度 ) 为 4 个 FL bin, 各 8x8 块的 ctxIdxInc 依赖已解码的 bin, 所以逐个写回当前宏块。
+    fn coded_block_pattern_cabac(&mut self, mb: &mut Macroblock) -> Result<(), Error> {
+        mb.coded_block_pattern_luma = 0;
+        mb.coded_block_pattern_chroma = 0;
+
+        for b8 in 0..4 {
+            let (x, y) = ((b8 % 2) * 8, (b8 / 2) * 8);
+            let cond_term_flag = |xn: i32, yn: i32| -> usize {
+                match self.neighbour_block(mb, xn, yn, 16, 16) {
+                    Some((mb_n, xw, yw)) => {
+                        let b8_n = 2 * (yw / 8) + xw / 8;
+                        let coded = (mb_n.coded_block_pattern_luma >> b8_n) & 1 != 0;
+                        (mb_n.mb_type != MbType::IPcm && !coded) as usize
+                    },
+                    None => 0,
+                }
+            };
+            let ctx_idx_inc = cond_term_flag(x - 1, y) + 2 * cond_term_flag(x, y - 1);
+
+            let (cabac, reader) = self.cabac();
+            if cabac.coded_block_pattern_bin(reader, cabac::CODED_BLOCK_PATTERN_LUMA, ctx_idx_inc)? {
+                mb.coded_block_pattern_luma |= 1 << b8;
+            }
+        }
+
+        if self.chroma_array_type != 1 && self.chroma_array_type != 2 {
+            return Ok(());
+        }
+
+        // 后缀 ( 色度 ) 为 TU, cMax = 2
+        let mb_a = self.neighbour_mb(mb.mb_addr, -1, 0).map(|mb_n| (mb_n.mb_type, mb_n.coded_block_pattern_chroma));
+        let mb_b = self.neighbour_mb(mb.mb_addr, 0, -1).map(|mb_n| (mb_n.mb_type, mb_n.coded_block_pattern_chroma));
+        let cond_term_flag = |mb_n: Option<(MbType, u8)>, bin_idx: u8| -> usize {
+            match mb_n {
+                Some((MbType::IPcm, _)) => 1,
+                Some((mb_type, chroma)) if !mb_type.is_skip() => {
+                    (if bin_idx == 0 { chroma != 0 } else { chroma == 2 }) as usize
+                },
+                _ => 0,
+            }
+        };
+
+        for bin_idx in 0..2 {
+            let ctx_idx_inc = cond_term_flag(mb_a, bin_idx) + 2 * cond_term_flag(mb_b, bin_idx) + 4 * bin_idx as usize;
+            let (cabac, reader) = self.cabac();
+            if !cabac.coded_block_pattern_bin(reader, cabac::CODED_BLOCK_PATTERN_CHROMA, ctx_idx_inc)? {
+                break;
+            }
+            mb.coded_block_pattern_chroma += 1;
+        }
+
+        Ok(())
+    }
+
+    // 9.2.1 Parsing process for total number of non-zero transform coefficient levels and number of trailing ones
+    //
+    // 分量 comp: 0 为 Y, 1 为 Cb, 2 为 Cr; chroma 表示 ChromaArrayType 为 1 或 2 的色度 AC 块
+    fn nc(&self, mb: &Macroblock, comp: usize, blk_idx: usize, chroma: bool) -> i32 {
+        let (x, y, max_w, max_h) = if chroma {
+            let x = (blk_idx % 2) as i32 * 4;
+            let y = (blk_idx / 2) as i32 * 4;
+            (x, y, self.sps.mb_width_c() as i32, self.sps.mb_height_c() as i32)
+        } else {
+            let (x, y) = luma4x4_blk_position(blk_idx);
+            (x, y, 16, 16)
+        };
+
+        let neighbour = |xn: i32, yn: i32| -> Option<i32> {
+            let (kind, addr, xw, yw) = self.neighbour_location(mb.mb_addr, xn, yn, max_w, max_h)?;
+            let blk = if chroma { chroma4x4_blk_idx(xw, yw) } else { luma4x4_blk_idx(xw, yw) };
+
+            if kind == MbNeighbour::Curr {
+                return Some(mb.total_coeff[comp][blk] as i32);
+            }
+
+            let index = self.mb_index[addr as usize]?;
+            let mb_n = &self.macroblocks[index];
+
+            if mb_n.mb_type.is_skip() {
+                return Some(0);
+            }
+
+            if mb_n.mb_type == MbType::IPcm {
+                return Some(16);
+            }
+
+            let data_partitioning = match self.header.nal_unit_type {
+                NaluKind::CodedSliceDataPartitionA
+                | NaluKind::CodedSliceDataPartitionB
+                | NaluKind::CodedSliceDataPartitionC => true,
+                _ => false,
+            };
+            if data_partitioning && self.pps.constrained_intra_pred_flag()
+                && mb.mb_type.is_intra() && mb_n.mb_type.is_inter() {
+                return Some(0);
+            }
+
+            Some(mb_n.total_coeff[comp][blk] as i32)
+        };
+
+        match (neighbour(x - 1, y), neighbour(x, y - 1)) {
+            (Some(na), Some(nb)) => (na + nb + 1) >> 1,
+            (Some(na), None) => na,
+            (None, Some(nb)) => nb,
+            (None, None) => 0,
+        }
+    }
+
+    // 9.3.3.1.1.9 Derivation process of ctxIdxInc for the syntax element coded_block_flag
+    fn coded_block_flag_ctx_idx_inc(&self, mb: &Macroblock, ctx_block_cat: usize, comp: usize, blk_idx: usize) -> usize {
+        let dc = match ctx_block_cat {
+            0 | 3 | 6 | 10 => true,
+            _ => false,
+        };
+        let is_8x8 = ctx_block_cat == 5 || ctx_block_cat == 9 || ctx_block_cat == 13;
+
+        let (x, y, max_w, max_h) = if dc {
+            (0, 0, 16, 16)
+        } else if ctx_block_cat == 4 {
+            ((blk_idx % 2) as i32 * 4, (blk_idx / 2) as i32 * 4, self.sps.mb_width_c() as i32, self.sps.mb_height_c() as i32)
+        } else if is_8x8 {
+            ((blk_idx % 2) as i32 * 8, (blk_idx / 2) as i32 * 8, 16, 16)
+        } else {
+            let (x, y) = luma4x4_blk_position(blk_idx);
+            (x, y, 16, 16)
+        };
+
+        let cond_term_flag = |xn: i32, yn: i32| -> usize {
+            let (mb_n, xw, yw) = match self.neighbour_block(mb, xn, yn, max_w, max_h) {
+                Some(neighbour) => neighbour,
+                None => return mb.mb_type.is_intra() as usize,
+            };
+
+            if mb_n.mb_type == MbType::IPcm {
+                return 1;
+            }
+
+            let coded_block_flag = if dc {
+                mb_n.coded_block_flag_dc[comp]
+            } else if ctx_block_cat == 4 {
+                mb_n.total_coeff[comp][chroma4x4_blk_idx(xw, yw)] != 0
+            } else if is_8x8 && !mb_n.transform_size_8x8_flag {
+                false
+            } else {
+                mb_n.total_coeff[comp][luma4x4_blk_idx(xw, yw)] != 0
+            };
+
+            coded_block_flag as usize
+        };
+
+        cond_term_flag(x - 1, y) + 2 * cond_term_flag(x, y - 1)
+    }
+
+    // residual_block( ): 根据 entropy_coding_mode_flag 选择 residual_block_cavlc 或 residual_block_cabac
+    //
+    // comp 为颜色分量, blk_idx 为 4x4 ( 或 8x8, 色度 4x4 ) 块的下标, 返回非零系数的个数。
+    #[allow(clippy::too_many_arguments)]
+    fn residual_block(&mut self,
+                      mb: &Macroblock,
+                      ctx_block_cat: usize,
+                      comp: usize,
+                      blk_idx: usize,
+                      coeff_level: &mut [i32],
+                      start_idx: usize,
+                      end_idx: usize,
+                      max_num_coeff: usize) -> Result<u8, Error> {
+        if self.cabac.is_some() {
+            let coded_block_flag_inc = if max_num_coeff != 64 || self.chroma_array_type == 3 {
+                Some(self.coded_block_flag_ctx_idx_inc(mb, ctx_block_cat, comp, blk_idx))
+            } else {
+                None
+            };
+            let field = mb.mb_field_decoding_flag;
+            let start = self.reader.position();
+
+            let (cabac, reader) = self.cabac();
+            let total_coeff = cabac.residual_block(reader, coeff_level, start_idx, end_idx, max_num_coeff,
+                                                   ctx_block_cat, field, coded_block_flag_inc)?;
+            self.residual_bits += self.reader.position() - start;
+            return Ok(total_coeff);
+        }
+
+        let nc = match ctx_block_cat {
+            3 => if self.chroma_array_type == 1 { -1 } else { -2 },
+            4 => self.nc(mb, comp, blk_idx, true),
+            0 | 6 | 10 => self.nc(mb, comp, 0, false),
+            _ => self.nc(mb, comp, blk_idx, false),
+        };
+
+        let reader = match self.residual_reader(mb.mb_type.is_intra()) {
+            Some(reader) => reader,
+            None => return Ok(0),
+        };
+
+        let start = reader.position();
+        let total_coeff = cavlc::residual_block_cavlc(reader, nc, coeff_level, start_idx, end_idx, max_num_coeff)?;
+        self.residual_bits += reader.position() - start;
+
+        Ok(total_coeff)
+    }
+
+    // 7.3.5.3 Residual data syntax ( Page 80 )
+    fn residual(&mut self, mb: &mut Macroblock, start_idx: usize, end_idx: usize) -> Result<(), Error> {
+        let mut residual = Residual::default();
+
+        residual.luma = self.residual_luma(mb, 0, start_idx, end_idx)?;
+
+        if self.chroma_array_type == 1 || self.chroma_array_type == 2 {
+            let num_c8x8 = (4 / (self.sps.sub_width_c() * self.sps.sub_height_c())) as usize;
+
+            for i_cb_cr in 0..2 {
+                if (mb.coded_block_pattern_chroma & 3) != 0 && start_idx == 0 {
+                    let total_coeff = self.residual_block(mb, 3, 1 + i_cb_cr, 0, &mut residual.chroma_dc[i_cb_cr],
+                                                          0, 4 * num_c8x8 - 1, 4 * num_c8x8)?;
+                    mb.coded_block_flag_dc[1 + i_cb_cr] = total_coeff > 0;
+                }
+            }
+
+            for i_cb_cr in 0..2 {
+                for blk_idx in 0..num_c8x8 * 4 {
+                    if (mb.coded_block_pattern_chroma & 2) != 0 {
+                        let total_coeff = self.residual_block(
+                            mb, 4, 1 + i_cb_cr, blk_idx, &mut residual.chroma_ac[i_cb_cr][blk_idx][1..],
+                            start_idx.saturating_sub(1), end_idx - 1, 15)?;
+                        mb.total_coeff[1 + i_cb_cr][blk_idx] = total_coeff;
+                    }
+                }
+            }
+        } else if self.chroma_array_type == 3 {
+            residual.cb = Some(self.residual_luma(mb, 1, start_idx, end_idx)?);
+            residual.cr = Some(self.residual_luma(mb, 2, start_idx, end_idx)?);
+        }
+
+        mb.residual = Some(Box::new(residual));
+
+        Ok(())
+    }
+
+    // 7.3.5.3.1 Residual luma syntax ( Page 81 )
+    fn residual_luma(&mut self,
+                     mb: &mut Macroblock,
+                     comp: usize,
+                     start_idx: usize,
+                     end_idx: usize) -> Result<ResidualBlocks, Error> {
+        // ctxBlockCat: Intra16x16DCLevel, Intra16x16ACLevel, LumaLevel4x4, LumaLevel8x8 ( 以及对应的 Cb, Cr )
+        const CTX_BLOCK_CATS: [[usize; 4]; 3] = [[0, 1, 2, 5], [6, 7, 8, 9], [10, 11, 12, 13]];
+        let [cat_dc, cat_ac, cat_4x4, cat_8x8] = CTX_BLOCK_CATS[comp];
+
+        let mut blocks = ResidualBlocks::default();
+        let intra_16x16 = mb.mb_type.is_intra_16x16();
+        let cabac = self.cabac.is_some();
+
+        if start_idx == 0 && intra_16x16 {
+            let total_coeff = self.residual_block(mb, cat_dc, comp, 0, &mut blocks.dc, 0, 15, 16)?;
+            mb.coded_block_flag_dc[comp] = total_coeff > 0;
+        }
+
+        for i8x8 in 0..4 {
+            let coded = (mb.coded_block_pattern_luma & (1 << i8x8)) != 0;
+
+            if mb.transform_size_8x8_flag && cabac {
+                if coded {
+                    let total_coeff = self.residual_block(mb, cat_8x8, comp, i8x8, &mut blocks.level8x8[i8x8],
+                                                          4 * start_idx, 4 * end_idx + 3, 64)?;
+                    for i4x4 in 0..4 {
+                        mb.total_coeff[comp][i8x8 * 4 + i4x4] = total_coeff;
+                    }
+                }
+                continue;
+            }
+
+            for i4x4 in 0..4 {
+                let blk_idx = i8x8 * 4 + i4x4;
+
+                if coded {
+                    let total_coeff = if intra_16x16 {
+                        self.residual_block(mb, cat_ac, comp, blk_idx, &mut blocks.level4x4[blk_idx][1..],
+                                            start_idx.saturating_sub(1), end_idx - 1, 15)?
+                    } else {
+                        self.residual_block(mb, cat_4x4, comp, blk_idx, &mut blocks.level4x4[blk_idx],
+                                            start_idx, end_idx, 16)?
+                    };
+                    mb.total_coeff[comp][blk_idx] = total_coeff;
+                }
+
+                if mb.transform_size_8x8_flag {
+                    for i in 0..16 {
+                        blocks.level8x8[i8x8][4 * i + i4x4] = blocks.level4x4[blk_idx][i];
+                    }
+                }
+            }
+        }
+
+        Ok(blocks)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::rbsp::{ ParameterSets, SliceType };
+    use crate::slice::Slice;
+    use crate::slice::test::Writer;
+    use crate::macroblock::{ MbType, SubMbType, PredMode, IntraPredMode };
+
+    // 48x16 的 High profile 码流 ( 4:2:0, 8 bit, direct_8x8_inference_flag 1, transform_8x8_mode_flag 1 ), 共 3 个宏块
+    fn high_profile_parameter_sets() -> ParameterSets {
+        let mut parameter_sets = ParameterSets::new();
+        let sps = Writer::new(0x67)
+            .u(8, 100).u(8, 0).u(8, 40).ue(0)
+            .ue(1).ue(0).ue(0).u(1, 0).u(1, 0)
+            .ue(0).ue(0).ue(0)
+            .ue(2).u(1, 0).ue(2).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        let pps = Writer::new(0x68)
+            .ue(0).ue(0).u(1, 0).u(1, 0).ue(0)
+            .ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0)
+            .u(1, 1).u(1, 0).se(0)
+            .finish();
+        assert!(parameter_sets.update(&sps).unwrap());
+        assert!(parameter_sets.update(&pps).unwrap());
+
+        let pps = parameter_sets.pps(0).unwrap();
+        assert!(pps.transform_8x8_mode_flag());
+
+        parameter_sets
+    }
+
+    // IDR slice: I_NxN ( transform_size_8x8_flag 1 ) + I_PCM, pcm_alignment_zero_bit 为 padding
+    fn intra_slice(padding: &str) -> Writer {
+        let mut writer = Writer::new(0x65);
+        writer.ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        // I_NxN, transform_size_8x8_flag, prev_intra8x8_pred_mode_flag / rem_intra8x8_pred_mode,
+        // intra_chroma_pred_mode, coded_block_pattern ( codeNum 1 -> Intra 31 ), mb_qp_delta
+        writer.ue(0).u(1, 1).bits("1").bits("0101").bits("1").bits("0111").ue(2).ue(1).se(-4);
+        // 16 个 4x4 块 ( 8x8 块按 4x4 交织解析 ): 第一个块为 TrailingOnes 1, TotalCoeff 1, 其余 nC < 2, TotalCoeff 为 0
+        writer.bits("0101");
+        for _ in 1..16 {
+            writer.bits("1");
+        }
+        // Cb / Cr 的 ChromaDCLevel ( nC = -1 ): TotalCoeff 1 ( 负 ) 以及 TotalCoeff 0
+        writer.bits("111").bits("01");
+        // I_PCM
+        writer.ue(25);
+        assert!(!writer.byte_aligned());
+        writer.bits(padding).align();
+        for i in 0..256 {
+            writer.u(8, i);
+        }
+        for i in 0..128 {
+            writer.u(8, 255 - i);
+        }
+        writer
+    }
+
+    #[test]
+    fn test_intra_macroblocks() {
+        let parameter_sets = high_profile_parameter_sets();
+        let slice = Slice::parse(&intra_slice("").finish(), &parameter_sets).unwrap();
+        assert_eq!(slice.header.slice_type, SliceType::I);
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 2);
+
+        let mb = &macroblocks[0];
+        assert_eq!(mb.mb_type, MbType::INxN);
+        assert!(mb.transform_size_8x8_flag);
+        assert_eq!(mb.intra_pred_mode(), Some(IntraPredMode::Intra8x8));
+        assert_eq!(mb.prev_intra_pred_mode_flag[..4], [true, false, true, false]);
+        assert_eq!(mb.rem_intra_pred_mode[..4], [0, 5, 0, 7]);
+        assert_eq!(mb.intra_chroma_pred_mode, 2);
+        assert_eq!(mb.coded_block_pattern_luma, 15);
+        assert_eq!(mb.coded_block_pattern_chroma, 1);
+        assert_eq!(mb.mb_qp_delta, -4);
+        assert_eq!(mb.total_coeff[0][..4], [1, 0, 0, 0]);
+        assert_eq!(mb.coded_block_flag_dc[1..], [true, false]);
+
+        let residual = mb.residual.as_ref().unwrap();
+        assert_eq!(residual.luma.level4x4[0][0], 1);
+        assert_eq!(residual.luma.level8x8[0][0], 1);
+        assert_eq!(residual.luma.level8x8[0][1..].iter().filter(|&&level| level != 0).count(), 0);
+        assert_eq!(residual.chroma_dc[0][0], -1);
+        assert_eq!(residual.chroma_dc[1], [0; 8]);
+
+        let mb = &macroblocks[1];
+        assert_eq!(mb.mb_type, MbType::IPcm);
+        let pcm = mb.pcm_samples.as_ref().unwrap();
+        assert_eq!(pcm.luma.len(), 256);
+        assert_eq!((pcm.luma[0], pcm.luma[255]), (0, 255));
+        assert_eq!((pcm.chroma[0], pcm.chroma[127]), (255, 128));
+        assert_eq!(mb.total_coeff, [[16; 16]; 3]);
+    }
+
+    #[test]
+    fn test_pcm_alignment_zero_bit() {
+        let parameter_sets = high_profile_parameter_sets();
+        assert!(Slice::parse(&intra_slice("0").finish(), &parameter_sets).is_ok());
+        assert!(Slice::parse(&intra_slice("1").finish(), &parameter_sets).is_err());
+    }
+
+    #[test]
+    fn test_predicted_macroblocks() {
+        let parameter_sets = high_profile_parameter_sets();
+
+        // num_ref_idx_active_override_flag: num_ref_idx_l0_active_minus1 为 1, ref_idx_l0 为 te(v) 的 1 个比特
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 1).ue(1)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        // mb_skip_run, P_8x8 ( P_L0_8x8, P_L0_8x4, P_L0_4x8, P_L0_4x4 ), ref_idx_l0 ( 0, 1, 1, 0 )
+        writer.ue(1).ue(3).ue(0).ue(1).ue(2).ue(3).bits("1001");
+        writer.se(1).se(0);
+        writer.se(-1).se(2).se(0).se(0);
+        writer.se(3).se(0).se(0).se(-3);
+        writer.se(1).se(1).se(2).se(2).se(3).se(3).se(4).se(4);
+        // coded_block_pattern ( codeNum 2 -> Inter 1 ), 子宏块分区小于 8x8, 所以没有 transform_size_8x8_flag
+        writer.ue(2).se(2);
+        writer.bits("0101").bits("1").bits("1").bits("1");
+        // mb_skip_run, P_8x8ref0 ( 4 个 P_L0_8x8, 没有 ref_idx_l0 ), coded_block_pattern ( codeNum 3 -> Inter 2 ),
+        // transform_size_8x8_flag, mb_qp_delta
+        writer.ue(0).ue(4).ue(0).ue(0).ue(0).ue(0);
+        writer.se(0).se(0).se(0).se(0).se(-4).se(0).se(0).se(0);
+        writer.ue(3).u(1, 1).se(0);
+        // 8x8 块 1 中的 4 个 4x4 块: +1, -1, 0, 0
+        writer.bits("0101").bits("0111").bits("1").bits("1");
+        let slice = Slice::parse(&writer.finish(), &parameter_sets).unwrap();
+        assert_eq!(slice.header.num_ref_idx_l0_active_minus1, 1);
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 3);
+        assert_eq!(macroblocks[0].mb_type, MbType::PSkip);
+
+        let mb = &macroblocks[1];
+        assert_eq!(mb.mb_type, MbType::P8x8);
+        assert_eq!(mb.sub_mb_type, Some([SubMbType::P8x8, SubMbType::P8x4, SubMbType::P4x8, SubMbType::P4x4]));
+        assert_eq!(mb.ref_idx, [[0, 1, 1, 0], [-1; 4]]);
+        assert_eq!(mb.mvd[0][0][0], [1, 0]);
+        assert_eq!(mb.mvd[0][1][..2], [[-1, 2], [0, 0]]);
+        assert_eq!(mb.mvd[0][2][..2], [[3, 0], [0, -3]]);
+        assert_eq!(mb.mvd[0][3], [[1, 1], [2, 2], [3, 3], [4, 4]]);
+        assert!(!mb.transform_size_8x8_flag);
+        assert_eq!((mb.coded_block_pattern_luma, mb.coded_block_pattern_chroma), (1, 0));
+        assert_eq!(mb.mb_qp_delta, 2);
+        assert_eq!(mb.total_coeff[0][..4], [1, 0, 0, 0]);
+        assert_eq!(mb.residual.as_ref().unwrap().luma.level4x4[0][0], 1);
+
+        let mb = &macroblocks[2];
+        assert_eq!(mb.mb_type, MbType::P8x8Ref0);
+        assert_eq!(mb.ref_idx[0], [0; 4]);
+        assert_eq!(mb.mvd[0][2][0], [-4, 0]);
+        assert!(mb.transform_size_8x8_flag);
+        assert_eq!((mb.coded_block_pattern_luma, mb.coded_block_pattern_chroma), (2, 0));
+        assert_eq!(mb.total_coeff[0][4..8], [1, 1, 0, 0]);
+
+        let residual = mb.residual.as_ref().unwrap();
+        assert_eq!(residual.luma.level8x8[1][..4], [1, -1, 0, 0]);
+        assert_eq!(residual.luma.level8x8[0], [0; 64]);
+    }
+
+    #[test]
+    fn test_bipredictive_macroblocks() {
+        let parameter_sets = high_profile_parameter_sets();
+
+        // 非参考 B slice, direct_spatial_mv_pred_flag 1
+        let mut writer = Writer::new(0x01);
+        writer.ue(0).ue(6).ue(0).u(4, 2).u(4, 4)
+            .u(1, 1).u(1, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        // mb_skip_run, B_8x8 ( B_Direct_8x8, B_Bi_8x8, B_L0_8x4, B_L1_4x4 )
+        writer.ue(1).ue(22).ue(0).ue(3).ue(4).ue(11);
+        writer.se(1).se(2).se(3).se(4).se(5).se(6);
+        writer.se(-1).se(-2).se(7).se(0).se(0).se(7).se(-7).se(0).se(0).se(-7);
+        // coded_block_pattern ( codeNum 0 -> Inter 0 )
+        writer.ue(0);
+        // mb_skip_run, B_L0_L1_16x8: mvd_l0 ( 分区 0 ), mvd_l1 ( 分区 1 ), coded_block_pattern ( codeNum 2 -> Inter 1 ),
+        // transform_size_8x8_flag 0, mb_qp_delta, 4 个 TotalCoeff 为 0 的 4x4 块
+        writer.ue(0).ue(8).se(2).se(-2).se(-3).se(3);
+        writer.ue(2).u(1, 0).se(1).bits("1111");
+        let slice = Slice::parse(&writer.finish(), &parameter_sets).unwrap();
+        assert_eq!(slice.header.slice_type, SliceType::B);
+        assert_eq!(slice.header.direct_spatial_mv_pred_flag, Some(true));
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 3);
+        assert_eq!(macroblocks[0].mb_type, MbType::BSkip);
+        assert_eq!(macroblocks[0].mb_type.mb_part_pred_mode(0), Some(PredMode::Direct));
+
+        let mb = &macroblocks[1];
+        assert_eq!(mb.mb_type, MbType::B8x8);
+        assert_eq!(mb.sub_mb_type, Some([
+            SubMbType::BDirect8x8,
+            SubMbType::B8x8(PredMode::BiPred),
+            SubMbType::B8x4(PredMode::L0),
+            SubMbType::B4x4(PredMode::L1),
+        ]));
+        assert_eq!(mb.ref_idx, [[-1, 0, 0, -1], [-1, 0, -1, 0]]);
+        assert_eq!(mb.mvd[0][1][0], [1, 2]);
+        assert_eq!(mb.mvd[0][2][..2], [[3, 4], [5, 6]]);
+        assert_eq!(mb.mvd[1][1][0], [-1, -2]);
+        assert_eq!(mb.mvd[1][3], [[7, 0], [0, 7], [-7, 0], [0, -7]]);
+        assert_eq!(mb.mvd[0][0], [[0, 0]; 4]);
+        assert!(mb.residual.is_none());
+
+        let mb = &macroblocks[2];
+        assert_eq!(mb.mb_type, MbType::B16x8(PredMode::L0, PredMode::L1));
+        assert_eq!(mb.ref_idx, [[0, -1, -1, -1], [-1, 0, -1, -1]]);
+        assert_eq!(mb.mvd[0][0][0], [2, -2]);
+        assert_eq!(mb.mvd[1][1][0], [-3, 3]);
+        assert!(!mb.transform_size_8x8_flag);
+        assert_eq!(mb.coded_block_pattern_luma, 1);
+        assert_eq!(mb.mb_qp_delta, 1);
+    }
+}
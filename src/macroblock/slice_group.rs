@@ -0,0 +1,287 @@
+// 8.2.2 Decoding process for macroblock to slice group map ( Page 100 )
+
+use crate::error::{ self, Error };
+use crate::rbsp::{ SequenceParameterSet, PictureParameterSet, SliceHeader };
+
+
+// mbToSliceGroupMap: 每个宏块所属的 slice group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceGroupMap {
+    mb_to_slice_group_map: Vec<u8>,
+}
+
+impl SliceGroupMap {
+    pub fn new(header: &SliceHeader, sps: &SequenceParameterSet, pps: &PictureParameterSet) -> Result<Self, Error> {
+        let map_units = map_unit_to_slice_group_map(sps, pps, header.slice_group_change_cycle.unwrap_or(0))?;
+
+        // 8.2.2.8 Specification for conversion of map unit to slice group map to macroblock to slice group map
+        let pic_width_in_mbs = sps.pic_width_in_mbs() as usize;
+        let pic_size_in_mbs = header.pic_size_in_mbs(sps) as usize;
+        let mb_to_slice_group_map = (0..pic_size_in_mbs)
+            .map(|i| {
+                if sps.frame_mbs_only_flag() || header.field_pic_flag {
+                    map_units[i]
+                } else if header.mbaff_frame_flag(sps) {
+                    map_units[i / 2]
+                } else {
+                    map_units[(i / (2 * pic_width_in_mbs)) * pic_width_in_mbs + (i % pic_width_in_mbs)]
+                }
+            })
+            .collect();
+
+        Ok(SliceGroupMap {
+            mb_to_slice_group_map: mb_to_slice_group_map,
+        })
+    }
+
+    pub fn slice_group(&self, mb_addr: u32) -> u8 {
+        self.mb_to_slice_group_map[mb_addr as usize]
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.mb_to_slice_group_map
+    }
+
+    // 8.2.2: NextMbAddress( n ), 同一 slice group 中的下一个宏块; 没有时返回 PicSizeInMbs
+    pub fn next_mb_address(&self, n: u32) -> u32 {
+        let slice_group = self.mb_to_slice_group_map[n as usize];
+        let mut i = n as usize + 1;
+        while i < self.mb_to_slice_group_map.len() && self.mb_to_slice_group_map[i] != slice_group {
+            i += 1;
+        }
+
+        i as u32
+    }
+}
+
+
+// mapUnitToSliceGroupMap ( 8.2.2.1 - 8.2.2.7 )
+pub fn map_unit_to_slice_group_map(sps: &SequenceParameterSet,
+                                   pps: &PictureParameterSet,
+                                   slice_group_change_cycle: u32) -> Result<Vec<u8>, Error> {
+    let pic_size_in_map_units = sps.pic_size_in_map_units() as usize;
+    let num_slice_groups_minus1 = pps.num_slice_groups_minus1();
+
+    if num_slice_groups_minus1 == 0 {
+        return Ok(vec![0; pic_size_in_map_units]);
+    }
+
+    let width = sps.pic_width_in_mbs() as i64;
+    let height = sps.pic_height_in_map_units() as i64;
+    let num_slice_groups = num_slice_groups_minus1 as usize + 1;
+
+    // MapUnitsInSliceGroup0 ( 7-34 )
+    let map_units_in_slice_group0 = (slice_group_change_cycle as usize * pps.slice_group_change_rate() as usize)
+        .min(pic_size_in_map_units);
+    let direction = pps.slice_group_change_direction_flag() as u8;
+    let size_of_upper_left_group = if direction == 1 {
+        pic_size_in_map_units - map_units_in_slice_group0
+    } else {
+        map_units_in_slice_group0
+    };
+
+    let mut map = vec![0u8; pic_size_in_map_units];
+
+    match pps.slice_group_map_type() {
+        // 8.2.2.1 Specification for interleaved slice group map type
+        Some(0) => {
+            let run_length_minus1 = pps.run_length_minus1();
+            if run_length_minus1.len() != num_slice_groups {
+                return Err(error::malformed("run_length_minus1 missing"));
+            }
+
+            let mut i = 0;
+            while i < pic_size_in_map_units {
+                let mut i_group = 0;
+                while i_group < num_slice_groups && i < pic_size_in_map_units {
+                    let mut j = 0;
+                    while j <= run_length_minus1[i_group] as usize && i + j < pic_size_in_map_units {
+                        map[i + j] = i_group as u8;
+                        j += 1;
+                    }
+                    i += run_length_minus1[i_group] as usize + 1;
+                    i_group += 1;
+                }
+            }
+        },
+        // 8.2.2.2 Specification for dispersed slice group map type
+        Some(1) => {
+            let width = width as usize;
+            for (i, slice_group) in map.iter_mut().enumerate() {
+                *slice_group = (((i % width) + (((i / width) * num_slice_groups) / 2)) % num_slice_groups) as u8;
+            }
+        },
+        // 8.2.2.3 Specification for foreground with left-over slice group map type
+        Some(2) => {
+            let (top_left, bottom_right) = (pps.top_left(), pps.bottom_right());
+            if top_left.len() != num_slice_groups_minus1 as usize || bottom_right.len() != num_slice_groups_minus1 as usize {
+                return Err(error::malformed("top_left or bottom_right missing"));
+            }
+
+            for slice_group in map.iter_mut() {
+                *slice_group = num_slice_groups_minus1 as u8;
+            }
+
+            let width = width as usize;
+            for i_group in (0..num_slice_groups_minus1 as usize).rev() {
+                let (y_top_left, x_top_left) = (top_left[i_group] as usize / width, top_left[i_group] as usize % width);
+                let (y_bottom_right, x_bottom_right) = (bottom_right[i_group] as usize / width, bottom_right[i_group] as usize % width);
+                if bottom_right[i_group] as usize >= pic_size_in_map_units || x_top_left > x_bottom_right || y_top_left > y_bottom_right {
+                    return Err(error::malformed("top_left and bottom_right do not form a rectangle"));
+                }
+
+                for y in y_top_left..=y_bottom_right {
+                    for x in x_top_left..=x_bottom_right {
+                        map[y * width + x] = i_group as u8;
+                    }
+                }
+            }
+        },
+        // 8.2.2.4 Specification for box-out slice group map types
+        Some(3) => {
+            for slice_group in map.iter_mut() {
+                *slice_group = 1;
+            }
+
+            let direction = direction as i64;
+            let mut x = (width - direction) / 2;
+            let mut y = (height - direction) / 2;
+            let (mut left_bound, mut top_bound) = (x, y);
+            let (mut right_bound, mut bottom_bound) = (x, y);
+            let (mut x_dir, mut y_dir) = (direction - 1, direction);
+
+            let mut k = 0;
+            while k < map_units_in_slice_group0 {
+                let index = (y * width + x) as usize;
+                let map_unit_vacant = map[index] == 1;
+                if map_unit_vacant {
+                    map[index] = 0;
+                }
+
+                if x_dir == -1 && x == left_bound {
+                    left_bound = (left_bound - 1).max(0);
+                    x = left_bound;
+                    x_dir = 0;
+                    y_dir = 2 * direction - 1;
+                } else if x_dir == 1 && x == right_bound {
+                    right_bound = (right_bound + 1).min(width - 1);
+                    x = right_bound;
+                    x_dir = 0;
+                    y_dir = 1 - 2 * direction;
+                } else if y_dir == -1 && y == top_bound {
+                    top_bound = (top_bound - 1).max(0);
+                    y = top_bound;
+                    x_dir = 1 - 2 * direction;
+                    y_dir = 0;
+                } else if y_dir == 1 && y == bottom_bound {
+                    bottom_bound = (bottom_bound + 1).min(height - 1);
+                    y = bottom_bound;
+                    x_dir = 2 * direction - 1;
+                    y_dir = 0;
+                } else {
+                    x += x_dir;
+                    y += y_dir;
+                }
+
+                k += map_unit_vacant as usize;
+            }
+        },
+        // 8.2.2.5 Specification for raster scan slice group map types
+        Some(4) => {
+            for (i, slice_group) in map.iter_mut().enumerate() {
+                *slice_group = if i < size_of_upper_left_group { direction } else { 1 - direction };
+            }
+        },
+        // 8.2.2.6 Specification for wipe slice group map types
+        Some(5) => {
+            let mut k = 0;
+            for j in 0..width as usize {
+                for i in 0..height as usize {
+                    map[i * width as usize + j] = if k < size_of_upper_left_group { direction } else { 1 - direction };
+                    k += 1;
+                }
+            }
+        },
+        // 8.2.2.7 Specification for explicit slice group map type
+        Some(6) => {
+            let slice_group_id = pps.slice_group_id();
+            if slice_group_id.len() != pic_size_in_map_units {
+                return Err(error::malformed("slice_group_id does not cover the picture"));
+            }
+
+            for (slice_group, &id) in map.iter_mut().zip(slice_group_id.iter()) {
+                *slice_group = id as u8;
+            }
+        },
+        _ => return Err(error::malformed("slice_group_map_type out of range")),
+    }
+
+    Ok(map)
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::slice::test::Writer;
+    use crate::rbsp::{ ParameterSets, PictureParameterSet };
+    use super::map_unit_to_slice_group_map;
+
+    // 4x3 宏块的 SPS ( pic_width_in_mbs_minus1 3, pic_height_in_map_units_minus1 2 )
+    fn parameter_sets(slice_groups: &dyn Fn(&mut Writer)) -> ParameterSets {
+        let mut parameter_sets = ParameterSets::new();
+
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(3).ue(2)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        assert!(parameter_sets.update(&sps).unwrap());
+
+        let mut pps = Writer::new(0x68);
+        pps.ue(0).ue(0).u(1, 0).u(1, 0);
+        slice_groups(&mut pps);
+        pps.ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0);
+        assert!(parameter_sets.update(&pps.finish()).unwrap());
+
+        parameter_sets
+    }
+
+    fn map(slice_groups: &dyn Fn(&mut Writer), slice_group_change_cycle: u32) -> Vec<u8> {
+        let parameter_sets = parameter_sets(slice_groups);
+        let pps: &PictureParameterSet = parameter_sets.pps(0).unwrap();
+        let sps = parameter_sets.sps(0).unwrap();
+        map_unit_to_slice_group_map(sps, pps, slice_group_change_cycle).unwrap()
+    }
+
+    #[test]
+    fn test_slice_group_map_types() {
+        // 交错: run_length 3, 5
+        assert_eq!(map(&|w| { w.ue(1).ue(0).ue(2).ue(4); }, 0),
+                   vec![0, 0, 0, 1, 1, 1, 1, 1, 0, 0, 0, 1]);
+        // 分散: 3 个 slice group
+        assert_eq!(map(&|w| { w.ue(2).ue(1); }, 0),
+                   vec![0, 1, 2, 0, 1, 2, 0, 1, 0, 1, 2, 0]);
+        // 前景: 宏块 5 .. 6 为 slice group 0, 其余为 1
+        assert_eq!(map(&|w| { w.ue(1).ue(2).ue(5).ue(6); }, 0),
+                   vec![1, 1, 1, 1, 1, 0, 0, 1, 1, 1, 1, 1]);
+        // box-out ( 顺时针 ), 3 个 map unit: 从 ( 2, 1 ) 开始向左, 然后向上
+        assert_eq!(map(&|w| { w.ue(1).ue(3).u(1, 0).ue(0); }, 3),
+                   vec![1, 0, 1, 1, 1, 0, 0, 1, 1, 1, 1, 1]);
+        // 光栅扫描, slice_group_change_direction_flag 1: 前 12 - 5 个 map unit 为 slice group 1
+        assert_eq!(map(&|w| { w.ue(1).ue(4).u(1, 1).ue(0); }, 5),
+                   vec![1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0]);
+        // 擦除: 按列扫描的前 5 个 map unit 为 slice group 0
+        assert_eq!(map(&|w| { w.ue(1).ue(5).u(1, 0).ue(0); }, 5),
+                   vec![0, 0, 1, 1, 0, 0, 1, 1, 0, 1, 1, 1]);
+        // 显式
+        assert_eq!(map(&|w| {
+            w.ue(1).ue(6).ue(11);
+            for i in 0..12 {
+                w.u(1, (i % 3 == 0) as u32);
+            }
+        }, 0), vec![1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0]);
+    }
+}
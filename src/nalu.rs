@@ -246,6 +246,10 @@ impl Nalu {
         }
     }
 
+    pub fn header(&self) -> NaluHeader {
+        self.header
+    }
+
     pub fn ref_idc(&self) -> NaluRefIdc {
         self.header.nal_ref_idc
     }
@@ -254,8 +258,8 @@ impl Nalu {
         self.header.nal_unit_type
     }
 
-    pub fn payload(&self) -> &Box<dyn RawByteSequencePayload> {
-        &self.payload
+    pub fn payload(&self) -> &dyn RawByteSequencePayload {
+        self.payload.as_ref()
     }
 
     pub fn payload_mut(&mut self) -> &mut Box<dyn RawByteSequencePayload> {
@@ -282,11 +286,23 @@ impl TryFrom<&[u8]> for Nalu {
         let header = NaluHeader::try_from(value[0])?;
         
         debug!("parse nal unit payload ...");
-        let payload = rbsp::DebugRbSp::try_from(&value[1..])?;
+        let payload: Box<dyn RawByteSequencePayload> = match header.nal_unit_type() {
+            NaluKind::SequenceParameterSet => {
+                let bytes = rbsp::ebsp_to_rbsp(&value[1..]);
+                let mut reader = rbsp::RbspReader::new(&bytes);
+                Box::new(rbsp::SequenceParameterSet::parse(&mut reader)?)
+            },
+            NaluKind::SupplementalEnhancementInformation => {
+                let bytes = rbsp::ebsp_to_rbsp(&value[1..]);
+                let mut reader = rbsp::RbspReader::new(&bytes);
+                Box::new(rbsp::SupplementalEnhancementInformation::parse(&mut reader)?)
+            },
+            _ => Box::new(rbsp::DebugRbSp::try_from(&value[1..])?),
+        };
 
         Ok(Nalu {
             header: header,
-            payload: Box::new(payload),
+            payload: payload,
         })
     }
 }
@@ -0,0 +1,388 @@
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ self, DebugRbSp, RbspReader, ParameterSets, SliceHeader };
+use crate::macroblock::SliceData;
+use crate::slice::Slice;
+
+use std::collections::VecDeque;
+
+
+// 7.3.2.9.1 Slice data partition A RBSP syntax ( Page 70 )
+#[derive(Debug, Clone)]
+pub struct PartitionA {
+    pub header: SliceHeader,
+    pub slice_id: u32,                  // ue(v)
+    // RBSP 以及其中 slice_data() ( 类别 2 ) 开始的比特位置
+    rbsp: Vec<u8>,
+    data_position: usize,
+}
+
+impl PartitionA {
+    pub fn parse(nalu: &Nalu, parameter_sets: &ParameterSets) -> Result<Self, Error> {
+        if nalu.kind() != NaluKind::CodedSliceDataPartitionA {
+            return Err(error::unsupported("not a slice data partition A"));
+        }
+
+        let rbsp = rbsp_bytes(nalu)?;
+        let mut reader = RbspReader::new(&rbsp);
+        let header = SliceHeader::parse(&mut reader, nalu.header(), parameter_sets)?;
+        let slice_id = reader.read_ue()?;
+        let data_position = reader.position();
+
+        Ok(PartitionA {
+            header: header,
+            slice_id: slice_id,
+            rbsp: rbsp,
+            data_position: data_position,
+        })
+    }
+}
+
+// 7.3.2.9.2 Slice data partition B RBSP syntax ( Page 71 )
+// 7.3.2.9.3 Slice data partition C RBSP syntax ( Page 71 )
+#[derive(Debug, Clone)]
+pub struct ResidualPartition {
+    pub nal_unit_type: NaluKind,
+    pub slice_id: u32,                  // ue(v)
+    pub colour_plane_id: Option<u8>,    // u(2)
+    pub redundant_pic_cnt: Option<u32>, // ue(v)
+    // RBSP 以及其中 slice_data() ( 类别 3 或 4 ) 开始的比特位置
+    rbsp: Vec<u8>,
+    data_position: usize,
+}
+
+impl ResidualPartition {
+    // 只解析 slice_id: colour_plane_id 与 redundant_pic_cnt 是否出现取决于 partition A 引用的参数集
+    pub fn parse_slice_id(nalu: &Nalu) -> Result<u32, Error> {
+        check_residual_partition(nalu)?;
+
+        let rbsp = rbsp_bytes(nalu)?;
+        RbspReader::new(&rbsp).read_ue()
+    }
+
+    pub fn parse(nalu: &Nalu, partition_a: &PartitionA, parameter_sets: &ParameterSets) -> Result<Self, Error> {
+        check_residual_partition(nalu)?;
+
+        let pps = parameter_sets.pps(partition_a.header.pic_parameter_set_id)
+            .ok_or_else(|| error::malformed("slice refers to an unknown pps"))?;
+        let sps = parameter_sets.sps(pps.seq_parameter_set_id())
+            .ok_or_else(|| error::malformed("pps refers to an unknown sps"))?;
+
+        let rbsp = rbsp_bytes(nalu)?;
+        let mut reader = RbspReader::new(&rbsp);
+        let slice_id = reader.read_ue()?;
+        let colour_plane_id = if sps.separate_colour_plane_flag() {
+            Some(reader.read_bits(2)? as u8)
+        } else {
+            None
+        };
+        let redundant_pic_cnt = if pps.redundant_pic_cnt_present_flag() {
+            Some(reader.read_ue()?)
+        } else {
+            None
+        };
+        let data_position = reader.position();
+
+        Ok(ResidualPartition {
+            nal_unit_type: nalu.kind(),
+            slice_id: slice_id,
+            colour_plane_id: colour_plane_id,
+            redundant_pic_cnt: redundant_pic_cnt,
+            rbsp: rbsp,
+            data_position: data_position,
+        })
+    }
+
+    // 7.4.2.9: 与 partition A 的 slice_id, colour_plane_id 以及 redundant_pic_cnt 相同
+    pub fn belongs_to(&self, partition_a: &PartitionA) -> bool {
+        self.slice_id == partition_a.slice_id
+            && self.colour_plane_id == partition_a.header.colour_plane_id
+            && self.redundant_pic_cnt == partition_a.header.redundant_pic_cnt
+    }
+}
+
+fn check_residual_partition(nalu: &Nalu) -> Result<(), Error> {
+    match nalu.kind() {
+        NaluKind::CodedSliceDataPartitionB | NaluKind::CodedSliceDataPartitionC => Ok(()),
+        _ => Err(error::unsupported("not a slice data partition B or C")),
+    }
+}
+
+fn rbsp_bytes(nalu: &Nalu) -> Result<Vec<u8>, Error> {
+    match nalu.payload().as_any().downcast_ref::<DebugRbSp>() {
+        Some(payload) => Ok(rbsp::ebsp_to_rbsp(payload.as_bytes())),
+        None => Err(error::malformed("slice data partition payload expected")),
+    }
+}
+
+fn data_reader(rbsp: &[u8], data_position: usize) -> Result<RbspReader<'_>, Error> {
+    let mut reader = RbspReader::new(rbsp);
+    reader.skip_bits(data_position)?;
+    Ok(reader)
+}
+
+
+// 一个 slice 的 partition A 以及与之对应的 partition B, C ( 可能缺失 )
+#[derive(Debug, Clone)]
+pub struct PartitionedSlice {
+    pub a: PartitionA,
+    pub b: Option<ResidualPartition>,
+    pub c: Option<ResidualPartition>,
+}
+
+impl PartitionedSlice {
+    pub fn new(a: PartitionA) -> Self {
+        PartitionedSlice { a: a, b: None, c: None }
+    }
+
+    // 合并三个分区的 slice_data(); 同时返回解析时需要但没有收到的分区
+    pub fn parse(&self, parameter_sets: &ParameterSets) -> Result<(Slice, Vec<NaluKind>), Error> {
+        let header = &self.a.header;
+        let pps = parameter_sets.pps(header.pic_parameter_set_id).expect("pps has been checked by the slice header");
+        let sps = parameter_sets.sps(pps.seq_parameter_set_id()).expect("sps has been checked by the slice header");
+
+        let mut reader = data_reader(&self.a.rbsp, self.a.data_position)?;
+        let mut intra = match self.b {
+            Some(ref b) => Some(data_reader(&b.rbsp, b.data_position)?),
+            None => None,
+        };
+        let mut inter = match self.c {
+            Some(ref c) => Some(data_reader(&c.rbsp, c.data_position)?),
+            None => None,
+        };
+
+        let (data, missing_partitions) = SliceData::parse_partitioned(
+            &mut reader, intra.as_mut(), inter.as_mut(), header, sps, pps)?;
+
+        let mut missing = vec![];
+        if missing_partitions[0] {
+            missing.push(NaluKind::CodedSliceDataPartitionB);
+        }
+        if missing_partitions[1] {
+            missing.push(NaluKind::CodedSliceDataPartitionC);
+        }
+
+        let slice = Slice {
+            header: header.clone(),
+            data: data,
+        };
+
+        Ok((slice, missing))
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub enum AssembledPartition {
+    // missing: 解析 slice_data() 时需要但没有收到的 partition B 或 C
+    Slice { slice_id: u32, slice: Box<Slice>, missing: Vec<NaluKind> },
+    // 没有对应的 partition A 的 partition B 或 C
+    Orphan { nal_unit_type: NaluKind, slice_id: u32 },
+}
+
+// 按解码顺序输入 NALU, 把 partition B, C 与之前的 partition A 配对 ( 7.4.1.2.5 )
+#[derive(Debug, Default)]
+pub struct PartitionAssembler {
+    parameter_sets: ParameterSets,
+    current: Option<PartitionedSlice>,
+    assembled: VecDeque<AssembledPartition>,
+}
+
+impl PartitionAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parameter_sets(&self) -> &ParameterSets {
+        &self.parameter_sets
+    }
+
+    // 处理一个 NALU; 其它类型的 NALU 会结束当前的 slice
+    pub fn push(&mut self, nalu: &Nalu) -> Result<(), Error> {
+        // 在更新参数集之前完成当前的 slice: 下一个访问单元的 SPS / PPS 不能用于解析它
+        match nalu.kind() {
+            NaluKind::CodedSliceDataPartitionB | NaluKind::CodedSliceDataPartitionC => { },
+            _ => {
+                let previous = self.current.take();
+                self.finish_slice(previous)?;
+            },
+        }
+
+        if self.parameter_sets.update(nalu)? {
+            return Ok(());
+        }
+
+        match nalu.kind() {
+            NaluKind::CodedSliceDataPartitionA => {
+                let a = PartitionA::parse(nalu, &self.parameter_sets)?;
+                self.current = Some(PartitionedSlice::new(a));
+                Ok(())
+            },
+            NaluKind::CodedSliceDataPartitionB | NaluKind::CodedSliceDataPartitionC => {
+                let kind = nalu.kind();
+                let slice_id = ResidualPartition::parse_slice_id(nalu)?;
+
+                if let Some(ref mut current) = self.current {
+                    let partition = ResidualPartition::parse(nalu, &current.a, &self.parameter_sets)?;
+                    // partition B 必须在 partition C 之前
+                    let slot = match kind {
+                        NaluKind::CodedSliceDataPartitionB if current.c.is_none() => Some(&mut current.b),
+                        NaluKind::CodedSliceDataPartitionC => Some(&mut current.c),
+                        _ => None,
+                    };
+
+                    if let Some(slot) = slot {
+                        if slot.is_none() && partition.belongs_to(&current.a) {
+                            *slot = Some(partition);
+                            return Ok(());
+                        }
+                    }
+                }
+
+                self.assembled.push_back(AssembledPartition::Orphan { nal_unit_type: kind, slice_id: slice_id });
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // 码流结束: 完成当前的 slice
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let previous = self.current.take();
+        self.finish_slice(previous)
+    }
+
+    // 取出下一个 ( 按解码顺序 ) 完成的 slice 或孤立的分区
+    pub fn next_assembled(&mut self) -> Option<AssembledPartition> {
+        self.assembled.pop_front()
+    }
+
+    fn finish_slice(&mut self, partitioned: Option<PartitionedSlice>) -> Result<(), Error> {
+        let partitioned = match partitioned {
+            Some(partitioned) => partitioned,
+            None => return Ok(()),
+        };
+
+        let (slice, missing) = partitioned.parse(&self.parameter_sets)?;
+        self.assembled.push_back(AssembledPartition::Slice {
+            slice_id: partitioned.a.slice_id,
+            slice: Box::new(slice),
+            missing: missing,
+        });
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::nalu::{ Nalu, NaluKind };
+    use crate::macroblock::MbType;
+    use crate::slice::test::{ Writer, parameter_sets };
+    use super::{ PartitionAssembler, AssembledPartition };
+
+    // P slice ( slice_id 3 ) 的 partition A: P_Skip + P_L0_16x16
+    fn partition_a() -> Nalu {
+        Writer::new(0x42)
+            .ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1)
+            .ue(3)
+            .ue(1).ue(0).se(1).se(-2).ue(2).se(0)
+            .finish()
+    }
+
+    // 帧间宏块的残差: luma4x4BlkIdx 0 .. 3
+    fn partition_c(slice_id: u32) -> Nalu {
+        Writer::new(0x04)
+            .ue(slice_id)
+            .bits("000010001110010111101101").bits("1111").bits("11").bits("1")
+            .finish()
+    }
+
+    fn assemble(nalus: &[Nalu]) -> Vec<AssembledPartition> {
+        let mut assembler = PartitionAssembler::new();
+        assembler.parameter_sets = parameter_sets();
+        for nalu in nalus {
+            assembler.push(nalu).unwrap();
+        }
+        assembler.flush().unwrap();
+
+        std::iter::from_fn(|| assembler.next_assembled()).collect()
+    }
+
+    #[test]
+    fn test_reassemble_partitions() {
+        let assembled = assemble(&[partition_a(), partition_c(3)]);
+        assert_eq!(assembled.len(), 1);
+
+        let (slice_id, slice, missing) = match assembled[0] {
+            AssembledPartition::Slice { slice_id, ref slice, ref missing } => (slice_id, slice, missing),
+            _ => panic!("slice expected"),
+        };
+        assert_eq!(slice_id, 3);
+        assert!(missing.is_empty());
+        assert_eq!(slice.header.nal_unit_type, NaluKind::CodedSliceDataPartitionA);
+        assert_eq!(slice.header.frame_num, 1);
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 2);
+        assert_eq!(macroblocks[0].mb_type, MbType::PSkip);
+        assert_eq!(macroblocks[1].mb_type, MbType::P16x16);
+        assert_eq!(macroblocks[1].mvd[0][0][0], [1, -2]);
+        assert_eq!(macroblocks[1].total_coeff[0][..4], [5, 0, 0, 0]);
+
+        let residual = macroblocks[1].residual.as_ref().unwrap();
+        assert_eq!(residual.luma.level4x4[0], [0, 3, 0, 1, -1, -1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        // 之后改为 CABAC 的 PPS 不影响之前的 slice
+        let cabac_pps = Writer::new(0x68)
+            .ue(0).ue(0).u(1, 1).u(1, 0).ue(0)
+            .ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        let reassembled = assemble(&[partition_a(), partition_c(3), cabac_pps]);
+        match reassembled[0] {
+            AssembledPartition::Slice { slice: ref reassembled, .. } => {
+                let mb = &reassembled.data.macroblocks[1];
+                assert_eq!(mb.mb_type, MbType::P16x16);
+                assert_eq!(mb.total_coeff[0][..4], [5, 0, 0, 0]);
+                assert_eq!(mb.residual.as_ref().unwrap().luma.level4x4[0], residual.luma.level4x4[0]);
+            },
+            _ => panic!("slice expected"),
+        }
+    }
+
+    #[test]
+    fn test_missing_partitions() {
+        // partition C 的 slice_id 与 partition A 不同
+        let assembled = assemble(&[partition_c(1), partition_a(), partition_c(4)]);
+        assert_eq!(assembled.len(), 3);
+
+        match assembled[0] {
+            AssembledPartition::Orphan { nal_unit_type, slice_id } => {
+                assert_eq!(nal_unit_type, NaluKind::CodedSliceDataPartitionC);
+                assert_eq!(slice_id, 1);
+            },
+            _ => panic!("orphan expected"),
+        }
+
+        match assembled[1] {
+            AssembledPartition::Orphan { slice_id, .. } => assert_eq!(slice_id, 4),
+            _ => panic!("orphan expected"),
+        }
+
+        // 缺少 partition C: 帧间宏块的残差按全零处理
+        match assembled[2] {
+            AssembledPartition::Slice { ref slice, ref missing, .. } => {
+                assert_eq!(missing, &vec![NaluKind::CodedSliceDataPartitionC]);
+                let mb = &slice.data.macroblocks[1];
+                assert_eq!(mb.mb_type, MbType::P16x16);
+                assert_eq!(mb.total_coeff[0][..4], [0, 0, 0, 0]);
+            },
+            _ => panic!("slice expected"),
+        }
+    }
+}
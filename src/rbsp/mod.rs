@@ -7,12 +7,37 @@
 use crate::error::Error;
 
 
+mod reader;
+mod writer;
+mod scaling_list;
 mod sps;
 mod pps;
 mod sei;
-
-pub use self::sps::{ SequenceParameterSet, SequenceParameterSetFlag };
+mod user_data;
+mod slice;
+mod parameter_sets;
+
+pub use self::reader::{ RbspReader, ebsp_to_rbsp };
+pub use self::writer::{ RbspWriter, rbsp_to_ebsp };
+pub use self::scaling_list::{ ScalingList, ScalingMatrix, ScalingListSource, ScalingSources };
+pub use self::sps::{ SequenceParameterSet, SequenceParameterSetFlag, VuiParameters, HrdParameters, Profile, Level };
 pub use self::pps::{ PictureParameterSet, };
+pub use self::sei::{
+    SupplementalEnhancementInformation, SeiMessage, SeiMessageKind, SeiPayload, UserDataRegistered,
+    UserDataUnregistered, RecoveryPoint, BufferingPeriod, InitialCpbRemovalDelay, PicTiming, ClockTimestamp,
+    StereoVideoInfo, FramePackingArrangement, FramePackingType, DisplayOrientation,
+    FilmGrainCharacteristics, FilmGrainColourDescription, FilmGrainIntensityInterval,
+    ToneMappingInfo, ToneMappingModel, LuminanceDynamicRange,
+    MasteringDisplayColourVolume, ContentLightLevelInfo, AlternativeTransferCharacteristics, AmbientViewingEnvironment,
+};
+pub use self::user_data::{
+    Uuid, UserDataPayload, UserDataDecoder, UserDataRegistry, UnregisteredUserData, X264Settings, X264_UUID, uuid_string,
+};
+pub use self::slice::{
+    SliceType, SliceHeader, PictureStructure, RefPicListModification, PredWeight, PredWeightTable,
+    DecRefPicMarking, MemoryManagementControlOperation,
+};
+pub use self::parameter_sets::ParameterSets;
 
 
 
@@ -43,12 +68,25 @@ impl RawByteSequencePayload for PictureParameterSet {
     }
 }
 
+impl RawByteSequencePayload for SupplementalEnhancementInformation {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 
 
 pub struct DebugRbSp {
     bytes: Vec<u8>,
 }
 
+impl DebugRbSp {
+    // 未去除 emulation_prevention_three_byte 的原始字节
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
 impl RawByteSequencePayload for DebugRbSp {
     fn as_any(&self) -> &dyn Any {
         self
@@ -0,0 +1,66 @@
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ self, DebugRbSp, RbspReader, SequenceParameterSet, PictureParameterSet };
+
+
+use std::collections::HashMap;
+
+
+// 已接收的参数集，解析 PPS 以及 Slice 时需要通过 id 引用。
+// 7.4.1.2.1 Order of sequence and picture parameter set RBSPs and their activation ( Page 87 )
+#[derive(Debug, Clone, Default)]
+pub struct ParameterSets {
+    sps: HashMap<u32, SequenceParameterSet>,
+    pps: HashMap<u32, PictureParameterSet>,
+}
+
+impl ParameterSets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sps(&self, seq_parameter_set_id: u32) -> Option<&SequenceParameterSet> {
+        self.sps.get(&seq_parameter_set_id)
+    }
+
+    pub fn pps(&self, pic_parameter_set_id: u32) -> Option<&PictureParameterSet> {
+        self.pps.get(&pic_parameter_set_id)
+    }
+
+    pub fn insert_sps(&mut self, sps: SequenceParameterSet) {
+        self.sps.insert(sps.seq_parameter_set_id(), sps);
+    }
+
+    pub fn insert_pps(&mut self, pps: PictureParameterSet) {
+        self.pps.insert(pps.pic_parameter_set_id(), pps);
+    }
+
+    // 若 NALU 为 SPS 或 PPS 则记录下来，返回值表示是否为参数集。
+    pub fn update(&mut self, nalu: &Nalu) -> Result<bool, Error> {
+        match nalu.kind() {
+            NaluKind::SequenceParameterSet => {
+                let sps = match nalu.payload().as_any().downcast_ref::<SequenceParameterSet>() {
+                    Some(sps) => sps.clone(),
+                    None => return Err(error::malformed("sps payload expected")),
+                };
+
+                self.insert_sps(sps);
+
+                Ok(true)
+            },
+            NaluKind::PictureParameterSet => {
+                let bytes = match nalu.payload().as_any().downcast_ref::<DebugRbSp>() {
+                    Some(payload) => rbsp::ebsp_to_rbsp(payload.as_bytes()),
+                    None => return Err(error::malformed("pps payload expected")),
+                };
+
+                let mut reader = RbspReader::new(&bytes);
+                let pps = PictureParameterSet::parse(&mut reader, self)?;
+                self.insert_pps(pps);
+
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+}
@@ -1,8 +1,320 @@
+use crate::error::{ self, Error };
+use crate::rbsp::{ RbspReader, ScalingList, ParameterSets };
 
 
+// Syntax: 7.3.2.2 ( Page 68 )
+// Semantic: 7.4.2.2 ( Page 102 )
+//
 // PPS
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct PictureParameterSet {
+    pic_parameter_set_id: u32,                  // ue(v)
+    seq_parameter_set_id: u32,                  // ue(v)
+    entropy_coding_mode_flag: bool,
+    bottom_field_pic_order_in_frame_present_flag: bool,
+    num_slice_groups_minus1: u32,               // ue(v)
 
+    slice_group_map_type: Option<u32>,          // ue(v)
+    run_length_minus1: Option<Vec<u32>>,        // ue(v)
+    top_left: Option<Vec<u32>>,                 // ue(v)
+    bottom_right: Option<Vec<u32>>,             // ue(v)
+    slice_group_change_direction_flag: Option<bool>,
+    slice_group_change_rate_minus1: Option<u32>,    // ue(v)
+    pic_size_in_map_units_minus1: Option<u32>,      // ue(v)
+    slice_group_id: Option<Vec<u32>>,               // u(v)
+
+    num_ref_idx_l0_default_active_minus1: u32,  // ue(v)
+    num_ref_idx_l1_default_active_minus1: u32,  // ue(v)
+    weighted_pred_flag: bool,
+    weighted_bipred_idc: u8,                    // u(2)
+    pic_init_qp_minus26: i32,                   // se(v)
+    pic_init_qs_minus26: i32,                   // se(v)
+    chroma_qp_index_offset: i32,                // se(v)
+    deblocking_filter_control_present_flag: bool,
+    constrained_intra_pred_flag: bool,
+    redundant_pic_cnt_present_flag: bool,
+
+    transform_8x8_mode_flag: Option<bool>,
+    pic_scaling_matrix_present_flag: Option<bool>,
+    // scaling_list()
+    pic_scaling_lists: Option<Vec<ScalingList>>,
+    second_chroma_qp_index_offset: Option<i32>, // se(v)
 }
 
+impl PictureParameterSet {
+    // 需要引用的 SPS 提供 chroma_format_idc ( 决定 8x8 scaling list 的数量 )
+    pub fn parse(reader: &mut RbspReader, parameter_sets: &ParameterSets) -> Result<Self, Error> {
+        let pic_parameter_set_id = reader.read_ue()?;
+        if pic_parameter_set_id > 255 {
+            return Err(error::malformed("pic_parameter_set_id out of range"));
+        }
+
+        let seq_parameter_set_id = reader.read_ue()?;
+        let sps = match parameter_sets.sps(seq_parameter_set_id) {
+            Some(sps) => sps,
+            None => return Err(error::malformed("pps refers to an unknown sps")),
+        };
+
+        let entropy_coding_mode_flag = reader.read_flag()?;
+        let bottom_field_pic_order_in_frame_present_flag = reader.read_flag()?;
+        let num_slice_groups_minus1 = reader.read_ue()?;
+        if num_slice_groups_minus1 > 7 {
+            return Err(error::malformed("num_slice_groups_minus1 out of range"));
+        }
+
+        let mut slice_group_map_type = None;
+        let mut run_length_minus1 = None;
+        let mut top_left = None;
+        let mut bottom_right = None;
+        let mut slice_group_change_direction_flag = None;
+        let mut slice_group_change_rate_minus1 = None;
+        let mut pic_size_in_map_units_minus1 = None;
+        let mut slice_group_id = None;
+
+        if num_slice_groups_minus1 > 0 {
+            let map_type = reader.read_ue()?;
+            match map_type {
+                0 => {
+                    let mut run_length = vec![];
+                    for _ in 0..=num_slice_groups_minus1 {
+                        run_length.push(reader.read_ue()?);
+                    }
+                    run_length_minus1 = Some(run_length);
+                },
+                2 => {
+                    let mut tl = vec![];
+                    let mut br = vec![];
+                    for _ in 0..num_slice_groups_minus1 {
+                        tl.push(reader.read_ue()?);
+                        br.push(reader.read_ue()?);
+                    }
+                    top_left = Some(tl);
+                    bottom_right = Some(br);
+                },
+                3 | 4 | 5 => {
+                    slice_group_change_direction_flag = Some(reader.read_flag()?);
+                    slice_group_change_rate_minus1 = Some(reader.read_ue()?);
+                },
+                6 => {
+                    let size_minus1 = reader.read_ue()?;
+                    if size_minus1 + 1 != sps.pic_size_in_map_units() {
+                        return Err(error::malformed("pic_size_in_map_units_minus1 mismatch"));
+                    }
+
+                    // Ceil( Log2( num_slice_groups_minus1 + 1 ) )
+                    let bits = 32 - num_slice_groups_minus1.leading_zeros();
+                    let mut ids = Vec::with_capacity(size_minus1 as usize + 1);
+                    for _ in 0..=size_minus1 {
+                        ids.push(reader.read_bits(bits)?);
+                    }
+                    pic_size_in_map_units_minus1 = Some(size_minus1);
+                    slice_group_id = Some(ids);
+                },
+                1 => { },
+                _ => return Err(error::malformed("slice_group_map_type out of range")),
+            }
+            slice_group_map_type = Some(map_type);
+        }
+
+        let num_ref_idx_l0_default_active_minus1 = reader.read_ue()?;
+        let num_ref_idx_l1_default_active_minus1 = reader.read_ue()?;
+        if num_ref_idx_l0_default_active_minus1 > 31 || num_ref_idx_l1_default_active_minus1 > 31 {
+            return Err(error::malformed("num_ref_idx_default_active_minus1 out of range"));
+        }
+
+        let weighted_pred_flag = reader.read_flag()?;
+        let weighted_bipred_idc = reader.read_bits(2)? as u8;
+        if weighted_bipred_idc > 2 {
+            return Err(error::malformed("weighted_bipred_idc out of range"));
+        }
+
+        let pic_init_qp_minus26 = reader.read_se()?;
+        let pic_init_qs_minus26 = reader.read_se()?;
+        let chroma_qp_index_offset = reader.read_se()?;
+        if chroma_qp_index_offset < -12 || chroma_qp_index_offset > 12 {
+            return Err(error::malformed("chroma_qp_index_offset out of range"));
+        }
+
+        let deblocking_filter_control_present_flag = reader.read_flag()?;
+        let constrained_intra_pred_flag = reader.read_flag()?;
+        let redundant_pic_cnt_present_flag = reader.read_flag()?;
+
+        let mut transform_8x8_mode_flag = None;
+        let mut pic_scaling_matrix_present_flag = None;
+        let mut pic_scaling_lists = None;
+        let mut second_chroma_qp_index_offset = None;
+
+        if reader.more_rbsp_data() {
+            let transform_8x8 = reader.read_flag()?;
+            let present_flag = reader.read_flag()?;
+            if present_flag {
+                let lists_8x8 = if sps.chroma_format_idc() != 3 { 2 } else { 6 };
+                let count = 6 + lists_8x8 * transform_8x8 as usize;
+                pic_scaling_lists = Some(ScalingList::parse_lists(reader, count)?);
+            }
+
+            let offset = reader.read_se()?;
+            if offset < -12 || offset > 12 {
+                return Err(error::malformed("second_chroma_qp_index_offset out of range"));
+            }
+
+            transform_8x8_mode_flag = Some(transform_8x8);
+            pic_scaling_matrix_present_flag = Some(present_flag);
+            second_chroma_qp_index_offset = Some(offset);
+        }
+
+        reader.rbsp_trailing_bits()?;
+
+        Ok(Self {
+            pic_parameter_set_id: pic_parameter_set_id,
+            seq_parameter_set_id: seq_parameter_set_id,
+            entropy_coding_mode_flag: entropy_coding_mode_flag,
+            bottom_field_pic_order_in_frame_present_flag: bottom_field_pic_order_in_frame_present_flag,
+            num_slice_groups_minus1: num_slice_groups_minus1,
+            slice_group_map_type: slice_group_map_type,
+            run_length_minus1: run_length_minus1,
+            top_left: top_left,
+            bottom_right: bottom_right,
+            slice_group_change_direction_flag: slice_group_change_direction_flag,
+            slice_group_change_rate_minus1: slice_group_change_rate_minus1,
+            pic_size_in_map_units_minus1: pic_size_in_map_units_minus1,
+            slice_group_id: slice_group_id,
+            num_ref_idx_l0_default_active_minus1: num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1: num_ref_idx_l1_default_active_minus1,
+            weighted_pred_flag: weighted_pred_flag,
+            weighted_bipred_idc: weighted_bipred_idc,
+            pic_init_qp_minus26: pic_init_qp_minus26,
+            pic_init_qs_minus26: pic_init_qs_minus26,
+            chroma_qp_index_offset: chroma_qp_index_offset,
+            deblocking_filter_control_present_flag: deblocking_filter_control_present_flag,
+            constrained_intra_pred_flag: constrained_intra_pred_flag,
+            redundant_pic_cnt_present_flag: redundant_pic_cnt_present_flag,
+            transform_8x8_mode_flag: transform_8x8_mode_flag,
+            pic_scaling_matrix_present_flag: pic_scaling_matrix_present_flag,
+            pic_scaling_lists: pic_scaling_lists,
+            second_chroma_qp_index_offset: second_chroma_qp_index_offset,
+        })
+    }
+
+    pub fn pic_parameter_set_id(&self) -> u32 {
+        self.pic_parameter_set_id
+    }
+
+    pub fn seq_parameter_set_id(&self) -> u32 {
+        self.seq_parameter_set_id
+    }
+
+    pub fn entropy_coding_mode_flag(&self) -> bool {
+        self.entropy_coding_mode_flag
+    }
+
+    pub fn bottom_field_pic_order_in_frame_present_flag(&self) -> bool {
+        self.bottom_field_pic_order_in_frame_present_flag
+    }
+
+    pub fn num_slice_groups_minus1(&self) -> u32 {
+        self.num_slice_groups_minus1
+    }
+
+    pub fn slice_group_map_type(&self) -> Option<u32> {
+        self.slice_group_map_type
+    }
+
+    pub fn run_length_minus1(&self) -> &[u32] {
+        match self.run_length_minus1 {
+            Some(ref values) => &values[..],
+            None => &[],
+        }
+    }
+
+    pub fn top_left(&self) -> &[u32] {
+        match self.top_left {
+            Some(ref values) => &values[..],
+            None => &[],
+        }
+    }
+
+    pub fn bottom_right(&self) -> &[u32] {
+        match self.bottom_right {
+            Some(ref values) => &values[..],
+            None => &[],
+        }
+    }
+
+    pub fn slice_group_change_direction_flag(&self) -> bool {
+        self.slice_group_change_direction_flag.unwrap_or(false)
+    }
+
+    pub fn slice_group_change_rate_minus1(&self) -> u32 {
+        self.slice_group_change_rate_minus1.unwrap_or(0)
+    }
+
+    pub fn slice_group_id(&self) -> &[u32] {
+        match self.slice_group_id {
+            Some(ref values) => &values[..],
+            None => &[],
+        }
+    }
+
+    pub fn num_ref_idx_l0_default_active_minus1(&self) -> u32 {
+        self.num_ref_idx_l0_default_active_minus1
+    }
+
+    pub fn num_ref_idx_l1_default_active_minus1(&self) -> u32 {
+        self.num_ref_idx_l1_default_active_minus1
+    }
+
+    pub fn weighted_pred_flag(&self) -> bool {
+        self.weighted_pred_flag
+    }
+
+    pub fn weighted_bipred_idc(&self) -> u8 {
+        self.weighted_bipred_idc
+    }
+
+    pub fn pic_init_qp_minus26(&self) -> i32 {
+        self.pic_init_qp_minus26
+    }
+
+    pub fn pic_init_qs_minus26(&self) -> i32 {
+        self.pic_init_qs_minus26
+    }
+
+    pub fn chroma_qp_index_offset(&self) -> i32 {
+        self.chroma_qp_index_offset
+    }
+
+    pub fn deblocking_filter_control_present_flag(&self) -> bool {
+        self.deblocking_filter_control_present_flag
+    }
+
+    pub fn constrained_intra_pred_flag(&self) -> bool {
+        self.constrained_intra_pred_flag
+    }
+
+    pub fn redundant_pic_cnt_present_flag(&self) -> bool {
+        self.redundant_pic_cnt_present_flag
+    }
+
+    pub fn transform_8x8_mode_flag(&self) -> bool {
+        self.transform_8x8_mode_flag.unwrap_or(false)
+    }
+
+    pub fn pic_scaling_matrix_present_flag(&self) -> bool {
+        self.pic_scaling_matrix_present_flag.unwrap_or(false)
+    }
+
+    pub fn pic_scaling_lists(&self) -> Option<&[ScalingList]> {
+        self.pic_scaling_lists.as_ref().map(|lists| &lists[..])
+    }
+
+    // 未出现时推导为 chroma_qp_index_offset
+    pub fn second_chroma_qp_index_offset(&self) -> i32 {
+        self.second_chroma_qp_index_offset.unwrap_or(self.chroma_qp_index_offset)
+    }
+
+    // SliceGroupChangeRate
+    pub fn slice_group_change_rate(&self) -> u32 {
+        self.slice_group_change_rate_minus1() + 1
+    }
+}
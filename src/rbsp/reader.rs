@@ -0,0 +1,232 @@
+use crate::error::{ self, Error };
+use crate::golomb;
+
+
+// 7.4.1 NAL unit semantics ( Page 85 )
+//
+// emulation_prevention_three_byte: 0x000003 中的 0x03 不属于 RBSP, 解析语法元素前需要去除。
+pub fn ebsp_to_rbsp(ebsp: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(ebsp.len());
+    let mut zeros = 0usize;
+
+    for &byte in ebsp {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+
+        if byte == 0x00 {
+            zeros += 1;
+        } else {
+            zeros = 0;
+        }
+
+        rbsp.push(byte);
+    }
+
+    rbsp
+}
+
+// 7.2 Specification of syntax functions, categories, and descriptors ( Page 62 )
+//
+// RBSP 比特读取器，记录当前比特位置（宏块级别的语法解析以及码率统计需要）。
+#[derive(Debug, Clone)]
+pub struct RbspReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    // rbsp_stop_one_bit 的位置
+    stop_bit: Option<usize>,
+}
+
+impl<'a> RbspReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        let stop_bit = data.iter()
+            .rposition(|&byte| byte != 0)
+            .map(|index| index * 8 + 7 - data[index].trailing_zeros() as usize);
+
+        Self {
+            data: data,
+            position: 0,
+            stop_bit: stop_bit,
+        }
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    // 当前的比特位置
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn bits_left(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.position)
+    }
+
+    // byte_aligned()
+    pub fn byte_aligned(&self) -> bool {
+        self.position.is_multiple_of(8)
+    }
+
+    pub fn byte_align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+
+    fn bit_at(&self, position: usize) -> bool {
+        let byte = self.data[position / 8];
+        (byte >> (7 - position % 8)) & 1 == 1
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Error> {
+        if self.position >= self.data.len() * 8 {
+            return Err(error::malformed("unexpected end of rbsp"));
+        }
+
+        let bit = self.bit_at(self.position);
+        self.position += 1;
+
+        Ok(bit)
+    }
+
+    // u(n)
+    pub fn read_bits(&mut self, n: u32) -> Result<u32, Error> {
+        assert!(n <= 32);
+
+        if self.bits_left() < n as usize {
+            return Err(error::malformed("unexpected end of rbsp"));
+        }
+
+        let mut value = 0u64;
+        for _ in 0..n {
+            value = (value << 1) | self.bit_at(self.position) as u64;
+            self.position += 1;
+        }
+
+        Ok(value as u32)
+    }
+
+    // u(1)
+    pub fn read_flag(&mut self) -> Result<bool, Error> {
+        self.read_bit()
+    }
+
+    // next_bits( n ), 超出 RBSP 的部分以 0 补齐（用于变长码表查找）
+    pub fn peek_bits(&self, n: u32) -> u32 {
+        assert!(n <= 32);
+
+        let mut value = 0u64;
+        for i in 0..n as usize {
+            let position = self.position + i;
+            let bit = position < self.data.len() * 8 && self.bit_at(position);
+            value = (value << 1) | bit as u64;
+        }
+
+        value as u32
+    }
+
+    pub fn skip_bits(&mut self, n: usize) -> Result<(), Error> {
+        if self.bits_left() < n {
+            return Err(error::malformed("unexpected end of rbsp"));
+        }
+
+        self.position += n;
+
+        Ok(())
+    }
+
+    // ue(v)
+    // 9.1 Parsing process for Exp-Golomb codes ( Page 208 )
+    pub fn read_ue(&mut self) -> Result<u32, Error> {
+        let mut leading_zero_bits = 0u32;
+        while !self.read_bit()? {
+            leading_zero_bits += 1;
+            if leading_zero_bits > 31 {
+                return Err(error::malformed("exp-golomb code is too long"));
+            }
+        }
+
+        let info = self.read_bits(leading_zero_bits)? as u64;
+        let code_num = (1u64 << leading_zero_bits) - 1 + info;
+
+        Ok(code_num as u32)
+    }
+
+    // se(v)
+    pub fn read_se(&mut self) -> Result<i32, Error> {
+        let code_num = self.read_ue()?;
+        Ok(golomb::se_decode(code_num))
+    }
+
+    // te(v), range 为语法元素的最大取值
+    pub fn read_te(&mut self, range: u32) -> Result<u32, Error> {
+        if range > 1 {
+            self.read_ue()
+        } else {
+            Ok(!self.read_bit()? as u32)
+        }
+    }
+
+    // more_rbsp_data()
+    // 7.2 ( Page 63 ): 当前位置之后若只剩下 rbsp_trailing_bits() 则返回 false
+    pub fn more_rbsp_data(&self) -> bool {
+        match self.stop_bit {
+            Some(stop_bit) => self.position < stop_bit,
+            None => false,
+        }
+    }
+
+    // rbsp_trailing_bits()
+    pub fn rbsp_trailing_bits(&mut self) -> Result<(), Error> {
+        if !self.read_bit()? {
+            return Err(error::malformed("rbsp_stop_one_bit must be equal to 1"));
+        }
+
+        while !self.byte_aligned() {
+            if self.read_bit()? {
+                return Err(error::malformed("rbsp_alignment_zero_bit must be equal to 0"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ ebsp_to_rbsp, RbspReader };
+
+    #[test]
+    fn test_ebsp_to_rbsp() {
+        assert_eq!(ebsp_to_rbsp(&[0x00, 0x00, 0x03, 0x01]), vec![0x00, 0x00, 0x01]);
+        assert_eq!(ebsp_to_rbsp(&[0x00, 0x00, 0x03, 0x00, 0x00, 0x03]), vec![0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(ebsp_to_rbsp(&[0x00, 0x03, 0x00]), vec![0x00, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn test_exp_golomb() {
+        // 1 010 011 00100 00101
+        let data = [0b1010_0110, 0b0100_0010, 0b1000_0000];
+        let mut reader = RbspReader::new(&data);
+        assert_eq!(reader.read_ue().unwrap(), 0);
+        assert_eq!(reader.read_ue().unwrap(), 1);
+        assert_eq!(reader.read_ue().unwrap(), 2);
+        assert_eq!(reader.read_se().unwrap(), 2);
+        assert_eq!(reader.read_se().unwrap(), -2);
+        assert_eq!(reader.position(), 17);
+    }
+
+    #[test]
+    fn test_more_rbsp_data() {
+        let data = [0b1011_0000];
+        let mut reader = RbspReader::new(&data);
+        assert!(reader.more_rbsp_data());
+        assert_eq!(reader.read_bits(2).unwrap(), 0b10);
+        assert!(reader.more_rbsp_data());
+        reader.read_bit().unwrap();
+        assert!(!reader.more_rbsp_data());
+        reader.rbsp_trailing_bits().unwrap();
+        assert!(reader.byte_aligned());
+    }
+}
@@ -0,0 +1,328 @@
+use crate::error::{ self, Error };
+use crate::rbsp::{ RbspReader, SequenceParameterSet, PictureParameterSet };
+
+
+// Syntax: 7.3.2.1.1.1 ( Page 66 )
+// Semantic: 7.4.2.1.1.1 ( Page 100 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScalingList {
+    // *_scaling_list_present_flag[i] == 0
+    NotPresent,
+    // useDefaultScalingMatrixFlag == 1
+    UseDefault,
+    // 按 zig-zag 扫描顺序排列 ( 4x4 为 16 项，8x8 为 64 项 )
+    Explicit(Vec<u8>),
+}
+
+impl ScalingList {
+    pub fn is_present(&self) -> bool {
+        *self != ScalingList::NotPresent
+    }
+
+    // scaling_list( scalingList, sizeOfScalingList, useDefaultScalingMatrixFlag )
+    pub fn parse(reader: &mut RbspReader, size_of_scaling_list: usize) -> Result<Self, Error> {
+        let mut scaling_list = Vec::with_capacity(size_of_scaling_list);
+        let mut last_scale = 8i32;
+        let mut next_scale = 8i32;
+        let mut use_default_scaling_matrix_flag = false;
+
+        for j in 0..size_of_scaling_list {
+            if next_scale != 0 {
+                let delta_scale = reader.read_se()?;
+                if delta_scale < -128 || delta_scale > 127 {
+                    return Err(error::malformed("delta_scale out of range"));
+                }
+
+                next_scale = (last_scale + delta_scale + 256) % 256;
+                use_default_scaling_matrix_flag = j == 0 && next_scale == 0;
+            }
+
+            let scale = if next_scale == 0 { last_scale } else { next_scale };
+            scaling_list.push(scale as u8);
+            last_scale = scale;
+        }
+
+        if use_default_scaling_matrix_flag {
+            Ok(ScalingList::UseDefault)
+        } else {
+            Ok(ScalingList::Explicit(scaling_list))
+        }
+    }
+
+    // 读取 `count` 个 *_scaling_list_present_flag[i] 以及对应的 scaling_list()
+    pub fn parse_lists(reader: &mut RbspReader, count: usize) -> Result<Vec<Self>, Error> {
+        let mut lists = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let present_flag = reader.read_flag()?;
+            if present_flag {
+                let size = if i < 6 { 16 } else { 64 };
+                lists.push(ScalingList::parse(reader, size)?);
+            } else {
+                lists.push(ScalingList::NotPresent);
+            }
+        }
+
+        Ok(lists)
+    }
+}
+
+
+// Table 7-3 – Specification of default scaling lists Default_4x4_Intra and Default_4x4_Inter ( Page 106 )
+// Table 7-4 – Specification of default scaling lists Default_8x8_Intra and Default_8x8_Inter
+//
+// 均按 zig-zag 扫描顺序排列
+pub const FLAT_4X4_16: [u8; 16] = [16; 16];
+pub const FLAT_8X8_16: [u8; 64] = [16; 64];
+
+pub const DEFAULT_4X4_INTRA: [u8; 16] = [
+     6, 13, 13, 20, 20, 20, 28, 28, 28, 28, 32, 32, 32, 37, 37, 42,
+];
+
+pub const DEFAULT_4X4_INTER: [u8; 16] = [
+    10, 14, 14, 20, 20, 20, 24, 24, 24, 24, 27, 27, 27, 30, 30, 34,
+];
+
+pub const DEFAULT_8X8_INTRA: [u8; 64] = [
+     6, 10, 10, 13, 11, 13, 16, 16, 16, 16, 18, 18, 18, 18, 18, 23,
+    23, 23, 23, 23, 23, 25, 25, 25, 25, 25, 25, 25, 27, 27, 27, 27,
+    27, 27, 27, 27, 29, 29, 29, 29, 29, 29, 29, 31, 31, 31, 31, 31,
+    31, 33, 33, 33, 33, 33, 36, 36, 36, 36, 38, 38, 38, 40, 40, 42,
+];
+
+pub const DEFAULT_8X8_INTER: [u8; 64] = [
+     9, 13, 13, 15, 13, 15, 17, 17, 17, 17, 19, 19, 19, 19, 19, 21,
+    21, 21, 21, 21, 21, 22, 22, 22, 22, 22, 22, 22, 24, 24, 24, 24,
+    24, 24, 24, 24, 25, 25, 25, 25, 25, 25, 25, 27, 27, 27, 27, 27,
+    27, 28, 28, 28, 28, 28, 30, 30, 30, 30, 32, 32, 32, 33, 33, 35,
+];
+
+
+// 当前图像实际使用的 ScalingList4x4[ 0 .. 5 ] 与 ScalingList8x8[ 0 .. 5 ] ( zig-zag 扫描顺序 )
+//
+// 4x4: Intra Y, Intra Cb, Intra Cr, Inter Y, Inter Cb, Inter Cr
+// 8x8: Intra Y, Inter Y, Intra Cb, Inter Cb, Intra Cr, Inter Cr
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalingMatrix {
+    pub list4x4: [[u8; 16]; 6],
+    pub list8x8: [[u8; 64]; 6],
+}
+
+impl Default for ScalingMatrix {
+    // Flat_4x4_16, Flat_8x8_16
+    fn default() -> Self {
+        Self {
+            list4x4: [FLAT_4X4_16; 6],
+            list8x8: [FLAT_8X8_16; 6],
+        }
+    }
+}
+
+impl ScalingMatrix {
+    // 是否所有列表均为 Flat_4x4_16 / Flat_8x8_16 ( 即没有实际使用量化矩阵 )
+    pub fn is_flat(&self) -> bool {
+        self.list4x4.iter().all(|list| *list == FLAT_4X4_16) && self.list8x8.iter().all(|list| list[..] == FLAT_8X8_16[..])
+    }
+
+    // 7.4.2.1.1 / 7.4.2.2: 按 Table 7-2 的 fall-back rule A ( SPS ) 与 fall-back rule B ( PPS ) 推导
+    pub fn derive(sps: &SequenceParameterSet, pps: &PictureParameterSet) -> Self {
+        ScalingMatrix::derive_with_sources(sps, pps).0
+    }
+
+    // 同 derive, 并给出每个列表的来源
+    pub fn derive_with_sources(sps: &SequenceParameterSet, pps: &PictureParameterSet) -> (Self, ScalingSources) {
+        let seq_scaling_matrix_present_flag = sps.seq_scaling_matrix_present_flag();
+
+        let seq = match sps.seq_scaling_lists() {
+            Some(lists) if seq_scaling_matrix_present_flag => {
+                ScalingMatrix::from_lists(lists, None, ScalingListSource::Sequence)
+            },
+            _ => (ScalingMatrix::default(), ScalingSources::default()),
+        };
+
+        // pic_scaling_list_present_flag[ i ] 为 0 时, seq_scaling_matrix_present_flag 为 0 则使用 rule A,
+        // 否则使用 rule B
+        match pps.pic_scaling_lists() {
+            Some(lists) if pps.pic_scaling_matrix_present_flag() => {
+                let fall_back = if seq_scaling_matrix_present_flag { Some((&seq.0, &seq.1)) } else { None };
+                ScalingMatrix::from_lists(lists, fall_back, ScalingListSource::Picture)
+            },
+            _ => seq,
+        }
+    }
+
+    // `fall_back` 为 rule B 时使用的序列级矩阵及其来源, 为 None 时使用 rule A
+    fn from_lists(lists: &[ScalingList],
+                  fall_back: Option<(&ScalingMatrix, &ScalingSources)>,
+                  explicit: ScalingListSource) -> (Self, ScalingSources) {
+        let mut matrix = ScalingMatrix::default();
+        let mut sources = ScalingSources::default();
+        let list = |i: usize| lists.get(i).unwrap_or(&ScalingList::NotPresent);
+
+        for i in 0..6 {
+            let (list4x4, source) = match list(i) {
+                ScalingList::Explicit(values) => {
+                    let mut list4x4 = [0u8; 16];
+                    list4x4.copy_from_slice(&values[..16]);
+                    (list4x4, explicit)
+                },
+                ScalingList::UseDefault if i < 3 => (DEFAULT_4X4_INTRA, ScalingListSource::Default),
+                ScalingList::UseDefault => (DEFAULT_4X4_INTER, ScalingListSource::Default),
+                ScalingList::NotPresent => match (i, fall_back) {
+                    (0, Some((seq, seq_sources))) | (3, Some((seq, seq_sources))) => {
+                        (seq.list4x4[i], seq_sources.list4x4[i])
+                    },
+                    (0, None) => (DEFAULT_4X4_INTRA, ScalingListSource::Default),
+                    (3, None) => (DEFAULT_4X4_INTER, ScalingListSource::Default),
+                    _ => (matrix.list4x4[i - 1], sources.list4x4[i - 1]),
+                },
+            };
+            matrix.list4x4[i] = list4x4;
+            sources.list4x4[i] = source;
+        }
+
+        for i in 0..6 {
+            let (list8x8, source) = match list(i + 6) {
+                ScalingList::Explicit(values) => {
+                    let mut list8x8 = [0u8; 64];
+                    list8x8.copy_from_slice(&values[..64]);
+                    (list8x8, explicit)
+                },
+                ScalingList::UseDefault if i % 2 == 0 => (DEFAULT_8X8_INTRA, ScalingListSource::Default),
+                ScalingList::UseDefault => (DEFAULT_8X8_INTER, ScalingListSource::Default),
+                ScalingList::NotPresent => match (i, fall_back) {
+                    (0, Some((seq, seq_sources))) | (1, Some((seq, seq_sources))) => {
+                        (seq.list8x8[i], seq_sources.list8x8[i])
+                    },
+                    (0, None) => (DEFAULT_8X8_INTRA, ScalingListSource::Default),
+                    (1, None) => (DEFAULT_8X8_INTER, ScalingListSource::Default),
+                    _ => (matrix.list8x8[i - 2], sources.list8x8[i - 2]),
+                },
+            };
+            matrix.list8x8[i] = list8x8;
+            sources.list8x8[i] = source;
+        }
+
+        (matrix, sources)
+    }
+}
+
+
+// 推导出的列表的来源, 用于统计编码器是否使用自定义的量化矩阵
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingListSource {
+    // Flat_4x4_16 / Flat_8x8_16: SPS 与 PPS 都没有 scaling matrix
+    Flat,
+    // Default_4x4_* / Default_8x8_*: useDefaultScalingMatrixFlag 或 fall-back rule A
+    Default,
+    // SPS 中传输的列表
+    Sequence,
+    // PPS 中传输的列表
+    Picture,
+}
+
+impl ScalingListSource {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ScalingListSource::Flat => "flat",
+            ScalingListSource::Default => "default",
+            ScalingListSource::Sequence => "sps",
+            ScalingListSource::Picture => "pps",
+        }
+    }
+}
+
+// ScalingMatrix 中每个列表的来源, 下标与 ScalingMatrix 一致
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalingSources {
+    pub list4x4: [ScalingListSource; 6],
+    pub list8x8: [ScalingListSource; 6],
+}
+
+impl Default for ScalingSources {
+    fn default() -> Self {
+        Self {
+            list4x4: [ScalingListSource::Flat; 6],
+            list8x8: [ScalingListSource::Flat; 6],
+        }
+    }
+}
+
+impl ScalingSources {
+    fn iter(&self) -> impl Iterator<Item = &ScalingListSource> {
+        self.list4x4.iter().chain(self.list8x8.iter())
+    }
+
+    // 是否有列表来自码流中传输的自定义值 ( 不是 Flat 或 Default 表 )
+    pub fn is_custom(&self) -> bool {
+        self.iter().any(|source| *source == ScalingListSource::Sequence || *source == ScalingListSource::Picture)
+    }
+
+    // 是否有列表使用 Default_* 表
+    pub fn uses_default(&self) -> bool {
+        self.iter().any(|source| *source == ScalingListSource::Default)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::slice::test::{ Writer, parameter_sets };
+
+    #[test]
+    fn test_fall_back_rules() {
+        let mut lists = vec![ScalingList::NotPresent; 8];
+        lists[1] = ScalingList::Explicit(vec![4; 16]);
+        lists[3] = ScalingList::UseDefault;
+        lists[7] = ScalingList::Explicit(vec![9; 64]);
+
+        // rule A: 0 -> Default_4x4_Intra, 2 -> 1, 4, 5 -> 3, 8 .. 11 -> 6, 7
+        let (seq, seq_sources) = ScalingMatrix::from_lists(&lists, None, ScalingListSource::Sequence);
+        assert_eq!(seq.list4x4[0], DEFAULT_4X4_INTRA);
+        assert_eq!(seq.list4x4[2], [4; 16]);
+        assert_eq!(seq.list4x4[5], DEFAULT_4X4_INTER);
+        assert_eq!(seq.list8x8[0], DEFAULT_8X8_INTRA);
+        assert_eq!(seq.list8x8[5][..], [9; 64][..]);
+        assert_eq!(seq_sources.list4x4[2], ScalingListSource::Sequence);
+        assert_eq!(seq_sources.list4x4[5], ScalingListSource::Default);
+        assert_eq!(seq_sources.list8x8[5], ScalingListSource::Sequence);
+
+        // rule B: 0, 3, 6, 7 回退到序列级的矩阵
+        let lists = vec![ScalingList::NotPresent; 6];
+        let (pic, pic_sources) = ScalingMatrix::from_lists(&lists, Some((&seq, &seq_sources)), ScalingListSource::Picture);
+        assert_eq!(pic.list4x4[0], DEFAULT_4X4_INTRA);
+        assert_eq!(pic.list4x4[1], DEFAULT_4X4_INTRA);
+        assert_eq!(pic.list4x4[3], DEFAULT_4X4_INTER);
+        assert_eq!(pic.list8x8[1][..], [9; 64][..]);
+        assert_eq!(pic_sources.list8x8[1], ScalingListSource::Sequence);
+        assert!(pic_sources.is_custom());
+    }
+
+    #[test]
+    fn test_derive_without_sequence_matrix() {
+        // SPS 没有 scaling matrix, PPS 只传输了 useDefaultScalingMatrixFlag 的 Intra Y 列表: 其余列表使用 rule A
+        let mut parameter_sets = parameter_sets();
+        let pps = Writer::new(0x68)
+            .ue(0).ue(0).u(1, 0).u(1, 0).ue(0)
+            .ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0)
+            .u(1, 0).u(1, 1).u(1, 1).se(-8).bits("00000")
+            .se(0)
+            .finish();
+        assert!(parameter_sets.update(&pps).unwrap());
+
+        let sps = parameter_sets.sps(0).unwrap();
+        let pps = parameter_sets.pps(0).unwrap();
+        assert_eq!(pps.pic_scaling_lists().unwrap()[0], ScalingList::UseDefault);
+
+        let (matrix, sources) = ScalingMatrix::derive_with_sources(sps, pps);
+        assert_eq!(matrix.list4x4[2], DEFAULT_4X4_INTRA);
+        assert_eq!(matrix.list4x4[3], DEFAULT_4X4_INTER);
+        assert_eq!(sources.list4x4, [ScalingListSource::Default; 6]);
+        assert!(!matrix.is_flat());
+        assert!(!sources.is_custom());
+        assert!(sources.uses_default());
+    }
+}
@@ -1,19 +1,9 @@
-use crate::bitstream_io::{ BitReader, Endianness, };
+use crate::error::{ self, Error };
+use crate::rbsp::{ RbspReader, RbspWriter, rbsp_to_ebsp, ParameterSets, SequenceParameterSet, HrdParameters };
 
-use std::fmt;
-use std::io::Read;
 use std::convert::TryFrom;
 
 
-// SeiMessage
-// SeiMessageKind
-pub trait SeiMessage: fmt::Debug {
-    fn kind(&self) -> SeiMessageKind;
-    fn len(&self) -> usize;
-    fn as_bytes(&self) -> &[u8];
-}
-
-
 // SEI Payloads: Annex D ( Page 350 )
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SeiMessageKind {
@@ -75,7 +65,9 @@ pub enum SeiMessageKind {
     GreenMetadata,
     MasteringDisplayColourVolume,
     ColourRemappingInfo,
+    ContentLightLevelInfo,
     AlternativeTransferCharacteristics,
+    AmbientViewingEnvironment,
     AlternativeDepthInfo,
     Reserved(u32),
 }
@@ -145,9 +137,11 @@ impl TryFrom<u32> for SeiMessageKind {
             56 => Ok(GreenMetadata),
             137 => Ok(MasteringDisplayColourVolume),
             142 => Ok(ColourRemappingInfo),
+            144 => Ok(ContentLightLevelInfo),
             147 => Ok(AlternativeTransferCharacteristics),
+            148 => Ok(AmbientViewingEnvironment),
             181 => Ok(AlternativeDepthInfo),
-            n @ _ => Ok(Reserved(n)),
+            n => Ok(Reserved(n)),
         }
     }
 }
@@ -216,31 +210,1331 @@ impl Into<u32> for SeiMessageKind {
             GreenMetadata => 56,
             MasteringDisplayColourVolume => 137,
             ColourRemappingInfo => 142,
+            ContentLightLevelInfo => 144,
             AlternativeTransferCharacteristics => 147,
+            AmbientViewingEnvironment => 148,
             AlternativeDepthInfo => 181,
             Reserved(n) => n,
         }
     }
 }
 
-#[derive(Debug)]
-pub struct SeiAnyMessage {
-    kind: SeiMessageKind,
-    size: usize,
-    body: Vec<u8>,
+// D.1.2 Buffering period SEI message syntax ( Page 331 ): 每个 SchedSelIdx 的初始 CPB 移除延时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitialCpbRemovalDelay {
+    pub initial_cpb_removal_delay: u32,
+    pub initial_cpb_removal_delay_offset: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferingPeriod {
+    pub seq_parameter_set_id: u32,
+    // NalHrdBpPresentFlag 与 VclHrdBpPresentFlag 为 1 时各有 cpb_cnt_minus1 + 1 项, 否则为空
+    pub nal_initial_cpb_removal_delays: Vec<InitialCpbRemovalDelay>,
+    pub vcl_initial_cpb_removal_delays: Vec<InitialCpbRemovalDelay>,
+}
+
+impl BufferingPeriod {
+    // 需要 seq_parameter_set_id 引用的 SPS 中的 HRD 参数
+    pub fn parse(bytes: &[u8], parameter_sets: &ParameterSets) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let seq_parameter_set_id = reader.read_ue()?;
+        let sps = match parameter_sets.sps(seq_parameter_set_id) {
+            Some(sps) => sps,
+            None => return Err(error::malformed("buffering_period refers to a missing sps")),
+        };
+        let vui = sps.vui_parameters();
+
+        let mut delays = |hrd: Option<&HrdParameters>| -> Result<Vec<InitialCpbRemovalDelay>, Error> {
+            let hrd = match hrd {
+                Some(hrd) => hrd,
+                None => return Ok(vec![]),
+            };
+
+            let length = hrd.initial_cpb_removal_delay_length_minus1 as u32 + 1;
+            let mut delays = Vec::with_capacity(hrd.cpb_cnt_minus1 as usize + 1);
+            for _ in 0..=hrd.cpb_cnt_minus1 {
+                delays.push(InitialCpbRemovalDelay {
+                    initial_cpb_removal_delay: reader.read_bits(length)?,
+                    initial_cpb_removal_delay_offset: reader.read_bits(length)?,
+                });
+            }
+            Ok(delays)
+        };
+
+        let nal_initial_cpb_removal_delays = delays(vui.and_then(|vui| vui.nal_hrd_parameters.as_ref()))?;
+        let vcl_initial_cpb_removal_delays = delays(vui.and_then(|vui| vui.vcl_hrd_parameters.as_ref()))?;
+
+        Ok(Self {
+            seq_parameter_set_id: seq_parameter_set_id,
+            nal_initial_cpb_removal_delays: nal_initial_cpb_removal_delays,
+            vcl_initial_cpb_removal_delays: vcl_initial_cpb_removal_delays,
+        })
+    }
+}
+
+// D.1.3 Picture timing SEI message syntax ( Page 332 ): clock_timestamp_flag 为 1 的时间戳
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTimestamp {
+    pub ct_type: u8,
+    pub nuit_field_based_flag: bool,
+    pub counting_type: u8,
+    pub full_timestamp_flag: bool,
+    pub discontinuity_flag: bool,
+    pub cnt_dropped_flag: bool,
+    pub n_frames: u8,
+    // full_timestamp_flag 为 0 时可能不存在 ( 取前一个时间戳的值 )
+    pub seconds_value: Option<u8>,
+    pub minutes_value: Option<u8>,
+    pub hours_value: Option<u8>,
+    // time_offset_length 为 0 时为 0
+    pub time_offset: i32,
+}
+
+impl ClockTimestamp {
+    fn parse(reader: &mut RbspReader, time_offset_length: u32) -> Result<Self, Error> {
+        let ct_type = reader.read_bits(2)? as u8;
+        let nuit_field_based_flag = reader.read_flag()?;
+        let counting_type = reader.read_bits(5)? as u8;
+        let full_timestamp_flag = reader.read_flag()?;
+        let discontinuity_flag = reader.read_flag()?;
+        let cnt_dropped_flag = reader.read_flag()?;
+        let n_frames = reader.read_bits(8)? as u8;
+
+        let mut seconds_value = None;
+        let mut minutes_value = None;
+        let mut hours_value = None;
+        if full_timestamp_flag {
+            seconds_value = Some(reader.read_bits(6)? as u8);
+            minutes_value = Some(reader.read_bits(6)? as u8);
+            hours_value = Some(reader.read_bits(5)? as u8);
+        } else if reader.read_flag()? {
+            seconds_value = Some(reader.read_bits(6)? as u8);
+            if reader.read_flag()? {
+                minutes_value = Some(reader.read_bits(6)? as u8);
+                if reader.read_flag()? {
+                    hours_value = Some(reader.read_bits(5)? as u8);
+                }
+            }
+        }
+
+        if seconds_value.is_some_and(|value| value > 59) || minutes_value.is_some_and(|value| value > 59)
+            || hours_value.is_some_and(|value| value > 23) {
+            return Err(error::malformed("clock timestamp out of range"));
+        }
+
+        // i(v): 二进制补码
+        let time_offset = if time_offset_length > 0 {
+            let value = reader.read_bits(time_offset_length)? as i64;
+            if value >> (time_offset_length - 1) == 1 { (value - (1i64 << time_offset_length)) as i32 } else { value as i32 }
+        } else {
+            0
+        };
+
+        Ok(Self {
+            ct_type: ct_type,
+            nuit_field_based_flag: nuit_field_based_flag,
+            counting_type: counting_type,
+            full_timestamp_flag: full_timestamp_flag,
+            discontinuity_flag: discontinuity_flag,
+            cnt_dropped_flag: cnt_dropped_flag,
+            n_frames: n_frames,
+            seconds_value: seconds_value,
+            minutes_value: minutes_value,
+            hours_value: hours_value,
+            time_offset: time_offset,
+        })
+    }
+
+    // D.2.3 ( D-1 ): clockTimestamp, 单位为 1 / time_scale 秒; 不存在的时, 分, 秒按 0 计算
+    pub fn clock_timestamp(&self, num_units_in_tick: u32, time_scale: u32) -> i64 {
+        let hours = self.hours_value.unwrap_or(0) as i64;
+        let minutes = self.minutes_value.unwrap_or(0) as i64;
+        let seconds = self.seconds_value.unwrap_or(0) as i64;
+
+        ((hours * 60 + minutes) * 60 + seconds) * time_scale as i64
+            + self.n_frames as i64 * num_units_in_tick as i64 * (1 + self.nuit_field_based_flag as i64)
+            + self.time_offset as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PicTiming {
+    // CpbDpbDelaysPresentFlag 为 1 时存在
+    pub cpb_removal_delay: Option<u32>,
+    pub dpb_output_delay: Option<u32>,
+    // pic_struct_present_flag 为 1 时存在 ( Table D-1 )
+    pub pic_struct: Option<u8>,
+    // NumClockTS 项, clock_timestamp_flag 为 0 的项为 None
+    pub clock_timestamps: Vec<Option<ClockTimestamp>>,
+}
+
+impl PicTiming {
+    // 需要当前激活的 SPS: pic_timing 中没有 seq_parameter_set_id
+    pub fn parse(bytes: &[u8], sps: &SequenceParameterSet) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+        let vui = match sps.vui_parameters() {
+            Some(vui) => vui,
+            None => return Err(error::malformed("pic_timing requires vui_parameters")),
+        };
+
+        // 长度取自 nal_hrd_parameters, 不存在时取自 vcl_hrd_parameters
+        let hrd = vui.nal_hrd_parameters.as_ref().or(vui.vcl_hrd_parameters.as_ref());
+
+        let mut cpb_removal_delay = None;
+        let mut dpb_output_delay = None;
+        if let Some(hrd) = hrd {
+            cpb_removal_delay = Some(reader.read_bits(hrd.cpb_removal_delay_length_minus1 as u32 + 1)?);
+            dpb_output_delay = Some(reader.read_bits(hrd.dpb_output_delay_length_minus1 as u32 + 1)?);
+        }
+
+        let mut pic_struct = None;
+        let mut clock_timestamps = vec![];
+        if vui.pic_struct_present_flag {
+            let value = reader.read_bits(4)? as u8;
+            let num_clock_ts = match PicTiming::num_clock_ts(value) {
+                Some(num_clock_ts) => num_clock_ts,
+                None => return Err(error::malformed("reserved pic_struct")),
+            };
+
+            let time_offset_length = hrd.map_or(24, |hrd| hrd.time_offset_length as u32);
+            for _ in 0..num_clock_ts {
+                if reader.read_flag()? {
+                    clock_timestamps.push(Some(ClockTimestamp::parse(&mut reader, time_offset_length)?));
+                } else {
+                    clock_timestamps.push(None);
+                }
+            }
+            pic_struct = Some(value);
+        }
+
+        Ok(Self {
+            cpb_removal_delay: cpb_removal_delay,
+            dpb_output_delay: dpb_output_delay,
+            pic_struct: pic_struct,
+            clock_timestamps: clock_timestamps,
+        })
+    }
+
+    // Table D-1 – Interpretation of pic_struct: NumClockTS, 保留值为 None
+    pub fn num_clock_ts(pic_struct: u8) -> Option<usize> {
+        match pic_struct {
+            0 ..= 2 => Some(1),
+            3 | 4 | 7 => Some(2),
+            5 | 6 | 8 => Some(3),
+            _ => None,
+        }
+    }
+}
+
+// D.1.6 User data registered by Rec. ITU-T T.35 SEI message syntax ( Page 338 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDataRegistered {
+    pub itu_t_t35_country_code: u8,
+    // itu_t_t35_country_code 为 0xFF 时存在
+    pub itu_t_t35_country_code_extension_byte: Option<u8>,
+    // itu_t_t35_payload_byte
+    pub payload: Vec<u8>,
+}
+
+impl UserDataRegistered {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let (&itu_t_t35_country_code, rest) = match bytes.split_first() {
+            Some(split) => split,
+            None => return Err(error::malformed("user_data_registered_itu_t_t35 is empty")),
+        };
+
+        let (itu_t_t35_country_code_extension_byte, payload) = if itu_t_t35_country_code == 0xFF {
+            match rest.split_first() {
+                Some((&extension_byte, payload)) => (Some(extension_byte), payload),
+                None => return Err(error::malformed("itu_t_t35_country_code_extension_byte is missing")),
+            }
+        } else {
+            (None, rest)
+        };
+
+        Ok(Self {
+            itu_t_t35_country_code: itu_t_t35_country_code,
+            itu_t_t35_country_code_extension_byte: itu_t_t35_country_code_extension_byte,
+            payload: payload.to_vec(),
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_bits(8, u32::from(self.itu_t_t35_country_code));
+        if let Some(extension_byte) = self.itu_t_t35_country_code_extension_byte {
+            writer.write_bits(8, u32::from(extension_byte));
+        }
+        writer.write_bytes(&self.payload);
+    }
+
+    // itu_t_t35_provider_code: 由 itu_t_t35_country_code 指定的国家分配, 位于 payload 的前两个字节 ( ATSC A/53 等 )
+    pub fn provider_code(&self) -> Option<u16> {
+        if self.payload.len() < 2 {
+            return None;
+        }
+
+        Some(u16::from(self.payload[0]) << 8 | u16::from(self.payload[1]))
+    }
+}
+
+// D.1.7 User data unregistered SEI message syntax ( Page 338 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDataUnregistered {
+    pub uuid_iso_iec_11578: [u8; 16],
+    // user_data_payload_byte
+    pub payload: Vec<u8>,
+}
+
+impl UserDataUnregistered {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 16 {
+            return Err(error::malformed("user_data_unregistered is shorter than uuid_iso_iec_11578"));
+        }
+
+        let mut uuid_iso_iec_11578 = [0u8; 16];
+        uuid_iso_iec_11578.copy_from_slice(&bytes[..16]);
+
+        Ok(Self {
+            uuid_iso_iec_11578: uuid_iso_iec_11578,
+            payload: bytes[16..].to_vec(),
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_bytes(&self.uuid_iso_iec_11578);
+        writer.write_bytes(&self.payload);
+    }
+}
+
+// D.1.8 Recovery point SEI message syntax ( Page 339 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryPoint {
+    pub recovery_frame_cnt: u32,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+    pub changing_slice_group_idc: u8,
+}
+
+impl RecoveryPoint {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let recovery_frame_cnt = reader.read_ue()?;
+        let exact_match_flag = reader.read_flag()?;
+        let broken_link_flag = reader.read_flag()?;
+        let changing_slice_group_idc = reader.read_bits(2)? as u8;
+        if changing_slice_group_idc > 2 {
+            return Err(error::malformed("changing_slice_group_idc out of range"));
+        }
+
+        Ok(Self {
+            recovery_frame_cnt: recovery_frame_cnt,
+            exact_match_flag: exact_match_flag,
+            broken_link_flag: broken_link_flag,
+            changing_slice_group_idc: changing_slice_group_idc,
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_ue(self.recovery_frame_cnt);
+        writer.write_flag(self.exact_match_flag);
+        writer.write_flag(self.broken_link_flag);
+        writer.write_bits(2, u32::from(self.changing_slice_group_idc));
+    }
+
+    // D.2.8: 输出正确的图像从 frame_num 为 ( 当前 frame_num + recovery_frame_cnt ) % MaxFrameNum 的帧开始 ( 按输出顺序 )
+    pub fn recovery_frame_num(&self, frame_num: u32, max_frame_num: u32) -> u32 {
+        ((u64::from(frame_num) + u64::from(self.recovery_frame_cnt)) % u64::from(max_frame_num)) as u32
+    }
+}
+
+// D.1.21 Film grain characteristics SEI message syntax: separate_colour_description_present_flag 为 1 时的颜色描述
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilmGrainColourDescription {
+    pub film_grain_bit_depth_luma_minus8: u8,
+    pub film_grain_bit_depth_chroma_minus8: u8,
+    pub film_grain_full_range_flag: bool,
+    pub film_grain_colour_primaries: u8,
+    pub film_grain_transfer_characteristics: u8,
+    pub film_grain_matrix_coefficients: u8,
+}
+
+// 一个亮度区间中的模型参数, 个数为 num_model_values_minus1 + 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilmGrainIntensityInterval {
+    pub intensity_interval_lower_bound: u8,
+    pub intensity_interval_upper_bound: u8,
+    pub comp_model_value: Vec<i32>,
+}
+
+// D.1.21 Film grain characteristics SEI message syntax
+// film_grain_characteristics_cancel_flag 为 1 时, 其后的语法元素不存在 ( 取 0 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilmGrainCharacteristics {
+    pub film_grain_characteristics_cancel_flag: bool,
+    // 0: 频率滤波, 1: 自回归, 2 与 3 保留
+    pub model_id: u8,
+    pub separate_colour_description: Option<FilmGrainColourDescription>,
+    // 0: 相加, 1: 相乘, 2 与 3 保留
+    pub blending_mode_id: u8,
+    pub log2_scale_factor: u8,
+    // 按 Y, Cb, Cr; comp_model_present_flag 为 0 时为 None
+    pub components: [Option<Vec<FilmGrainIntensityInterval>>; 3],
+    // 与 frame_packing_arrangement_repetition_period 的含义相同
+    pub film_grain_characteristics_repetition_period: u32,
+}
+
+impl FilmGrainCharacteristics {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let film_grain_characteristics_cancel_flag = reader.read_flag()?;
+        let mut film_grain = Self {
+            film_grain_characteristics_cancel_flag: film_grain_characteristics_cancel_flag,
+            model_id: 0,
+            separate_colour_description: None,
+            blending_mode_id: 0,
+            log2_scale_factor: 0,
+            components: [None, None, None],
+            film_grain_characteristics_repetition_period: 0,
+        };
+        if film_grain_characteristics_cancel_flag {
+            return Ok(film_grain);
+        }
+
+        film_grain.model_id = reader.read_bits(2)? as u8;
+        if reader.read_flag()? {
+            film_grain.separate_colour_description = Some(FilmGrainColourDescription {
+                film_grain_bit_depth_luma_minus8: reader.read_bits(3)? as u8,
+                film_grain_bit_depth_chroma_minus8: reader.read_bits(3)? as u8,
+                film_grain_full_range_flag: reader.read_flag()?,
+                film_grain_colour_primaries: reader.read_bits(8)? as u8,
+                film_grain_transfer_characteristics: reader.read_bits(8)? as u8,
+                film_grain_matrix_coefficients: reader.read_bits(8)? as u8,
+            });
+        }
+        film_grain.blending_mode_id = reader.read_bits(2)? as u8;
+        film_grain.log2_scale_factor = reader.read_bits(4)? as u8;
+
+        let mut comp_model_present_flag = [false; 3];
+        for flag in comp_model_present_flag.iter_mut() {
+            *flag = reader.read_flag()?;
+        }
+
+        for (component, &present) in film_grain.components.iter_mut().zip(comp_model_present_flag.iter()) {
+            if !present {
+                continue;
+            }
+
+            let num_intensity_intervals_minus1 = reader.read_bits(8)?;
+            let num_model_values_minus1 = reader.read_bits(3)?;
+            if num_model_values_minus1 > 5 {
+                return Err(error::malformed("num_model_values_minus1 out of range"));
+            }
+
+            let mut intervals = Vec::with_capacity(num_intensity_intervals_minus1 as usize + 1);
+            for _ in 0..=num_intensity_intervals_minus1 {
+                let intensity_interval_lower_bound = reader.read_bits(8)? as u8;
+                let intensity_interval_upper_bound = reader.read_bits(8)? as u8;
+                let mut comp_model_value = Vec::with_capacity(num_model_values_minus1 as usize + 1);
+                for _ in 0..=num_model_values_minus1 {
+                    comp_model_value.push(reader.read_se()?);
+                }
+                intervals.push(FilmGrainIntensityInterval {
+                    intensity_interval_lower_bound: intensity_interval_lower_bound,
+                    intensity_interval_upper_bound: intensity_interval_upper_bound,
+                    comp_model_value: comp_model_value,
+                });
+            }
+            *component = Some(intervals);
+        }
+
+        film_grain.film_grain_characteristics_repetition_period = reader.read_ue()?;
+        if film_grain.film_grain_characteristics_repetition_period > 16384 {
+            return Err(error::malformed("film_grain_characteristics_repetition_period out of range"));
+        }
+
+        Ok(film_grain)
+    }
+
+    // model_id 或 blending_mode_id 为保留值时解码器忽略这个 SEI, 但其语法不变, 重新封装时仍然保留
+    pub fn is_reserved(&self) -> bool {
+        !self.film_grain_characteristics_cancel_flag && (self.model_id > 1 || self.blending_mode_id > 1)
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_flag(self.film_grain_characteristics_cancel_flag);
+        if self.film_grain_characteristics_cancel_flag {
+            return;
+        }
+
+        writer.write_bits(2, u32::from(self.model_id));
+        writer.write_flag(self.separate_colour_description.is_some());
+        if let Some(description) = self.separate_colour_description {
+            writer.write_bits(3, u32::from(description.film_grain_bit_depth_luma_minus8));
+            writer.write_bits(3, u32::from(description.film_grain_bit_depth_chroma_minus8));
+            writer.write_flag(description.film_grain_full_range_flag);
+            writer.write_bits(8, u32::from(description.film_grain_colour_primaries));
+            writer.write_bits(8, u32::from(description.film_grain_transfer_characteristics));
+            writer.write_bits(8, u32::from(description.film_grain_matrix_coefficients));
+        }
+        writer.write_bits(2, u32::from(self.blending_mode_id));
+        writer.write_bits(4, u32::from(self.log2_scale_factor));
+        for component in self.components.iter() {
+            writer.write_flag(component.is_some());
+        }
+
+        for intervals in self.components.iter().flatten() {
+            let num_model_values = intervals.first().map_or(1, |interval| interval.comp_model_value.len());
+            writer.write_bits(8, intervals.len() as u32 - 1);
+            writer.write_bits(3, num_model_values as u32 - 1);
+            for interval in intervals.iter() {
+                writer.write_bits(8, u32::from(interval.intensity_interval_lower_bound));
+                writer.write_bits(8, u32::from(interval.intensity_interval_upper_bound));
+                for &value in interval.comp_model_value.iter() {
+                    writer.write_se(value);
+                }
+            }
+        }
+
+        writer.write_ue(self.film_grain_characteristics_repetition_period);
+    }
+}
+
+// D.1.22 Stereo video information SEI message syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StereoVideoInfo {
+    // 为 1 时两个视图为同一帧的两个场
+    pub field_views_flag: bool,
+    // field_views_flag 为 1 时存在
+    pub top_field_is_left_view_flag: bool,
+    // field_views_flag 为 0 时存在
+    pub current_frame_is_left_view_flag: bool,
+    pub next_frame_is_second_view_flag: bool,
+    pub left_view_self_contained_flag: bool,
+    pub right_view_self_contained_flag: bool,
+}
+
+impl StereoVideoInfo {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let field_views_flag = reader.read_flag()?;
+        let mut top_field_is_left_view_flag = false;
+        let mut current_frame_is_left_view_flag = false;
+        let mut next_frame_is_second_view_flag = false;
+        if field_views_flag {
+            top_field_is_left_view_flag = reader.read_flag()?;
+        } else {
+            current_frame_is_left_view_flag = reader.read_flag()?;
+            next_frame_is_second_view_flag = reader.read_flag()?;
+        }
+        let left_view_self_contained_flag = reader.read_flag()?;
+        let right_view_self_contained_flag = reader.read_flag()?;
+
+        Ok(Self {
+            field_views_flag: field_views_flag,
+            top_field_is_left_view_flag: top_field_is_left_view_flag,
+            current_frame_is_left_view_flag: current_frame_is_left_view_flag,
+            next_frame_is_second_view_flag: next_frame_is_second_view_flag,
+            left_view_self_contained_flag: left_view_self_contained_flag,
+            right_view_self_contained_flag: right_view_self_contained_flag,
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_flag(self.field_views_flag);
+        if self.field_views_flag {
+            writer.write_flag(self.top_field_is_left_view_flag);
+        } else {
+            writer.write_flag(self.current_frame_is_left_view_flag);
+            writer.write_flag(self.next_frame_is_second_view_flag);
+        }
+        writer.write_flag(self.left_view_self_contained_flag);
+        writer.write_flag(self.right_view_self_contained_flag);
+    }
+}
+
+// D.1.24 Tone mapping information SEI message syntax: tone_map_model_id 为 4 时的亮度动态范围信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LuminanceDynamicRange {
+    pub camera_iso_speed_idc: u8,
+    // camera_iso_speed_idc 为 255 ( Extended_ISO ) 时存在
+    pub camera_iso_speed_value: Option<u32>,
+    pub exposure_index_idc: u8,
+    pub exposure_index_value: Option<u32>,
+    pub exposure_compensation_value_sign_flag: bool,
+    pub exposure_compensation_value_numerator: u16,
+    pub exposure_compensation_value_denom_idc: u16,
+    pub ref_screen_luminance_white: u32,
+    pub extended_range_white_level: u32,
+    pub nominal_black_level_code_value: u16,
+    pub nominal_white_level_code_value: u16,
+    pub extended_white_level_code_value: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToneMappingModel {
+    // 0: 线性映射并截断
+    Linear { min_value: u32, max_value: u32 },
+    // 1: S 形曲线
+    Sigmoid { sigmoid_midpoint: u32, sigmoid_width: u32 },
+    // 2: start_of_coded_interval, 共 1 << target_bit_depth 项
+    UserTable(Vec<u32>),
+    // 3: ( coded_pivot_value, target_pivot_value ), 共 num_pivots 项
+    PiecewiseLinear(Vec<(u32, u32)>),
+    // 4
+    LuminanceDynamicRange(LuminanceDynamicRange),
+    // tone_map_model_id 大于 4 时解码器忽略其余的语法元素
+    Reserved(u32),
+}
+
+// D.1.24 Tone mapping information SEI message syntax
+// tone_map_cancel_flag 为 1 时, 其后的语法元素不存在 ( 取 0, model 为 None )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToneMappingInfo {
+    pub tone_map_id: u32,
+    pub tone_map_cancel_flag: bool,
+    pub tone_map_repetition_period: u32,
+    pub coded_data_bit_depth: u8,
+    pub target_bit_depth: u8,
+    pub model: Option<ToneMappingModel>,
+}
+
+impl ToneMappingInfo {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let tone_map_id = reader.read_ue()?;
+        let tone_map_cancel_flag = reader.read_flag()?;
+        let mut tone_mapping = Self {
+            tone_map_id: tone_map_id,
+            tone_map_cancel_flag: tone_map_cancel_flag,
+            tone_map_repetition_period: 0,
+            coded_data_bit_depth: 0,
+            target_bit_depth: 0,
+            model: None,
+        };
+        if tone_map_cancel_flag {
+            return Ok(tone_mapping);
+        }
+
+        tone_mapping.tone_map_repetition_period = reader.read_ue()?;
+        if tone_mapping.tone_map_repetition_period > 16384 {
+            return Err(error::malformed("tone_map_repetition_period out of range"));
+        }
+        tone_mapping.coded_data_bit_depth = reader.read_bits(8)? as u8;
+        tone_mapping.target_bit_depth = reader.read_bits(8)? as u8;
+        if tone_mapping.coded_data_bit_depth < 1 || tone_mapping.coded_data_bit_depth > 32 {
+            return Err(error::malformed("coded_data_bit_depth out of range"));
+        }
+        if tone_mapping.target_bit_depth < 1 || tone_mapping.target_bit_depth > 16 {
+            return Err(error::malformed("target_bit_depth out of range"));
+        }
+        let (coded_bits, target_bits) = tone_mapping.value_bits();
+
+        let tone_map_model_id = reader.read_ue()?;
+        let model = match tone_map_model_id {
+            0 => ToneMappingModel::Linear {
+                min_value: reader.read_bits(32)?,
+                max_value: reader.read_bits(32)?,
+            },
+            1 => ToneMappingModel::Sigmoid {
+                sigmoid_midpoint: reader.read_bits(32)?,
+                sigmoid_width: reader.read_bits(32)?,
+            },
+            2 => {
+                let mut start_of_coded_interval = Vec::with_capacity(1 << tone_mapping.target_bit_depth);
+                for _ in 0..1u32 << tone_mapping.target_bit_depth {
+                    start_of_coded_interval.push(reader.read_bits(coded_bits)?);
+                }
+                ToneMappingModel::UserTable(start_of_coded_interval)
+            },
+            3 => {
+                let num_pivots = reader.read_bits(16)?;
+                let mut pivots = Vec::with_capacity(num_pivots as usize);
+                for _ in 0..num_pivots {
+                    let coded_pivot_value = reader.read_bits(coded_bits)?;
+                    let target_pivot_value = reader.read_bits(target_bits)?;
+                    pivots.push((coded_pivot_value, target_pivot_value));
+                }
+                ToneMappingModel::PiecewiseLinear(pivots)
+            },
+            4 => {
+                let camera_iso_speed_idc = reader.read_bits(8)? as u8;
+                let camera_iso_speed_value = if camera_iso_speed_idc == 255 { Some(reader.read_bits(32)?) } else { None };
+                let exposure_index_idc = reader.read_bits(8)? as u8;
+                let exposure_index_value = if exposure_index_idc == 255 { Some(reader.read_bits(32)?) } else { None };
+                ToneMappingModel::LuminanceDynamicRange(LuminanceDynamicRange {
+                    camera_iso_speed_idc: camera_iso_speed_idc,
+                    camera_iso_speed_value: camera_iso_speed_value,
+                    exposure_index_idc: exposure_index_idc,
+                    exposure_index_value: exposure_index_value,
+                    exposure_compensation_value_sign_flag: reader.read_flag()?,
+                    exposure_compensation_value_numerator: reader.read_bits(16)? as u16,
+                    exposure_compensation_value_denom_idc: reader.read_bits(16)? as u16,
+                    ref_screen_luminance_white: reader.read_bits(32)?,
+                    extended_range_white_level: reader.read_bits(32)?,
+                    nominal_black_level_code_value: reader.read_bits(16)? as u16,
+                    nominal_white_level_code_value: reader.read_bits(16)? as u16,
+                    extended_white_level_code_value: reader.read_bits(16)? as u16,
+                })
+            },
+            n => ToneMappingModel::Reserved(n),
+        };
+        tone_mapping.model = Some(model);
+
+        Ok(tone_mapping)
+    }
+
+    // u(v): 按字节取整的 coded_data_bit_depth 与 target_bit_depth
+    fn value_bits(&self) -> (u32, u32) {
+        ((u32::from(self.coded_data_bit_depth) + 7) >> 3 << 3, (u32::from(self.target_bit_depth) + 7) >> 3 << 3)
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_ue(self.tone_map_id);
+        writer.write_flag(self.tone_map_cancel_flag);
+        let model = match self.model {
+            Some(ref model) if !self.tone_map_cancel_flag => model,
+            _ => return,
+        };
+
+        writer.write_ue(self.tone_map_repetition_period);
+        writer.write_bits(8, u32::from(self.coded_data_bit_depth));
+        writer.write_bits(8, u32::from(self.target_bit_depth));
+        let (coded_bits, target_bits) = self.value_bits();
+
+        match *model {
+            ToneMappingModel::Linear { min_value, max_value } => {
+                writer.write_ue(0);
+                writer.write_bits(32, min_value);
+                writer.write_bits(32, max_value);
+            },
+            ToneMappingModel::Sigmoid { sigmoid_midpoint, sigmoid_width } => {
+                writer.write_ue(1);
+                writer.write_bits(32, sigmoid_midpoint);
+                writer.write_bits(32, sigmoid_width);
+            },
+            ToneMappingModel::UserTable(ref start_of_coded_interval) => {
+                writer.write_ue(2);
+                for &value in start_of_coded_interval.iter() {
+                    writer.write_bits(coded_bits, value);
+                }
+            },
+            ToneMappingModel::PiecewiseLinear(ref pivots) => {
+                writer.write_ue(3);
+                writer.write_bits(16, pivots.len() as u32);
+                for &(coded_pivot_value, target_pivot_value) in pivots.iter() {
+                    writer.write_bits(coded_bits, coded_pivot_value);
+                    writer.write_bits(target_bits, target_pivot_value);
+                }
+            },
+            ToneMappingModel::LuminanceDynamicRange(ref range) => {
+                writer.write_ue(4);
+                writer.write_bits(8, u32::from(range.camera_iso_speed_idc));
+                if let Some(value) = range.camera_iso_speed_value {
+                    writer.write_bits(32, value);
+                }
+                writer.write_bits(8, u32::from(range.exposure_index_idc));
+                if let Some(value) = range.exposure_index_value {
+                    writer.write_bits(32, value);
+                }
+                writer.write_flag(range.exposure_compensation_value_sign_flag);
+                writer.write_bits(16, u32::from(range.exposure_compensation_value_numerator));
+                writer.write_bits(16, u32::from(range.exposure_compensation_value_denom_idc));
+                writer.write_bits(32, range.ref_screen_luminance_white);
+                writer.write_bits(32, range.extended_range_white_level);
+                writer.write_bits(16, u32::from(range.nominal_black_level_code_value));
+                writer.write_bits(16, u32::from(range.nominal_white_level_code_value));
+                writer.write_bits(16, u32::from(range.extended_white_level_code_value));
+            },
+            ToneMappingModel::Reserved(tone_map_model_id) => writer.write_ue(tone_map_model_id),
+        }
+    }
+}
+
+// Table D-8 – Definition of frame_packing_arrangement_type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePackingType {
+    Checkerboard,
+    ColumnInterleaving,
+    RowInterleaving,
+    SideBySide,
+    TopBottom,
+    // 两个视图按时间交替出现
+    FrameAlternation,
+    // 只有一个视图 ( 2D )
+    TwoDimensional,
+    Tile,
+    Reserved(u8),
+}
+
+impl From<u8> for FramePackingType {
+    fn from(value: u8) -> Self {
+        use self::FramePackingType::*;
+
+        match value {
+            0 => Checkerboard,
+            1 => ColumnInterleaving,
+            2 => RowInterleaving,
+            3 => SideBySide,
+            4 => TopBottom,
+            5 => FrameAlternation,
+            6 => TwoDimensional,
+            7 => Tile,
+            n => Reserved(n),
+        }
+    }
+}
+
+// D.1.26 Frame packing arrangement SEI message syntax
+// frame_packing_arrangement_cancel_flag 为 1 时, 其后的语法元素不存在 ( 取 0 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramePackingArrangement {
+    pub frame_packing_arrangement_id: u32,
+    pub frame_packing_arrangement_cancel_flag: bool,
+    pub frame_packing_arrangement_type: u8,
+    pub quincunx_sampling_flag: bool,
+    // 1: frame 0 为左视图, 2: frame 0 为右视图
+    pub content_interpretation_type: u8,
+    pub spatial_flipping_flag: bool,
+    pub frame0_flipped_flag: bool,
+    pub field_views_flag: bool,
+    pub current_frame_is_frame0_flag: bool,
+    pub frame0_self_contained_flag: bool,
+    pub frame1_self_contained_flag: bool,
+    // frame0_grid_position_x, frame0_grid_position_y, frame1_grid_position_x, frame1_grid_position_y;
+    // 只在 quincunx_sampling_flag 为 0 且不是 frame alternation 时存在
+    pub frame_grid_positions: Option<[u8; 4]>,
+    // 0: 只作用于当前访问单元, 1: 一直持续, 大于 1: 在这么多帧内会重复出现
+    pub frame_packing_arrangement_repetition_period: u32,
+    pub frame_packing_arrangement_extension_flag: bool,
+}
+
+impl FramePackingArrangement {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let frame_packing_arrangement_id = reader.read_ue()?;
+        let frame_packing_arrangement_cancel_flag = reader.read_flag()?;
+        let mut arrangement = Self {
+            frame_packing_arrangement_id: frame_packing_arrangement_id,
+            frame_packing_arrangement_cancel_flag: frame_packing_arrangement_cancel_flag,
+            frame_packing_arrangement_type: 0,
+            quincunx_sampling_flag: false,
+            content_interpretation_type: 0,
+            spatial_flipping_flag: false,
+            frame0_flipped_flag: false,
+            field_views_flag: false,
+            current_frame_is_frame0_flag: false,
+            frame0_self_contained_flag: false,
+            frame1_self_contained_flag: false,
+            frame_grid_positions: None,
+            frame_packing_arrangement_repetition_period: 0,
+            frame_packing_arrangement_extension_flag: false,
+        };
+
+        if !frame_packing_arrangement_cancel_flag {
+            arrangement.frame_packing_arrangement_type = reader.read_bits(7)? as u8;
+            arrangement.quincunx_sampling_flag = reader.read_flag()?;
+            arrangement.content_interpretation_type = reader.read_bits(6)? as u8;
+            arrangement.spatial_flipping_flag = reader.read_flag()?;
+            arrangement.frame0_flipped_flag = reader.read_flag()?;
+            arrangement.field_views_flag = reader.read_flag()?;
+            arrangement.current_frame_is_frame0_flag = reader.read_flag()?;
+            arrangement.frame0_self_contained_flag = reader.read_flag()?;
+            arrangement.frame1_self_contained_flag = reader.read_flag()?;
+
+            if !arrangement.quincunx_sampling_flag && arrangement.frame_packing_arrangement_type != 5 {
+                let mut positions = [0u8; 4];
+                for position in positions.iter_mut() {
+                    *position = reader.read_bits(4)? as u8;
+                }
+                arrangement.frame_grid_positions = Some(positions);
+            }
+
+            // frame_packing_arrangement_reserved_byte
+            reader.skip_bits(8)?;
+            arrangement.frame_packing_arrangement_repetition_period = reader.read_ue()?;
+            if arrangement.frame_packing_arrangement_repetition_period > 16384 {
+                return Err(error::malformed("frame_packing_arrangement_repetition_period out of range"));
+            }
+        }
+        arrangement.frame_packing_arrangement_extension_flag = reader.read_flag()?;
+
+        Ok(arrangement)
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_ue(self.frame_packing_arrangement_id);
+        writer.write_flag(self.frame_packing_arrangement_cancel_flag);
+        if !self.frame_packing_arrangement_cancel_flag {
+            writer.write_bits(7, u32::from(self.frame_packing_arrangement_type));
+            writer.write_flag(self.quincunx_sampling_flag);
+            writer.write_bits(6, u32::from(self.content_interpretation_type));
+            writer.write_flag(self.spatial_flipping_flag);
+            writer.write_flag(self.frame0_flipped_flag);
+            writer.write_flag(self.field_views_flag);
+            writer.write_flag(self.current_frame_is_frame0_flag);
+            writer.write_flag(self.frame0_self_contained_flag);
+            writer.write_flag(self.frame1_self_contained_flag);
+            if !self.quincunx_sampling_flag && self.frame_packing_arrangement_type != 5 {
+                for &position in self.frame_grid_positions.unwrap_or_default().iter() {
+                    writer.write_bits(4, u32::from(position));
+                }
+            }
+            writer.write_bits(8, 0);
+            writer.write_ue(self.frame_packing_arrangement_repetition_period);
+        }
+        writer.write_flag(self.frame_packing_arrangement_extension_flag);
+    }
+
+    pub fn packing_type(&self) -> FramePackingType {
+        FramePackingType::from(self.frame_packing_arrangement_type)
+    }
+}
+
+// D.1.27 Display orientation SEI message syntax
+// display_orientation_cancel_flag 为 1 时, 其后的语法元素不存在 ( 取 0 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayOrientation {
+    pub display_orientation_cancel_flag: bool,
+    pub hor_flip: bool,
+    pub ver_flip: bool,
+    // 逆时针旋转的角度, 以 2^-16 * 360 度为单位
+    pub anticlockwise_rotation: u16,
+    // 与 frame_packing_arrangement_repetition_period 的含义相同
+    pub display_orientation_repetition_period: u32,
+    pub display_orientation_extension_flag: bool,
 }
 
-impl SeiMessage for SeiAnyMessage {
-    fn kind(&self) -> SeiMessageKind {
-        self.kind
+impl DisplayOrientation {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let display_orientation_cancel_flag = reader.read_flag()?;
+        let mut orientation = Self {
+            display_orientation_cancel_flag: display_orientation_cancel_flag,
+            hor_flip: false,
+            ver_flip: false,
+            anticlockwise_rotation: 0,
+            display_orientation_repetition_period: 0,
+            display_orientation_extension_flag: false,
+        };
+
+        if !display_orientation_cancel_flag {
+            orientation.hor_flip = reader.read_flag()?;
+            orientation.ver_flip = reader.read_flag()?;
+            orientation.anticlockwise_rotation = reader.read_bits(16)? as u16;
+            orientation.display_orientation_repetition_period = reader.read_ue()?;
+            if orientation.display_orientation_repetition_period > 16384 {
+                return Err(error::malformed("display_orientation_repetition_period out of range"));
+            }
+            orientation.display_orientation_extension_flag = reader.read_flag()?;
+        }
+
+        Ok(orientation)
     }
 
-    fn len(&self) -> usize {
-        self.size
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_flag(self.display_orientation_cancel_flag);
+        if !self.display_orientation_cancel_flag {
+            writer.write_flag(self.hor_flip);
+            writer.write_flag(self.ver_flip);
+            writer.write_bits(16, u32::from(self.anticlockwise_rotation));
+            writer.write_ue(self.display_orientation_repetition_period);
+            writer.write_flag(self.display_orientation_extension_flag);
+        }
     }
 
-    fn as_bytes(&self) -> &[u8] {
-        &self.body[..]
+    // 逆时针旋转的角度 ( 度 ), 先翻转再旋转
+    pub fn rotation_degrees(&self) -> f64 {
+        f64::from(self.anticlockwise_rotation) * 360.0 / 65536.0
+    }
+}
+
+// D.1.29 Mastering display colour volume SEI message syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasteringDisplayColourVolume {
+    // 色度坐标以 0.00002 为单位, c 通常按 G, B, R 的顺序 ( SMPTE ST 2086 )
+    pub display_primaries_x: [u16; 3],
+    pub display_primaries_y: [u16; 3],
+    pub white_point_x: u16,
+    pub white_point_y: u16,
+    // 以 0.0001 cd/m2 为单位
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+
+impl MasteringDisplayColourVolume {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let mut display_primaries_x = [0u16; 3];
+        let mut display_primaries_y = [0u16; 3];
+        for c in 0..3 {
+            display_primaries_x[c] = reader.read_bits(16)? as u16;
+            display_primaries_y[c] = reader.read_bits(16)? as u16;
+        }
+        let white_point_x = reader.read_bits(16)? as u16;
+        let white_point_y = reader.read_bits(16)? as u16;
+        let max_display_mastering_luminance = reader.read_bits(32)?;
+        let min_display_mastering_luminance = reader.read_bits(32)?;
+
+        Ok(Self {
+            display_primaries_x: display_primaries_x,
+            display_primaries_y: display_primaries_y,
+            white_point_x: white_point_x,
+            white_point_y: white_point_y,
+            max_display_mastering_luminance: max_display_mastering_luminance,
+            min_display_mastering_luminance: min_display_mastering_luminance,
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        for c in 0..3 {
+            writer.write_bits(16, u32::from(self.display_primaries_x[c]));
+            writer.write_bits(16, u32::from(self.display_primaries_y[c]));
+        }
+        writer.write_bits(16, u32::from(self.white_point_x));
+        writer.write_bits(16, u32::from(self.white_point_y));
+        writer.write_bits(32, self.max_display_mastering_luminance);
+        writer.write_bits(32, self.min_display_mastering_luminance);
+    }
+
+    // cd/m2
+    pub fn max_luminance(&self) -> f64 {
+        self.max_display_mastering_luminance as f64 * 0.0001
+    }
+
+    pub fn min_luminance(&self) -> f64 {
+        self.min_display_mastering_luminance as f64 * 0.0001
+    }
+
+    // CIE 1931 的 ( x, y )
+    pub fn primary(&self, c: usize) -> (f64, f64) {
+        (self.display_primaries_x[c] as f64 * 0.00002, self.display_primaries_y[c] as f64 * 0.00002)
+    }
+
+    pub fn white_point(&self) -> (f64, f64) {
+        (self.white_point_x as f64 * 0.00002, self.white_point_y as f64 * 0.00002)
+    }
+}
+
+// D.1.31 Content light level information SEI message syntax: 以 cd/m2 为单位, 0 表示未知
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevelInfo {
+    // MaxCLL
+    pub max_content_light_level: u16,
+    // MaxFALL
+    pub max_pic_average_light_level: u16,
+}
+
+impl ContentLightLevelInfo {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        Ok(Self {
+            max_content_light_level: reader.read_bits(16)? as u16,
+            max_pic_average_light_level: reader.read_bits(16)? as u16,
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_bits(16, u32::from(self.max_content_light_level));
+        writer.write_bits(16, u32::from(self.max_pic_average_light_level));
+    }
+}
+
+// D.1.33 Alternative transfer characteristics SEI message syntax: 取值与 VUI 的 transfer_characteristics 相同 ( Table E-4 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlternativeTransferCharacteristics {
+    pub preferred_transfer_characteristics: u8,
+}
+
+impl AlternativeTransferCharacteristics {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        Ok(Self {
+            preferred_transfer_characteristics: reader.read_bits(8)? as u8,
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_bits(8, u32::from(self.preferred_transfer_characteristics));
+    }
+}
+
+// D.1.34 Ambient viewing environment SEI message syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmbientViewingEnvironment {
+    // 以 0.0001 lux 为单位, 不应为 0 ( 语义约束, 见 HdrMetadata::hdr10_violations )
+    pub ambient_illuminance: u32,
+    // 以 0.00002 为单位
+    pub ambient_light_x: u16,
+    pub ambient_light_y: u16,
+}
+
+impl AmbientViewingEnvironment {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = RbspReader::new(bytes);
+
+        let ambient_illuminance = reader.read_bits(32)?;
+        let ambient_light_x = reader.read_bits(16)? as u16;
+        let ambient_light_y = reader.read_bits(16)? as u16;
+
+        Ok(Self {
+            ambient_illuminance: ambient_illuminance,
+            ambient_light_x: ambient_light_x,
+            ambient_light_y: ambient_light_y,
+        })
+    }
+
+    pub fn write(&self, writer: &mut RbspWriter) {
+        writer.write_bits(32, self.ambient_illuminance);
+        writer.write_bits(16, u32::from(self.ambient_light_x));
+        writer.write_bits(16, u32::from(self.ambient_light_y));
+    }
+}
+
+// D.1.1 General SEI message syntax: sei_payload( payloadType, payloadSize )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeiPayload {
+    UserDataRegisteredItuTT35(UserDataRegistered),
+    UserDataUnregistered(UserDataUnregistered),
+    RecoveryPoint(RecoveryPoint),
+    FilmGrainCharacteristics(FilmGrainCharacteristics),
+    StereoVideoInfo(StereoVideoInfo),
+    ToneMappingInfo(ToneMappingInfo),
+    FramePackingArrangement(FramePackingArrangement),
+    DisplayOrientation(DisplayOrientation),
+    MasteringDisplayColourVolume(MasteringDisplayColourVolume),
+    ContentLightLevelInfo(ContentLightLevelInfo),
+    AlternativeTransferCharacteristics(AlternativeTransferCharacteristics),
+    AmbientViewingEnvironment(AmbientViewingEnvironment),
+    // ff_byte 的个数即 payloadSize
+    FillerPayload,
+    // 其它 payload ( 包括依赖 SPS 的 buffering_period 与 pic_timing ) 以及解析失败的 payload 保留原始字节,
+    // 见 SeiMessage::as_bytes 与 SeiMessage::payload_error
+    Raw,
+}
+
+impl SeiPayload {
+    pub fn parse(kind: SeiMessageKind, bytes: &[u8]) -> Result<Self, Error> {
+        let payload = match kind {
+            SeiMessageKind::UserDataRegisteredItuTT35 => SeiPayload::UserDataRegisteredItuTT35(UserDataRegistered::parse(bytes)?),
+            SeiMessageKind::UserDataUnregistered => SeiPayload::UserDataUnregistered(UserDataUnregistered::parse(bytes)?),
+            SeiMessageKind::RecoveryPoint => SeiPayload::RecoveryPoint(RecoveryPoint::parse(bytes)?),
+            SeiMessageKind::FilmGrainCharacteristics => {
+                SeiPayload::FilmGrainCharacteristics(FilmGrainCharacteristics::parse(bytes)?)
+            },
+            SeiMessageKind::StereoVideoInfo => SeiPayload::StereoVideoInfo(StereoVideoInfo::parse(bytes)?),
+            SeiMessageKind::ToneMappingInfo => SeiPayload::ToneMappingInfo(ToneMappingInfo::parse(bytes)?),
+            SeiMessageKind::FramePackingArrangement => {
+                SeiPayload::FramePackingArrangement(FramePackingArrangement::parse(bytes)?)
+            },
+            SeiMessageKind::DisplayOrientation => SeiPayload::DisplayOrientation(DisplayOrientation::parse(bytes)?),
+            SeiMessageKind::MasteringDisplayColourVolume => {
+                SeiPayload::MasteringDisplayColourVolume(MasteringDisplayColourVolume::parse(bytes)?)
+            },
+            SeiMessageKind::ContentLightLevelInfo => SeiPayload::ContentLightLevelInfo(ContentLightLevelInfo::parse(bytes)?),
+            SeiMessageKind::AlternativeTransferCharacteristics => {
+                SeiPayload::AlternativeTransferCharacteristics(AlternativeTransferCharacteristics::parse(bytes)?)
+            },
+            SeiMessageKind::AmbientViewingEnvironment => {
+                SeiPayload::AmbientViewingEnvironment(AmbientViewingEnvironment::parse(bytes)?)
+            },
+            SeiMessageKind::FillerPayload => {
+                if bytes.iter().any(|&byte| byte != 0xFF) {
+                    return Err(error::malformed("ff_byte must be equal to 0xFF"));
+                }
+                SeiPayload::FillerPayload
+            },
+            _ => SeiPayload::Raw,
+        };
+
+        Ok(payload)
+    }
+
+    // 有语法结构的 payload 对应的 payloadType; FillerPayload 与 Raw 为 None
+    pub fn kind(&self) -> Option<SeiMessageKind> {
+        let kind = match *self {
+            SeiPayload::UserDataRegisteredItuTT35(_) => SeiMessageKind::UserDataRegisteredItuTT35,
+            SeiPayload::UserDataUnregistered(_) => SeiMessageKind::UserDataUnregistered,
+            SeiPayload::RecoveryPoint(_) => SeiMessageKind::RecoveryPoint,
+            SeiPayload::FilmGrainCharacteristics(_) => SeiMessageKind::FilmGrainCharacteristics,
+            SeiPayload::StereoVideoInfo(_) => SeiMessageKind::StereoVideoInfo,
+            SeiPayload::ToneMappingInfo(_) => SeiMessageKind::ToneMappingInfo,
+            SeiPayload::FramePackingArrangement(_) => SeiMessageKind::FramePackingArrangement,
+            SeiPayload::DisplayOrientation(_) => SeiMessageKind::DisplayOrientation,
+            SeiPayload::MasteringDisplayColourVolume(_) => SeiMessageKind::MasteringDisplayColourVolume,
+            SeiPayload::ContentLightLevelInfo(_) => SeiMessageKind::ContentLightLevelInfo,
+            SeiPayload::AlternativeTransferCharacteristics(_) => SeiMessageKind::AlternativeTransferCharacteristics,
+            SeiPayload::AmbientViewingEnvironment(_) => SeiMessageKind::AmbientViewingEnvironment,
+            SeiPayload::FillerPayload | SeiPayload::Raw => return None,
+        };
+
+        Some(kind)
+    }
+
+    // sei_payload( payloadType, payloadSize ): 不是字节对齐时写入 bit_equal_to_one 与 bit_equal_to_zero
+    pub fn write(&self, writer: &mut RbspWriter) -> Result<(), Error> {
+        match *self {
+            SeiPayload::UserDataRegisteredItuTT35(ref payload) => payload.write(writer),
+            SeiPayload::UserDataUnregistered(ref payload) => payload.write(writer),
+            SeiPayload::RecoveryPoint(ref payload) => payload.write(writer),
+            SeiPayload::FilmGrainCharacteristics(ref payload) => payload.write(writer),
+            SeiPayload::StereoVideoInfo(ref payload) => payload.write(writer),
+            SeiPayload::ToneMappingInfo(ref payload) => payload.write(writer),
+            SeiPayload::FramePackingArrangement(ref payload) => payload.write(writer),
+            SeiPayload::DisplayOrientation(ref payload) => payload.write(writer),
+            SeiPayload::MasteringDisplayColourVolume(ref payload) => payload.write(writer),
+            SeiPayload::ContentLightLevelInfo(ref payload) => payload.write(writer),
+            SeiPayload::AlternativeTransferCharacteristics(ref payload) => payload.write(writer),
+            SeiPayload::AmbientViewingEnvironment(ref payload) => payload.write(writer),
+            SeiPayload::FillerPayload | SeiPayload::Raw => {
+                return Err(error::malformed("sei payload without syntax must be written from its bytes"));
+            },
+        }
+
+        if !writer.byte_aligned() {
+            writer.write_bit(true);
+            while !writer.byte_aligned() {
+                writer.write_bit(false);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// 7.3.2.3.1 Supplemental enhancement information message syntax ( Page 69 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeiMessage {
+    pub kind: SeiMessageKind,
+    pub payload: SeiPayload,
+    // sei_payload() 的原始字节, 长度为 payloadSize
+    bytes: Vec<u8>,
+    // payload 解析失败 ( 此时为 SeiPayload::Raw ) 的原因
+    payload_error: Option<String>,
+}
+
+impl SeiMessage {
+    pub fn parse(reader: &mut RbspReader) -> Result<Self, Error> {
+        let payload_type = SeiMessage::read_ff_coded(reader)?;
+        let payload_size = SeiMessage::read_ff_coded(reader)? as usize;
+
+        if !reader.byte_aligned() || reader.bits_left() < payload_size * 8 {
+            return Err(error::malformed("sei payload exceeds the rbsp"));
+        }
+
+        let start = reader.position() / 8;
+        let bytes = reader.data()[start..start + payload_size].to_vec();
+        reader.skip_bits(payload_size * 8)?;
+
+        let kind = SeiMessageKind::try_from(payload_type).expect("every payloadType has a kind");
+        Ok(SeiMessage::from_bytes(kind, bytes))
+    }
+
+    // 由 sei_payload() 的原始字节构造 ( 用于 buffering_period, pic_timing 等没有解析的 payload )
+    // 只有 payloadType 与 payloadSize 决定 SEI NALU 的结构, 一个 payload 解析失败不影响同一个 NALU 中的其它 SEI,
+    // 此时保留为 SeiPayload::Raw 并记录原因
+    pub fn from_bytes(kind: SeiMessageKind, bytes: Vec<u8>) -> Self {
+        let (payload, payload_error) = match SeiPayload::parse(kind, &bytes) {
+            Ok(payload) => (payload, None),
+            Err(Error::IoError(error)) => (SeiPayload::Raw, Some(error.to_string())),
+        };
+
+        Self {
+            kind: kind,
+            payload: payload,
+            bytes: bytes,
+            payload_error: payload_error,
+        }
+    }
+
+    // 由解析后的 payload 构造, 与 SeiPayload::parse 对应
+    pub fn new(payload: SeiPayload) -> Result<Self, Error> {
+        let kind = match payload.kind() {
+            Some(kind) => kind,
+            None => return Err(error::malformed("sei payload without syntax must be built from its bytes")),
+        };
+
+        let mut writer = RbspWriter::new();
+        payload.write(&mut writer)?;
+
+        Ok(Self {
+            kind: kind,
+            payload: payload,
+            bytes: writer.into_bytes(),
+            payload_error: None,
+        })
+    }
+
+    // sei_message(): payloadType, payloadSize 与 sei_payload()
+    pub fn write(&self, writer: &mut RbspWriter) {
+        SeiMessage::write_ff_coded(writer, self.payload_type());
+        SeiMessage::write_ff_coded(writer, self.bytes.len() as u32);
+        writer.write_bytes(&self.bytes);
+    }
+
+    fn write_ff_coded(writer: &mut RbspWriter, mut value: u32) {
+        while value >= 0xFF {
+            writer.write_bits(8, 0xFF);
+            value -= 0xFF;
+        }
+        writer.write_bits(8, value);
+    }
+
+    // payloadType 与 payloadSize: 若干个 0xFF ( ff_byte ) 加上最后一个字节
+    fn read_ff_coded(reader: &mut RbspReader) -> Result<u32, Error> {
+        let mut value = 0u32;
+        loop {
+            let byte = reader.read_bits(8)?;
+            value = value.checked_add(byte)
+                .ok_or_else(|| error::malformed("sei payloadType or payloadSize is too large"))?;
+            if byte != 0xFF {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn payload_type(&self) -> u32 {
+        self.kind.into()
+    }
+
+    pub fn payload_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn payload_error(&self) -> Option<&str> {
+        self.payload_error.as_deref()
+    }
+
+    // buffering_period 需要参数集才能解析, 不是 buffering_period 时返回 None
+    pub fn buffering_period(&self, parameter_sets: &ParameterSets) -> Result<Option<BufferingPeriod>, Error> {
+        if self.kind != SeiMessageKind::BufferingPeriod {
+            return Ok(None);
+        }
+
+        BufferingPeriod::parse(&self.bytes, parameter_sets).map(Some)
+    }
+
+    // pic_timing 需要当前激活的 SPS, 不是 pic_timing 时返回 None
+    pub fn pic_timing(&self, sps: &SequenceParameterSet) -> Result<Option<PicTiming>, Error> {
+        if self.kind != SeiMessageKind::PicTiming {
+            return Ok(None);
+        }
+
+        PicTiming::parse(&self.bytes, sps).map(Some)
     }
 }
 
@@ -248,56 +1542,244 @@ impl SeiMessage for SeiAnyMessage {
 // Syntax: 7.3.2.3 ( Page 69 )
 // Semantics: 7.4.2.3 ( Page 105 )
 // SEI Payloads: Annex D ( Page 350 )
-// SEI
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SupplementalEnhancementInformation {
-    messages: Vec<Box<dyn SeiMessage>>,
+    messages: Vec<SeiMessage>,
 }
 
 impl SupplementalEnhancementInformation {
-    pub fn parse<R: Read, E: Endianness>(bitreader: &mut BitReader<R, E>) -> Result<Self, ()> {
-        
-        let mut messages: Vec<Box<dyn SeiMessage>> = vec![];
+    // sei_rbsp(): 至少一个 sei_message(), 直到只剩下 rbsp_trailing_bits()
+    pub fn parse(reader: &mut RbspReader) -> Result<Self, Error> {
+        let mut messages = vec![];
 
         loop {
-            let mut payload_type = 0u32;
-            let mut payload_size = 0usize;
+            messages.push(SeiMessage::parse(reader)?);
 
-            let mut ff_byte = bitreader.read::<u8>(8).unwrap();
-            while ff_byte == 0xff {
-                payload_type += 255;
-                ff_byte = bitreader.read::<u8>(8).unwrap();
-            }
-            payload_type += ff_byte as u32;
-
-            
-            ff_byte = bitreader.read::<u8>(8).unwrap();
-            while ff_byte == 0xff {
-                payload_size += 255;
-                ff_byte = bitreader.read::<u8>(8).unwrap();
+            if !reader.more_rbsp_data() {
+                break;
             }
+        }
 
-            payload_size += ff_byte as usize;
+        reader.rbsp_trailing_bits()?;
 
-            let mut payload_body: Vec<u8> = vec![0u8; payload_size];
+        Ok(Self {
+            messages: messages,
+        })
+    }
 
-            bitreader.read_bytes(&mut payload_body).unwrap();
+    // 至少需要一个 sei_message()
+    pub fn new(messages: Vec<SeiMessage>) -> Result<Self, Error> {
+        if messages.is_empty() {
+            return Err(error::malformed("sei_rbsp requires at least one sei_message"));
+        }
 
-            let message = SeiAnyMessage {
-                kind: SeiMessageKind::try_from(payload_type).unwrap(),
-                size: payload_size,
-                body: payload_body,
-            };
+        Ok(Self {
+            messages: messages,
+        })
+    }
 
-            messages.push(Box::new(message));
+    pub fn messages(&self) -> &[SeiMessage] {
+        &self.messages
+    }
 
-            // more_rbsp_data()  msg[offset] != 0x80
+    pub fn into_messages(self) -> Vec<SeiMessage> {
+        self.messages
+    }
 
+    // sei_rbsp()
+    pub fn write(&self, writer: &mut RbspWriter) {
+        for message in self.messages.iter() {
+            message.write(writer);
         }
-        
-        Ok(SupplementalEnhancementInformation {
-            messages: messages,
-        })
+        writer.rbsp_trailing_bits();
+    }
+
+    // 完整的 SEI NALU ( nal_ref_idc 为 0, 不包括起始码 ), RBSP 已插入 emulation_prevention_three_byte
+    pub fn to_nalu_bytes(&self) -> Vec<u8> {
+        let mut writer = RbspWriter::new();
+        self.write(&mut writer);
+
+        let mut bytes = vec![0x06];
+        bytes.extend(rbsp_to_ebsp(writer.as_bytes()));
+        bytes
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use crate::rbsp::{
+        SupplementalEnhancementInformation, SeiMessageKind, SeiPayload, RecoveryPoint, ParameterSets, ClockTimestamp,
+        SeiMessage, FilmGrainIntensityInterval, ToneMappingModel,
+    };
+    use crate::slice::test::Writer;
+
+    #[test]
+    fn test_sei_messages() {
+        let mut writer = Writer::new(0x06);
+        // user_data_unregistered: uuid + "x264"
+        writer.u(8, 5).u(8, 20);
+        for i in 0..16 {
+            writer.u(8, i);
+        }
+        writer.u(8, b'x' as u32).u(8, b'2' as u32).u(8, b'6' as u32).u(8, b'4' as u32);
+        // recovery_point: recovery_frame_cnt 0, exact_match_flag 1, bit_equal_to_one, bit_equal_to_zero
+        writer.u(8, 6).u(8, 1).bits("11000").bits("100");
+        // payloadSize 为 0 的 recovery_point 无法解析, 保留为 Raw 而不影响其它 SEI
+        writer.u(8, 6).u(8, 0);
+        // pic_timing ( payloadType 1 ) 保留原始字节, payloadSize 为 255 + 2 时使用 ff_byte
+        writer.u(8, 1).u(8, 0xFF).u(8, 2);
+        for _ in 0..257 {
+            writer.u(8, 0x5A);
+        }
+        let nalu = writer.finish();
+
+        let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+        let messages = sei.messages();
+        assert_eq!(messages.len(), 4);
+
+        match messages[0].payload {
+            SeiPayload::UserDataUnregistered(ref user_data) => {
+                assert_eq!(user_data.uuid_iso_iec_11578[15], 15);
+                assert_eq!(&user_data.payload[..], b"x264");
+            },
+            ref payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        assert_eq!(messages[1].kind, SeiMessageKind::RecoveryPoint);
+        assert_eq!(messages[1].payload, SeiPayload::RecoveryPoint(RecoveryPoint {
+            recovery_frame_cnt: 0,
+            exact_match_flag: true,
+            broken_link_flag: false,
+            changing_slice_group_idc: 0,
+        }));
+
+        assert_eq!(messages[1].payload_error(), None);
+
+        assert_eq!(messages[2].kind, SeiMessageKind::RecoveryPoint);
+        assert_eq!(messages[2].payload, SeiPayload::Raw);
+        assert!(messages[2].payload_error().is_some());
+
+        assert_eq!(messages[3].kind, SeiMessageKind::PicTiming);
+        assert_eq!(messages[3].payload, SeiPayload::Raw);
+        assert_eq!(messages[3].payload_size(), 257);
+        assert_eq!(messages[3].payload_type(), 1);
+    }
+
+    #[test]
+    fn test_buffering_period_and_pic_timing() {
+        // VUI: 1001 / 60000, NAL HRD ( 24 比特的延时, time_offset_length 8 ), pic_struct_present_flag
+        let mut parameter_sets = ParameterSets::new();
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 1)
+            .u(1, 0).u(1, 0).u(1, 0).u(1, 0)
+            .u(1, 1).u(32, 1001).u(32, 60000).u(1, 1)
+            .u(1, 1).ue(0).u(4, 0).u(4, 0).ue(999).ue(999).u(1, 0).u(5, 23).u(5, 23).u(5, 23).u(5, 8)
+            .u(1, 0).u(1, 0).u(1, 1).u(1, 0)
+            .finish();
+        assert!(parameter_sets.update(&sps).unwrap());
+
+        let mut writer = Writer::new(0x06);
+        // buffering_period: seq_parameter_set_id 0, initial_cpb_removal_delay 90000, offset 0, 再补齐到字节边界
+        writer.u(8, 0).u(8, 7).bits("1").u(24, 90000).u(24, 0).bits("1000000");
+        // pic_timing: cpb_removal_delay 2, dpb_output_delay 4, pic_struct 3 ( 两个时间戳, 只有第二个存在 )
+        writer.u(8, 1).u(8, 13).u(24, 2).u(24, 4).u(4, 3).bits("0").bits("1")
+            .u(2, 1).bits("0").u(5, 4).bits("1").bits("0").bits("0").u(8, 29).u(6, 59).u(6, 1).u(5, 10).u(8, 0xFE)
+            .bits("100000");
+        let nalu = writer.finish();
+
+        let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+        let messages = sei.messages();
+        assert_eq!(messages[0].payload, SeiPayload::Raw);
+
+        let buffering_period = messages[0].buffering_period(&parameter_sets).unwrap().unwrap();
+        assert_eq!(buffering_period.seq_parameter_set_id, 0);
+        assert_eq!(buffering_period.nal_initial_cpb_removal_delays.len(), 1);
+        assert_eq!(buffering_period.nal_initial_cpb_removal_delays[0].initial_cpb_removal_delay, 90000);
+        assert!(buffering_period.vcl_initial_cpb_removal_delays.is_empty());
+        assert_eq!(messages[0].pic_timing(parameter_sets.sps(0).unwrap()).unwrap(), None);
+
+        let pic_timing = messages[1].pic_timing(parameter_sets.sps(0).unwrap()).unwrap().unwrap();
+        assert_eq!(pic_timing.cpb_removal_delay, Some(2));
+        assert_eq!(pic_timing.dpb_output_delay, Some(4));
+        assert_eq!(pic_timing.pic_struct, Some(3));
+        assert_eq!(pic_timing.clock_timestamps.len(), 2);
+        assert_eq!(pic_timing.clock_timestamps[0], None);
+
+        let timestamp = pic_timing.clock_timestamps[1].unwrap();
+        assert_eq!(timestamp, ClockTimestamp {
+            ct_type: 1,
+            nuit_field_based_flag: false,
+            counting_type: 4,
+            full_timestamp_flag: true,
+            discontinuity_flag: false,
+            cnt_dropped_flag: false,
+            n_frames: 29,
+            seconds_value: Some(59),
+            minutes_value: Some(1),
+            hours_value: Some(10),
+            time_offset: -2,
+        });
+        assert_eq!(timestamp.clock_timestamp(1001, 60000), ((10 * 60 + 1) * 60 + 59) * 60000i64 + 29 * 1001 - 2);
+    }
+
+    #[test]
+    fn test_film_grain_and_tone_mapping() {
+        let mut writer = Writer::new(0x06);
+        // film_grain_characteristics: 频率滤波, 相加, log2_scale_factor 6, 只有 Y 分量 ( 一个区间, 三个模型参数 )
+        writer.u(8, 19).u(8, 7).bits("0").u(2, 0).bits("0").u(2, 0).u(4, 6).bits("100")
+            .u(8, 0).u(3, 2).u(8, 0).u(8, 255).se(5).se(-3).se(0).ue(1);
+        // tone_mapping_info: 10 比特到 8 比特的分段线性映射, 两个 pivot ( coded 按 16 比特写入 )
+        writer.u(8, 23).u(8, 11).ue(0).bits("0").ue(0).u(8, 10).u(8, 8).ue(3)
+            .u(16, 2).u(16, 64).u(8, 16).u(16, 1023).u(8, 255);
+        let nalu = writer.finish();
+
+        let sei = nalu.payload_downcast_ref::<SupplementalEnhancementInformation>();
+        let messages = sei.messages();
+        assert_eq!(messages.len(), 2);
+
+        match messages[0].payload {
+            SeiPayload::FilmGrainCharacteristics(ref film_grain) => {
+                assert!(!film_grain.film_grain_characteristics_cancel_flag);
+                assert_eq!(film_grain.log2_scale_factor, 6);
+                assert_eq!(film_grain.separate_colour_description, None);
+                assert_eq!(film_grain.components[0], Some(vec![FilmGrainIntensityInterval {
+                    intensity_interval_lower_bound: 0,
+                    intensity_interval_upper_bound: 255,
+                    comp_model_value: vec![5, -3, 0],
+                }]));
+                assert!(film_grain.components[1].is_none() && film_grain.components[2].is_none());
+                assert_eq!(film_grain.film_grain_characteristics_repetition_period, 1);
+            },
+            ref payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        match messages[1].payload {
+            SeiPayload::ToneMappingInfo(ref tone_mapping) => {
+                assert_eq!(tone_mapping.coded_data_bit_depth, 10);
+                assert_eq!(tone_mapping.target_bit_depth, 8);
+                assert_eq!(tone_mapping.model, Some(ToneMappingModel::PiecewiseLinear(vec![(64, 16), (1023, 255)])));
+            },
+            ref payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        // 保留的 model_id 不是语法错误
+        let reserved = SeiMessage::from_bytes(SeiMessageKind::FilmGrainCharacteristics, vec![0x60, 0x04]);
+        match reserved.payload {
+            SeiPayload::FilmGrainCharacteristics(ref film_grain) => {
+                assert_eq!(film_grain.model_id, 3);
+                assert!(film_grain.is_reserved());
+            },
+            ref payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        // 重新封装时写出的字节与原始的相同
+        for message in messages {
+            let written = SeiMessage::new(message.payload.clone()).unwrap();
+            assert_eq!(written.as_bytes(), message.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use crate::error::{ self, Error };
+use crate::nalu::{ NaluHeader, NaluKind, NaluRefIdc };
+use crate::rbsp::{ RbspReader, ParameterSets, SequenceParameterSet, PictureParameterSet };
+
+
+use std::convert::TryFrom;
+
+
+// Table 7-6 – Name association to slice_type ( Page 118 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceType {
+    P,
+    B,
+    I,
+    SP,
+    SI,
+}
+
+impl TryFrom<u32> for SliceType {
+    type Error = Error;
+
+    // slice_type 5 .. 9 表示当前图像的所有 slice 均为同一类型
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value % 5 {
+            _ if value > 9 => Err(error::malformed("slice_type out of range")),
+            0 => Ok(SliceType::P),
+            1 => Ok(SliceType::B),
+            2 => Ok(SliceType::I),
+            3 => Ok(SliceType::SP),
+            _ => Ok(SliceType::SI),
+        }
+    }
+}
+
+impl Into<u32> for SliceType {
+    fn into(self) -> u32 {
+        match self {
+            SliceType::P => 0,
+            SliceType::B => 1,
+            SliceType::I => 2,
+            SliceType::SP => 3,
+            SliceType::SI => 4,
+        }
+    }
+}
+
+impl SliceType {
+    pub fn is_intra(&self) -> bool {
+        *self == SliceType::I || *self == SliceType::SI
+    }
+
+    // P 或 SP
+    pub fn is_predictive(&self) -> bool {
+        *self == SliceType::P || *self == SliceType::SP
+    }
+
+    pub fn is_bipredictive(&self) -> bool {
+        *self == SliceType::B
+    }
+}
+
+
+// 图像的结构 ( 3.30, 3.63, 3.104 ): 帧, 顶场, 底场, 或两个场组成的互补场对。
+// 一个编码图像 ( 即一个 slice header ) 只可能是前三者, 互补场对用于 DPB 中的项与输出的图像。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PictureStructure {
+    Frame,
+    TopField,
+    BottomField,
+    FieldPair,
+}
+
+impl PictureStructure {
+    pub fn is_field(&self) -> bool {
+        *self == PictureStructure::TopField || *self == PictureStructure::BottomField
+    }
+
+    // 场的下标: 顶场为 0, 底场为 1
+    pub fn parity(&self) -> Option<usize> {
+        match *self {
+            PictureStructure::TopField => Some(0),
+            PictureStructure::BottomField => Some(1),
+            _ => None,
+        }
+    }
+
+    // 与 `parity` 相反
+    pub fn from_parity(parity: usize) -> Self {
+        if parity == 0 { PictureStructure::TopField } else { PictureStructure::BottomField }
+    }
+
+    // 另一个场
+    pub fn opposite(&self) -> Self {
+        match *self {
+            PictureStructure::TopField => PictureStructure::BottomField,
+            PictureStructure::BottomField => PictureStructure::TopField,
+            structure => structure,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PictureStructure::Frame => "frame",
+            PictureStructure::TopField => "top field",
+            PictureStructure::BottomField => "bottom field",
+            PictureStructure::FieldPair => "field pair",
+        }
+    }
+}
+
+
+// 7.3.3.1 Reference picture list modification syntax ( Page 73 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefPicListModification {
+    // modification_of_pic_nums_idc == 0
+    SubtractAbsDiffPicNum(u32),
+    // modification_of_pic_nums_idc == 1
+    AddAbsDiffPicNum(u32),
+    // modification_of_pic_nums_idc == 2
+    LongTermPicNum(u32),
+}
+
+impl RefPicListModification {
+    fn parse_list(reader: &mut RbspReader) -> Result<Option<Vec<Self>>, Error> {
+        let ref_pic_list_modification_flag = reader.read_flag()?;
+        if !ref_pic_list_modification_flag {
+            return Ok(None);
+        }
+
+        let mut modifications = vec![];
+        loop {
+            let modification_of_pic_nums_idc = reader.read_ue()?;
+            let modification = match modification_of_pic_nums_idc {
+                0 => RefPicListModification::SubtractAbsDiffPicNum(reader.read_ue()?),
+                1 => RefPicListModification::AddAbsDiffPicNum(reader.read_ue()?),
+                2 => RefPicListModification::LongTermPicNum(reader.read_ue()?),
+                3 => break,
+                _ => return Err(error::malformed("modification_of_pic_nums_idc out of range")),
+            };
+
+            // 每个列表最多 num_ref_idx_lX_active_minus1 + 1 ( <= 32 ) 次修改
+            if modifications.len() > 32 {
+                return Err(error::malformed("too many ref_pic_list_modification operations"));
+            }
+            modifications.push(modification);
+        }
+
+        Ok(Some(modifications))
+    }
+}
+
+
+// 7.3.3.2 Prediction weight table syntax ( Page 74 )
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PredWeight {
+    // ( luma_weight_lX, luma_offset_lX )
+    pub luma: Option<(i32, i32)>,
+    // [ ( chroma_weight_lX, chroma_offset_lX ); Cb, Cr ]
+    pub chroma: Option<[(i32, i32); 2]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredWeightTable {
+    pub luma_log2_weight_denom: u32,
+    pub chroma_log2_weight_denom: Option<u32>,
+    pub l0: Vec<PredWeight>,
+    pub l1: Vec<PredWeight>,
+}
+
+impl PredWeightTable {
+    fn parse(reader: &mut RbspReader, header: &SliceHeader, chroma_array_type: u32) -> Result<Self, Error> {
+        let luma_log2_weight_denom = reader.read_ue()?;
+        if luma_log2_weight_denom > 7 {
+            return Err(error::malformed("luma_log2_weight_denom out of range"));
+        }
+
+        let mut chroma_log2_weight_denom = None;
+        if chroma_array_type != 0 {
+            let denom = reader.read_ue()?;
+            if denom > 7 {
+                return Err(error::malformed("chroma_log2_weight_denom out of range"));
+            }
+            chroma_log2_weight_denom = Some(denom);
+        }
+
+        let parse_list = |reader: &mut RbspReader, num_ref_idx_active_minus1: u32| -> Result<Vec<PredWeight>, Error> {
+            let mut weights = vec![];
+
+            for _ in 0..=num_ref_idx_active_minus1 {
+                let mut weight = PredWeight::default();
+
+                let luma_weight_flag = reader.read_flag()?;
+                if luma_weight_flag {
+                    weight.luma = Some((reader.read_se()?, reader.read_se()?));
+                }
+
+                if chroma_array_type != 0 {
+                    let chroma_weight_flag = reader.read_flag()?;
+                    if chroma_weight_flag {
+                        let cb = (reader.read_se()?, reader.read_se()?);
+                        let cr = (reader.read_se()?, reader.read_se()?);
+                        weight.chroma = Some([cb, cr]);
+                    }
+                }
+
+                weights.push(weight);
+            }
+
+            Ok(weights)
+        };
+
+        let l0 = parse_list(reader, header.num_ref_idx_l0_active_minus1)?;
+        let l1 = if header.slice_type.is_bipredictive() {
+            parse_list(reader, header.num_ref_idx_l1_active_minus1)?
+        } else {
+            vec![]
+        };
+
+        Ok(Self {
+            luma_log2_weight_denom: luma_log2_weight_denom,
+            chroma_log2_weight_denom: chroma_log2_weight_denom,
+            l0: l0,
+            l1: l1,
+        })
+    }
+}
+
+
+// 7.3.3.3 Decoded reference picture marking syntax ( Page 74 )
+// Table 7-9 – Memory management control operation (memory_management_control_operation) values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryManagementControlOperation {
+    // 1
+    MarkShortTermUnused { difference_of_pic_nums_minus1: u32 },
+    // 2
+    MarkLongTermUnused { long_term_pic_num: u32 },
+    // 3
+    MarkShortTermAsLongTerm { difference_of_pic_nums_minus1: u32, long_term_frame_idx: u32 },
+    // 4
+    SetMaxLongTermFrameIdx { max_long_term_frame_idx_plus1: u32 },
+    // 5
+    MarkAllUnused,
+    // 6
+    MarkCurrentAsLongTerm { long_term_frame_idx: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecRefPicMarking {
+    pub no_output_of_prior_pics_flag: Option<bool>,
+    pub long_term_reference_flag: Option<bool>,
+    pub adaptive_ref_pic_marking_mode_flag: Option<bool>,
+    pub operations: Vec<MemoryManagementControlOperation>,
+}
+
+impl DecRefPicMarking {
+    fn parse(reader: &mut RbspReader, idr_pic_flag: bool) -> Result<Self, Error> {
+        use self::MemoryManagementControlOperation::*;
+
+        let mut marking = DecRefPicMarking {
+            no_output_of_prior_pics_flag: None,
+            long_term_reference_flag: None,
+            adaptive_ref_pic_marking_mode_flag: None,
+            operations: vec![],
+        };
+
+        if idr_pic_flag {
+            marking.no_output_of_prior_pics_flag = Some(reader.read_flag()?);
+            marking.long_term_reference_flag = Some(reader.read_flag()?);
+            return Ok(marking);
+        }
+
+        let adaptive_ref_pic_marking_mode_flag = reader.read_flag()?;
+        marking.adaptive_ref_pic_marking_mode_flag = Some(adaptive_ref_pic_marking_mode_flag);
+
+        if adaptive_ref_pic_marking_mode_flag {
+            loop {
+                let memory_management_control_operation = reader.read_ue()?;
+                let operation = match memory_management_control_operation {
+                    0 => break,
+                    1 => MarkShortTermUnused { difference_of_pic_nums_minus1: reader.read_ue()? },
+                    2 => MarkLongTermUnused { long_term_pic_num: reader.read_ue()? },
+                    3 => MarkShortTermAsLongTerm {
+                        difference_of_pic_nums_minus1: reader.read_ue()?,
+                        long_term_frame_idx: reader.read_ue()?,
+                    },
+                    4 => SetMaxLongTermFrameIdx { max_long_term_frame_idx_plus1: reader.read_ue()? },
+                    5 => MarkAllUnused,
+                    6 => MarkCurrentAsLongTerm { long_term_frame_idx: reader.read_ue()? },
+                    _ => return Err(error::malformed("memory_management_control_operation out of range")),
+                };
+
+                if marking.operations.len() > 66 {
+                    return Err(error::malformed("too many memory_management_control_operation"));
+                }
+                marking.operations.push(operation);
+            }
+        }
+
+        Ok(marking)
+    }
+}
+
+
+// Syntax: 7.3.3 ( Page 71 )
+// Semantic: 7.4.3 ( Page 117 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceHeader {
+    pub nal_unit_type: NaluKind,
+    pub nal_ref_idc: NaluRefIdc,
+
+    pub first_mb_in_slice: u32,                 // ue(v)
+    pub slice_type: SliceType,                  // ue(v)
+    pub slice_type_fixed: bool,                 // slice_type > 4
+    pub pic_parameter_set_id: u32,              // ue(v)
+    pub colour_plane_id: Option<u8>,            // u(2)
+    pub frame_num: u32,                         // u(v)
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: Option<u32>,                // ue(v)
+    pub pic_order_cnt_lsb: Option<u32>,         // u(v)
+    pub delta_pic_order_cnt_bottom: Option<i32>,    // se(v)
+    pub delta_pic_order_cnt: [Option<i32>; 2],      // se(v)
+    pub redundant_pic_cnt: Option<u32>,         // ue(v)
+    pub direct_spatial_mv_pred_flag: Option<bool>,
+    pub num_ref_idx_active_override_flag: Option<bool>,
+    // 未出现时由 PPS 推导
+    pub num_ref_idx_l0_active_minus1: u32,      // ue(v)
+    pub num_ref_idx_l1_active_minus1: u32,      // ue(v)
+
+    // ref_pic_list_modification()
+    pub ref_pic_list_modification_l0: Option<Vec<RefPicListModification>>,
+    pub ref_pic_list_modification_l1: Option<Vec<RefPicListModification>>,
+    // pred_weight_table()
+    pub pred_weight_table: Option<PredWeightTable>,
+    // dec_ref_pic_marking()
+    pub dec_ref_pic_marking: Option<DecRefPicMarking>,
+
+    pub cabac_init_idc: Option<u32>,            // ue(v)
+    pub slice_qp_delta: i32,                    // se(v)
+    pub sp_for_switch_flag: Option<bool>,
+    pub slice_qs_delta: Option<i32>,            // se(v)
+    pub disable_deblocking_filter_idc: u32,     // ue(v)
+    pub slice_alpha_c0_offset_div2: i32,        // se(v)
+    pub slice_beta_offset_div2: i32,            // se(v)
+    pub slice_group_change_cycle: Option<u32>,  // u(v)
+}
+
+impl SliceHeader {
+    pub fn parse(reader: &mut RbspReader, nalu_header: NaluHeader, parameter_sets: &ParameterSets) -> Result<Self, Error> {
+        let nal_unit_type = nalu_header.nal_unit_type();
+        let nal_ref_idc = nalu_header.nal_ref_idc();
+        let idr_pic_flag = nal_unit_type == NaluKind::CodedSliceIdr;
+
+        match nal_unit_type {
+            NaluKind::CodedSliceNonIdr
+            | NaluKind::CodedSliceIdr
+            | NaluKind::CodedSliceDataPartitionA
+            | NaluKind::CodedSliceOfAnAuxiliaryCodedPictureWithoutPartitioning => { },
+            _ => return Err(error::unsupported("slice header of this nal unit type is not supported")),
+        }
+
+        let first_mb_in_slice = reader.read_ue()?;
+        let slice_type_value = reader.read_ue()?;
+        let slice_type = SliceType::try_from(slice_type_value)?;
+        let pic_parameter_set_id = reader.read_ue()?;
+
+        let pps = match parameter_sets.pps(pic_parameter_set_id) {
+            Some(pps) => pps,
+            None => return Err(error::malformed("slice refers to an unknown pps")),
+        };
+        let sps = match parameter_sets.sps(pps.seq_parameter_set_id()) {
+            Some(sps) => sps,
+            None => return Err(error::malformed("pps refers to an unknown sps")),
+        };
+
+        if idr_pic_flag && !slice_type.is_intra() {
+            return Err(error::malformed("idr picture must only contain I or SI slices"));
+        }
+
+        let mut header = SliceHeader {
+            nal_unit_type: nal_unit_type,
+            nal_ref_idc: nal_ref_idc,
+            first_mb_in_slice: first_mb_in_slice,
+            slice_type: slice_type,
+            slice_type_fixed: slice_type_value > 4,
+            pic_parameter_set_id: pic_parameter_set_id,
+            colour_plane_id: None,
+            frame_num: 0,
+            field_pic_flag: false,
+            bottom_field_flag: false,
+            idr_pic_id: None,
+            pic_order_cnt_lsb: None,
+            delta_pic_order_cnt_bottom: None,
+            delta_pic_order_cnt: [None, None],
+            redundant_pic_cnt: None,
+            direct_spatial_mv_pred_flag: None,
+            num_ref_idx_active_override_flag: None,
+            num_ref_idx_l0_active_minus1: pps.num_ref_idx_l0_default_active_minus1(),
+            num_ref_idx_l1_active_minus1: pps.num_ref_idx_l1_default_active_minus1(),
+            ref_pic_list_modification_l0: None,
+            ref_pic_list_modification_l1: None,
+            pred_weight_table: None,
+            dec_ref_pic_marking: None,
+            cabac_init_idc: None,
+            slice_qp_delta: 0,
+            sp_for_switch_flag: None,
+            slice_qs_delta: None,
+            disable_deblocking_filter_idc: 0,
+            slice_alpha_c0_offset_div2: 0,
+            slice_beta_offset_div2: 0,
+            slice_group_change_cycle: None,
+        };
+
+        if sps.separate_colour_plane_flag() {
+            header.colour_plane_id = Some(reader.read_bits(2)? as u8);
+        }
+
+        header.frame_num = reader.read_bits(sps.log2_max_frame_num_minus4() + 4)?;
+
+        if !sps.frame_mbs_only_flag() {
+            header.field_pic_flag = reader.read_flag()?;
+            if header.field_pic_flag {
+                header.bottom_field_flag = reader.read_flag()?;
+            }
+        }
+
+        if idr_pic_flag {
+            header.idr_pic_id = Some(reader.read_ue()?);
+        }
+
+        let bottom_present = pps.bottom_field_pic_order_in_frame_present_flag() && !header.field_pic_flag;
+
+        if sps.pic_order_cnt_type() == 0 {
+            let bits = sps.log2_max_pic_order_cnt_lsb_minus4().unwrap_or(0) + 4;
+            header.pic_order_cnt_lsb = Some(reader.read_bits(bits)?);
+            if bottom_present {
+                header.delta_pic_order_cnt_bottom = Some(reader.read_se()?);
+            }
+        }
+
+        if sps.pic_order_cnt_type() == 1 && !sps.delta_pic_order_always_zero_flag() {
+            header.delta_pic_order_cnt[0] = Some(reader.read_se()?);
+            if bottom_present {
+                header.delta_pic_order_cnt[1] = Some(reader.read_se()?);
+            }
+        }
+
+        if pps.redundant_pic_cnt_present_flag() {
+            header.redundant_pic_cnt = Some(reader.read_ue()?);
+        }
+
+        if slice_type.is_bipredictive() {
+            header.direct_spatial_mv_pred_flag = Some(reader.read_flag()?);
+        }
+
+        if slice_type.is_predictive() || slice_type.is_bipredictive() {
+            let num_ref_idx_active_override_flag = reader.read_flag()?;
+            if num_ref_idx_active_override_flag {
+                header.num_ref_idx_l0_active_minus1 = reader.read_ue()?;
+                if slice_type.is_bipredictive() {
+                    header.num_ref_idx_l1_active_minus1 = reader.read_ue()?;
+                }
+            }
+            header.num_ref_idx_active_override_flag = Some(num_ref_idx_active_override_flag);
+
+            // 帧: 0 .. 15, 场: 0 .. 31
+            let max = if header.field_pic_flag { 31 } else { 15 };
+            if header.num_ref_idx_l0_active_minus1 > max || header.num_ref_idx_l1_active_minus1 > max {
+                return Err(error::malformed("num_ref_idx_active_minus1 out of range"));
+            }
+        }
+
+        // ref_pic_list_modification()
+        if !slice_type.is_intra() {
+            header.ref_pic_list_modification_l0 = RefPicListModification::parse_list(reader)?;
+        }
+        if slice_type.is_bipredictive() {
+            header.ref_pic_list_modification_l1 = RefPicListModification::parse_list(reader)?;
+        }
+
+        if (pps.weighted_pred_flag() && slice_type.is_predictive())
+            || (pps.weighted_bipred_idc() == 1 && slice_type.is_bipredictive()) {
+            header.pred_weight_table = Some(PredWeightTable::parse(reader, &header, sps.chroma_array_type())?);
+        }
+
+        if nal_ref_idc != NaluRefIdc::DISPOSABLE {
+            header.dec_ref_pic_marking = Some(DecRefPicMarking::parse(reader, idr_pic_flag)?);
+        }
+
+        if pps.entropy_coding_mode_flag() && !slice_type.is_intra() {
+            let cabac_init_idc = reader.read_ue()?;
+            if cabac_init_idc > 2 {
+                return Err(error::malformed("cabac_init_idc out of range"));
+            }
+            header.cabac_init_idc = Some(cabac_init_idc);
+        }
+
+        header.slice_qp_delta = reader.read_se()?;
+
+        if slice_type == SliceType::SP || slice_type == SliceType::SI {
+            if slice_type == SliceType::SP {
+                header.sp_for_switch_flag = Some(reader.read_flag()?);
+            }
+            header.slice_qs_delta = Some(reader.read_se()?);
+        }
+
+        if pps.deblocking_filter_control_present_flag() {
+            header.disable_deblocking_filter_idc = reader.read_ue()?;
+            if header.disable_deblocking_filter_idc > 2 {
+                return Err(error::malformed("disable_deblocking_filter_idc out of range"));
+            }
+
+            if header.disable_deblocking_filter_idc != 1 {
+                header.slice_alpha_c0_offset_div2 = reader.read_se()?;
+                header.slice_beta_offset_div2 = reader.read_se()?;
+            }
+        }
+
+        if pps.num_slice_groups_minus1() > 0 {
+            match pps.slice_group_map_type() {
+                Some(3) | Some(4) | Some(5) => {
+                    // Ceil( Log2( PicSizeInMapUnits ÷ SliceGroupChangeRate + 1 ) )
+                    let rate = pps.slice_group_change_rate();
+                    let pic_size_in_map_units = sps.pic_size_in_map_units();
+                    let value = pic_size_in_map_units / rate + (pic_size_in_map_units % rate != 0) as u32 + 1;
+                    let bits = 32 - (value - 1).leading_zeros();
+                    header.slice_group_change_cycle = Some(reader.read_bits(bits)?);
+                },
+                _ => { },
+            }
+        }
+
+        // MBAFF 帧中 first_mb_in_slice 为宏块对的地址 ( CurrMbAddr = first_mb_in_slice * 2 )
+        if header.first_mb_in_slice * (1 + header.mbaff_frame_flag(sps) as u32) >= header.pic_size_in_mbs(sps) {
+            return Err(error::malformed("first_mb_in_slice out of range"));
+        }
+
+        Ok(header)
+    }
+
+    // IdrPicFlag
+    pub fn idr_pic_flag(&self) -> bool {
+        self.nal_unit_type == NaluKind::CodedSliceIdr
+    }
+
+    // field_pic_flag 与 bottom_field_flag 决定的当前图像的结构
+    pub fn structure(&self) -> PictureStructure {
+        match (self.field_pic_flag, self.bottom_field_flag) {
+            (false, _) => PictureStructure::Frame,
+            (true, false) => PictureStructure::TopField,
+            (true, true) => PictureStructure::BottomField,
+        }
+    }
+
+    // 是否包含 memory_management_control_operation 5
+    pub fn has_mmco5(&self) -> bool {
+        self.dec_ref_pic_marking.as_ref()
+            .is_some_and(|marking| marking.operations.contains(&MemoryManagementControlOperation::MarkAllUnused))
+    }
+
+    // MbaffFrameFlag
+    pub fn mbaff_frame_flag(&self, sps: &SequenceParameterSet) -> bool {
+        sps.mb_adaptive_frame_field_flag() && !self.field_pic_flag
+    }
+
+    // PicHeightInMbs
+    pub fn pic_height_in_mbs(&self, sps: &SequenceParameterSet) -> u32 {
+        sps.frame_height_in_mbs() / (1 + self.field_pic_flag as u32)
+    }
+
+    // PicSizeInMbs
+    pub fn pic_size_in_mbs(&self, sps: &SequenceParameterSet) -> u32 {
+        sps.pic_width_in_mbs() * self.pic_height_in_mbs(sps)
+    }
+
+    // SliceQPY
+    pub fn slice_qp_y(&self, pps: &PictureParameterSet) -> i32 {
+        26 + pps.pic_init_qp_minus26() + self.slice_qp_delta
+    }
+
+    // FilterOffsetA, FilterOffsetB
+    pub fn filter_offset_a(&self) -> i32 {
+        self.slice_alpha_c0_offset_div2 << 1
+    }
+
+    pub fn filter_offset_b(&self) -> i32 {
+        self.slice_beta_offset_div2 << 1
+    }
+}
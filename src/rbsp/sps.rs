@@ -1,3 +1,6 @@
+use crate::error::{ self, Error };
+use crate::rbsp::{ RbspReader, ScalingList };
+
 
 use std::fmt;
 
@@ -85,33 +88,56 @@ impl fmt::Display for Level {
 }
 
 
+impl Level {
+    // Table A-1 – Level limits: MaxDpbMbs ( Page 314 )
+    pub fn max_dpb_mbs(&self) -> u32 {
+        use self::Level::*;
+
+        match *self {
+            L1 | L1B => 396,
+            L1_1 => 900,
+            L1_2 | L1_3 | L2 => 2376,
+            L2_1 => 4752,
+            L2_2 | L3 => 8100,
+            L3_1 => 18000,
+            L3_2 => 20480,
+            L4 | L4_1 => 32768,
+            L4_2 => 34816,
+            L5 => 110400,
+            L5_1 | L5_2 => 184320,
+            L6 | L6_1 | L6_2 => 696320,
+        }
+    }
+}
+
+
 // SPS
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SequenceParameterSetFlag(u8);
 
 impl SequenceParameterSetFlag {
     pub fn set0(&self) -> bool {
-        (self.0 & 0b1000_0000) == 1
+        (self.0 & 0b1000_0000) != 0
     }
     
     pub fn set1(&self) -> bool {
-        (self.0 & 0b0100_0000) == 1
+        (self.0 & 0b0100_0000) != 0
     }
 
     pub fn set2(&self) -> bool {
-        (self.0 & 0b0010_0000) == 1
+        (self.0 & 0b0010_0000) != 0
     }
 
     pub fn set3(&self) -> bool {
-        (self.0 & 0b0001_0000) == 1
+        (self.0 & 0b0001_0000) != 0
     }
 
     pub fn set4(&self) -> bool {
-        (self.0 & 0b0000_1000) == 1
+        (self.0 & 0b0000_1000) != 0
     }
 
     pub fn set5(&self) -> bool {
-        (self.0 & 0b0000_0100) == 1
+        (self.0 & 0b0000_0100) != 0
     }
 }
 
@@ -128,6 +154,225 @@ impl Into<u8> for SequenceParameterSetFlag {
 }
 
 
+// Syntax: E.1.2 ( Page 416 )
+// Semantic: E.2.2 ( Page 431 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HrdParameters {
+    pub cpb_cnt_minus1: u32,                        // ue(v)
+    pub bit_rate_scale: u8,                         // u(4)
+    pub cpb_size_scale: u8,                         // u(4)
+    pub bit_rate_value_minus1: Vec<u32>,            // ue(v)
+    pub cpb_size_value_minus1: Vec<u32>,            // ue(v)
+    pub cbr_flag: Vec<bool>,
+    pub initial_cpb_removal_delay_length_minus1: u8,    // u(5)
+    pub cpb_removal_delay_length_minus1: u8,            // u(5)
+    pub dpb_output_delay_length_minus1: u8,             // u(5)
+    pub time_offset_length: u8,                         // u(5)
+}
+
+impl HrdParameters {
+    pub fn parse(reader: &mut RbspReader) -> Result<Self, Error> {
+        let cpb_cnt_minus1 = reader.read_ue()?;
+        if cpb_cnt_minus1 > 31 {
+            return Err(error::malformed("cpb_cnt_minus1 out of range"));
+        }
+
+        let bit_rate_scale = reader.read_bits(4)? as u8;
+        let cpb_size_scale = reader.read_bits(4)? as u8;
+
+        let mut bit_rate_value_minus1 = vec![];
+        let mut cpb_size_value_minus1 = vec![];
+        let mut cbr_flag = vec![];
+
+        for _ in 0..=cpb_cnt_minus1 {
+            bit_rate_value_minus1.push(reader.read_ue()?);
+            cpb_size_value_minus1.push(reader.read_ue()?);
+            cbr_flag.push(reader.read_flag()?);
+        }
+
+        Ok(Self {
+            cpb_cnt_minus1: cpb_cnt_minus1,
+            bit_rate_scale: bit_rate_scale,
+            cpb_size_scale: cpb_size_scale,
+            bit_rate_value_minus1: bit_rate_value_minus1,
+            cpb_size_value_minus1: cpb_size_value_minus1,
+            cbr_flag: cbr_flag,
+            initial_cpb_removal_delay_length_minus1: reader.read_bits(5)? as u8,
+            cpb_removal_delay_length_minus1: reader.read_bits(5)? as u8,
+            dpb_output_delay_length_minus1: reader.read_bits(5)? as u8,
+            time_offset_length: reader.read_bits(5)? as u8,
+        })
+    }
+}
+
+
+// Syntax: E.1.1 ( Page 414 )
+// Semantic: E.2.1 ( Page 419 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VuiParameters {
+    pub aspect_ratio_idc: Option<u8>,              // u(8)
+    pub sar_width: Option<u16>,                    // u(16)
+    pub sar_height: Option<u16>,                   // u(16)
+    pub overscan_appropriate_flag: Option<bool>,
+
+    pub video_format: Option<u8>,                  // u(3)
+    pub video_full_range_flag: Option<bool>,
+    pub colour_primaries: Option<u8>,              // u(8)
+    pub transfer_characteristics: Option<u8>,      // u(8)
+    pub matrix_coefficients: Option<u8>,           // u(8)
+
+    pub chroma_sample_loc_type_top_field: Option<u32>,     // ue(v)
+    pub chroma_sample_loc_type_bottom_field: Option<u32>,  // ue(v)
+
+    pub num_units_in_tick: Option<u32>,            // u(32)
+    pub time_scale: Option<u32>,                   // u(32)
+    pub fixed_frame_rate_flag: Option<bool>,
+
+    pub nal_hrd_parameters: Option<HrdParameters>,
+    pub vcl_hrd_parameters: Option<HrdParameters>,
+    pub low_delay_hrd_flag: Option<bool>,
+    pub pic_struct_present_flag: bool,
+
+    pub motion_vectors_over_pic_boundaries_flag: Option<bool>,
+    pub max_bytes_per_pic_denom: Option<u32>,          // ue(v)
+    pub max_bits_per_mb_denom: Option<u32>,            // ue(v)
+    pub log2_max_mv_length_horizontal: Option<u32>,    // ue(v)
+    pub log2_max_mv_length_vertical: Option<u32>,      // ue(v)
+    pub max_num_reorder_frames: Option<u32>,           // ue(v)
+    pub max_dec_frame_buffering: Option<u32>,          // ue(v)
+}
+
+impl VuiParameters {
+    // Table E-1 – Meaning of sample aspect ratio indicator
+    pub const EXTENDED_SAR: u8 = 255;
+
+    pub fn parse(reader: &mut RbspReader) -> Result<Self, Error> {
+        let mut aspect_ratio_idc = None;
+        let mut sar_width = None;
+        let mut sar_height = None;
+
+        let aspect_ratio_info_present_flag = reader.read_flag()?;
+        if aspect_ratio_info_present_flag {
+            let idc = reader.read_bits(8)? as u8;
+            if idc == Self::EXTENDED_SAR {
+                sar_width = Some(reader.read_bits(16)? as u16);
+                sar_height = Some(reader.read_bits(16)? as u16);
+            }
+            aspect_ratio_idc = Some(idc);
+        }
+
+        let mut overscan_appropriate_flag = None;
+        let overscan_info_present_flag = reader.read_flag()?;
+        if overscan_info_present_flag {
+            overscan_appropriate_flag = Some(reader.read_flag()?);
+        }
+
+        let mut video_format = None;
+        let mut video_full_range_flag = None;
+        let mut colour_primaries = None;
+        let mut transfer_characteristics = None;
+        let mut matrix_coefficients = None;
+
+        let video_signal_type_present_flag = reader.read_flag()?;
+        if video_signal_type_present_flag {
+            video_format = Some(reader.read_bits(3)? as u8);
+            video_full_range_flag = Some(reader.read_flag()?);
+
+            let colour_description_present_flag = reader.read_flag()?;
+            if colour_description_present_flag {
+                colour_primaries = Some(reader.read_bits(8)? as u8);
+                transfer_characteristics = Some(reader.read_bits(8)? as u8);
+                matrix_coefficients = Some(reader.read_bits(8)? as u8);
+            }
+        }
+
+        let mut chroma_sample_loc_type_top_field = None;
+        let mut chroma_sample_loc_type_bottom_field = None;
+        let chroma_loc_info_present_flag = reader.read_flag()?;
+        if chroma_loc_info_present_flag {
+            chroma_sample_loc_type_top_field = Some(reader.read_ue()?);
+            chroma_sample_loc_type_bottom_field = Some(reader.read_ue()?);
+        }
+
+        let mut num_units_in_tick = None;
+        let mut time_scale = None;
+        let mut fixed_frame_rate_flag = None;
+        let timing_info_present_flag = reader.read_flag()?;
+        if timing_info_present_flag {
+            num_units_in_tick = Some(reader.read_bits(32)?);
+            time_scale = Some(reader.read_bits(32)?);
+            fixed_frame_rate_flag = Some(reader.read_flag()?);
+        }
+
+        let mut nal_hrd_parameters = None;
+        let nal_hrd_parameters_present_flag = reader.read_flag()?;
+        if nal_hrd_parameters_present_flag {
+            nal_hrd_parameters = Some(HrdParameters::parse(reader)?);
+        }
+
+        let mut vcl_hrd_parameters = None;
+        let vcl_hrd_parameters_present_flag = reader.read_flag()?;
+        if vcl_hrd_parameters_present_flag {
+            vcl_hrd_parameters = Some(HrdParameters::parse(reader)?);
+        }
+
+        let mut low_delay_hrd_flag = None;
+        if nal_hrd_parameters_present_flag || vcl_hrd_parameters_present_flag {
+            low_delay_hrd_flag = Some(reader.read_flag()?);
+        }
+
+        let pic_struct_present_flag = reader.read_flag()?;
+
+        let mut motion_vectors_over_pic_boundaries_flag = None;
+        let mut max_bytes_per_pic_denom = None;
+        let mut max_bits_per_mb_denom = None;
+        let mut log2_max_mv_length_horizontal = None;
+        let mut log2_max_mv_length_vertical = None;
+        let mut max_num_reorder_frames = None;
+        let mut max_dec_frame_buffering = None;
+
+        let bitstream_restriction_flag = reader.read_flag()?;
+        if bitstream_restriction_flag {
+            motion_vectors_over_pic_boundaries_flag = Some(reader.read_flag()?);
+            max_bytes_per_pic_denom = Some(reader.read_ue()?);
+            max_bits_per_mb_denom = Some(reader.read_ue()?);
+            log2_max_mv_length_horizontal = Some(reader.read_ue()?);
+            log2_max_mv_length_vertical = Some(reader.read_ue()?);
+            max_num_reorder_frames = Some(reader.read_ue()?);
+            max_dec_frame_buffering = Some(reader.read_ue()?);
+        }
+
+        Ok(Self {
+            aspect_ratio_idc: aspect_ratio_idc,
+            sar_width: sar_width,
+            sar_height: sar_height,
+            overscan_appropriate_flag: overscan_appropriate_flag,
+            video_format: video_format,
+            video_full_range_flag: video_full_range_flag,
+            colour_primaries: colour_primaries,
+            transfer_characteristics: transfer_characteristics,
+            matrix_coefficients: matrix_coefficients,
+            chroma_sample_loc_type_top_field: chroma_sample_loc_type_top_field,
+            chroma_sample_loc_type_bottom_field: chroma_sample_loc_type_bottom_field,
+            num_units_in_tick: num_units_in_tick,
+            time_scale: time_scale,
+            fixed_frame_rate_flag: fixed_frame_rate_flag,
+            nal_hrd_parameters: nal_hrd_parameters,
+            vcl_hrd_parameters: vcl_hrd_parameters,
+            low_delay_hrd_flag: low_delay_hrd_flag,
+            pic_struct_present_flag: pic_struct_present_flag,
+            motion_vectors_over_pic_boundaries_flag: motion_vectors_over_pic_boundaries_flag,
+            max_bytes_per_pic_denom: max_bytes_per_pic_denom,
+            max_bits_per_mb_denom: max_bits_per_mb_denom,
+            log2_max_mv_length_horizontal: log2_max_mv_length_horizontal,
+            log2_max_mv_length_vertical: log2_max_mv_length_vertical,
+            max_num_reorder_frames: max_num_reorder_frames,
+            max_dec_frame_buffering: max_dec_frame_buffering,
+        })
+    }
+}
+
+
 // Syntax: 7.3.2.1.1 ( Page 64 )
 // Semantic: 7.4.2.1 ( Page 94 )
 // VUI syntax: Annex E ( Page 414 )
@@ -141,14 +386,13 @@ pub struct SequenceParameterSet {
     seq_parameter_set_id: u32,             // ue(v)
 
     chroma_format_idc: Option<u32>,        // ue(v)
-    separate_colour_plane_flag: Option<u8>,
+    separate_colour_plane_flag: Option<bool>,
     bit_depth_luma_minus8: Option<u32>,    // ue(v)
     bit_depth_chroma_minus8: Option<u32>,  // ue(v)
     qpprime_y_zero_transform_bypass_flag: Option<bool>,
     seq_scaling_matrix_present_flag: Option<bool>,
-    seq_scaling_list_present_flag: Option<Vec<bool>>,
-    // TODO:
     // scaling_list()
+    seq_scaling_lists: Option<Vec<ScalingList>>,
 
     log2_max_frame_num_minus4: u32,        // ue(v)
     pic_order_cnt_type: u32,               // ue(v)
@@ -174,11 +418,449 @@ pub struct SequenceParameterSet {
     frame_crop_bottom_offset: Option<u32>,   // ue(v)
 
     vui_parameters_present_flag: bool,
-
-    // TODO: 
     // vui_parameters()
-
+    vui_parameters: Option<VuiParameters>,
 }
 
+impl SequenceParameterSet {
+    pub fn parse(reader: &mut RbspReader) -> Result<Self, Error> {
+        let profile_idc = reader.read_bits(8)? as u8;
+        let flag = SequenceParameterSetFlag::from(reader.read_bits(8)? as u8);
+        let level_idc = reader.read_bits(8)? as u8;
+        let seq_parameter_set_id = reader.read_ue()?;
+        if seq_parameter_set_id > 31 {
+            return Err(error::malformed("seq_parameter_set_id out of range"));
+        }
+
+        let mut chroma_format_idc = None;
+        let mut separate_colour_plane_flag = None;
+        let mut bit_depth_luma_minus8 = None;
+        let mut bit_depth_chroma_minus8 = None;
+        let mut qpprime_y_zero_transform_bypass_flag = None;
+        let mut seq_scaling_matrix_present_flag = None;
+        let mut seq_scaling_lists = None;
+
+        match profile_idc {
+            100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135 => {
+                let idc = reader.read_ue()?;
+                if idc > 3 {
+                    return Err(error::malformed("chroma_format_idc out of range"));
+                }
+                if idc == 3 {
+                    separate_colour_plane_flag = Some(reader.read_flag()?);
+                }
+                chroma_format_idc = Some(idc);
+
+                let luma = reader.read_ue()?;
+                let chroma = reader.read_ue()?;
+                if luma > 6 || chroma > 6 {
+                    return Err(error::malformed("bit_depth_minus8 out of range"));
+                }
+                bit_depth_luma_minus8 = Some(luma);
+                bit_depth_chroma_minus8 = Some(chroma);
+
+                qpprime_y_zero_transform_bypass_flag = Some(reader.read_flag()?);
+
+                let present_flag = reader.read_flag()?;
+                if present_flag {
+                    let count = if idc != 3 { 8 } else { 12 };
+                    seq_scaling_lists = Some(ScalingList::parse_lists(reader, count)?);
+                }
+                seq_scaling_matrix_present_flag = Some(present_flag);
+            },
+            _ => { },
+        }
+
+        let log2_max_frame_num_minus4 = reader.read_ue()?;
+        if log2_max_frame_num_minus4 > 12 {
+            return Err(error::malformed("log2_max_frame_num_minus4 out of range"));
+        }
+
+        let pic_order_cnt_type = reader.read_ue()?;
+        let mut log2_max_pic_order_cnt_lsb_minus4 = None;
+        let mut delta_pic_order_always_zero_flag = None;
+        let mut offset_for_non_ref_pic = None;
+        let mut offset_for_top_to_bottom_field = None;
+        let mut num_ref_frames_in_pic_order_cnt_cycle = None;
+        let mut offset_for_ref_frame = None;
+
+        match pic_order_cnt_type {
+            0 => {
+                let lsb = reader.read_ue()?;
+                if lsb > 12 {
+                    return Err(error::malformed("log2_max_pic_order_cnt_lsb_minus4 out of range"));
+                }
+                log2_max_pic_order_cnt_lsb_minus4 = Some(lsb);
+            },
+            1 => {
+                delta_pic_order_always_zero_flag = Some(reader.read_flag()?);
+                offset_for_non_ref_pic = Some(reader.read_se()?);
+                offset_for_top_to_bottom_field = Some(reader.read_se()?);
+
+                let num = reader.read_ue()?;
+                if num > 255 {
+                    return Err(error::malformed("num_ref_frames_in_pic_order_cnt_cycle out of range"));
+                }
+                let mut offsets = Vec::with_capacity(num as usize);
+                for _ in 0..num {
+                    offsets.push(reader.read_se()?);
+                }
+                num_ref_frames_in_pic_order_cnt_cycle = Some(num);
+                offset_for_ref_frame = Some(offsets);
+            },
+            2 => { },
+            _ => return Err(error::malformed("pic_order_cnt_type out of range")),
+        }
+
+        let max_num_ref_frames = reader.read_ue()?;
+        let gaps_in_frame_num_value_allowed_flag = reader.read_flag()?;
+        let pic_width_in_mbs_minus1 = reader.read_ue()?;
+        let pic_height_in_map_units_minus1 = reader.read_ue()?;
+        let frame_mbs_only_flag = reader.read_flag()?;
+
+        let mut mb_adaptive_frame_field_flag = None;
+        if !frame_mbs_only_flag {
+            mb_adaptive_frame_field_flag = Some(reader.read_flag()?);
+        }
+
+        let direct_8x8_inference_flag = reader.read_flag()?;
+        let frame_cropping_flag = reader.read_flag()?;
+
+        let mut frame_crop_left_offset = None;
+        let mut frame_crop_right_offset = None;
+        let mut frame_crop_top_offset = None;
+        let mut frame_crop_bottom_offset = None;
+        if frame_cropping_flag {
+            frame_crop_left_offset = Some(reader.read_ue()?);
+            frame_crop_right_offset = Some(reader.read_ue()?);
+            frame_crop_top_offset = Some(reader.read_ue()?);
+            frame_crop_bottom_offset = Some(reader.read_ue()?);
+        }
+
+        let vui_parameters_present_flag = reader.read_flag()?;
+        let mut vui_parameters = None;
+        if vui_parameters_present_flag {
+            vui_parameters = Some(VuiParameters::parse(reader)?);
+        }
+
+        reader.rbsp_trailing_bits()?;
+
+        Ok(Self {
+            profile_idc: profile_idc,
+            flag: flag,
+            level_idc: level_idc,
+            seq_parameter_set_id: seq_parameter_set_id,
+            chroma_format_idc: chroma_format_idc,
+            separate_colour_plane_flag: separate_colour_plane_flag,
+            bit_depth_luma_minus8: bit_depth_luma_minus8,
+            bit_depth_chroma_minus8: bit_depth_chroma_minus8,
+            qpprime_y_zero_transform_bypass_flag: qpprime_y_zero_transform_bypass_flag,
+            seq_scaling_matrix_present_flag: seq_scaling_matrix_present_flag,
+            seq_scaling_lists: seq_scaling_lists,
+            log2_max_frame_num_minus4: log2_max_frame_num_minus4,
+            pic_order_cnt_type: pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb_minus4: log2_max_pic_order_cnt_lsb_minus4,
+            delta_pic_order_always_zero_flag: delta_pic_order_always_zero_flag,
+            offset_for_non_ref_pic: offset_for_non_ref_pic,
+            offset_for_top_to_bottom_field: offset_for_top_to_bottom_field,
+            num_ref_frames_in_pic_order_cnt_cycle: num_ref_frames_in_pic_order_cnt_cycle,
+            offset_for_ref_frame: offset_for_ref_frame,
+            max_num_ref_frames: max_num_ref_frames,
+            gaps_in_frame_num_value_allowed_flag: gaps_in_frame_num_value_allowed_flag,
+            pic_width_in_mbs_minus1: pic_width_in_mbs_minus1,
+            pic_height_in_map_units_minus1: pic_height_in_map_units_minus1,
+            frame_mbs_only_flag: frame_mbs_only_flag,
+            mb_adaptive_frame_field_flag: mb_adaptive_frame_field_flag,
+            direct_8x8_inference_flag: direct_8x8_inference_flag,
+            frame_cropping_flag: frame_cropping_flag,
+            frame_crop_left_offset: frame_crop_left_offset,
+            frame_crop_right_offset: frame_crop_right_offset,
+            frame_crop_top_offset: frame_crop_top_offset,
+            frame_crop_bottom_offset: frame_crop_bottom_offset,
+            vui_parameters_present_flag: vui_parameters_present_flag,
+            vui_parameters: vui_parameters,
+        })
+    }
+
+    pub fn profile_idc(&self) -> u8 {
+        self.profile_idc
+    }
+
+    pub fn flag(&self) -> SequenceParameterSetFlag {
+        self.flag
+    }
+
+    pub fn level_idc(&self) -> u8 {
+        self.level_idc
+    }
+
+    pub fn seq_parameter_set_id(&self) -> u32 {
+        self.seq_parameter_set_id
+    }
+
+    // 未出现时推导为 1 ( 4:2:0 )
+    pub fn chroma_format_idc(&self) -> u32 {
+        self.chroma_format_idc.unwrap_or(1)
+    }
+
+    pub fn separate_colour_plane_flag(&self) -> bool {
+        self.separate_colour_plane_flag.unwrap_or(false)
+    }
 
+    pub fn bit_depth_luma_minus8(&self) -> u32 {
+        self.bit_depth_luma_minus8.unwrap_or(0)
+    }
+
+    pub fn bit_depth_chroma_minus8(&self) -> u32 {
+        self.bit_depth_chroma_minus8.unwrap_or(0)
+    }
 
+    pub fn qpprime_y_zero_transform_bypass_flag(&self) -> bool {
+        self.qpprime_y_zero_transform_bypass_flag.unwrap_or(false)
+    }
+
+    pub fn seq_scaling_matrix_present_flag(&self) -> bool {
+        self.seq_scaling_matrix_present_flag.unwrap_or(false)
+    }
+
+    pub fn seq_scaling_lists(&self) -> Option<&[ScalingList]> {
+        self.seq_scaling_lists.as_ref().map(|lists| &lists[..])
+    }
+
+    pub fn log2_max_frame_num_minus4(&self) -> u32 {
+        self.log2_max_frame_num_minus4
+    }
+
+    pub fn pic_order_cnt_type(&self) -> u32 {
+        self.pic_order_cnt_type
+    }
+
+    pub fn log2_max_pic_order_cnt_lsb_minus4(&self) -> Option<u32> {
+        self.log2_max_pic_order_cnt_lsb_minus4
+    }
+
+    pub fn delta_pic_order_always_zero_flag(&self) -> bool {
+        self.delta_pic_order_always_zero_flag.unwrap_or(false)
+    }
+
+    pub fn offset_for_non_ref_pic(&self) -> i32 {
+        self.offset_for_non_ref_pic.unwrap_or(0)
+    }
+
+    pub fn offset_for_top_to_bottom_field(&self) -> i32 {
+        self.offset_for_top_to_bottom_field.unwrap_or(0)
+    }
+
+    pub fn num_ref_frames_in_pic_order_cnt_cycle(&self) -> u32 {
+        self.num_ref_frames_in_pic_order_cnt_cycle.unwrap_or(0)
+    }
+
+    pub fn offset_for_ref_frame(&self) -> &[i32] {
+        match self.offset_for_ref_frame {
+            Some(ref offsets) => &offsets[..],
+            None => &[],
+        }
+    }
+
+    pub fn max_num_ref_frames(&self) -> u32 {
+        self.max_num_ref_frames
+    }
+
+    pub fn gaps_in_frame_num_value_allowed_flag(&self) -> bool {
+        self.gaps_in_frame_num_value_allowed_flag
+    }
+
+    pub fn pic_width_in_mbs_minus1(&self) -> u32 {
+        self.pic_width_in_mbs_minus1
+    }
+
+    pub fn pic_height_in_map_units_minus1(&self) -> u32 {
+        self.pic_height_in_map_units_minus1
+    }
+
+    pub fn frame_mbs_only_flag(&self) -> bool {
+        self.frame_mbs_only_flag
+    }
+
+    pub fn mb_adaptive_frame_field_flag(&self) -> bool {
+        self.mb_adaptive_frame_field_flag.unwrap_or(false)
+    }
+
+    pub fn direct_8x8_inference_flag(&self) -> bool {
+        self.direct_8x8_inference_flag
+    }
+
+    pub fn frame_cropping_flag(&self) -> bool {
+        self.frame_cropping_flag
+    }
+
+    pub fn vui_parameters(&self) -> Option<&VuiParameters> {
+        self.vui_parameters.as_ref()
+    }
+
+    // A.3.1 / A.3.2: level_idc 为 11 且 constraint_set3_flag 为 1 ( Baseline, Main, Extended ), 或 level_idc 为 9 时表示 Level 1b
+    pub fn level(&self) -> Option<Level> {
+        use self::Level::*;
+
+        let level = match self.level_idc {
+            9 => L1B,
+            10 => L1,
+            11 if self.flag.set3() && (self.profile_idc == 66 || self.profile_idc == 77 || self.profile_idc == 88) => L1B,
+            11 => L1_1,
+            12 => L1_2,
+            13 => L1_3,
+            20 => L2,
+            21 => L2_1,
+            22 => L2_2,
+            30 => L3,
+            31 => L3_1,
+            32 => L3_2,
+            40 => L4,
+            41 => L4_1,
+            42 => L4_2,
+            50 => L5,
+            51 => L5_1,
+            52 => L5_2,
+            60 => L6,
+            61 => L6_1,
+            62 => L6_2,
+            _ => return None,
+        };
+
+        Some(level)
+    }
+
+    // A.3.1 item h): MaxDpbFrames = Min( MaxDpbMbs / ( PicWidthInMbs * FrameHeightInMbs ), 16 )
+    pub fn max_dpb_frames(&self) -> u32 {
+        let max_dpb_mbs = self.level().map(|level| level.max_dpb_mbs()).unwrap_or(696320);
+        let frame_size_in_mbs = self.pic_width_in_mbs() * self.frame_height_in_mbs();
+        (max_dpb_mbs / frame_size_in_mbs).min(16)
+    }
+
+    // ChromaArrayType ( 7.4.2.1.1 )
+    pub fn chroma_array_type(&self) -> u32 {
+        if self.separate_colour_plane_flag() {
+            0
+        } else {
+            self.chroma_format_idc()
+        }
+    }
+
+    // Table 6-1 – SubWidthC, and SubHeightC values derived from chroma_format_idc and separate_colour_plane_flag
+    pub fn sub_width_c(&self) -> u32 {
+        match self.chroma_array_type() {
+            1 | 2 => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn sub_height_c(&self) -> u32 {
+        match self.chroma_array_type() {
+            1 => 2,
+            _ => 1,
+        }
+    }
+
+    // MbWidthC, MbHeightC ( 6.2 )
+    pub fn mb_width_c(&self) -> u32 {
+        match self.chroma_array_type() {
+            0 => 0,
+            _ => 16 / self.sub_width_c(),
+        }
+    }
+
+    pub fn mb_height_c(&self) -> u32 {
+        match self.chroma_array_type() {
+            0 => 0,
+            _ => 16 / self.sub_height_c(),
+        }
+    }
+
+    // BitDepthY, QpBdOffsetY
+    pub fn bit_depth_luma(&self) -> u32 {
+        8 + self.bit_depth_luma_minus8()
+    }
+
+    pub fn qp_bd_offset_luma(&self) -> i32 {
+        6 * self.bit_depth_luma_minus8() as i32
+    }
+
+    // BitDepthC, QpBdOffsetC
+    pub fn bit_depth_chroma(&self) -> u32 {
+        8 + self.bit_depth_chroma_minus8()
+    }
+
+    pub fn qp_bd_offset_chroma(&self) -> i32 {
+        6 * self.bit_depth_chroma_minus8() as i32
+    }
+
+    // MaxFrameNum
+    pub fn max_frame_num(&self) -> u32 {
+        1 << (self.log2_max_frame_num_minus4 + 4)
+    }
+
+    // MaxPicOrderCntLsb
+    pub fn max_pic_order_cnt_lsb(&self) -> u32 {
+        1 << (self.log2_max_pic_order_cnt_lsb_minus4.unwrap_or(0) + 4)
+    }
+
+    // PicWidthInMbs
+    pub fn pic_width_in_mbs(&self) -> u32 {
+        self.pic_width_in_mbs_minus1 + 1
+    }
+
+    // PicHeightInMapUnits
+    pub fn pic_height_in_map_units(&self) -> u32 {
+        self.pic_height_in_map_units_minus1 + 1
+    }
+
+    // PicSizeInMapUnits
+    pub fn pic_size_in_map_units(&self) -> u32 {
+        self.pic_width_in_mbs() * self.pic_height_in_map_units()
+    }
+
+    // FrameHeightInMbs
+    pub fn frame_height_in_mbs(&self) -> u32 {
+        (2 - self.frame_mbs_only_flag as u32) * self.pic_height_in_map_units()
+    }
+
+    // 亮度样本的宽高 ( 未裁剪 )
+    pub fn width(&self) -> u32 {
+        self.pic_width_in_mbs() * 16
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frame_height_in_mbs() * 16
+    }
+
+    // 裁剪矩形 ( left, right, top, bottom ), 单位为亮度样本
+    // 7.4.2.1.1: CropUnitX, CropUnitY
+    pub fn crop_rect(&self) -> (u32, u32, u32, u32) {
+        if !self.frame_cropping_flag {
+            return (0, 0, 0, 0);
+        }
+
+        let (crop_unit_x, crop_unit_y) = match self.chroma_array_type() {
+            0 => (1, 2 - self.frame_mbs_only_flag as u32),
+            _ => (self.sub_width_c(), self.sub_height_c() * (2 - self.frame_mbs_only_flag as u32)),
+        };
+
+        (
+            crop_unit_x * self.frame_crop_left_offset.unwrap_or(0),
+            crop_unit_x * self.frame_crop_right_offset.unwrap_or(0),
+            crop_unit_y * self.frame_crop_top_offset.unwrap_or(0),
+            crop_unit_y * self.frame_crop_bottom_offset.unwrap_or(0),
+        )
+    }
+
+    // 裁剪后的亮度样本宽高
+    pub fn cropped_width(&self) -> u32 {
+        let (left, right, _, _) = self.crop_rect();
+        self.width().saturating_sub(left + right)
+    }
+
+    pub fn cropped_height(&self) -> u32 {
+        let (_, _, top, bottom) = self.crop_rect();
+        self.height().saturating_sub(top + bottom)
+    }
+}
@@ -0,0 +1,221 @@
+// user_data_unregistered SEI 的 payload 由 uuid_iso_iec_11578 标识, 格式由各个编码器或应用自行定义。
+// UserDataRegistry 按 UUID 分派给注册的解码函数, 没有注册的 UUID 保留原始字节。
+
+use crate::error::{ self, Error };
+use crate::rbsp::UserDataUnregistered;
+
+use std::fmt;
+use std::any::Any;
+use std::collections::HashMap;
+
+
+pub type Uuid = [u8; 16];
+
+// x264 的编码参数: dc45e9bd-e6d9-48b7-962c-d820d923eeef
+pub const X264_UUID: Uuid = [
+    0xDC, 0x45, 0xE9, 0xBD, 0xE6, 0xD9, 0x48, 0xB7, 0x96, 0x2C, 0xD8, 0x20, 0xD9, 0x23, 0xEE, 0xEF,
+];
+
+// 8-4-4-4-12 形式的 UUID
+pub fn uuid_string(uuid: &Uuid) -> String {
+    let hex: Vec<String> = uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", hex[..4].concat(), hex[4..6].concat(), hex[6..8].concat(), hex[8..10].concat(), hex[10..].concat())
+}
+
+
+// 解码之后的 user data, 通过 as_any 转换为具体的类型
+pub trait UserDataPayload: fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+}
+
+pub type UserDataDecoder = Box<dyn Fn(&[u8]) -> Result<Box<dyn UserDataPayload>, Error>>;
+
+#[derive(Debug)]
+pub enum UnregisteredUserData<'a> {
+    // 注册的解码函数的结果
+    Decoded(Box<dyn UserDataPayload>),
+    // 没有注册的 UUID: user_data_payload_byte
+    Raw(&'a [u8]),
+}
+
+impl<'a> UnregisteredUserData<'a> {
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        match *self {
+            UnregisteredUserData::Decoded(ref payload) => payload.as_any().downcast_ref::<T>(),
+            UnregisteredUserData::Raw(_) => None,
+        }
+    }
+}
+
+
+pub struct UserDataRegistry {
+    decoders: HashMap<Uuid, (String, UserDataDecoder)>,
+}
+
+impl Default for UserDataRegistry {
+    // 包括内置的解码函数 ( x264 )
+    fn default() -> Self {
+        let mut registry = UserDataRegistry::empty();
+        registry.register(X264_UUID, "x264", |payload| Ok(Box::new(X264Settings::parse(payload)?)));
+        registry
+    }
+}
+
+impl fmt::Debug for UserDataRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = self.decoders.values().map(|(name, _)| name.as_str()).collect();
+        write!(f, "UserDataRegistry {{ decoders: {:?} }}", names)
+    }
+}
+
+impl UserDataRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 没有任何解码函数
+    pub fn empty() -> Self {
+        Self {
+            decoders: HashMap::new(),
+        }
+    }
+
+    // 注册 ( 或替换 ) 一个 UUID 的解码函数
+    pub fn register<F>(&mut self, uuid: Uuid, name: &str, decoder: F)
+        where F: Fn(&[u8]) -> Result<Box<dyn UserDataPayload>, Error> + 'static {
+        self.decoders.insert(uuid, (name.to_string(), Box::new(decoder)));
+    }
+
+    pub fn unregister(&mut self, uuid: &Uuid) -> bool {
+        self.decoders.remove(uuid).is_some()
+    }
+
+    pub fn name(&self, uuid: &Uuid) -> Option<&str> {
+        self.decoders.get(uuid).map(|(name, _)| name.as_str())
+    }
+
+    pub fn decode<'a>(&self, user_data: &'a UserDataUnregistered) -> Result<UnregisteredUserData<'a>, Error> {
+        match self.decoders.get(&user_data.uuid_iso_iec_11578) {
+            Some((_, decoder)) => decoder(&user_data.payload).map(UnregisteredUserData::Decoded),
+            None => Ok(UnregisteredUserData::Raw(&user_data.payload)),
+        }
+    }
+}
+
+
+// x264 写入的编码参数字符串:
+// "x264 - core 164 r3095 baf4ec0 - H.264/MPEG-4 AVC codec - Copyleft 2003-2022 - http://www.videolan.org/x264.html -
+// options: cabac=1 ref=3 ..." ( 以 0 结尾 )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct X264Settings {
+    // "options:" 之前的部分
+    pub version: String,
+    // x264 的 core 版本号
+    pub core: Option<u32>,
+    // 按出现顺序的 name=value
+    pub options: Vec<(String, String)>,
+}
+
+impl X264Settings {
+    pub fn parse(payload: &[u8]) -> Result<Self, Error> {
+        let end = payload.iter().position(|&byte| byte == 0).unwrap_or(payload.len());
+        let text = String::from_utf8_lossy(&payload[..end]);
+        if !text.starts_with("x264") {
+            return Err(error::malformed("x264 user data does not start with \"x264\""));
+        }
+
+        let (version, options) = match text.find("options:") {
+            Some(index) => (&text[..index], &text[index + "options:".len()..]),
+            None => (&text[..], ""),
+        };
+        let version = version.trim_end().trim_end_matches('-').trim_end();
+
+        let core = version.split(" - ")
+            .filter_map(|part| part.strip_prefix("core "))
+            .filter_map(|part| part.split_whitespace().next()?.parse().ok())
+            .next();
+
+        let options = options.split_whitespace()
+            .map(|option| match option.find('=') {
+                Some(index) => (option[..index].to_string(), option[index + 1..].to_string()),
+                None => (option.to_string(), String::new()),
+            })
+            .collect();
+
+        Ok(Self {
+            version: version.to_string(),
+            core: core,
+            options: options,
+        })
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().find(|(option, _)| option == name).map(|(_, value)| value.as_str())
+    }
+}
+
+impl UserDataPayload for X264Settings {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ UserDataRegistry, UserDataPayload, UnregisteredUserData, X264Settings, X264_UUID, uuid_string };
+    use crate::rbsp::UserDataUnregistered;
+
+    use std::any::Any;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct FrameId(u32);
+
+    impl UserDataPayload for FrameId {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_user_data_registry() {
+        assert_eq!(uuid_string(&X264_UUID), "dc45e9bd-e6d9-48b7-962c-d820d923eeef");
+
+        let mut bytes = X264_UUID.to_vec();
+        bytes.extend_from_slice(b"x264 - core 164 r3095 baf4ec0 - H.264/MPEG-4 AVC codec - Copyleft 2003-2022 - \
+            http://www.videolan.org/x264.html - options: cabac=1 ref=3 deblock=1:0:0 analyse=0x3:0x113 bframes=3\0");
+        let x264 = UserDataUnregistered::parse(&bytes).unwrap();
+
+        let mut registry = UserDataRegistry::new();
+        assert_eq!(registry.name(&X264_UUID), Some("x264"));
+        let decoded = registry.decode(&x264).unwrap();
+        let settings = decoded.downcast_ref::<X264Settings>().unwrap();
+        assert_eq!(settings.core, Some(164));
+        assert!(settings.version.ends_with("x264.html"));
+        assert_eq!(settings.options.len(), 5);
+        assert_eq!(settings.option("ref"), Some("3"));
+        assert_eq!(settings.option("deblock"), Some("1:0:0"));
+        assert_eq!(settings.option("weightp"), None);
+
+        // 自定义的 UUID
+        let uuid = [0x11; 16];
+        let mut bytes = uuid.to_vec();
+        bytes.extend_from_slice(&[0x00, 0x00, 0x01, 0x2C]);
+        let frame_id = UserDataUnregistered::parse(&bytes).unwrap();
+        match registry.decode(&frame_id).unwrap() {
+            UnregisteredUserData::Raw(payload) => assert_eq!(payload, &[0x00, 0x00, 0x01, 0x2C]),
+            decoded => panic!("unexpected user data {:?}", decoded),
+        }
+
+        registry.register(uuid, "frame id", |payload| {
+            let mut id = [0u8; 4];
+            id.copy_from_slice(&payload[..4]);
+            Ok(Box::new(FrameId(u32::from_be_bytes(id))))
+        });
+        assert_eq!(registry.decode(&frame_id).unwrap().downcast_ref::<FrameId>(), Some(&FrameId(300)));
+
+        assert!(registry.unregister(&X264_UUID));
+        assert!(registry.decode(&x264).unwrap().downcast_ref::<X264Settings>().is_none());
+        assert!(UserDataRegistry::empty().name(&uuid).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+// 7.4.1 NAL unit semantics ( Page 85 )
+//
+// 写入 NALU 时, RBSP 中的 0x000000, 0x000001, 0x000002, 0x000003 需要插入 emulation_prevention_three_byte,
+// 以 0x00 结尾的 RBSP 也需要在末尾追加 0x03 ( 见 7.4.1 中 cabac_zero_word 的说明 )。
+pub fn rbsp_to_ebsp(rbsp: &[u8]) -> Vec<u8> {
+    let mut ebsp = Vec::with_capacity(rbsp.len() + rbsp.len() / 64);
+    let mut zeros = 0usize;
+
+    for &byte in rbsp {
+        if zeros >= 2 && byte <= 0x03 {
+            ebsp.push(0x03);
+            zeros = 0;
+        }
+
+        if byte == 0x00 {
+            zeros += 1;
+        } else {
+            zeros = 0;
+        }
+
+        ebsp.push(byte);
+    }
+
+    if rbsp.last() == Some(&0x00) {
+        ebsp.push(0x03);
+    }
+
+    ebsp
+}
+
+// RBSP 比特写入器, 与 RbspReader 对应
+#[derive(Debug, Clone, Default)]
+pub struct RbspWriter {
+    data: Vec<u8>,
+    // 已写入的比特数
+    position: usize,
+}
+
+impl RbspWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // byte_aligned()
+    pub fn byte_aligned(&self) -> bool {
+        self.position.is_multiple_of(8)
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.byte_aligned() {
+            self.data.push(0);
+        }
+        if bit {
+            let last = self.data.len() - 1;
+            self.data[last] |= 1 << (7 - self.position % 8);
+        }
+        self.position += 1;
+    }
+
+    // u(n)
+    pub fn write_bits(&mut self, n: u32, value: u32) {
+        assert!(n <= 32);
+
+        for i in (0..n).rev() {
+            self.write_bit((u64::from(value) >> i) & 1 == 1);
+        }
+    }
+
+    // u(1)
+    pub fn write_flag(&mut self, flag: bool) {
+        self.write_bit(flag);
+    }
+
+    // ue(v)
+    pub fn write_ue(&mut self, value: u32) {
+        let code = u64::from(value) + 1;
+        let leading_zero_bits = 63 - code.leading_zeros();
+        for _ in 0..leading_zero_bits {
+            self.write_bit(false);
+        }
+        for i in (0..=leading_zero_bits).rev() {
+            self.write_bit((code >> i) & 1 == 1);
+        }
+    }
+
+    // se(v)
+    pub fn write_se(&mut self, value: i32) {
+        let code = if value > 0 { 2 * i64::from(value) - 1 } else { -2 * i64::from(value) };
+        self.write_ue(code as u32);
+    }
+
+    // 字节对齐时整字节写入
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.byte_aligned() {
+            self.data.extend_from_slice(bytes);
+            self.position += bytes.len() * 8;
+        } else {
+            for &byte in bytes {
+                self.write_bits(8, u32::from(byte));
+            }
+        }
+    }
+
+    // rbsp_trailing_bits()
+    pub fn rbsp_trailing_bits(&mut self) {
+        self.write_bit(true);
+        while !self.byte_aligned() {
+            self.write_bit(false);
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{ rbsp_to_ebsp, RbspWriter };
+    use crate::rbsp::{ ebsp_to_rbsp, RbspReader };
+
+    #[test]
+    fn test_rbsp_writer() {
+        let mut writer = RbspWriter::new();
+        writer.write_bits(3, 0b101);
+        writer.write_ue(0);
+        writer.write_ue(7);
+        writer.write_se(-3);
+        writer.write_flag(true);
+        writer.rbsp_trailing_bits();
+        writer.write_bytes(&[0x00, 0x00, 0x01, 0x00, 0x00]);
+
+        let rbsp = writer.into_bytes();
+        let mut reader = RbspReader::new(&rbsp);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_ue().unwrap(), 0);
+        assert_eq!(reader.read_ue().unwrap(), 7);
+        assert_eq!(reader.read_se().unwrap(), -3);
+        assert!(reader.read_flag().unwrap());
+
+        let ebsp = rbsp_to_ebsp(&rbsp);
+        assert_eq!(&ebsp[ebsp.len() - 7..], [0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03]);
+        assert_eq!(ebsp_to_rbsp(&ebsp), rbsp);
+    }
+}
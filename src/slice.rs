@@ -0,0 +1,304 @@
+use crate::error::{ self, Error };
+use crate::nalu::{ Nalu, NaluKind };
+use crate::rbsp::{ self, DebugRbSp, RbspReader, ParameterSets, SliceHeader };
+use crate::macroblock::SliceData;
+
+
+// 7.3.2.8 Slice layer without partitioning RBSP syntax ( Page 70 )
+#[derive(Debug, Clone)]
+pub struct Slice {
+    pub header: SliceHeader,
+    pub data: SliceData,
+}
+
+impl Slice {
+    // slice_layer_without_partitioning_rbsp()
+    pub fn parse(nalu: &Nalu, parameter_sets: &ParameterSets) -> Result<Self, Error> {
+        match nalu.kind() {
+            NaluKind::CodedSliceNonIdr | NaluKind::CodedSliceIdr => { },
+            _ => return Err(error::unsupported("not a slice layer without partitioning")),
+        }
+
+        let bytes = rbsp_bytes(nalu)?;
+        let mut reader = RbspReader::new(&bytes);
+        let header = SliceHeader::parse(&mut reader, nalu.header(), parameter_sets)?;
+
+        let pps = parameter_sets.pps(header.pic_parameter_set_id).expect("pps has been checked by the slice header");
+        let sps = parameter_sets.sps(pps.seq_parameter_set_id()).expect("sps has been checked by the slice header");
+
+        let data = SliceData::parse(&mut reader, &header, sps, pps)?;
+
+        Ok(Slice {
+            header: header,
+            data: data,
+        })
+    }
+
+    // 只解析 slice_header(), 不解析 slice_data()
+    pub fn parse_header(nalu: &Nalu, parameter_sets: &ParameterSets) -> Result<SliceHeader, Error> {
+        match nalu.kind() {
+            NaluKind::CodedSliceNonIdr | NaluKind::CodedSliceIdr => { },
+            _ => return Err(error::unsupported("not a slice layer without partitioning")),
+        }
+
+        let bytes = rbsp_bytes(nalu)?;
+        SliceHeader::parse(&mut RbspReader::new(&bytes), nalu.header(), parameter_sets)
+    }
+}
+
+fn rbsp_bytes(nalu: &Nalu) -> Result<Vec<u8>, Error> {
+    match nalu.payload().as_any().downcast_ref::<DebugRbSp>() {
+        Some(payload) => Ok(rbsp::ebsp_to_rbsp(payload.as_bytes())),
+        None => Err(error::malformed("slice payload expected")),
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::bitstream_io::{ BitWriter, BigEndian };
+    use crate::golomb::ue_encode;
+    use crate::nalu::Nalu;
+    use crate::rbsp::{ self, DebugRbSp, RbspReader, ParameterSets, SliceHeader, SliceType, PictureStructure };
+    use crate::macroblock::{ MbType, PredMode };
+    use super::Slice;
+
+    use std::convert::TryFrom;
+
+    pub(crate) struct Writer(BitWriter<Vec<u8>, BigEndian>);
+
+    impl Writer {
+        pub(crate) fn new(nal_header: u8) -> Self {
+            let mut writer = BitWriter::endian(Vec::new(), BigEndian);
+            writer.write(8, nal_header).unwrap();
+            Writer(writer)
+        }
+
+        pub(crate) fn u(&mut self, bits: u32, value: u32) -> &mut Self {
+            self.0.write(bits, value).unwrap();
+            self
+        }
+
+        pub(crate) fn ue(&mut self, value: u32) -> &mut Self {
+            ue_encode(value, &mut self.0);
+            self
+        }
+
+        pub(crate) fn se(&mut self, value: i32) -> &mut Self {
+            let code = if value > 0 { 2 * value - 1 } else { -2 * value };
+            self.ue(code as u32)
+        }
+
+        pub(crate) fn bits(&mut self, bits: &str) -> &mut Self {
+            for c in bits.chars() {
+                self.0.write_bit(c == '1').unwrap();
+            }
+            self
+        }
+
+        pub(crate) fn align(&mut self) -> &mut Self {
+            while !self.0.byte_aligned() {
+                self.0.write_bit(false).unwrap();
+            }
+            self
+        }
+
+        pub(crate) fn byte_aligned(&self) -> bool {
+            self.0.byte_aligned()
+        }
+
+        // cabac_alignment_one_bit
+        pub(crate) fn align_ones(&mut self) -> &mut Self {
+            while !self.0.byte_aligned() {
+                self.0.write_bit(true).unwrap();
+            }
+            self
+        }
+
+        pub(crate) fn finish(&mut self) -> Nalu {
+            Nalu::try_from(&self.finish_bytes()[..]).unwrap()
+        }
+
+        // NALU 的原始字节 ( 不插入 emulation_prevention_three_byte )
+        pub(crate) fn finish_bytes(&mut self) -> Vec<u8> {
+            self.0.write_bit(true).unwrap();
+            self.align();
+            let writer = std::mem::replace(&mut self.0, BitWriter::endian(Vec::new(), BigEndian));
+            writer.into_writer()
+        }
+    }
+
+    // parameter_sets() 中的 SPS 与 PPS 的 NALU
+    pub(crate) fn parameter_set_nalus() -> (Nalu, Nalu) {
+        let (sps, pps) = parameter_set_bytes();
+        (Nalu::try_from(&sps[..]).unwrap(), Nalu::try_from(&pps[..]).unwrap())
+    }
+
+    pub(crate) fn parameter_set_bytes() -> (Vec<u8>, Vec<u8>) {
+        let sps = Writer::new(0x67)
+            .u(8, 66).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(1).ue(0)
+            .u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish_bytes();
+        let pps = Writer::new(0x68)
+            .ue(0).ue(0).u(1, 0).u(1, 0).ue(0)
+            .ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0)
+            .finish_bytes();
+        (sps, pps)
+    }
+
+    // 32x16 的 Baseline 码流: 一个 IDR slice ( I_16x16 + I_PCM ) 以及一个 P slice ( P_Skip + P_L0_16x16 )
+    pub(crate) fn parameter_sets() -> ParameterSets {
+        let mut parameter_sets = ParameterSets::new();
+
+        let (sps, pps) = parameter_set_nalus();
+        assert!(parameter_sets.update(&sps).unwrap());
+        assert!(parameter_sets.update(&pps).unwrap());
+
+        let sps = parameter_sets.sps(0).unwrap();
+        assert_eq!(sps.width(), 32);
+        assert_eq!(sps.height(), 16);
+
+        parameter_sets
+    }
+
+    // P slice 的头部 ( frame_num 1, pic_order_cnt_lsb 2, nal_ref_idc 2 ), 供其它测试修改使用
+    pub(crate) fn predicted_slice_header(parameter_sets: &ParameterSets) -> SliceHeader {
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        let nalu = writer.finish();
+
+        let bytes = rbsp::ebsp_to_rbsp(nalu.payload_downcast_ref::<DebugRbSp>().as_bytes());
+        SliceHeader::parse(&mut RbspReader::new(&bytes), nalu.header(), parameter_sets).unwrap()
+    }
+
+    #[test]
+    fn test_intra_slice() {
+        let parameter_sets = parameter_sets();
+
+        let mut writer = Writer::new(0x65);
+        writer.ue(0).ue(7).ue(0).u(4, 0).ue(0).u(4, 0)
+            .u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        // I_16x16_2_0_0, intra_chroma_pred_mode, mb_qp_delta, Intra16x16DCLevel ( TotalCoeff = 0 )
+        writer.ue(3).ue(0).se(-1).bits("1");
+        // I_PCM
+        writer.ue(25).align();
+        for i in 0..256 {
+            writer.u(8, 16 + i % 200);
+        }
+        for _ in 0..128 {
+            writer.u(8, 128);
+        }
+        let nalu = writer.finish();
+
+        let slice = Slice::parse(&nalu, &parameter_sets).unwrap();
+        assert_eq!(slice.header.slice_type, SliceType::I);
+        assert!(slice.header.slice_type_fixed);
+        assert_eq!(slice.header.disable_deblocking_filter_idc, 1);
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 2);
+        assert_eq!(macroblocks[0].mb_type, MbType::I16x16 {
+            intra_16x16_pred_mode: 2,
+            coded_block_pattern_chroma: 0,
+            coded_block_pattern_luma: 0,
+        });
+        assert_eq!(macroblocks[0].mb_qp_delta, -1);
+        assert_eq!(macroblocks[1].mb_type, MbType::IPcm);
+
+        let pcm = macroblocks[1].pcm_samples.as_ref().unwrap();
+        assert_eq!(pcm.luma[255], 16 + 255 % 200);
+        assert_eq!(pcm.chroma.len(), 128);
+    }
+
+    #[test]
+    fn test_predicted_slice() {
+        let parameter_sets = parameter_sets();
+
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        // mb_skip_run, P_L0_16x16, mvd_l0, coded_block_pattern ( 1 ), mb_qp_delta
+        writer.ue(1).ue(0).se(1).se(-2).ue(2).se(0);
+        // luma4x4BlkIdx 0 .. 3: nC = 0, 5, 3, 0
+        writer.bits("000010001110010111101101").bits("1111").bits("11").bits("1");
+        let nalu = writer.finish();
+
+        let slice = Slice::parse(&nalu, &parameter_sets).unwrap();
+        assert_eq!(slice.header.slice_type, SliceType::P);
+        assert_eq!(slice.header.frame_num, 1);
+        assert_eq!(slice.header.pic_order_cnt_lsb, Some(2));
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 2);
+        assert_eq!(macroblocks[0].mb_type, MbType::PSkip);
+
+        let mb = &macroblocks[1];
+        assert_eq!(mb.mb_type, MbType::P16x16);
+        assert_eq!(mb.mb_type.mb_part_pred_mode(0), Some(PredMode::L0));
+        assert_eq!(mb.ref_idx[0][0], 0);
+        assert_eq!(mb.ref_idx[1][0], -1);
+        assert_eq!(mb.mvd[0][0][0], [1, -2]);
+        assert_eq!(mb.coded_block_pattern_luma, 1);
+        assert_eq!(mb.total_coeff[0][..4], [5, 0, 0, 0]);
+
+        let residual = mb.residual.as_ref().unwrap();
+        assert_eq!(residual.luma.level4x4[0], [0, 3, 0, 1, -1, -1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_mbaff_slice() {
+        // 32x32 的 MBAFF 帧 ( frame_mbs_only_flag 0, mb_adaptive_frame_field_flag 1 ), 两个宏块对
+        let mut parameter_sets = ParameterSets::new();
+        let sps = Writer::new(0x67)
+            .u(8, 77).u(8, 0).u(8, 30).ue(0)
+            .ue(0).ue(0).ue(0)
+            .ue(1).u(1, 0).ue(1).ue(0)
+            .u(1, 0).u(1, 1).u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        let pps = Writer::new(0x68)
+            .ue(0).ue(0).u(1, 0).u(1, 0).ue(0)
+            .ue(0).ue(0).u(1, 0).u(2, 0)
+            .se(0).se(0).se(0)
+            .u(1, 1).u(1, 0).u(1, 0)
+            .finish();
+        assert!(parameter_sets.update(&sps).unwrap());
+        assert!(parameter_sets.update(&pps).unwrap());
+
+        let mut writer = Writer::new(0x41);
+        writer.ue(0).ue(5).ue(0).u(4, 1).u(1, 0).u(4, 2)
+            .u(1, 0).u(1, 0).u(1, 0)
+            .se(0).ue(1);
+        // 宏块对 0: 顶部宏块跳过, 底部宏块读取 mb_field_decoding_flag ( 1 ) 后为 P_L0_16x16,
+        // 场宏块的 ref_idx_l0 范围为 0 .. 1 ( te(v) 为 1 个比特 )
+        writer.ue(1).u(1, 1).ue(0).u(1, 1).se(0).se(3).ue(0);
+        // 宏块对 1: 顶部宏块为帧宏块 P_L0_16x16, 底部宏块跳过
+        writer.ue(0).u(1, 0).ue(0).se(-1).se(0).ue(0);
+        writer.ue(1);
+        let nalu = writer.finish();
+
+        let slice = Slice::parse(&nalu, &parameter_sets).unwrap();
+        let sps = parameter_sets.sps(0).unwrap();
+        assert!(slice.header.mbaff_frame_flag(sps));
+        assert_eq!(slice.header.structure(), PictureStructure::Frame);
+
+        let macroblocks = &slice.data.macroblocks;
+        assert_eq!(macroblocks.len(), 4);
+        assert_eq!(macroblocks.iter().map(|mb| mb.mb_addr).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(macroblocks.iter().map(|mb| mb.mb_field_decoding_flag).collect::<Vec<_>>(),
+                   [true, true, false, false]);
+        assert_eq!(macroblocks[0].mb_type, MbType::PSkip);
+        assert_eq!(macroblocks[1].mb_type, MbType::P16x16);
+        assert_eq!(macroblocks[1].ref_idx[0][0], 0);
+        assert_eq!(macroblocks[1].mvd[0][0][0], [0, 3]);
+        assert_eq!(macroblocks[2].mvd[0][0][0], [-1, 0]);
+        assert_eq!(macroblocks[3].mb_type, MbType::PSkip);
+    }
+}